@@ -0,0 +1,112 @@
+// Compares the old single Mutex around the movie table with the RwLock MemoryMovieStore uses now, under a
+// read-heavy load: a handful of GET-like readers hammering the table while one writer keeps updating it.
+//
+// Run with `cargo bench --bench state_contention`.
+
+use std::{collections::BTreeMap, hint::black_box, sync::{atomic::{AtomicBool, AtomicU64, Ordering}, Arc}, time::{Duration, Instant}};
+use tokio::sync::{Mutex, RwLock};
+
+const MOVIES: usize = 10_000;
+const READERS: usize = 8;
+const RUN_FOR: Duration = Duration::from_secs(2);
+
+#[derive(Clone)]
+#[allow(dead_code)]
+struct Movie {
+    id: String,
+    name: String,
+    year: u16,
+    was_good: bool,
+}
+
+fn table() -> BTreeMap<String, Movie> {
+    (0..MOVIES)
+        .map(|i| {
+            let id = format!("movie-{i}");
+            (id.clone(), Movie { id, name: format!("Movie number {i}"), year: 1900 + (i % 120) as u16, was_good: i % 2 == 0 })
+        })
+        .collect()
+}
+
+// Abstracts over the two locking strategies so both get exactly the same workload.
+trait Table: Send + Sync + 'static {
+    fn read(&self, id: &str) -> impl Future<Output = Option<Movie>> + Send;
+    fn write(&self, movie: Movie) -> impl Future<Output = ()> + Send;
+}
+
+impl Table for Mutex<BTreeMap<String, Movie>> {
+    async fn read(&self, id: &str) -> Option<Movie> {
+        self.lock().await.get(id).cloned()
+    }
+
+    async fn write(&self, movie: Movie) {
+        self.lock().await.insert(movie.id.clone(), movie);
+    }
+}
+
+impl Table for RwLock<BTreeMap<String, Movie>> {
+    async fn read(&self, id: &str) -> Option<Movie> {
+        self.read().await.get(id).cloned()
+    }
+
+    async fn write(&self, movie: Movie) {
+        self.write().await.insert(movie.id.clone(), movie);
+    }
+}
+
+// Returns (reads per second, writes per second).
+async fn run<T: Table>(table: Arc<T>) -> (f64, f64) {
+    let stop = Arc::new(AtomicBool::new(false));
+    let reads = Arc::new(AtomicU64::new(0));
+    let writes = Arc::new(AtomicU64::new(0));
+    let mut tasks = Vec::new();
+
+    for reader in 0..READERS {
+        let (table, stop, reads) = (table.clone(), stop.clone(), reads.clone());
+        tasks.push(tokio::spawn(async move {
+            let mut i = reader;
+            while !stop.load(Ordering::Relaxed) {
+                black_box(table.read(&format!("movie-{}", i % MOVIES)).await);
+                reads.fetch_add(1, Ordering::Relaxed);
+                i += 7;
+            }
+        }));
+    }
+    {
+        let (table, stop, writes) = (table.clone(), stop.clone(), writes.clone());
+        tasks.push(tokio::spawn(async move {
+            let mut i = 0;
+            while !stop.load(Ordering::Relaxed) {
+                let id = format!("movie-{}", i % MOVIES);
+                table.write(Movie { id, name: format!("Renamed {i}"), year: 2000, was_good: true }).await;
+                writes.fetch_add(1, Ordering::Relaxed);
+                i += 13;
+                tokio::task::yield_now().await;
+            }
+        }));
+    }
+
+    let started = Instant::now();
+    tokio::time::sleep(RUN_FOR).await;
+    stop.store(true, Ordering::Relaxed);
+    for task in tasks {
+        task.await.unwrap();
+    }
+    let elapsed = started.elapsed().as_secs_f64();
+    (reads.load(Ordering::Relaxed) as f64 / elapsed, writes.load(Ordering::Relaxed) as f64 / elapsed)
+}
+
+fn main() {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(READERS + 1)
+        .enable_time()
+        .build()
+        .unwrap();
+    runtime.block_on(async {
+        let (mutex_reads, mutex_writes) = run(Arc::new(Mutex::new(table()))).await;
+        println!("Mutex:  {:>12.0} reads/s {:>10.0} writes/s", mutex_reads, mutex_writes);
+        let (rwlock_reads, rwlock_writes) = run(Arc::new(RwLock::new(table()))).await;
+        println!("RwLock: {:>12.0} reads/s {:>10.0} writes/s", rwlock_reads, rwlock_writes);
+        println!("RwLock read throughput is {:.2}x the Mutex's", rwlock_reads / mutex_reads);
+    });
+}
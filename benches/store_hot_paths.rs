@@ -0,0 +1,141 @@
+// Times what most requests come down to against each store and cache setup: getting a movie by id, listing with
+// filters, and inserting one movie after another. Each runs on its own for a while and is reported as how many went
+// through a second and how long one took, so a slower lock or cache shows up as a drop next to the last run's numbers.
+//
+// Run with `cargo bench --bench store_hot_paths`, or `cargo bench --bench store_hot_paths -- wal` for only the setups
+// with "wal" in their names.
+
+use std::{hint::black_box, path::{Path, PathBuf}, sync::Arc, time::{Duration, Instant}};
+
+use syndica_rust::{
+    cache::{CacheConfig, CachedMovieStore},
+    event_sourced::EventSourcedMovieStore,
+    model::Movie,
+    snapshot::SnapshotMovieStore,
+    state::{state_init, StateWrapper},
+    store::{Backup, MovieFilter},
+    wal::{WalConfig, WalMovieStore},
+};
+
+const MOVIES: usize = 10_000;
+const WARM_UP: Duration = Duration::from_millis(200);
+const RUN_FOR: Duration = Duration::from_secs(1);
+const GENRES: [&str; 4] = ["Drama", "Comedy", "Horror", "Western"];
+
+fn movie(i: usize) -> Movie {
+    Movie {
+        id: format!("movie-{:05}", i),
+        name: format!("Movie number {}", i),
+        year: 1920 + (i % 100) as u16,
+        was_good: !i.is_multiple_of(3),
+        genres: vec![GENRES[i % GENRES.len()].to_string()],
+        tags: if i.is_multiple_of(10) { vec!["noir".to_string()] } else { Vec::new() },
+        version: 1,
+        ..Default::default()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Setup {
+    Memory,
+    // Big enough for every movie, so every get after the first is a hit.
+    CachedAll,
+    // Holds 1% of the movies, so nearly every get misses and evicts.
+    CachedFew,
+    Snapshot,
+    Wal,
+    EventSourced,
+}
+
+const SETUPS: [Setup; 6] = [Setup::Memory, Setup::CachedAll, Setup::CachedFew, Setup::Snapshot, Setup::Wal, Setup::EventSourced];
+
+impl Setup {
+    fn name(self) -> &'static str {
+        match self {
+            Setup::Memory => "memory",
+            Setup::CachedAll => "cached (all)",
+            Setup::CachedFew => "cached (1%)",
+            Setup::Snapshot => "snapshot",
+            Setup::Wal => "wal",
+            Setup::EventSourced => "event-sourced",
+        }
+    }
+
+    // An empty store, keeping its files under `dir` if it has any.
+    async fn open(self, dir: &Path) -> StateWrapper {
+        let _ = std::fs::remove_dir_all(dir);
+        std::fs::create_dir_all(dir).unwrap();
+        match self {
+            Setup::Memory => state_init(),
+            Setup::CachedAll => Arc::new(CachedMovieStore::new(state_init(), CacheConfig { capacity: MOVIES, ttl: None })),
+            Setup::CachedFew => Arc::new(CachedMovieStore::new(state_init(), CacheConfig { capacity: MOVIES / 100, ttl: None })),
+            Setup::Snapshot => Arc::new(SnapshotMovieStore::open(dir.join("movies.json")).await.unwrap()),
+            Setup::Wal => Arc::new(WalMovieStore::open(WalConfig { path: dir.join("movies.wal"), max_bytes: u64::MAX }).await.unwrap()),
+            Setup::EventSourced => Arc::new(EventSourcedMovieStore::open(dir.join("movies.events")).await.unwrap()),
+        }
+    }
+}
+
+// Runs `op` with 0, 1, 2... for RUN_FOR after warming up, and returns how long one took on average.
+async fn measure<F: AsyncFnMut(usize)>(mut op: F) -> Duration {
+    let mut i = 0;
+    let warm_up = Instant::now();
+    while warm_up.elapsed() < WARM_UP {
+        op(i).await;
+        i += 1;
+    }
+    let (started, mut runs) = (Instant::now(), 0u32);
+    while started.elapsed() < RUN_FOR {
+        op(i).await;
+        i += 1;
+        runs += 1;
+    }
+    started.elapsed() / runs.max(1)
+}
+
+fn report(setup: Setup, bench: &str, each: Duration) {
+    println!("{:<14} {:<14} {:>12.0} ops/s {:>10} ns/op", setup.name(), bench, 1.0 / each.as_secs_f64(), each.as_nanos());
+}
+
+async fn bench(setup: Setup, dir: &Path) {
+    let store = setup.open(dir).await;
+    // Loaded in one go, rather than a write (and for some, an fsync) per movie.
+    let backup = Backup { movies: (0..MOVIES).map(movie).collect(), ..Default::default() };
+    store.replace(backup).await.unwrap();
+
+    // Spread over the whole store rather than walking it in order.
+    let each = measure(async |i| {
+        black_box(store.get(&movie(i * 7919 % MOVIES).id).await);
+    }).await;
+    report(setup, "get by id", each);
+
+    let filters = [
+        MovieFilter { year_gte: Some(1990), year_lte: Some(1999), was_good: Some(true), ..Default::default() },
+        MovieFilter { genre: Some("western".to_string()), ..Default::default() },
+        MovieFilter { tag: Some("noir".to_string()), year_gte: Some(1950), ..Default::default() },
+        MovieFilter { name_contains: Some("number 42".to_string()), ..Default::default() },
+    ];
+    let each = measure(async |i| {
+        black_box(store.list(&filters[i % filters.len()]).await);
+    }).await;
+    report(setup, "list filtered", each);
+
+    let store = setup.open(dir).await;
+    let each = measure(async |i| {
+        store.insert(movie(i)).await.unwrap();
+    }).await;
+    report(setup, "insert", each);
+}
+
+fn main() {
+    // cargo bench passes --bench, and anything after -- picks the setups to run.
+    let only: Vec<String> = std::env::args().skip(1).filter(|arg| !arg.starts_with("--")).collect();
+    let runtime = tokio::runtime::Builder::new_multi_thread().enable_all().build().unwrap();
+    let dir: PathBuf = std::env::temp_dir().join(format!("syndica-bench-{}", std::process::id()));
+    runtime.block_on(async {
+        for setup in SETUPS.into_iter().filter(|setup| only.is_empty() || only.iter().any(|name| setup.name().contains(name.as_str()))) {
+            bench(setup, &dir).await;
+        }
+    });
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,48 @@
+// Sends whatever libFuzzer comes up with as the body of the requests that take movies, in each format they can come
+// in, and fails if one is answered with anything but a 2xx or 4xx, or panics on the way. The first byte picks the
+// request and format, the rest is the body.
+//
+// Run from this directory with `cargo +nightly fuzz run movie_payload`.
+
+#![no_main]
+
+use std::sync::LazyLock;
+
+use axum::{body::Body, http::Request};
+use libfuzzer_sys::fuzz_target;
+use syndica_rust::test_app;
+use tower::ServiceExt;
+
+const REQUESTS: [(&str, &str); 5] = [
+    ("POST", "/v1/movie"),
+    ("POST", "/v1/movie?upsert=true"),
+    ("PUT", "/v1/movie/fuzz"),
+    ("PATCH", "/v1/movie/fuzz"),
+    ("POST", "/v1/movies/batch"),
+];
+const CONTENT_TYPES: [&str; 3] = ["application/json", "application/msgpack", "application/cbor"];
+
+static RUNTIME: LazyLock<tokio::runtime::Runtime> = LazyLock::new(|| tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap());
+
+fn request(method: &str, uri: &str, content_type: &str, body: Vec<u8>) -> Request<Body> {
+    Request::builder().method(method).uri(uri).header("content-type", content_type).body(Body::from(body)).unwrap()
+}
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&pick, body)) = data.split_first() else {
+        return;
+    };
+    let (method, uri) = REQUESTS[pick as usize % REQUESTS.len()];
+    let content_type = CONTENT_TYPES[pick as usize / REQUESTS.len() % CONTENT_TYPES.len()];
+    RUNTIME.block_on(async {
+        // A fresh store each time, with a movie for PUT and PATCH to find, so one input can't change what the next
+        // one meets.
+        let app = test_app();
+        let fuzz = br#"{"id":"fuzz","name":"Fuzz","year":2000,"was_good":true}"#.to_vec();
+        app.clone().oneshot(request("POST", "/v1/movie", "application/json", fuzz)).await.unwrap();
+
+        let response = app.oneshot(request(method, uri, content_type, body.to_vec())).await.unwrap();
+        let status = response.status();
+        assert!(status.is_success() || status.is_client_error(), "{} {} as {} was answered with {}", method, uri, content_type, status);
+    });
+});
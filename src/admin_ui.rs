@@ -0,0 +1,34 @@
+use axum::{http::header, response::{IntoResponse, Response}};
+
+// A small admin page at GET /admin/ui for managing movies from a browser: the stats, a page at a time of the movies,
+// and a form to add and edit them, with deleting from the list. The files are compiled into the binary, so it's there
+// wherever the server is. It's plain HTML and JavaScript calling the same /v1 routes as any client, so it holds no
+// data of its own and anyone may load it; what it can do is up to the X-Api-Key or bearer token entered on it. OIDC
+// logins only count on the admin routes, so they aren't enough for it on their own.
+
+struct Asset {
+    name: &'static str,
+    content_type: &'static str,
+    contents: &'static str,
+}
+
+const ASSETS: &[Asset] = &[
+    Asset { name: "index.html", content_type: "text/html; charset=utf-8", contents: include_str!("admin_ui/index.html") },
+    Asset { name: "app.js", content_type: "text/javascript; charset=utf-8", contents: include_str!("admin_ui/app.js") },
+    Asset { name: "style.css", content_type: "text/css; charset=utf-8", contents: include_str!("admin_ui/style.css") },
+];
+
+// Nothing but these files runs on the page, whatever ends up in a movie's name.
+const CONTENT_SECURITY_POLICY: &str = "default-src 'none'; script-src 'self'; style-src 'self'; connect-src 'self'; form-action 'none'; frame-ancestors 'none'; base-uri 'none'";
+
+// The file called `name`, if there is one. Sent with no-cache, so a new build's files are picked up straight away.
+pub fn serve(name: &str) -> Option<Response> {
+    let asset = ASSETS.iter().find(|asset| asset.name == name)?;
+    let headers = [
+        (header::CONTENT_TYPE, asset.content_type),
+        (header::CACHE_CONTROL, "no-cache"),
+        (header::CONTENT_SECURITY_POLICY, CONTENT_SECURITY_POLICY),
+        (header::X_CONTENT_TYPE_OPTIONS, "nosniff"),
+    ];
+    Some((headers, asset.contents).into_response())
+}
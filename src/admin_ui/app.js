@@ -0,0 +1,225 @@
+"use strict";
+
+// Everything goes through the same /v1 API as any other client, with the key or token and tenant entered at the top,
+// which are kept for this tab only.
+const PAGE_SIZE = 20;
+const state = { offset: 0, filter: "", editing: null };
+
+function $(selector) {
+  return document.querySelector(selector);
+}
+
+function credentials() {
+  const saved = sessionStorage.getItem("movies-credentials");
+  return saved ? JSON.parse(saved) : null;
+}
+
+async function api(method, path, body) {
+  const headers = { "Accept": "application/json" };
+  const saved = credentials();
+  if (saved && saved.secret) {
+    if (saved.kind === "bearer") {
+      headers["Authorization"] = "Bearer " + saved.secret;
+    } else {
+      headers["X-Api-Key"] = saved.secret;
+    }
+  }
+  if (saved && saved.tenant) {
+    headers["X-Tenant-Id"] = saved.tenant;
+  }
+  if (body !== undefined) {
+    headers["Content-Type"] = "application/json";
+  }
+  const response = await fetch(path, { method, headers, body: body === undefined ? undefined : JSON.stringify(body) });
+  if (response.status === 204) {
+    return null;
+  }
+  const json = await response.json().catch(() => null);
+  if (!response.ok) {
+    const error = json && json.error;
+    let message = error ? error.message : response.status + " " + response.statusText;
+    if (error && error.details && Array.isArray(error.details.fields)) {
+      message += ": " + error.details.fields.map((field) => field.field + " " + field.message).join(", ");
+    }
+    throw new Error(message);
+  }
+  return json;
+}
+
+function show(message, isError) {
+  const element = $("#message");
+  element.textContent = message;
+  element.className = isError ? "error" : "";
+  element.hidden = false;
+}
+
+async function loadStats() {
+  const stats = await api("GET", "/v1/movies/stats");
+  const set = (name, value) => { $(`[data-stat="${name}"]`).textContent = value === null || value === undefined ? "-" : value; };
+  set("total", stats.total);
+  set("good", stats.by_was_good["true"]);
+  set("bad", stats.by_was_good["false"]);
+  set("years", stats.min_year === null ? null : stats.min_year + " to " + stats.max_year);
+  set("median_year", stats.median_year);
+}
+
+function cell(text) {
+  const td = document.createElement("td");
+  td.textContent = text === null || text === undefined ? "" : text;
+  return td;
+}
+
+function button(label, onClick) {
+  const element = document.createElement("button");
+  element.type = "button";
+  element.textContent = label;
+  element.addEventListener("click", onClick);
+  return element;
+}
+
+async function loadMovies() {
+  const query = new URLSearchParams({ limit: PAGE_SIZE, offset: state.offset });
+  if (state.filter) {
+    query.set("name_contains", state.filter);
+  }
+  const page = await api("GET", "/v1/movies?" + query);
+  const rows = page.items.map((movie) => {
+    const row = document.createElement("tr");
+    row.append(cell(movie.id), cell(movie.name), cell(movie.year), cell(movie.director), cell(movie.was_good ? "yes" : "no"));
+    const actions = document.createElement("td");
+    actions.append(button("Edit", () => edit(movie)), " ", button("Delete", () => remove(movie)));
+    row.append(actions);
+    return row;
+  });
+  $("#movies").replaceChildren(...rows);
+  const last = Math.min(state.offset + page.items.length, page.total);
+  $("#page").textContent = page.total === 0 ? "No movies" : `${state.offset + 1} to ${last} of ${page.total}`;
+  $("#previous").disabled = state.offset === 0;
+  $("#next").disabled = !page.next;
+}
+
+async function refresh() {
+  try {
+    await Promise.all([loadStats(), loadMovies()]);
+  } catch (error) {
+    show(error.message, true);
+  }
+}
+
+function list(text) {
+  return text.split(",").map((item) => item.trim()).filter((item) => item !== "");
+}
+
+// What the form says, with empty optional fields as null so an edit clears them.
+function formMovie(form) {
+  const optional = (name) => form.elements[name].value.trim() || null;
+  const runtime = optional("runtime_minutes");
+  return {
+    name: form.elements.name.value.trim(),
+    year: Number(form.elements.year.value),
+    was_good: form.elements.was_good.checked,
+    director: optional("director"),
+    runtime_minutes: runtime === null ? null : Number(runtime),
+    synopsis: optional("synopsis"),
+    genres: list(form.elements.genres.value),
+    tags: list(form.elements.tags.value),
+  };
+}
+
+function edit(movie) {
+  const form = $("#movie");
+  state.editing = movie.id;
+  form.elements.id.value = movie.id;
+  form.elements.id.disabled = true;
+  form.elements.name.value = movie.name;
+  form.elements.year.value = movie.year;
+  form.elements.was_good.checked = movie.was_good;
+  form.elements.director.value = movie.director || "";
+  form.elements.runtime_minutes.value = movie.runtime_minutes || "";
+  form.elements.synopsis.value = movie.synopsis || "";
+  form.elements.genres.value = (movie.genres || []).join(", ");
+  form.elements.tags.value = (movie.tags || []).join(", ");
+  $("#form-title").textContent = "Edit " + movie.name;
+  $("#cancel").hidden = false;
+  form.scrollIntoView();
+}
+
+function resetForm() {
+  const form = $("#movie");
+  form.reset();
+  form.elements.id.disabled = false;
+  state.editing = null;
+  $("#form-title").textContent = "Add a movie";
+  $("#cancel").hidden = true;
+}
+
+async function save(event) {
+  event.preventDefault();
+  const form = event.target;
+  const movie = formMovie(form);
+  try {
+    if (state.editing) {
+      await api("PATCH", "/v1/movie/" + encodeURIComponent(state.editing), movie);
+      show("Saved " + movie.name);
+    } else {
+      const id = form.elements.id.value.trim();
+      if (id) {
+        movie.id = id;
+      }
+      const created = await api("POST", "/v1/movie", movie);
+      show("Added " + created.name + " as " + created.id);
+    }
+    resetForm();
+    await refresh();
+  } catch (error) {
+    show(error.message, true);
+  }
+}
+
+async function remove(movie) {
+  if (!confirm(`Delete ${movie.name}? It goes to the trash, where an admin can restore it from.`)) {
+    return;
+  }
+  try {
+    await api("DELETE", "/v1/movie/" + encodeURIComponent(movie.id));
+    show("Deleted " + movie.name);
+    if (state.editing === movie.id) {
+      resetForm();
+    }
+    await refresh();
+  } catch (error) {
+    show(error.message, true);
+  }
+}
+
+document.addEventListener("DOMContentLoaded", () => {
+  const saved = credentials();
+  if (saved) {
+    $("#credentials").elements.kind.value = saved.kind;
+    $("#credentials").elements.secret.value = saved.secret;
+    $("#credentials").elements.tenant.value = saved.tenant || "";
+  }
+  $("#credentials").addEventListener("submit", (event) => {
+    event.preventDefault();
+    const form = event.target;
+    sessionStorage.setItem("movies-credentials", JSON.stringify({ kind: form.elements.kind.value, secret: form.elements.secret.value, tenant: form.elements.tenant.value.trim() }));
+    refresh();
+  });
+  $("#movie").addEventListener("submit", save);
+  $("#cancel").addEventListener("click", resetForm);
+  $("#filter").addEventListener("submit", (event) => {
+    event.preventDefault();
+    state.filter = event.target.elements.name_contains.value.trim();
+    state.offset = 0;
+    refresh();
+  });
+  $("#previous").addEventListener("click", () => {
+    state.offset = Math.max(0, state.offset - PAGE_SIZE);
+    loadMovies().catch((error) => show(error.message, true));
+  });
+  $("#next").addEventListener("click", () => {
+    state.offset += PAGE_SIZE;
+    loadMovies().catch((error) => show(error.message, true));
+  });
+  refresh();
+});
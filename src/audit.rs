@@ -0,0 +1,155 @@
+use std::{collections::{BTreeMap, VecDeque}, fs::{File, OpenOptions}, future::Future, io::{self, Write}, path::Path, sync::Mutex};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use tracing::{info, warn};
+
+// Records who changed what and when, for every movie, user and link the store creates, updates or deletes, for
+// operators to look through with GET /admin/audit. MemoryMovieStore records them once a change has been applied, and
+// every other store keeps its data in one, so this covers them all. Deleting a movie also takes it off watchlists and
+// unlinks it, which is part of the movie's entry rather than entries of its own.
+// With a store on disk the entries are appended to a file next to it as JSON lines, and the newest are loaded back
+// from it on the next start. Like the tracing logs they're written without waiting for the disk, so a power cut can
+// lose the last few.
+
+// How many entries are kept in memory for GET /admin/audit. The file keeps them all.
+pub const MAX_ENTRIES: usize = 10_000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    // 1 for the first entry, and one more for each after it, across restarts.
+    pub seq: u64,
+    // RFC 3339, in UTC.
+    pub at: String,
+    // Who made the change, see Caller::actor.
+    pub actor: String,
+    // created, updated or deleted.
+    pub action: String,
+    // movie, user or link, or store when everything was replaced with a backup.
+    pub entity: String,
+    pub id: String,
+    // Every top-level field that changed, by name. Fields that weren't there are null.
+    pub changes: BTreeMap<String, Change>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Change {
+    pub before: Value,
+    pub after: Value,
+}
+
+struct Log {
+    // Oldest first.
+    entries: VecDeque<AuditEntry>,
+    last_seq: u64,
+    file: Option<File>,
+}
+
+static LOG: Mutex<Log> = Mutex::new(Log { entries: VecDeque::new(), last_seq: 0, file: None });
+
+tokio::task_local! {
+    // Who the request being handled on this task is from, so the store can tell without every handler passing it on.
+    static ACTOR: String;
+}
+
+// Runs `future` with the changes it makes recorded as made by `actor`.
+pub async fn acting_as<F: Future>(actor: String, future: F) -> F::Output {
+    ACTOR.scope(actor, future).await
+}
+
+// Changes made outside of a request, or by one anyone may make, are made by the server itself.
+pub fn current_actor() -> String {
+    ACTOR.try_with(Clone::clone).unwrap_or_else(|_| "system".to_string())
+}
+
+// Loads the newest entries back from the file at `path`, and appends every entry from now on to it.
+pub fn open(path: &Path) -> io::Result<()> {
+    let contents = match std::fs::read(path) {
+        Ok(contents) => contents,
+        // First run.
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(e),
+    };
+    let mut entries = VecDeque::new();
+    let mut valid_len = 0;
+    let mut lines = contents.split_inclusive(|byte| *byte == b'\n').peekable();
+    while let Some(line) = lines.next() {
+        match serde_json::from_slice::<AuditEntry>(line) {
+            Ok(entry) => entries.push_back(entry),
+            // A torn final line means we stopped mid-append.
+            Err(e) if lines.peek().is_none() => {
+                warn!("Dropping incomplete last entry in audit log: {}", e);
+                break;
+            },
+            Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+        }
+        if entries.len() > MAX_ENTRIES {
+            entries.pop_front();
+        }
+        valid_len += line.len() as u64;
+    }
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.set_len(valid_len)?;
+    let mut log = LOG.lock().unwrap();
+    log.last_seq = entries.back().map_or(0, |entry| entry.seq);
+    info!("Loaded {} audit entries from {}", entries.len(), path.display());
+    log.entries = entries;
+    log.file = Some(file);
+    Ok(())
+}
+
+// Records a change from `before` to `after`, either of which is None if the thing didn't exist on that side.
+pub fn record<T: Serialize>(entity: &str, id: &str, before: Option<&T>, after: Option<&T>) {
+    let action = match (before, after) {
+        (None, Some(_)) => "created",
+        (Some(_), Some(_)) => "updated",
+        (Some(_), None) => "deleted",
+        (None, None) => return,
+    };
+    let fields = |value: Option<&T>| match value.map(serde_json::to_value) {
+        Some(Ok(Value::Object(fields))) => fields,
+        _ => serde_json::Map::new(),
+    };
+    let (before, after) = (fields(before), fields(after));
+    let changes = before.keys().chain(after.keys())
+        .filter(|field| before.get(*field) != after.get(*field))
+        .map(|field| {
+            let change = Change { before: before.get(field).cloned().unwrap_or_default(), after: after.get(field).cloned().unwrap_or_default() };
+            (field.clone(), change)
+        })
+        .collect();
+
+    let mut log = LOG.lock().unwrap();
+    let entry = AuditEntry {
+        seq: log.last_seq + 1,
+        at: OffsetDateTime::now_utc().format(&Rfc3339).unwrap_or_default(),
+        actor: current_actor(),
+        action: action.to_string(),
+        entity: entity.to_string(),
+        id: id.to_string(),
+        changes,
+    };
+    if let Some(file) = &mut log.file {
+        let mut line = serde_json::to_vec(&entry).expect("audit entries always serialize");
+        line.push(b'\n');
+        // The change has already been made, so there's nothing to do about this but say so.
+        if let Err(e) = file.write_all(&line) {
+            warn!("Failed to write audit entry {}: {}", entry.seq, e);
+        }
+    }
+    log.last_seq = entry.seq;
+    log.entries.push_back(entry);
+    if log.entries.len() > MAX_ENTRIES {
+        log.entries.pop_front();
+    }
+}
+
+// Oldest first: those made after `since`, by `actor`, if given.
+pub fn entries(since: Option<OffsetDateTime>, actor: Option<&str>) -> Vec<AuditEntry> {
+    LOG.lock().unwrap().entries.iter()
+        // Stamped by us, so they always parse.
+        .filter(|entry| since.is_none_or(|since| OffsetDateTime::parse(&entry.at, &Rfc3339).is_ok_and(|at| at > since)))
+        .filter(|entry| actor.is_none_or(|actor| entry.actor == actor))
+        .cloned()
+        .collect()
+}
@@ -0,0 +1,214 @@
+use axum::{extract::{Extension, MatchedPath, Request, State}, http::{header, HeaderMap, HeaderName, Method}, middleware::Next, response::{IntoResponse, Response}};
+use serde::Serialize;
+use tracing::debug;
+
+use crate::audit;
+use crate::crypto;
+use crate::error::ApiError;
+use crate::jwt::{self, JwtConfig};
+use crate::oidc;
+use crate::settings::Settings;
+use crate::versioning;
+
+pub static API_KEY_HEADER: HeaderName = HeaderName::from_static("x-api-key");
+
+// What a caller may do. Each role may do everything the ones before it may.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    // Reads.
+    Reader,
+    // Creates and updates.
+    Editor,
+    // Deletes, and the admin routes.
+    Admin,
+}
+
+impl Role {
+    pub(crate) fn from_name(name: &str) -> Option<Role> {
+        match name {
+            "reader" => Some(Role::Reader),
+            "editor" => Some(Role::Editor),
+            "admin" => Some(Role::Admin),
+            _ => None,
+        }
+    }
+
+    // The role a request needs by default: reads need Reader, deletes and anything under /admin need Admin, and
+    // other writes need Editor.
+    pub fn required_for(method: &Method, route: &str) -> Role {
+        if is_admin_route(route) || *method == Method::DELETE {
+            Role::Admin
+        }
+        else if matches!(*method, Method::GET | Method::HEAD) {
+            Role::Reader
+        }
+        else {
+            Role::Editor
+        }
+    }
+}
+
+fn is_admin_route(route: &str) -> bool {
+    route == "/admin" || route.starts_with("/admin/")
+}
+
+// Who made the request, put in its extensions by authorize for handlers that check roles themselves.
+#[derive(Debug, Clone, Serialize)]
+pub struct Caller {
+    // The sub of their token or login, if they logged in that way.
+    pub subject: Option<String>,
+    // None when the caller may do nothing at all.
+    pub role: Option<Role>,
+    // Whether the caller showed a token or key, to tell "who are you?" (401) from "you may not" (403).
+    pub authenticated: bool,
+    // The first 8 hex digits of their API key's SHA-256, if they used one, which tells keys apart without giving
+    // them away.
+    #[serde(skip)]
+    pub key: Option<String>,
+    // The tenant their API key is for, if it's one of --tenant-api-keys. They can't reach any other, see tenant.rs.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+    // A hash of the token, key or session cookie that was accepted, so rate_limit.rs counts requests against what
+    // actually let the caller in, and not against whatever else they sent along with it.
+    #[serde(skip)]
+    pub credential: Option<String>,
+}
+
+impl Caller {
+    // Who they are in the audit log: user:<subject> for tokens and logins, key:<hash prefix> for API keys, and
+    // anonymous otherwise.
+    pub fn actor(&self) -> String {
+        match (&self.subject, &self.key) {
+            (Some(subject), _) => format!("user:{}", subject),
+            (None, Some(key)) => format!("key:{}", key),
+            (None, None) => "anonymous".to_string(),
+        }
+    }
+
+    pub fn require(&self, role: Role) -> Result<(), ApiError> {
+        if self.role.is_some_and(|own| own >= role) {
+            Ok(())
+        }
+        else if self.authenticated {
+            Err(ApiError::Forbidden(format!("This needs the {:?} role", role)))
+        }
+        else {
+            Err(ApiError::Unauthorized(format!("This needs a bearer token or {} header", API_KEY_HEADER)))
+        }
+    }
+}
+
+// Who may do what, kept in the router's Settings.
+#[derive(Default)]
+pub(crate) struct Policy {
+    // SHA-256 of every key that may write, as configured with --api-keys. Only hashes are kept, so neither the config
+    // file nor the process's memory gives the keys away. A valid key can do anything, like Admin.
+    pub(crate) api_keys: Vec<[u8; 32]>,
+    // Keys that can do anything as well, but only to the movies of the tenant they're for, and not on the admin routes.
+    pub(crate) tenant_keys: Vec<([u8; 32], String)>,
+    // With this set, every request needs a bearer token or API key, and tokens say which roles the caller has.
+    pub(crate) jwt: Option<JwtConfig>,
+}
+
+// Parses one configured key hash: 64 hex digits, as printed by `printf %s "$key" | sha256sum`.
+pub fn parse_key_hash(hex: &str) -> Result<[u8; 32], String> {
+    let hex = hex.trim();
+    if hex.len() != 64 || !hex.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return Err(format!("expected the 64 hex digit SHA-256 of a key, got {:?}", hex));
+    }
+    let mut hash = [0u8; 32];
+    for (i, byte) in hash.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).unwrap();
+    }
+    Ok(hash)
+}
+
+// Works out who the caller is from their Authorization or X-Api-Key header. A token or key that is sent but isn't
+// valid is an error rather than being ignored, so a client with a broken one finds out.
+//
+// Without one, what they may do depends on what's configured: nothing once tokens are, reads once only API keys or
+// logins are, and everything when none are, as before there was authentication.
+pub fn identify(settings: &Settings, headers: &HeaderMap) -> Result<Caller, ApiError> {
+    let policy = settings.policy();
+    let unauthorized = |reason: String| ApiError::Unauthorized(reason);
+
+    if let Some(authorization) = headers.get(header::AUTHORIZATION) {
+        let token = authorization.to_str().ok()
+            .and_then(|value| value.strip_prefix("Bearer ").or_else(|| value.strip_prefix("bearer ")))
+            .ok_or_else(|| unauthorized("Authorization has to be a bearer token".to_string()))?;
+        let jwt = policy.jwt.as_ref().ok_or_else(|| unauthorized("Bearer tokens aren't configured".to_string()))?;
+        let claims = jwt::validate(jwt, token.trim()).map_err(|reason| unauthorized(format!("Invalid bearer token: {}", reason)))?;
+        debug!("Bearer token for {:?} with roles {:?}", claims.subject, claims.roles);
+        let role = claims.roles.iter().filter_map(|name| Role::from_name(name)).max();
+        let credential = Some(fingerprint("token", token.trim().as_bytes()));
+        return Ok(Caller { subject: claims.subject, role, authenticated: true, key: None, tenant: None, credential });
+    }
+
+    if let Some(key) = headers.get(&API_KEY_HEADER) {
+        let hash = crypto::sha256(key.as_bytes());
+        // Check every key rather than stopping at a match, so timing doesn't tell which one it was.
+        let known = policy.api_keys.iter().fold(false, |found, known| found | crypto::constant_time_eq(known, &hash));
+        let tenant = policy.tenant_keys.iter().fold(None, |found, (known, tenant)| {
+            if crypto::constant_time_eq(known, &hash) { Some(tenant.clone()) } else { found }
+        });
+        if !known && tenant.is_none() {
+            return Err(unauthorized(format!("Invalid {} header", API_KEY_HEADER)));
+        }
+        let credential = Some(fingerprint("key", key.as_bytes()));
+        let key = hash[..4].iter().map(|byte| format!("{:02x}", byte)).collect();
+        // A key listed both ways is for the one tenant only.
+        return Ok(Caller { subject: None, role: Some(Role::Admin), authenticated: true, key: Some(key), tenant, credential });
+    }
+
+    let role = match (&policy.jwt, policy.api_keys.is_empty() && policy.tenant_keys.is_empty() && !oidc::enabled()) {
+        (Some(_), _) => None,
+        (None, false) => Some(Role::Reader),
+        (None, true) => Some(Role::Admin),
+    };
+    Ok(Caller { subject: None, role, authenticated: false, key: None, tenant: None, credential: None })
+}
+
+// Tells credentials of a kind apart without keeping them: the kind and the first 16 bytes of the SHA-256, in hex.
+pub fn fingerprint(kind: &str, secret: &[u8]) -> String {
+    let hash = crypto::sha256(secret);
+    let hex: String = hash[..16].iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("{}:{}", kind, hex)
+}
+
+// Middleware for the whole router. Every request needs the role Role::required_for gives it, unless the state lists
+// its (method, route) with a different one, or with None for routes anyone may call, like the health checks. Routes
+// are listed without their API version, and apply to every version that has them.
+pub async fn authorize(State(overrides): State<&'static [(Method, &'static str, Option<Role>)]>, Extension(settings): Extension<Settings>, mut request: Request, next: Next) -> Response {
+    let route = request.extensions().get::<MatchedPath>().map_or(request.uri().path(), MatchedPath::as_str);
+    let route = versioning::unversioned_route(route);
+    let required = match overrides.iter().find(|(method, path, _)| method == request.method() && *path == route) {
+        Some((_, _, required)) => *required,
+        // Which methods a route has is no secret, see fallback.rs.
+        None if request.method() == Method::OPTIONS => None,
+        None => Some(Role::required_for(request.method(), route)),
+    };
+    let Some(required) = required else {
+        return next.run(request).await;
+    };
+    let mut caller = match identify(&settings, request.headers()) {
+        Ok(caller) => caller,
+        Err(error) => return error.into_response(),
+    };
+    // People logged in through the OIDC provider only get to use their session on the admin routes, everything else
+    // is for API clients. The cookie's path keeps browsers from sending it anywhere else anyway.
+    if !caller.authenticated && is_admin_route(route)
+        && let Some(session) = oidc::session_caller(request.headers()) {
+        caller = session;
+    }
+    if caller.tenant.is_some() && is_admin_route(route) {
+        return ApiError::Forbidden("Keys for a tenant can't be used on the admin routes".to_string()).into_response();
+    }
+    if let Err(error) = caller.require(required) {
+        debug!("Refusing {} {}: {}", request.method(), request.uri().path(), error.message());
+        return error.into_response();
+    }
+    let actor = caller.actor();
+    request.extensions_mut().insert(caller);
+    audit::acting_as(actor, next.run(request)).await
+}
@@ -0,0 +1,107 @@
+use std::{collections::BTreeSet, io, path::{Path, PathBuf}, sync::RwLock};
+use serde::{Deserialize, Serialize};
+use time::{format_description::well_known::Rfc3339, macros::format_description, OffsetDateTime};
+
+use crate::model::{Link, StoredMovie, StoredTrashed, StoredUser};
+use crate::store::Backup;
+
+// Backups of the whole store, for getting it back after a disaster or moving it to another --store. POST
+// /admin/snapshot takes one, with writes held off only for as long as copying everything takes, so it's of a single
+// moment. POST /admin/restore swaps everything for what one holds, all at once. They're the same JSON whatever the
+// store, and kept in --backup-dir when that's set, or else downloaded and uploaded again by the caller.
+// Poster images aren't in them, only which image each movie has, so --poster-dir needs backing up alongside.
+
+static DIR: RwLock<Option<PathBuf>> = RwLock::new(None);
+
+pub fn set_dir(dir: Option<PathBuf>) {
+    *DIR.write().unwrap() = dir;
+}
+
+pub fn dir() -> Option<PathBuf> {
+    DIR.read().unwrap().clone()
+}
+
+// A backup as it's written out, with the ratings, reviews and history that clients don't otherwise get.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BackupFile {
+    // RFC 3339, in UTC.
+    pub taken_at: String,
+    pub movies: Vec<StoredMovie>,
+    pub users: Vec<StoredUser>,
+    pub links: Vec<Link>,
+    pub trash: Vec<StoredTrashed>,
+}
+
+impl BackupFile {
+    pub fn new(backup: Backup, taken_at: OffsetDateTime) -> BackupFile {
+        BackupFile {
+            taken_at: taken_at.format(&Rfc3339).unwrap_or_default(),
+            movies: backup.movies.into_iter().map(StoredMovie::from).collect(),
+            users: backup.users.into_iter().map(StoredUser::from).collect(),
+            links: backup.links,
+            trash: backup.trash.into_iter().map(StoredTrashed::from).collect(),
+        }
+    }
+
+    pub fn into_backup(self) -> Backup {
+        Backup {
+            movies: self.movies.into_iter().map(Into::into).collect(),
+            users: self.users.into_iter().map(Into::into).collect(),
+            links: self.links,
+            trash: self.trash.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+// e.g. movies-20261015T093000.250Z.json, which sorts oldest first.
+pub fn file_name(taken_at: OffsetDateTime) -> String {
+    let stamp = taken_at.format(format_description!("[year][month][day]T[hour][minute][second].[subsecond digits:3]Z")).unwrap_or_default();
+    format!("movies-{}.json", stamp)
+}
+
+// Restores only read files straight out of --backup-dir, never from anywhere else a name could lead.
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && !name.starts_with('.') && !name.contains(['/', '\\'])
+}
+
+pub async fn write(dir: &Path, name: &str, file: &BackupFile) -> io::Result<()> {
+    let contents = serde_json::to_vec(file)?;
+    // Written next to where it goes and renamed into place, so a half-written backup never looks like a whole one.
+    let temp_path = dir.join(format!(".{}.tmp", name));
+    tokio::fs::write(&temp_path, contents).await?;
+    tokio::fs::rename(&temp_path, dir.join(name)).await
+}
+
+pub async fn read(dir: &Path, name: &str) -> io::Result<BackupFile> {
+    let contents = tokio::fs::read(dir.join(name)).await?;
+    serde_json::from_slice(&contents).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+// Stores count on nothing pointing at a movie that isn't there, which a backup edited by hand might break.
+pub fn check(backup: &Backup) -> Result<(), String> {
+    let mut movie_ids = BTreeSet::new();
+    for movie in &backup.movies {
+        if !movie_ids.insert(movie.id.as_str()) {
+            return Err(format!("Movie {} is in the backup more than once", movie.id));
+        }
+    }
+    let mut user_ids = BTreeSet::new();
+    for user in &backup.users {
+        if !user_ids.insert(user.id.as_str()) {
+            return Err(format!("User {} is in the backup more than once", user.id));
+        }
+        if let Some(movie_id) = user.watchlist.iter().chain(&user.favorites).find(|movie_id| !movie_ids.contains(movie_id.as_str())) {
+            return Err(format!("User {} has movie {}, which isn't in the backup", user.id, movie_id));
+        }
+    }
+    if let Some(link) = backup.links.iter().find(|link| !movie_ids.contains(link.from.as_str()) || !movie_ids.contains(link.to.as_str())) {
+        return Err(format!("Link from {} to {} is to a movie that isn't in the backup", link.from, link.to));
+    }
+    let mut trashed_ids = BTreeSet::new();
+    for trashed in &backup.trash {
+        if !trashed_ids.insert(trashed.movie.id.as_str()) {
+            return Err(format!("Movie {} is in the backup's trash more than once", trashed.movie.id));
+        }
+    }
+    Ok(())
+}
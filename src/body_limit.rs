@@ -0,0 +1,98 @@
+use std::{error::Error, fmt, future};
+use axum::{body::Body, BoxError, extract::{Extension, MatchedPath, Request, State}, http::{header, Method, StatusCode}, middleware::Next, response::{IntoResponse, Response}};
+use futures_util::StreamExt;
+
+use crate::error::ApiError;
+use crate::settings::Settings;
+use crate::versioning;
+
+// Caps how big a request body may be, so a client can't run the server out of memory with a huge POST. Bodies that
+// say up front they're too big are turned away before any of them is read, and the rest once they get there. Either
+// way the client gets a 413 saying what the limit is, rather than the connection being dropped.
+
+// Enough for a batch of 1000 movies.
+pub const DEFAULT_MAX_BODY_BYTES: usize = 2 * 1024 * 1024;
+pub const DEFAULT_MAX_UPLOAD_BYTES: usize = 1024 * 1024 * 1024;
+
+// The limit of a route that doesn't have --max-body-bytes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BodyLimit {
+    // --max-upload-bytes, for whole files like CSV imports and backups.
+    Upload,
+    // A size of the route's own, which it checks more closely itself.
+    Fixed(usize),
+}
+
+impl BodyLimit {
+    fn bytes(self, settings: &Settings) -> usize {
+        match self {
+            BodyLimit::Upload => settings.max_upload_bytes(),
+            BodyLimit::Fixed(bytes) => bytes,
+        }
+    }
+}
+
+// What a body that went past its limit fails with, carrying the limit. Handlers reading bodies themselves turn it into
+// a 413 with read_error.
+#[derive(Debug)]
+pub struct BodyTooLarge(pub usize);
+
+impl fmt::Display for BodyTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "the body is over {} bytes", self.0)
+    }
+}
+
+impl Error for BodyTooLarge {}
+
+// The limit `error` was caused by going past, if it was.
+pub fn exceeded(error: &(dyn Error + 'static)) -> Option<usize> {
+    let mut cause = Some(error);
+    while let Some(error) = cause {
+        if let Some(BodyTooLarge(limit)) = error.downcast_ref() {
+            return Some(*limit);
+        }
+        cause = error.source();
+    }
+    None
+}
+
+// For handlers reading the body a chunk at a time.
+pub fn read_error(error: axum::Error) -> ApiError {
+    match exceeded(&error) {
+        Some(limit) => ApiError::PayloadTooLarge(limit),
+        None => ApiError::InvalidBody(StatusCode::BAD_REQUEST, format!("Failed to read the body: {}", error)),
+    }
+}
+
+// Middleware for the whole router. Routes in the state get the limit listed with them, in any API version, and the
+// rest --max-body-bytes.
+pub async fn limit_bodies(State(limits): State<&'static [(&'static str, BodyLimit)]>, Extension(settings): Extension<Settings>, request: Request, next: Next) -> Response {
+    if matches!(*request.method(), Method::GET | Method::HEAD | Method::OPTIONS) {
+        return next.run(request).await;
+    }
+    let route = request.extensions().get::<MatchedPath>().map_or(request.uri().path(), MatchedPath::as_str);
+    let route = versioning::unversioned_route(route);
+    let limit = match limits.iter().find(|(limited, _)| *limited == route) {
+        Some((_, limit)) => limit.bytes(&settings),
+        None => settings.max_body_bytes(),
+    };
+    let declared = request.headers().get(header::CONTENT_LENGTH).and_then(|length| length.to_str().ok()?.parse::<u64>().ok());
+    if declared.is_some_and(|length| length > limit as u64) {
+        return ApiError::PayloadTooLarge(limit).into_response();
+    }
+    let request = request.map(|body| {
+        let limited = body.into_data_stream().scan(0, move |read, chunk| {
+            let chunk = chunk.map_err(BoxError::from).and_then(|chunk| {
+                *read += chunk.len();
+                if *read > limit {
+                    return Err(BoxError::from(BodyTooLarge(limit)));
+                }
+                Ok(chunk)
+            });
+            future::ready(Some(chunk))
+        });
+        Body::from_stream(limited)
+    });
+    next.run(request).await
+}
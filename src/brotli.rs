@@ -0,0 +1,302 @@
+use std::{cmp::Reverse, collections::BinaryHeap, ops::Range};
+
+use crate::gzip::{lz77, BitWriter, Token};
+
+// A brotli (RFC 7932) encoder, for compressing responses. Like the gzip one it works a chunk at a time, so streamed
+// bodies stay streamed.
+//
+// Each chunk becomes meta-blocks with one block type and one prefix code each for literals, commands and distances,
+// built from that chunk's counts, and the same search for repeats gzip.rs does, with brotli's bigger window. That
+// leaves out context modeling, the static dictionary and distances from the ring buffer, which is where the reference
+// encoder gets the rest of its edge over gzip. Request bodies can only be gzip, so there's no decoder.
+
+// log2 of the window, as the stream header declares it.
+const WINDOW_BITS: u32 = 22;
+const WINDOW: usize = (1 << WINDOW_BITS) - 16;
+// Meta-blocks can't be longer than this.
+const MAX_META_BLOCK: usize = 1 << 24;
+const MAX_MATCH: usize = 1 << 16;
+
+// Base lengths and extra bits of insert length codes 0..=23.
+const INSERT_BASE: [u32; 24] = [0, 1, 2, 3, 4, 5, 6, 8, 10, 14, 18, 26, 34, 50, 66, 98, 130, 194, 322, 578, 1090, 2114, 6210, 22594];
+const INSERT_EXTRA: [u32; 24] = [0, 0, 0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 7, 8, 9, 10, 12, 14, 24];
+// Base lengths and extra bits of copy length codes 0..=23.
+const COPY_BASE: [u32; 24] = [2, 3, 4, 5, 6, 7, 8, 9, 10, 12, 14, 18, 22, 30, 38, 54, 70, 102, 134, 198, 326, 582, 1094, 2118];
+const COPY_EXTRA: [u32; 24] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 7, 8, 9, 10, 24];
+// The first command symbol for each pair of insert and copy length codes, by which eight of them they're in. Only the
+// cells that read a distance, the others reuse the last one.
+const COMMAND_CELLS: [[u16; 3]; 3] = [[128, 192, 384], [256, 320, 512], [448, 576, 640]];
+// The order code length code lengths come in, and the fixed code each of those lengths is written with.
+const CODE_LENGTH_ORDER: [usize; 18] = [1, 2, 3, 4, 0, 5, 17, 6, 16, 7, 8, 9, 10, 11, 12, 13, 14, 15];
+const CODE_LENGTH_CODE: [(u32, u32); 6] = [(0, 2), (7, 4), (3, 3), (2, 2), (1, 2), (15, 4)];
+
+// Bits it takes to write a symbol of each alphabet in a simple prefix code.
+const LITERAL_BITS: u32 = 8;
+const COMMAND_BITS: u32 = 10;
+const DISTANCE_BITS: u32 = 6;
+
+// Code lengths for symbols seen `counts` times, none longer than `limit`. Where the plain Huffman code would go past
+// it, the counts are flattened until it doesn't, which is what the reference encoder does too.
+fn code_lengths(counts: &[u32], limit: u8) -> Vec<u8> {
+    let mut counts = counts.to_vec();
+    loop {
+        let symbols: Vec<usize> = (0..counts.len()).filter(|symbol| counts[*symbol] > 0).collect();
+        // The parent of each node: the symbols first, then the nodes joining them.
+        let mut parents = vec![usize::MAX; symbols.len()];
+        let mut heap: BinaryHeap<_> = symbols.iter().enumerate().map(|(node, symbol)| Reverse((counts[*symbol] as u64, node))).collect();
+        while heap.len() > 1 {
+            let Reverse((a, first)) = heap.pop().unwrap();
+            let Reverse((b, second)) = heap.pop().unwrap();
+            let node = parents.len();
+            parents.push(usize::MAX);
+            parents[first] = node;
+            parents[second] = node;
+            heap.push(Reverse((a + b, node)));
+        }
+        let mut lengths = vec![0u8; counts.len()];
+        for (node, symbol) in symbols.iter().enumerate() {
+            let mut depth = 0;
+            let mut at = node;
+            while parents[at] != usize::MAX {
+                at = parents[at];
+                depth += 1;
+            }
+            lengths[*symbol] = depth;
+        }
+        if lengths.iter().all(|len| *len <= limit) {
+            return lengths;
+        }
+        for count in counts.iter_mut().filter(|count| **count > 0) {
+            *count = count.div_ceil(2);
+        }
+    }
+}
+
+struct PrefixCode {
+    lengths: Vec<u8>,
+    codes: Vec<u32>,
+    // The one symbol there is, when there's only one. It's written with no bits at all.
+    only: Option<usize>,
+}
+
+impl PrefixCode {
+    fn new(counts: &[u32], limit: u8) -> PrefixCode {
+        let mut used = (0..counts.len()).filter(|symbol| counts[*symbol] > 0);
+        let (first, second) = (used.next(), used.next());
+        if second.is_none() {
+            let only = first.unwrap_or(0);
+            let mut lengths = vec![0; counts.len()];
+            lengths[only] = 1;
+            return PrefixCode { lengths, codes: vec![0; counts.len()], only: Some(only) };
+        }
+        let lengths = code_lengths(counts, limit);
+        // Canonical codes, as in DEFLATE: shorter codes first, and in symbol order within a length.
+        let mut next = [0u32; 17];
+        for len in 1..=15 {
+            next[len + 1] = (next[len] + lengths.iter().filter(|l| **l as usize == len).count() as u32) << 1;
+        }
+        let codes = lengths.iter().map(|len| {
+            let code = next[*len as usize];
+            next[*len as usize] += 1;
+            code
+        }).collect();
+        PrefixCode { lengths, codes, only: None }
+    }
+
+    fn write(&self, writer: &mut BitWriter, symbol: usize) {
+        if self.only.is_none() {
+            writer.write_code(self.codes[symbol], self.lengths[symbol] as u32);
+        }
+    }
+
+    // Writes the code itself, for the decoder to rebuild, with symbols of `alphabet_bits` bits.
+    fn write_definition(&self, writer: &mut BitWriter, alphabet_bits: u32) {
+        if let Some(only) = self.only {
+            // A simple prefix code of one symbol.
+            writer.write(1, 2);
+            writer.write(0, 2);
+            writer.write(only as u32, alphabet_bits);
+            return;
+        }
+        // The decoder stops reading lengths when the code is complete, which it is at the last symbol that has one.
+        let last = self.lengths.iter().rposition(|len| *len > 0).unwrap();
+        let lengths = &self.lengths[..=last];
+        let mut counts = [0u32; 18];
+        for len in lengths {
+            counts[*len as usize] += 1;
+        }
+        let length_code = PrefixCode::new(&counts, 5);
+        // No code length code lengths skipped.
+        writer.write(0, 2);
+        // Those stop the same way, unless there's only one, in which case all of them are written.
+        let mut space = 32;
+        for symbol in CODE_LENGTH_ORDER {
+            let len = length_code.lengths[symbol];
+            let (code, code_len) = CODE_LENGTH_CODE[len as usize];
+            writer.write(code, code_len);
+            if len > 0 {
+                space -= 32 >> len;
+                if space == 0 {
+                    break;
+                }
+            }
+        }
+        for len in lengths {
+            length_code.write(writer, *len as usize);
+        }
+    }
+}
+
+// Which of the length codes `len` falls in, and what's left over for its extra bits.
+fn length_code(bases: &[u32; 24], len: usize) -> (usize, u32) {
+    let code = bases.iter().rposition(|base| *base as usize <= len).unwrap();
+    (code, len as u32 - bases[code])
+}
+
+// The distance code for `distance` with no postfix bits and no direct codes, its extra bits and how many there are.
+fn distance_symbol(distance: usize) -> (usize, u32, u32) {
+    let offset = distance as u32 + 3;
+    let bits = 31 - offset.leading_zeros() - 1;
+    let high = (offset >> bits) & 1;
+    (16 + 2 * (bits as usize - 1) + high as usize, offset - ((2 + high) << bits), bits)
+}
+
+// Literals inserted, then `copy` bytes from `distance` back. Only the last command of a meta-block may copy nothing.
+struct Command {
+    literals: Range<usize>,
+    copy: usize,
+    distance: usize,
+}
+
+impl Command {
+    fn codes(&self) -> (usize, (usize, u32), (usize, u32)) {
+        let insert = length_code(&INSERT_BASE, self.literals.len());
+        // A command that copies nothing still says a copy length, which the decoder doesn't get to.
+        let copy = length_code(&COPY_BASE, self.copy.max(2));
+        let symbol = COMMAND_CELLS[insert.0 >> 3][copy.0 >> 3] as usize + ((insert.0 & 7) << 3 | (copy.0 & 7));
+        (symbol, insert, copy)
+    }
+}
+
+pub struct BrotliEncoder {
+    writer: BitWriter,
+    started: bool,
+}
+
+impl Default for BrotliEncoder {
+    fn default() -> BrotliEncoder {
+        BrotliEncoder::new()
+    }
+}
+
+impl BrotliEncoder {
+    pub fn new() -> BrotliEncoder {
+        BrotliEncoder { writer: BitWriter::new(), started: false }
+    }
+
+    fn header(&mut self) {
+        if !self.started {
+            self.writer.write(1, 1);
+            self.writer.write(WINDOW_BITS - 17, 3);
+            self.started = true;
+        }
+    }
+
+    // Compresses one chunk and returns what can be sent so far. Each chunk ends with an empty metadata block, which
+    // gets it to a byte boundary so the client can decompress everything it has been sent.
+    pub fn chunk(&mut self, data: &[u8]) -> Vec<u8> {
+        self.header();
+        if !data.is_empty() {
+            for block in data.chunks(MAX_META_BLOCK) {
+                self.meta_block(block);
+            }
+            // Not the last, metadata, reserved bit, no bytes to skip.
+            self.writer.write(0, 1);
+            self.writer.write(3, 2);
+            self.writer.write(0, 1);
+            self.writer.write(0, 2);
+            self.writer.align();
+        }
+        self.writer.take()
+    }
+
+    // Ends the stream with an empty last meta-block.
+    pub fn finish(mut self) -> Vec<u8> {
+        self.header();
+        self.writer.write(1, 1);
+        self.writer.write(1, 1);
+        self.writer.align();
+        self.writer.take()
+    }
+
+    fn meta_block(&mut self, data: &[u8]) {
+        let mut commands = Vec::new();
+        let (mut position, mut literals_start) = (0, 0);
+        lz77(data, WINDOW, MAX_MATCH, |token| match token {
+            Token::Literal(_) => position += 1,
+            Token::Match { len, distance } => {
+                commands.push(Command { literals: literals_start..position, copy: len, distance });
+                position += len;
+                literals_start = position;
+            },
+        });
+        if literals_start < data.len() {
+            commands.push(Command { literals: literals_start..data.len(), copy: 0, distance: 0 });
+        }
+
+        let (mut literal_counts, mut command_counts, mut distance_counts) = ([0u32; 256], [0u32; 704], [0u32; 64]);
+        for command in &commands {
+            command_counts[command.codes().0] += 1;
+            for byte in &data[command.literals.clone()] {
+                literal_counts[*byte as usize] += 1;
+            }
+            if command.copy > 0 {
+                distance_counts[distance_symbol(command.distance).0] += 1;
+            }
+        }
+        let literal_code = PrefixCode::new(&literal_counts, 15);
+        let command_code = PrefixCode::new(&command_counts, 15);
+        let distance_code = PrefixCode::new(&distance_counts, 15);
+
+        // Not the last, as few nibbles of length as it fits in, compressed.
+        let len = data.len() as u32 - 1;
+        let nibbles = (32 - len.leading_zeros()).div_ceil(4).max(4);
+        self.writer.write(0, 1);
+        self.writer.write(nibbles - 4, 2);
+        self.writer.write(len, nibbles * 4);
+        self.writer.write(0, 1);
+        // One block type for literals, commands and distances, no postfix bits or direct distance codes, the first
+        // context mode for the one literal block type, and one prefix code for literals and one for distances.
+        self.writer.write(0, 3);
+        self.writer.write(0, 2);
+        self.writer.write(0, 4);
+        self.writer.write(0, 2);
+        self.writer.write(0, 2);
+        literal_code.write_definition(&mut self.writer, LITERAL_BITS);
+        command_code.write_definition(&mut self.writer, COMMAND_BITS);
+        distance_code.write_definition(&mut self.writer, DISTANCE_BITS);
+
+        for command in &commands {
+            let (symbol, (insert, insert_extra), (copy, copy_extra)) = command.codes();
+            command_code.write(&mut self.writer, symbol);
+            self.writer.write(insert_extra, INSERT_EXTRA[insert]);
+            self.writer.write(copy_extra, COPY_EXTRA[copy]);
+            for byte in &data[command.literals.clone()] {
+                literal_code.write(&mut self.writer, *byte as usize);
+            }
+            if command.copy > 0 {
+                let (code, extra, bits) = distance_symbol(command.distance);
+                distance_code.write(&mut self.writer, code);
+                self.writer.write(extra, bits);
+            }
+        }
+    }
+}
+
+// The whole of data, compressed.
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    let mut encoder = BrotliEncoder::new();
+    let mut out = encoder.chunk(data);
+    out.extend(encoder.finish());
+    out
+}
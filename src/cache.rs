@@ -0,0 +1,338 @@
+use std::{collections::{BTreeMap, HashMap}, sync::{atomic::{AtomicU64, Ordering}, Arc, Mutex}, time::{Duration, Instant}};
+use time::OffsetDateTime;
+use tokio::sync::broadcast;
+use tracing::{info_span, Instrument};
+
+use crate::changes::{ChangePage, CursorExpired, Since};
+use crate::events::MovieEvent;
+use crate::labels::Label;
+use crate::model::{Link, Movie, MoviePatch, Revision, Trashed, User, UserChange};
+use crate::store::{Backup, MovieFilter, MovieStore, Precondition, StoreError, StoreFuture};
+use crate::tenant::{self, TenantConfig};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CacheConfig {
+    pub capacity: usize,
+    // Entries older than this are treated as misses. None keeps them until they're evicted or invalidated.
+    pub ttl: Option<Duration>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: usize,
+}
+
+struct CacheEntry {
+    movie: Movie,
+    inserted: Instant,
+    // Position in Lru::recency.
+    last_used: u64,
+}
+
+// Least-recently-used map. `recency` maps a use counter to the key that was used at that point, so its first entry
+// is always the one to evict.
+struct Lru {
+    capacity: usize,
+    ttl: Option<Duration>,
+    entries: HashMap<String, CacheEntry>,
+    recency: BTreeMap<u64, String>,
+    clock: u64,
+    // Bumped on every write that goes through the cache, see CachedMovieStore::get.
+    write_epoch: u64,
+}
+
+impl Lru {
+    fn touch(&mut self, id: &str) -> u64 {
+        self.clock += 1;
+        let entry = self.entries.get_mut(id).expect("touched a cache entry that isn't there");
+        self.recency.remove(&entry.last_used);
+        self.recency.insert(self.clock, id.to_string());
+        entry.last_used = self.clock;
+        self.clock
+    }
+
+    fn remove(&mut self, id: &str) {
+        if let Some(entry) = self.entries.remove(id) {
+            self.recency.remove(&entry.last_used);
+        }
+    }
+
+    // Evicts least recently used entries until at most `len` are left.
+    fn shrink_to(&mut self, len: usize) {
+        while self.entries.len() > len {
+            let Some((_, oldest)) = self.recency.pop_first() else { break };
+            self.entries.remove(&oldest);
+        }
+    }
+
+    fn insert(&mut self, key: String, movie: Movie) {
+        self.remove(&key);
+        self.shrink_to(self.capacity - 1);
+        self.entries.insert(key.clone(), CacheEntry { movie, inserted: Instant::now(), last_used: 0 });
+        self.touch(&key);
+    }
+}
+
+// Read-through cache in front of another store. Reads by id are served from memory when possible; every write goes
+// straight to the backing store and drops the cached copy. Listing always goes to the backing store. Movies are cached
+// under their tenant as well as their id, see tenant::namespaced, since tenants may use the same ids.
+pub struct CachedMovieStore {
+    inner: Arc<dyn MovieStore>,
+    lru: Mutex<Lru>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl CachedMovieStore {
+    pub fn new(inner: Arc<dyn MovieStore>, config: CacheConfig) -> CachedMovieStore {
+        CachedMovieStore {
+            inner,
+            lru: Mutex::new(Lru {
+                capacity: config.capacity.max(1),
+                ttl: config.ttl,
+                entries: HashMap::new(),
+                recency: BTreeMap::new(),
+                clock: 0,
+                write_epoch: 0,
+            }),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    // Applies a new capacity and TTL in place. Only entries over the new capacity are dropped.
+    pub fn reconfigure(&self, config: &CacheConfig) {
+        let mut lru = self.lru.lock().unwrap();
+        lru.capacity = config.capacity.max(1);
+        lru.ttl = config.ttl;
+        let capacity = lru.capacity;
+        lru.shrink_to(capacity);
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            entries: self.lru.lock().unwrap().entries.len(),
+        }
+    }
+
+    // Returns the cached movie, if there's a fresh one, or else the current write epoch.
+    fn lookup(&self, key: &str) -> Result<Movie, u64> {
+        let mut lru = self.lru.lock().unwrap();
+        let expired = match lru.entries.get(key) {
+            Some(entry) => lru.ttl.is_some_and(|ttl| entry.inserted.elapsed() > ttl),
+            None => return Err(lru.write_epoch),
+        };
+        if expired {
+            lru.remove(key);
+            return Err(lru.write_epoch);
+        }
+        lru.touch(key);
+        Ok(lru.entries[key].movie.clone())
+    }
+
+    fn invalidate(&self, id: &str) {
+        let mut lru = self.lru.lock().unwrap();
+        lru.write_epoch += 1;
+        lru.remove(&tenant::namespaced(id));
+    }
+
+    fn invalidate_all(&self) {
+        let mut lru = self.lru.lock().unwrap();
+        lru.write_epoch += 1;
+        lru.entries.clear();
+        lru.recency.clear();
+    }
+}
+
+impl MovieStore for CachedMovieStore {
+    fn get<'a>(&'a self, id: &'a str) -> StoreFuture<'a, Option<Movie>> {
+        let span = info_span!("cache.get", hit = tracing::field::Empty);
+        let outcome = span.clone();
+        Box::pin(async move {
+            let key = tenant::namespaced(id);
+            let epoch = match self.lookup(&key) {
+                Ok(movie) => {
+                    self.hits.fetch_add(1, Ordering::Relaxed);
+                    outcome.record("hit", true);
+                    return Some(movie);
+                },
+                Err(epoch) => epoch,
+            };
+            self.misses.fetch_add(1, Ordering::Relaxed);
+            outcome.record("hit", false);
+            let movie = self.inner.get(id).await?;
+            let mut lru = self.lru.lock().unwrap();
+            // If something was written while we were reading, what we read may already be stale. Don't cache it.
+            if lru.write_epoch == epoch {
+                lru.insert(key, movie.clone());
+            }
+            Some(movie)
+        }.instrument(span))
+    }
+
+    fn insert(&self, movie: Movie) -> StoreFuture<'_, Result<(), StoreError>> {
+        Box::pin(async move {
+            let id = movie.id.clone();
+            let result = self.inner.insert(movie).await;
+            self.invalidate(&id);
+            result
+        })
+    }
+
+    fn upsert(&self, movie: Movie) -> StoreFuture<'_, Result<bool, StoreError>> {
+        Box::pin(async move {
+            let id = movie.id.clone();
+            let result = self.inner.upsert(movie).await;
+            self.invalidate(&id);
+            result
+        })
+    }
+
+    fn put(&self, movie: Movie) -> StoreFuture<'_, Result<bool, StoreError>> {
+        Box::pin(async move {
+            let id = movie.id.clone();
+            let result = self.inner.put(movie).await;
+            self.invalidate(&id);
+            result
+        })
+    }
+
+    fn update_if<'a>(&'a self, movie: Movie, precondition: Precondition<'a>) -> StoreFuture<'a, Result<(), StoreError>> {
+        Box::pin(async move {
+            let id = movie.id.clone();
+            let result = self.inner.update_if(movie, precondition).await;
+            self.invalidate(&id);
+            result
+        })
+    }
+
+    fn patch<'a>(&'a self, id: &'a str, patch: MoviePatch) -> StoreFuture<'a, Result<Movie, StoreError>> {
+        Box::pin(async move {
+            let result = self.inner.patch(id, patch).await;
+            self.invalidate(id);
+            result
+        })
+    }
+
+    fn delete_if<'a>(&'a self, id: &'a str, precondition: Precondition<'a>) -> StoreFuture<'a, Result<Movie, StoreError>> {
+        Box::pin(async move {
+            let result = self.inner.delete_if(id, precondition).await;
+            self.invalidate(id);
+            result
+        })
+    }
+
+    fn trash(&self) -> StoreFuture<'_, Vec<Trashed>> {
+        self.inner.trash()
+    }
+
+    fn restore<'a>(&'a self, id: &'a str) -> StoreFuture<'a, Result<Movie, StoreError>> {
+        Box::pin(async move {
+            let result = self.inner.restore(id).await;
+            self.invalidate(id);
+            result
+        })
+    }
+
+    // Only touches movies in the trash, which are never cached.
+    fn purge(&self, deleted_before: OffsetDateTime) -> StoreFuture<'_, Result<Vec<String>, StoreError>> {
+        self.inner.purge(deleted_before)
+    }
+
+    fn backup(&self) -> StoreFuture<'_, Backup> {
+        self.inner.backup()
+    }
+
+    fn replace(&self, backup: Backup) -> StoreFuture<'_, Result<(), StoreError>> {
+        Box::pin(async move {
+            let result = self.inner.replace(backup).await;
+            self.invalidate_all();
+            result
+        })
+    }
+
+    fn list<'a>(&'a self, filter: &'a MovieFilter) -> StoreFuture<'a, Vec<Movie>> {
+        self.inner.list(filter)
+    }
+
+    fn scan<'a>(&'a self, after: Option<&'a str>, limit: usize) -> StoreFuture<'a, Vec<Movie>> {
+        self.inner.scan(after, limit)
+    }
+
+    fn suggest<'a>(&'a self, prefix: &'a str, limit: usize) -> StoreFuture<'a, Vec<String>> {
+        self.inner.suggest(prefix, limit)
+    }
+
+    fn genres(&self) -> StoreFuture<'_, Vec<Label>> {
+        self.inner.genres()
+    }
+
+    fn tags(&self) -> StoreFuture<'_, Vec<Label>> {
+        self.inner.tags()
+    }
+
+    fn genre_movie_ids<'a>(&'a self, genre: &'a str) -> StoreFuture<'a, Vec<String>> {
+        self.inner.genre_movie_ids(genre)
+    }
+
+    fn count(&self) -> StoreFuture<'_, usize> {
+        self.inner.count()
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<MovieEvent> {
+        self.inner.subscribe()
+    }
+
+    fn changes(&self, since: &Since, limit: usize) -> Result<ChangePage, CursorExpired> {
+        self.inner.changes(since, limit)
+    }
+
+    fn check_ready(&self) -> StoreFuture<'_, Result<(), StoreError>> {
+        self.inner.check_ready()
+    }
+
+    fn close(&self) -> StoreFuture<'_, Result<(), StoreError>> {
+        self.inner.close()
+    }
+
+    fn history<'a>(&'a self, id: &'a str) -> StoreFuture<'a, Option<Vec<Revision>>> {
+        self.inner.history(id)
+    }
+
+    fn tenants(&self) -> Vec<TenantConfig> {
+        self.inner.tenants()
+    }
+
+    fn max_movies(&self) -> Option<usize> {
+        self.inner.max_movies()
+    }
+
+    // Only movies are cached.
+    fn get_user<'a>(&'a self, id: &'a str) -> StoreFuture<'a, Option<User>> {
+        self.inner.get_user(id)
+    }
+
+    fn insert_user(&self, user: User) -> StoreFuture<'_, Result<(), StoreError>> {
+        self.inner.insert_user(user)
+    }
+
+    fn change_user<'a>(&'a self, user_id: &'a str, change: UserChange) -> StoreFuture<'a, Result<User, StoreError>> {
+        self.inner.change_user(user_id, change)
+    }
+
+    fn links<'a>(&'a self, movie_id: &'a str) -> StoreFuture<'a, Vec<Link>> {
+        self.inner.links(movie_id)
+    }
+
+    fn link(&self, link: Link) -> StoreFuture<'_, Result<bool, StoreError>> {
+        self.inner.link(link)
+    }
+
+    fn unlink<'a>(&'a self, link: &'a Link) -> StoreFuture<'a, Result<bool, StoreError>> {
+        self.inner.unlink(link)
+    }
+}
@@ -0,0 +1,262 @@
+use std::{collections::VecDeque, fs::{File, OpenOptions}, io::{self, Write}, path::{Path, PathBuf}};
+use serde::{Deserialize, Serialize};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use tracing::warn;
+
+use crate::crypto;
+use crate::events::MovieEvent;
+use crate::model::{Movie, StoredMovie};
+use crate::random;
+
+// The feed behind GET /movies/changes, for clients that go offline: rather than download every movie again when they
+// reconnect, they hand back the cursor from their last page and get the changes made since, in order. Each store keeps
+// the latest MAX_CHANGES of its changes, numbered as it makes them, and a cursor names the log it came from as well as
+// the number. One from a log the store no longer has, or from so long ago that the changes after it have been dropped,
+// is refused with a 410: the client has to take a fresh cursor with since=latest and then download everything again.
+// Followers, see replication.rs, start out the same way.
+//
+// Stores that keep their movies on disk keep the log in a file next to theirs, so cursors survive a restart. Only a
+// clean one, though: the file is marked closed on shutdown, after the store has written everything out, and a log
+// that wasn't closed is started over, since the store may have changes the file missed or the other way around. Stores
+// in memory start over with the process, as their movies do.
+
+// How many changes each store keeps for the feed.
+pub const MAX_CHANGES: usize = 10_000;
+
+// One change, as the feed lists it.
+#[derive(Debug, Clone, Serialize)]
+pub struct Change {
+    // 1 for the store's first change, and one more for each after it.
+    pub seq: u64,
+    // created, updated or deleted.
+    pub event: &'static str,
+    pub id: String,
+    // RFC 3339, in UTC.
+    pub at: String,
+    // The movie as it is after the change, or as it was before a delete.
+    pub movie: Movie,
+}
+
+// Where a client is up to in a store's log. Opaque to clients, who only ever hand back what they were given. It isn't
+// signed like the list cursors are, since a made-up one only gets its maker changes they could have asked for anyway.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChangeCursor {
+    // The log the cursor is from.
+    pub log: String,
+    // The last change the client has seen, 0 for none.
+    pub seq: u64,
+}
+
+impl ChangeCursor {
+    pub fn encode(&self) -> String {
+        crypto::base64url_encode(&serde_json::to_vec(self).expect("cursors always serialize"))
+    }
+
+    pub fn decode(token: &str) -> Option<ChangeCursor> {
+        serde_json::from_slice(&crypto::base64_decode(token)?).ok()
+    }
+}
+
+// Where a page of the feed starts.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Since {
+    // The oldest change the store still has.
+    Oldest,
+    // After the newest change, so the page is empty but for a cursor for what comes next.
+    Latest,
+    Cursor(ChangeCursor),
+}
+
+// A page of the feed: the changes after the cursor asked for, and the cursor to ask with next. When there are no
+// changes it's the same cursor, so clients can keep the last one they got either way.
+#[derive(Debug, Clone, Serialize)]
+pub struct ChangePage {
+    pub items: Vec<Change>,
+    pub cursor: String,
+    // Whether more changes were already made than fit on the page, so the client should ask again straight away.
+    pub has_more: bool,
+}
+
+// The cursor is from another log, or the changes right after it have been dropped.
+#[derive(Debug)]
+pub struct CursorExpired;
+
+pub struct ChangeLog {
+    id: String,
+    // Oldest first.
+    changes: VecDeque<Change>,
+    last_seq: u64,
+    file: Option<LogFile>,
+}
+
+struct LogFile {
+    path: PathBuf,
+    file: File,
+    // Changes appended since the file was last written anew.
+    appended: usize,
+}
+
+// One line of a log's file. The first names the log, and after a clean shutdown the last says so.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "line", rename_all = "snake_case")]
+enum Line {
+    Log { id: String },
+    Change { seq: u64, event: String, at: String, movie: Box<StoredMovie> },
+    Closed { last_seq: u64 },
+}
+
+impl Line {
+    fn change(change: &Change) -> Line {
+        Line::Change { seq: change.seq, event: change.event.to_string(), at: change.at.clone(), movie: Box::new(change.movie.clone().into()) }
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut line = serde_json::to_vec(self).expect("change log lines always serialize");
+        line.push(b'\n');
+        line
+    }
+}
+
+// Where a store with its movies at `path` keeps its log: next to them, with .changes added to the name.
+pub fn path_beside(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".changes");
+    PathBuf::from(name)
+}
+
+// The log in a file's contents, its changes and the number of its last change, if it was closed after its last change.
+fn read_closed(contents: &[u8]) -> Option<(String, VecDeque<Change>, u64)> {
+    let mut lines = contents.split(|byte| *byte == b'\n').filter(|line| !line.is_empty());
+    let Ok(Line::Log { id }) = serde_json::from_slice(lines.next()?) else {
+        return None;
+    };
+    let mut changes = VecDeque::new();
+    let mut closed = None;
+    for line in lines {
+        match serde_json::from_slice(line).ok()? {
+            Line::Change { seq, event, at, movie } => {
+                let event = ["created", "updated", "deleted"].into_iter().find(|name| *name == event)?;
+                let movie = Movie::from(*movie);
+                changes.push_back(Change { seq, event, id: movie.id.clone(), at, movie });
+                if changes.len() > MAX_CHANGES {
+                    changes.pop_front();
+                }
+                closed = None;
+            },
+            Line::Closed { last_seq } => closed = Some(last_seq),
+            Line::Log { .. } => return None,
+        }
+    }
+    Some((id, changes, closed?))
+}
+
+impl ChangeLog {
+    pub fn new() -> ChangeLog {
+        let mut id = [0u8; 12];
+        random::fill_bytes(&mut id);
+        ChangeLog { id: crypto::base64url_encode(&id), changes: VecDeque::new(), last_seq: 0, file: None }
+    }
+
+    // The log kept in the file at `path`, carrying on from where it was closed, or a new one kept there if it wasn't.
+    pub fn open(path: &Path) -> io::Result<ChangeLog> {
+        let contents = match std::fs::read(path) {
+            Ok(contents) => contents,
+            // First run.
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        let mut log = match read_closed(&contents) {
+            Some((id, changes, last_seq)) => ChangeLog { id, changes, last_seq, file: None },
+            None => {
+                if !contents.is_empty() {
+                    warn!("{} wasn't closed on shutdown, so cursors for its changes from before now get a 410", path.display());
+                }
+                ChangeLog::new()
+            },
+        };
+        // Which also takes the closed line off, as the log is open again.
+        log.rewrite(path)?;
+        Ok(log)
+    }
+
+    // Writes the file anew with only the changes kept, next to it first and then renamed over it.
+    fn rewrite(&mut self, path: &Path) -> io::Result<()> {
+        let mut contents = Line::Log { id: self.id.clone() }.to_bytes();
+        for change in &self.changes {
+            contents.extend(Line::change(change).to_bytes());
+        }
+        let mut temp_path = path.as_os_str().to_owned();
+        temp_path.push(".tmp");
+        std::fs::write(&temp_path, contents)?;
+        std::fs::rename(&temp_path, path)?;
+        let file = OpenOptions::new().append(true).open(path)?;
+        self.file = Some(LogFile { path: path.to_path_buf(), file, appended: 0 });
+        Ok(())
+    }
+
+    // Marks the file closed, once the store has written out everything up to the last change. A change recorded after
+    // this goes after the mark, which undoes it.
+    pub fn close(&mut self) -> io::Result<()> {
+        let Some(log_file) = &mut self.file else {
+            return Ok(());
+        };
+        log_file.file.write_all(&Line::Closed { last_seq: self.last_seq }.to_bytes())?;
+        log_file.file.sync_all()
+    }
+
+    // Appends the newest change to the file, or once it holds as many changes again as are kept, writes it anew.
+    fn append(&mut self) -> io::Result<()> {
+        let (Some(log_file), Some(change)) = (&mut self.file, self.changes.back()) else {
+            return Ok(());
+        };
+        if log_file.appended < MAX_CHANGES {
+            log_file.file.write_all(&Line::change(change).to_bytes())?;
+            log_file.appended += 1;
+            return Ok(());
+        }
+        let path = log_file.path.clone();
+        self.rewrite(&path)
+    }
+
+    pub fn record(&mut self, event: &MovieEvent) {
+        self.last_seq += 1;
+        self.changes.push_back(Change {
+            seq: self.last_seq,
+            event: event.name(),
+            id: event.movie().id.clone(),
+            at: OffsetDateTime::now_utc().format(&Rfc3339).unwrap_or_default(),
+            movie: event.movie().clone(),
+        });
+        if self.changes.len() > MAX_CHANGES {
+            self.changes.pop_front();
+        }
+        // The change has already been made. Without it the file can't be carried on from, so it's given up on, and
+        // without a closed line the next start begins a new log.
+        if let Err(e) = self.append() {
+            warn!("Failed to write change {} to the change log, cursors won't survive a restart: {}", self.last_seq, e);
+            self.file = None;
+        }
+    }
+
+    // Up to `limit` changes, starting from `since`.
+    pub fn page(&self, since: &Since, limit: usize) -> Result<ChangePage, CursorExpired> {
+        let oldest = self.changes.front().map_or(self.last_seq + 1, |change| change.seq);
+        let after_seq = match since {
+            Since::Oldest => oldest - 1,
+            Since::Latest => self.last_seq,
+            Since::Cursor(cursor) if cursor.log != self.id || cursor.seq > self.last_seq || cursor.seq + 1 < oldest => return Err(CursorExpired),
+            Since::Cursor(cursor) => cursor.seq,
+        };
+        let newer = self.changes.iter().skip((after_seq + 1 - oldest) as usize);
+        let items: Vec<Change> = newer.take(limit).cloned().collect();
+        let seq = items.last().map_or(after_seq, |change| change.seq);
+        let cursor = ChangeCursor { log: self.id.clone(), seq };
+        Ok(ChangePage { items, cursor: cursor.encode(), has_more: seq < self.last_seq })
+    }
+}
+
+impl Default for ChangeLog {
+    fn default() -> ChangeLog {
+        ChangeLog::new()
+    }
+}
@@ -0,0 +1,238 @@
+use std::{fmt, io, time::Duration};
+use axum::body::Bytes;
+use http_body_util::{BodyExt, Full};
+use hyper::{body::Incoming, header, Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use serde::de::DeserializeOwned;
+use time::format_description::well_known::Rfc3339;
+use tokio::net::TcpStream;
+
+use crate::auth::API_KEY_HEADER;
+use crate::events::MovieEvent;
+use crate::http_client;
+use crate::model::{Movie, MoviePage, NewMovie};
+use crate::store::MovieFilter;
+
+// A typed client for the API, built with the `client` feature. It sends and reads the same Movie, NewMovie and
+// MoviePage the server uses, so the two can't disagree on the JSON. Each call is a request on a connection of its own,
+// over plain http like the rest of this crate.
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug)]
+pub enum ClientError {
+    // Connecting or talking to the server failed, or it took longer than the timeout.
+    Io(io::Error),
+    // The server answered with an error, as its code and message.
+    Api { status: StatusCode, code: String, message: String },
+    // The server answered with something that isn't what the API sends.
+    InvalidResponse(String),
+    // A watch fell so far behind that the server dropped this many changes. Reload what's needed and keep watching.
+    Lagged(u64),
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Io(e) => write!(f, "{}", e),
+            ClientError::Api { status, code, message } => write!(f, "{} ({}): {}", status, code, message),
+            ClientError::InvalidResponse(problem) => write!(f, "invalid response: {}", problem),
+            ClientError::Lagged(missed) => write!(f, "missed {} changes", missed),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+impl From<io::Error> for ClientError {
+    fn from(error: io::Error) -> ClientError {
+        ClientError::Io(error)
+    }
+}
+
+impl From<hyper::Error> for ClientError {
+    fn from(error: hyper::Error) -> ClientError {
+        ClientError::Io(io::Error::other(error))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MoviesClient {
+    address: String,
+    host: String,
+    // Whatever path the base URL had, without a trailing slash, for a server behind a proxy that adds one.
+    base_path: String,
+    api_key: Option<String>,
+    timeout: Duration,
+}
+
+impl MoviesClient {
+    // For the server at an http:// URL, e.g. http://localhost:1234.
+    pub fn new(base_url: &str) -> Result<MoviesClient, ClientError> {
+        let (address, host, path) = http_client::parse_url(base_url)?;
+        Ok(MoviesClient { address, host, base_path: path.trim_end_matches('/').to_string(), api_key: None, timeout: DEFAULT_TIMEOUT })
+    }
+
+    // Sends the key as X-Api-Key, for servers where writes need one.
+    pub fn with_api_key(mut self, api_key: impl Into<String>) -> MoviesClient {
+        self.api_key = Some(api_key.into());
+        self
+    }
+
+    // How long a call may take before it fails. Doesn't apply to watch once it's started.
+    pub fn with_timeout(mut self, timeout: Duration) -> MoviesClient {
+        self.timeout = timeout;
+        self
+    }
+
+    pub async fn create(&self, movie: &NewMovie) -> Result<Movie, ClientError> {
+        let body = serde_json::to_vec(movie).expect("movies always serialize");
+        self.call(Method::POST, "/v1/movie", Some(body)).await
+    }
+
+    // None if there's no movie with the id.
+    pub async fn get(&self, id: &str) -> Result<Option<Movie>, ClientError> {
+        match self.call(Method::GET, &format!("/v1/movie/{}", encode(id)), None).await {
+            Err(ClientError::Api { status: StatusCode::NOT_FOUND, .. }) => Ok(None),
+            result => result.map(Some),
+        }
+    }
+
+    // One page of the movies matching the filter, in id order.
+    pub async fn list(&self, filter: &MovieFilter, limit: usize, offset: usize) -> Result<MoviePage, ClientError> {
+        let mut query = vec![("limit", limit.to_string()), ("offset", offset.to_string())];
+        let numbers = [("year", filter.year), ("year_gte", filter.year_gte), ("year_lte", filter.year_lte), ("runtime_gte", filter.runtime_gte), ("runtime_lte", filter.runtime_lte)];
+        query.extend(numbers.into_iter().filter_map(|(name, value)| Some((name, value?.to_string()))));
+        query.extend(filter.was_good.map(|was_good| ("was_good", was_good.to_string())));
+        let texts = [("name_contains", &filter.name_contains), ("genre", &filter.genre), ("director", &filter.director), ("tag", &filter.tag)];
+        query.extend(texts.into_iter().filter_map(|(name, value)| Some((name, value.clone()?))));
+        query.extend(filter.updated_since.and_then(|since| Some(("updated_since", since.format(&Rfc3339).ok()?))));
+        let query = serde_urlencoded::to_string(&query).expect("pairs of strings always encode");
+        self.call(Method::GET, &format!("/v1/movies?{}", query), None).await
+    }
+
+    // Deletes the movie, to the trash, where it can be restored from for a while.
+    pub async fn delete(&self, id: &str) -> Result<(), ClientError> {
+        let response = tokio::time::timeout(self.timeout, self.send(Method::DELETE, &format!("/v1/movie/{}", encode(id)), None)).await.map_err(|_| self.timed_out())??;
+        read(response, self.timeout).await.map(|_| ())
+    }
+
+    // Every change made from now on, as it happens, until the server shuts down or the watch is dropped.
+    pub async fn watch(&self) -> Result<MovieWatch, ClientError> {
+        let response = tokio::time::timeout(self.timeout, self.send(Method::GET, "/v1/movies/events", None)).await.map_err(|_| self.timed_out())??;
+        if !response.status().is_success() {
+            return Err(read(response, self.timeout).await.err().unwrap_or_else(|| ClientError::InvalidResponse("unexpected status".to_string())));
+        }
+        Ok(MovieWatch { body: response.into_body(), buffer: Vec::new() })
+    }
+
+    async fn call<T: DeserializeOwned>(&self, method: Method, path: &str, body: Option<Vec<u8>>) -> Result<T, ClientError> {
+        let response = tokio::time::timeout(self.timeout, self.send(method, path, body)).await.map_err(|_| self.timed_out())??;
+        let body = read(response, self.timeout).await?;
+        serde_json::from_slice(&body).map_err(|e| ClientError::InvalidResponse(e.to_string()))
+    }
+
+    async fn send(&self, method: Method, path: &str, body: Option<Vec<u8>>) -> Result<Response<Incoming>, ClientError> {
+        let stream = TcpStream::connect(&self.address).await?;
+        let (mut sender, connection) = hyper::client::conn::http1::handshake(TokioIo::new(stream)).await?;
+        // Drives the connection until the response has been read, then ends with it.
+        tokio::spawn(connection);
+        let mut request = Request::builder().method(method).uri(format!("{}{}", self.base_path, path))
+            .header(header::HOST, &self.host)
+            .header(header::ACCEPT, "application/json");
+        if body.is_some() {
+            request = request.header(header::CONTENT_TYPE, "application/json");
+        }
+        if let Some(api_key) = &self.api_key {
+            request = request.header(&API_KEY_HEADER, api_key);
+        }
+        let request = request.body(Full::new(Bytes::from(body.unwrap_or_default())))
+            .map_err(|e| ClientError::Io(io::Error::new(io::ErrorKind::InvalidInput, e)))?;
+        Ok(sender.send_request(request).await?)
+    }
+
+    fn timed_out(&self) -> ClientError {
+        ClientError::Io(io::Error::new(io::ErrorKind::TimedOut, format!("no response within {:?}", self.timeout)))
+    }
+}
+
+// Ids can be anything, so they're escaped to stay one path segment.
+fn encode(id: &str) -> String {
+    id.bytes().map(|byte| match byte {
+        b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (byte as char).to_string(),
+        _ => format!("%{:02X}", byte),
+    }).collect()
+}
+
+// The body of a successful response, or the error the server answered with instead.
+async fn read(response: Response<Incoming>, timeout: Duration) -> Result<Bytes, ClientError> {
+    let status = response.status();
+    let body = tokio::time::timeout(timeout, response.into_body().collect()).await
+        .map_err(|_| ClientError::Io(io::Error::new(io::ErrorKind::TimedOut, format!("response not read within {:?}", timeout))))??
+        .to_bytes();
+    if status.is_success() {
+        return Ok(body);
+    }
+    let error: serde_json::Value = serde_json::from_slice(&body).unwrap_or_default();
+    let field = |name: &str| error["error"][name].as_str().unwrap_or_default().to_string();
+    Err(ClientError::Api { status, code: field("code"), message: field("message") })
+}
+
+// The changes coming from GET /movies/events, see MoviesClient::watch.
+pub struct MovieWatch {
+    body: Incoming,
+    // What's been read of the event being received.
+    buffer: Vec<u8>,
+}
+
+impl MovieWatch {
+    // The next change, or None once the server has ended the stream.
+    pub async fn next(&mut self) -> Option<Result<MovieEvent, ClientError>> {
+        loop {
+            // Server-Sent Events end with a blank line.
+            if let Some(end) = self.buffer.windows(2).position(|window| window == b"\n\n") {
+                let event: Vec<u8> = self.buffer.drain(..end + 2).collect();
+                match parse_event(&String::from_utf8_lossy(&event)) {
+                    Some(parsed) => return Some(parsed),
+                    // A keep-alive comment.
+                    None => continue,
+                }
+            }
+            match self.body.frame().await? {
+                Ok(frame) => {
+                    if let Ok(data) = frame.into_data() {
+                        self.buffer.extend_from_slice(&data);
+                    }
+                },
+                Err(e) => return Some(Err(e.into())),
+            }
+        }
+    }
+}
+
+fn parse_event(event: &str) -> Option<Result<MovieEvent, ClientError>> {
+    let (mut name, mut data) = (None, String::new());
+    for line in event.lines() {
+        if let Some(value) = line.strip_prefix("event:") {
+            name = Some(value.trim_start());
+        }
+        else if let Some(value) = line.strip_prefix("data:") {
+            data.push_str(value.trim_start());
+        }
+    }
+    let name = name?;
+    if name == "lagged" {
+        let missed = serde_json::from_str::<serde_json::Value>(&data).ok().and_then(|lagged| lagged["missed"].as_u64()).unwrap_or(0);
+        return Some(Err(ClientError::Lagged(missed)));
+    }
+    let movie = match serde_json::from_str::<Movie>(&data) {
+        Ok(movie) => movie,
+        Err(e) => return Some(Err(ClientError::InvalidResponse(format!("{} event: {}", name, e)))),
+    };
+    Some(match name {
+        "created" => Ok(MovieEvent::Created(movie)),
+        "updated" => Ok(MovieEvent::Updated(movie)),
+        "deleted" => Ok(MovieEvent::Deleted(movie)),
+        other => Err(ClientError::InvalidResponse(format!("unknown event {:?}", other))),
+    })
+}
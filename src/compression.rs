@@ -0,0 +1,150 @@
+use std::io;
+use axum::{body::{Body, Bytes, HttpBody}, extract::Request, http::{header, HeaderMap, HeaderValue, Method, StatusCode}, middleware::Next, response::{IntoResponse, Response}};
+use futures_util::StreamExt;
+
+use crate::brotli::BrotliEncoder;
+use crate::error::ApiError;
+use crate::gzip::{GzipDecoder, GzipEncoder};
+
+// br or gzip for response bodies, whichever the client prefers, and gzip for request bodies sent with
+// Content-Encoding: gzip, like a compressed CSV for /movies/import.
+
+// Below this, compressing saves less than the headers it adds.
+const MIN_COMPRESS_BYTES: u64 = 1024;
+// A compressed request can't expand past this, so a small body can't keep the server decompressing forever.
+const MAX_DECOMPRESSED_BYTES: usize = 1024 * 1024 * 1024;
+
+enum Encoder {
+    Brotli(BrotliEncoder),
+    Gzip(GzipEncoder),
+}
+
+impl Encoder {
+    fn name(&self) -> &'static str {
+        match self {
+            Encoder::Brotli(_) => "br",
+            Encoder::Gzip(_) => "gzip",
+        }
+    }
+
+    fn chunk(&mut self, data: &[u8]) -> Vec<u8> {
+        match self {
+            Encoder::Brotli(encoder) => encoder.chunk(data),
+            Encoder::Gzip(encoder) => encoder.chunk(data),
+        }
+    }
+
+    fn finish(self) -> Vec<u8> {
+        match self {
+            Encoder::Brotli(encoder) => encoder.finish(),
+            Encoder::Gzip(encoder) => encoder.finish(),
+        }
+    }
+}
+
+// The coding Accept-Encoding gives the highest q, br when they're even, or None when it refuses both or lists
+// neither. * counts for a coding that isn't listed by name.
+fn negotiate(headers: &HeaderMap) -> Option<Encoder> {
+    let (mut br, mut gzip, mut any) = (None, None, None);
+    for coding in headers.get_all(header::ACCEPT_ENCODING).iter().filter_map(|value| value.to_str().ok()).flat_map(|value| value.split(',')) {
+        let mut parts = coding.split(';').map(str::trim);
+        let name = parts.next().unwrap_or_default();
+        let q = parts.find_map(|parameter| parameter.strip_prefix("q=")).map_or(1.0, |q| q.parse::<f32>().unwrap_or(0.0));
+        if name.eq_ignore_ascii_case("br") {
+            br = Some(q);
+        }
+        else if name.eq_ignore_ascii_case("gzip") || name.eq_ignore_ascii_case("x-gzip") {
+            gzip = Some(q);
+        }
+        else if name == "*" {
+            any = Some(q);
+        }
+    }
+    let (br, gzip) = (br.or(any).unwrap_or(0.0), gzip.or(any).unwrap_or(0.0));
+    if br > 0.0 && br >= gzip {
+        Some(Encoder::Brotli(BrotliEncoder::new()))
+    }
+    else if gzip > 0.0 {
+        Some(Encoder::Gzip(GzipEncoder::new()))
+    }
+    else {
+        None
+    }
+}
+
+// Text-like bodies compress well. Event streams are left alone, since clients read them as they go.
+fn compressible(headers: &HeaderMap) -> bool {
+    let Some(content_type) = headers.get(header::CONTENT_TYPE).and_then(|value| value.to_str().ok()) else { return false };
+    let content_type = content_type.to_ascii_lowercase();
+    !content_type.starts_with("text/event-stream")
+        && (content_type.starts_with("text/") || content_type.contains("json") || content_type.contains("xml") || content_type.contains("csv"))
+}
+
+// Middleware for the whole router. The ETag stays as it is: the content it identifies is the same either way, and
+// clients send it back in If-Match without knowing how it was transferred.
+pub async fn compress_responses(request: Request, next: Next) -> Response {
+    let encoder = negotiate(request.headers()).filter(|_| request.method() != Method::HEAD);
+    let mut response = next.run(request).await;
+    if !compressible(response.headers()) || matches!(response.status(), StatusCode::NO_CONTENT | StatusCode::NOT_MODIFIED) {
+        return response;
+    }
+    response.headers_mut().append(header::VARY, HeaderValue::from_static("accept-encoding"));
+    let small = response.body().size_hint().exact().is_some_and(|len| len < MIN_COMPRESS_BYTES);
+    let Some(encoder) = encoder.filter(|_| !small && !response.headers().contains_key(header::CONTENT_ENCODING)) else {
+        return response;
+    };
+
+    let (mut parts, body) = response.into_parts();
+    parts.headers.insert(header::CONTENT_ENCODING, HeaderValue::from_static(encoder.name()));
+    parts.headers.remove(header::CONTENT_LENGTH);
+    // Each chunk is compressed as it comes, so streamed responses like /movies/export still stream.
+    let compressed = futures_util::stream::unfold(Some((body.into_data_stream(), encoder)), |state| async move {
+        let (mut chunks, mut encoder) = state?;
+        match chunks.next().await {
+            Some(Ok(chunk)) => Some((Ok(Bytes::from(encoder.chunk(&chunk))), Some((chunks, encoder)))),
+            Some(Err(e)) => Some((Err(e), None)),
+            None => Some((Ok(Bytes::from(encoder.finish())), None)),
+        }
+    });
+    Response::from_parts(parts, Body::from_stream(compressed))
+}
+
+// Middleware for the whole router. Handlers see the decompressed body, so they don't need to know about any of this.
+// A body that turns out not to be valid gzip fails while the handler reads it, like a connection that drops.
+pub async fn decompress_requests(mut request: Request, next: Next) -> Response {
+    let Some(encoding) = request.headers().get(header::CONTENT_ENCODING) else {
+        return next.run(request).await;
+    };
+    let encoding = encoding.to_str().unwrap_or_default().trim().to_ascii_lowercase();
+    match encoding.as_str() {
+        "identity" => return next.run(request).await,
+        "gzip" | "x-gzip" => {},
+        _ => return ApiError::InvalidBody(StatusCode::UNSUPPORTED_MEDIA_TYPE, format!("Content-Encoding {:?} isn't supported, only gzip", encoding)).into_response(),
+    }
+    request.headers_mut().remove(header::CONTENT_ENCODING);
+    request.headers_mut().remove(header::CONTENT_LENGTH);
+    let (parts, body) = request.into_parts();
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+    let decompressed = futures_util::stream::unfold(Some((body.into_data_stream(), GzipDecoder::new(), 0)), move |state| async move {
+        let (mut chunks, mut decoder, mut total) = state?;
+        loop {
+            let chunk = match chunks.next().await {
+                Some(Ok(chunk)) => chunk,
+                Some(Err(e)) => return Some((Err(io::Error::other(e)), None)),
+                None => return decoder.finish().err().map(|e| (Err(invalid(format!("Invalid gzip body: {}", e))), None)),
+            };
+            let out = match decoder.chunk(&chunk) {
+                Ok(out) => out,
+                Err(e) => return Some((Err(invalid(format!("Invalid gzip body: {}", e))), None)),
+            };
+            total += out.len();
+            if total > MAX_DECOMPRESSED_BYTES {
+                return Some((Err(invalid(format!("Body is over {} bytes once decompressed", MAX_DECOMPRESSED_BYTES))), None));
+            }
+            if !out.is_empty() {
+                return Some((Ok(Bytes::from(out)), Some((chunks, decoder, total))));
+            }
+        }
+    });
+    next.run(Request::from_parts(parts, Body::from_stream(decompressed))).await
+}
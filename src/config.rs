@@ -0,0 +1,777 @@
+use std::{collections::HashMap, ffi::OsString, fmt, net::SocketAddr, path::{Path, PathBuf}, str::FromStr, time::{Duration, SystemTime}};
+use tracing::{error, info, level_filters::LevelFilter};
+
+use crate::auth;
+use crate::body_limit;
+use crate::cache::CacheConfig;
+use crate::cors::{self, AllowedOrigins, CorsConfig};
+use crate::crypto::RsaPublicKey;
+use crate::duplicates::DuplicatePolicy;
+use crate::enrich::{self, EnrichConfig, Provider};
+use crate::idempotency;
+use crate::jwt::{JwtConfig, JwtKey};
+use crate::listener::{self, HttpConfig, ListenAddr};
+use crate::oidc::OidcConfig;
+use crate::publish::{self, Broker, PublishConfig};
+use crate::rate_limit::RateLimit;
+use crate::replication::{self, FollowConfig};
+use crate::s3_backup::{self, S3BackupConfig};
+#[cfg(feature = "postgres")]
+use crate::postgres::PostgresConfig;
+use crate::snapshot::SnapshotConfig;
+use crate::telemetry::LogFormat;
+#[cfg(feature = "tls")]
+use crate::tls::TlsConfig;
+use crate::tenant::TenantConfig;
+use crate::timeout;
+use crate::trash;
+use crate::wal::WalConfig;
+
+// A setting can be given as a command line flag (--bind-addr 127.0.0.1:8080 or --bind-addr=127.0.0.1:8080), as an
+// environment variable (MOVIES_BIND_ADDR) or as a top-level key in the TOML file passed with --config
+// (bind_addr = "127.0.0.1:8080"). Flags win over the environment, which wins over the file, which wins over the default.
+struct Setting {
+    key: &'static str,
+    flag: &'static str,
+    env: &'static str,
+    help: &'static str,
+}
+
+const SETTINGS: &[Setting] = &[
+    Setting { key: "config", flag: "--config", env: "MOVIES_CONFIG", help: "TOML file to read settings from, re-read whenever it changes" },
+    Setting { key: "bind_addr", flag: "--bind-addr", env: "MOVIES_BIND_ADDR", help: "Address to listen on [default: 0.0.0.0:1234]" },
+    Setting { key: "listen", flag: "--listen", env: "MOVIES_LISTEN", help: "Comma-separated addresses to listen on instead of bind_addr, host:port or unix:<path>, e.g. unix:/run/movies.sock" },
+    Setting { key: "http2", flag: "--http2", env: "MOVIES_HTTP2", help: "Whether clients may use HTTP/2 as well as HTTP/1.1 [default: true]" },
+    Setting { key: "http2_max_concurrent_streams", flag: "--http2-max-concurrent-streams", env: "MOVIES_HTTP2_MAX_CONCURRENT_STREAMS", help: "Requests an HTTP/2 client may have going at once on one connection [default: 200]" },
+    Setting { key: "keep_alive", flag: "--keep-alive", env: "MOVIES_KEEP_ALIVE", help: "Whether HTTP/1.1 connections are reused for more than one request [default: true]" },
+    Setting { key: "keep_alive_timeout_secs", flag: "--keep-alive-timeout-secs", env: "MOVIES_KEEP_ALIVE_TIMEOUT_SECS", help: "Seconds an HTTP/1.1 connection may wait for the next request, and between pings of idle HTTP/2 connections, 0 for no limit [default: 30]" },
+    Setting { key: "max_header_bytes", flag: "--max-header-bytes", env: "MOVIES_MAX_HEADER_BYTES", help: "Largest request headers accepted, at least 8192 [default: 16384 for HTTP/2, about 400 KiB for HTTP/1.1]" },
+    Setting { key: "tls_cert", flag: "--tls-cert", env: "MOVIES_TLS_CERT", help: "PEM certificate chain to serve HTTPS with on TCP addresses, in builds with the tls feature, re-read with tls_key on SIGHUP [default: plain HTTP]" },
+    Setting { key: "tls_key", flag: "--tls-key", env: "MOVIES_TLS_KEY", help: "PEM private key for tls_cert" },
+    Setting { key: "log_level", flag: "--log-level", env: "MOVIES_LOG_LEVEL", help: "off, error, warn, info, debug or trace [default: info]" },
+    Setting { key: "log_format", flag: "--log-format", env: "MOVIES_LOG_FORMAT", help: "text, or json for one object per line [default: text]" },
+    Setting { key: "otel_endpoint", flag: "--otel-endpoint", env: "MOVIES_OTEL_ENDPOINT", help: "OTLP/HTTP collector to send traces to, e.g. http://localhost:4318 [default: no export]" },
+    Setting { key: "store", flag: "--store", env: "MOVIES_STORE", help: "memory://, snapshot://<path>, wal://<path>, events://<path> to keep every change, or sqlite://<path> and postgres://<user>:<password>@<host>/<database> in builds with the sqlite and postgres features [default: memory://]" },
+    Setting { key: "snapshot_interval_secs", flag: "--snapshot-interval-secs", env: "MOVIES_SNAPSHOT_INTERVAL_SECS", help: "How often snapshot:// stores are written out [default: 30]" },
+    Setting { key: "wal_max_bytes", flag: "--wal-max-bytes", env: "MOVIES_WAL_MAX_BYTES", help: "Size at which wal:// logs get compacted [default: 67108864]" },
+    Setting { key: "postgres_pool_size", flag: "--postgres-pool-size", env: "MOVIES_POSTGRES_POOL_SIZE", help: "How many connections postgres:// stores keep open to the server [default: 8]" },
+    Setting { key: "auto_migrate", flag: "--auto-migrate", env: "MOVIES_AUTO_MIGRATE", help: "Whether a store written by an older version is upgraded as it's opened, rather than refused until `syndica-rust migrate` has been run [default: true]" },
+    Setting { key: "trash_retention_secs", flag: "--trash-retention-secs", env: "MOVIES_TRASH_RETENTION_SECS", help: "How long deleted movies can be restored before they're purged for good, 0 keeps them [default: 2592000]" },
+    Setting { key: "duplicates", flag: "--duplicates", env: "MOVIES_DUPLICATES", help: "What happens to a new movie with the same name and year as another: warn to add it with a Warning header, or reject for a 409 [default: warn]" },
+    Setting { key: "backup_dir", flag: "--backup-dir", env: "MOVIES_BACKUP_DIR", help: "Directory for POST /admin/snapshot to write backups to and POST /admin/restore to read them from [default: downloaded instead]" },
+    Setting { key: "s3_backup_endpoint", flag: "--s3-backup-endpoint", env: "MOVIES_S3_BACKUP_ENDPOINT", help: "http://host[:port] of S3-compatible storage to upload a backup to every s3_backup_interval_secs [default: off]" },
+    Setting { key: "s3_backup_bucket", flag: "--s3-backup-bucket", env: "MOVIES_S3_BACKUP_BUCKET", help: "Bucket to upload backups to" },
+    Setting { key: "s3_backup_prefix", flag: "--s3-backup-prefix", env: "MOVIES_S3_BACKUP_PREFIX", help: "Put in front of the name of every backup in the bucket [default: movies/]" },
+    Setting { key: "s3_backup_region", flag: "--s3-backup-region", env: "MOVIES_S3_BACKUP_REGION", help: "Region to sign requests for [default: us-east-1]" },
+    Setting { key: "s3_backup_access_key", flag: "--s3-backup-access-key", env: "MOVIES_S3_BACKUP_ACCESS_KEY", help: "Access key id to sign requests with" },
+    Setting { key: "s3_backup_secret_key", flag: "--s3-backup-secret-key", env: "MOVIES_S3_BACKUP_SECRET_KEY", help: "Secret access key to sign requests with" },
+    Setting { key: "s3_backup_interval_secs", flag: "--s3-backup-interval-secs", env: "MOVIES_S3_BACKUP_INTERVAL_SECS", help: "How often a backup is uploaded [default: 3600]" },
+    Setting { key: "s3_backup_keep", flag: "--s3-backup-keep", env: "MOVIES_S3_BACKUP_KEEP", help: "Backups to leave in the bucket, older ones are deleted, 0 keeps them all [default: 24]" },
+    Setting { key: "follow", flag: "--follow", env: "MOVIES_FOLLOW", help: "http://host[:port] of an instance to follow: copy its movies, keep up with its changes and refuse writes [default: off]" },
+    Setting { key: "follow_api_key", flag: "--follow-api-key", env: "MOVIES_FOLLOW_API_KEY", help: "X-Api-Key to read from the instance followed with, if it needs one" },
+    Setting { key: "follow_interval_secs", flag: "--follow-interval-secs", env: "MOVIES_FOLLOW_INTERVAL_SECS", help: "How often to ask the instance followed for changes when it had none [default: 1]" },
+    Setting { key: "seed", flag: "--seed", env: "MOVIES_SEED", help: "JSON file with an array of movies to add at startup, as POST /movie takes them, before /readyz says ready [default: none]" },
+    Setting { key: "poster_dir", flag: "--poster-dir", env: "MOVIES_POSTER_DIR", help: "Directory to keep poster images in [default: in memory, gone on restart]" },
+    Setting { key: "enrich_provider", flag: "--enrich-provider", env: "MOVIES_ENRICH_PROVIDER", help: "omdb or tmdb, to fill in the runtime, genres and poster_url of movies added with only a name and year [default: off]" },
+    Setting { key: "enrich_api_key", flag: "--enrich-api-key", env: "MOVIES_ENRICH_API_KEY", help: "API key for the metadata provider" },
+    Setting { key: "enrich_url", flag: "--enrich-url", env: "MOVIES_ENRICH_URL", help: "http:// URL of the metadata provider's API, or of a proxy in front of it [default: http://www.omdbapi.com for omdb, tmdb needs one]" },
+    Setting { key: "publish_nats_url", flag: "--publish-nats-url", env: "MOVIES_PUBLISH_NATS_URL", help: "nats://[user:password@]host[:port] of a NATS server to publish every change to [default: off]" },
+    Setting { key: "publish_kafka_rest_url", flag: "--publish-kafka-rest-url", env: "MOVIES_PUBLISH_KAFKA_REST_URL", help: "http:// URL of a Kafka REST Proxy to publish every change through, instead of NATS [default: off]" },
+    Setting { key: "publish_topic", flag: "--publish-topic", env: "MOVIES_PUBLISH_TOPIC", help: "NATS subject or Kafka topic to publish changes to [default: movies.changes]" },
+    Setting { key: "cache_capacity", flag: "--cache-capacity", env: "MOVIES_CACHE_CAPACITY", help: "Movies to keep in the lookup cache, 0 disables it [default: 0]" },
+    Setting { key: "cache_ttl_secs", flag: "--cache-ttl-secs", env: "MOVIES_CACHE_TTL_SECS", help: "Seconds before a cached movie is looked up again [default: no limit]" },
+    Setting { key: "idempotency_window_secs", flag: "--idempotency-window-secs", env: "MOVIES_IDEMPOTENCY_WINDOW_SECS", help: "How long POST responses are replayed for a repeated Idempotency-Key, 0 disables it [default: 86400]" },
+    Setting { key: "api_keys", flag: "--api-keys", env: "MOVIES_API_KEYS", help: "Comma-separated SHA-256 hashes (printf %s \"$KEY\" | sha256sum) of the X-Api-Key values that may write [default: writes are open]" },
+    Setting { key: "tenants", flag: "--tenants", env: "MOVIES_TENANTS", help: "Comma-separated tenant ids, each with :<max movies> after it for a quota, e.g. team-a,team-b:5000, whose movies are kept apart from the default catalog's in a store of their own [default: none]" },
+    Setting { key: "max_movies", flag: "--max-movies", env: "MOVIES_MAX_MOVIES", help: "Most movies to store, across the default catalog and every tenant's, before adding more gets a 507, 0 for no limit [default: 0]" },
+    Setting { key: "tenant_api_keys", flag: "--tenant-api-keys", env: "MOVIES_TENANT_API_KEYS", help: "Comma-separated <tenant>=<SHA-256> of X-Api-Key values that may do anything to that tenant's movies, and nothing else" },
+    Setting { key: "jwt_hs256_secret", flag: "--jwt-hs256-secret", env: "MOVIES_JWT_HS256_SECRET", help: "Shared secret to check HS256 bearer tokens with [default: bearer tokens are off]" },
+    Setting { key: "jwt_rs256_public_key", flag: "--jwt-rs256-public-key", env: "MOVIES_JWT_RS256_PUBLIC_KEY", help: "PEM file with the RSA public key to check RS256 bearer tokens with [default: bearer tokens are off]" },
+    Setting { key: "jwt_issuer", flag: "--jwt-issuer", env: "MOVIES_JWT_ISSUER", help: "The iss bearer tokens must have [default: any]" },
+    Setting { key: "jwt_audience", flag: "--jwt-audience", env: "MOVIES_JWT_AUDIENCE", help: "The aud bearer tokens must list [default: any]" },
+    Setting { key: "cursor_secret", flag: "--cursor-secret", env: "MOVIES_CURSOR_SECRET", help: "At least 32 bytes to sign list cursors with, the same on every instance [default: random, so cursors don't survive a restart]" },
+    Setting { key: "rate_limit_per_sec", flag: "--rate-limit-per-sec", env: "MOVIES_RATE_LIMIT_PER_SEC", help: "Requests per second each API key, bearer token or client address gets, 0 for no limit [default: 0]" },
+    Setting { key: "rate_limit_burst", flag: "--rate-limit-burst", env: "MOVIES_RATE_LIMIT_BURST", help: "Requests a client can make at once on top of the rate [default: one second's worth]" },
+    Setting { key: "max_in_flight", flag: "--max-in-flight", env: "MOVIES_MAX_IN_FLIGHT", help: "Requests handled at once before new ones get a 503, 0 for no limit [default: 0]" },
+    Setting { key: "max_body_bytes", flag: "--max-body-bytes", env: "MOVIES_MAX_BODY_BYTES", help: "Largest request body most routes take, bigger ones get a 413 [default: 2097152]" },
+    Setting { key: "max_upload_bytes", flag: "--max-upload-bytes", env: "MOVIES_MAX_UPLOAD_BYTES", help: "Largest body POST /movies/import and POST /admin/restore take [default: 1073741824]" },
+    Setting { key: "request_timeout_secs", flag: "--request-timeout-secs", env: "MOVIES_REQUEST_TIMEOUT_SECS", help: "Seconds a request may take before it gets a 504, 0 for no limit [default: 30]" },
+    Setting { key: "oidc_issuer", flag: "--oidc-issuer", env: "MOVIES_OIDC_ISSUER", help: "http:// issuer URL of the OpenID Connect provider operators log in to the admin routes with [default: no logins]" },
+    Setting { key: "oidc_client_id", flag: "--oidc-client-id", env: "MOVIES_OIDC_CLIENT_ID", help: "This service's client id at the OIDC provider" },
+    Setting { key: "oidc_client_secret", flag: "--oidc-client-secret", env: "MOVIES_OIDC_CLIENT_SECRET", help: "This service's client secret at the OIDC provider" },
+    Setting { key: "oidc_redirect_url", flag: "--oidc-redirect-url", env: "MOVIES_OIDC_REDIRECT_URL", help: "The /admin/callback URL the provider sends people back to, e.g. https://movies.example.com/admin/callback" },
+    Setting { key: "cors_allowed_origins", flag: "--cors-allowed-origins", env: "MOVIES_CORS_ALLOWED_ORIGINS", help: "Comma-separated origins browsers may call the API from, e.g. https://movies.example.com, or * for any while developing [default: none]" },
+    Setting { key: "cors_allowed_methods", flag: "--cors-allowed-methods", env: "MOVIES_CORS_ALLOWED_METHODS", help: "Comma-separated methods those origins may use [default: every method the API has, or any with *]" },
+    Setting { key: "cors_allowed_headers", flag: "--cors-allowed-headers", env: "MOVIES_CORS_ALLOWED_HEADERS", help: "Comma-separated request headers those origins may send [default: every header the API reads, or any with *]" },
+    Setting { key: "cors_max_age_secs", flag: "--cors-max-age-secs", env: "MOVIES_CORS_MAX_AGE_SECS", help: "How long browsers may cache a preflight response [default: 600]" },
+    Setting { key: "shutdown_timeout_secs", flag: "--shutdown-timeout-secs", env: "MOVIES_SHUTDOWN_TIMEOUT_SECS", help: "How long to wait for in-flight requests on SIGINT/SIGTERM [default: 30]" },
+];
+
+const DEFAULT_BIND_ADDR: &str = "0.0.0.0:1234";
+const DEFAULT_SNAPSHOT_INTERVAL_SECS: u64 = 30;
+const DEFAULT_WAL_MAX_BYTES: u64 = 64 * 1024 * 1024;
+#[cfg(feature = "postgres")]
+const DEFAULT_POSTGRES_POOL_SIZE: usize = 8;
+const DEFAULT_SHUTDOWN_TIMEOUT_SECS: u64 = 30;
+const RELOAD_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+#[derive(Debug)]
+pub enum ConfigError {
+    // --help was passed. Carries the usage text.
+    Help(String),
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Help(usage) => f.write_str(usage),
+            ConfigError::Invalid(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StoreConfig {
+    Memory,
+    Snapshot(SnapshotConfig),
+    Wal(WalConfig),
+    // The event log's path, see event_sourced.rs.
+    Events(PathBuf),
+    // The database's path, see sqlite.rs.
+    #[cfg(feature = "sqlite")]
+    Sqlite(PathBuf),
+    // The server to connect to, see postgres.rs.
+    #[cfg(feature = "postgres")]
+    Postgres(PostgresConfig),
+}
+
+impl StoreConfig {
+    // Where the audit log goes: next to the store's file, with .audit added to its name. None for stores in memory, and
+    // in Postgres, which have no file of their own here, whose audit log is in memory.
+    pub fn audit_path(&self) -> Option<PathBuf> {
+        let path = match self {
+            StoreConfig::Memory => return None,
+            StoreConfig::Snapshot(snapshot) => &snapshot.path,
+            StoreConfig::Wal(wal) => &wal.path,
+            StoreConfig::Events(path) => path,
+            #[cfg(feature = "sqlite")]
+            StoreConfig::Sqlite(path) => path,
+            #[cfg(feature = "postgres")]
+            StoreConfig::Postgres(_) => return None,
+        };
+        let mut name = path.clone().into_os_string();
+        name.push(".audit");
+        Some(name.into())
+    }
+
+    // The same kind of store for the tenant's movies: in memory, in a file next to this one, with the tenant's id and a
+    // dot put in front of its name, or in a Postgres schema named after the tenant.
+    pub fn for_tenant(&self, tenant: &str) -> StoreConfig {
+        let beside = |path: &PathBuf| {
+            let mut name = OsString::from(format!("{}.", tenant));
+            name.push(path.file_name().unwrap_or_default());
+            path.with_file_name(name)
+        };
+        match self {
+            StoreConfig::Memory => StoreConfig::Memory,
+            StoreConfig::Snapshot(snapshot) => StoreConfig::Snapshot(SnapshotConfig { path: beside(&snapshot.path), ..snapshot.clone() }),
+            StoreConfig::Wal(wal) => StoreConfig::Wal(WalConfig { path: beside(&wal.path), ..wal.clone() }),
+            StoreConfig::Events(path) => StoreConfig::Events(beside(path)),
+            #[cfg(feature = "sqlite")]
+            StoreConfig::Sqlite(path) => StoreConfig::Sqlite(beside(path)),
+            #[cfg(feature = "postgres")]
+            StoreConfig::Postgres(postgres) => StoreConfig::Postgres(PostgresConfig { schema: Some(tenant.to_string()), ..postgres.clone() }),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub bind_addr: SocketAddr,
+    // bind_addr alone, unless listen is set.
+    pub listen: Vec<ListenAddr>,
+    pub http: HttpConfig,
+    // None for plain HTTP.
+    #[cfg(feature = "tls")]
+    pub tls: Option<TlsConfig>,
+    pub log_level: LevelFilter,
+    pub log_format: LogFormat,
+    pub otel_endpoint: Option<String>,
+    pub store: StoreConfig,
+    pub auto_migrate: bool,
+    // Zero keeps deleted movies in the trash until they're restored.
+    pub trash_retention: Duration,
+    pub duplicates: DuplicatePolicy,
+    pub backup_dir: Option<PathBuf>,
+    pub s3_backup: Option<S3BackupConfig>,
+    pub follow: Option<FollowConfig>,
+    pub seed: Option<PathBuf>,
+    pub poster_dir: Option<PathBuf>,
+    pub enrich: Option<EnrichConfig>,
+    pub publish: Option<PublishConfig>,
+    pub cache: Option<CacheConfig>,
+    pub idempotency_window: Duration,
+    pub api_keys: Vec<[u8; 32]>,
+    pub tenants: Vec<TenantConfig>,
+    pub max_movies: Option<usize>,
+    // Hashes of keys, and the tenant each is for.
+    pub tenant_api_keys: Vec<([u8; 32], String)>,
+    pub jwt: Option<JwtConfig>,
+    pub oidc: Option<OidcConfig>,
+    pub cursor_secret: Option<Vec<u8>>,
+    pub rate_limit: Option<RateLimit>,
+    // 0 for no limit.
+    pub max_in_flight: usize,
+    pub max_body_bytes: usize,
+    pub max_upload_bytes: usize,
+    pub request_timeout: Duration,
+    pub cors: Option<CorsConfig>,
+    pub shutdown_timeout: Duration,
+    pub file: Option<PathBuf>,
+    // What the flags and environment set, kept so a reload of the file can be layered underneath them again.
+    overrides: HashMap<&'static str, String>,
+}
+
+impl Config {
+    // Reads the process's own arguments and environment.
+    pub fn load() -> Result<Config, ConfigError> {
+        Config::from_sources(std::env::args().skip(1), |name| std::env::var(name).ok())
+    }
+
+    pub fn from_sources(args: impl IntoIterator<Item = String>, env: impl Fn(&str) -> Option<String>) -> Result<Config, ConfigError> {
+        let mut overrides = HashMap::new();
+        for setting in SETTINGS {
+            if let Some(value) = env(setting.env) {
+                overrides.insert(setting.key, value);
+            }
+        }
+        overrides.extend(parse_args(args)?);
+        Config::resolve(overrides)
+    }
+
+    // Reads the config file again, keeping the flags and environment the process started with.
+    pub fn reload(&self) -> Result<Config, ConfigError> {
+        Config::resolve(self.overrides.clone())
+    }
+
+    fn resolve(overrides: HashMap<&'static str, String>) -> Result<Config, ConfigError> {
+        let file = overrides.get("config").map(PathBuf::from);
+        let mut raw = match &file {
+            Some(path) => read_config_file(path)?,
+            None => HashMap::new(),
+        };
+        raw.extend(overrides.iter().map(|(key, value)| (*key, value.clone())));
+        Config::from_raw(&raw, file, overrides)
+    }
+
+    fn from_raw(raw: &HashMap<&'static str, String>, file: Option<PathBuf>, overrides: HashMap<&'static str, String>) -> Result<Config, ConfigError> {
+        let bind_addr = parse(raw, "bind_addr")?.unwrap_or_else(|| DEFAULT_BIND_ADDR.parse().unwrap());
+        let listen = match raw.get("listen") {
+            Some(_) if raw.contains_key("bind_addr") => return Err(ConfigError::Invalid("bind_addr and listen can't both be set".to_string())),
+            Some(addresses) => split_list(addresses)
+                .map(|address| address.parse().map_err(|e| ConfigError::Invalid(format!("listen: {}", e))))
+                .collect::<Result<Vec<_>, _>>()?,
+            None => vec![ListenAddr::Tcp(bind_addr)],
+        };
+        if listen.is_empty() {
+            return Err(ConfigError::Invalid("listen: needs at least one address".to_string()));
+        }
+        let http = HttpConfig {
+            http2: parse(raw, "http2")?.unwrap_or(true),
+            max_concurrent_streams: parse(raw, "http2_max_concurrent_streams")?.unwrap_or(listener::DEFAULT_MAX_CONCURRENT_STREAMS),
+            keep_alive: parse(raw, "keep_alive")?.unwrap_or(true),
+            keep_alive_timeout: parse(raw, "keep_alive_timeout_secs")?.map_or(listener::DEFAULT_KEEP_ALIVE_TIMEOUT, Duration::from_secs),
+            max_header_bytes: parse(raw, "max_header_bytes")?,
+        };
+        if http.max_header_bytes.is_some_and(|max| max < listener::MIN_HEADER_BYTES) {
+            return Err(ConfigError::Invalid(format!("max_header_bytes: must be at least {}", listener::MIN_HEADER_BYTES)));
+        }
+        if http.max_concurrent_streams == 0 {
+            return Err(ConfigError::Invalid("http2_max_concurrent_streams: must be at least 1".to_string()));
+        }
+        let tls = match (raw.get("tls_cert"), raw.get("tls_key")) {
+            (None, None) => None,
+            (Some(cert), Some(key)) => Some((PathBuf::from(cert), PathBuf::from(key))),
+            _ => return Err(ConfigError::Invalid("tls_cert and tls_key have to be set together".to_string())),
+        };
+        #[cfg(feature = "tls")]
+        let tls = tls.map(|(cert, key)| TlsConfig { cert, key });
+        #[cfg(not(feature = "tls"))]
+        if tls.is_some() {
+            return Err(ConfigError::Invalid("tls_cert: HTTPS needs a build with the tls feature".to_string()));
+        }
+        let log_level = parse(raw, "log_level")?.unwrap_or(LevelFilter::INFO);
+        let log_format = parse(raw, "log_format")?.unwrap_or(LogFormat::Text);
+        let otel_endpoint = raw.get("otel_endpoint").cloned();
+        if let Some(endpoint) = &otel_endpoint
+            && !endpoint.starts_with("http://") {
+            return Err(ConfigError::Invalid(format!("otel_endpoint: only http:// collectors are supported, got {:?}", endpoint)));
+        }
+
+        let store = match raw.get("store").map(String::as_str).unwrap_or("memory://") {
+            "memory" | "memory://" => StoreConfig::Memory,
+            location => {
+                let (scheme, path) = location.split_once("://")
+                    .ok_or_else(|| ConfigError::Invalid(format!("store: expected <scheme>://<path>, got {:?}", location)))?;
+                if path.is_empty() {
+                    return Err(ConfigError::Invalid(format!("store: {}:// needs a file path", scheme)));
+                }
+                match scheme {
+                    "snapshot" => StoreConfig::Snapshot(SnapshotConfig {
+                        path: PathBuf::from(path),
+                        interval: Duration::from_secs(parse(raw, "snapshot_interval_secs")?.unwrap_or(DEFAULT_SNAPSHOT_INTERVAL_SECS)),
+                    }),
+                    "wal" => StoreConfig::Wal(WalConfig {
+                        path: PathBuf::from(path),
+                        max_bytes: parse(raw, "wal_max_bytes")?.unwrap_or(DEFAULT_WAL_MAX_BYTES),
+                    }),
+                    "events" => StoreConfig::Events(PathBuf::from(path)),
+                    #[cfg(feature = "sqlite")]
+                    "sqlite" => StoreConfig::Sqlite(PathBuf::from(path)),
+                    #[cfg(not(feature = "sqlite"))]
+                    "sqlite" => return Err(ConfigError::Invalid("store: sqlite:// needs a build with the sqlite feature".to_string())),
+                    // The whole location is handed to libpq, which reads postgres:// URLs itself.
+                    #[cfg(feature = "postgres")]
+                    "postgres" => StoreConfig::Postgres(PostgresConfig {
+                        url: location.to_string(),
+                        pool_size: parse(raw, "postgres_pool_size")?.unwrap_or(DEFAULT_POSTGRES_POOL_SIZE),
+                        schema: None,
+                    }),
+                    #[cfg(not(feature = "postgres"))]
+                    "postgres" => return Err(ConfigError::Invalid("store: postgres:// needs a build with the postgres feature".to_string())),
+                    _ => return Err(ConfigError::Invalid(format!("store: unsupported scheme {:?}, expected memory, snapshot, wal, events, sqlite or postgres", scheme))),
+                }
+            },
+        };
+        if let StoreConfig::Snapshot(snapshot) = &store
+            && snapshot.interval.is_zero() {
+            return Err(ConfigError::Invalid("snapshot_interval_secs: must be at least 1".to_string()));
+        }
+        #[cfg(feature = "postgres")]
+        if let StoreConfig::Postgres(postgres) = &store
+            && postgres.pool_size == 0 {
+            return Err(ConfigError::Invalid("postgres_pool_size: must be at least 1".to_string()));
+        }
+
+        let auto_migrate = parse(raw, "auto_migrate")?.unwrap_or(true);
+        let trash_retention = parse(raw, "trash_retention_secs")?.map_or(trash::DEFAULT_RETENTION, Duration::from_secs);
+        let duplicates = parse(raw, "duplicates")?.unwrap_or(DuplicatePolicy::Warn);
+
+        let backup_dir = match raw.get("backup_dir").map(|dir| dir.trim()) {
+            Some("") => return Err(ConfigError::Invalid("backup_dir: needs a directory".to_string())),
+            dir => dir.map(PathBuf::from),
+        };
+
+        let s3_backup = match (raw.get("s3_backup_endpoint"), raw.get("s3_backup_bucket"), raw.get("s3_backup_access_key"), raw.get("s3_backup_secret_key")) {
+            (None, None, None, None) => {
+                if let Some(key) = ["s3_backup_prefix", "s3_backup_region", "s3_backup_interval_secs", "s3_backup_keep"].into_iter().find(|key| raw.contains_key(key)) {
+                    return Err(ConfigError::Invalid(format!("{}: needs s3_backup_endpoint", key)));
+                }
+                None
+            },
+            (Some(endpoint), Some(bucket), Some(access_key), Some(secret_key)) => {
+                let endpoint = endpoint.trim_end_matches('/');
+                if !endpoint.strip_prefix("http://").is_some_and(|host| !host.is_empty() && !host.contains('/')) {
+                    return Err(ConfigError::Invalid(format!("s3_backup_endpoint: expected http://host[:port], got {:?}", endpoint)));
+                }
+                // S3's own rules for bucket names, which path-style addressing relies on.
+                if bucket.len() < 3 || bucket.len() > 63 || !bucket.bytes().all(|byte| byte.is_ascii_lowercase() || byte.is_ascii_digit() || byte == b'.' || byte == b'-')
+                    || !bucket.starts_with(|c: char| c.is_ascii_alphanumeric()) || !bucket.ends_with(|c: char| c.is_ascii_alphanumeric()) {
+                    return Err(ConfigError::Invalid(format!("s3_backup_bucket: expected 3 to 63 lowercase letters, digits, '.' and '-', got {:?}", bucket)));
+                }
+                let interval = parse(raw, "s3_backup_interval_secs")?.map_or(s3_backup::DEFAULT_INTERVAL, Duration::from_secs);
+                if interval.is_zero() {
+                    return Err(ConfigError::Invalid("s3_backup_interval_secs: must be at least 1".to_string()));
+                }
+                Some(S3BackupConfig {
+                    endpoint: endpoint.to_string(),
+                    bucket: bucket.clone(),
+                    prefix: raw.get("s3_backup_prefix").cloned().unwrap_or_else(|| s3_backup::DEFAULT_PREFIX.to_string()),
+                    region: raw.get("s3_backup_region").cloned().unwrap_or_else(|| s3_backup::DEFAULT_REGION.to_string()),
+                    access_key: access_key.clone(),
+                    secret_key: secret_key.clone(),
+                    interval,
+                    keep: parse(raw, "s3_backup_keep")?.unwrap_or(s3_backup::DEFAULT_KEEP),
+                })
+            },
+            _ => return Err(ConfigError::Invalid("s3_backup_endpoint, s3_backup_bucket, s3_backup_access_key and s3_backup_secret_key go together".to_string())),
+        };
+
+        let seed = match raw.get("seed").map(|path| path.trim()) {
+            Some("") => return Err(ConfigError::Invalid("seed: needs a file".to_string())),
+            path => path.map(PathBuf::from),
+        };
+
+        let poster_dir = match raw.get("poster_dir").map(|dir| dir.trim()) {
+            Some("") => return Err(ConfigError::Invalid("poster_dir: needs a directory".to_string())),
+            dir => dir.map(PathBuf::from),
+        };
+
+        // TMDB is https only, so unlike OMDb it can't be reached without a proxy.
+        let enrich = match (parse::<Provider>(raw, "enrich_provider")?, raw.get("enrich_api_key")) {
+            (None, None) => {
+                if raw.contains_key("enrich_url") {
+                    return Err(ConfigError::Invalid("enrich_url: needs enrich_provider".to_string()));
+                }
+                None
+            },
+            (Some(provider), Some(api_key)) => {
+                let url = match (provider, raw.get("enrich_url")) {
+                    (_, Some(url)) if !url.starts_with("http://") => return Err(ConfigError::Invalid(format!("enrich_url: only http:// providers are supported, got {:?}", url))),
+                    (_, Some(url)) => url.trim_end_matches('/').to_string(),
+                    (Provider::Omdb, None) => enrich::OMDB_URL.to_string(),
+                    (Provider::Tmdb, None) => return Err(ConfigError::Invalid("enrich_url: tmdb is only served over https, so needs the http:// URL of a proxy in front of it".to_string())),
+                };
+                Some(EnrichConfig { provider, url, api_key: api_key.clone() })
+            },
+            _ => return Err(ConfigError::Invalid("enrich_provider and enrich_api_key go together".to_string())),
+        };
+
+        let broker = match (raw.get("publish_nats_url"), raw.get("publish_kafka_rest_url")) {
+            (None, None) => None,
+            (Some(url), None) => Some(Broker::nats(url).map_err(|e| ConfigError::Invalid(format!("publish_nats_url: {}", e)))?),
+            (None, Some(url)) if url.starts_with("http://") => Some(Broker::KafkaRest { url: url.trim_end_matches('/').to_string() }),
+            (None, Some(url)) => return Err(ConfigError::Invalid(format!("publish_kafka_rest_url: only http:// proxies are supported, got {:?}", url))),
+            (Some(_), Some(_)) => return Err(ConfigError::Invalid("publish_nats_url and publish_kafka_rest_url: only one broker at a time".to_string())),
+        };
+        let topic = raw.get("publish_topic").cloned();
+        // What both NATS subjects and Kafka topic names may be.
+        if let Some(topic) = &topic
+            && (topic.is_empty() || topic.len() > 249 || !topic.bytes().all(|byte| byte.is_ascii_alphanumeric() || b"._-".contains(&byte))
+                || topic.starts_with('.') || topic.ends_with('.') || topic.contains("..")) {
+            return Err(ConfigError::Invalid(format!("publish_topic: expected up to 249 letters, digits, '.', '_' and '-', got {:?}", topic)));
+        }
+        let publish = match (broker, topic) {
+            (Some(broker), topic) => Some(PublishConfig { broker, topic: topic.unwrap_or_else(|| publish::DEFAULT_TOPIC.to_string()) }),
+            (None, Some(_)) => return Err(ConfigError::Invalid("publish_topic: needs publish_nats_url or publish_kafka_rest_url".to_string())),
+            (None, None) => None,
+        };
+
+        let cache = match parse(raw, "cache_capacity")?.unwrap_or(0) {
+            0 => None,
+            capacity => Some(CacheConfig {
+                capacity,
+                ttl: parse(raw, "cache_ttl_secs")?.map(Duration::from_secs),
+            }),
+        };
+
+        let idempotency_window = parse(raw, "idempotency_window_secs")?.map_or(idempotency::DEFAULT_WINDOW, Duration::from_secs);
+        let api_keys = match raw.get("api_keys") {
+            Some(hashes) => hashes.split(',')
+                .filter(|hash| !hash.trim().is_empty())
+                .map(|hash| auth::parse_key_hash(hash).map_err(|e| ConfigError::Invalid(format!("api_keys: {}", e))))
+                .collect::<Result<_, _>>()?,
+            None => Vec::new(),
+        };
+        let tenants: Vec<TenantConfig> = match raw.get("tenants") {
+            Some(tenants) => split_list(tenants)
+                .map(|entry| TenantConfig::parse(entry).map_err(|e| ConfigError::Invalid(format!("tenants: {}", e))))
+                .collect::<Result<_, _>>()?,
+            None => Vec::new(),
+        };
+        if let Some(duplicate) = tenants.iter().enumerate().find(|(i, tenant)| tenants[..*i].iter().any(|other| other.id == tenant.id)) {
+            return Err(ConfigError::Invalid(format!("tenants: {} is listed twice", duplicate.1.id)));
+        }
+        let max_movies = parse(raw, "max_movies")?.filter(|max| *max > 0);
+
+        let follow = match raw.get("follow").map(|leader| leader.trim().trim_end_matches('/')) {
+            None => {
+                if let Some(key) = ["follow_api_key", "follow_interval_secs"].into_iter().find(|key| raw.contains_key(key)) {
+                    return Err(ConfigError::Invalid(format!("{}: needs follow", key)));
+                }
+                None
+            },
+            Some(leader) => {
+                if !leader.strip_prefix("http://").is_some_and(|host| !host.is_empty() && !host.contains('/')) {
+                    return Err(ConfigError::Invalid(format!("follow: expected http://host[:port], got {:?}", leader)));
+                }
+                // A follower's movies are the leader's, so there's nothing of its own to seed or split up.
+                if let Some(key) = ["seed", "tenants"].into_iter().find(|key| raw.contains_key(key)) {
+                    return Err(ConfigError::Invalid(format!("{}: can't be used with follow", key)));
+                }
+                let interval = parse(raw, "follow_interval_secs")?.map_or(replication::DEFAULT_INTERVAL, Duration::from_secs);
+                if interval.is_zero() {
+                    return Err(ConfigError::Invalid("follow_interval_secs: must be at least 1".to_string()));
+                }
+                Some(FollowConfig { leader: leader.to_string(), api_key: raw.get("follow_api_key").cloned(), interval })
+            },
+        };
+        let tenant_api_keys = match raw.get("tenant_api_keys") {
+            Some(keys) => split_list(keys).map(|entry| {
+                let (tenant, hash) = entry.split_once('=')
+                    .ok_or_else(|| ConfigError::Invalid(format!("tenant_api_keys: expected <tenant>=<SHA-256>, got {:?}", entry)))?;
+                let tenant = tenant.trim();
+                if !tenants.iter().any(|declared| declared.id == tenant) {
+                    return Err(ConfigError::Invalid(format!("tenant_api_keys: {:?} isn't one of the tenants", tenant)));
+                }
+                let hash = auth::parse_key_hash(hash).map_err(|e| ConfigError::Invalid(format!("tenant_api_keys: {}", e)))?;
+                Ok((hash, tenant.to_string()))
+            }).collect::<Result<_, _>>()?,
+            None => Vec::new(),
+        };
+        let jwt_key = match (raw.get("jwt_hs256_secret"), raw.get("jwt_rs256_public_key")) {
+            (Some(_), Some(_)) => return Err(ConfigError::Invalid("jwt_hs256_secret and jwt_rs256_public_key can't both be set".to_string())),
+            (Some(secret), None) if secret.len() < 32 => return Err(ConfigError::Invalid("jwt_hs256_secret: needs at least 32 bytes".to_string())),
+            (Some(secret), None) => Some(JwtKey::Hs256(secret.as_bytes().to_vec())),
+            (None, Some(path)) => {
+                let invalid = |message: String| ConfigError::Invalid(format!("jwt_rs256_public_key: {}: {}", path, message));
+                let pem = std::fs::read_to_string(path).map_err(|e| invalid(e.to_string()))?;
+                Some(JwtKey::Rs256(RsaPublicKey::from_pem(&pem).map_err(invalid)?))
+            },
+            (None, None) => None,
+        };
+        let jwt = jwt_key.map(|key| JwtConfig { key, issuer: raw.get("jwt_issuer").cloned(), audience: raw.get("jwt_audience").cloned() });
+        if jwt.is_none() && (raw.contains_key("jwt_issuer") || raw.contains_key("jwt_audience")) {
+            return Err(ConfigError::Invalid("jwt_issuer and jwt_audience need jwt_hs256_secret or jwt_rs256_public_key".to_string()));
+        }
+        let oidc = match (raw.get("oidc_issuer"), raw.get("oidc_client_id"), raw.get("oidc_client_secret"), raw.get("oidc_redirect_url")) {
+            (None, None, None, None) => None,
+            (Some(issuer), Some(client_id), Some(client_secret), Some(redirect_url)) => {
+                if !issuer.starts_with("http://") {
+                    return Err(ConfigError::Invalid(format!("oidc_issuer: only http:// providers are supported, got {:?}", issuer)));
+                }
+                if !redirect_url.starts_with("http://") && !redirect_url.starts_with("https://") {
+                    return Err(ConfigError::Invalid(format!("oidc_redirect_url: expected an absolute URL, got {:?}", redirect_url)));
+                }
+                Some(OidcConfig { issuer: issuer.clone(), client_id: client_id.clone(), client_secret: client_secret.clone(), redirect_url: redirect_url.clone() })
+            },
+            _ => return Err(ConfigError::Invalid("oidc_issuer, oidc_client_id, oidc_client_secret and oidc_redirect_url go together".to_string())),
+        };
+        let cursor_secret = match raw.get("cursor_secret") {
+            Some(secret) if secret.len() < 32 => return Err(ConfigError::Invalid("cursor_secret: needs at least 32 bytes".to_string())),
+            secret => secret.map(|secret| secret.as_bytes().to_vec()),
+        };
+        let rate: f64 = parse(raw, "rate_limit_per_sec")?.unwrap_or(0.0);
+        if !rate.is_finite() || rate < 0.0 {
+            return Err(ConfigError::Invalid(format!("rate_limit_per_sec: expected a number of requests, got {}", rate)));
+        }
+        let rate_limit = if rate == 0.0 {
+            None
+        }
+        else {
+            Some(RateLimit { rate, burst: parse(raw, "rate_limit_burst")?.unwrap_or((rate.ceil() as u32).max(1)) })
+        };
+        if rate_limit.is_some_and(|limit| limit.burst == 0) {
+            return Err(ConfigError::Invalid("rate_limit_burst: must be at least 1".to_string()));
+        }
+        let max_in_flight = parse(raw, "max_in_flight")?.unwrap_or(0);
+        let max_body_bytes = parse(raw, "max_body_bytes")?.unwrap_or(body_limit::DEFAULT_MAX_BODY_BYTES);
+        let max_upload_bytes = parse(raw, "max_upload_bytes")?.unwrap_or(body_limit::DEFAULT_MAX_UPLOAD_BYTES);
+        if let Some(key) = [("max_body_bytes", max_body_bytes), ("max_upload_bytes", max_upload_bytes)].into_iter().find_map(|(key, bytes)| (bytes == 0).then_some(key)) {
+            return Err(ConfigError::Invalid(format!("{}: must be at least 1", key)));
+        }
+        let request_timeout = parse(raw, "request_timeout_secs")?.map_or(timeout::DEFAULT_TIMEOUT, Duration::from_secs);
+        let cors = match raw.get("cors_allowed_origins").map(|origins| origins.trim()) {
+            None | Some("") => {
+                if let Some(key) = ["cors_allowed_methods", "cors_allowed_headers", "cors_max_age_secs"].into_iter().find(|key| raw.contains_key(key)) {
+                    return Err(ConfigError::Invalid(format!("{}: needs cors_allowed_origins", key)));
+                }
+                None
+            },
+            Some(origins) => {
+                let origins = if origins == "*" {
+                    AllowedOrigins::Any
+                }
+                else {
+                    AllowedOrigins::List(split_list(origins).map(|origin| {
+                        // Browsers send the origin as scheme://host[:port], so anything more would never match.
+                        let host = origin.strip_prefix("http://").or_else(|| origin.strip_prefix("https://")).unwrap_or_default();
+                        if host.is_empty() || host.contains('/') {
+                            return Err(ConfigError::Invalid(format!("cors_allowed_origins: expected scheme://host[:port], got {:?}", origin)));
+                        }
+                        Ok(origin.to_string())
+                    }).collect::<Result<_, _>>()?)
+                };
+                let methods = raw.get("cors_allowed_methods").map(|methods| split_list(methods)
+                    .map(|method| method.to_ascii_uppercase().parse().map_err(|_| ConfigError::Invalid(format!("cors_allowed_methods: invalid method {:?}", method))))
+                    .collect::<Result<_, _>>()).transpose()?;
+                let headers = raw.get("cors_allowed_headers").map(|headers| split_list(headers)
+                    .map(|name| name.parse().map_err(|_| ConfigError::Invalid(format!("cors_allowed_headers: invalid header name {:?}", name))))
+                    .collect::<Result<_, _>>()).transpose()?;
+                let max_age = parse(raw, "cors_max_age_secs")?.map_or(cors::DEFAULT_MAX_AGE, Duration::from_secs);
+                Some(CorsConfig { origins, methods, headers, max_age })
+            },
+        };
+        let shutdown_timeout = Duration::from_secs(parse(raw, "shutdown_timeout_secs")?.unwrap_or(DEFAULT_SHUTDOWN_TIMEOUT_SECS));
+
+        Ok(Config { bind_addr, listen, http, #[cfg(feature = "tls")] tls, log_level, log_format, otel_endpoint, store, auto_migrate, trash_retention, duplicates, backup_dir, s3_backup, follow, seed, poster_dir, enrich, publish, cache, idempotency_window, api_keys, tenants, max_movies, tenant_api_keys, jwt, oidc, cursor_secret, rate_limit, max_in_flight, max_body_bytes, max_upload_bytes, request_timeout, cors, shutdown_timeout, file, overrides })
+    }
+}
+
+// Polls the config file and calls `apply` with the old and new config every time it changes. A file that doesn't
+// parse is logged and otherwise ignored, so the last good config stays in effect.
+pub fn spawn_reload_task(mut current: Config, apply: impl Fn(&Config, &Config) + Send + 'static) {
+    let Some(path) = current.file.clone() else { return };
+    tokio::spawn(async move {
+        let mut last_seen = file_version(&path).await;
+        let mut ticker = tokio::time::interval(RELOAD_POLL_INTERVAL);
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            let seen = file_version(&path).await;
+            if seen == last_seen {
+                continue;
+            }
+            last_seen = seen;
+            match current.reload() {
+                Ok(config) => {
+                    info!("Reloaded config from {}", path.display());
+                    apply(&current, &config);
+                    current = config;
+                },
+                Err(e) => error!("Ignoring config change, keeping the previous settings: {}", e),
+            }
+        }
+    });
+}
+
+// Modification time and size, which together catch every edit that matters in practice.
+async fn file_version(path: &Path) -> Option<(Option<SystemTime>, u64)> {
+    let metadata = tokio::fs::metadata(path).await.ok()?;
+    Some((metadata.modified().ok(), metadata.len()))
+}
+
+fn read_config_file(path: &Path) -> Result<HashMap<&'static str, String>, ConfigError> {
+    let invalid = |message: String| ConfigError::Invalid(format!("{}: {}", path.display(), message));
+    let text = std::fs::read_to_string(path).map_err(|e| invalid(e.to_string()))?;
+    let mut raw = HashMap::new();
+    for (key, value) in parse_toml(&text).map_err(invalid)? {
+        let setting = SETTINGS.iter().find(|setting| setting.key == key && setting.key != "config")
+            .ok_or_else(|| invalid(format!("unknown setting {:?}", key)))?;
+        raw.insert(setting.key, value);
+    }
+    Ok(raw)
+}
+
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<HashMap<&'static str, String>, ConfigError> {
+    let mut raw = HashMap::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == "--help" || arg == "-h" {
+            return Err(ConfigError::Help(usage()));
+        }
+        let (flag, inline_value) = match arg.split_once('=') {
+            Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+            None => (arg, None),
+        };
+        let setting = SETTINGS.iter().find(|setting| setting.flag == flag)
+            .ok_or_else(|| ConfigError::Invalid(format!("unknown argument {:?}, see --help", flag)))?;
+        let value = match inline_value {
+            Some(value) => value,
+            None => args.next().ok_or_else(|| ConfigError::Invalid(format!("{} needs a value", flag)))?,
+        };
+        raw.insert(setting.key, value);
+    }
+    Ok(raw)
+}
+
+fn parse<T: FromStr>(raw: &HashMap<&'static str, String>, key: &'static str) -> Result<Option<T>, ConfigError>
+where T::Err: fmt::Display {
+    raw.get(key)
+        .map(|value| value.trim().parse().map_err(|e| ConfigError::Invalid(format!("{}: can't parse {:?}: {}", key, value, e))))
+        .transpose()
+}
+
+// The items of a comma-separated setting, ignoring blanks.
+fn split_list(value: &str) -> impl Iterator<Item = &str> {
+    value.split(',').map(str::trim).filter(|item| !item.is_empty())
+}
+
+fn usage() -> String {
+    let mut usage = String::from(concat!(
+        "Usage: syndica-rust [serve] [OPTIONS]\n",
+        "       syndica-rust import <file> [OPTIONS], to fill an empty store from a backup file\n",
+        "       syndica-rust export <file> [OPTIONS], to write the store out as a backup file\n",
+        "       syndica-rust migrate [OPTIONS], to rewrite the store, and every tenant's, in the current format\n",
+        "       syndica-rust bench --target <url> [OPTIONS], to load test a running instance, see bench --help\n",
+        "\nOptions:\n",
+    ));
+    for setting in SETTINGS {
+        usage.push_str(&format!("  {} <value>  (env {})\n      {}\n", setting.flag, setting.env, setting.help));
+    }
+    usage
+}
+
+// Just enough TOML for a flat file of settings: `key = value` lines holding strings, numbers or booleans, and comments.
+// Values come back as the text the typed parse in Config::from_raw expects, the same as flags and environment variables.
+fn parse_toml(text: &str) -> Result<Vec<(String, String)>, String> {
+    let mut pairs: Vec<(String, String)> = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let number = index + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line.starts_with('[') {
+            return Err(format!("line {}: tables aren't supported, put settings at the top level", number));
+        }
+        let (key, rest) = line.split_once('=').ok_or_else(|| format!("line {}: expected key = value", number))?;
+        let key = key.trim();
+        let key = key.strip_prefix('"').and_then(|key| key.strip_suffix('"')).unwrap_or(key);
+        if key.is_empty() || !key.bytes().all(|byte| byte.is_ascii_alphanumeric() || byte == b'_' || byte == b'-') {
+            return Err(format!("line {}: invalid key {:?}", number, key));
+        }
+        let (value, rest) = parse_toml_value(rest.trim()).map_err(|e| format!("line {}: {}", number, e))?;
+        let rest = rest.trim_start();
+        if !rest.is_empty() && !rest.starts_with('#') {
+            return Err(format!("line {}: unexpected {:?} after the value", number, rest));
+        }
+        if pairs.iter().any(|(existing, _)| existing == key) {
+            return Err(format!("line {}: {} is set twice", number, key));
+        }
+        pairs.push((key.to_string(), value));
+    }
+    Ok(pairs)
+}
+
+// Returns the value and whatever follows it on the line.
+fn parse_toml_value(text: &str) -> Result<(String, &str), String> {
+    if let Some(rest) = text.strip_prefix('\'') {
+        let end = rest.find('\'').ok_or("unterminated string")?;
+        return Ok((rest[..end].to_string(), &rest[end + 1..]));
+    }
+    if let Some(rest) = text.strip_prefix('"') {
+        let mut value = String::new();
+        let mut chars = rest.char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => return Ok((value, &rest[i + 1..])),
+                '\\' => match chars.next().map(|(_, c)| c) {
+                    Some('"') => value.push('"'),
+                    Some('\\') => value.push('\\'),
+                    Some('n') => value.push('\n'),
+                    Some('t') => value.push('\t'),
+                    Some('r') => value.push('\r'),
+                    Some('u') => {
+                        let hex: String = chars.by_ref().take(4).map(|(_, c)| c).collect();
+                        let c = u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32)
+                            .ok_or_else(|| format!("invalid escape \\u{}", hex))?;
+                        value.push(c);
+                    },
+                    other => return Err(format!("invalid escape {:?}", other)),
+                },
+                c => value.push(c),
+            }
+        }
+        return Err("unterminated string".to_string());
+    }
+    let end = text.find(|c: char| c.is_whitespace() || c == '#').unwrap_or(text.len());
+    let (token, rest) = text.split_at(end);
+    let is_number = token.starts_with(|c: char| c.is_ascii_digit() || c == '+' || c == '-')
+        && token.chars().all(|c| c.is_ascii_digit() || "+-._eE".contains(c));
+    if token == "true" || token == "false" {
+        Ok((token.to_string(), rest))
+    }
+    else if is_number {
+        Ok((token.trim_start_matches('+').replace('_', ""), rest))
+    }
+    else if token.is_empty() {
+        Err("missing value".to_string())
+    }
+    else {
+        Err(format!("unsupported value {:?}, strings need quotes", token))
+    }
+}
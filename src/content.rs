@@ -0,0 +1,149 @@
+use std::convert::Infallible;
+use axum::{body::Bytes, extract::{FromRequest, FromRequestParts, Request}, http::{header, request::Parts, HeaderMap, HeaderValue, StatusCode}, response::{IntoResponse, Response}};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::body_limit;
+use crate::error::{ApiError, ApiJson};
+
+// Movies go out as JSON unless the Accept header prefers MessagePack or CBOR, which some internal services would rather
+// parse, and request bodies may be any of the three going by their Content-Type. Errors are always JSON.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    MessagePack,
+    Cbor,
+}
+
+impl Format {
+    // In order of preference, for when the client likes several of them as much.
+    const ALL: [Format; 3] = [Format::Json, Format::MessagePack, Format::Cbor];
+
+    // The first one is what responses are labelled with. MessagePack has gone by several names over the years.
+    fn media_types(self) -> &'static [&'static str] {
+        match self {
+            Format::Json => &["application/json"],
+            Format::MessagePack => &["application/msgpack", "application/x-msgpack", "application/vnd.msgpack"],
+            Format::Cbor => &["application/cbor"],
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Format::Json => "JSON",
+            Format::MessagePack => "MessagePack",
+            Format::Cbor => "CBOR",
+        }
+    }
+
+    // A short name for the format, for the ETags of the representations in it.
+    pub fn token(self) -> &'static str {
+        match self {
+            Format::Json => "json",
+            Format::MessagePack => "msgpack",
+            Format::Cbor => "cbor",
+        }
+    }
+
+    // The format of a Content-Type, ignoring parameters like charset.
+    pub fn from_content_type(content_type: &str) -> Option<Format> {
+        let media_type = content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+        Format::ALL.into_iter().find(|format| format.media_types().contains(&media_type.as_str()))
+    }
+
+    // The format the client would most like, by the q-values in Accept. Without an Accept header, or one that allows
+    // none of them, it's JSON.
+    pub fn from_accept(headers: &HeaderMap) -> Format {
+        let ranges: Vec<(String, f32)> = headers.get_all(header::ACCEPT).iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(|range| {
+                let mut parts = range.split(';').map(str::trim);
+                let media_range = parts.next().unwrap_or_default().to_ascii_lowercase();
+                let q = parts.find_map(|parameter| parameter.strip_prefix("q=")).and_then(|q| q.parse().ok()).unwrap_or(1.0);
+                (media_range, q)
+            })
+            .collect();
+        // A media type named outright counts over a wildcard that would also cover it.
+        let quality = |format: Format| {
+            let q_of = |matches: &dyn Fn(&str) -> bool| ranges.iter().filter(|(range, _)| matches(range)).map(|(_, q)| *q).reduce(f32::max);
+            q_of(&|range| format.media_types().contains(&range))
+                .or_else(|| q_of(&|range| range == "*/*" || range == "application/*"))
+                .unwrap_or(0.0)
+        };
+        let mut best = (Format::Json, 0.0);
+        for format in Format::ALL {
+            let q = quality(format);
+            if q > best.1 {
+                best = (format, q);
+            }
+        }
+        best.0
+    }
+
+    // The value as a response in this format. JSON stays the pretty-printed text it has always been.
+    pub fn respond<T: Serialize>(self, value: &T) -> Result<Response, ApiError> {
+        let failed = |e: String| ApiError::Internal(format!("Failed to serialize response as {}: {}", self.name(), e));
+        let mut response = match self {
+            Format::Json => serde_json::to_string_pretty(value)?.into_response(),
+            // With field names, so the maps look like the JSON objects rather than bare arrays.
+            Format::MessagePack => {
+                let body = rmp_serde::to_vec_named(value).map_err(|e| failed(e.to_string()))?;
+                ([(header::CONTENT_TYPE, self.media_types()[0])], body).into_response()
+            },
+            Format::Cbor => {
+                let mut body = Vec::new();
+                ciborium::into_writer(value, &mut body).map_err(|e| failed(e.to_string()))?;
+                ([(header::CONTENT_TYPE, self.media_types()[0])], body).into_response()
+            },
+        };
+        response.headers_mut().append(header::VARY, HeaderValue::from_static("accept"));
+        Ok(response)
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for Format {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Format, Infallible> {
+        Ok(Format::from_accept(&parts.headers))
+    }
+}
+
+// Drop-in replacement for ApiJson that also takes MessagePack and CBOR bodies. As with JSON, a body that isn't the
+// format at all is a 400 and one that is but doesn't fit the type is a 422.
+pub struct ApiBody<T>(pub T);
+
+impl<T: DeserializeOwned, S: Send + Sync> FromRequest<S> for ApiBody<T> {
+    type Rejection = ApiError;
+
+    async fn from_request(request: Request, state: &S) -> Result<ApiBody<T>, ApiError> {
+        let format = request.headers().get(header::CONTENT_TYPE).and_then(|value| value.to_str().ok()).and_then(Format::from_content_type);
+        let format = match format {
+            Some(format @ (Format::MessagePack | Format::Cbor)) => format,
+            _ => return ApiJson::from_request(request, state).await.map(|ApiJson(value)| ApiBody(value)),
+        };
+        let body = Bytes::from_request(request, state).await.map_err(|rejection| match body_limit::exceeded(&rejection) {
+            Some(limit) => ApiError::PayloadTooLarge(limit),
+            None => ApiError::InvalidBody(rejection.status(), rejection.body_text()),
+        })?;
+        let decoded = match format {
+            Format::MessagePack => rmp_serde::from_slice(&body).map_err(|e| {
+                use rmp_serde::decode::Error;
+                let status = match e {
+                    Error::Syntax(_) | Error::TypeMismatch(_) | Error::OutOfRange | Error::LengthMismatch(_) => StatusCode::UNPROCESSABLE_ENTITY,
+                    _ => StatusCode::BAD_REQUEST,
+                };
+                (status, e.to_string())
+            }),
+            _ => ciborium::from_reader(&body[..]).map_err(|e| {
+                let status = match e {
+                    ciborium::de::Error::Semantic(..) => StatusCode::UNPROCESSABLE_ENTITY,
+                    _ => StatusCode::BAD_REQUEST,
+                };
+                (status, e.to_string())
+            }),
+        };
+        decoded.map(ApiBody).map_err(|(status, e)| ApiError::InvalidBody(status, format!("Failed to deserialize the {} body: {}", format.name(), e)))
+    }
+}
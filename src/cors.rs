@@ -0,0 +1,113 @@
+use std::{sync::LazyLock, time::Duration};
+use axum::{body::Body, extract::{Extension, Request}, http::{header, HeaderMap, HeaderName, HeaderValue, Method, StatusCode}, middleware::Next, response::Response};
+
+use crate::auth::API_KEY_HEADER;
+use crate::idempotency::{IDEMPOTENCY_KEY_HEADER, REPLAYED_HEADER};
+use crate::request_id::REQUEST_ID_HEADER;
+use crate::settings::Settings;
+
+// Lets browser frontends on other origins call the API. Nothing is allowed until origins are configured, so a server
+// that doesn't set any sends no CORS headers at all and browsers keep other sites' scripts out of its responses.
+
+pub const DEFAULT_MAX_AGE: Duration = Duration::from_secs(600);
+
+// Everything the routes answer to.
+pub const DEFAULT_METHODS: &[Method] = &[Method::GET, Method::HEAD, Method::POST, Method::PUT, Method::PATCH, Method::DELETE];
+
+// Everything the API reads from a request that isn't already allowed cross-origin.
+pub static DEFAULT_HEADERS: LazyLock<Vec<HeaderName>> = LazyLock::new(|| vec![
+    header::AUTHORIZATION,
+    header::CONTENT_TYPE,
+    header::CONTENT_ENCODING,
+    header::IF_MATCH,
+    header::IF_NONE_MATCH,
+    API_KEY_HEADER.clone(),
+    IDEMPOTENCY_KEY_HEADER.clone(),
+    REQUEST_ID_HEADER.clone(),
+]);
+
+// Response headers scripts get to read, beyond the handful browsers always show them.
+static EXPOSED_HEADERS: LazyLock<HeaderValue> = LazyLock::new(|| {
+    let exposed = [header::ETAG, header::LOCATION, header::RETRY_AFTER, header::CONTENT_DISPOSITION, REQUEST_ID_HEADER.clone(), REPLAYED_HEADER.clone()];
+    HeaderValue::from_str(&exposed.map(|name| name.to_string()).join(", ")).unwrap()
+});
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AllowedOrigins {
+    // Any origin, with whatever methods and headers it asks for. Meant for development against a local frontend.
+    Any,
+    // Exact origins, like https://movies.example.com.
+    List(Vec<String>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CorsConfig {
+    pub origins: AllowedOrigins,
+    // With AllowedOrigins::Any, None allows whatever the preflight asks for. Otherwise None means the defaults above.
+    pub methods: Option<Vec<Method>>,
+    pub headers: Option<Vec<HeaderName>>,
+    // How long browsers may cache a preflight's answer.
+    pub max_age: Duration,
+}
+
+fn join<T: ToString>(items: &[T]) -> HeaderValue {
+    HeaderValue::from_str(&items.iter().map(ToString::to_string).collect::<Vec<_>>().join(", ")).unwrap()
+}
+
+fn allowed_origin(config: &CorsConfig, origin: &HeaderValue) -> Option<HeaderValue> {
+    match &config.origins {
+        AllowedOrigins::Any => Some(HeaderValue::from_static("*")),
+        AllowedOrigins::List(origins) => origins.iter().any(|allowed| allowed.as_bytes() == origin.as_bytes()).then(|| origin.clone()),
+    }
+}
+
+fn preflight(config: &CorsConfig, origin: Option<HeaderValue>, request: &HeaderMap) -> Response {
+    let mut response = Response::new(Body::empty());
+    *response.status_mut() = StatusCode::NO_CONTENT;
+    let headers = response.headers_mut();
+    headers.insert(header::VARY, HeaderValue::from_static("origin, access-control-request-method, access-control-request-headers"));
+    // A browser that gets no Access-Control-Allow-Origin back doesn't send the request it was asking about.
+    let Some(origin) = origin else { return response };
+    headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+    let any = config.origins == AllowedOrigins::Any;
+    let methods = match &config.methods {
+        None if any => request.get(header::ACCESS_CONTROL_REQUEST_METHOD).cloned().unwrap_or_else(|| join(DEFAULT_METHODS)),
+        None => join(DEFAULT_METHODS),
+        Some(methods) => join(methods),
+    };
+    headers.insert(header::ACCESS_CONTROL_ALLOW_METHODS, methods);
+    let allowed_headers = match &config.headers {
+        None if any => request.get(header::ACCESS_CONTROL_REQUEST_HEADERS).cloned(),
+        None => Some(join(&DEFAULT_HEADERS)),
+        Some(names) => Some(join(names)),
+    };
+    if let Some(allowed_headers) = allowed_headers {
+        headers.insert(header::ACCESS_CONTROL_ALLOW_HEADERS, allowed_headers);
+    }
+    headers.insert(header::ACCESS_CONTROL_MAX_AGE, HeaderValue::from(config.max_age.as_secs()));
+    response
+}
+
+// Middleware for the whole router. Preflights are answered here without going any further, so they need no
+// credentials and don't count against rate limits. Everything else gets the CORS headers added to whatever it gets
+// back, errors included, so scripts can read why a request failed.
+pub async fn handle_cors(Extension(settings): Extension<Settings>, request: Request, next: Next) -> Response {
+    let Some(config) = settings.cors() else {
+        return next.run(request).await;
+    };
+    let origin = request.headers().get(header::ORIGIN).and_then(|origin| allowed_origin(&config, origin));
+    if request.method() == Method::OPTIONS && request.headers().contains_key(header::ACCESS_CONTROL_REQUEST_METHOD) {
+        return preflight(&config, origin, request.headers());
+    }
+    let mut response = next.run(request).await;
+    let headers = response.headers_mut();
+    // What we send depends on the Origin unless it's always *, so shared caches have to keep them apart.
+    if config.origins != AllowedOrigins::Any {
+        headers.append(header::VARY, HeaderValue::from_static("origin"));
+    }
+    if let Some(origin) = origin {
+        headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+        headers.insert(header::ACCESS_CONTROL_EXPOSE_HEADERS, EXPOSED_HEADERS.clone());
+    }
+    response
+}
@@ -0,0 +1,350 @@
+// The few primitives API keys and JWTs need: SHA-256, HMAC-SHA256, base64 and RSA PKCS#1 v1.5 signature checks. No
+// crypto crate is available to this build, so they're written out here. Mostly used to check things clients send. The
+// only thing of ours that's signed is list cursors, with HMAC, and nothing is encrypted, which keeps timing attacks on
+// the RSA code moot.
+
+// SHA-256 (FIPS 180-4).
+pub fn sha256(data: &[u8]) -> [u8; 32] {
+    const K: [u32; 64] = [
+        0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+        0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+        0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+        0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+        0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+        0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+        0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+        0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+    ];
+    let mut state: [u32; 8] = [0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&(data.len() as u64 * 8).to_be_bytes());
+
+    for block in message.chunks(64) {
+        let mut words = [0u32; 64];
+        for (word, bytes) in words.iter_mut().zip(block.chunks(4)) {
+            *word = u32::from_be_bytes(bytes.try_into().unwrap());
+        }
+        for i in 16..64 {
+            let s0 = words[i - 15].rotate_right(7) ^ words[i - 15].rotate_right(18) ^ (words[i - 15] >> 3);
+            let s1 = words[i - 2].rotate_right(17) ^ words[i - 2].rotate_right(19) ^ (words[i - 2] >> 10);
+            words[i] = words[i - 16].wrapping_add(s0).wrapping_add(words[i - 7]).wrapping_add(s1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = state;
+        for (k, word) in K.iter().zip(words) {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let choice = (e & f) ^ (!e & g);
+            let temp1 = h.wrapping_add(s1).wrapping_add(choice).wrapping_add(*k).wrapping_add(word);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let majority = (a & b) ^ (a & c) ^ (b & c);
+            let temp2 = s0.wrapping_add(majority);
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(temp1);
+            d = c;
+            c = b;
+            b = a;
+            a = temp1.wrapping_add(temp2);
+        }
+        for (value, add) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *value = value.wrapping_add(add);
+        }
+    }
+
+    let mut digest = [0u8; 32];
+    for (bytes, value) in digest.chunks_mut(4).zip(state) {
+        bytes.copy_from_slice(&value.to_be_bytes());
+    }
+    digest
+}
+
+// HMAC-SHA256 (RFC 2104).
+pub fn hmac_sha256(key: &[u8], message: &[u8]) -> [u8; 32] {
+    let mut block = [0u8; 64];
+    if key.len() > 64 {
+        block[..32].copy_from_slice(&sha256(key));
+    }
+    else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let inner: Vec<u8> = block.iter().map(|byte| byte ^ 0x36).chain(message.iter().copied()).collect();
+    let outer: Vec<u8> = block.iter().map(|byte| byte ^ 0x5c).chain(sha256(&inner)).collect();
+    sha256(&outer)
+}
+
+// Compares every byte rather than stopping at the first difference, so the time taken doesn't tell how close a guess
+// came.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+// Decodes standard or URL-safe base64, with or without padding. Whitespace is skipped, for PEM.
+pub fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::new();
+    let mut bits = 0u32;
+    let mut count = 0;
+    for c in text.bytes().filter(|byte| !byte.is_ascii_whitespace()) {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            b'=' => break,
+            _ => return None,
+        };
+        bits = bits << 6 | value as u32;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            bytes.push((bits >> count) as u8);
+        }
+    }
+    Some(bytes)
+}
+
+// Encodes as URL-safe base64 without padding, the way JWTs and PKCE want it.
+pub fn base64url_encode(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+    let mut text = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, byte)| bits | (*byte as u32) << (16 - 8 * i));
+        for i in 0..=chunk.len() {
+            text.push(ALPHABET[(bits >> (18 - 6 * i) & 0x3f) as usize] as char);
+        }
+    }
+    text
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RsaPublicKey {
+    // Big-endian, without leading zeros.
+    modulus: Vec<u8>,
+    exponent: Vec<u8>,
+}
+
+// DigestInfo for SHA-256 (RFC 8017 section 9.2), which goes in front of the hash in a PKCS#1 v1.5 signature.
+const SHA256_DIGEST_INFO: [u8; 19] = [0x30, 0x31, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01, 0x05, 0x00, 0x04, 0x20];
+// 1.2.840.113549.1.1.1, rsaEncryption.
+const RSA_ENCRYPTION_OID: [u8; 9] = [0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01];
+
+impl RsaPublicKey {
+    // Reads a PEM "PUBLIC KEY" (SubjectPublicKeyInfo, what `openssl rsa -pubout` writes) or "RSA PUBLIC KEY" (PKCS#1).
+    pub fn from_pem(pem: &str) -> Result<RsaPublicKey, String> {
+        let (label, body) = pem_body(pem).ok_or("expected a PEM block")?;
+        let der = base64_decode(body).ok_or("PEM body isn't base64")?;
+        match label {
+            "PUBLIC KEY" => {
+                let mut spki = Der::new(&der).sequence()?;
+                let mut algorithm = spki.sequence()?;
+                if algorithm.read(0x06)? != RSA_ENCRYPTION_OID {
+                    return Err("not an RSA key".to_string());
+                }
+                let key = spki.read(0x03)?;
+                match key.split_first() {
+                    Some((0, key)) => RsaPublicKey::from_pkcs1(key),
+                    _ => Err("malformed public key bit string".to_string()),
+                }
+            },
+            "RSA PUBLIC KEY" => RsaPublicKey::from_pkcs1(&der),
+            other => Err(format!("expected a PUBLIC KEY, got {}", other)),
+        }
+    }
+
+    fn from_pkcs1(der: &[u8]) -> Result<RsaPublicKey, String> {
+        let mut key = Der::new(der).sequence()?;
+        let modulus = key.read(0x02)?;
+        let exponent = key.read(0x02)?;
+        RsaPublicKey::from_components(modulus, exponent)
+    }
+
+    // From the big-endian modulus and exponent, like the n and e of a JWK.
+    pub fn from_components(modulus: &[u8], exponent: &[u8]) -> Result<RsaPublicKey, String> {
+        let strip = |bytes: &[u8]| bytes.iter().skip_while(|byte| **byte == 0).copied().collect::<Vec<u8>>();
+        let (modulus, exponent) = (strip(modulus), strip(exponent));
+        if modulus.len() < 256 {
+            return Err(format!("RSA keys need at least 2048 bits, this one has {}", modulus.len() * 8));
+        }
+        Ok(RsaPublicKey { modulus, exponent })
+    }
+
+    // Checks an RSASSA-PKCS1-v1_5 signature with SHA-256 (RS256 in JWT terms).
+    pub fn verify_sha256(&self, message: &[u8], signature: &[u8]) -> bool {
+        let len = self.modulus.len();
+        if signature.len() != len {
+            return false;
+        }
+        let modulus = BigUint::from_be_bytes(&self.modulus);
+        let signature = BigUint::from_be_bytes(signature);
+        if signature.cmp(&modulus).is_ge() {
+            return false;
+        }
+        let decrypted = signature.pow_mod(&self.exponent, &modulus).to_be_bytes(len);
+        let mut expected = vec![0x00, 0x01];
+        expected.resize(len - SHA256_DIGEST_INFO.len() - 32 - 1, 0xff);
+        expected.push(0x00);
+        expected.extend_from_slice(&SHA256_DIGEST_INFO);
+        expected.extend_from_slice(&sha256(message));
+        constant_time_eq(&decrypted, &expected)
+    }
+}
+
+fn pem_body(pem: &str) -> Option<(&str, &str)> {
+    let start = pem.find("-----BEGIN ")?;
+    let rest = &pem[start + "-----BEGIN ".len()..];
+    let label_end = rest.find("-----")?;
+    let label = &rest[..label_end];
+    let body = &rest[label_end + 5..];
+    let end = body.find(&format!("-----END {}-----", label))?;
+    Some((label, &body[..end]))
+}
+
+// Just enough DER to take apart a public key: definite lengths, one tag byte.
+struct Der<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Der<'a> {
+    fn new(bytes: &'a [u8]) -> Der<'a> {
+        Der { bytes }
+    }
+
+    // The contents of the next element, which has to have this tag.
+    fn read(&mut self, tag: u8) -> Result<&'a [u8], String> {
+        let malformed = || "malformed DER".to_string();
+        let (&found, rest) = self.bytes.split_first().ok_or_else(malformed)?;
+        if found != tag {
+            return Err(format!("expected DER tag {:#04x}, got {:#04x}", tag, found));
+        }
+        let (&first, mut rest) = rest.split_first().ok_or_else(malformed)?;
+        let len = if first < 0x80 {
+            first as usize
+        }
+        else {
+            let count = (first & 0x7f) as usize;
+            if count == 0 || count > 4 || rest.len() < count {
+                return Err(malformed());
+            }
+            let len = rest[..count].iter().fold(0usize, |len, byte| len << 8 | *byte as usize);
+            rest = &rest[count..];
+            len
+        };
+        if rest.len() < len {
+            return Err(malformed());
+        }
+        let (contents, rest) = rest.split_at(len);
+        self.bytes = rest;
+        Ok(contents)
+    }
+
+    fn sequence(&mut self) -> Result<Der<'a>, String> {
+        self.read(0x30).map(Der::new)
+    }
+}
+
+// Unsigned integers as little-endian 32-bit limbs, with only what pow_mod needs. Multiplication is done a bit at a time
+// by shifting and adding, which is slow but simple; with the usual exponent of 65537 a 2048-bit check still takes
+// a few milliseconds in release builds.
+#[derive(Debug, Clone, PartialEq)]
+struct BigUint {
+    limbs: Vec<u32>,
+}
+
+impl BigUint {
+    fn from_be_bytes(bytes: &[u8]) -> BigUint {
+        let mut limbs: Vec<u32> = bytes.rchunks(4)
+            .map(|chunk| chunk.iter().fold(0u32, |limb, byte| limb << 8 | *byte as u32))
+            .collect();
+        while limbs.last() == Some(&0) {
+            limbs.pop();
+        }
+        BigUint { limbs }
+    }
+
+    fn to_be_bytes(&self, len: usize) -> Vec<u8> {
+        let mut bytes: Vec<u8> = self.limbs.iter().rev().flat_map(|limb| limb.to_be_bytes()).collect();
+        let leading = bytes.iter().take_while(|byte| **byte == 0).count();
+        bytes.drain(..leading);
+        let mut padded = vec![0; len.saturating_sub(bytes.len())];
+        padded.extend(bytes);
+        padded
+    }
+
+    fn bits(&self) -> usize {
+        self.limbs.last().map_or(0, |top| (self.limbs.len() - 1) * 32 + (32 - top.leading_zeros() as usize))
+    }
+
+    fn bit(&self, i: usize) -> bool {
+        self.limbs.get(i / 32).is_some_and(|limb| limb >> (i % 32) & 1 == 1)
+    }
+
+    fn cmp(&self, other: &BigUint) -> std::cmp::Ordering {
+        self.limbs.len().cmp(&other.limbs.len())
+            .then_with(|| self.limbs.iter().rev().cmp(other.limbs.iter().rev()))
+    }
+
+    fn add(&mut self, other: &BigUint) {
+        let mut carry = 0u64;
+        for i in 0..self.limbs.len().max(other.limbs.len()) {
+            if i == self.limbs.len() {
+                self.limbs.push(0);
+            }
+            let sum = self.limbs[i] as u64 + *other.limbs.get(i).unwrap_or(&0) as u64 + carry;
+            self.limbs[i] = sum as u32;
+            carry = sum >> 32;
+        }
+        if carry > 0 {
+            self.limbs.push(carry as u32);
+        }
+    }
+
+    // Only called with other <= self.
+    fn sub(&mut self, other: &BigUint) {
+        let mut borrow = 0i64;
+        for i in 0..self.limbs.len() {
+            let difference = self.limbs[i] as i64 - *other.limbs.get(i).unwrap_or(&0) as i64 - borrow;
+            self.limbs[i] = difference.rem_euclid(1 << 32) as u32;
+            borrow = (difference < 0) as i64;
+        }
+        while self.limbs.last() == Some(&0) {
+            self.limbs.pop();
+        }
+    }
+
+    // (self + other) mod modulus, for values already below it.
+    fn add_mod(&mut self, other: &BigUint, modulus: &BigUint) {
+        self.add(other);
+        if self.cmp(modulus).is_ge() {
+            self.sub(modulus);
+        }
+    }
+
+    fn mul_mod(&self, other: &BigUint, modulus: &BigUint) -> BigUint {
+        let mut result = BigUint { limbs: Vec::new() };
+        for i in (0..self.bits()).rev() {
+            let doubled = result.clone();
+            result.add_mod(&doubled, modulus);
+            if self.bit(i) {
+                result.add_mod(other, modulus);
+            }
+        }
+        result
+    }
+
+    fn pow_mod(&self, exponent: &[u8], modulus: &BigUint) -> BigUint {
+        let mut result = BigUint { limbs: vec![1] };
+        for byte in exponent {
+            for i in (0..8).rev() {
+                result = result.mul_mod(&result, modulus);
+                if byte >> i & 1 == 1 {
+                    result = result.mul_mod(self, modulus);
+                }
+            }
+        }
+        result
+    }
+}
@@ -0,0 +1,172 @@
+// CSV (RFC 4180) for imports and exports. It's read incrementally, so an import never needs more than one record in
+// memory. Fields are separated by commas and may be quoted, with "" for a quote inside quotes. Line endings are \n or
+// \r\n, and blank lines are skipped.
+
+// Records longer than this are rejected rather than buffered, whatever they contain.
+const MAX_RECORD_BYTES: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+    StartOfField,
+    Unquoted,
+    Quoted,
+    // Just saw a quote inside a quoted field: either the end of it or the first half of "".
+    QuoteInQuoted,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct CsvRecord {
+    // Line the record starts on, counting from 1. Quoted fields can make a record span several.
+    pub line: usize,
+    // The fields, or why the record couldn't be read. A bad record doesn't stop the ones after it.
+    pub fields: Result<Vec<String>, String>,
+}
+
+#[derive(Debug)]
+pub struct CsvReader {
+    state: State,
+    field: Vec<u8>,
+    fields: Vec<String>,
+    error: Option<String>,
+    // Whether anything but line endings has been seen since the last record, to tell blank lines apart.
+    started: bool,
+    record_len: usize,
+    line: usize,
+    record_line: usize,
+}
+
+impl Default for CsvReader {
+    fn default() -> CsvReader {
+        CsvReader::new()
+    }
+}
+
+impl CsvReader {
+    pub fn new() -> CsvReader {
+        CsvReader {
+            state: State::StartOfField,
+            field: Vec::new(),
+            fields: Vec::new(),
+            error: None,
+            started: false,
+            record_len: 0,
+            line: 1,
+            record_line: 1,
+        }
+    }
+
+    // The records completed by this chunk of input. Chunks can be split anywhere, even inside a UTF-8 character.
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<CsvRecord> {
+        bytes.iter().filter_map(|&byte| self.push(byte)).collect()
+    }
+
+    // The last record, for input that doesn't end with a line ending.
+    pub fn finish(mut self) -> Option<CsvRecord> {
+        if self.state == State::Quoted {
+            self.error.get_or_insert_with(|| "Quoted field is never closed".to_string());
+        }
+        self.end_record()
+    }
+
+    fn push(&mut self, byte: u8) -> Option<CsvRecord> {
+        match (self.state, byte) {
+            (State::Quoted, b'"') => self.state = State::QuoteInQuoted,
+            (State::Quoted, byte) => {
+                if byte == b'\n' {
+                    self.line += 1;
+                }
+                self.push_byte(byte);
+            },
+            (State::QuoteInQuoted, b'"') => {
+                self.push_byte(b'"');
+                self.state = State::Quoted;
+            },
+            (_, b'\r') => {},
+            (_, b',') => {
+                self.started = true;
+                self.end_field();
+                self.state = State::StartOfField;
+            },
+            (_, b'\n') => {
+                let record = self.end_record();
+                self.line += 1;
+                self.record_line = self.line;
+                return record;
+            },
+            (State::StartOfField, b'"') => {
+                self.started = true;
+                self.state = State::Quoted;
+            },
+            (State::QuoteInQuoted, _) => {
+                self.error.get_or_insert_with(|| "Unexpected character after a closing quote".to_string());
+                self.state = State::Unquoted;
+            },
+            (_, byte) => {
+                // A quote in the middle of an unquoted field is kept as it is.
+                self.started = true;
+                self.push_byte(byte);
+                self.state = State::Unquoted;
+            },
+        }
+        None
+    }
+
+    fn push_byte(&mut self, byte: u8) {
+        self.record_len += 1;
+        if self.record_len > MAX_RECORD_BYTES {
+            self.error.get_or_insert_with(|| format!("Record is longer than {} bytes", MAX_RECORD_BYTES));
+        }
+        else {
+            self.field.push(byte);
+        }
+    }
+
+    fn end_field(&mut self) {
+        let field = std::mem::take(&mut self.field);
+        match String::from_utf8(field) {
+            Ok(field) => self.fields.push(field),
+            Err(_) => {
+                self.error.get_or_insert_with(|| "Record isn't valid UTF-8".to_string());
+            },
+        }
+    }
+
+    fn end_record(&mut self) -> Option<CsvRecord> {
+        let started = std::mem::take(&mut self.started);
+        self.state = State::StartOfField;
+        self.record_len = 0;
+        if !started {
+            return None;
+        }
+        self.end_field();
+        let fields = std::mem::take(&mut self.fields);
+        Some(CsvRecord {
+            line: self.record_line,
+            fields: match self.error.take() {
+                Some(error) => Err(error),
+                None => Ok(fields),
+            },
+        })
+    }
+}
+
+// One record as a line of CSV, quoting the fields that need it.
+pub fn write_record<S: AsRef<str>>(fields: &[S]) -> String {
+    let mut line = String::new();
+    for (i, field) in fields.iter().enumerate() {
+        let field = field.as_ref();
+        if i > 0 {
+            line.push(',');
+        }
+        if field.contains([',', '"', '\r', '\n']) || field.starts_with(' ') || field.ends_with(' ') {
+            line.push('"');
+            line.push_str(&field.replace('"', "\"\""));
+            line.push('"');
+        }
+        else {
+            line.push_str(field);
+        }
+    }
+    line.push_str("\r\n");
+    line
+}
@@ -0,0 +1,67 @@
+use std::sync::{LazyLock, RwLock};
+use serde::{Deserialize, Serialize};
+
+use crate::crypto;
+use crate::model::Movie;
+use crate::random;
+use crate::sort::Sort;
+
+// Keyset pagination for GET /movies?cursor=. Rather than an offset, which skips or repeats movies when others are
+// added or removed in front of it, a cursor holds the sort keys of the last movie on the page, and the next page starts
+// right after wherever that movie would sort. The cursor is HMAC-signed, so clients can only hand back ones we gave
+// out, and is opaque to them: its contents may change between releases.
+
+// Without --cursor-secret a key is made up at startup, so cursors stop working across restarts and between instances
+// behind the same load balancer.
+static SECRET: LazyLock<RwLock<Vec<u8>>> = LazyLock::new(|| RwLock::new(random_secret()));
+
+fn random_secret() -> Vec<u8> {
+    let mut secret = vec![0u8; 32];
+    random::fill_bytes(&mut secret);
+    secret
+}
+
+// None goes back to a key of our own. Cursors signed with the old one are refused from then on.
+pub fn set_secret(secret: Option<Vec<u8>>) {
+    *SECRET.write().unwrap() = secret.unwrap_or_else(random_secret);
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Cursor {
+    // The sort the cursor was made for, as given in ?sort=. A cursor means nothing in another order.
+    pub sort: String,
+    // The last movie on the page, with only the fields any sort can use.
+    pub id: String,
+    pub name: String,
+    pub year: u16,
+    pub was_good: bool,
+}
+
+impl Cursor {
+    pub fn after(movie: &Movie, sort: &Sort) -> Cursor {
+        Cursor { sort: sort.to_string(), id: movie.id.clone(), name: movie.name.clone(), year: movie.year, was_good: movie.was_good }
+    }
+
+    // Stands in for the movie the cursor was made after, which may have been changed or deleted since.
+    pub fn last(&self) -> Movie {
+        Movie { id: self.id.clone(), name: self.name.clone(), year: self.year, was_good: self.was_good, ..Movie::default() }
+    }
+
+    // base64url of the JSON, a dot and base64url of its signature.
+    pub fn encode(&self) -> String {
+        let payload = serde_json::to_vec(self).expect("cursors always serialize");
+        let signature = crypto::hmac_sha256(&SECRET.read().unwrap(), &payload);
+        format!("{}.{}", crypto::base64url_encode(&payload), crypto::base64url_encode(&signature))
+    }
+
+    pub fn decode(token: &str) -> Result<Cursor, String> {
+        let invalid = || "Invalid cursor, it may have expired: start again without one".to_string();
+        let (payload, signature) = token.split_once('.').ok_or_else(invalid)?;
+        let payload = crypto::base64_decode(payload).ok_or_else(invalid)?;
+        let signature = crypto::base64_decode(signature).ok_or_else(invalid)?;
+        if !crypto::constant_time_eq(&crypto::hmac_sha256(&SECRET.read().unwrap(), &payload), &signature) {
+            return Err(invalid());
+        }
+        serde_json::from_slice(&payload).map_err(|_| invalid())
+    }
+}
@@ -0,0 +1,52 @@
+use std::str::FromStr;
+
+use crate::error::ApiError;
+use crate::model::Movie;
+use crate::similar;
+use crate::store::{MovieFilter, MovieStore};
+
+// Catches a movie going in twice under different ids, which id collisions can't: a new movie is a duplicate of any
+// other with the same year and the same name once similar::normalize has evened out case, accents, punctuation, a
+// leading "The" and a trailing "(year)", so "Amélie" duplicates "Amelie" and "The Matrix" duplicates "Matrix". By
+// default it's added anyway and POST /movie says so in a Warning header. With --duplicates=reject it's refused with a
+// 409 unless the request says allow_duplicate=true, for the odd remake that shares both. Duplicates that got in can be
+// folded into one with POST /admin/merge.
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum DuplicatePolicy {
+    #[default]
+    Warn,
+    Reject,
+}
+
+impl FromStr for DuplicatePolicy {
+    type Err = String;
+
+    fn from_str(policy: &str) -> Result<DuplicatePolicy, String> {
+        match policy {
+            "warn" => Ok(DuplicatePolicy::Warn),
+            "reject" => Ok(DuplicatePolicy::Reject),
+            other => Err(format!("expected warn or reject, got {:?}", other)),
+        }
+    }
+}
+
+// The first movie, in id order, that `movie` would duplicate. A movie never duplicates itself, so overwriting one
+// under its own id doesn't count.
+pub async fn find(store: &dyn MovieStore, movie: &Movie) -> Option<Movie> {
+    let name = similar::normalize(&movie.name).0;
+    let same_year = store.list(&MovieFilter { year: Some(movie.year), ..MovieFilter::default() }).await;
+    same_year.into_iter().find(|other| other.id != movie.id && similar::normalize(&other.name).0 == name)
+}
+
+// The movie that `movie` would duplicate, to warn about, or Duplicate if the policy is to reject it and the request
+// didn't say to allow it.
+pub async fn check(store: &dyn MovieStore, policy: DuplicatePolicy, movie: &Movie, allow_duplicate: bool) -> Result<Option<Movie>, ApiError> {
+    let Some(existing) = find(store, movie).await else {
+        return Ok(None);
+    };
+    if policy == DuplicatePolicy::Reject && !allow_duplicate {
+        return Err(ApiError::Duplicate(Box::new(existing)));
+    }
+    Ok(Some(existing))
+}
@@ -0,0 +1,266 @@
+use std::{collections::HashMap, fmt, str::FromStr, sync::{Arc, LazyLock, Mutex}, time::{Duration, Instant}};
+use serde::{de::DeserializeOwned, Deserialize};
+use tracing::{debug, warn};
+
+use crate::http_client;
+use crate::model::Movie;
+use crate::resilience::{self, BreakerPolicy, CallError, Integration, RetryPolicy};
+use crate::validation::{validate_movie, MAX_GENRES};
+
+// Fills in a new movie's runtime, genres and poster URL from OMDb or TMDB, when POST /movie is sent nothing but a
+// name and year. It's a nicety, so the movie goes in whatever happens: a provider that doesn't know the movie, is
+// slow, or sends back something that doesn't validate just leaves it as it was. Answers are cached by name and year,
+// and calls go through resilience.rs, so after a run of failures the provider is left alone for a while and an outage
+// soon costs a create nothing rather than PROVIDER_TIMEOUT every time. Batches and imports aren't
+// enriched, a thousand lookups per request being more than any provider's rate limit allows.
+// Like every outbound call here it only speaks plain http, see http_client.rs.
+
+pub const OMDB_URL: &str = "http://www.omdbapi.com";
+// TMDB hands out poster paths, which go after this to make a URL.
+const TMDB_IMAGE_URL: &str = "https://image.tmdb.org/t/p/w500";
+// Short, since someone is waiting on the create.
+const PROVIDER_TIMEOUT: Duration = Duration::from_secs(3);
+// Runtimes and genres don't change, but posters and the provider's mistakes get fixed.
+const CACHE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+const MAX_CACHED: usize = 10_000;
+// One quick retry at most, someone's still waiting.
+const RETRY: RetryPolicy = RetryPolicy { attempts: 2, base_delay: Duration::from_millis(50), max_delay: Duration::from_millis(50) };
+const BREAKER: BreakerPolicy = BreakerPolicy { failures_to_open: 5, open_for: Duration::from_secs(30) };
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Provider {
+    Omdb,
+    Tmdb,
+}
+
+impl FromStr for Provider {
+    type Err = String;
+
+    fn from_str(provider: &str) -> Result<Provider, String> {
+        match provider.to_ascii_lowercase().as_str() {
+            "omdb" => Ok(Provider::Omdb),
+            "tmdb" => Ok(Provider::Tmdb),
+            other => Err(format!("expected omdb or tmdb, got {:?}", other)),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq)]
+pub struct EnrichConfig {
+    pub provider: Provider,
+    // Where the provider's API is, without a trailing slash: OMDB_URL, or a proxy in front of the provider.
+    pub url: String,
+    pub api_key: String,
+}
+
+// Keeps the key out of logs.
+impl fmt::Debug for EnrichConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EnrichConfig")
+            .field("provider", &self.provider)
+            .field("url", &self.url)
+            .finish_non_exhaustive()
+    }
+}
+
+// What a provider knows about a movie. Any of it can be missing.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Enrichment {
+    pub runtime_minutes: Option<u16>,
+    pub genres: Vec<String>,
+    pub poster_url: Option<String>,
+}
+
+#[derive(Default)]
+struct Enricher {
+    config: Option<EnrichConfig>,
+    // By lowercased name and year, with when it was fetched. None if the provider didn't know the movie.
+    cache: HashMap<(String, u16), (Instant, Option<Enrichment>)>,
+}
+
+static ENRICHER: LazyLock<Mutex<Enricher>> = LazyLock::new(|| Mutex::new(Enricher::default()));
+static PROVIDER: LazyLock<Arc<Integration>> = LazyLock::new(|| Integration::register("enrich", RETRY, BREAKER));
+
+// None turns enrichment off. A different provider starts with nothing cached and the breaker closed.
+pub fn set_config(config: Option<EnrichConfig>) {
+    let mut enricher = ENRICHER.lock().unwrap();
+    if enricher.config != config {
+        *enricher = Enricher { config, ..Enricher::default() };
+        PROVIDER.reset();
+    }
+}
+
+// Whether the movie came with nothing but a name and year, so there's nothing the provider could overrule.
+fn bare(movie: &Movie) -> bool {
+    movie.genres.is_empty() && movie.director.is_none() && movie.runtime_minutes.is_none() && movie.synopsis.is_none()
+        && movie.poster_url.is_none() && movie.tags.is_empty()
+}
+
+// Fills in what the provider knows about the movie, if it's bare and enrichment is on. Never fails, see the top.
+pub async fn enrich(movie: &mut Movie) {
+    if !bare(movie) {
+        return;
+    }
+    let key = (movie.name.to_lowercase(), movie.year);
+    let config = {
+        let enricher = ENRICHER.lock().unwrap();
+        let Some(config) = enricher.config.clone() else { return };
+        match enricher.cache.get(&key) {
+            Some((fetched, found)) if fetched.elapsed() < CACHE_TTL => {
+                if let Some(found) = found.clone() {
+                    drop(enricher);
+                    apply(movie, found);
+                }
+                return;
+            },
+            _ => config,
+        }
+    };
+
+    let result = PROVIDER.call(|| async {
+        match config.provider {
+            Provider::Omdb => fetch_omdb(&config, &movie.name, movie.year).await,
+            Provider::Tmdb => fetch_tmdb(&config, &movie.name, movie.year).await,
+        }
+    }).await;
+    let found = match result {
+        Ok(found) => found,
+        Err(CallError::Open) => {
+            debug!("Not enriching {:?}, the metadata provider is failing", movie.name);
+            return;
+        },
+        Err(e) => {
+            warn!("Couldn't enrich {:?} ({}): the metadata provider {}", movie.name, movie.year, e);
+            return;
+        },
+    };
+    {
+        let mut enricher = ENRICHER.lock().unwrap();
+        // Answers from a provider that's since been swapped out aren't kept.
+        if enricher.config.as_ref() == Some(&config) {
+            let now = Instant::now();
+            if enricher.cache.len() >= MAX_CACHED {
+                enricher.cache.retain(|_, (fetched, _)| now.duration_since(*fetched) < CACHE_TTL);
+                // Everything still fresh: start over rather than pick what to drop one by one.
+                if enricher.cache.len() >= MAX_CACHED {
+                    enricher.cache.clear();
+                }
+            }
+            enricher.cache.insert(key, (now, found.clone()));
+        }
+    }
+    match found {
+        Some(found) => apply(movie, found),
+        None => debug!("The metadata provider doesn't know {:?} ({})", movie.name, movie.year),
+    }
+}
+
+// Takes what the provider found, unless that makes the movie invalid, e.g. a runtime of 0 or a poster without a URL.
+fn apply(movie: &mut Movie, found: Enrichment) {
+    let mut enriched = movie.clone();
+    enriched.runtime_minutes = found.runtime_minutes;
+    enriched.genres = found.genres;
+    enriched.poster_url = found.poster_url;
+    match validate_movie(&enriched) {
+        Ok(()) => *movie = enriched,
+        Err(errors) => warn!("Not enriching {:?}, the metadata provider's {} isn't valid", movie.name, errors[0].field),
+    }
+}
+
+// The URL isn't in the errors, since it has the API key in it.
+async fn get_json<T: DeserializeOwned>(url: &str) -> Result<T, CallError> {
+    let response = http_client::request("GET", url, &[("Accept", "application/json")], b"", PROVIDER_TIMEOUT).await?;
+    if response.status != 200 {
+        return Err(resilience::status_error(response.status));
+    }
+    serde_json::from_slice(&response.body).map_err(|e| CallError::Permanent(format!("sent an unexpected response: {}", e)))
+}
+
+fn query(pairs: &[(&str, &str)]) -> Result<String, CallError> {
+    serde_urlencoded::to_string(pairs).map_err(|e| CallError::Permanent(e.to_string()))
+}
+
+// Each genre once, and no more of them than a movie can have.
+fn genres<'a>(names: impl Iterator<Item = &'a str>) -> Vec<String> {
+    let mut genres: Vec<String> = Vec::new();
+    for name in names.map(str::trim).filter(|name| !name.is_empty()) {
+        if genres.len() < MAX_GENRES && !genres.iter().any(|genre| genre.eq_ignore_ascii_case(name)) {
+            genres.push(name.to_string());
+        }
+    }
+    genres
+}
+
+// OMDb's answer to ?t=, with N/A for whatever it doesn't know.
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct OmdbMovie {
+    response: String,
+    #[serde(default)]
+    error: Option<String>,
+    #[serde(default)]
+    runtime: Option<String>,
+    #[serde(default)]
+    genre: Option<String>,
+    #[serde(default)]
+    poster: Option<String>,
+}
+
+async fn fetch_omdb(config: &EnrichConfig, name: &str, year: u16) -> Result<Option<Enrichment>, CallError> {
+    let query = query(&[("apikey", &config.api_key), ("t", name), ("y", &year.to_string()), ("type", "movie")])?;
+    let movie: OmdbMovie = get_json(&format!("{}/?{}", config.url, query)).await?;
+    if movie.response != "True" {
+        // Which is also how it says the key is wrong.
+        return match movie.error {
+            Some(error) if error.to_lowercase().contains("not found") => Ok(None),
+            error => Err(CallError::Permanent(format!("said {:?}", error.unwrap_or_default()))),
+        };
+    }
+    let known = |value: Option<String>| value.filter(|value| value != "N/A");
+    Ok(Some(Enrichment {
+        // Like "117 min".
+        runtime_minutes: known(movie.runtime).and_then(|runtime| runtime.trim_end_matches("min").trim().parse().ok()),
+        genres: known(movie.genre).map_or_else(Vec::new, |genre| genres(genre.split(','))),
+        poster_url: known(movie.poster),
+    }))
+}
+
+#[derive(Deserialize)]
+struct TmdbSearch {
+    results: Vec<TmdbResult>,
+}
+
+#[derive(Deserialize)]
+struct TmdbResult {
+    id: u64,
+}
+
+#[derive(Deserialize)]
+struct TmdbMovie {
+    #[serde(default)]
+    runtime: Option<u16>,
+    #[serde(default)]
+    genres: Vec<TmdbGenre>,
+    #[serde(default)]
+    poster_path: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TmdbGenre {
+    name: String,
+}
+
+// TMDB's search doesn't say much about each movie, so the best match is then looked up on its own.
+async fn fetch_tmdb(config: &EnrichConfig, name: &str, year: u16) -> Result<Option<Enrichment>, CallError> {
+    let query_string = query(&[("api_key", &config.api_key), ("query", name), ("year", &year.to_string())])?;
+    let search: TmdbSearch = get_json(&format!("{}/3/search/movie?{}", config.url, query_string)).await?;
+    let Some(best) = search.results.first() else { return Ok(None) };
+    let query = query(&[("api_key", &config.api_key)])?;
+    let movie: TmdbMovie = get_json(&format!("{}/3/movie/{}?{}", config.url, best.id, query)).await?;
+    Ok(Some(Enrichment {
+        // 0 when TMDB doesn't know.
+        runtime_minutes: movie.runtime.filter(|&runtime| runtime > 0),
+        genres: genres(movie.genres.iter().map(|genre| genre.name.as_str())),
+        poster_url: movie.poster_path.map(|path| format!("{}{}", TMDB_IMAGE_URL, path)),
+    }))
+}
@@ -0,0 +1,247 @@
+use std::time::Duration;
+use axum::{extract::{rejection::{JsonRejection, PathRejection, QueryRejection}, FromRequest, FromRequestParts}, http::{header, HeaderValue, StatusCode}, response::{IntoResponse, Response}, Json};
+use tracing::error;
+use serde_json::{json, Value};
+
+use crate::body_limit;
+use crate::model::Movie;
+use crate::quota::QuotaExceeded;
+use crate::request_id;
+use crate::store::StoreError;
+use crate::validation::FieldError;
+
+// Every error a route can return. They all go out as
+// { "error": { "code": "...", "message": "...", "details": ..., "request_id": "..." } }
+// so clients only ever have to handle one shape. The request id matches the x-request-id header and the server logs.
+#[derive(Debug)]
+pub enum ApiError {
+    // The request is well-formed but doesn't make sense, e.g. the path and body disagree about the id.
+    BadRequest(String),
+    NotFound(String),
+    // The route doesn't take the request's method. Carries the methods it does take, also sent as Allow.
+    MethodNotAllowed(String, Vec<String>),
+    // The request needs a valid API key or bearer token and didn't come with one.
+    Unauthorized(String),
+    // The caller is known, but their role doesn't allow this.
+    Forbidden(String),
+    // Handle attempts to submit a movie with the same ID as another movie already in our database. The body includes
+    // the movie that's already there so the client can decide what to do about it.
+    AlreadyExists(Box<Movie>),
+    // With --duplicates=reject, the movie has a different id from one that's already there but the same name and year,
+    // see duplicates.rs. The body includes that movie.
+    Duplicate(Box<Movie>),
+    // The request clashes with something other than a movie that's already there, e.g. a genre being created again.
+    Conflict(String),
+    // An If-Match precondition didn't hold, the movie has changed since the client last saw it. The body includes the
+    // movie as it is now.
+    PreconditionFailed(Box<Movie>),
+    // An Idempotency-Key was sent again with a different request than the one it was first used for.
+    IdempotencyKeyReused(String),
+    // An Idempotency-Key was sent again while the first request with it is still running.
+    RequestInProgress(String),
+    // The client has used up its rate limit. Carries how long until it may try again, sent as Retry-After.
+    RateLimited(Duration),
+    // The body is bigger than the route takes. Carries the route's limit in bytes, see body_limit.rs.
+    PayloadTooLarge(usize),
+    // The body couldn't be parsed into what the route expects. Carries the status axum picked for the rejection.
+    InvalidBody(StatusCode, String),
+    InvalidQuery(String),
+    InvalidPath(String),
+    // The body parsed fine, but some fields have values we won't accept. One entry per problem.
+    Validation(Vec<FieldError>),
+    // Something went wrong on our end. The message is logged, but clients only get a generic one.
+    Internal(String),
+    // Storing what the request adds would go past a quota. The body says which one, how full it is and what its limit
+    // is.
+    QuotaExceeded(QuotaExceeded),
+    // What the request points at is no longer kept, e.g. a change feed cursor whose changes have been dropped, and the
+    // client has to start over.
+    Gone(String),
+    // We can't serve requests right now, e.g. the storage backend is failing its readiness check.
+    Unavailable(String),
+    // The request took longer than the configured timeout and was given up on.
+    Timeout(Duration),
+}
+
+impl ApiError {
+    fn status(&self) -> StatusCode {
+        match self {
+            ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::MethodNotAllowed(..) => StatusCode::METHOD_NOT_ALLOWED,
+            ApiError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden(_) => StatusCode::FORBIDDEN,
+            ApiError::AlreadyExists(_) => StatusCode::CONFLICT,
+            ApiError::Duplicate(_) => StatusCode::CONFLICT,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::PreconditionFailed(_) => StatusCode::PRECONDITION_FAILED,
+            ApiError::IdempotencyKeyReused(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::RequestInProgress(_) => StatusCode::CONFLICT,
+            ApiError::RateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
+            ApiError::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            ApiError::InvalidBody(status, _) => *status,
+            ApiError::InvalidQuery(_) => StatusCode::BAD_REQUEST,
+            ApiError::InvalidPath(_) => StatusCode::BAD_REQUEST,
+            ApiError::Validation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            ApiError::QuotaExceeded(_) => StatusCode::INSUFFICIENT_STORAGE,
+            ApiError::Gone(_) => StatusCode::GONE,
+            ApiError::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::Timeout(_) => StatusCode::GATEWAY_TIMEOUT,
+        }
+    }
+
+    pub(crate) fn code(&self) -> &'static str {
+        match self {
+            ApiError::BadRequest(_) => "bad_request",
+            ApiError::NotFound(_) => "not_found",
+            ApiError::MethodNotAllowed(..) => "method_not_allowed",
+            ApiError::Unauthorized(_) => "unauthorized",
+            ApiError::Forbidden(_) => "forbidden",
+            ApiError::AlreadyExists(_) => "already_exists",
+            ApiError::Duplicate(_) => "duplicate",
+            ApiError::Conflict(_) => "conflict",
+            ApiError::PreconditionFailed(_) => "precondition_failed",
+            ApiError::IdempotencyKeyReused(_) => "idempotency_key_reused",
+            ApiError::RequestInProgress(_) => "request_in_progress",
+            ApiError::RateLimited(_) => "rate_limited",
+            ApiError::PayloadTooLarge(_) => "payload_too_large",
+            ApiError::InvalidBody(..) => "invalid_body",
+            ApiError::InvalidQuery(_) => "invalid_query",
+            ApiError::InvalidPath(_) => "invalid_path",
+            ApiError::Validation(_) => "validation_failed",
+            ApiError::Internal(_) => "internal",
+            ApiError::QuotaExceeded(_) => "quota_exceeded",
+            ApiError::Gone(_) => "gone",
+            ApiError::Unavailable(_) => "unavailable",
+            ApiError::Timeout(_) => "timeout",
+        }
+    }
+
+    pub(crate) fn message(&self) -> String {
+        match self {
+            ApiError::BadRequest(message)
+            | ApiError::NotFound(message)
+            | ApiError::MethodNotAllowed(message, _)
+            | ApiError::Unauthorized(message)
+            | ApiError::Forbidden(message)
+            | ApiError::Conflict(message)
+            | ApiError::InvalidBody(_, message)
+            | ApiError::InvalidQuery(message)
+            | ApiError::InvalidPath(message)
+            | ApiError::Gone(message)
+            | ApiError::Unavailable(message) => message.clone(),
+            ApiError::AlreadyExists(existing) => format!("A movie with id {:?} already exists", existing.id),
+            ApiError::Duplicate(existing) => format!("Movie {:?} already has this name and year, send allow_duplicate=true to add it anyway", existing.id),
+            ApiError::PreconditionFailed(current) => format!("Movie {:?} has changed since it was read", current.id),
+            ApiError::IdempotencyKeyReused(key) => format!("Idempotency-Key {:?} was already used for a different request", key),
+            ApiError::RequestInProgress(key) => format!("A request with Idempotency-Key {:?} is still in progress", key),
+            ApiError::RateLimited(retry_after) => format!("Too many requests, try again in {}s", retry_after_secs(*retry_after)),
+            ApiError::PayloadTooLarge(limit) => format!("The body is bigger than the {} bytes this route takes", limit),
+            ApiError::Validation(_) => "Some fields are invalid".to_string(),
+            ApiError::Internal(_) => "Internal server error".to_string(),
+            ApiError::QuotaExceeded(exceeded) => exceeded.message(),
+            ApiError::Timeout(timeout) => format!("The request didn't finish within {:?}", timeout),
+        }
+    }
+
+    pub(crate) fn details(&self) -> Value {
+        match self {
+            ApiError::AlreadyExists(existing) | ApiError::Duplicate(existing) => json!({ "existing": existing }),
+            ApiError::PreconditionFailed(current) => json!({ "current": current }),
+            ApiError::MethodNotAllowed(_, allowed) => json!({ "allowed": allowed }),
+            ApiError::PayloadTooLarge(limit) => json!({ "limit": limit }),
+            ApiError::Validation(errors) => json!({ "fields": errors }),
+            ApiError::QuotaExceeded(exceeded) => json!(exceeded),
+            _ => Value::Null,
+        }
+    }
+
+    // The code, message and details without the request id, for errors about one part of a request, like an item in
+    // POST /movies/batch.
+    pub(crate) fn body(&self) -> Value {
+        json!({ "code": self.code(), "message": self.message(), "details": self.details() })
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        if let ApiError::Internal(message) = &self {
+            error!("Internal error: {}", message);
+        }
+        let mut error = self.body();
+        error["request_id"] = json!(request_id::current());
+        let mut response = (self.status(), Json(json!({ "error": error }))).into_response();
+        if let ApiError::RateLimited(retry_after) = self {
+            response.headers_mut().insert(header::RETRY_AFTER, HeaderValue::from(retry_after_secs(retry_after)));
+        }
+        response
+    }
+}
+
+// Retry-After only has whole seconds, and saying 0 would have clients retry straight away.
+fn retry_after_secs(retry_after: Duration) -> u64 {
+    retry_after.as_secs_f64().ceil().max(1.0) as u64
+}
+
+impl From<StoreError> for ApiError {
+    fn from(error: StoreError) -> ApiError {
+        match error {
+            StoreError::NotFound => ApiError::NotFound("No such movie".to_string()),
+            StoreError::AlreadyExists(existing) => ApiError::AlreadyExists(existing),
+            StoreError::PreconditionFailed(current) => ApiError::PreconditionFailed(current),
+            StoreError::UserAlreadyExists(existing) => ApiError::Conflict(format!("User {:?} is already registered", existing.id)),
+            StoreError::UserNotFound => ApiError::NotFound("No such user".to_string()),
+            StoreError::SequelCycle => ApiError::Conflict("That would make a movie a sequel of itself".to_string()),
+            StoreError::QuotaExceeded(exceeded) => ApiError::QuotaExceeded(exceeded),
+            StoreError::Backend(message) => ApiError::Internal(format!("Storage backend failed: {}", message)),
+        }
+    }
+}
+
+impl From<Vec<FieldError>> for ApiError {
+    fn from(errors: Vec<FieldError>) -> ApiError {
+        ApiError::Validation(errors)
+    }
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> ApiError {
+        match body_limit::exceeded(&rejection) {
+            Some(limit) => ApiError::PayloadTooLarge(limit),
+            None => ApiError::InvalidBody(rejection.status(), rejection.body_text()),
+        }
+    }
+}
+
+impl From<QueryRejection> for ApiError {
+    fn from(rejection: QueryRejection) -> ApiError {
+        ApiError::InvalidQuery(rejection.body_text())
+    }
+}
+
+impl From<PathRejection> for ApiError {
+    fn from(rejection: PathRejection) -> ApiError {
+        ApiError::InvalidPath(rejection.body_text())
+    }
+}
+
+impl From<serde_json::Error> for ApiError {
+    fn from(error: serde_json::Error) -> ApiError {
+        ApiError::Internal(format!("Failed to serialize response: {}", error))
+    }
+}
+
+// Drop-in replacements for axum's extractors that reject with an ApiError instead of a plain-text body.
+
+#[derive(FromRequest)]
+#[from_request(via(axum::Json), rejection(ApiError))]
+pub struct ApiJson<T>(pub T);
+
+#[derive(FromRequestParts)]
+#[from_request(via(axum::extract::Query), rejection(ApiError))]
+pub struct ApiQuery<T>(pub T);
+
+#[derive(FromRequestParts)]
+#[from_request(via(axum::extract::Path), rejection(ApiError))]
+pub struct ApiPath<T>(pub T);
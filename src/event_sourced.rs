@@ -0,0 +1,494 @@
+use std::{collections::{BTreeMap, BTreeSet, HashMap}, io, path::{Path, PathBuf}, sync::RwLock as SyncRwLock, time::Instant};
+use tracing::{info, info_span, Instrument};
+use serde::{Deserialize, Serialize};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use tokio::{fs::{File, OpenOptions}, io::AsyncWriteExt, sync::{broadcast, Mutex, MutexGuard}};
+
+use crate::metrics::{self, Lock};
+use crate::changes::{self, ChangeLog, ChangePage, CursorExpired, Since};
+use crate::events::MovieEvent;
+use crate::labels::Label;
+use crate::model::{self, Link, Movie, MoviePatch, Revision, StoredMovie, StoredTrashed, StoredUser, Trashed, User, UserChange};
+use crate::schema::{self, Migration, Schema};
+use crate::store::{check_precondition, movie_changes, Backup, MemoryMovieStore, MovieFilter, MovieStore, Precondition, StoreError, StoreFuture};
+
+// Keeps every change ever made as an event in an append-only log, and the current movies, users and links are what
+// folding over those events comes to. Unlike the write-ahead log the events are never compacted away, which is what
+// lets GET /movie/{id}/history list every revision of a movie and ?as_of= show one as it was at any time. The price is
+// a log that only grows, and a startup that replays all of it.
+// Like the write-ahead log, changes are logged whole rather than as the patch or rating that made them, so folding
+// never depends on how patches are applied, which may change between versions while the log stays as it was.
+
+// Version 1 logs are from before logs were stamped with their version, and version 2 stamps them. See schema.rs.
+pub static SCHEMA: Schema = Schema {
+    name: "Event log",
+    first: 1,
+    migrations: &[Migration { to: 2, description: "logs say their schema version", apply: schema::unchanged }],
+};
+
+// One line of the log. Nothing in it is ever rewritten.
+#[derive(Debug, Serialize, Deserialize)]
+struct Recorded {
+    // 1 for the first event, and one more for each after it.
+    seq: u64,
+    // RFC 3339, in UTC.
+    at: String,
+    #[serde(flatten)]
+    event: Event,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum Event {
+    MovieCreated { movie: StoredMovie },
+    MovieUpdated { movie: StoredMovie },
+    // Moves the movie to the trash, and takes it off every watchlist and favorites and unlinks it, without an event for
+    // each of them.
+    MovieDeleted { id: String },
+    MovieRestored { id: String },
+    // Deletes the movies in the trash for good. Their revisions are kept.
+    MoviesPurged { ids: Vec<String> },
+    // Users are recorded whole after every change, like movies.
+    UserChanged { user: StoredUser },
+    MoviesLinked { link: Link },
+    MoviesUnlinked { link: Link },
+    // Everything was replaced with a backup. Each movie it changed gets a revision, as if it had been changed on its
+    // own, so history and ?as_of= carry on across it.
+    StoreReplaced {
+        movies: Vec<StoredMovie>,
+        users: Vec<StoredUser>,
+        links: Vec<Link>,
+        trash: Vec<StoredTrashed>,
+    },
+}
+
+// What folding over the log comes to.
+#[derive(Debug, Default)]
+struct Folded {
+    movies: BTreeMap<String, Movie>,
+    users: BTreeMap<String, User>,
+    links: BTreeSet<Link>,
+    trash: BTreeMap<String, Trashed>,
+    revisions: HashMap<String, Vec<Revision>>,
+    last_seq: u64,
+}
+
+impl Folded {
+    fn apply(&mut self, recorded: Recorded) {
+        self.last_seq = recorded.seq;
+        let revision = |event: &'static str, movie: &Movie| Revision { seq: recorded.seq, event, at: recorded.at.clone(), movie: movie.clone() };
+        match recorded.event {
+            Event::MovieCreated { movie } | Event::MovieUpdated { movie } => {
+                let movie = Movie::from(movie);
+                let event = if self.movies.contains_key(&movie.id) { "updated" } else { "created" };
+                self.revisions.entry(movie.id.clone()).or_default().push(revision(event, &movie));
+                self.movies.insert(movie.id.clone(), movie);
+            },
+            Event::MovieDeleted { id } => {
+                if let Some(movie) = self.movies.remove(&id) {
+                    self.revisions.entry(id.clone()).or_default().push(revision("deleted", &movie));
+                    self.trash.insert(id.clone(), Trashed { deleted_at: recorded.at.clone(), movie });
+                }
+                for user in self.users.values_mut() {
+                    user.forget(&id);
+                }
+                self.links.retain(|link| !link.touches(&id));
+            },
+            Event::MovieRestored { id } => {
+                if let Some(trashed) = self.trash.remove(&id) {
+                    self.revisions.entry(id.clone()).or_default().push(revision("restored", &trashed.movie));
+                    self.movies.insert(id, trashed.movie);
+                }
+            },
+            Event::MoviesPurged { ids } => {
+                for id in ids {
+                    self.trash.remove(&id);
+                }
+            },
+            Event::UserChanged { user } => {
+                self.users.insert(user.user.id.clone(), user.into());
+            },
+            Event::MoviesLinked { link } => {
+                self.links.insert(link);
+            },
+            Event::MoviesUnlinked { link } => {
+                self.links.remove(&link);
+            },
+            Event::StoreReplaced { movies, users, links, trash } => {
+                let movies = movies.into_iter().map(|movie| (movie.movie.id.clone(), Movie::from(movie))).collect();
+                for change in movie_changes(&self.movies, &movies) {
+                    self.revisions.entry(change.movie().id.clone()).or_default().push(revision(change.name(), change.movie()));
+                }
+                self.movies = movies;
+                self.users = users.into_iter().map(|user| (user.user.id.clone(), User::from(user))).collect();
+                self.links = links.into_iter().collect();
+                self.trash = trash.into_iter().map(|trashed| (trashed.movie.movie.id.clone(), Trashed::from(trashed))).collect();
+            },
+        }
+    }
+}
+
+// The movie as it was at `at`, from its revisions: None if it didn't exist yet, or had been deleted.
+pub fn movie_as_of(revisions: &[Revision], at: OffsetDateTime) -> Option<&Movie> {
+    let last = revisions.iter().rev().find(|revision| OffsetDateTime::parse(&revision.at, &Rfc3339).is_ok_and(|changed| changed <= at))?;
+    (last.event != "deleted").then_some(&last.movie)
+}
+
+struct LogFile {
+    file: File,
+    size: u64,
+    last_seq: u64,
+}
+
+// Serves reads from memory like MemoryMovieStore. Every change is appended to the log before it's applied, with the
+// log locked, so the log's order is the order changes happened in.
+pub struct EventSourcedMovieStore {
+    inner: MemoryMovieStore,
+    path: PathBuf,
+    log: Mutex<LogFile>,
+    // Every revision of every movie there has ever been, by id. Only changed with the log locked.
+    revisions: SyncRwLock<HashMap<String, Vec<Revision>>>,
+}
+
+impl EventSourcedMovieStore {
+    pub async fn open(path: PathBuf) -> io::Result<EventSourcedMovieStore> {
+        let (folded, mut size, version) = replay_log(&path).await?;
+        info!("Folded {} events into {} movies and {} users from event log {}", folded.last_seq, folded.movies.len(), folded.users.len(), path.display());
+        let mut file = OpenOptions::new().create(true).append(true).open(&path).await?;
+        // Get rid of a torn last event, if there was one, before appending after it.
+        file.set_len(size).await?;
+        // The events before stay as they were written, and are migrated every time they're folded. Only what's
+        // appended from now on is in the current version, which the log says first.
+        if version < SCHEMA.current() {
+            let stamp = SCHEMA.stamp();
+            file.write_all(&stamp).await?;
+            file.sync_data().await?;
+            size += stamp.len() as u64;
+        }
+        Ok(EventSourcedMovieStore {
+            inner: MemoryMovieStore::from_tables(
+                folded.movies.into_values().collect(),
+                folded.users.into_values().collect(),
+                folded.links.into_iter().collect(),
+                folded.trash.into_values().collect(),
+            ).with_change_log(ChangeLog::open(&changes::path_beside(&path))?),
+            path,
+            log: Mutex::new(LogFile { file, size, last_seq: folded.last_seq }),
+            revisions: SyncRwLock::new(folded.revisions),
+        })
+    }
+
+    async fn lock_log(&self) -> MutexGuard<'_, LogFile> {
+        let started = Instant::now();
+        let log = self.log.lock().await;
+        metrics::record_lock_wait(Lock::EventLog, started.elapsed());
+        log
+    }
+
+    // Returns the seq and time it was recorded with.
+    async fn append(&self, log: &mut LogFile, event: Event) -> Result<(u64, String), StoreError> {
+        let recorded = Recorded {
+            seq: log.last_seq + 1,
+            at: OffsetDateTime::now_utc().format(&Rfc3339).map_err(backend_error)?,
+            event,
+        };
+        let mut line = serde_json::to_vec(&recorded).map_err(backend_error)?;
+        line.push(b'\n');
+        // Only acknowledge the change once it is actually on disk.
+        let written = async {
+            log.file.write_all(&line).await?;
+            log.file.sync_data().await
+        }.instrument(info_span!("event_log.append", bytes = line.len())).await;
+        if let Err(e) = written {
+            // Cut off whatever part of the line made it out, or the next event would get glued onto it.
+            let _ = log.file.set_len(log.size).await;
+            return Err(backend_error(e));
+        }
+        log.size += line.len() as u64;
+        log.last_seq = recorded.seq;
+        Ok((recorded.seq, recorded.at))
+    }
+
+    // Called with the log locked, once the change is applied.
+    fn record_revision(&self, (seq, at): (u64, String), event: &'static str, movie: &Movie) {
+        let revision = Revision { seq, event, at, movie: movie.clone() };
+        self.revisions.write().unwrap().entry(movie.id.clone()).or_default().push(revision);
+    }
+}
+
+fn backend_error(e: impl std::fmt::Display) -> StoreError {
+    StoreError::Backend(format!("event log: {}", e))
+}
+
+// Returns what the log folds into, how many bytes of it are intact, and the schema version it was last stamped with.
+async fn replay_log(path: &Path) -> io::Result<(Folded, u64, u32)> {
+    let contents = match tokio::fs::read(path).await {
+        Ok(contents) => contents,
+        // No log yet, first run.
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok((Folded::default(), 0, SCHEMA.first)),
+        Err(e) => return Err(e),
+    };
+    let mut folded = Folded::default();
+    let (valid_len, version) = schema::read_log(&SCHEMA, path, &contents, |recorded: Recorded| folded.apply(recorded))?;
+    Ok((folded, valid_len, version))
+}
+
+impl MovieStore for EventSourcedMovieStore {
+    fn get<'a>(&'a self, id: &'a str) -> StoreFuture<'a, Option<Movie>> {
+        self.inner.get(id)
+    }
+
+    fn insert(&self, movie: Movie) -> StoreFuture<'_, Result<(), StoreError>> {
+        Box::pin(async move {
+            let mut log = self.lock_log().await;
+            if let Some(existing) = self.inner.get(&movie.id).await {
+                return Err(StoreError::AlreadyExists(Box::new(existing)));
+            }
+            let recorded = self.append(&mut log, Event::MovieCreated { movie: movie.clone().into() }).await?;
+            self.record_revision(recorded, "created", &movie);
+            self.inner.insert(movie).await
+        })
+    }
+
+    // Folding takes recorded movies as they are, so they're recorded with the version inner is about to give them.
+    fn upsert(&self, mut movie: Movie) -> StoreFuture<'_, Result<bool, StoreError>> {
+        Box::pin(async move {
+            let mut log = self.lock_log().await;
+            let existing = self.inner.get(&movie.id).await;
+            movie.succeed(existing.as_ref());
+            let (event, name) = match existing {
+                Some(_) => (Event::MovieUpdated { movie: movie.clone().into() }, "updated"),
+                None => (Event::MovieCreated { movie: movie.clone().into() }, "created"),
+            };
+            let recorded = self.append(&mut log, event).await?;
+            self.record_revision(recorded, name, &movie);
+            self.inner.upsert(movie).await
+        })
+    }
+
+    fn put(&self, movie: Movie) -> StoreFuture<'_, Result<bool, StoreError>> {
+        Box::pin(async move {
+            let mut log = self.lock_log().await;
+            let (event, name) = match self.inner.get(&movie.id).await {
+                Some(_) => (Event::MovieUpdated { movie: movie.clone().into() }, "updated"),
+                None => (Event::MovieCreated { movie: movie.clone().into() }, "created"),
+            };
+            let recorded = self.append(&mut log, event).await?;
+            self.record_revision(recorded, name, &movie);
+            self.inner.put(movie).await
+        })
+    }
+
+    // Every write holds the log lock, so checking the precondition under it is as good as checking it in inner.
+    fn update_if<'a>(&'a self, mut movie: Movie, precondition: Precondition<'a>) -> StoreFuture<'a, Result<(), StoreError>> {
+        Box::pin(async move {
+            let mut log = self.lock_log().await;
+            let existing = self.inner.get(&movie.id).await.ok_or(StoreError::NotFound)?;
+            check_precondition(precondition, &existing)?;
+            movie.succeed(Some(&existing));
+            let recorded = self.append(&mut log, Event::MovieUpdated { movie: movie.clone().into() }).await?;
+            self.record_revision(recorded, "updated", &movie);
+            self.inner.update(movie).await
+        })
+    }
+
+    fn patch<'a>(&'a self, id: &'a str, mut patch: MoviePatch) -> StoreFuture<'a, Result<Movie, StoreError>> {
+        Box::pin(async move {
+            let mut log = self.lock_log().await;
+            let mut movie = self.inner.get(id).await.ok_or(StoreError::NotFound)?;
+            if !patch.expects(&movie) {
+                return Err(StoreError::PreconditionFailed(Box::new(movie)));
+            }
+            // Applied twice, so stamped once.
+            patch.updated_at.get_or_insert_with(model::timestamp);
+            patch.clone().apply(&mut movie);
+            let recorded = self.append(&mut log, Event::MovieUpdated { movie: movie.into() }).await?;
+            // Nothing else can have written in between, so this comes out the same as what was recorded.
+            let movie = self.inner.patch(id, patch).await?;
+            self.record_revision(recorded, "updated", &movie);
+            Ok(movie)
+        })
+    }
+
+    fn delete_if<'a>(&'a self, id: &'a str, precondition: Precondition<'a>) -> StoreFuture<'a, Result<Movie, StoreError>> {
+        Box::pin(async move {
+            let mut log = self.lock_log().await;
+            let existing = self.inner.get(id).await.ok_or(StoreError::NotFound)?;
+            check_precondition(precondition, &existing)?;
+            let recorded = self.append(&mut log, Event::MovieDeleted { id: id.to_string() }).await?;
+            // In the trash as of when it was recorded, as folding will have it.
+            let movie = self.inner.delete_at(id, None, recorded.1.clone()).await?;
+            self.record_revision(recorded, "deleted", &movie);
+            Ok(movie)
+        })
+    }
+
+    fn trash(&self) -> StoreFuture<'_, Vec<Trashed>> {
+        self.inner.trash()
+    }
+
+    fn restore<'a>(&'a self, id: &'a str) -> StoreFuture<'a, Result<Movie, StoreError>> {
+        Box::pin(async move {
+            let mut log = self.lock_log().await;
+            if !self.inner.is_trashed(id) {
+                return Err(StoreError::NotFound);
+            }
+            if let Some(existing) = self.inner.get(id).await {
+                return Err(StoreError::AlreadyExists(Box::new(existing)));
+            }
+            let recorded = self.append(&mut log, Event::MovieRestored { id: id.to_string() }).await?;
+            let movie = self.inner.restore(id).await?;
+            self.record_revision(recorded, "restored", &movie);
+            Ok(movie)
+        })
+    }
+
+    fn purge(&self, deleted_before: OffsetDateTime) -> StoreFuture<'_, Result<Vec<String>, StoreError>> {
+        Box::pin(async move {
+            let mut log = self.lock_log().await;
+            let ids = self.inner.trashed_before(deleted_before);
+            if ids.is_empty() {
+                return Ok(ids);
+            }
+            self.append(&mut log, Event::MoviesPurged { ids }).await?;
+            self.inner.purge(deleted_before).await
+        })
+    }
+
+    fn backup(&self) -> StoreFuture<'_, Backup> {
+        self.inner.backup()
+    }
+
+    fn replace(&self, backup: Backup) -> StoreFuture<'_, Result<(), StoreError>> {
+        Box::pin(async move {
+            let mut log = self.lock_log().await;
+            let old = self.inner.backup().await.movies.into_iter().map(|movie| (movie.id.clone(), movie)).collect();
+            let new = backup.movies.iter().map(|movie| (movie.id.clone(), movie.clone())).collect();
+            let event = Event::StoreReplaced {
+                movies: backup.movies.iter().cloned().map(StoredMovie::from).collect(),
+                users: backup.users.iter().cloned().map(StoredUser::from).collect(),
+                links: backup.links.clone(),
+                trash: backup.trash.iter().cloned().map(StoredTrashed::from).collect(),
+            };
+            let recorded = self.append(&mut log, event).await?;
+            for change in movie_changes(&old, &new) {
+                self.record_revision(recorded.clone(), change.name(), change.movie());
+            }
+            self.inner.replace(backup).await
+        })
+    }
+
+    fn list<'a>(&'a self, filter: &'a MovieFilter) -> StoreFuture<'a, Vec<Movie>> {
+        self.inner.list(filter)
+    }
+
+    fn scan<'a>(&'a self, after: Option<&'a str>, limit: usize) -> StoreFuture<'a, Vec<Movie>> {
+        self.inner.scan(after, limit)
+    }
+
+    fn suggest<'a>(&'a self, prefix: &'a str, limit: usize) -> StoreFuture<'a, Vec<String>> {
+        self.inner.suggest(prefix, limit)
+    }
+
+    fn genres(&self) -> StoreFuture<'_, Vec<Label>> {
+        self.inner.genres()
+    }
+
+    fn tags(&self) -> StoreFuture<'_, Vec<Label>> {
+        self.inner.tags()
+    }
+
+    fn genre_movie_ids<'a>(&'a self, genre: &'a str) -> StoreFuture<'a, Vec<String>> {
+        self.inner.genre_movie_ids(genre)
+    }
+
+    fn count(&self) -> StoreFuture<'_, usize> {
+        self.inner.count()
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<MovieEvent> {
+        self.inner.subscribe()
+    }
+
+    fn changes(&self, since: &Since, limit: usize) -> Result<ChangePage, CursorExpired> {
+        self.inner.changes(since, limit)
+    }
+
+    fn history<'a>(&'a self, id: &'a str) -> StoreFuture<'a, Option<Vec<Revision>>> {
+        Box::pin(async move {
+            Some(self.revisions.read().unwrap().get(id).cloned().unwrap_or_default())
+        })
+    }
+
+    fn get_user<'a>(&'a self, id: &'a str) -> StoreFuture<'a, Option<User>> {
+        self.inner.get_user(id)
+    }
+
+    fn insert_user(&self, user: User) -> StoreFuture<'_, Result<(), StoreError>> {
+        Box::pin(async move {
+            let mut log = self.lock_log().await;
+            if let Some(existing) = self.inner.get_user(&user.id).await {
+                return Err(StoreError::UserAlreadyExists(Box::new(existing)));
+            }
+            self.append(&mut log, Event::UserChanged { user: user.clone().into() }).await?;
+            self.inner.insert_user(user).await
+        })
+    }
+
+    fn change_user<'a>(&'a self, user_id: &'a str, change: UserChange) -> StoreFuture<'a, Result<User, StoreError>> {
+        Box::pin(async move {
+            let mut log = self.lock_log().await;
+            let mut user = self.inner.get_user(user_id).await.ok_or(StoreError::UserNotFound)?;
+            if let Some(movie_id) = change.added_movie() {
+                self.inner.get(movie_id).await.ok_or(StoreError::NotFound)?;
+            }
+            user.apply(change.clone());
+            self.append(&mut log, Event::UserChanged { user: user.into() }).await?;
+            self.inner.change_user(user_id, change).await
+        })
+    }
+
+    fn links<'a>(&'a self, movie_id: &'a str) -> StoreFuture<'a, Vec<Link>> {
+        self.inner.links(movie_id)
+    }
+
+    // Links already there aren't recorded again.
+    fn link(&self, link: Link) -> StoreFuture<'_, Result<bool, StoreError>> {
+        Box::pin(async move {
+            let mut log = self.lock_log().await;
+            if !self.inner.check_link(&link).await? {
+                return Ok(false);
+            }
+            self.append(&mut log, Event::MoviesLinked { link: link.clone() }).await?;
+            self.inner.link(link).await
+        })
+    }
+
+    fn unlink<'a>(&'a self, link: &'a Link) -> StoreFuture<'a, Result<bool, StoreError>> {
+        Box::pin(async move {
+            let mut log = self.lock_log().await;
+            if !self.inner.links(&link.from).await.contains(link) {
+                return Ok(false);
+            }
+            self.append(&mut log, Event::MoviesUnlinked { link: link.clone() }).await?;
+            self.inner.unlink(link).await
+        })
+    }
+
+    // Every event is synced as it's appended, so only the change log is left.
+    fn close(&self) -> StoreFuture<'_, Result<(), StoreError>> {
+        Box::pin(async move {
+            let _log = self.lock_log().await;
+            self.inner.close_change_log()
+        })
+    }
+
+    // Catches the log having been deleted or made read-only underneath us, which would make the next write fail.
+    fn check_ready(&self) -> StoreFuture<'_, Result<(), StoreError>> {
+        Box::pin(async move {
+            let _log = self.lock_log().await;
+            OpenOptions::new().append(true).open(&self.path).await.map_err(backend_error)?;
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,182 @@
+use std::convert::Infallible;
+use axum::response::sse::Event;
+use futures_util::{stream, Stream};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::{io::{AsyncRead, AsyncWrite}, sync::{broadcast::{self, error::RecvError}, mpsc}};
+use tracing::{debug, warn};
+
+use crate::model::Movie;
+use crate::shutdown;
+use crate::store::MovieFilter;
+use crate::websocket::{self, Message, MessageReader, WebSocketError};
+
+// How many changes a subscriber can fall behind by before it starts missing some.
+pub const EVENT_BUFFER: usize = 1024;
+
+// Subscription messages are a small JSON object, anything this big is a mistake.
+const MAX_CLIENT_MESSAGE: usize = 64 * 1024;
+
+// A change to the store, published by MemoryMovieStore once it has been applied. Every other store keeps its movies
+// in one, so this covers them all.
+#[derive(Debug, Clone)]
+pub enum MovieEvent {
+    Created(Movie),
+    Updated(Movie),
+    // Carries the movie as it was before it was removed.
+    Deleted(Movie),
+}
+
+impl MovieEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            MovieEvent::Created(_) => "created",
+            MovieEvent::Updated(_) => "updated",
+            MovieEvent::Deleted(_) => "deleted",
+        }
+    }
+
+    pub fn movie(&self) -> &Movie {
+        match self {
+            MovieEvent::Created(movie) | MovieEvent::Updated(movie) | MovieEvent::Deleted(movie) => movie,
+        }
+    }
+}
+
+// A change as sent to other systems, by webhooks.rs and publish.rs. The id is unique to each message, so receivers
+// can tell a redelivery from a second change.
+#[derive(Debug, Serialize)]
+pub struct ChangeMessage<'a> {
+    pub id: &'a str,
+    pub event: &'static str,
+    // RFC 3339, in UTC.
+    pub occurred_at: &'a str,
+    pub movie: &'a Movie,
+}
+
+// Turns a subscription into Server-Sent Events: one event named after the change with the movie as its data. A
+// subscriber that falls too far behind gets a "lagged" event saying how many changes it missed, so it knows to reload.
+// The stream ends when the server starts shutting down.
+pub fn sse_stream(receiver: broadcast::Receiver<MovieEvent>) -> impl Stream<Item = Result<Event, Infallible>> {
+    stream::unfold(receiver, |mut receiver| async move {
+        let received = tokio::select! {
+            received = receiver.recv() => received,
+            _ = shutdown::draining() => return None,
+        };
+        let event = match received {
+            Ok(change) => Event::default().event(change.name()).data(serde_json::to_string(change.movie()).ok()?),
+            Err(RecvError::Lagged(missed)) => {
+                warn!("Event subscriber fell behind and missed {} changes", missed);
+                Event::default().event("lagged").data(json!({ "missed": missed }).to_string())
+            },
+            Err(RecvError::Closed) => return None,
+        };
+        Some((Ok(event), receiver))
+    })
+}
+
+// Which changes a WebSocket subscriber wants, from the query string of GET /movies/subscribe or a later
+// {"filter": {...}} message. Like MovieFilter, plus a year range. Changes are matched on the movie as it is after an
+// update, or as it was before a delete.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SubscriptionFilter {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub year: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_year: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_year: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub was_good: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name_contains: Option<String>,
+}
+
+impl SubscriptionFilter {
+    pub fn matches(&self, movie: &Movie) -> bool {
+        let filter = MovieFilter {
+            year: self.year,
+            year_gte: self.min_year,
+            year_lte: self.max_year,
+            was_good: self.was_good,
+            name_contains: self.name_contains.clone(),
+            ..MovieFilter::default()
+        };
+        filter.matches(movie)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ClientMessage {
+    filter: SubscriptionFilter,
+}
+
+// Runs one WebSocket subscription until either side closes it. Matching changes go out as text frames like
+// {"event": "created", "movie": {...}}, with the same "lagged" event as the SSE stream. The client changes its filter
+// by sending {"filter": {...}}, which is answered with {"subscribed": {...}}.
+pub async fn run_subscription<S>(socket: S, mut changes: broadcast::Receiver<MovieEvent>, mut filter: SubscriptionFilter)
+where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    let (reader, mut writer) = tokio::io::split(socket);
+    // Reading frames isn't cancel safe, so it happens on its own task and the loop below only waits on channels.
+    let (incoming_sender, mut incoming) = mpsc::channel(8);
+    let reader_task = tokio::spawn(async move {
+        let mut reader = MessageReader::new(reader, MAX_CLIENT_MESSAGE);
+        loop {
+            let message = reader.next().await;
+            let stop = matches!(message, Err(_) | Ok(Message::Close(_)));
+            if incoming_sender.send(message).await.is_err() || stop {
+                return;
+            }
+        }
+    });
+
+    let close = loop {
+        let reply = tokio::select! {
+            message = incoming.recv() => match message {
+                Some(Ok(Message::Text(text))) => match serde_json::from_str::<ClientMessage>(&text) {
+                    Ok(message) => {
+                        filter = message.filter;
+                        json!({ "subscribed": filter })
+                    },
+                    Err(e) => json!({ "error": { "code": "invalid_body", "message": format!("Expected {{\"filter\": {{...}}}}: {}", e) } }),
+                },
+                Some(Ok(Message::Binary(_))) => break Some((websocket::CLOSE_UNSUPPORTED_DATA, "Only text messages are supported".to_string())),
+                Some(Ok(Message::Ping(data))) => {
+                    if websocket::write_message(&mut writer, &Message::Pong(data)).await.is_err() {
+                        break None;
+                    }
+                    continue;
+                },
+                Some(Ok(Message::Pong(_))) => continue,
+                Some(Ok(Message::Close(_))) => break Some((websocket::CLOSE_NORMAL, String::new())),
+                Some(Err(WebSocketError::Protocol(code, reason))) => break Some((code, reason)),
+                Some(Err(WebSocketError::Io(e))) => {
+                    debug!("WebSocket subscriber went away: {}", e);
+                    break None;
+                },
+                None => break None,
+            },
+            change = changes.recv() => match change {
+                Ok(change) if filter.matches(change.movie()) => json!({ "event": change.name(), "movie": change.movie() }),
+                Ok(_) => continue,
+                Err(RecvError::Lagged(missed)) => {
+                    warn!("Event subscriber fell behind and missed {} changes", missed);
+                    json!({ "event": "lagged", "missed": missed })
+                },
+                Err(RecvError::Closed) => break Some((websocket::CLOSE_GOING_AWAY, String::new())),
+            },
+            _ = shutdown::draining() => break Some((websocket::CLOSE_GOING_AWAY, "Server is shutting down".to_string())),
+        };
+        if websocket::write_message(&mut writer, &Message::Text(reply.to_string())).await.is_err() {
+            break None;
+        }
+    };
+    if let Some(close) = close {
+        let _ = websocket::write_message(&mut writer, &Message::Close(Some(close))).await;
+    }
+    reader_task.abort();
+}
@@ -0,0 +1,80 @@
+use std::sync::LazyLock;
+use axum::{extract::Request, http::{header, HeaderValue, Method, StatusCode}, middleware::Next, response::{IntoResponse, Response}};
+
+use crate::error::ApiError;
+use crate::openapi;
+use crate::similar::levenshtein;
+
+// What the router sends for requests no route takes. Paths that don't exist and methods a route doesn't have get the
+// same JSON errors as everything else, with a pointer to what was probably meant, and OPTIONS on any route says which
+// methods it has in an Allow header. Browsers' CORS preflights are OPTIONS too, but those are answered in cors.rs.
+
+// Most typos a path may have for a route to still be suggested for it.
+const MAX_TYPOS: usize = 2;
+
+// Every documented route, and the movie routes again without their version, since they're served there too.
+static ROUTES: LazyLock<Vec<String>> = LazyLock::new(|| {
+    let mut routes: Vec<String> = openapi::document()["paths"].as_object().unwrap().keys().cloned().collect();
+    let unversioned: Vec<String> = routes.iter().filter_map(|route| route.strip_prefix("/v1")).map(str::to_string).collect();
+    routes.extend(unversioned);
+    routes
+});
+
+// The route `path` is most likely a typo of, with {id} and the like matching any segment.
+fn closest_route(path: &str) -> Option<&'static str> {
+    let segments: Vec<Vec<char>> = path.trim_end_matches('/').split('/').map(|segment| segment.chars().collect()).collect();
+    ROUTES.iter()
+        .filter(|route| route.as_str() != path)
+        .filter_map(|route| {
+            let route_segments: Vec<&str> = route.split('/').collect();
+            if route_segments.len() != segments.len() {
+                return None;
+            }
+            let typos: usize = route_segments.iter().zip(&segments)
+                .map(|(expected, segment)| if expected.starts_with('{') { 0 } else { levenshtein(&expected.chars().collect::<Vec<_>>(), segment) })
+                .sum();
+            (typos <= MAX_TYPOS).then_some((typos, route.as_str()))
+        })
+        .min_by_key(|(typos, _)| *typos)
+        .map(|(_, route)| route)
+}
+
+// The router's fallback, for paths no route has.
+pub async fn no_route(method: Method, request: Request) -> ApiError {
+    let path = request.uri().path();
+    let message = match closest_route(path) {
+        Some(route) => format!("There's no route {} {}, did you mean {}? Every route is listed at /api-docs/openapi.json", method, path, route),
+        None => format!("There's no route {} {}, every route is listed at /api-docs/openapi.json", method, path),
+    };
+    ApiError::NotFound(message)
+}
+
+// Middleware around the whole router. No route has an OPTIONS handler, so the router answers them with a 405 that
+// lists the route's methods in Allow, which only needs to become a 204 with OPTIONS added. Other 405s get the usual
+// error body in place of the router's empty one. Axum only adds Allow once the response has been through the router's
+// own layers, which is why this can't be one of them.
+pub async fn answer_methods(request: Request, next: Next) -> Response {
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    let response = next.run(request).await;
+    if response.status() != StatusCode::METHOD_NOT_ALLOWED {
+        return response;
+    }
+    let (mut parts, _) = response.into_parts();
+    let mut allowed: Vec<String> = parts.headers.get(header::ALLOW)
+        .and_then(|allow| allow.to_str().ok())
+        .map(|allow| allow.split(',').map(|method| method.trim().to_string()).filter(|method| !method.is_empty()).collect())
+        .unwrap_or_default();
+    allowed.push(Method::OPTIONS.to_string());
+    parts.headers.insert(header::ALLOW, HeaderValue::from_str(&allowed.join(",")).unwrap());
+    parts.headers.remove(header::CONTENT_LENGTH);
+    if method == Method::OPTIONS {
+        parts.status = StatusCode::NO_CONTENT;
+        return Response::from_parts(parts, Default::default());
+    }
+    let message = format!("{} {} isn't allowed, it takes {}", method, path, allowed.join(", "));
+    let (error_parts, body) = ApiError::MethodNotAllowed(message, allowed).into_response().into_parts();
+    parts.status = error_parts.status;
+    parts.headers.extend(error_parts.headers);
+    Response::from_parts(parts, body)
+}
@@ -0,0 +1,1037 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use serde::{ser::{SerializeMap, SerializeSeq}, Deserialize, Serialize, Serializer};
+use serde_json::{json, Map, Value};
+use tracing::error;
+
+use crate::auth::{Caller, Role};
+use crate::duplicates::{self, DuplicatePolicy};
+use crate::error::ApiError;
+use crate::model::{Movie, MoviePatch, NewMovie};
+use crate::replication;
+use crate::routes::{DEFAULT_PAGE_LIMIT, MAX_PAGE_LIMIT};
+use crate::store::{MovieFilter, MovieStore};
+use crate::validation::{validate_movie, validate_patch};
+
+// GraphQL on /graphql, over the same MovieStore as the REST routes. async-graphql isn't available to this build, so
+// this is a small executor for the one fixed schema below. It handles queries and mutations with variables, aliases,
+// fragments and @skip/@include, but not subscriptions or introspection; clients can fetch SCHEMA from GET /graphql.
+pub const SCHEMA: &str = r#"type Query {
+  movie(id: ID!): Movie
+  movies(filter: MovieFilter, page: Page): MoviePage!
+}
+
+type Mutation {
+  createMovie(input: MovieInput!, upsert: Boolean = false, allowDuplicate: Boolean = false): Movie!
+  updateMovie(id: ID!, patch: MoviePatch!): Movie!
+  deleteMovie(id: ID!): Movie!
+}
+
+type Movie {
+  id: ID!
+  name: String!
+  year: Int!
+  wasGood: Boolean!
+  genres: [String!]!
+  director: String
+  runtimeMinutes: Int
+  synopsis: String
+  posterUrl: String
+  tags: [String!]!
+  averageRating: Float
+  ratingCount: Int!
+  reviewCount: Int!
+  version: Int!
+  createdAt: String
+  updatedAt: String
+}
+
+type MoviePage {
+  items: [Movie!]!
+  total: Int!
+}
+
+input MovieFilter {
+  year: Int
+  wasGood: Boolean
+  nameContains: String
+  genre: String
+  director: String
+  tag: String
+}
+
+input Page {
+  limit: Int = 20
+  offset: Int = 0
+}
+
+input MovieInput {
+  id: ID
+  name: String!
+  year: Int!
+  wasGood: Boolean!
+  genres: [String!]
+  director: String
+  runtimeMinutes: Int
+  synopsis: String
+  posterUrl: String
+  tags: [String!]
+}
+
+# null removes genres, director, runtimeMinutes, synopsis, posterUrl or tags.
+input MoviePatch {
+  name: String
+  year: Int
+  wasGood: Boolean
+  genres: [String!]
+  director: String
+  runtimeMinutes: Int
+  synopsis: String
+  posterUrl: String
+  tags: [String!]
+  version: Int
+}
+"#;
+
+#[derive(Debug, Deserialize)]
+pub struct GraphQLRequest {
+    pub query: String,
+    #[serde(default)]
+    pub variables: Option<Map<String, Value>>,
+    #[serde(default, rename = "operationName")]
+    pub operation_name: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GraphQLResponse {
+    // Left out entirely when the request couldn't be executed at all, e.g. it didn't parse.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<Output>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<GraphQLError>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GraphQLError {
+    pub message: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub path: Vec<Value>,
+    // The same code and details as the REST error for the same problem, see ApiError.
+    pub extensions: Value,
+}
+
+impl GraphQLError {
+    fn new(error: ApiError, path: &[String]) -> GraphQLError {
+        if let ApiError::Internal(message) = &error {
+            error!("Internal error: {}", message);
+        }
+        let mut extensions = json!({ "code": error.code() });
+        if !error.details().is_null() {
+            extensions["details"] = error.details();
+        }
+        GraphQLError {
+            message: error.message(),
+            path: path.iter().map(|key| Value::from(key.as_str())).collect(),
+            extensions,
+        }
+    }
+}
+
+// A result value. serde_json's Map would sort the keys, and GraphQL responses keep the order fields were asked for.
+#[derive(Debug)]
+pub enum Output {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+    List(Vec<Output>),
+    Object(Vec<(String, Output)>),
+}
+
+impl Serialize for Output {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Output::Null => serializer.serialize_unit(),
+            Output::Bool(value) => serializer.serialize_bool(*value),
+            Output::Int(value) => serializer.serialize_i64(*value),
+            Output::Float(value) => serializer.serialize_f64(*value),
+            Output::String(value) => serializer.serialize_str(value),
+            Output::List(items) => {
+                let mut seq = serializer.serialize_seq(Some(items.len()))?;
+                for item in items {
+                    seq.serialize_element(item)?;
+                }
+                seq.end()
+            },
+            Output::Object(fields) => {
+                let mut map = serializer.serialize_map(Some(fields.len()))?;
+                for (key, value) in fields {
+                    map.serialize_entry(key, value)?;
+                }
+                map.end()
+            },
+        }
+    }
+}
+
+// Queries are open to anyone who could read over REST. Mutations need the role the REST route for the same change
+// would, which the caller may or may not have.
+pub async fn execute(store: &dyn MovieStore, duplicates: DuplicatePolicy, request: GraphQLRequest, caller: &Caller) -> GraphQLResponse {
+    let failed = |message: String| GraphQLResponse {
+        data: None,
+        errors: vec![GraphQLError::new(ApiError::BadRequest(message), &[])],
+    };
+    let document = match Parser::new(&request.query).and_then(|mut parser| parser.document()) {
+        Ok(document) => document,
+        Err(message) => return failed(format!("Syntax error: {}", message)),
+    };
+    let operation = match (&request.operation_name, document.operations.as_slice()) {
+        (None, [operation]) => operation,
+        (None, _) => return failed("operationName is required when the document has more than one operation".to_string()),
+        (Some(name), operations) => match operations.iter().find(|operation| operation.name.as_ref() == Some(name)) {
+            Some(operation) => operation,
+            None => return failed(format!("No operation named {:?}", name)),
+        },
+    };
+    if operation.kind == OperationKind::Subscription {
+        return failed("Subscriptions aren't supported".to_string());
+    }
+
+    let mut variables = Map::new();
+    let mut supplied = request.variables.unwrap_or_default();
+    for definition in &operation.variables {
+        let value = match supplied.remove(&definition.name) {
+            Some(value) => value,
+            None => match &definition.default {
+                Some(default) => match literal(default) {
+                    Ok(value) => value,
+                    Err(message) => return failed(message),
+                },
+                None => Value::Null,
+            },
+        };
+        if value.is_null() && definition.required {
+            return failed(format!("Variable ${} is required", definition.name));
+        }
+        variables.insert(definition.name.clone(), value);
+    }
+
+    let mut executor = Executor { store, duplicates, caller, variables, fragments: &document.fragments, errors: Vec::new() };
+    let data = executor.root(operation).await;
+    GraphQLResponse { data: Some(data), errors: executor.errors }
+}
+
+// Turns a constant value from the document, e.g. a variable default, into JSON.
+fn literal(node: &ValueNode) -> Result<Value, String> {
+    Ok(match node {
+        ValueNode::Variable(name) => return Err(format!("Variable ${} can't be used in a default value", name)),
+        ValueNode::Int(value) => Value::from(*value),
+        ValueNode::Float(value) => Value::from(*value),
+        ValueNode::String(value) | ValueNode::Enum(value) => Value::from(value.as_str()),
+        ValueNode::Bool(value) => Value::from(*value),
+        ValueNode::Null => Value::Null,
+        ValueNode::List(items) => Value::Array(items.iter().map(literal).collect::<Result<_, _>>()?),
+        ValueNode::Object(fields) => Value::Object(fields.iter().map(|(key, value)| Ok((key.clone(), literal(value)?))).collect::<Result<_, String>>()?),
+    })
+}
+
+struct Executor<'a> {
+    store: &'a dyn MovieStore,
+    duplicates: DuplicatePolicy,
+    caller: &'a Caller,
+    variables: Map<String, Value>,
+    fragments: &'a HashMap<String, Fragment>,
+    errors: Vec<GraphQLError>,
+}
+
+// What a root field resolved to, before its selection set is applied.
+enum Resolved {
+    Movie(Box<Movie>),
+    Missing,
+    Page(Vec<Movie>, usize),
+    Typename(&'static str),
+}
+
+impl<'a> Executor<'a> {
+    async fn root(&mut self, operation: &'a Operation) -> Output {
+        let fields = match self.collect_fields(&[&operation.selections]) {
+            Ok(fields) => fields,
+            Err(message) => {
+                self.errors.push(GraphQLError::new(ApiError::BadRequest(message), &[]));
+                return Output::Null;
+            },
+        };
+        let mut output = Vec::new();
+        // Fields run one after another, which is what the spec requires for mutations and harmless for queries.
+        for (key, nodes) in fields {
+            let path = vec![key.clone()];
+            let resolved = match operation.kind {
+                OperationKind::Mutation => self.mutation_field(nodes[0]).await,
+                _ => self.query_field(nodes[0]).await,
+            };
+            let value = match resolved {
+                Ok(resolved) => self.complete(resolved, &nodes, &path),
+                Err(error) => {
+                    self.errors.push(GraphQLError::new(error, &path));
+                    Output::Null
+                },
+            };
+            output.push((key, value));
+        }
+        Output::Object(output)
+    }
+
+    async fn query_field(&self, field: &FieldNode) -> Result<Resolved, ApiError> {
+        match field.name.as_str() {
+            "__typename" => Ok(Resolved::Typename("Query")),
+            "movie" => {
+                let id = self.required(field, "id").and_then(|id| as_id(id, "id"))?;
+                Ok(self.store.get(&id).await.map(|movie| Resolved::Movie(Box::new(movie))).unwrap_or(Resolved::Missing))
+            },
+            "movies" => {
+                let filter = match self.argument(field, "filter")? {
+                    Some(filter) => {
+                        let filter = input_object(filter, "filter", &["year", "wasGood", "nameContains", "genre", "director", "tag"])?;
+                        MovieFilter {
+                            year: filter.get("year").cloned().filter(|year| !year.is_null()).map(|year| as_year(year, "filter.year")).transpose()?,
+                            was_good: filter.get("wasGood").cloned().filter(|value| !value.is_null()).map(|value| as_bool(value, "filter.wasGood")).transpose()?,
+                            name_contains: filter.get("nameContains").cloned().filter(|value| !value.is_null()).map(|value| as_string(value, "filter.nameContains")).transpose()?,
+                            genre: filter.get("genre").cloned().filter(|value| !value.is_null()).map(|value| as_string(value, "filter.genre")).transpose()?,
+                            director: filter.get("director").cloned().filter(|value| !value.is_null()).map(|value| as_string(value, "filter.director")).transpose()?,
+                            tag: filter.get("tag").cloned().filter(|value| !value.is_null()).map(|value| as_string(value, "filter.tag")).transpose()?,
+                            ..MovieFilter::default()
+                        }
+                    },
+                    None => MovieFilter::default(),
+                };
+                let (limit, offset) = match self.argument(field, "page")? {
+                    Some(page) => {
+                        let page = input_object(page, "page", &["limit", "offset"])?;
+                        let limit = page.get("limit").cloned().filter(|value| !value.is_null()).map(|value| as_int(value, "page.limit")).transpose()?;
+                        let offset = page.get("offset").cloned().filter(|value| !value.is_null()).map(|value| as_int(value, "page.offset")).transpose()?;
+                        (limit, offset)
+                    },
+                    None => (None, None),
+                };
+                // Same paging as GET /movies, but out-of-range values are clamped rather than rejected.
+                let limit = limit.map_or(DEFAULT_PAGE_LIMIT, |limit| limit.clamp(1, MAX_PAGE_LIMIT as i64) as usize);
+                let offset = offset.map_or(0, |offset| offset.max(0) as usize);
+                let matching = self.store.list(&filter).await;
+                let total = matching.len();
+                Ok(Resolved::Page(matching.into_iter().skip(offset).take(limit).collect(), total))
+            },
+            other => Err(unknown_field(other, "Query")),
+        }
+    }
+
+    async fn mutation_field(&self, field: &FieldNode) -> Result<Resolved, ApiError> {
+        match field.name.as_str() {
+            "__typename" => Ok(Resolved::Typename("Mutation")),
+            "createMovie" => {
+                self.caller.require(Role::Editor)?;
+                replication::check_writable()?;
+                let input = input_object(self.required(field, "input")?, "input", &["id", "name", "year", "wasGood", "genres", "director", "runtimeMinutes", "synopsis", "posterUrl", "tags"])?;
+                let field_of = |name: &str| input.get(name).cloned().filter(|value| !value.is_null());
+                let new_movie = NewMovie {
+                    id: field_of("id").map(|id| as_id(id, "input.id")).transpose()?,
+                    name: as_string(field_of("name").ok_or_else(|| missing("input.name"))?, "input.name")?,
+                    year: as_year(field_of("year").ok_or_else(|| missing("input.year"))?, "input.year")?,
+                    was_good: as_bool(field_of("wasGood").ok_or_else(|| missing("input.wasGood"))?, "input.wasGood")?,
+                    genres: field_of("genres").map(|genres| as_strings(genres, "input.genres")).transpose()?.unwrap_or_default(),
+                    director: field_of("director").map(|director| as_string(director, "input.director")).transpose()?,
+                    runtime_minutes: field_of("runtimeMinutes").map(|runtime| as_u16(runtime, "input.runtimeMinutes")).transpose()?,
+                    synopsis: field_of("synopsis").map(|synopsis| as_string(synopsis, "input.synopsis")).transpose()?,
+                    poster_url: field_of("posterUrl").map(|url| as_string(url, "input.posterUrl")).transpose()?,
+                    tags: field_of("tags").map(|tags| as_strings(tags, "input.tags")).transpose()?.unwrap_or_default(),
+                    titles: BTreeMap::new(),
+                };
+                let upsert = self.argument(field, "upsert")?.map(|upsert| as_bool(upsert, "upsert")).transpose()?.unwrap_or(false);
+                let allow_duplicate = self.argument(field, "allowDuplicate")?.map(|allow| as_bool(allow, "allowDuplicate")).transpose()?.unwrap_or(false);
+                let movie = new_movie.into_movie();
+                validate_movie(&movie)?;
+                duplicates::check(self.store, self.duplicates, &movie, allow_duplicate).await?;
+                if upsert {
+                    self.store.upsert(movie.clone()).await?;
+                    // Overwriting a movie moves it on from that movie's version, it doesn't start again at 1.
+                    let stored = self.store.get(&movie.id).await;
+                    return Ok(Resolved::Movie(Box::new(stored.unwrap_or(movie))));
+                }
+                self.store.insert(movie.clone()).await?;
+                Ok(Resolved::Movie(Box::new(movie)))
+            },
+            "updateMovie" => {
+                self.caller.require(Role::Editor)?;
+                replication::check_writable()?;
+                let id = self.required(field, "id").and_then(|id| as_id(id, "id"))?;
+                let patch = input_object(self.required(field, "patch")?, "patch", &["name", "year", "wasGood", "genres", "director", "runtimeMinutes", "synopsis", "posterUrl", "tags", "version"])?;
+                let field_of = |name: &str| patch.get(name).cloned().filter(|value| !value.is_null());
+                // Some(None) for an explicit null, which removes the detail.
+                let detail_of = |name: &str| patch.get(name).cloned().map(|value| Some(value).filter(|value| !value.is_null()));
+                let patch = MoviePatch {
+                    id: None,
+                    name: field_of("name").map(|name| as_string(name, "patch.name")).transpose()?,
+                    year: field_of("year").map(|year| as_year(year, "patch.year")).transpose()?,
+                    was_good: field_of("wasGood").map(|value| as_bool(value, "patch.wasGood")).transpose()?,
+                    genres: detail_of("genres").map(|genres| genres.map(|genres| as_strings(genres, "patch.genres")).transpose()).transpose()?,
+                    director: detail_of("director").map(|director| director.map(|director| as_string(director, "patch.director")).transpose()).transpose()?,
+                    runtime_minutes: detail_of("runtimeMinutes").map(|runtime| runtime.map(|runtime| as_u16(runtime, "patch.runtimeMinutes")).transpose()).transpose()?,
+                    synopsis: detail_of("synopsis").map(|synopsis| synopsis.map(|synopsis| as_string(synopsis, "patch.synopsis")).transpose()).transpose()?,
+                    poster_url: detail_of("posterUrl").map(|url| url.map(|url| as_string(url, "patch.posterUrl")).transpose()).transpose()?,
+                    tags: detail_of("tags").map(|tags| tags.map(|tags| as_strings(tags, "patch.tags")).transpose()).transpose()?,
+                    titles: None,
+                    version: field_of("version").map(|version| as_version(version, "patch.version")).transpose()?,
+                    rating: None,
+                    review: None,
+                    poster: None,
+                    merge: None,
+                    updated_at: None,
+                };
+                validate_patch(&patch)?;
+                Ok(Resolved::Movie(Box::new(self.store.patch(&id, patch).await?)))
+            },
+            "deleteMovie" => {
+                self.caller.require(Role::Admin)?;
+                replication::check_writable()?;
+                let id = self.required(field, "id").and_then(|id| as_id(id, "id"))?;
+                Ok(Resolved::Movie(Box::new(self.store.delete(&id).await?)))
+            },
+            other => Err(unknown_field(other, "Mutation")),
+        }
+    }
+
+    fn complete(&mut self, resolved: Resolved, nodes: &[&'a FieldNode], path: &[String]) -> Output {
+        match resolved {
+            Resolved::Typename(name) => Output::String(name.to_string()),
+            Resolved::Missing => Output::Null,
+            Resolved::Movie(movie) => self.complete_object(nodes, path, |key, _, _| movie_field(&movie, key)),
+            Resolved::Page(items, total) => self.complete_object(nodes, path, |key, executor, subfields| match key {
+                "items" => Ok(Output::List(items.iter().enumerate()
+                    .map(|(index, movie)| {
+                        let mut item_path = subfields.1.to_vec();
+                        item_path.push(index.to_string());
+                        executor.complete_object(subfields.0, &item_path, |key, _, _| movie_field(movie, key))
+                    })
+                    .collect())),
+                "total" => Ok(Output::Int(total as i64)),
+                "__typename" => Ok(Output::String("MoviePage".to_string())),
+                other => Err(unknown_field(other, "MoviePage")),
+            }),
+        }
+    }
+
+    // Applies the selection set of `nodes` to an object, resolving each selected field with `field`, which gets the
+    // field name, the executor, and that field's own nodes and path for anything nested further.
+    fn complete_object(
+        &mut self,
+        nodes: &[&'a FieldNode],
+        path: &[String],
+        mut field: impl FnMut(&str, &mut Executor<'a>, (&[&'a FieldNode], &[String])) -> Result<Output, ApiError>,
+    ) -> Output {
+        let selections: Vec<&'a [Selection]> = nodes.iter().map(|node| node.selections.as_slice()).collect();
+        if selections.iter().all(|selection| selection.is_empty()) {
+            self.errors.push(GraphQLError::new(ApiError::BadRequest(format!("Field {:?} needs a selection of subfields", nodes[0].name)), path));
+            return Output::Null;
+        }
+        let fields = match self.collect_fields(&selections) {
+            Ok(fields) => fields,
+            Err(message) => {
+                self.errors.push(GraphQLError::new(ApiError::BadRequest(message), path));
+                return Output::Null;
+            },
+        };
+        let mut output = Vec::new();
+        for (key, subnodes) in fields {
+            let mut field_path = path.to_vec();
+            field_path.push(key.clone());
+            let name = subnodes[0].name.clone();
+            let value = match field(&name, self, (&subnodes, &field_path)) {
+                Ok(value) => value,
+                Err(error) => {
+                    self.errors.push(GraphQLError::new(error, &field_path));
+                    Output::Null
+                },
+            };
+            output.push((key, value));
+        }
+        Output::Object(output)
+    }
+
+    // Flattens fragments and drops @skip'd fields, grouping fields by the key they'll appear under in the response.
+    fn collect_fields(&self, selections: &[&'a [Selection]]) -> Result<Vec<(String, Vec<&'a FieldNode>)>, String> {
+        let mut fields: Vec<(String, Vec<&'a FieldNode>)> = Vec::new();
+        let mut visited = HashSet::new();
+        for selection in selections {
+            self.collect_into(selection, &mut fields, &mut visited)?;
+        }
+        Ok(fields)
+    }
+
+    fn collect_into(&self, selections: &'a [Selection], fields: &mut Vec<(String, Vec<&'a FieldNode>)>, visited: &mut HashSet<&'a str>) -> Result<(), String> {
+        for selection in selections {
+            match selection {
+                Selection::Field(field) => {
+                    if !self.included(&field.directives)? {
+                        continue;
+                    }
+                    let key = field.alias.clone().unwrap_or_else(|| field.name.clone());
+                    match fields.iter_mut().find(|(existing, _)| *existing == key) {
+                        Some((_, nodes)) => {
+                            if nodes[0].name != field.name {
+                                return Err(format!("{:?} is used as the key for both {:?} and {:?}", key, nodes[0].name, field.name));
+                            }
+                            nodes.push(field);
+                        },
+                        None => fields.push((key, vec![field])),
+                    }
+                },
+                Selection::FragmentSpread(name, directives) => {
+                    if !self.included(directives)? || !visited.insert(name.as_str()) {
+                        continue;
+                    }
+                    let fragment = self.fragments.get(name).ok_or_else(|| format!("Unknown fragment {:?}", name))?;
+                    self.collect_into(&fragment.selections, fields, visited)?;
+                },
+                Selection::InlineFragment(directives, selections) => {
+                    if self.included(directives)? {
+                        self.collect_into(selections, fields, visited)?;
+                    }
+                },
+            }
+        }
+        Ok(())
+    }
+
+    fn included(&self, directives: &[Directive]) -> Result<bool, String> {
+        for directive in directives {
+            let condition = || -> Result<bool, String> {
+                let node = directive.arguments.iter().find(|(name, _)| name == "if").map(|(_, value)| value)
+                    .ok_or_else(|| format!("@{} needs an if argument", directive.name))?;
+                self.value(node)?.as_bool().ok_or_else(|| format!("@{}(if:) must be a Boolean", directive.name))
+            };
+            match directive.name.as_str() {
+                "skip" if condition()? => return Ok(false),
+                "include" if !condition()? => return Ok(false),
+                "skip" | "include" => {},
+                other => return Err(format!("Unknown directive @{}", other)),
+            }
+        }
+        Ok(true)
+    }
+
+    fn value(&self, node: &ValueNode) -> Result<Value, String> {
+        Ok(match node {
+            ValueNode::Variable(name) => self.variables.get(name).cloned().ok_or_else(|| format!("Variable ${} isn't defined by the operation", name))?,
+            ValueNode::List(items) => Value::Array(items.iter().map(|item| self.value(item)).collect::<Result<_, _>>()?),
+            ValueNode::Object(fields) => Value::Object(fields.iter().map(|(key, value)| Ok((key.clone(), self.value(value)?))).collect::<Result<_, String>>()?),
+            constant => literal(constant)?,
+        })
+    }
+
+    // The argument's value, or None if it was left out or null.
+    fn argument(&self, field: &FieldNode, name: &str) -> Result<Option<Value>, ApiError> {
+        match field.arguments.iter().find(|(argument, _)| argument == name) {
+            Some((_, node)) => Ok(Some(self.value(node).map_err(ApiError::BadRequest)?).filter(|value| !value.is_null())),
+            None => Ok(None),
+        }
+    }
+
+    fn required(&self, field: &FieldNode, name: &str) -> Result<Value, ApiError> {
+        self.argument(field, name)?.ok_or_else(|| missing(name))
+    }
+}
+
+fn movie_field(movie: &Movie, key: &str) -> Result<Output, ApiError> {
+    Ok(match key {
+        "id" => Output::String(movie.id.clone()),
+        "name" => Output::String(movie.name.clone()),
+        "year" => Output::Int(movie.year.into()),
+        "wasGood" => Output::Bool(movie.was_good),
+        "genres" => Output::List(movie.genres.iter().cloned().map(Output::String).collect()),
+        "director" => movie.director.clone().map_or(Output::Null, Output::String),
+        "runtimeMinutes" => movie.runtime_minutes.map_or(Output::Null, |runtime| Output::Int(runtime.into())),
+        "synopsis" => movie.synopsis.clone().map_or(Output::Null, Output::String),
+        "posterUrl" => movie.poster_url.clone().map_or(Output::Null, Output::String),
+        "tags" => Output::List(movie.tags.iter().cloned().map(Output::String).collect()),
+        "averageRating" => movie.average_rating.map_or(Output::Null, Output::Float),
+        "ratingCount" => Output::Int(movie.rating_count as i64),
+        "reviewCount" => Output::Int(movie.review_count as i64),
+        "version" => Output::Int(movie.version as i64),
+        "createdAt" => movie.created_at.clone().map_or(Output::Null, Output::String),
+        "updatedAt" => movie.updated_at.clone().map_or(Output::Null, Output::String),
+        "__typename" => Output::String("Movie".to_string()),
+        other => return Err(unknown_field(other, "Movie")),
+    })
+}
+
+fn unknown_field(name: &str, parent: &str) -> ApiError {
+    ApiError::BadRequest(format!("Cannot query field {:?} on type {:?}", name, parent))
+}
+
+fn missing(name: &str) -> ApiError {
+    ApiError::BadRequest(format!("{} is required", name))
+}
+
+fn as_id(value: Value, name: &str) -> Result<String, ApiError> {
+    match value {
+        Value::String(id) => Ok(id),
+        Value::Number(number) if number.is_i64() => Ok(number.to_string()),
+        _ => Err(ApiError::BadRequest(format!("{} must be an ID", name))),
+    }
+}
+
+fn as_string(value: Value, name: &str) -> Result<String, ApiError> {
+    match value {
+        Value::String(string) => Ok(string),
+        _ => Err(ApiError::BadRequest(format!("{} must be a String", name))),
+    }
+}
+
+fn as_int(value: Value, name: &str) -> Result<i64, ApiError> {
+    value.as_i64().ok_or_else(|| ApiError::BadRequest(format!("{} must be an Int", name)))
+}
+
+fn as_year(value: Value, name: &str) -> Result<u16, ApiError> {
+    as_u16(value, name)
+}
+
+fn as_u16(value: Value, name: &str) -> Result<u16, ApiError> {
+    u16::try_from(as_int(value, name)?).map_err(|_| ApiError::BadRequest(format!("{} is out of range", name)))
+}
+
+// A single string is also accepted, as GraphQL input coercion allows for lists.
+fn as_strings(value: Value, name: &str) -> Result<Vec<String>, ApiError> {
+    match value {
+        Value::Array(items) => items.into_iter().map(|item| as_string(item, name)).collect(),
+        value => as_string(value, name).map(|string| vec![string]),
+    }
+}
+
+fn as_version(value: Value, name: &str) -> Result<u64, ApiError> {
+    u64::try_from(as_int(value, name)?).map_err(|_| ApiError::BadRequest(format!("{} is out of range", name)))
+}
+
+fn as_bool(value: Value, name: &str) -> Result<bool, ApiError> {
+    value.as_bool().ok_or_else(|| ApiError::BadRequest(format!("{} must be a Boolean", name)))
+}
+
+fn input_object(value: Value, name: &str, allowed: &[&str]) -> Result<Map<String, Value>, ApiError> {
+    let Value::Object(object) = value else {
+        return Err(ApiError::BadRequest(format!("{} must be an input object", name)));
+    };
+    if let Some(unknown) = object.keys().find(|key| !allowed.contains(&key.as_str())) {
+        return Err(ApiError::BadRequest(format!("{} has no field {:?}", name, unknown)));
+    }
+    Ok(object)
+}
+
+// The document, as parsed. Types in variable definitions are only checked for being non-null.
+
+struct Document {
+    operations: Vec<Operation>,
+    fragments: HashMap<String, Fragment>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum OperationKind {
+    Query,
+    Mutation,
+    Subscription,
+}
+
+struct Operation {
+    kind: OperationKind,
+    name: Option<String>,
+    variables: Vec<VariableDefinition>,
+    selections: Vec<Selection>,
+}
+
+struct VariableDefinition {
+    name: String,
+    required: bool,
+    default: Option<ValueNode>,
+}
+
+struct Fragment {
+    selections: Vec<Selection>,
+}
+
+enum Selection {
+    Field(FieldNode),
+    FragmentSpread(String, Vec<Directive>),
+    InlineFragment(Vec<Directive>, Vec<Selection>),
+}
+
+struct FieldNode {
+    alias: Option<String>,
+    name: String,
+    arguments: Vec<(String, ValueNode)>,
+    directives: Vec<Directive>,
+    selections: Vec<Selection>,
+}
+
+struct Directive {
+    name: String,
+    arguments: Vec<(String, ValueNode)>,
+}
+
+enum ValueNode {
+    Variable(String),
+    Int(i64),
+    Float(f64),
+    String(String),
+    Bool(bool),
+    Null,
+    Enum(String),
+    List(Vec<ValueNode>),
+    Object(Vec<(String, ValueNode)>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Punctuator(char),
+    Spread,
+    Name(String),
+    Int(i64),
+    Float(f64),
+    String(String),
+    End,
+}
+
+// Stops runaway nesting before it stops the stack.
+const MAX_DEPTH: usize = 32;
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn new(source: &str) -> Result<Parser, String> {
+        Ok(Parser { tokens: tokenize(source)?, position: 0, depth: 0 })
+    }
+
+    fn peek(&self) -> &Token {
+        &self.tokens[self.position]
+    }
+
+    fn next(&mut self) -> Token {
+        let token = self.tokens[self.position].clone();
+        if token != Token::End {
+            self.position += 1;
+        }
+        token
+    }
+
+    fn eat(&mut self, punctuator: char) -> bool {
+        if *self.peek() == Token::Punctuator(punctuator) {
+            self.position += 1;
+            true
+        }
+        else {
+            false
+        }
+    }
+
+    fn expect(&mut self, punctuator: char) -> Result<(), String> {
+        if self.eat(punctuator) { Ok(()) } else { Err(format!("expected {:?}, found {}", punctuator, describe(self.peek()))) }
+    }
+
+    fn name(&mut self) -> Result<String, String> {
+        match self.next() {
+            Token::Name(name) => Ok(name),
+            other => Err(format!("expected a name, found {}", describe(&other))),
+        }
+    }
+
+    fn document(&mut self) -> Result<Document, String> {
+        let mut document = Document { operations: Vec::new(), fragments: HashMap::new() };
+        while *self.peek() != Token::End {
+            match self.peek().clone() {
+                Token::Punctuator('{') => {
+                    let selections = self.selection_set()?;
+                    document.operations.push(Operation { kind: OperationKind::Query, name: None, variables: Vec::new(), selections });
+                },
+                Token::Name(keyword) if keyword == "fragment" => {
+                    self.next();
+                    let name = self.name()?;
+                    if self.name()? != "on" {
+                        return Err(format!("expected \"on\" after fragment {}", name));
+                    }
+                    self.name()?;
+                    self.directives()?;
+                    let selections = self.selection_set()?;
+                    if document.fragments.insert(name.clone(), Fragment { selections }).is_some() {
+                        return Err(format!("fragment {} is defined twice", name));
+                    }
+                },
+                Token::Name(keyword) if keyword == "query" || keyword == "mutation" || keyword == "subscription" => {
+                    self.next();
+                    let kind = match keyword.as_str() {
+                        "query" => OperationKind::Query,
+                        "mutation" => OperationKind::Mutation,
+                        _ => OperationKind::Subscription,
+                    };
+                    let name = match self.peek() {
+                        Token::Name(_) => Some(self.name()?),
+                        _ => None,
+                    };
+                    let variables = self.variable_definitions()?;
+                    self.directives()?;
+                    let selections = self.selection_set()?;
+                    document.operations.push(Operation { kind, name, variables, selections });
+                },
+                other => return Err(format!("expected an operation or fragment, found {}", describe(&other))),
+            }
+        }
+        if document.operations.is_empty() {
+            return Err("the document has no operations".to_string());
+        }
+        Ok(document)
+    }
+
+    fn variable_definitions(&mut self) -> Result<Vec<VariableDefinition>, String> {
+        let mut definitions = Vec::new();
+        if !self.eat('(') {
+            return Ok(definitions);
+        }
+        while !self.eat(')') {
+            self.expect('$')?;
+            let name = self.name()?;
+            self.expect(':')?;
+            let required = self.skip_type()?;
+            let default = if self.eat('=') { Some(self.value()?) } else { None };
+            self.directives()?;
+            definitions.push(VariableDefinition { name, required, default });
+        }
+        Ok(definitions)
+    }
+
+    // Returns whether the outermost type is non-null.
+    fn skip_type(&mut self) -> Result<bool, String> {
+        if self.eat('[') {
+            self.skip_type()?;
+            self.expect(']')?;
+        }
+        else {
+            self.name()?;
+        }
+        Ok(self.eat('!'))
+    }
+
+    fn selection_set(&mut self) -> Result<Vec<Selection>, String> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(format!("selections are nested more than {} deep", MAX_DEPTH));
+        }
+        self.expect('{')?;
+        let mut selections = Vec::new();
+        while !self.eat('}') {
+            selections.push(self.selection()?);
+        }
+        if selections.is_empty() {
+            return Err("empty selection set".to_string());
+        }
+        self.depth -= 1;
+        Ok(selections)
+    }
+
+    fn selection(&mut self) -> Result<Selection, String> {
+        if *self.peek() == Token::Spread {
+            self.next();
+            return match self.peek().clone() {
+                Token::Name(name) if name != "on" => {
+                    self.next();
+                    Ok(Selection::FragmentSpread(name, self.directives()?))
+                },
+                _ => {
+                    if *self.peek() == Token::Name("on".to_string()) {
+                        self.next();
+                        self.name()?;
+                    }
+                    let directives = self.directives()?;
+                    Ok(Selection::InlineFragment(directives, self.selection_set()?))
+                },
+            };
+        }
+        let mut name = self.name()?;
+        let mut alias = None;
+        if self.eat(':') {
+            alias = Some(name);
+            name = self.name()?;
+        }
+        let arguments = self.arguments()?;
+        let directives = self.directives()?;
+        let selections = if *self.peek() == Token::Punctuator('{') { self.selection_set()? } else { Vec::new() };
+        Ok(Selection::Field(FieldNode { alias, name, arguments, directives, selections }))
+    }
+
+    fn arguments(&mut self) -> Result<Vec<(String, ValueNode)>, String> {
+        let mut arguments = Vec::new();
+        if self.eat('(') {
+            while !self.eat(')') {
+                let name = self.name()?;
+                self.expect(':')?;
+                arguments.push((name, self.value()?));
+            }
+        }
+        Ok(arguments)
+    }
+
+    fn directives(&mut self) -> Result<Vec<Directive>, String> {
+        let mut directives = Vec::new();
+        while self.eat('@') {
+            let name = self.name()?;
+            directives.push(Directive { name, arguments: self.arguments()? });
+        }
+        Ok(directives)
+    }
+
+    fn value(&mut self) -> Result<ValueNode, String> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(format!("values are nested more than {} deep", MAX_DEPTH));
+        }
+        let value = match self.next() {
+            Token::Punctuator('$') => ValueNode::Variable(self.name()?),
+            Token::Int(value) => ValueNode::Int(value),
+            Token::Float(value) => ValueNode::Float(value),
+            Token::String(value) => ValueNode::String(value),
+            Token::Name(name) => match name.as_str() {
+                "true" => ValueNode::Bool(true),
+                "false" => ValueNode::Bool(false),
+                "null" => ValueNode::Null,
+                _ => ValueNode::Enum(name),
+            },
+            Token::Punctuator('[') => {
+                let mut items = Vec::new();
+                while !self.eat(']') {
+                    items.push(self.value()?);
+                }
+                ValueNode::List(items)
+            },
+            Token::Punctuator('{') => {
+                let mut fields = Vec::new();
+                while !self.eat('}') {
+                    let name = self.name()?;
+                    self.expect(':')?;
+                    fields.push((name, self.value()?));
+                }
+                ValueNode::Object(fields)
+            },
+            other => return Err(format!("expected a value, found {}", describe(&other))),
+        };
+        self.depth -= 1;
+        Ok(value)
+    }
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Punctuator(punctuator) => format!("{:?}", punctuator),
+        Token::Spread => "\"...\"".to_string(),
+        Token::Name(name) => format!("name {:?}", name),
+        Token::Int(value) => format!("number {}", value),
+        Token::Float(value) => format!("number {}", value),
+        Token::String(value) => format!("string {:?}", value),
+        Token::End => "the end of the document".to_string(),
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            ' ' | '\t' | '\n' | '\r' | ',' | '\u{feff}' => i += 1,
+            '#' => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+            },
+            '!' | '$' | '&' | '(' | ')' | ':' | '=' | '@' | '[' | ']' | '{' | '|' | '}' => {
+                tokens.push(Token::Punctuator(c));
+                i += 1;
+            },
+            '.' => {
+                if chars[i..].starts_with(&['.', '.', '.']) {
+                    tokens.push(Token::Spread);
+                    i += 3;
+                }
+                else {
+                    return Err("unexpected \".\"".to_string());
+                }
+            },
+            '"' if chars[i..].starts_with(&['"', '"', '"']) => {
+                let start = i + 3;
+                let end = (start..chars.len().saturating_sub(2)).find(|&j| chars[j..].starts_with(&['"', '"', '"']))
+                    .ok_or("unterminated block string")?;
+                let raw: String = chars[start..end].iter().collect();
+                tokens.push(Token::String(raw.trim().to_string()));
+                i = end + 3;
+            },
+            '"' => {
+                let mut value = String::new();
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        None | Some('\n') => return Err("unterminated string".to_string()),
+                        Some('"') => break,
+                        Some('\\') => {
+                            let escaped = match chars.get(i + 1) {
+                                Some('"') => '"',
+                                Some('\\') => '\\',
+                                Some('/') => '/',
+                                Some('b') => '\u{8}',
+                                Some('f') => '\u{c}',
+                                Some('n') => '\n',
+                                Some('r') => '\r',
+                                Some('t') => '\t',
+                                Some('u') => {
+                                    let hex: String = chars.get(i + 2..i + 6).map(|hex| hex.iter().collect()).unwrap_or_default();
+                                    let escaped = u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32)
+                                        .ok_or_else(|| format!("invalid escape \\u{}", hex))?;
+                                    i += 4;
+                                    escaped
+                                },
+                                other => return Err(format!("invalid escape {:?}", other)),
+                            };
+                            value.push(escaped);
+                            i += 2;
+                        },
+                        Some(&c) => {
+                            value.push(c);
+                            i += 1;
+                        },
+                    }
+                }
+                tokens.push(Token::String(value));
+                i += 1;
+            },
+            c if c == '_' || c.is_ascii_alphabetic() => {
+                let start = i;
+                while i < chars.len() && (chars[i] == '_' || chars[i].is_ascii_alphanumeric()) {
+                    i += 1;
+                }
+                tokens.push(Token::Name(chars[start..i].iter().collect()));
+            },
+            c if c == '-' || c.is_ascii_digit() => {
+                let start = i;
+                i += 1;
+                let mut float = false;
+                while i < chars.len() && (chars[i].is_ascii_digit() || matches!(chars[i], '.' | 'e' | 'E') || (matches!(chars[i], '+' | '-') && matches!(chars[i - 1], 'e' | 'E'))) {
+                    float |= matches!(chars[i], '.' | 'e' | 'E');
+                    i += 1;
+                }
+                let text: String = chars[start..i].iter().collect();
+                let token = if float { text.parse().map(Token::Float).ok() } else { text.parse().map(Token::Int).ok() };
+                tokens.push(token.ok_or_else(|| format!("invalid number {:?}", text))?);
+            },
+            other => return Err(format!("unexpected character {:?}", other)),
+        }
+    }
+    tokens.push(Token::End);
+    Ok(tokens)
+}
@@ -0,0 +1,498 @@
+use std::{collections::BTreeMap, convert::Infallible, pin::Pin, task::{Context, Poll}};
+use axum::{body::{Body, Bytes}, extract::{Path, State}, http::{header, HeaderMap, HeaderValue, StatusCode}, response::{IntoResponse, Response}, Extension};
+use hyper::body::Frame;
+use tokio::sync::{broadcast::error::RecvError, mpsc};
+use tracing::{debug, warn};
+
+use crate::auth::{Caller, Role};
+use crate::duplicates::{self, DuplicatePolicy};
+use crate::error::ApiError;
+use crate::events::MovieEvent;
+use crate::model::{Movie, NewMovie};
+use crate::replication;
+use crate::routes::{DEFAULT_PAGE_LIMIT, MAX_PAGE_LIMIT};
+use crate::settings::Settings;
+use crate::shutdown;
+use crate::state::StateWrapper;
+use crate::store::{MovieFilter, StoreError};
+use crate::validation::validate_movie;
+
+// The movies over gRPC, for consumers that would rather have generated stubs than JSON: movies.v1.MovieService in
+// proto/movies.proto, with Get, Put, List, Delete and Watch. It's served by the same router as everything else, at
+// /movies.v1.MovieService/{method}, so the store, keys, tokens, tenants and rate limits are all shared with the HTTP
+// API, and gRPC clients connect to the same port over HTTP/2 (h2c, since like the rest it only speaks plain TCP, see
+// listener.rs). The messages are encoded and decoded here by hand, being a handful of strings and integers, and only
+// uncompressed ones are taken. Errors from the handlers come back as a grpc-status, while the layers in front of the
+// router answer with their usual status codes, which gRPC clients map to UNAUTHENTICATED, PERMISSION_DENIED,
+// UNAVAILABLE and so on themselves.
+
+// gRPC status codes, from grpc/doc/statuscodes.md.
+const OK: u8 = 0;
+const INVALID_ARGUMENT: u8 = 3;
+const DEADLINE_EXCEEDED: u8 = 4;
+const NOT_FOUND: u8 = 5;
+const ALREADY_EXISTS: u8 = 6;
+const PERMISSION_DENIED: u8 = 7;
+const RESOURCE_EXHAUSTED: u8 = 8;
+const FAILED_PRECONDITION: u8 = 9;
+const ABORTED: u8 = 10;
+const UNIMPLEMENTED: u8 = 12;
+const INTERNAL: u8 = 13;
+const UNAVAILABLE: u8 = 14;
+const DATA_LOSS: u8 = 15;
+const UNAUTHENTICATED: u8 = 16;
+
+// Protobuf wire types.
+const VARINT: u8 = 0;
+const FIXED64: u8 = 1;
+const LEN: u8 = 2;
+const FIXED32: u8 = 5;
+
+// Changes a Watch may have waiting to be sent before it stops taking more from the store, and falls behind.
+const WATCH_BUFFER: usize = 64;
+
+// How a call ended, sent as the grpc-status and grpc-message trailers.
+#[derive(Debug)]
+struct Status {
+    code: u8,
+    message: String,
+}
+
+impl Status {
+    fn new(code: u8, message: impl Into<String>) -> Status {
+        Status { code, message: message.into() }
+    }
+
+    fn invalid(message: impl Into<String>) -> Status {
+        Status::new(INVALID_ARGUMENT, message)
+    }
+}
+
+impl From<ApiError> for Status {
+    fn from(error: ApiError) -> Status {
+        let code = match &error {
+            ApiError::BadRequest(_) | ApiError::InvalidBody(..) | ApiError::InvalidQuery(_) | ApiError::InvalidPath(_)
+            | ApiError::Validation(_) | ApiError::IdempotencyKeyReused(_) => INVALID_ARGUMENT,
+            ApiError::NotFound(_) | ApiError::Gone(_) => NOT_FOUND,
+            ApiError::MethodNotAllowed(..) => UNIMPLEMENTED,
+            ApiError::Unauthorized(_) => UNAUTHENTICATED,
+            ApiError::Forbidden(_) => PERMISSION_DENIED,
+            ApiError::AlreadyExists(_) | ApiError::Duplicate(_) => ALREADY_EXISTS,
+            ApiError::Conflict(_) | ApiError::RequestInProgress(_) => ABORTED,
+            ApiError::PreconditionFailed(_) => FAILED_PRECONDITION,
+            ApiError::RateLimited(_) | ApiError::PayloadTooLarge(_) | ApiError::QuotaExceeded(_) => RESOURCE_EXHAUSTED,
+            ApiError::Internal(_) => INTERNAL,
+            ApiError::Unavailable(_) => UNAVAILABLE,
+            ApiError::Timeout(_) => DEADLINE_EXCEEDED,
+        };
+        if code == INTERNAL {
+            // Like the HTTP API, the details are only logged.
+            warn!("gRPC call failed: {:?}", error);
+        }
+        Status::new(code, error.message())
+    }
+}
+
+impl From<StoreError> for Status {
+    fn from(error: StoreError) -> Status {
+        ApiError::from(error).into()
+    }
+}
+
+// POST /movies.v1.MovieService/{method}.
+pub async fn call(State(state): State<StateWrapper>, Extension(caller): Extension<Caller>, Extension(settings): Extension<Settings>, Path(method): Path<String>, headers: HeaderMap, body: Bytes) -> Response {
+    let content_type = headers.get(header::CONTENT_TYPE).and_then(|value| value.to_str().ok()).unwrap_or_default();
+    if !content_type.starts_with("application/grpc") {
+        return ApiError::InvalidBody(StatusCode::UNSUPPORTED_MEDIA_TYPE, format!("Expected application/grpc, got {:?}", content_type)).into_response();
+    }
+    let message = match unframe(&body) {
+        Ok(message) => message,
+        Err(status) => return unary(Err(status)),
+    };
+    debug!("gRPC call to {}", method);
+    let result = match method.as_str() {
+        "Get" => get(&state, message).await,
+        "Put" => put(&state, &caller, settings.duplicates(), message).await,
+        "List" => list(&state, message).await,
+        "Delete" => delete(&state, &caller, message).await,
+        "Watch" => return watch(&state, message),
+        other => Err(Status::new(UNIMPLEMENTED, format!("movies.v1.MovieService has no method {:?}", other))),
+    };
+    unary(result)
+}
+
+async fn get(state: &StateWrapper, message: &[u8]) -> Result<Vec<u8>, Status> {
+    let id = decode_id(message)?;
+    let movie = state.get(&id).await.ok_or_else(|| Status::new(NOT_FOUND, "No such movie"))?;
+    Ok(encode_movie(&movie))
+}
+
+async fn put(state: &StateWrapper, caller: &Caller, duplicates: DuplicatePolicy, message: &[u8]) -> Result<Vec<u8>, Status> {
+    caller.require(Role::Editor)?;
+    replication::check_writable()?;
+    let (new_movie, version) = decode_movie(message)?;
+    let mut movie = new_movie.into_movie();
+    validate_movie(&movie).map_err(ApiError::from)?;
+    match version {
+        None => {
+            duplicates::check(state.as_ref(), duplicates, &movie, false).await?;
+            state.insert(movie.clone()).await?;
+        },
+        // Like PUT /movie/{id}, replacing any other version would lose someone's change.
+        Some(expected) => {
+            movie.version = expected;
+            let precondition = |current: &Movie| current.version == expected;
+            state.update_if(movie.clone(), Some(&precondition)).await?;
+        },
+    }
+    // As the store left it, at its next version and with what only the server keeps.
+    let stored = state.get(&movie.id).await.unwrap_or(movie);
+    Ok(encode_movie(&stored))
+}
+
+async fn list(state: &StateWrapper, message: &[u8]) -> Result<Vec<u8>, Status> {
+    let mut limit = DEFAULT_PAGE_LIMIT;
+    let mut offset = 0;
+    let mut filter = MovieFilter::default();
+    for field in Fields::new(message) {
+        match field? {
+            (1, Value::Varint(value)) if value > 0 => limit = (value as usize).min(MAX_PAGE_LIMIT),
+            (2, Value::Varint(value)) => offset = value as usize,
+            (3, Value::Varint(value)) => filter.year = Some(as_u16(value, "year")?),
+            (4, Value::Varint(value)) => filter.was_good = Some(value != 0),
+            (5, Value::Len(value)) => filter.genre = Some(as_string(value, "genre")?).filter(|genre| !genre.is_empty()),
+            (6, Value::Len(value)) => filter.name_contains = Some(as_string(value, "name_contains")?).filter(|name| !name.is_empty()),
+            (1, Value::Varint(_)) => {},
+            (number @ 1..=6, _) => return Err(wrong_type("ListMoviesRequest", number)),
+            _ => {},
+        }
+    }
+    let matching = state.list(&filter).await;
+    let mut encoder = Encoder::default();
+    for movie in matching.iter().skip(offset).take(limit) {
+        encoder.bytes(1, &encode_movie(movie));
+    }
+    encoder.uint(2, matching.len() as u64);
+    Ok(encoder.0)
+}
+
+async fn delete(state: &StateWrapper, caller: &Caller, message: &[u8]) -> Result<Vec<u8>, Status> {
+    caller.require(Role::Admin)?;
+    replication::check_writable()?;
+    let id = decode_id(message)?;
+    Ok(encode_movie(&state.delete(&id).await?))
+}
+
+// Streams every change from now on, until the client goes away or the server starts draining.
+fn watch(state: &StateWrapper, message: &[u8]) -> Response {
+    if let Some(Err(status)) = Fields::new(message).find(Result::is_err) {
+        return unary(Err(status));
+    }
+    // Subscribed before answering, so nothing that happens once the client has its response is missed.
+    let mut changes = state.subscribe();
+    let (sender, receiver) = mpsc::channel(WATCH_BUFFER);
+    tokio::spawn(async move {
+        let status = loop {
+            let received = tokio::select! {
+                received = changes.recv() => received,
+                _ = shutdown::draining() => break Status::new(UNAVAILABLE, "The server is shutting down"),
+                _ = sender.closed() => return,
+            };
+            match received {
+                Ok(change) => {
+                    if sender.send(Frame::data(frame(&encode_change(&change)))).await.is_err() {
+                        return;
+                    }
+                },
+                Err(RecvError::Lagged(missed)) => {
+                    warn!("gRPC watcher fell behind and missed {} changes", missed);
+                    break Status::new(DATA_LOSS, format!("Fell behind and missed {} changes, read the movies again and watch anew", missed));
+                },
+                Err(RecvError::Closed) => break Status::new(OK, ""),
+            }
+        };
+        let _ = sender.send(Frame::trailers(trailers(&status))).await;
+    });
+    respond(receiver)
+}
+
+// The one message of a unary call's body. Each is a compressed flag, a big-endian length and the message.
+fn unframe(body: &[u8]) -> Result<&[u8], Status> {
+    let [compressed, a, b, c, d, message @ ..] = body else {
+        return Err(Status::invalid("The body isn't a gRPC message"));
+    };
+    if *compressed != 0 {
+        return Err(Status::new(UNIMPLEMENTED, "Compressed messages aren't supported, send them with grpc-encoding identity"));
+    }
+    if u32::from_be_bytes([*a, *b, *c, *d]) as usize != message.len() {
+        return Err(Status::invalid("Expected exactly one gRPC message"));
+    }
+    Ok(message)
+}
+
+fn frame(message: &[u8]) -> Bytes {
+    let mut framed = Vec::with_capacity(message.len() + 5);
+    framed.push(0);
+    framed.extend_from_slice(&(message.len() as u32).to_be_bytes());
+    framed.extend_from_slice(message);
+    framed.into()
+}
+
+fn trailers(status: &Status) -> HeaderMap {
+    let mut trailers = HeaderMap::new();
+    trailers.insert("grpc-status", HeaderValue::from(u16::from(status.code)));
+    if !status.message.is_empty() {
+        // Percent-encoded, as the gRPC spec has it, so any text fits in a header.
+        let message: String = status.message.bytes()
+            .map(|byte| if (0x20..0x7f).contains(&byte) && byte != b'%' { (byte as char).to_string() } else { format!("%{:02X}", byte) })
+            .collect();
+        if let Ok(message) = HeaderValue::from_str(&message) {
+            trailers.insert("grpc-message", message);
+        }
+    }
+    trailers
+}
+
+fn unary(result: Result<Vec<u8>, Status>) -> Response {
+    let (sender, receiver) = mpsc::channel(2);
+    let status = match result {
+        Ok(message) => {
+            let _ = sender.try_send(Frame::data(frame(&message)));
+            Status::new(OK, "")
+        },
+        Err(status) => status,
+    };
+    let _ = sender.try_send(Frame::trailers(trailers(&status)));
+    respond(receiver)
+}
+
+fn respond(frames: mpsc::Receiver<Frame<Bytes>>) -> Response {
+    ([(header::CONTENT_TYPE, "application/grpc")], Body::new(Frames(frames))).into_response()
+}
+
+// A body of whatever frames are sent to it, trailers included, which axum's own bodies can't end with.
+struct Frames(mpsc::Receiver<Frame<Bytes>>);
+
+impl hyper::body::Body for Frames {
+    type Data = Bytes;
+    type Error = Infallible;
+
+    fn poll_frame(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Frame<Bytes>, Infallible>>> {
+        self.0.poll_recv(cx).map(|frame| frame.map(Ok))
+    }
+}
+
+#[derive(Default)]
+struct Encoder(Vec<u8>);
+
+impl Encoder {
+    fn varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.0.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        self.0.push(value as u8);
+    }
+
+    fn key(&mut self, field: u32, wire_type: u8) {
+        self.varint(u64::from(field << 3 | u32::from(wire_type)));
+    }
+
+    fn uint(&mut self, field: u32, value: u64) {
+        self.key(field, VARINT);
+        self.varint(value);
+    }
+
+    fn bytes(&mut self, field: u32, value: &[u8]) {
+        self.key(field, LEN);
+        self.varint(value.len() as u64);
+        self.0.extend_from_slice(value);
+    }
+
+    // Left out when empty, as proto3 does for strings that aren't optional.
+    fn string(&mut self, field: u32, value: &str) {
+        if !value.is_empty() {
+            self.bytes(field, value.as_bytes());
+        }
+    }
+}
+
+enum Value<'a> {
+    Varint(u64),
+    Len(&'a [u8]),
+    // Fixed-width numbers, which none of our messages have, but which unknown fields may be.
+    Fixed,
+}
+
+// The fields of a message, in the order they were sent.
+struct Fields<'a>(&'a [u8]);
+
+impl<'a> Fields<'a> {
+    fn new(message: &'a [u8]) -> Fields<'a> {
+        Fields(message)
+    }
+
+    fn varint(&mut self) -> Result<u64, Status> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let (&byte, rest) = self.0.split_first().ok_or_else(|| Status::invalid("The message ends part way through a number"))?;
+            self.0 = rest;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte < 0x80 {
+                return Ok(value);
+            }
+        }
+        Err(Status::invalid("The message has a number longer than 64 bits"))
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Status> {
+        if len > self.0.len() {
+            return Err(Status::invalid("The message ends part way through a field"));
+        }
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn field(&mut self) -> Result<(u32, Value<'a>), Status> {
+        let key = self.varint()?;
+        let field = u32::try_from(key >> 3).map_err(|_| Status::invalid("The message has a field number out of range"))?;
+        let value = match (key & 7) as u8 {
+            VARINT => Value::Varint(self.varint()?),
+            FIXED64 => self.take(8).map(|_| Value::Fixed)?,
+            LEN => {
+                let len = self.varint()?;
+                Value::Len(self.take(usize::try_from(len).unwrap_or(usize::MAX))?)
+            },
+            FIXED32 => self.take(4).map(|_| Value::Fixed)?,
+            other => return Err(Status::invalid(format!("The message has a field of wire type {}, which isn't supported", other))),
+        };
+        Ok((field, value))
+    }
+}
+
+impl<'a> Iterator for Fields<'a> {
+    type Item = Result<(u32, Value<'a>), Status>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.0.is_empty() {
+            return None;
+        }
+        let field = self.field();
+        if field.is_err() {
+            // Nothing after a bad field can be read.
+            self.0 = &[];
+        }
+        Some(field)
+    }
+}
+
+fn as_string(value: &[u8], name: &str) -> Result<String, Status> {
+    String::from_utf8(value.to_vec()).map_err(|_| Status::invalid(format!("{} isn't UTF-8", name)))
+}
+
+fn as_u16(value: u64, name: &str) -> Result<u16, Status> {
+    u16::try_from(value).map_err(|_| Status::invalid(format!("{} is out of range", name)))
+}
+
+fn wrong_type(message: &str, field: u32) -> Status {
+    Status::invalid(format!("Field {} of {} has the wrong type", field, message))
+}
+
+// GetMovieRequest and DeleteMovieRequest, which only have the id.
+fn decode_id(message: &[u8]) -> Result<String, Status> {
+    let mut id = String::new();
+    for field in Fields::new(message) {
+        match field? {
+            (1, Value::Len(value)) => id = as_string(value, "id")?,
+            (1, _) => return Err(wrong_type("the request", 1)),
+            _ => {},
+        }
+    }
+    Ok(id)
+}
+
+// A Movie as sent to Put: what it says about the movie, and the version it replaces, if any.
+fn decode_movie(message: &[u8]) -> Result<(NewMovie, Option<u64>), Status> {
+    let mut movie = NewMovie { id: None, name: String::new(), year: 0, was_good: false, genres: Vec::new(), director: None, runtime_minutes: None, synopsis: None, poster_url: None, tags: Vec::new(), titles: BTreeMap::new() };
+    let mut version = None;
+    for field in Fields::new(message) {
+        match field? {
+            (1, Value::Len(value)) => movie.id = Some(as_string(value, "id")?).filter(|id| !id.is_empty()),
+            (2, Value::Len(value)) => movie.name = as_string(value, "name")?,
+            (3, Value::Varint(value)) => movie.year = as_u16(value, "year")?,
+            (4, Value::Varint(value)) => movie.was_good = value != 0,
+            (5, Value::Len(value)) => movie.genres.push(as_string(value, "genres")?),
+            (6, Value::Len(value)) => movie.director = Some(as_string(value, "director")?),
+            (7, Value::Varint(value)) => movie.runtime_minutes = Some(as_u16(value, "runtime_minutes")?),
+            (8, Value::Len(value)) => movie.synopsis = Some(as_string(value, "synopsis")?),
+            (9, Value::Len(value)) => movie.poster_url = Some(as_string(value, "poster_url")?),
+            (10, Value::Len(value)) => movie.tags.push(as_string(value, "tags")?),
+            // Each entry of a map is a message of its own, with the key as field 1 and the value as field 2.
+            (11, Value::Len(entry)) => {
+                let (mut language, mut title) = (String::new(), String::new());
+                for field in Fields::new(entry) {
+                    match field? {
+                        (1, Value::Len(value)) => language = as_string(value, "titles")?,
+                        (2, Value::Len(value)) => title = as_string(value, "titles")?,
+                        (number, _) => return Err(wrong_type("a titles entry", number)),
+                    }
+                }
+                movie.titles.insert(language, title);
+            },
+            (12, Value::Varint(value)) => version = Some(value),
+            // The timestamps are the server's to keep.
+            (13 | 14, Value::Len(_)) => {},
+            (number @ 1..=14, _) => return Err(wrong_type("Movie", number)),
+            _ => {},
+        }
+    }
+    Ok((movie, version))
+}
+
+fn encode_movie(movie: &Movie) -> Vec<u8> {
+    let mut encoder = Encoder::default();
+    encoder.string(1, &movie.id);
+    encoder.string(2, &movie.name);
+    if movie.year != 0 {
+        encoder.uint(3, movie.year.into());
+    }
+    if movie.was_good {
+        encoder.uint(4, 1);
+    }
+    for genre in &movie.genres {
+        encoder.bytes(5, genre.as_bytes());
+    }
+    if let Some(director) = &movie.director {
+        encoder.bytes(6, director.as_bytes());
+    }
+    if let Some(runtime) = movie.runtime_minutes {
+        encoder.uint(7, runtime.into());
+    }
+    if let Some(synopsis) = &movie.synopsis {
+        encoder.bytes(8, synopsis.as_bytes());
+    }
+    if let Some(poster_url) = &movie.poster_url {
+        encoder.bytes(9, poster_url.as_bytes());
+    }
+    for tag in &movie.tags {
+        encoder.bytes(10, tag.as_bytes());
+    }
+    for (language, title) in &movie.titles {
+        let mut entry = Encoder::default();
+        entry.bytes(1, language.as_bytes());
+        entry.bytes(2, title.as_bytes());
+        encoder.bytes(11, &entry.0);
+    }
+    encoder.uint(12, movie.version);
+    encoder.string(13, movie.created_at.as_deref().unwrap_or_default());
+    encoder.string(14, movie.updated_at.as_deref().unwrap_or_default());
+    encoder.0
+}
+
+fn encode_change(change: &MovieEvent) -> Vec<u8> {
+    let mut encoder = Encoder::default();
+    encoder.bytes(1, change.name().as_bytes());
+    encoder.bytes(2, &encode_movie(change.movie()));
+    encoder.0
+}
@@ -0,0 +1,554 @@
+// gzip (RFC 1952) around DEFLATE (RFC 1951), for compressing responses and reading compressed request bodies. Both
+// directions work a chunk at a time, so streamed bodies stay streamed.
+//
+// The encoder only uses the fixed Huffman codes and looks for repeats within each chunk, which gets JSON and CSV most
+// of the way to what gzip -6 does for a fraction of the code. The decoder handles everything DEFLATE allows.
+
+// Base lengths and extra bits of length codes 257..=285.
+const LENGTH_BASE: [u16; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+// Base distances and extra bits of distance codes 0..=29.
+const DISTANCE_BASE: [u16; 30] = [1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577];
+const DISTANCE_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+// The order code length code lengths come in, in a dynamic block header.
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+const WINDOW: usize = 32 * 1024;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+// How many earlier positions with the same hash the encoder tries before settling for the best match so far.
+const MAX_CHAIN: usize = 32;
+
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { 0xedb8_8320 ^ (crc >> 1) } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+fn crc32_update(crc: u32, bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!crc, |crc, byte| CRC_TABLE[((crc ^ *byte as u32) & 0xff) as usize] ^ (crc >> 8))
+}
+
+pub(crate) struct BitWriter {
+    out: Vec<u8>,
+    bits: u64,
+    count: u32,
+}
+
+impl BitWriter {
+    pub(crate) fn new() -> BitWriter {
+        BitWriter { out: Vec::new(), bits: 0, count: 0 }
+    }
+
+    pub(crate) fn write(&mut self, value: u32, count: u32) {
+        self.bits |= (value as u64) << self.count;
+        self.count += count;
+        while self.count >= 8 {
+            self.out.push(self.bits as u8);
+            self.bits >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    // Huffman codes go out most significant bit first, unlike everything else.
+    pub(crate) fn write_code(&mut self, code: u32, len: u32) {
+        self.write(code.reverse_bits() >> (32 - len), len);
+    }
+
+    pub(crate) fn align(&mut self) {
+        if self.count > 0 {
+            self.write(0, 8 - self.count);
+        }
+    }
+
+    pub(crate) fn take(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.out)
+    }
+}
+
+// The fixed literal/length code for a symbol, as (code, length).
+fn fixed_literal_code(symbol: u16) -> (u32, u32) {
+    match symbol {
+        0..=143 => (0x30 + symbol as u32, 8),
+        144..=255 => (0x190 + (symbol as u32 - 144), 9),
+        256..=279 => (symbol as u32 - 256, 7),
+        _ => (0xc0 + (symbol as u32 - 280), 8),
+    }
+}
+
+// What the encoders make of their input before coding it: a byte as it is, or a copy of `len` bytes from `distance`
+// back.
+pub(crate) enum Token {
+    Literal(u8),
+    Match { len: usize, distance: usize },
+}
+
+// Finds repeats of at least MIN_MATCH bytes within data, no further back than `window` and no longer than
+// `max_match`, and hands over the tokens in order.
+pub(crate) fn lz77(data: &[u8], window: usize, max_match: usize, mut emit: impl FnMut(Token)) {
+    const HASH_BITS: u32 = 15;
+    let hash = |i: usize| (((data[i] as u32) << 16 | (data[i + 1] as u32) << 8 | data[i + 2] as u32).wrapping_mul(2654435761) >> (32 - HASH_BITS)) as usize;
+    // Most recent position for each hash, and the one before each position with the same hash, plus one so 0 can
+    // mean none.
+    let mut head = vec![0usize; 1 << HASH_BITS];
+    let mut previous = vec![0usize; data.len()];
+    let insert = |i: usize, head: &mut [usize], previous: &mut [usize]| {
+        if i + MIN_MATCH <= data.len() {
+            previous[i] = head[hash(i)];
+            head[hash(i)] = i + 1;
+        }
+    };
+
+    let mut i = 0;
+    while i < data.len() {
+        let (mut best_len, mut best_distance) = (0, 0);
+        if i + MIN_MATCH <= data.len() {
+            let mut candidate = head[hash(i)];
+            let mut chain = 0;
+            while candidate > 0 && chain < MAX_CHAIN {
+                let start = candidate - 1;
+                if i - start > window {
+                    break;
+                }
+                let max = (data.len() - i).min(max_match);
+                let len = data[start..].iter().zip(&data[i..i + max]).take_while(|(a, b)| a == b).count();
+                if len > best_len {
+                    (best_len, best_distance) = (len, i - start);
+                    if len == max {
+                        break;
+                    }
+                }
+                candidate = previous[start];
+                chain += 1;
+            }
+        }
+        if best_len >= MIN_MATCH {
+            emit(Token::Match { len: best_len, distance: best_distance });
+            for position in i..i + best_len {
+                insert(position, &mut head, &mut previous);
+            }
+            i += best_len;
+        }
+        else {
+            emit(Token::Literal(data[i]));
+            insert(i, &mut head, &mut previous);
+            i += 1;
+        }
+    }
+}
+
+pub struct GzipEncoder {
+    writer: BitWriter,
+    crc: u32,
+    size: u32,
+    started: bool,
+}
+
+impl Default for GzipEncoder {
+    fn default() -> GzipEncoder {
+        GzipEncoder::new()
+    }
+}
+
+impl GzipEncoder {
+    pub fn new() -> GzipEncoder {
+        GzipEncoder { writer: BitWriter::new(), crc: 0, size: 0, started: false }
+    }
+
+    fn header(&mut self) {
+        if !self.started {
+            // Magic, deflate, no flags, no mtime, no extra flags, unknown OS.
+            self.writer.out.extend_from_slice(&[0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 0xff]);
+            self.started = true;
+        }
+    }
+
+    // Compresses one chunk and returns what can be sent so far. Each chunk ends on a byte boundary (a sync flush), so
+    // the client can decompress everything it has been sent.
+    pub fn chunk(&mut self, data: &[u8]) -> Vec<u8> {
+        self.header();
+        if !data.is_empty() {
+            self.crc = crc32_update(self.crc, data);
+            self.size = self.size.wrapping_add(data.len() as u32);
+            // A fixed Huffman block that isn't the last one.
+            self.writer.write(0, 1);
+            self.writer.write(1, 2);
+            self.compress(data);
+            // An empty stored block to get back to a byte boundary.
+            self.writer.write(0, 3);
+            self.writer.align();
+            self.writer.out.extend_from_slice(&[0, 0, 0xff, 0xff]);
+        }
+        self.writer.take()
+    }
+
+    // Ends the stream with an empty last block and the trailer.
+    pub fn finish(mut self) -> Vec<u8> {
+        self.header();
+        self.writer.write(1, 1);
+        self.writer.write(1, 2);
+        let (code, len) = fixed_literal_code(256);
+        self.writer.write_code(code, len);
+        self.writer.align();
+        self.writer.out.extend_from_slice(&self.crc.to_le_bytes());
+        self.writer.out.extend_from_slice(&self.size.to_le_bytes());
+        self.writer.take()
+    }
+
+    fn compress(&mut self, data: &[u8]) {
+        lz77(data, WINDOW, MAX_MATCH, |token| match token {
+            Token::Literal(byte) => {
+                let (code, len) = fixed_literal_code(byte as u16);
+                self.writer.write_code(code, len);
+            },
+            Token::Match { len, distance } => self.write_match(len, distance),
+        });
+        let (code, len) = fixed_literal_code(256);
+        self.writer.write_code(code, len);
+    }
+
+    fn write_match(&mut self, len: usize, distance: usize) {
+        let index = LENGTH_BASE.iter().rposition(|base| *base as usize <= len).unwrap();
+        let (code, code_len) = fixed_literal_code(257 + index as u16);
+        self.writer.write_code(code, code_len);
+        self.writer.write((len - LENGTH_BASE[index] as usize) as u32, LENGTH_EXTRA[index] as u32);
+        let index = DISTANCE_BASE.iter().rposition(|base| *base as usize <= distance).unwrap();
+        self.writer.write_code(index as u32, 5);
+        self.writer.write((distance - DISTANCE_BASE[index] as usize) as u32, DISTANCE_EXTRA[index] as u32);
+    }
+}
+
+// Why decoding stopped: either there's no more input yet, or the stream is broken.
+#[derive(Debug)]
+enum Stop {
+    NeedMore,
+    Invalid(&'static str),
+}
+
+impl From<&'static str> for Stop {
+    fn from(message: &'static str) -> Stop {
+        Stop::Invalid(message)
+    }
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    // In bits.
+    position: usize,
+}
+
+impl BitReader<'_> {
+    fn bits(&mut self, count: u32) -> Result<u32, Stop> {
+        let mut value = 0;
+        for i in 0..count {
+            let byte = *self.data.get(self.position / 8).ok_or(Stop::NeedMore)?;
+            value |= ((byte >> (self.position % 8)) as u32 & 1) << i;
+            self.position += 1;
+        }
+        Ok(value)
+    }
+
+    fn align(&mut self) {
+        self.position = self.position.div_ceil(8) * 8;
+    }
+
+    fn byte(&mut self) -> Result<u8, Stop> {
+        Ok(self.bits(8)? as u8)
+    }
+
+    fn u16_le(&mut self) -> Result<u16, Stop> {
+        Ok(self.bits(16)? as u16)
+    }
+
+    fn u32_le(&mut self) -> Result<u32, Stop> {
+        Ok(self.u16_le()? as u32 | (self.u16_le()? as u32) << 16)
+    }
+
+    // Reads a canonical Huffman code a bit at a time.
+    fn decode(&mut self, huffman: &Huffman) -> Result<u16, Stop> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for len in 1..16 {
+            code |= self.bits(1)? as i32;
+            let count = huffman.counts[len] as i32;
+            if code - first < count {
+                return Ok(huffman.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(Stop::Invalid("invalid Huffman code"))
+    }
+}
+
+#[derive(Clone)]
+struct Huffman {
+    // How many codes there are of each length.
+    counts: [u16; 16],
+    // Symbols ordered by code.
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Huffman, Stop> {
+        let mut counts = [0u16; 16];
+        for len in lengths {
+            counts[*len as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut left = 1i32;
+        for count in &counts[1..] {
+            left = (left << 1) - *count as i32;
+            if left < 0 {
+                return Err(Stop::Invalid("over-subscribed Huffman code"));
+            }
+        }
+        let mut offsets = [0u16; 16];
+        for len in 1..15 {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+        let mut symbols = vec![0u16; lengths.len()];
+        for (symbol, len) in lengths.iter().enumerate().filter(|(_, len)| **len != 0) {
+            symbols[offsets[*len as usize] as usize] = symbol as u16;
+            offsets[*len as usize] += 1;
+        }
+        Ok(Huffman { counts, symbols })
+    }
+
+    fn fixed() -> (Huffman, Huffman) {
+        let mut lengths = [8u8; 288];
+        lengths[144..256].fill(9);
+        lengths[256..280].fill(7);
+        (Huffman::new(&lengths).unwrap(), Huffman::new(&[5u8; 30]).unwrap())
+    }
+}
+
+enum State {
+    Header,
+    BlockHeader,
+    // Bytes left in a stored block.
+    Stored(u16),
+    Codes(Box<(Huffman, Huffman)>),
+    Trailer,
+}
+
+pub struct GzipDecoder {
+    // Input that hasn't been used up yet, and how many bits of its first byte have been.
+    input: Vec<u8>,
+    bit: usize,
+    state: State,
+    last_block: bool,
+    // The last WINDOW bytes of output at least, for matches to copy from.
+    window: Vec<u8>,
+    crc: u32,
+    size: u32,
+}
+
+impl Default for GzipDecoder {
+    fn default() -> GzipDecoder {
+        GzipDecoder::new()
+    }
+}
+
+impl GzipDecoder {
+    pub fn new() -> GzipDecoder {
+        GzipDecoder { input: Vec::new(), bit: 0, state: State::Header, last_block: false, window: Vec::new(), crc: 0, size: 0 }
+    }
+
+    // Decompresses as much as the input so far allows.
+    pub fn chunk(&mut self, data: &[u8]) -> Result<Vec<u8>, String> {
+        self.input.extend_from_slice(data);
+        let mut out = Vec::new();
+        let mut reader = BitReader { data: &self.input, position: self.bit };
+        loop {
+            // Each step either finishes or leaves the position where it was, to be tried again with more input.
+            let start = reader.position;
+            match Self::step(&mut self.state, &mut self.last_block, &mut reader, &mut self.window, &mut out, &mut self.crc, &mut self.size) {
+                Ok(()) => {},
+                Err(Stop::NeedMore) => {
+                    reader.position = start;
+                    break;
+                },
+                Err(Stop::Invalid(message)) => return Err(message.to_string()),
+            }
+        }
+        let consumed = reader.position / 8;
+        self.bit = reader.position % 8;
+        self.input.drain(..consumed);
+        if self.window.len() > 2 * WINDOW {
+            self.window.drain(..self.window.len() - WINDOW);
+        }
+        Ok(out)
+    }
+
+    // Checks the input ended where a gzip member does.
+    pub fn finish(&self) -> Result<(), String> {
+        match self.state {
+            State::Header if self.input.is_empty() => Ok(()),
+            _ => Err("gzip stream ended early".to_string()),
+        }
+    }
+
+    fn step(state: &mut State, last_block: &mut bool, reader: &mut BitReader, window: &mut Vec<u8>, out: &mut Vec<u8>, crc: &mut u32, size: &mut u32) -> Result<(), Stop> {
+        match state {
+            State::Header => {
+                if reader.byte()? != 0x1f || reader.byte()? != 0x8b {
+                    return Err("not gzip".into());
+                }
+                if reader.byte()? != 8 {
+                    return Err("unsupported gzip compression method".into());
+                }
+                let flags = reader.byte()?;
+                // mtime, extra flags and OS.
+                reader.bits(32)?;
+                reader.bits(16)?;
+                if flags & 0x04 != 0 {
+                    let len = reader.u16_le()?;
+                    for _ in 0..len {
+                        reader.byte()?;
+                    }
+                }
+                // The file name and comment are zero-terminated.
+                for flag in [0x08, 0x10] {
+                    if flags & flag != 0 {
+                        while reader.byte()? != 0 {}
+                    }
+                }
+                if flags & 0x02 != 0 {
+                    reader.u16_le()?;
+                }
+                *crc = 0;
+                *size = 0;
+                *state = State::BlockHeader;
+            },
+            State::BlockHeader => {
+                *last_block = reader.bits(1)? == 1;
+                *state = match reader.bits(2)? {
+                    0 => {
+                        reader.align();
+                        let len = reader.u16_le()?;
+                        if reader.u16_le()? != !len {
+                            return Err("corrupt stored block length".into());
+                        }
+                        State::Stored(len)
+                    },
+                    1 => State::Codes(Box::new(Huffman::fixed())),
+                    2 => State::Codes(Box::new(Self::dynamic_codes(reader)?)),
+                    _ => return Err("invalid block type".into()),
+                };
+            },
+            State::Stored(0) => *state = if *last_block { State::Trailer } else { State::BlockHeader },
+            State::Stored(left) => {
+                // Stored blocks start on a byte boundary, so their bytes can be copied as they are.
+                let start = reader.position / 8;
+                let available = (*left as usize).min(reader.data.len() - start);
+                if available == 0 {
+                    return Err(Stop::NeedMore);
+                }
+                Self::emit(&reader.data[start..start + available], window, out, crc, size);
+                reader.position += available * 8;
+                *left -= available as u16;
+            },
+            State::Codes(codes) => {
+                let (literals, distances) = codes.as_ref();
+                let symbol = reader.decode(literals)?;
+                match symbol {
+                    0..=255 => Self::emit(&[symbol as u8], window, out, crc, size),
+                    256 => *state = if *last_block { State::Trailer } else { State::BlockHeader },
+                    257..=285 => {
+                        let index = symbol as usize - 257;
+                        let len = LENGTH_BASE[index] as usize + reader.bits(LENGTH_EXTRA[index] as u32)? as usize;
+                        let index = reader.decode(distances)? as usize;
+                        if index >= DISTANCE_BASE.len() {
+                            return Err("invalid distance code".into());
+                        }
+                        let distance = DISTANCE_BASE[index] as usize + reader.bits(DISTANCE_EXTRA[index] as u32)? as usize;
+                        if distance > window.len() {
+                            return Err("distance goes back before the start of the stream".into());
+                        }
+                        let start = window.len() - distance;
+                        let copied: Vec<u8> = (0..len).map(|i| window[start + i % distance]).collect();
+                        Self::emit(&copied, window, out, crc, size);
+                    },
+                    _ => return Err("invalid literal/length code".into()),
+                }
+            },
+            State::Trailer => {
+                reader.align();
+                let (expected_crc, expected_size) = (reader.u32_le()?, reader.u32_le()?);
+                if expected_crc != *crc || expected_size != *size {
+                    return Err("gzip checksum doesn't match".into());
+                }
+                // Another member may follow, as `cat a.gz b.gz` makes.
+                *state = State::Header;
+            },
+        }
+        Ok(())
+    }
+
+    fn emit(bytes: &[u8], window: &mut Vec<u8>, out: &mut Vec<u8>, crc: &mut u32, size: &mut u32) {
+        window.extend_from_slice(bytes);
+        out.extend_from_slice(bytes);
+        *crc = crc32_update(*crc, bytes);
+        *size = size.wrapping_add(bytes.len() as u32);
+    }
+
+    fn dynamic_codes(reader: &mut BitReader) -> Result<(Huffman, Huffman), Stop> {
+        let literal_count = reader.bits(5)? as usize + 257;
+        let distance_count = reader.bits(5)? as usize + 1;
+        let code_length_count = reader.bits(4)? as usize + 4;
+        if literal_count > 286 || distance_count > 30 {
+            return Err("too many codes in dynamic block".into());
+        }
+        let mut code_lengths = [0u8; 19];
+        for position in &CODE_LENGTH_ORDER[..code_length_count] {
+            code_lengths[*position] = reader.bits(3)? as u8;
+        }
+        let code_length_code = Huffman::new(&code_lengths)?;
+
+        let mut lengths = Vec::with_capacity(literal_count + distance_count);
+        while lengths.len() < literal_count + distance_count {
+            let symbol = reader.decode(&code_length_code)?;
+            let (value, repeat) = match symbol {
+                0..=15 => (symbol as u8, 1),
+                16 => (*lengths.last().ok_or("repeat with no previous length")?, 3 + reader.bits(2)? as usize),
+                17 => (0, 3 + reader.bits(3)? as usize),
+                18 => (0, 11 + reader.bits(7)? as usize),
+                _ => return Err("invalid code length code".into()),
+            };
+            if lengths.len() + repeat > literal_count + distance_count {
+                return Err("code lengths run past the end".into());
+            }
+            lengths.extend(std::iter::repeat_n(value, repeat));
+        }
+        if lengths[256] == 0 {
+            return Err("no end of block code".into());
+        }
+        Ok((Huffman::new(&lengths[..literal_count])?, Huffman::new(&lengths[literal_count..])?))
+    }
+}
+
+// Compresses a whole body at once, for callers that have it all.
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    let mut encoder = GzipEncoder::new();
+    let mut out = encoder.chunk(data);
+    out.extend(encoder.finish());
+    out
+}
+
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut decoder = GzipDecoder::new();
+    let out = decoder.chunk(data)?;
+    decoder.finish()?;
+    Ok(out)
+}
@@ -0,0 +1,97 @@
+use std::{io, time::Duration};
+use tokio::{io::{AsyncReadExt, AsyncWriteExt}, net::TcpStream};
+
+// Responses bigger than this aren't something any caller here wants to read.
+const MAX_RESPONSE_BYTES: usize = 1024 * 1024;
+
+#[derive(Debug)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub body: Vec<u8>,
+}
+
+// Splits http://host[:port][/path] into the address to connect to, the Host header and the request target. Only
+// plain http is supported, there's no TLS in this build.
+pub(crate) fn parse_url(url: &str) -> io::Result<(String, String, String)> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidInput, format!("{}: {}", message, url));
+    let rest = url.strip_prefix("http://").ok_or_else(|| invalid("only http:// URLs are supported"))?;
+    let (authority, path) = match rest.find('/') {
+        Some(slash) => (&rest[..slash], &rest[slash..]),
+        None => (rest, "/"),
+    };
+    if authority.is_empty() {
+        return Err(invalid("URL has no host"));
+    }
+    let address = if authority.rsplit_once(':').is_some_and(|(_, port)| !port.contains(']')) {
+        authority.to_string()
+    }
+    else {
+        format!("{}:80", authority)
+    };
+    Ok((address, authority.to_string(), path.to_string()))
+}
+
+// Sends one request on a fresh connection and reads the whole response. Enough for the occasional outbound call
+// (exporting traces, delivering webhooks), not meant for anything chatty.
+pub async fn request(method: &str, url: &str, headers: &[(&str, &str)], body: &[u8], timeout: Duration) -> io::Result<HttpResponse> {
+    let (address, host, path) = parse_url(url)?;
+    tokio::time::timeout(timeout, async {
+        let mut stream = TcpStream::connect(&address).await?;
+        let mut head = format!("{} {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\nContent-Length: {}\r\n", method, path, host, body.len());
+        for (name, value) in headers {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        head.push_str("\r\n");
+        stream.write_all(head.as_bytes()).await?;
+        stream.write_all(body).await?;
+
+        let mut response = Vec::new();
+        let mut chunk = [0u8; 8192];
+        loop {
+            let read = stream.read(&mut chunk).await?;
+            if read == 0 {
+                break;
+            }
+            response.extend_from_slice(&chunk[..read]);
+            if response.len() > MAX_RESPONSE_BYTES {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "response too large"));
+            }
+        }
+        parse_response(&response)
+    }).await.map_err(|_| io::Error::new(io::ErrorKind::TimedOut, format!("no response from {} within {:?}", url, timeout)))?
+}
+
+pub async fn post(url: &str, content_type: &str, body: &[u8], timeout: Duration) -> io::Result<HttpResponse> {
+    request("POST", url, &[("Content-Type", content_type)], body, timeout).await
+}
+
+fn parse_response(response: &[u8]) -> io::Result<HttpResponse> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+    let mut headers = [httparse::EMPTY_HEADER; 64];
+    let mut parsed = httparse::Response::new(&mut headers);
+    let head_len = match parsed.parse(response).map_err(|e| invalid(format!("malformed response: {}", e)))? {
+        httparse::Status::Complete(len) => len,
+        httparse::Status::Partial => return Err(invalid("connection closed before the response headers ended".to_string())),
+    };
+    let status = parsed.code.unwrap_or(0);
+    let chunked = parsed.headers.iter()
+        .any(|header| header.name.eq_ignore_ascii_case("transfer-encoding") && header.value.eq_ignore_ascii_case(b"chunked"));
+    let body = &response[head_len..];
+    let body = if chunked { decode_chunked(body).ok_or_else(|| invalid("malformed chunked body".to_string()))? } else { body.to_vec() };
+    Ok(HttpResponse { status, body })
+}
+
+fn decode_chunked(mut body: &[u8]) -> Option<Vec<u8>> {
+    let mut decoded = Vec::new();
+    loop {
+        let line_end = body.windows(2).position(|window| window == b"\r\n")?;
+        let size_text = std::str::from_utf8(&body[..line_end]).ok()?;
+        let size = usize::from_str_radix(size_text.split(';').next()?.trim(), 16).ok()?;
+        body = &body[line_end + 2..];
+        if size == 0 {
+            return Some(decoded);
+        }
+        decoded.extend_from_slice(body.get(..size)?);
+        body = body.get(size + 2..)?;
+    }
+}
@@ -0,0 +1,169 @@
+use std::{collections::{HashMap, VecDeque}, sync::{LazyLock, Mutex}, time::{Duration, Instant}};
+use axum::{body::{self, Body, Bytes}, extract::Request, http::{HeaderMap, HeaderName, HeaderValue, StatusCode}, middleware::Next, response::{IntoResponse, Response}};
+use tracing::{debug, warn};
+
+use crate::auth::Caller;
+use crate::body_limit;
+use crate::error::ApiError;
+use crate::tenant;
+
+// Idempotency-Key on POSTs (draft-ietf-httpapi-idempotency-key-header). A client that retries a POST after a timeout
+// sends the same key again, and gets the response the first attempt produced instead of running it twice. Responses
+// are kept in memory for the configured window, so keys don't survive a restart.
+
+pub static IDEMPOTENCY_KEY_HEADER: HeaderName = HeaderName::from_static("idempotency-key");
+// Set on replayed responses, so a client can tell it didn't just create something.
+pub static REPLAYED_HEADER: HeaderName = HeaderName::from_static("idempotent-replayed");
+
+pub const DEFAULT_WINDOW: Duration = Duration::from_secs(24 * 60 * 60);
+
+const MAX_KEY_LEN: usize = 255;
+// When there are more keys than this the oldest ones go early, however long is left of their window.
+const MAX_ENTRIES: usize = 10_000;
+
+struct Recorded {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+struct Entry {
+    // Hash of the path, query and body, to catch a key being reused for a different request.
+    fingerprint: u64,
+    // None while the first request with the key is still running.
+    response: Option<Recorded>,
+    recorded_at: Instant,
+}
+
+struct Responses {
+    window: Duration,
+    entries: HashMap<String, Entry>,
+    // Keys in the order they were first seen, which is also the order they expire in.
+    order: VecDeque<(Instant, String)>,
+}
+
+impl Responses {
+    fn expire(&mut self, now: Instant) {
+        while let Some((recorded_at, key)) = self.order.front() {
+            let expired = now.duration_since(*recorded_at) >= self.window || self.order.len() > MAX_ENTRIES;
+            if !expired {
+                break;
+            }
+            // An entry whose key has been seen again since, after expiring, belongs to the newer queue slot.
+            if self.entries.get(key).is_some_and(|entry| entry.recorded_at == *recorded_at) {
+                self.entries.remove(key);
+            }
+            self.order.pop_front();
+        }
+    }
+}
+
+static RESPONSES: LazyLock<Mutex<Responses>> = LazyLock::new(|| Mutex::new(Responses {
+    window: DEFAULT_WINDOW,
+    entries: HashMap::new(),
+    order: VecDeque::new(),
+}));
+
+// How long responses are kept around for. Zero turns replaying off, keys are then ignored.
+pub fn set_window(window: Duration) {
+    let mut responses = RESPONSES.lock().unwrap();
+    responses.window = window;
+    responses.expire(Instant::now());
+}
+
+// Middleware for POST routes. Requests without an Idempotency-Key go straight through. The first request with a key
+// runs as usual and its response is recorded, unless it's a 5xx, which the client should be free to retry. Repeats of
+// it get the recorded response, 409 while the first is still running, or 422 if they aren't the same request.
+pub async fn replay_responses(request: Request, next: Next) -> Response {
+    let Some(key) = request.headers().get(&IDEMPOTENCY_KEY_HEADER) else {
+        return next.run(request).await;
+    };
+    let key = match key.to_str() {
+        Ok(key) if !key.is_empty() && key.len() <= MAX_KEY_LEN && key.bytes().all(|byte| byte.is_ascii_graphic()) => key.to_string(),
+        _ => return ApiError::BadRequest(format!("Idempotency-Key must be 1 to {} visible ASCII characters", MAX_KEY_LEN)).into_response(),
+    };
+    if RESPONSES.lock().unwrap().window.is_zero() {
+        return next.run(request).await;
+    }
+
+    let (parts, body) = request.into_parts();
+    // Already no bigger than --max-body-bytes, see body_limit.rs.
+    let body = match body::to_bytes(body, usize::MAX).await {
+        Ok(body) => body,
+        Err(e) => return body_limit::read_error(e).into_response(),
+    };
+    let target = parts.uri.path_and_query().map_or("", |target| target.as_str());
+    let fingerprint = fnv1a(target.bytes().chain([0]).chain(body.iter().copied()));
+    // Clients pick their keys without knowing each other's, so each caller has keys of its own, and each tenant too. A
+    // key reused by someone else is a new request, not a way to read the response they got.
+    let actor = parts.extensions.get::<Caller>().map_or_else(|| "anonymous".to_string(), Caller::actor);
+    let slot = tenant::namespaced(&format!("{} {}", actor, key));
+
+    let now = Instant::now();
+    {
+        let mut responses = RESPONSES.lock().unwrap();
+        responses.expire(now);
+        match responses.entries.get(&slot) {
+            Some(entry) if entry.fingerprint != fingerprint => {
+                return ApiError::IdempotencyKeyReused(key).into_response();
+            },
+            Some(Entry { response: None, .. }) => {
+                return ApiError::RequestInProgress(key).into_response();
+            },
+            Some(Entry { response: Some(recorded), .. }) => {
+                debug!("Replaying the response to Idempotency-Key {:?}", key);
+                let mut response = (recorded.status, recorded.headers.clone(), recorded.body.clone()).into_response();
+                response.headers_mut().insert(REPLAYED_HEADER.clone(), HeaderValue::from_static("true"));
+                return response;
+            },
+            None => {
+                responses.entries.insert(slot.clone(), Entry { fingerprint, response: None, recorded_at: now });
+                responses.order.push_back((now, slot.clone()));
+            },
+        }
+    }
+
+    // Forget the key unless a response gets recorded, e.g. when the client goes away before this finishes, or a retry
+    // would get 409 until the key expired.
+    let mut pending = Pending { key: slot, started: now, recorded: false };
+    let response = next.run(Request::from_parts(parts, Body::from(body))).await;
+    if response.status().is_server_error() {
+        return response;
+    }
+    let (parts, body) = response.into_parts();
+    let body = match body::to_bytes(body, usize::MAX).await {
+        Ok(body) => body,
+        Err(e) => {
+            warn!("Failed to read the response to record for Idempotency-Key {:?}: {}", key, e);
+            return ApiError::Internal(format!("Failed to read the response body: {}", e)).into_response();
+        },
+    };
+    if let Some(entry) = RESPONSES.lock().unwrap().entries.get_mut(&pending.key).filter(|entry| entry.recorded_at == now) {
+        entry.response = Some(Recorded { status: parts.status, headers: parts.headers.clone(), body: body.clone() });
+        pending.recorded = true;
+    }
+    Response::from_parts(parts, Body::from(body))
+}
+
+struct Pending {
+    key: String,
+    started: Instant,
+    recorded: bool,
+}
+
+impl Drop for Pending {
+    fn drop(&mut self) {
+        if self.recorded {
+            return;
+        }
+        let mut responses = RESPONSES.lock().unwrap();
+        if responses.entries.get(&self.key).is_some_and(|entry| entry.recorded_at == self.started) {
+            responses.entries.remove(&self.key);
+        }
+    }
+}
+
+// 64-bit FNV-1a, the same hash as Movie::etag.
+fn fnv1a(bytes: impl Iterator<Item = u8>) -> u64 {
+    bytes.fold(0xcbf29ce484222325u64, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+}
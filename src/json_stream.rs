@@ -0,0 +1,191 @@
+// Splits a stream of JSON into its records as it arrives, for imports too big to parse in one go: either one array of
+// them, or NDJSON with one per line. Only the record being read is held in memory, the others are handed on as soon as
+// they're complete. The records aren't parsed here, only found, so one bad record doesn't stop the ones after it.
+
+// Records longer than this are rejected rather than buffered, whatever they contain.
+const MAX_RECORD_BYTES: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Framing {
+    // [{...}, {...}], with any whitespace between.
+    Array,
+    // {...}\n{...}\n, with blank lines skipped.
+    Lines,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+    // Before the array's [.
+    Start,
+    // After the [ or a comma, waiting for a record.
+    BeforeRecord,
+    InRecord,
+    // After the ], where only whitespace may follow.
+    End,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct JsonRecord {
+    // Line the record starts on, counting from 1.
+    pub line: usize,
+    // The record's JSON, or why it was skipped.
+    pub json: Result<Vec<u8>, String>,
+}
+
+#[derive(Debug)]
+pub struct JsonReader {
+    framing: Framing,
+    state: State,
+    record: Vec<u8>,
+    too_long: bool,
+    // Brackets and braces open in the record, and where in a string it is, to tell the commas between records apart
+    // from the ones inside them.
+    depth: usize,
+    in_string: bool,
+    escaped: bool,
+    // Whether the array has had a record, so [] can be told apart from [1,].
+    had_record: bool,
+    line: usize,
+    record_line: usize,
+    error: Option<String>,
+}
+
+impl JsonReader {
+    pub fn new(framing: Framing) -> JsonReader {
+        JsonReader {
+            framing,
+            state: if framing == Framing::Array { State::Start } else { State::BeforeRecord },
+            record: Vec::new(),
+            too_long: false,
+            depth: 0,
+            in_string: false,
+            escaped: false,
+            had_record: false,
+            line: 1,
+            record_line: 1,
+            error: None,
+        }
+    }
+
+    // The records completed by this chunk of input. Chunks can be split anywhere, even inside a UTF-8 character.
+    // Nothing more is read once the input can't be an array, see error.
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<JsonRecord> {
+        let mut records = Vec::new();
+        for &byte in bytes {
+            if self.error.is_some() {
+                break;
+            }
+            records.extend(self.push(byte));
+            if byte == b'\n' {
+                self.line += 1;
+            }
+        }
+        records
+    }
+
+    // Why the input isn't an array of records, once it's clear that it isn't. The records before that point have
+    // already been handed on.
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    // The last record, for NDJSON that doesn't end with a line ending, or why the input ended too soon.
+    pub fn finish(&mut self) -> Result<Option<JsonRecord>, String> {
+        if let Some(error) = &self.error {
+            return Err(error.clone());
+        }
+        match (self.framing, self.state) {
+            (Framing::Lines, State::InRecord) => Ok(Some(self.end_record())),
+            (Framing::Lines, _) | (Framing::Array, State::End) => Ok(None),
+            (Framing::Array, State::Start) => Err("The body is empty, expected a JSON array".to_string()),
+            (Framing::Array, _) => Err(format!("Line {}: the array is never closed", self.line)),
+        }
+    }
+
+    fn push(&mut self, byte: u8) -> Option<JsonRecord> {
+        match (self.framing, self.state) {
+            (_, State::Start) => match byte {
+                b'[' => self.state = State::BeforeRecord,
+                byte if byte.is_ascii_whitespace() => {},
+                _ => self.fail("expected a JSON array"),
+            },
+            (Framing::Array, State::BeforeRecord) => match byte {
+                b']' if !self.had_record => self.state = State::End,
+                b']' | b',' => self.fail("expected a record"),
+                byte if byte.is_ascii_whitespace() => {},
+                byte => self.start_record(byte),
+            },
+            (Framing::Lines, State::BeforeRecord) => {
+                if !byte.is_ascii_whitespace() {
+                    self.start_record(byte);
+                }
+            },
+            (Framing::Array, State::InRecord) => {
+                if self.in_string {
+                    self.in_string = self.escaped || byte != b'"';
+                    self.escaped = !self.escaped && byte == b'\\';
+                }
+                else {
+                    match byte {
+                        b'"' => self.in_string = true,
+                        b'{' | b'[' => self.depth += 1,
+                        b'}' | b']' if self.depth > 0 => self.depth -= 1,
+                        b',' | b']' if self.depth == 0 => {
+                            self.state = if byte == b',' { State::BeforeRecord } else { State::End };
+                            return Some(self.end_record());
+                        },
+                        _ => {},
+                    }
+                }
+                self.push_byte(byte);
+            },
+            (Framing::Lines, State::InRecord) => {
+                if byte == b'\n' {
+                    self.state = State::BeforeRecord;
+                    return Some(self.end_record());
+                }
+                self.push_byte(byte);
+            },
+            (_, State::End) => {
+                if !byte.is_ascii_whitespace() {
+                    self.fail("nothing may follow the array");
+                }
+            },
+        }
+        None
+    }
+
+    fn start_record(&mut self, byte: u8) {
+        self.state = State::InRecord;
+        self.record_line = self.line;
+        self.had_record = true;
+        self.depth = 0;
+        self.in_string = false;
+        self.escaped = false;
+        // The record's first byte goes through the same checks as the rest.
+        self.push(byte);
+    }
+
+    fn push_byte(&mut self, byte: u8) {
+        if self.record.len() >= MAX_RECORD_BYTES {
+            self.too_long = true;
+            return;
+        }
+        self.record.push(byte);
+    }
+
+    fn end_record(&mut self) -> JsonRecord {
+        let record = std::mem::take(&mut self.record);
+        let json = if std::mem::take(&mut self.too_long) {
+            Err(format!("Record is longer than {} bytes", MAX_RECORD_BYTES))
+        }
+        else {
+            Ok(record.trim_ascii_end().to_vec())
+        };
+        JsonRecord { line: self.record_line, json }
+    }
+
+    fn fail(&mut self, problem: &str) {
+        self.error = Some(format!("Line {}: {}", self.line, problem));
+    }
+}
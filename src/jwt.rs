@@ -0,0 +1,130 @@
+use std::{fmt, time::{SystemTime, UNIX_EPOCH}};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::crypto::{self, RsaPublicKey};
+
+// Checks bearer tokens (RFC 7519) signed by an identity provider we trust. Tokens are only ever checked here, the
+// service never issues any.
+
+// How far our clock and the issuer's may disagree before exp and nbf start to matter.
+const LEEWAY_SECS: u64 = 60;
+
+#[derive(Clone, PartialEq)]
+pub enum JwtKey {
+    // Shared secret for HS256.
+    Hs256(Vec<u8>),
+    // The issuer's public key for RS256.
+    Rs256(RsaPublicKey),
+}
+
+// Keeps the secret out of logs.
+impl fmt::Debug for JwtKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JwtKey::Hs256(_) => f.write_str("Hs256(..)"),
+            JwtKey::Rs256(_) => f.write_str("Rs256(..)"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct JwtConfig {
+    pub key: JwtKey,
+    // When set, tokens have to have this iss.
+    pub issuer: Option<String>,
+    // When set, tokens have to list this in aud.
+    pub audience: Option<String>,
+}
+
+// What we use from a valid token.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Claims {
+    pub subject: Option<String>,
+    // From a "roles" array, or a single "role".
+    pub roles: Vec<String>,
+    // For ID tokens, the value the login that asked for it sent.
+    pub nonce: Option<String>,
+    pub expires_at: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Header {
+    pub alg: String,
+    // Which of the issuer's keys signed the token, when it has several.
+    pub kid: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Payload {
+    sub: Option<String>,
+    iss: Option<String>,
+    #[serde(default)]
+    aud: Option<Value>,
+    // NumericDates, which may have a fraction.
+    exp: Option<f64>,
+    nbf: Option<f64>,
+    #[serde(default)]
+    roles: Vec<String>,
+    role: Option<String>,
+    nonce: Option<String>,
+}
+
+// Reads a token's header without checking anything, to find out which key to check it with.
+pub fn header(token: &str) -> Result<Header, String> {
+    let header = token.split('.').next().unwrap_or_default();
+    let header = crypto::base64_decode(header).ok_or_else(|| "token isn't base64url".to_string())?;
+    serde_json::from_slice(&header).map_err(|e| format!("bad header: {}", e))
+}
+
+// Checks the signature and the registered claims, and returns the rest. The error says why the token was refused.
+pub fn validate(config: &JwtConfig, token: &str) -> Result<Claims, String> {
+    let mut parts = token.split('.');
+    let (Some(header), Some(payload), Some(signature), None) = (parts.next(), parts.next(), parts.next(), parts.next()) else {
+        return Err("expected header.payload.signature".to_string());
+    };
+    let decode = |part: &str| crypto::base64_decode(part).ok_or_else(|| "token isn't base64url".to_string());
+    let header: Header = serde_json::from_slice(&decode(header)?).map_err(|e| format!("bad header: {}", e))?;
+    let signed = &token[..token.len() - signature.len() - 1];
+    let signature = decode(signature)?;
+    // The algorithm has to be the one for our key, otherwise a token could pick one we'd check it badly with.
+    let valid = match (&config.key, header.alg.as_str()) {
+        (JwtKey::Hs256(secret), "HS256") => crypto::constant_time_eq(&crypto::hmac_sha256(secret, signed.as_bytes()), &signature),
+        (JwtKey::Rs256(key), "RS256") => key.verify_sha256(signed.as_bytes(), &signature),
+        (_, alg) => return Err(format!("{} tokens aren't accepted", alg)),
+    };
+    if !valid {
+        return Err("bad signature".to_string());
+    }
+
+    let payload: Payload = serde_json::from_slice(&decode(payload)?).map_err(|e| format!("bad claims: {}", e))?;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |now| now.as_secs());
+    // Whole seconds are all that matter. Casting saturates, so dates before 1970 are 0 and those too far off to count
+    // are u64::MAX, and adding the leeway does the same rather than overflow.
+    let exp = payload.exp.map(|exp| exp.floor() as u64);
+    let nbf = payload.nbf.map(|nbf| nbf.floor() as u64);
+    if exp.is_some_and(|exp| exp.saturating_add(LEEWAY_SECS) <= now) {
+        return Err("token has expired".to_string());
+    }
+    if nbf.is_some_and(|nbf| nbf > now.saturating_add(LEEWAY_SECS)) {
+        return Err("token isn't valid yet".to_string());
+    }
+    if let Some(issuer) = &config.issuer
+        && payload.iss.as_ref() != Some(issuer) {
+        return Err(format!("token wasn't issued by {}", issuer));
+    }
+    if let Some(audience) = &config.audience {
+        let listed = match &payload.aud {
+            Some(Value::String(aud)) => aud == audience,
+            Some(Value::Array(auds)) => auds.iter().any(|aud| aud.as_str() == Some(audience)),
+            _ => false,
+        };
+        if !listed {
+            return Err(format!("token isn't meant for {}", audience));
+        }
+    }
+
+    let mut roles = payload.roles;
+    roles.extend(payload.role);
+    Ok(Claims { subject: payload.sub, roles, nonce: payload.nonce, expires_at: exp })
+}
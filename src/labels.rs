@@ -0,0 +1,93 @@
+use std::collections::BTreeMap;
+use serde::Serialize;
+
+use crate::model::Movie;
+
+// Genres and tags are both labels on movies, matched ignoring case, that exist for as long as some movie has them.
+// Neither is stored on its own: the /genres and /tags routes work by changing the movies. Stores keep a LabelIndex of
+// each up to date as they write, so listing them, or the movies with one, is a lookup instead of a pass over every
+// movie.
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Label {
+    // As the movie with the lowest id spells it.
+    pub name: String,
+    pub movie_count: usize,
+}
+
+#[derive(Debug)]
+pub struct LabelIndex {
+    // Which of the movie's labels this indexes.
+    of: fn(&Movie) -> &[String],
+    // Lowercased label to the ids of the movies that have it, each with the label as that movie spells it.
+    labels: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+impl LabelIndex {
+    pub fn genres<'a>(movies: impl IntoIterator<Item = &'a Movie>) -> LabelIndex {
+        LabelIndex::new(|movie| &movie.genres, movies)
+    }
+
+    pub fn tags<'a>(movies: impl IntoIterator<Item = &'a Movie>) -> LabelIndex {
+        LabelIndex::new(|movie| &movie.tags, movies)
+    }
+
+    fn new<'a>(of: fn(&Movie) -> &[String], movies: impl IntoIterator<Item = &'a Movie>) -> LabelIndex {
+        let mut index = LabelIndex { of, labels: BTreeMap::new() };
+        for movie in movies {
+            index.insert(movie);
+        }
+        index
+    }
+
+    pub fn insert(&mut self, movie: &Movie) {
+        for label in (self.of)(movie) {
+            self.labels.entry(label.to_lowercase()).or_default().insert(movie.id.clone(), label.clone());
+        }
+    }
+
+    pub fn remove(&mut self, movie: &Movie) {
+        for label in (self.of)(movie) {
+            let key = label.to_lowercase();
+            if let Some(ids) = self.labels.get_mut(&key) {
+                ids.remove(&movie.id);
+                if ids.is_empty() {
+                    self.labels.remove(&key);
+                }
+            }
+        }
+    }
+
+    // Replaces what was indexed for `old` with `new`, either of which may be missing for creates and deletes.
+    pub fn replace(&mut self, old: Option<&Movie>, new: Option<&Movie>) {
+        if let Some(old) = old {
+            self.remove(old);
+        }
+        if let Some(new) = new {
+            self.insert(new);
+        }
+    }
+
+    // Every label some movie has, in case-insensitive alphabetical order.
+    pub fn labels(&self) -> Vec<Label> {
+        self.labels.values().map(label).collect()
+    }
+
+    // The ids of the movies with the label, ignoring case, in id order.
+    pub fn movie_ids(&self, name: &str) -> Vec<String> {
+        self.labels.get(&name.to_lowercase()).map_or_else(Vec::new, |ids| ids.keys().cloned().collect())
+    }
+}
+
+// Never called with an empty map, those are removed along with their last movie.
+fn label(ids: &BTreeMap<String, String>) -> Label {
+    Label { name: ids.values().next().cloned().unwrap_or_default(), movie_count: ids.len() }
+}
+
+// Percent-encodes a label for use as one segment of a path, as in the links to /genres/{genre}.
+pub fn path_segment(label: &str) -> String {
+    label.bytes().map(|byte| match byte {
+        b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (byte as char).to_string(),
+        _ => format!("%{:02X}", byte),
+    }).collect()
+}
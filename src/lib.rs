@@ -0,0 +1,92 @@
+pub mod admin_ui;
+pub mod audit;
+pub mod auth;
+pub mod backup;
+pub mod body_limit;
+pub mod brotli;
+pub mod cache;
+pub mod changes;
+#[cfg(feature = "client")]
+pub mod client;
+pub mod compression;
+pub mod config;
+pub mod content;
+pub mod cors;
+pub mod crypto;
+pub mod csv;
+pub mod cursor;
+pub mod duplicates;
+pub mod enrich;
+pub mod error;
+pub mod event_sourced;
+pub mod events;
+pub mod fallback;
+pub mod graphql;
+pub mod grpc;
+pub mod gzip;
+pub mod http_client;
+pub mod idempotency;
+pub mod json_stream;
+pub mod jwt;
+pub mod labels;
+pub mod listener;
+pub mod locale;
+pub mod load_shed;
+pub mod load_test;
+pub mod metrics;
+pub mod model;
+pub mod oidc;
+pub mod openapi;
+pub mod otel;
+pub mod posters;
+#[cfg(feature = "postgres")]
+pub mod postgres;
+pub mod publish;
+pub mod quota;
+pub mod random;
+pub mod recommend;
+pub mod rate_limit;
+pub mod replication;
+pub mod request_id;
+pub mod resilience;
+pub mod routes;
+pub mod s3_backup;
+pub mod schema;
+pub mod search;
+pub mod seed;
+pub mod settings;
+pub mod shutdown;
+pub mod similar;
+pub mod snapshot;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod state;
+pub mod sort;
+pub mod stats;
+pub mod store;
+pub mod suggest;
+pub mod telemetry;
+pub mod tenant;
+pub mod timeout;
+pub mod title;
+#[cfg(feature = "tls")]
+pub mod tls;
+pub mod trash;
+pub mod validation;
+pub mod versioning;
+pub mod wal;
+pub mod webhooks;
+pub mod websocket;
+
+pub use routes::build_router;
+
+// The whole API over an empty in-memory store, with nothing configured, for tests to send requests to with
+// tower::ServiceExt::oneshot.
+pub fn test_app() -> axum::Router {
+    test_app_with(settings::Settings::default())
+}
+
+// test_app with settings like API keys, which the test can go on changing through its clone of them.
+pub fn test_app_with(settings: settings::Settings) -> axum::Router {
+    build_router(state::state_init(), settings)
+}
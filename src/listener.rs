@@ -0,0 +1,261 @@
+#[cfg(feature = "tls")]
+use std::sync::Arc;
+use std::{fmt, future::Future, io, net::SocketAddr, os::unix::fs::FileTypeExt, path::{Path, PathBuf}, pin::Pin, str::FromStr, time::Duration};
+use axum::{body::Body, extract::{ConnectInfo, Request}, Router};
+use hyper::{body::Incoming, server::conn::http1};
+use hyper_util::{rt::{TokioExecutor, TokioIo, TokioTimer}, server::conn::auto};
+#[cfg(feature = "tls")]
+use tokio::net::TcpStream;
+use tokio::{net::{TcpListener, UnixListener}, sync::watch};
+use tower_service::Service;
+use tracing::{debug, warn};
+
+#[cfg(feature = "tls")]
+use crate::tls::{TlsAcceptor, TlsStream};
+
+// Where the server accepts connections: a TCP address, or a Unix socket for a reverse proxy on the same machine.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListenAddr {
+    Tcp(SocketAddr),
+    Unix(PathBuf),
+}
+
+impl FromStr for ListenAddr {
+    type Err = String;
+
+    // unix:<path>, or host:port with an optional tcp: in front.
+    fn from_str(value: &str) -> Result<ListenAddr, String> {
+        match value.strip_prefix("unix:") {
+            Some("") => Err("unix: needs a socket path".to_string()),
+            Some(path) => Ok(ListenAddr::Unix(PathBuf::from(path))),
+            None => {
+                let address = value.strip_prefix("tcp:").unwrap_or(value);
+                address.parse().map(ListenAddr::Tcp).map_err(|_| format!("expected host:port or unix:<path>, got {:?}", value))
+            },
+        }
+    }
+}
+
+impl fmt::Display for ListenAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ListenAddr::Tcp(address) => write!(f, "{}", address),
+            ListenAddr::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+pub const DEFAULT_MAX_CONCURRENT_STREAMS: u32 = 200;
+pub const DEFAULT_KEEP_ALIVE_TIMEOUT: Duration = Duration::from_secs(30);
+// hyper can't read a request into anything smaller.
+pub const MIN_HEADER_BYTES: usize = 8192;
+
+// How connections are handled, for tuning the server to clients that keep many requests going at once.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HttpConfig {
+    // Whether clients may speak HTTP/2 as well as HTTP/1.1: over TLS if they ask for it with ALPN, and otherwise with
+    // prior knowledge.
+    pub http2: bool,
+    pub max_concurrent_streams: u32,
+    // Whether HTTP/1.1 connections are reused for further requests.
+    pub keep_alive: bool,
+    // How long an HTTP/1.1 connection may sit waiting for the next request's headers, and how often an idle HTTP/2
+    // connection is pinged to check the client is still there. Zero for no limit.
+    pub keep_alive_timeout: Duration,
+    // None for hyper's own limits, 16 KiB for HTTP/2 and around 400 KiB for HTTP/1.1.
+    pub max_header_bytes: Option<usize>,
+}
+
+impl Default for HttpConfig {
+    fn default() -> HttpConfig {
+        HttpConfig {
+            http2: true,
+            max_concurrent_streams: DEFAULT_MAX_CONCURRENT_STREAMS,
+            keep_alive: true,
+            keep_alive_timeout: DEFAULT_KEEP_ALIVE_TIMEOUT,
+            max_header_bytes: None,
+        }
+    }
+}
+
+impl HttpConfig {
+    fn keep_alive_timeout(&self) -> Option<Duration> {
+        Some(self.keep_alive_timeout).filter(|timeout| !timeout.is_zero())
+    }
+
+    // For HTTP/1.1 as well as HTTP/2, told apart by the first bytes the client sends.
+    fn auto_builder(&self) -> auto::Builder<TokioExecutor> {
+        let mut builder = auto::Builder::new(TokioExecutor::new());
+        let mut http1 = builder.http1();
+        http1.timer(TokioTimer::new()).keep_alive(self.keep_alive).header_read_timeout(self.keep_alive_timeout());
+        if let Some(max) = self.max_header_bytes {
+            http1.max_buf_size(max);
+        }
+        let mut http2 = builder.http2();
+        http2.timer(TokioTimer::new()).max_concurrent_streams(self.max_concurrent_streams).keep_alive_interval(self.keep_alive_timeout());
+        if let Some(max) = self.max_header_bytes {
+            http2.max_header_list_size(max.try_into().unwrap_or(u32::MAX));
+        }
+        builder
+    }
+
+    // auto::Builder::http1_only doesn't apply to connections that can be upgraded, so HTTP/1.1 alone needs its own.
+    fn http1_builder(&self) -> http1::Builder {
+        let mut builder = http1::Builder::new();
+        builder.timer(TokioTimer::new()).keep_alive(self.keep_alive).header_read_timeout(self.keep_alive_timeout());
+        if let Some(max) = self.max_header_bytes {
+            builder.max_buf_size(max);
+        }
+        builder
+    }
+}
+
+pub enum Listener {
+    Tcp(TcpListener),
+    // TCP, with every connection made TLS, see tls.rs.
+    #[cfg(feature = "tls")]
+    Tls(TcpListener, Arc<TlsAcceptor>),
+    Unix(UnixListener, PathBuf),
+}
+
+impl Listener {
+    pub async fn bind(address: &ListenAddr) -> io::Result<Listener> {
+        match address {
+            ListenAddr::Tcp(address) => Ok(Listener::Tcp(TcpListener::bind(address).await?)),
+            ListenAddr::Unix(path) => {
+                remove_stale_socket(path)?;
+                Ok(Listener::Unix(UnixListener::bind(path)?, path.clone()))
+            },
+        }
+    }
+
+    // Serves HTTPS rather than HTTP, if it's listening on TCP. Unix sockets are for a proxy on the same machine, which
+    // has no need of it.
+    #[cfg(feature = "tls")]
+    pub fn with_tls(self, acceptor: Arc<TlsAcceptor>) -> Listener {
+        match self {
+            Listener::Tcp(listener) => Listener::Tls(listener, acceptor),
+            other => other,
+        }
+    }
+
+    // Serves the router until `shutdown` resolves and the connections still open have finished. Unix socket clients
+    // have no address, so they all share the rate limit of an unknown client, which a proxy in front has to be anyway.
+    pub async fn serve(self, app: Router, http: HttpConfig, shutdown: impl Future<Output = ()>) {
+        match self {
+            Listener::Tcp(listener) => serve_connections(listener, app, &http, shutdown, Some).await,
+            #[cfg(feature = "tls")]
+            Listener::Tls(listener, acceptor) => serve_connections(TlsListener { listener, acceptor }, app, &http, shutdown, Some).await,
+            Listener::Unix(listener, path) => {
+                serve_connections(listener, app, &http, shutdown, |_| None).await;
+                if let Err(e) = std::fs::remove_file(&path) {
+                    warn!("Failed to remove {}: {}", path.display(), e);
+                }
+            },
+        }
+    }
+}
+
+// Starts TLS on each connection as it's accepted. The handshake itself happens as hyper first reads from it, on the
+// connection's own task, so a slow client doesn't hold up the others.
+#[cfg(feature = "tls")]
+struct TlsListener {
+    listener: TcpListener,
+    acceptor: Arc<TlsAcceptor>,
+}
+
+#[cfg(feature = "tls")]
+impl axum::serve::Listener for TlsListener {
+    type Io = TlsStream<TcpStream>;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (TlsStream<TcpStream>, SocketAddr) {
+        loop {
+            let (stream, address) = axum::serve::Listener::accept(&mut self.listener).await;
+            match self.acceptor.accept(stream) {
+                Ok(stream) => return (stream, address),
+                Err(e) => warn!("Failed to start TLS with {}: {}", address, e),
+            }
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+}
+
+// Rather than axum::serve, which has no way to tune the connections it makes.
+async fn serve_connections<L: axum::serve::Listener>(
+    mut listener: L,
+    app: Router,
+    http: &HttpConfig,
+    shutdown: impl Future<Output = ()>,
+    client_address: impl Fn(L::Addr) -> Option<SocketAddr>,
+) {
+    let auto_builder = http.auto_builder();
+    let http1_builder = http.http1_builder();
+    // Every connection holds a receiver, so the sender is closed once they've all finished.
+    let (closed, open) = watch::channel(());
+    // And every connection watches this one, which drops it once the server starts draining.
+    let (draining, _) = watch::channel(());
+    let mut shutdown = std::pin::pin!(shutdown);
+    loop {
+        let (io, address) = tokio::select! {
+            accepted = listener.accept() => accepted,
+            _ = &mut shutdown => break,
+        };
+        let address = client_address(address);
+        let app = app.clone();
+        let service = hyper::service::service_fn(move |request: Request<Incoming>| {
+            let mut request = request.map(Body::new);
+            if let Some(address) = address {
+                request.extensions_mut().insert(ConnectInfo(address));
+            }
+            app.clone().call(request)
+        });
+        let io = TokioIo::new(io);
+        let (open, draining) = (open.clone(), draining.subscribe());
+        if http.http2 {
+            let connection = auto_builder.serve_connection_with_upgrades(io, service).into_owned();
+            tokio::spawn(drive(connection, |connection| connection.graceful_shutdown(), draining, open, address));
+        }
+        else {
+            let connection = http1_builder.serve_connection(io, service).with_upgrades();
+            tokio::spawn(drive(connection, |connection| connection.graceful_shutdown(), draining, open, address));
+        }
+    }
+    drop(listener);
+    drop(draining);
+    drop(open);
+    closed.closed().await;
+}
+
+// Runs a connection to the end, letting the request in flight finish and then closing it once the server drains.
+async fn drive<C, E>(connection: C, graceful_shutdown: impl FnOnce(Pin<&mut C>), mut draining: watch::Receiver<()>, _open: watch::Receiver<()>, address: Option<SocketAddr>)
+where C: Future<Output = Result<(), E>>, E: fmt::Display {
+    let mut connection = std::pin::pin!(connection);
+    let result = tokio::select! {
+        result = connection.as_mut() => result,
+        _ = draining.changed() => {
+            graceful_shutdown(connection.as_mut());
+            connection.await
+        },
+    };
+    if let Err(e) = result {
+        debug!("Connection from {:?} ended with an error: {}", address, e);
+    }
+}
+
+// A socket file left behind by a server that didn't shut down cleanly would make the bind fail. Nothing answering on
+// it means it's stale, anything else at the path is left alone.
+fn remove_stale_socket(path: &Path) -> io::Result<()> {
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => {
+            if std::os::unix::net::UnixStream::connect(path).is_ok() {
+                return Err(io::Error::new(io::ErrorKind::AddrInUse, format!("another server is listening on {}", path.display())));
+            }
+            std::fs::remove_file(path)
+        },
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,36 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use axum::{extract::{Extension, MatchedPath, Request, State}, middleware::Next, response::{IntoResponse, Response}};
+use tracing::debug;
+
+use crate::error::ApiError;
+use crate::settings::Settings;
+
+// Caps how many requests are being handled at once. Past the cap new requests get a 503 straight away instead of
+// queueing for the store's lock behind everything else, so a client can back off or go to another instance while the
+// ones already in get answered in reasonable time. The cap and the count are the router's, see Settings.
+
+// Gives the request's slot back however it ends, including when the client goes away and its future is dropped.
+struct Slot<'a>(&'a AtomicUsize);
+
+impl Drop for Slot<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+// Middleware for the whole router. Routes in the state, like the liveness check, are always let through and not
+// counted, since failing those would get a busy but healthy instance restarted.
+pub async fn shed_load(State(exempt): State<&'static [&'static str]>, Extension(settings): Extension<Settings>, request: Request, next: Next) -> Response {
+    let route = request.extensions().get::<MatchedPath>().map_or(request.uri().path(), MatchedPath::as_str);
+    if exempt.contains(&route) {
+        return next.run(request).await;
+    }
+    let max = settings.max_in_flight();
+    let already = settings.in_flight_count().fetch_add(1, Ordering::Relaxed);
+    let _slot = Slot(settings.in_flight_count());
+    if max != 0 && already >= max {
+        debug!("Shedding {} {}, {} requests are already in flight", request.method(), route, max);
+        return ApiError::Unavailable(format!("The server is handling as many requests as it can ({}), try again shortly", max)).into_response();
+    }
+    next.run(request).await
+}
@@ -0,0 +1,267 @@
+use std::{collections::HashMap, fmt, sync::{Arc, Mutex}, time::Duration};
+use serde_json::json;
+use tokio::{sync::Semaphore, time::Instant};
+
+use crate::config::ConfigError;
+use crate::http_client;
+use crate::random;
+
+// `syndica-rust bench`: sends a steady rate of requests to a running instance, creating made-up movies and reading them
+// back, and reports how long they took. The rate is kept whether or not the server keeps up, and each request is
+// timed from when it was due rather than when it went out, so a slow server shows up as slow rather than as fewer
+// requests.
+
+const DEFAULT_RPS: u32 = 100;
+const DEFAULT_DURATION: Duration = Duration::from_secs(30);
+const DEFAULT_MAX_IN_FLIGHT: usize = 1000;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const GENRES: [&str; 6] = ["Drama", "Comedy", "Horror", "Western", "Thriller", "Animation"];
+const WORDS: [&str; 12] = ["Night", "Return", "Last", "City", "Silent", "Summer", "Ghost", "River", "Empire", "Lost", "Blue", "Storm"];
+
+struct Flag {
+    flag: &'static str,
+    help: &'static str,
+}
+
+const FLAGS: &[Flag] = &[
+    Flag { flag: "--target", help: "http:// URL of the instance to test, e.g. http://localhost:1234" },
+    Flag { flag: "--rps", help: "Requests to send a second [default: 100]" },
+    Flag { flag: "--duration-secs", help: "How long to keep sending them [default: 30]" },
+    Flag { flag: "--max-in-flight", help: "Requests waiting on a response before more are dropped rather than sent [default: 1000]" },
+    Flag { flag: "--api-key", help: "X-Api-Key to send, for instances where writes need one [default: none]" },
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoadTestConfig {
+    // Without a trailing slash.
+    pub target: String,
+    pub rps: u32,
+    pub duration: Duration,
+    pub max_in_flight: usize,
+    pub api_key: Option<String>,
+}
+
+impl LoadTestConfig {
+    // From the arguments after `bench`.
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<LoadTestConfig, ConfigError> {
+        let mut raw = HashMap::new();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if arg == "--help" || arg == "-h" {
+                return Err(ConfigError::Help(usage()));
+            }
+            let (flag, inline_value) = match arg.split_once('=') {
+                Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+                None => (arg, None),
+            };
+            let known = FLAGS.iter().find(|known| known.flag == flag)
+                .ok_or_else(|| ConfigError::Invalid(format!("unknown argument {:?}, see bench --help", flag)))?;
+            let value = match inline_value {
+                Some(value) => value,
+                None => args.next().ok_or_else(|| ConfigError::Invalid(format!("{} needs a value", flag)))?,
+            };
+            raw.insert(known.flag, value);
+        }
+
+        let target = raw.remove("--target").ok_or_else(|| ConfigError::Invalid("--target is required, see bench --help".to_string()))?;
+        if !target.starts_with("http://") {
+            return Err(ConfigError::Invalid(format!("--target: only http:// URLs are supported, got {:?}", target)));
+        }
+        let number = |flag: &str| raw.get(flag).map(|value| value.trim().parse::<u64>().map_err(|e| ConfigError::Invalid(format!("{}: can't parse {:?}: {}", flag, value, e)))).transpose();
+        let rps = number("--rps")?.map_or(Ok(DEFAULT_RPS), u32::try_from).map_err(|_| ConfigError::Invalid("--rps: too many".to_string()))?;
+        if rps == 0 {
+            return Err(ConfigError::Invalid("--rps: must be at least 1".to_string()));
+        }
+        let duration = number("--duration-secs")?.map_or(DEFAULT_DURATION, Duration::from_secs);
+        if duration.is_zero() {
+            return Err(ConfigError::Invalid("--duration-secs: must be at least 1".to_string()));
+        }
+        let max_in_flight = number("--max-in-flight")?.map_or(DEFAULT_MAX_IN_FLIGHT, |max| max as usize);
+        if max_in_flight == 0 {
+            return Err(ConfigError::Invalid("--max-in-flight: must be at least 1".to_string()));
+        }
+        Ok(LoadTestConfig { target: target.trim_end_matches('/').to_string(), rps, duration, max_in_flight, api_key: raw.remove("--api-key") })
+    }
+}
+
+fn usage() -> String {
+    let mut usage = String::from("Usage: syndica-rust bench --target <url> [OPTIONS]\n\nSends a steady rate of requests to a running instance and reports their latency.\n\nOptions:\n");
+    for flag in FLAGS {
+        usage.push_str(&format!("  {} <value>\n      {}\n", flag.flag, flag.help));
+    }
+    usage
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Operation {
+    // POST /v1/movie with a made-up movie.
+    Create,
+    // GET /v1/movie/{id} of one created earlier in the run.
+    Get,
+    // GET /v1/movies filtered by a genre.
+    List,
+}
+
+impl Operation {
+    fn name(self) -> &'static str {
+        match self {
+            Operation::Create => "create",
+            Operation::Get => "get",
+            Operation::List => "list",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    operation: Operation,
+    // None if no response came back at all.
+    status: Option<u16>,
+    latency: Duration,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Latencies {
+    pub requests: usize,
+    pub succeeded: usize,
+    pub client_errors: usize,
+    pub server_errors: usize,
+    // Connection errors and timeouts.
+    pub failed: usize,
+    pub p50: Duration,
+    pub p90: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+impl Latencies {
+    fn of<'a>(samples: impl Iterator<Item = &'a Sample>) -> Latencies {
+        let mut latencies = Latencies::default();
+        let mut times = Vec::new();
+        for sample in samples {
+            latencies.requests += 1;
+            match sample.status {
+                Some(200..=299) => latencies.succeeded += 1,
+                Some(400..=499) => latencies.client_errors += 1,
+                Some(_) => latencies.server_errors += 1,
+                None => latencies.failed += 1,
+            }
+            times.push(sample.latency);
+        }
+        times.sort_unstable();
+        // Nearest rank: the smallest time at least that share of the requests took no longer than.
+        let percentile = |share: f64| times.get(((times.len() as f64 * share).ceil() as usize).saturating_sub(1)).copied().unwrap_or_default();
+        latencies.p50 = percentile(0.5);
+        latencies.p90 = percentile(0.9);
+        latencies.p99 = percentile(0.99);
+        latencies.max = times.last().copied().unwrap_or_default();
+        latencies
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoadTestReport {
+    pub elapsed: Duration,
+    pub total: Latencies,
+    pub by_operation: Vec<(Operation, Latencies)>,
+    // Requests that were due while max_in_flight were already waiting, and so were never sent.
+    pub dropped: usize,
+}
+
+impl fmt::Display for LoadTestReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let rate = self.total.requests as f64 / self.elapsed.as_secs_f64();
+        writeln!(f, "{} requests in {:.1}s, {:.1}/s, {} dropped", self.total.requests, self.elapsed.as_secs_f64(), rate, self.dropped)?;
+        writeln!(f, "{:<8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>10} {:>10} {:>10} {:>10}", "", "requests", "2xx", "4xx", "5xx", "failed", "p50", "p90", "p99", "max")?;
+        let rows = self.by_operation.iter().map(|(operation, latencies)| (operation.name(), latencies)).chain([("total", &self.total)]);
+        for (name, latencies) in rows {
+            writeln!(f, "{:<8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>10} {:>10} {:>10} {:>10}",
+                name, latencies.requests, latencies.succeeded, latencies.client_errors, latencies.server_errors, latencies.failed,
+                millis(latencies.p50), millis(latencies.p90), millis(latencies.p99), millis(latencies.max))?;
+        }
+        Ok(())
+    }
+}
+
+fn millis(duration: Duration) -> String {
+    format!("{:.2}ms", duration.as_secs_f64() * 1000.0)
+}
+
+fn synthetic_movie(id: &str) -> serde_json::Value {
+    let name = format!("{} {}", WORDS[random::below(WORDS.len())], WORDS[random::below(WORDS.len())]);
+    json!({
+        "id": id,
+        "name": name,
+        "year": 1920 + random::below(106),
+        "was_good": random::below(2) == 0,
+        "genres": [GENRES[random::below(GENRES.len())]],
+        "runtime_minutes": 80 + random::below(100),
+    })
+}
+
+// Runs the load test, returning once every request sent has been answered or timed out.
+pub async fn run(config: &LoadTestConfig) -> LoadTestReport {
+    // Ids of this run's movies start with this, so runs against the same instance don't collide.
+    let mut run_bytes = [0u8; 4];
+    random::fill_bytes(&mut run_bytes);
+    let run_id: String = run_bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    let headers: Vec<(String, String)> = config.api_key.iter().map(|key| ("X-Api-Key".to_string(), key.clone())).collect();
+    let headers = Arc::new(headers);
+    let created = Arc::new(Mutex::new(Vec::<String>::new()));
+    let samples = Arc::new(Mutex::new(Vec::new()));
+    let in_flight = Arc::new(Semaphore::new(config.max_in_flight));
+    let total = (config.duration.as_secs_f64() * config.rps as f64).round() as u64;
+
+    let started = Instant::now();
+    let mut tasks = Vec::new();
+    let mut dropped = 0;
+    for n in 0..total {
+        let due = started + Duration::from_secs_f64(n as f64 / config.rps as f64);
+        tokio::time::sleep_until(due).await;
+        let Ok(permit) = in_flight.clone().try_acquire_owned() else {
+            dropped += 1;
+            continue;
+        };
+        // One in five creates, one in five lists and the rest read back what's been created.
+        let known = {
+            let created = created.lock().unwrap();
+            (!created.is_empty()).then(|| created[random::below(created.len())].clone())
+        };
+        let (operation, method, url, body) = match (n % 5, known) {
+            (0, _) | (_, None) => {
+                let id = format!("bench-{}-{}", run_id, n);
+                (Operation::Create, "POST", format!("{}/v1/movie", config.target), Some((id.clone(), synthetic_movie(&id).to_string())))
+            },
+            (1, _) => (Operation::List, "GET", format!("{}/v1/movies?genre={}&limit=20", config.target, GENRES[random::below(GENRES.len())]), None),
+            (_, Some(id)) => (Operation::Get, "GET", format!("{}/v1/movie/{}", config.target, id), None),
+        };
+        let (headers, created, samples) = (headers.clone(), created.clone(), samples.clone());
+        tasks.push(tokio::spawn(async move {
+            let mut request_headers: Vec<(&str, &str)> = headers.iter().map(|(name, value)| (name.as_str(), value.as_str())).collect();
+            if body.is_some() {
+                request_headers.push(("Content-Type", "application/json"));
+            }
+            let payload = body.as_ref().map_or(&[][..], |(_, json)| json.as_bytes());
+            let status = http_client::request(method, &url, &request_headers, payload, REQUEST_TIMEOUT).await.ok().map(|response| response.status);
+            let latency = due.elapsed();
+            drop(permit);
+            if let (Some((id, _)), Some(200..=299)) = (body, status) {
+                created.lock().unwrap().push(id);
+            }
+            samples.lock().unwrap().push(Sample { operation, status, latency });
+        }));
+    }
+    for task in tasks {
+        let _ = task.await;
+    }
+    let elapsed = started.elapsed();
+
+    let samples = std::mem::take(&mut *samples.lock().unwrap());
+    let mut operations: Vec<Operation> = samples.iter().map(|sample| sample.operation).collect();
+    operations.sort_unstable();
+    operations.dedup();
+    let by_operation = operations.into_iter()
+        .map(|operation| (operation, Latencies::of(samples.iter().filter(|sample| sample.operation == operation))))
+        .collect();
+    LoadTestReport { elapsed, total: Latencies::of(samples.iter()), by_operation, dropped }
+}
@@ -0,0 +1,82 @@
+use std::collections::BTreeMap;
+use axum::http::{header, HeaderMap};
+use serde::Serialize;
+
+use crate::model::Movie;
+
+// A movie's name is what it's known by everywhere, and its titles are what it's called in other languages, keyed by
+// language tag (RFC 5646), like "ja" or "pt-BR". GET /movie/{id} picks the title for the languages in Accept-Language
+// (RFC 9110) by the lookup of RFC 4647: the most preferred language that has a title wins, with "pt-PT" falling back
+// to a title for "pt", and then to one for another kind of "pt" like "pt-BR". The name has no language of its own,
+// so it's only the title when no language asked for has one.
+
+pub const MAX_LANGUAGE_LEN: usize = 35;
+
+// The language ranges of Accept-Language, lowercased, most preferred first. The wildcard is left out, since any title
+// would do for it and so would the name, and so is anything with q=0, which means "not this".
+pub fn accepted_languages(headers: &HeaderMap) -> Vec<String> {
+    let mut ranges: Vec<(String, f32)> = headers.get_all(header::ACCEPT_LANGUAGE).iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|range| {
+            let mut parts = range.split(';').map(str::trim);
+            let language = parts.next().unwrap_or_default().to_ascii_lowercase();
+            let q = parts.find_map(|parameter| parameter.strip_prefix("q=")).and_then(|q| q.parse().ok()).unwrap_or(1.0);
+            (!language.is_empty() && language != "*" && q > 0.0).then_some((language, q))
+        })
+        .collect();
+    // Stable, so languages as preferred as each other stay in the order they were listed.
+    ranges.sort_by(|a, b| b.1.total_cmp(&a.1));
+    ranges.into_iter().map(|(language, _)| language).collect()
+}
+
+// The language and title that best suit `languages`, or None if none of them has one.
+pub fn best_title<'a>(titles: &'a BTreeMap<String, String>, languages: &[String]) -> Option<(&'a str, &'a str)> {
+    let find = |matches: &dyn Fn(&str) -> bool| titles.iter()
+        .find(|(language, _)| matches(&language.to_ascii_lowercase()))
+        .map(|(language, title)| (language.as_str(), title.as_str()));
+    languages.iter().find_map(|range| {
+        // The range itself and then shorter and shorter prefixes of it, a subtag at a time, each first as it is and
+        // then as the start of a longer tag.
+        let mut prefix = range.as_str();
+        loop {
+            let found = find(&|language| language == prefix)
+                .or_else(|| find(&|language| language.strip_prefix(prefix).is_some_and(|rest| rest.starts_with('-'))));
+            if found.is_some() {
+                return found;
+            }
+            prefix = prefix.rsplit_once('-')?.0;
+        }
+    })
+}
+
+// Language tags are letters, digits and hyphens in subtags of up to 8 characters, the first all letters.
+pub fn valid_language(language: &str) -> bool {
+    let mut subtags = language.split('-');
+    let primary = subtags.next().unwrap_or_default();
+    language.len() <= MAX_LANGUAGE_LEN
+        && (1..=8).contains(&primary.len())
+        && primary.bytes().all(|byte| byte.is_ascii_alphabetic())
+        && subtags.all(|subtag| (1..=8).contains(&subtag.len()) && subtag.bytes().all(|byte| byte.is_ascii_alphanumeric()))
+}
+
+// A movie as GET /movie/{id} answers a request with Accept-Language: all of it, with the title to show besides.
+#[derive(Debug, Serialize)]
+pub struct LocalizedMovie<'a> {
+    #[serde(flatten)]
+    pub movie: &'a Movie,
+    // The best title, or the name if no language asked for has one.
+    pub title: &'a str,
+    // Which of the titles it is, left out when it's the name.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title_language: Option<&'a str>,
+}
+
+impl<'a> LocalizedMovie<'a> {
+    pub fn new(movie: &'a Movie, languages: &[String]) -> LocalizedMovie<'a> {
+        match best_title(&movie.titles, languages) {
+            Some((language, title)) => LocalizedMovie { movie, title, title_language: Some(language) },
+            None => LocalizedMovie { movie, title: &movie.name, title_language: None },
+        }
+    }
+}
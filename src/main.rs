@@ -1,86 +1,518 @@
-use std::{collections::HashMap, io, sync::Arc};
-use axum::{extract::{Path, State}, http::StatusCode, response::{IntoResponse, Response}, routing::{get, post}, Json, Router};
-use log::debug;
-use serde::{Serialize, Deserialize};
-use tokio::sync::Mutex;
+use std::{path::{Path, PathBuf}, process::ExitCode, sync::Arc};
+use time::OffsetDateTime;
+use tracing::{error, info, warn};
+use syndica_rust::audit;
+use syndica_rust::backup::{self, BackupFile};
+use syndica_rust::build_router;
+use syndica_rust::cache::CachedMovieStore;
+use syndica_rust::config::{self, Config, ConfigError, StoreConfig};
+use syndica_rust::cursor;
+use syndica_rust::enrich;
+use syndica_rust::event_sourced::{self, EventSourcedMovieStore};
+use syndica_rust::idempotency;
+use syndica_rust::listener::Listener;
+use syndica_rust::load_test::{self, LoadTestConfig};
+use syndica_rust::metrics;
+use syndica_rust::oidc;
+use syndica_rust::replication;
+use syndica_rust::otel::OtelExporter;
+use syndica_rust::posters::{self, DiskPosterStore};
+use syndica_rust::publish;
+use syndica_rust::quota::QuotaMovieStore;
+use syndica_rust::s3_backup;
+use syndica_rust::seed;
+use syndica_rust::settings::Settings;
+use syndica_rust::shutdown::{self, shutdown_signal};
+use syndica_rust::schema;
+use syndica_rust::snapshot::{self, SnapshotMovieStore};
+#[cfg(feature = "postgres")]
+use syndica_rust::postgres::{self, PostgresMovieStore};
+#[cfg(feature = "sqlite")]
+use syndica_rust::sqlite::{self, SqliteMovieStore};
+use syndica_rust::state::{state_init, StateWrapper};
+use syndica_rust::telemetry;
+use syndica_rust::tenant::TenantMovieStore;
+#[cfg(feature = "tls")]
+use syndica_rust::tls::{self, TlsAcceptor};
+use syndica_rust::trash;
+use syndica_rust::wal::{self, WalMovieStore};
+use syndica_rust::webhooks;
 
-#[derive(Debug, Serialize, Deserialize)]
-struct Movie {
-    pub id: String,
-    pub name: String,
-    pub year: u16,
-    pub was_good: bool
+// What the binary was asked to do. Without a command it serves, as it did before there were others.
+enum Command {
+    Serve,
+    Import(PathBuf),
+    Export(PathBuf),
+    Migrate,
 }
 
-struct MoviesState { 
-    pub movies: HashMap<String, Movie>,
+#[tokio::main]
+async fn main() -> ExitCode {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let command = match args.first().map(String::as_str) {
+        // `bench` load tests a running instance instead of being one, so it has flags of its own.
+        Some("bench") => return bench(args.into_iter().skip(1)).await,
+        Some("serve") => Command::Serve,
+        Some(name @ ("import" | "export")) => match args.get(1).filter(|file| !file.starts_with('-')) {
+            Some(file) if name == "import" => Command::Import(PathBuf::from(file)),
+            Some(file) => Command::Export(PathBuf::from(file)),
+            None => {
+                eprintln!("{} needs a file, e.g. {} movies.json", name, name);
+                return ExitCode::from(2);
+            },
+        },
+        Some("migrate") => Command::Migrate,
+        Some(other) if !other.starts_with('-') => {
+            eprintln!("Unknown command {:?}, expected serve, import, export, migrate or bench", other);
+            return ExitCode::from(2);
+        },
+        _ => Command::Serve,
+    };
+    // The rest are the same settings whatever the command, so import, export and migrate find the store serve uses.
+    let skip = match command {
+        Command::Import(_) | Command::Export(_) => 2,
+        Command::Serve | Command::Migrate => usize::from(args.first().is_some_and(|arg| !arg.starts_with('-'))),
+    };
+    args.drain(..skip);
+    let config = match Config::from_sources(args, |name| std::env::var(name).ok()) {
+        Ok(config) => config,
+        Err(ConfigError::Help(usage)) => {
+            println!("{}", usage);
+            return ExitCode::SUCCESS;
+        },
+        Err(e) => {
+            eprintln!("Invalid configuration: {}", e);
+            return ExitCode::from(2);
+        },
+    };
+    telemetry::init(config.log_level, config.log_format);
+    schema::set_auto_migrate(config.auto_migrate);
+    match command {
+        Command::Serve => serve(config).await,
+        Command::Import(path) => import(&config, &path).await,
+        Command::Export(path) => export(&config, &path).await,
+        Command::Migrate => migrate(&config).await,
+    }
 }
 
-impl MoviesState { 
-    fn new() -> MoviesState { 
-        MoviesState { 
-            movies: HashMap::new(),
+async fn serve(config: Config) -> ExitCode {
+    let otel = config.otel_endpoint.as_deref().map(OtelExporter::start);
+
+    let Some(mut state) = open_store(&config.store).await else {
+        return ExitCode::FAILURE;
+    };
+    if !config.tenants.is_empty() {
+        let mut tenants = Vec::new();
+        for tenant in &config.tenants {
+            let Some(store) = open_store(&config.store.for_tenant(&tenant.id)).await else {
+                return ExitCode::FAILURE;
+            };
+            tenants.push((tenant.clone(), store));
+        }
+        state = Arc::new(TenantMovieStore::new(state, tenants));
+        info!("Serving the tenants {}", config.tenants.iter().map(|tenant| tenant.id.as_str()).collect::<Vec<_>>().join(", "));
+    }
+    let quota = Arc::new(QuotaMovieStore::new(state, config.max_movies));
+    let state: StateWrapper = quota.clone();
+    if let Some(path) = config.store.audit_path()
+        && let Err(e) = audit::open(&path) {
+        error!("Failed to open the audit log {}: {}", path.display(), e);
+        return ExitCode::FAILURE;
+    }
+    if let Some(dir) = &config.poster_dir {
+        posters::set_store(Arc::new(DiskPosterStore::new(dir.clone())));
+    }
+    let mut cache = None;
+    let state = match &config.cache {
+        Some(cache_config) => {
+            let store = Arc::new(CachedMovieStore::new(state, cache_config.clone()));
+            spawn_cache_stats_task(store.clone());
+            metrics::register_cache(store.clone());
+            cache = Some(store.clone());
+            store as StateWrapper
+        },
+        None => state,
+    };
+    idempotency::set_window(config.idempotency_window);
+    oidc::set_config(config.oidc.clone());
+    enrich::set_config(config.enrich.clone());
+    cursor::set_secret(config.cursor_secret.clone());
+    let settings = Settings::default();
+    settings.set_api_keys(config.api_keys.clone());
+    settings.set_tenant_keys(config.tenant_api_keys.clone());
+    settings.set_jwt(config.jwt.clone());
+    settings.set_rate_limit(config.rate_limit);
+    settings.set_max_in_flight(config.max_in_flight);
+    settings.set_timeout(config.request_timeout);
+    settings.set_body_limits(config.max_body_bytes, config.max_upload_bytes);
+    settings.set_cors(config.cors.clone());
+    settings.set_duplicates(config.duplicates);
+    if config.api_keys.is_empty() && config.tenant_api_keys.is_empty() && config.jwt.is_none() && config.oidc.is_none() {
+        warn!("No API keys or bearer tokens are configured, anyone can write");
+    }
+    if let Some(publish) = &config.publish {
+        publish::start(publish.clone(), state.subscribe());
+    }
+    webhooks::spawn_dispatcher(state.subscribe());
+    trash::set_retention(config.trash_retention);
+    trash::spawn_purge_task(state.clone());
+    backup::set_dir(config.backup_dir.clone());
+    if let Some(s3_backup) = &config.s3_backup {
+        s3_backup::start(s3_backup.clone(), state.clone());
+    }
+    if let Some(follow) = &config.follow {
+        replication::start(follow.clone(), state.clone());
+    }
+    if let Some(path) = &config.seed {
+        match seed::read(path) {
+            Ok(entries) => seed::start(state.clone(), entries),
+            Err(e) => {
+                error!("Failed to read the seed file {}: {}", path.display(), e);
+                return ExitCode::FAILURE;
+            },
         }
     }
+    let app = build_router(state.clone(), settings.clone());
+
+    let signal = shutdown_signal().expect("failed to install signal handlers");
+    // One for every TCP listener, so a SIGHUP reloads the certificate for all of them.
+    #[cfg(feature = "tls")]
+    let tls = match &config.tls {
+        Some(tls) => match TlsAcceptor::new(tls.clone(), config.http.http2) {
+            Ok(acceptor) => {
+                info!("Serving HTTPS on TCP addresses with {}", tls.cert.display());
+                let acceptor = Arc::new(acceptor);
+                shutdown::catch_hangups().expect("failed to install signal handlers");
+                tls::spawn_reload_task(acceptor.clone());
+                Some(acceptor)
+            },
+            Err(e) => {
+                error!("Failed to load the TLS certificate: {}", e);
+                return ExitCode::FAILURE;
+            },
+        },
+        None => None,
+    };
+    let mut listeners = Vec::new();
+    for address in &config.listen {
+        let listener = match Listener::bind(address).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                error!("Failed to listen on {}: {}", address, e);
+                return ExitCode::FAILURE;
+            },
+        };
+        #[cfg(feature = "tls")]
+        let listener = match &tls {
+            Some(acceptor) => listener.with_tls(acceptor.clone()),
+            None => listener,
+        };
+        listeners.push(listener);
+        info!("Listening on {}", address);
+    }
+    let shutdown_timeout = config.shutdown_timeout;
+    let http = config.http.clone();
+    config::spawn_reload_task(config, move |old, new| apply_reload(old, new, &settings, &quota, cache.as_deref()));
+
+    // On SIGINT/SIGTERM stop accepting connections and let the ones in flight finish, up to the shutdown timeout.
+    let servers = futures_util::future::join_all(listeners.into_iter().map(|listener| listener.serve(app.clone(), http.clone(), shutdown::draining())));
+    tokio::select! {
+        _ = servers => {},
+        _ = async {
+            info!("Received {}, finishing in-flight requests", signal.await);
+            shutdown::start_draining();
+            tokio::time::sleep(shutdown_timeout).await
+        } => {
+            warn!("Requests still running after {:?}, shutting down anyway", shutdown_timeout);
+        },
+    }
+
+    // Don't lose whatever changed since the last periodic flush, and mark the change logs closed so that cursors for
+    // them still work after a restart. The write-ahead log is synced on every write, so it has nothing pending.
+    if !close(&state).await {
+        return ExitCode::FAILURE;
+    }
+    info!("Store is up to date");
+    // The last requests' spans would otherwise wait for an export that never comes.
+    if let Some(otel) = otel {
+        otel.flush().await;
+    }
+    info!("Shutdown complete");
+    ExitCode::SUCCESS
 }
 
-type StateWrapper = Arc<Mutex<MoviesState>>;
+async fn bench(args: impl Iterator<Item = String>) -> ExitCode {
+    let config = match LoadTestConfig::from_args(args) {
+        Ok(config) => config,
+        Err(ConfigError::Help(usage)) => {
+            println!("{}", usage);
+            return ExitCode::SUCCESS;
+        },
+        Err(e) => {
+            eprintln!("Invalid arguments: {}", e);
+            return ExitCode::from(2);
+        },
+    };
+    println!("Sending {} requests a second to {} for {:?}", config.rps, config.target, config.duration);
+    let report = load_test::run(&config).await;
+    print!("{}", report);
+    // Nothing got through at all, most likely the wrong address.
+    if report.total.requests == report.total.failed {
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
 
-fn state_init() -> StateWrapper { 
-    Arc::new(
-        Mutex::new(
-            MoviesState::new()
-        )
-    )
+// Fills an empty --store from a backup file, as POST /admin/snapshot writes them and `export` does. Tenants' stores are
+// left alone.
+async fn import(config: &Config, path: &Path) -> ExitCode {
+    let file = match std::fs::read(path).map_err(|e| e.to_string()).and_then(|contents| serde_json::from_slice::<BackupFile>(&contents).map_err(|e| e.to_string())) {
+        Ok(file) => file,
+        Err(e) => {
+            error!("Failed to read the backup {}: {}", path.display(), e);
+            return ExitCode::FAILURE;
+        },
+    };
+    let imported = file.into_backup();
+    if let Err(e) = backup::check(&imported) {
+        error!("Can't import {}: {}", path.display(), e);
+        return ExitCode::FAILURE;
+    }
+    let Some(state) = open_store(&config.store).await else {
+        return ExitCode::FAILURE;
+    };
+    // Replacing a store that's in use is what POST /admin/restore is for, this won't overwrite anything.
+    let existing = state.count().await;
+    if existing > 0 {
+        error!("The store already has {} movies, import only fills an empty one", existing);
+        return ExitCode::FAILURE;
+    }
+    let counts = (imported.movies.len(), imported.users.len(), imported.links.len(), imported.trash.len());
+    if let Err(e) = state.replace(imported).await {
+        error!("Failed to import {}: {:?}", path.display(), e);
+        return ExitCode::FAILURE;
+    }
+    if !close(&state).await {
+        return ExitCode::FAILURE;
+    }
+    info!("Imported {} movies, {} users, {} links and {} movies in the trash from {}", counts.0, counts.1, counts.2, counts.3, path.display());
+    ExitCode::SUCCESS
 }
 
-#[axum::debug_handler]
-async fn post_handler(State(state): State<StateWrapper>, Json(movie): Json<Movie>) -> Result<(), StatusCode> { 
-    let mut state_ref = state.lock().await;
-    if state_ref.movies.contains_key(&movie.id) {
-        // Handle attempts to submit a movie with the same ID as another movie already in our database.
-        return Err(StatusCode::BAD_REQUEST);
-    }
-    debug!("Adding movie {}", movie.name);
-    state_ref.movies.insert(movie.id.clone(), movie);
-    debug!("Current application movie table is: {:#?}", state_ref.movies);
-    Ok(())
+// Writes everything in --store out as a backup file, which `import` and POST /admin/restore read back. Tenants' stores
+// are left out.
+async fn export(config: &Config, path: &Path) -> ExitCode {
+    let Some(state) = open_store(&config.store).await else {
+        return ExitCode::FAILURE;
+    };
+    let file = BackupFile::new(state.backup().await, OffsetDateTime::now_utc());
+    // Nothing was changed, so the store is left as it was found.
+    if !close(&state).await {
+        return ExitCode::FAILURE;
+    }
+    let counts = (file.movies.len(), file.users.len(), file.links.len(), file.trash.len());
+    let (dir, name) = match (path.parent(), path.file_name().and_then(|name| name.to_str())) {
+        (Some(dir), Some(name)) => (if dir.as_os_str().is_empty() { Path::new(".") } else { dir }, name),
+        _ => {
+            error!("Can't export to {}, expected a file name", path.display());
+            return ExitCode::from(2);
+        },
+    };
+    if let Err(e) = backup::write(dir, name, &file).await {
+        error!("Failed to write {}: {}", path.display(), e);
+        return ExitCode::FAILURE;
+    }
+    info!("Exported {} movies, {} users, {} links and {} movies in the trash to {}", counts.0, counts.1, counts.2, counts.3, path.display());
+    ExitCode::SUCCESS
 }
 
-#[axum::debug_handler]
-async fn get_handler(Path(id): Path<String>, State(state): State<StateWrapper>, ) -> Result<String, StatusCode> { 
-    let state_ref = state.lock().await;
-    if let Some(movie) = state_ref.movies.get(&id) { 
-        match serde_json::to_string_pretty(movie) {
-            Ok(serialized) => Ok(serialized),
-            Err(_e) => Err(StatusCode::NOT_FOUND),
+// Brings --store, and every tenant's, up to the current schema version (see schema.rs), whatever auto_migrate says.
+// Snapshots, write-ahead logs and SQLite and Postgres databases are rewritten in it. Event logs are the history itself,
+// so the events in them stay as they were written, and are only stamped so what's appended after is in the current
+// version.
+async fn migrate(config: &Config) -> ExitCode {
+    schema::set_auto_migrate(true);
+    let stores = std::iter::once(config.store.clone()).chain(config.tenants.iter().map(|tenant| config.store.for_tenant(&tenant.id)));
+    for store in stores {
+        let location = match &store {
+            StoreConfig::Memory => {
+                info!("memory:// keeps nothing to migrate");
+                return ExitCode::SUCCESS;
+            },
+            StoreConfig::Events(path) => {
+                let Some(state) = open_store(&store).await else {
+                    return ExitCode::FAILURE;
+                };
+                if !close(&state).await {
+                    return ExitCode::FAILURE;
+                }
+                info!("Event log {} is stamped with schema version {}", path.display(), event_sourced::SCHEMA.current());
+                continue;
+            },
+            // Opening the database migrates it, in one transaction.
+            #[cfg(feature = "postgres")]
+            StoreConfig::Postgres(postgres) => {
+                let Some(state) = open_store(&store).await else {
+                    return ExitCode::FAILURE;
+                };
+                if !close(&state).await {
+                    return ExitCode::FAILURE;
+                }
+                info!("Postgres tables in the {} schema are in schema version {}", postgres.schema.as_deref().unwrap_or("default"), postgres::SCHEMA.current());
+                continue;
+            },
+            StoreConfig::Snapshot(snapshot) => snapshot.path.clone(),
+            StoreConfig::Wal(wal) => wal.path.clone(),
+            #[cfg(feature = "sqlite")]
+            StoreConfig::Sqlite(path) => path.clone(),
+        };
+        let Some(state) = open_store(&store).await else {
+            return ExitCode::FAILURE;
+        };
+        // Putting back what's there has each store write it out afresh, as a restore would.
+        let everything = state.backup().await;
+        let movies = everything.movies.len();
+        if let Err(e) = state.replace(everything).await {
+            error!("Failed to migrate {}: {:?}", location.display(), e);
+            return ExitCode::FAILURE;
+        }
+        if !close(&state).await {
+            return ExitCode::FAILURE;
         }
+        info!("Migrated {} with {} movies to schema version {}", location.display(), movies, match store {
+            StoreConfig::Snapshot(_) => snapshot::SCHEMA.current(),
+            #[cfg(feature = "sqlite")]
+            StoreConfig::Sqlite(_) => sqlite::SCHEMA.current(),
+            _ => wal::SCHEMA.current(),
+        });
     }
-    else { 
-        Err(StatusCode::NOT_FOUND)
+    ExitCode::SUCCESS
+}
+
+// Writes out whatever the store still holds back and closes its change log, saying whether that worked.
+async fn close(store: &StateWrapper) -> bool {
+    if let Err(e) = store.close().await {
+        error!("Failed to close the store: {:?}", e);
+        return false;
     }
+    true
 }
 
-#[tokio::main]
-async fn main() {
-    // Create Axum server with the following endpoints:
-    // 1. GET /movie/{id} - This should return back a movie given the id
-    // 2. POST /movie - this should save move in a DB (HashMap<String, Movie>). This movie will be sent
-    // via a JSON payload.
-    
-    let state = state_init();
-    
-    // As a bonus: implement a caching layer so we don't need to make expensive "DB" lookups, etc.
-    let state_clone = state.clone();
-    let app = Router::new()
-        .route("/movie", post(post_handler))
-        .route("/movie/{id}",
-            get({
-                move |path| get_handler(path, State(state_clone))
-            }),
-        )
-        .with_state(state.clone());
+// Settings that live in the running server get changed in place, the rest only take effect on the next start.
+fn apply_reload(old: &Config, new: &Config, settings: &Settings, quota: &QuotaMovieStore, cache: Option<&CachedMovieStore>) { 
+    #[cfg(feature = "tls")]
+    if new.tls != old.tls {
+        warn!("Changes to tls_cert and tls_key need a restart, SIGHUP only reads the files they name again");
+    }
+    if new.log_level != old.log_level {
+        telemetry::set_level(new.log_level);
+        info!("Log level is now {}", new.log_level);
+    }
+    if new.log_format != old.log_format {
+        telemetry::set_format(new.log_format);
+    }
+    if new.idempotency_window != old.idempotency_window {
+        idempotency::set_window(new.idempotency_window);
+        info!("Idempotency keys are now kept for {:?}", new.idempotency_window);
+    }
+    if new.api_keys != old.api_keys {
+        settings.set_api_keys(new.api_keys.clone());
+        info!("{} API keys are now configured", new.api_keys.len());
+    }
+    if new.tenant_api_keys != old.tenant_api_keys {
+        settings.set_tenant_keys(new.tenant_api_keys.clone());
+        info!("{} tenant API keys are now configured", new.tenant_api_keys.len());
+    }
+    if new.max_movies != old.max_movies {
+        quota.set_max_movies(new.max_movies);
+        info!("At most {:?} movies may now be stored", new.max_movies);
+    }
+    if new.jwt != old.jwt {
+        settings.set_jwt(new.jwt.clone());
+        info!("Bearer token settings changed");
+    }
+    if new.rate_limit != old.rate_limit {
+        settings.set_rate_limit(new.rate_limit);
+        info!("Rate limit is now {:?}", new.rate_limit);
+    }
+    if new.max_in_flight != old.max_in_flight {
+        settings.set_max_in_flight(new.max_in_flight);
+        info!("At most {} requests are now handled at once", new.max_in_flight);
+    }
+    if (new.max_body_bytes, new.max_upload_bytes) != (old.max_body_bytes, old.max_upload_bytes) {
+        settings.set_body_limits(new.max_body_bytes, new.max_upload_bytes);
+        info!("Bodies may now be {} bytes, and uploads {} bytes", new.max_body_bytes, new.max_upload_bytes);
+    }
+    if new.request_timeout != old.request_timeout {
+        settings.set_timeout(new.request_timeout);
+        info!("Requests now time out after {:?}", new.request_timeout);
+    }
+    if new.cors != old.cors {
+        settings.set_cors(new.cors.clone());
+        info!("CORS settings are now {:?}", new.cors);
+    }
+    if new.oidc != old.oidc {
+        oidc::set_config(new.oidc.clone());
+        info!("OIDC login settings changed, everyone has been logged out");
+    }
+    if new.enrich != old.enrich {
+        enrich::set_config(new.enrich.clone());
+        info!("Metadata provider is now {:?}", new.enrich);
+    }
+    if new.trash_retention != old.trash_retention {
+        trash::set_retention(new.trash_retention);
+        info!("Deleted movies are now kept in the trash for {:?}", new.trash_retention);
+    }
+    if new.duplicates != old.duplicates {
+        settings.set_duplicates(new.duplicates);
+        info!("Duplicate movies are now handled with {:?}", new.duplicates);
+    }
+    if new.backup_dir != old.backup_dir {
+        backup::set_dir(new.backup_dir.clone());
+        info!("Backups now go to {:?}", new.backup_dir);
+    }
+    if new.cursor_secret != old.cursor_secret {
+        cursor::set_secret(new.cursor_secret.clone());
+        info!("Cursor secret changed, cursors handed out before are no longer accepted");
+    }
+    match (cache, &new.cache) {
+        (Some(cache), Some(cache_config)) => cache.reconfigure(cache_config),
+        _ if new.cache.is_some() != old.cache.is_some() => warn!("Turning the movie cache on or off needs a restart"),
+        _ => {},
+    }
+    if new.listen != old.listen || new.http != old.http || new.store != old.store || new.poster_dir != old.poster_dir || new.publish != old.publish
+        || new.s3_backup != old.s3_backup || new.follow != old.follow || new.seed != old.seed || new.tenants != old.tenants || new.auto_migrate != old.auto_migrate {
+        warn!("Changes to bind_addr, listen, the HTTP connection settings, store, auto_migrate, poster_dir, seed, tenants and the follow*, publish_* and s3_backup_* settings need a restart");
+    }
+}
 
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:1234").await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+// Opens the store `config` describes. Snapshot stores are flushed periodically, and when the store is closed.
+// A store that can't be opened, e.g. one written by a newer version, is logged and None.
+async fn open_store(config: &StoreConfig) -> Option<StateWrapper> {
+    let opened = match config {
+        StoreConfig::Memory => return Some(state_init()),
+        StoreConfig::Wal(wal) => WalMovieStore::open(wal.clone()).await.map(|store| Arc::new(store) as StateWrapper),
+        StoreConfig::Events(path) => EventSourcedMovieStore::open(path.clone()).await.map(|store| Arc::new(store) as StateWrapper),
+        #[cfg(feature = "sqlite")]
+        StoreConfig::Sqlite(path) => SqliteMovieStore::open(path.clone()).await.map(|store| Arc::new(store) as StateWrapper),
+        #[cfg(feature = "postgres")]
+        StoreConfig::Postgres(postgres) => PostgresMovieStore::open(postgres.clone()).await.map(|store| Arc::new(store) as StateWrapper),
+        StoreConfig::Snapshot(snapshot) => SnapshotMovieStore::open(snapshot.path.clone()).await.map(|store| {
+            let store = Arc::new(store);
+            store.spawn_flush_task(snapshot.interval);
+            store as StateWrapper
+        }),
+    };
+    opened.inspect_err(|e| error!("Failed to open the store: {}", e)).ok()
+}
+
+fn spawn_cache_stats_task(cache: Arc<CachedMovieStore>) { 
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(std::time::Duration::from_secs(60));
+        loop {
+            ticker.tick().await;
+            let stats = cache.stats();
+            info!("Movie cache: {} entries, {} hits, {} misses", stats.entries, stats.hits, stats.misses);
+        }
+    });
 }
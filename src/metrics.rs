@@ -0,0 +1,232 @@
+use std::{collections::BTreeMap, fmt::Write, sync::{atomic::{AtomicU64, Ordering}, Arc, Mutex}, time::{Duration, Instant}};
+use axum::{extract::{MatchedPath, Request}, middleware::Next, response::Response};
+
+use crate::cache::CachedMovieStore;
+use crate::settings::Settings;
+use crate::resilience::{self, BreakerState};
+use crate::replication;
+use crate::s3_backup;
+use crate::store::MovieStore;
+use crate::tenant;
+
+// Upper bounds of the histogram buckets in seconds, from an uncontended lock up to a request that is badly stuck.
+const BUCKETS: [f64; 12] = [0.00001, 0.0001, 0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.25, 0.5, 1.0, 5.0];
+
+// Everything /metrics reports lives in this one process-wide registry, so the store and the cache can record into
+// it without having metrics threaded through them.
+static METRICS: Metrics = Metrics {
+    routes: Mutex::new(BTreeMap::new()),
+    lock_waits: [const { Histogram::new() }; 6],
+    cache: Mutex::new(None),
+};
+
+struct Metrics {
+    // Keyed by (method, route), where the route is the pattern that matched, e.g. /movie/{id}.
+    routes: Mutex<BTreeMap<(String, String), RouteMetrics>>,
+    // Indexed by Lock.
+    lock_waits: [Histogram; 6],
+    cache: Mutex<Option<Arc<CachedMovieStore>>>,
+}
+
+#[derive(Default)]
+struct RouteMetrics {
+    responses: BTreeMap<u16, u64>,
+    latency: Histogram,
+}
+
+// The locks whose wait times are tracked.
+#[derive(Debug, Clone, Copy)]
+pub enum Lock {
+    MoviesRead = 0,
+    MoviesWrite = 1,
+    WalLog = 2,
+    EventLog = 3,
+    SqliteWrites = 4,
+    PostgresWrites = 5,
+}
+
+const LOCK_NAMES: [&str; 6] = ["movies_read", "movies_write", "wal_log", "event_log", "sqlite_writes", "postgres_writes"];
+
+struct Histogram {
+    // Not cumulative, each observation is only counted in the first bucket it fits.
+    buckets: [AtomicU64; BUCKETS.len()],
+    count: AtomicU64,
+    sum_nanos: AtomicU64,
+}
+
+impl Histogram {
+    const fn new() -> Histogram {
+        Histogram {
+            buckets: [const { AtomicU64::new(0) }; BUCKETS.len()],
+            count: AtomicU64::new(0),
+            sum_nanos: AtomicU64::new(0),
+        }
+    }
+
+    fn observe(&self, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64();
+        if let Some(bucket) = BUCKETS.iter().position(|bound| seconds <= *bound) {
+            self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_nanos.fetch_add(elapsed.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    fn render(&self, out: &mut String, name: &str, labels: &str) {
+        let mut cumulative = 0;
+        for (bound, bucket) in BUCKETS.iter().zip(&self.buckets) {
+            cumulative += bucket.load(Ordering::Relaxed);
+            writeln!(out, "{}_bucket{{{}le=\"{}\"}} {}", name, labels, bound, cumulative).unwrap();
+        }
+        let count = self.count.load(Ordering::Relaxed);
+        writeln!(out, "{}_bucket{{{}le=\"+Inf\"}} {}", name, labels, count).unwrap();
+        let sum = self.sum_nanos.load(Ordering::Relaxed) as f64 / 1e9;
+        let labels = labels.trim_end_matches(',');
+        writeln!(out, "{}_sum{{{}}} {}", name, labels, sum).unwrap();
+        writeln!(out, "{}_count{{{}}} {}", name, labels, count).unwrap();
+    }
+}
+
+impl Default for Histogram {
+    fn default() -> Histogram {
+        Histogram::new()
+    }
+}
+
+pub fn record_lock_wait(lock: Lock, waited: Duration) {
+    METRICS.lock_waits[lock as usize].observe(waited);
+}
+
+// Makes the cache's hit and miss counts show up in /metrics.
+pub fn register_cache(cache: Arc<CachedMovieStore>) {
+    *METRICS.cache.lock().unwrap() = Some(cache);
+}
+
+// Middleware counting every response and timing it, per route.
+pub async fn track_requests(request: Request, next: Next) -> Response {
+    let method = request.method().to_string();
+    // Unmatched paths are lumped together, or anyone could make up as many label values as they liked.
+    let route = request.extensions().get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+    let started = Instant::now();
+    let response = next.run(request).await;
+    let elapsed = started.elapsed();
+
+    let mut routes = METRICS.routes.lock().unwrap();
+    let route = routes.entry((method, route)).or_default();
+    *route.responses.entry(response.status().as_u16()).or_default() += 1;
+    route.latency.observe(elapsed);
+    response
+}
+
+// Everything in the Prometheus text exposition format. In-flight requests are counted by the router's settings.
+pub async fn render(store: &dyn MovieStore, settings: &Settings) -> String {
+    let mut out = String::new();
+
+    out.push_str("# HELP http_requests_total Responses sent, by method, route and status.\n");
+    out.push_str("# TYPE http_requests_total counter\n");
+    {
+        let routes = METRICS.routes.lock().unwrap();
+        for ((method, route), metrics) in routes.iter() {
+            for (status, count) in &metrics.responses {
+                writeln!(out, "http_requests_total{{method=\"{}\",route=\"{}\",status=\"{}\"}} {}", escape(method), escape(route), status, count).unwrap();
+            }
+        }
+        out.push_str("# HELP http_request_duration_seconds Time from receiving a request to having its response, by method and route.\n");
+        out.push_str("# TYPE http_request_duration_seconds histogram\n");
+        for ((method, route), metrics) in routes.iter() {
+            let labels = format!("method=\"{}\",route=\"{}\",", escape(method), escape(route));
+            metrics.latency.render(&mut out, "http_request_duration_seconds", &labels);
+        }
+    }
+
+    out.push_str("# HELP http_requests_in_flight Requests being handled right now.\n");
+    out.push_str("# TYPE http_requests_in_flight gauge\n");
+    writeln!(out, "http_requests_in_flight {}", settings.in_flight()).unwrap();
+
+    out.push_str("# HELP movies_lock_wait_seconds Time spent waiting to acquire store locks.\n");
+    out.push_str("# TYPE movies_lock_wait_seconds histogram\n");
+    for (name, histogram) in LOCK_NAMES.iter().zip(&METRICS.lock_waits) {
+        histogram.render(&mut out, "movies_lock_wait_seconds", &format!("lock=\"{}\",", name));
+    }
+
+    out.push_str("# HELP movies_stored Movies currently in the store.\n");
+    out.push_str("# TYPE movies_stored gauge\n");
+    writeln!(out, "movies_stored {}", store.count().await).unwrap();
+
+    if !store.tenants().is_empty() {
+        out.push_str("# HELP movies_tenant_stored Movies currently in each tenant's catalog, default for the one of requests without a tenant.\n");
+        out.push_str("# TYPE movies_tenant_stored gauge\n");
+        for stats in tenant::stats(store).await {
+            writeln!(out, "movies_tenant_stored{{tenant=\"{}\"}} {}", stats.id, stats.movies).unwrap();
+        }
+    }
+
+    if let Some(last_success) = s3_backup::last_success() {
+        out.push_str("# HELP movies_backup_last_success_timestamp_seconds When the last scheduled backup was uploaded, 0 if none has been yet.\n");
+        out.push_str("# TYPE movies_backup_last_success_timestamp_seconds gauge\n");
+        writeln!(out, "movies_backup_last_success_timestamp_seconds {}", last_success).unwrap();
+    }
+
+    if let Some(last_sync) = replication::last_sync() {
+        out.push_str("# HELP movies_follower_last_sync_timestamp_seconds When this follower last caught up with its leader, 0 if it hasn't yet.\n");
+        out.push_str("# TYPE movies_follower_last_sync_timestamp_seconds gauge\n");
+        writeln!(out, "movies_follower_last_sync_timestamp_seconds {}", last_sync).unwrap();
+    }
+
+    let integrations = resilience::stats();
+    if !integrations.is_empty() {
+        out.push_str("# HELP outbound_breaker_state Circuit breaker of each service called out to: 0 closed, 1 half-open, 2 open.\n");
+        out.push_str("# TYPE outbound_breaker_state gauge\n");
+        for integration in &integrations {
+            let state = match integration.state {
+                BreakerState::Closed => 0,
+                BreakerState::HalfOpen => 1,
+                BreakerState::Open => 2,
+            };
+            writeln!(out, "outbound_breaker_state{{integration=\"{}\"}} {}", escape(&integration.name), state).unwrap();
+        }
+        out.push_str("# HELP outbound_breaker_trips_total Times each breaker has opened.\n");
+        out.push_str("# TYPE outbound_breaker_trips_total counter\n");
+        for integration in &integrations {
+            writeln!(out, "outbound_breaker_trips_total{{integration=\"{}\"}} {}", escape(&integration.name), integration.trips).unwrap();
+        }
+        out.push_str("# HELP outbound_calls_total Calls out to each service by outcome, after retries. Rejected calls weren't made because the breaker was open.\n");
+        out.push_str("# TYPE outbound_calls_total counter\n");
+        for integration in &integrations {
+            for (outcome, count) in [("success", integration.successes), ("failure", integration.failures), ("rejected", integration.rejections)] {
+                writeln!(out, "outbound_calls_total{{integration=\"{}\",outcome=\"{}\"}} {}", escape(&integration.name), outcome, count).unwrap();
+            }
+        }
+        out.push_str("# HELP outbound_retries_total Attempts made again after a failure that might pass.\n");
+        out.push_str("# TYPE outbound_retries_total counter\n");
+        for integration in &integrations {
+            writeln!(out, "outbound_retries_total{{integration=\"{}\"}} {}", escape(&integration.name), integration.retries).unwrap();
+        }
+    }
+
+    let cache = METRICS.cache.lock().unwrap().clone();
+    if let Some(cache) = cache {
+        let stats = cache.stats();
+        let lookups = stats.hits + stats.misses;
+        let ratio = if lookups == 0 { 0.0 } else { stats.hits as f64 / lookups as f64 };
+        out.push_str("# HELP movies_cache_hits_total Lookups by id served from the cache.\n");
+        out.push_str("# TYPE movies_cache_hits_total counter\n");
+        writeln!(out, "movies_cache_hits_total {}", stats.hits).unwrap();
+        out.push_str("# HELP movies_cache_misses_total Lookups by id that had to go to the store.\n");
+        out.push_str("# TYPE movies_cache_misses_total counter\n");
+        writeln!(out, "movies_cache_misses_total {}", stats.misses).unwrap();
+        out.push_str("# HELP movies_cache_hit_ratio Share of lookups by id served from the cache since startup.\n");
+        out.push_str("# TYPE movies_cache_hit_ratio gauge\n");
+        writeln!(out, "movies_cache_hit_ratio {}", ratio).unwrap();
+        out.push_str("# HELP movies_cache_entries Movies currently cached.\n");
+        out.push_str("# TYPE movies_cache_entries gauge\n");
+        writeln!(out, "movies_cache_entries {}", stats.entries).unwrap();
+    }
+    out
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
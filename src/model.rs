@@ -0,0 +1,575 @@
+use std::{collections::BTreeMap, sync::Arc};
+use serde::{Deserializer, Serialize, Deserialize};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+use crate::random;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Movie {
+    pub id: String,
+    pub name: String,
+    pub year: u16,
+    pub was_good: bool,
+    // The details below are optional, and left out of the JSON when not set, so movies saved before they existed read
+    // back, and look to clients and ETags, exactly as they did.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub genres: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub director: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub runtime_minutes: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub synopsis: Option<String>,
+    // Where a poster for the movie can be found elsewhere, e.g. as given by the metadata provider in enrich.rs. Not the
+    // same as an image stored with PUT /movie/{id}/poster, which is `poster` below.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub poster_url: Option<String>,
+    // Free-form labels for finding movies by, like "noir" or "watch-with-kids". Matched ignoring case, like genres.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    // What the movie is called in other languages, by language tag, like {"ja": "七人の侍"}. The name stays what it's
+    // known by, and GET /movie/{id} picks one of these to show by Accept-Language, see locale.rs.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub titles: BTreeMap<String, String>,
+    // The mean of the scores in `ratings`, and how many there are. Left out until someone rates the movie. Meant to take
+    // over from was_good in time.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub average_rating: Option<f64>,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub rating_count: usize,
+    // Each user's score from 1 to 10, by user. Never sent to clients with the movie, who'd otherwise get every rating
+    // on every page, nor taken from them: only POST /movie/{id}/ratings changes it. Stores save it with StoredMovie.
+    #[serde(skip)]
+    pub ratings: BTreeMap<String, u8>,
+    // How many reviews there are, left out until there are any. The reviews themselves are only sent by GET
+    // /movie/{id}/reviews and only added by POST to it, like ratings. They go when the movie does.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub review_count: usize,
+    // Oldest first. Shared, since a movie is cloned on every read and reviews can be long.
+    #[serde(skip)]
+    pub reviews: Arc<Vec<Review>>,
+    // Which image PUT /movie/{id}/poster last stored for it, if any. The image is kept apart by posters.rs, this only
+    // points at it, so it goes when the movie does. Stores save it with StoredMovie.
+    #[serde(skip)]
+    pub poster: Option<Poster>,
+    // Starts at 1 and goes up by one with every change, which the store does, never the client. Clients send back the
+    // version they read with an update, and it's refused if the movie has moved on since. Movies saved before there
+    // were versions read back as version 0.
+    #[serde(default)]
+    pub version: u64,
+    // When the movie was added and when it last changed, in RFC 3339 and UTC. Kept by the store like the version,
+    // whatever clients send. Movies saved before there were timestamps have neither until they next change, and then
+    // only updated_at, since when they were added is lost.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<String>,
+}
+
+// The time now, as movies are stamped with it.
+pub fn timestamp() -> String {
+    OffsetDateTime::now_utc().format(&Rfc3339).unwrap_or_default()
+}
+
+fn is_zero(count: &usize) -> bool {
+    *count == 0
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Review {
+    pub id: String,
+    pub author: String,
+    pub text: String,
+    // RFC 3339, in UTC.
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Poster {
+    // One of posters::CONTENT_TYPES, as read from the image itself.
+    pub content_type: String,
+    pub size: usize,
+    // Of the image, in hex. Images are stored under it and it's their ETag.
+    pub sha256: String,
+}
+
+// A movie as stores write it to disk: what clients see of it, plus the ratings, reviews and poster they don't.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StoredMovie {
+    #[serde(flatten)]
+    pub movie: Movie,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub ratings: BTreeMap<String, u8>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reviews: Vec<Review>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub poster: Option<Poster>,
+}
+
+impl From<Movie> for StoredMovie {
+    fn from(mut movie: Movie) -> StoredMovie {
+        StoredMovie {
+            ratings: std::mem::take(&mut movie.ratings),
+            reviews: Arc::unwrap_or_clone(std::mem::take(&mut movie.reviews)),
+            poster: movie.poster.take(),
+            movie,
+        }
+    }
+}
+
+impl From<StoredMovie> for Movie {
+    fn from(stored: StoredMovie) -> Movie {
+        Movie { ratings: stored.ratings, reviews: Arc::new(stored.reviews), poster: stored.poster, ..stored.movie }
+    }
+}
+
+// A deleted movie. Deletes only move movies to the trash, where they can be restored from until they're purged.
+#[derive(Debug, Clone, Serialize)]
+pub struct Trashed {
+    // RFC 3339, in UTC.
+    pub deleted_at: String,
+    pub movie: Movie,
+}
+
+// A movie in the trash as stores write it to disk.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StoredTrashed {
+    pub deleted_at: String,
+    pub movie: StoredMovie,
+}
+
+impl From<Trashed> for StoredTrashed {
+    fn from(trashed: Trashed) -> StoredTrashed {
+        StoredTrashed { deleted_at: trashed.deleted_at, movie: trashed.movie.into() }
+    }
+}
+
+impl From<StoredTrashed> for Trashed {
+    fn from(stored: StoredTrashed) -> Trashed {
+        Trashed { deleted_at: stored.deleted_at, movie: stored.movie.into() }
+    }
+}
+
+// Someone with a watchlist, registered with POST /users or, with a bearer token, made on their first change to it.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct User {
+    pub id: String,
+    // What to call them, which needn't be unique.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    // RFC 3339, in UTC.
+    pub created_at: String,
+    // Ids of the movies they mean to watch, in the order they were added. A movie comes off every watchlist when it's
+    // deleted, and off favorites too.
+    #[serde(default)]
+    pub watchlist: Vec<String>,
+    #[serde(default)]
+    pub favorites: Vec<String>,
+    // Every movie they've said they watched, in the order they said so. Only sent by GET /users/{id}/history, since it
+    // only ever grows, and kept when the movie is deleted, since they did still watch it. Stores save it with StoredUser.
+    #[serde(skip)]
+    pub history: Arc<Vec<Watch>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Watch {
+    pub movie_id: String,
+    // When they watched it, as they tell it, and when we were told, which is what GET /users/{id}/history?since= goes
+    // by so that syncing clients don't miss watches that were recorded late. Both RFC 3339, in UTC.
+    pub watched_at: String,
+    pub recorded_at: String,
+}
+
+// A user as stores write them to disk, with the history clients don't get along with them.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StoredUser {
+    #[serde(flatten)]
+    pub user: User,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub history: Vec<Watch>,
+}
+
+impl From<User> for StoredUser {
+    fn from(mut user: User) -> StoredUser {
+        StoredUser { history: Arc::unwrap_or_clone(std::mem::take(&mut user.history)), user }
+    }
+}
+
+impl From<StoredUser> for User {
+    fn from(stored: StoredUser) -> User {
+        User { history: Arc::new(stored.history), ..stored.user }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum UserChange {
+    // Leaves the movie where it is if it's already there, as does AddFavorite.
+    AddToWatchlist(String),
+    RemoveFromWatchlist(String),
+    AddFavorite(String),
+    RemoveFavorite(String),
+    Watched(Watch),
+}
+
+impl UserChange {
+    // The movie the change brings in, which has to exist.
+    pub fn added_movie(&self) -> Option<&str> {
+        match self {
+            UserChange::AddToWatchlist(movie_id) | UserChange::AddFavorite(movie_id) => Some(movie_id),
+            UserChange::Watched(watch) => Some(&watch.movie_id),
+            UserChange::RemoveFromWatchlist(_) | UserChange::RemoveFavorite(_) => None,
+        }
+    }
+}
+
+impl User {
+    pub fn apply(&mut self, change: UserChange) {
+        match change {
+            UserChange::AddToWatchlist(movie_id) => add_once(&mut self.watchlist, movie_id),
+            UserChange::RemoveFromWatchlist(movie_id) => self.watchlist.retain(|other| *other != movie_id),
+            UserChange::AddFavorite(movie_id) => add_once(&mut self.favorites, movie_id),
+            UserChange::RemoveFavorite(movie_id) => self.favorites.retain(|other| *other != movie_id),
+            UserChange::Watched(watch) => Arc::make_mut(&mut self.history).push(watch),
+        }
+    }
+
+    // For when the movie is deleted.
+    pub fn forget(&mut self, movie_id: &str) {
+        self.watchlist.retain(|other| other != movie_id);
+        self.favorites.retain(|other| other != movie_id);
+    }
+}
+
+fn add_once(movie_ids: &mut Vec<String>, movie_id: String) {
+    if !movie_ids.contains(&movie_id) {
+        movie_ids.push(movie_id);
+    }
+}
+
+// How a movie relates to the one it links to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Relation {
+    SequelOf,
+    RemakeOf,
+    PartOfFranchise,
+}
+
+impl Relation {
+    pub fn name(self) -> &'static str {
+        match self {
+            Relation::SequelOf => "sequel-of",
+            Relation::RemakeOf => "remake-of",
+            Relation::PartOfFranchise => "part-of-franchise",
+        }
+    }
+
+    // The relation as seen from the movie linked to.
+    pub fn inverse_name(self) -> &'static str {
+        match self {
+            Relation::SequelOf => "followed-by",
+            Relation::RemakeOf => "remade-as",
+            Relation::PartOfFranchise => "part-of-franchise",
+        }
+    }
+}
+
+// One change to a movie, as kept by stores that keep every change, see event_sourced.rs.
+#[derive(Debug, Clone, Serialize)]
+pub struct Revision {
+    // Where the change is in the store's log. Counts every change to anything, so revisions of different movies can be
+    // put in order too.
+    pub seq: u64,
+    // created, updated or deleted, like MovieEvent, or restored from the trash.
+    pub event: &'static str,
+    // RFC 3339, in UTC.
+    pub at: String,
+    // As it was after the change, or right before it for a delete.
+    pub movie: Movie,
+}
+
+// One movie's relation to another, e.g. aliens is the sequel-of alien. Kept by the store apart from the movies, and
+// gone when either of them is.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Link {
+    pub from: String,
+    pub relation: Relation,
+    pub to: String,
+}
+
+impl Link {
+    // Being in the same franchise goes both ways, so those links always point from the lower id to the higher one and
+    // linking them the other way round is the same link.
+    pub fn new(from: String, relation: Relation, to: String) -> Link {
+        if relation == Relation::PartOfFranchise && to < from {
+            return Link { from: to, relation, to: from };
+        }
+        Link { from, relation, to }
+    }
+
+    pub fn touches(&self, movie_id: &str) -> bool {
+        self.from == movie_id || self.to == movie_id
+    }
+}
+
+// Body of POST /movie. Clients normally leave the id out and let the server pick one, but may still supply their own.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NewMovie {
+    #[serde(default)]
+    pub id: Option<String>,
+    pub name: String,
+    pub year: u16,
+    pub was_good: bool,
+    #[serde(default)]
+    pub genres: Vec<String>,
+    #[serde(default)]
+    pub director: Option<String>,
+    #[serde(default)]
+    pub runtime_minutes: Option<u16>,
+    #[serde(default)]
+    pub synopsis: Option<String>,
+    #[serde(default)]
+    pub poster_url: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub titles: BTreeMap<String, String>,
+}
+
+// A page of GET /movies and the other listings of movies.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MoviePage {
+    pub items: Vec<Movie>,
+    pub total: usize,
+    // Link to the following page, or None if this is the last one.
+    pub next: Option<String>,
+    // Only when paging with ?cursor=, the cursor the next link has in it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+}
+
+impl Movie { 
+    // Strong ETag (RFC 9110) for the movie as it is: a 64-bit FNV-1a hash of its JSON. Any change to any field changes
+    // it, and it's the same across restarts.
+    pub fn etag(&self) -> String { 
+        let json = serde_json::to_vec(self).expect("movies always serialize");
+        let hash = json.iter().fold(0xcbf29ce484222325u64, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3));
+        format!("\"{:016x}\"", hash)
+    }
+
+    // Numbers the movie as the one replacing `previous`, or as a new one if nothing is being replaced. Ratings aren't
+    // part of what gets replaced, nor are reviews or the poster, so they're taken over from `previous` whatever the
+    // new movie said about them.
+    pub fn succeed(&mut self, previous: Option<&Movie>) { 
+        self.version = previous.map_or(1, |previous| previous.version + 1);
+        self.touch(previous);
+        self.ratings = previous.map(|previous| previous.ratings.clone()).unwrap_or_default();
+        self.update_rating();
+        self.reviews = previous.map(|previous| previous.reviews.clone()).unwrap_or_default();
+        self.review_count = self.reviews.len();
+        self.poster = previous.and_then(|previous| previous.poster.clone());
+    }
+
+    // When the movie last changed, if that's known.
+    pub fn updated(&self) -> Option<OffsetDateTime> {
+        self.updated_at.as_deref().and_then(|updated_at| OffsetDateTime::parse(updated_at, &Rfc3339).ok())
+    }
+
+    // Stamps the movie as changed now, and as added when `previous` was. Stores that log a movie before handing it to
+    // the store inside them stamp it first, and both have to give it the same stamp or replaying the log would change
+    // it, so a movie already stamped later than `previous` keeps its stamp. One carried over from a stale read is older
+    // and replaced.
+    fn touch(&mut self, previous: Option<&Movie>) {
+        let stamped = match (self.updated(), previous.and_then(Movie::updated)) {
+            (Some(updated), Some(before)) => updated > before,
+            (updated, _) => updated.is_some(),
+        };
+        if !stamped {
+            self.updated_at = Some(timestamp());
+        }
+        self.created_at = match previous {
+            Some(previous) => previous.created_at.clone(),
+            None => self.created_at.take().or_else(|| self.updated_at.clone()),
+        };
+    }
+
+    // Sets the user's score, replacing any they gave before.
+    pub fn rate(&mut self, user: String, score: u8) {
+        self.ratings.insert(user, score);
+        self.update_rating();
+    }
+
+    pub fn add_review(&mut self, review: Review) {
+        Arc::make_mut(&mut self.reviews).push(review);
+        self.review_count = self.reviews.len();
+    }
+
+    // Takes in a duplicate's ratings, reviews, tags and titles. A user who rated both keeps the score they gave this
+    // one, the reviews stay oldest first, and where both have a title in a language this one's is kept.
+    pub fn absorb(&mut self, duplicate: Movie) {
+        for (user, score) in duplicate.ratings {
+            self.ratings.entry(user).or_insert(score);
+        }
+        self.update_rating();
+        let reviews = Arc::make_mut(&mut self.reviews);
+        reviews.extend(duplicate.reviews.iter().cloned());
+        // RFC 3339 in UTC, so they sort as strings.
+        reviews.sort_by(|a, b| a.created_at.cmp(&b.created_at));
+        self.review_count = self.reviews.len();
+        for tag in duplicate.tags {
+            if !self.tags.iter().any(|other| other.to_lowercase() == tag.to_lowercase()) {
+                self.tags.push(tag);
+            }
+        }
+        for (language, title) in duplicate.titles {
+            if !self.titles.keys().any(|other| other.eq_ignore_ascii_case(&language)) {
+                self.titles.insert(language, title);
+            }
+        }
+    }
+
+    fn update_rating(&mut self) {
+        self.rating_count = self.ratings.len();
+        let total: u32 = self.ratings.values().map(|&score| u32::from(score)).sum();
+        self.average_rating = (self.rating_count > 0).then(|| f64::from(total) / self.rating_count as f64);
+    }
+}
+
+impl NewMovie { 
+    pub fn into_movie(self) -> Movie { 
+        let now = timestamp();
+        Movie { 
+            id: self.id.unwrap_or_else(random::uuid_v7),
+            name: self.name,
+            year: self.year,
+            was_good: self.was_good,
+            genres: self.genres,
+            director: self.director,
+            runtime_minutes: self.runtime_minutes,
+            synopsis: self.synopsis,
+            poster_url: self.poster_url,
+            tags: self.tags,
+            titles: self.titles,
+            version: 1,
+            created_at: Some(now.clone()),
+            updated_at: Some(now),
+            ..Movie::default()
+        }
+    }
+}
+
+// Partial update for PATCH, following JSON Merge Patch (RFC 7396): fields left out of the patch are left alone. An
+// explicit null means "remove", which only the optional details can be, so it's rejected for the rest.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MoviePatch {
+    #[serde(default, deserialize_with = "non_null")]
+    pub id: Option<String>,
+    #[serde(default, deserialize_with = "non_null")]
+    pub name: Option<String>,
+    #[serde(default, deserialize_with = "non_null")]
+    pub year: Option<u16>,
+    #[serde(default, deserialize_with = "non_null")]
+    pub was_good: Option<bool>,
+    // Some(None) to remove the detail, or for genres Some(empty).
+    #[serde(default, deserialize_with = "nullable")]
+    pub genres: Option<Option<Vec<String>>>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub director: Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub runtime_minutes: Option<Option<u16>>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub synopsis: Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub poster_url: Option<Option<String>>,
+    // Some(None) or Some(empty) to remove every tag.
+    #[serde(default, deserialize_with = "nullable")]
+    pub tags: Option<Option<Vec<String>>>,
+    // Replaces every title, like tags, rather than being merged into them.
+    #[serde(default, deserialize_with = "nullable")]
+    pub titles: Option<Option<BTreeMap<String, String>>>,
+    // The version the client last read. If it's given, the patch is only applied to that version.
+    #[serde(default, deserialize_with = "non_null")]
+    pub version: Option<u64>,
+    // A user and their score, for POST /movie/{id}/ratings. Not something a PATCH can send.
+    #[serde(skip)]
+    pub rating: Option<(String, u8)>,
+    // Likewise for POST /movie/{id}/reviews.
+    #[serde(skip)]
+    pub review: Option<Review>,
+    // And for PUT /movie/{id}/poster.
+    #[serde(skip)]
+    pub poster: Option<Poster>,
+    // A duplicate of the movie, for POST /admin/merge, whose ratings, reviews, tags and titles it takes in, see
+    // Movie::absorb.
+    #[serde(skip)]
+    pub merge: Option<Box<Movie>>,
+    // When the patch was made, which is when it's applied unless a store that applies it twice sets it first, so that
+    // both come out the same.
+    #[serde(skip)]
+    pub updated_at: Option<String>,
+}
+
+fn non_null<'de, D: Deserializer<'de>, T: Deserialize<'de>>(deserializer: D) -> Result<Option<T>, D::Error> { 
+    T::deserialize(deserializer).map(Some)
+}
+
+// Only called for fields that are there, so a null comes out as Some(None) rather than the None of a missing field.
+fn nullable<'de, D: Deserializer<'de>, T: Deserialize<'de>>(deserializer: D) -> Result<Option<Option<T>>, D::Error> {
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+impl MoviePatch { 
+    pub fn expects(&self, movie: &Movie) -> bool { 
+        self.version.is_none_or(|version| version == movie.version)
+    }
+
+    // Also moves the movie on to its next version, and stamps it as changed, whether or not any field actually
+    // changed.
+    pub fn apply(self, movie: &mut Movie) { 
+        movie.version += 1;
+        movie.updated_at = Some(self.updated_at.unwrap_or_else(timestamp));
+        if let Some(name) = self.name {
+            movie.name = name;
+        }
+        if let Some(year) = self.year {
+            movie.year = year;
+        }
+        if let Some(was_good) = self.was_good {
+            movie.was_good = was_good;
+        }
+        if let Some(genres) = self.genres {
+            movie.genres = genres.unwrap_or_default();
+        }
+        if let Some(director) = self.director {
+            movie.director = director;
+        }
+        if let Some(runtime_minutes) = self.runtime_minutes {
+            movie.runtime_minutes = runtime_minutes;
+        }
+        if let Some(synopsis) = self.synopsis {
+            movie.synopsis = synopsis;
+        }
+        if let Some(poster_url) = self.poster_url {
+            movie.poster_url = poster_url;
+        }
+        if let Some(tags) = self.tags {
+            movie.tags = tags.unwrap_or_default();
+        }
+        if let Some(titles) = self.titles {
+            movie.titles = titles.unwrap_or_default();
+        }
+        if let Some((user, score)) = self.rating {
+            movie.rate(user, score);
+        }
+        if let Some(review) = self.review {
+            movie.add_review(review);
+        }
+        if let Some(poster) = self.poster {
+            movie.poster = Some(poster);
+        }
+        if let Some(duplicate) = self.merge {
+            movie.absorb(*duplicate);
+        }
+    }
+}
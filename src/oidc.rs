@@ -0,0 +1,308 @@
+use std::{collections::HashMap, fmt, sync::{LazyLock, Mutex}, time::{Duration, Instant, SystemTime, UNIX_EPOCH}};
+use axum::http::{header, HeaderMap};
+use serde::Deserialize;
+use tracing::{debug, info, warn};
+
+use crate::auth::{self, Caller, Role};
+use crate::crypto::{self, RsaPublicKey};
+use crate::error::ApiError;
+use crate::http_client;
+use crate::jwt::{self, JwtConfig, JwtKey};
+use crate::random;
+
+// Logs people in through an OpenID Connect provider, with the authorization code flow and PKCE, so operators can use
+// the admin routes from a browser. A login ends with a session cookie, which only counts on the admin routes; API
+// clients keep using keys and bearer tokens. Like every outbound call here it only speaks plain http, so the provider
+// has to be reachable over http://, e.g. through a TLS-terminating proxy next to the service.
+
+pub const SESSION_COOKIE: &str = "movies_session";
+// Where a login goes back to when it didn't say.
+pub const DEFAULT_RETURN_TO: &str = "/admin/session";
+
+// How long someone has at the provider before they have to start the login again.
+const LOGIN_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+// Sessions end with the ID token they came from, but never last longer than this.
+const MAX_SESSION: Duration = Duration::from_secs(8 * 60 * 60);
+// Anyone can start a login, so there's a limit on how many can be waiting for the provider.
+const MAX_PENDING_LOGINS: usize = 10_000;
+const PROVIDER_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Clone, PartialEq)]
+pub struct OidcConfig {
+    // The provider's issuer URL, which /.well-known/openid-configuration is found under.
+    pub issuer: String,
+    pub client_id: String,
+    pub client_secret: String,
+    // This service's /admin/callback as the provider should send people back to it.
+    pub redirect_url: String,
+}
+
+// Keeps the secret out of logs.
+impl fmt::Debug for OidcConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OidcConfig")
+            .field("issuer", &self.issuer)
+            .field("client_id", &self.client_id)
+            .field("redirect_url", &self.redirect_url)
+            .finish_non_exhaustive()
+    }
+}
+
+// The parts of the provider's discovery document we use.
+#[derive(Debug, Clone, Deserialize)]
+struct Provider {
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: Option<String>,
+}
+
+struct PendingLogin {
+    nonce: String,
+    // The PKCE code_verifier, whose hash went to the provider with the login.
+    verifier: String,
+    return_to: String,
+    started: Instant,
+}
+
+struct Session {
+    subject: Option<String>,
+    role: Option<Role>,
+    expires: Instant,
+}
+
+struct Logins {
+    config: Option<OidcConfig>,
+    // Looked up the first time someone logs in.
+    provider: Option<Provider>,
+    // The provider's RS256 keys by kid, fetched again when a token names one we don't know.
+    keys: HashMap<String, RsaPublicKey>,
+    // By the state parameter sent to the provider.
+    pending: HashMap<String, PendingLogin>,
+    // By the id in the session cookie.
+    sessions: HashMap<String, Session>,
+}
+
+static LOGINS: LazyLock<Mutex<Logins>> = LazyLock::new(|| Mutex::new(Logins {
+    config: None,
+    provider: None,
+    keys: HashMap::new(),
+    pending: HashMap::new(),
+    sessions: HashMap::new(),
+}));
+
+// Changing the provider logs everyone out, and None turns logins off.
+pub fn set_config(config: Option<OidcConfig>) {
+    let mut logins = LOGINS.lock().unwrap();
+    if logins.config != config {
+        logins.config = config;
+        logins.provider = None;
+        logins.keys.clear();
+        logins.pending.clear();
+        logins.sessions.clear();
+    }
+}
+
+pub fn enabled() -> bool {
+    LOGINS.lock().unwrap().config.is_some()
+}
+
+fn config() -> Result<OidcConfig, ApiError> {
+    LOGINS.lock().unwrap().config.clone().ok_or_else(|| ApiError::NotFound("OIDC login isn't configured".to_string()))
+}
+
+fn random_token() -> String {
+    let mut bytes = [0u8; 32];
+    random::fill_bytes(&mut bytes);
+    crypto::base64url_encode(&bytes)
+}
+
+fn unavailable(message: String) -> ApiError {
+    warn!("{}", message);
+    ApiError::Unavailable(message)
+}
+
+async fn get_json<T: for<'de> Deserialize<'de>>(url: &str) -> Result<T, ApiError> {
+    let response = http_client::request("GET", url, &[("Accept", "application/json")], b"", PROVIDER_TIMEOUT).await
+        .map_err(|e| unavailable(format!("Couldn't reach the identity provider at {}: {}", url, e)))?;
+    if response.status != 200 {
+        return Err(unavailable(format!("The identity provider returned {} for {}", response.status, url)));
+    }
+    serde_json::from_slice(&response.body).map_err(|e| unavailable(format!("Unexpected response from {}: {}", url, e)))
+}
+
+async fn provider(config: &OidcConfig) -> Result<Provider, ApiError> {
+    if let Some(provider) = LOGINS.lock().unwrap().provider.clone() {
+        return Ok(provider);
+    }
+    let url = format!("{}/.well-known/openid-configuration", config.issuer.trim_end_matches('/'));
+    let provider: Provider = get_json(&url).await?;
+    info!("Using OIDC provider {}", config.issuer);
+    LOGINS.lock().unwrap().provider = Some(provider.clone());
+    Ok(provider)
+}
+
+#[derive(Deserialize)]
+struct Jwks {
+    keys: Vec<Jwk>,
+}
+
+#[derive(Deserialize)]
+struct Jwk {
+    kty: String,
+    kid: Option<String>,
+    n: Option<String>,
+    e: Option<String>,
+}
+
+// The key to check an ID token with: the client secret for HS256, which providers use for confidential clients, or
+// one of the provider's published keys for RS256.
+async fn signing_key(config: &OidcConfig, provider: &Provider, id_token: &str) -> Result<JwtKey, ApiError> {
+    let refused = |reason: String| ApiError::Unauthorized(format!("Invalid ID token: {}", reason));
+    let header = jwt::header(id_token).map_err(refused)?;
+    if header.alg == "HS256" {
+        return Ok(JwtKey::Hs256(config.client_secret.as_bytes().to_vec()));
+    }
+    let kid = header.kid.unwrap_or_default();
+    if let Some(key) = LOGINS.lock().unwrap().keys.get(&kid) {
+        return Ok(JwtKey::Rs256(key.clone()));
+    }
+    let jwks_uri = provider.jwks_uri.as_deref().ok_or_else(|| refused("the provider publishes no keys".to_string()))?;
+    let jwks: Jwks = get_json(jwks_uri).await?;
+    let mut keys = HashMap::new();
+    for jwk in jwks.keys.into_iter().filter(|jwk| jwk.kty == "RSA") {
+        let (Some(n), Some(e)) = (jwk.n.as_deref().and_then(crypto::base64_decode), jwk.e.as_deref().and_then(crypto::base64_decode)) else {
+            continue;
+        };
+        match RsaPublicKey::from_components(&n, &e) {
+            Ok(key) => {
+                keys.insert(jwk.kid.unwrap_or_default(), key);
+            },
+            Err(e) => warn!("Skipping the provider's key {:?}: {}", jwk.kid, e),
+        }
+    }
+    let key = keys.get(&kid).cloned();
+    LOGINS.lock().unwrap().keys = keys;
+    key.map(JwtKey::Rs256).ok_or_else(|| refused(format!("signed with unknown key {:?}", kid)))
+}
+
+// Starts a login and returns the provider URL to send the browser to. return_to is where it ends up afterwards, and
+// has to be a path here so nobody can use the login to send people elsewhere.
+pub async fn begin_login(return_to: Option<String>) -> Result<String, ApiError> {
+    let config = config()?;
+    let return_to = return_to.unwrap_or_else(|| DEFAULT_RETURN_TO.to_string());
+    if !return_to.starts_with('/') || return_to.starts_with("//") || return_to.contains('\\') {
+        return Err(ApiError::BadRequest(format!("return_to has to be a path on this server, got {:?}", return_to)));
+    }
+    let provider = provider(&config).await?;
+
+    let (state, nonce, verifier) = (random_token(), random_token(), random_token());
+    let challenge = crypto::base64url_encode(&crypto::sha256(verifier.as_bytes()));
+    {
+        let mut logins = LOGINS.lock().unwrap();
+        logins.pending.retain(|_, login| login.started.elapsed() < LOGIN_TIMEOUT);
+        if logins.pending.len() >= MAX_PENDING_LOGINS {
+            return Err(ApiError::Unavailable("Too many logins in progress, try again shortly".to_string()));
+        }
+        logins.pending.insert(state.clone(), PendingLogin { nonce: nonce.clone(), verifier, return_to, started: Instant::now() });
+    }
+    let query = serde_urlencoded::to_string([
+        ("response_type", "code"),
+        ("client_id", &config.client_id),
+        ("redirect_uri", &config.redirect_url),
+        ("scope", "openid profile"),
+        ("state", &state),
+        ("nonce", &nonce),
+        ("code_challenge", &challenge),
+        ("code_challenge_method", "S256"),
+    ]).map_err(|e| ApiError::Internal(format!("Failed to build the login URL: {}", e)))?;
+    let separator = if provider.authorization_endpoint.contains('?') { '&' } else { '?' };
+    Ok(format!("{}{}{}", provider.authorization_endpoint, separator, query))
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+// A login that went through, for the callback to answer with.
+pub struct Login {
+    // The session's id, for the cookie.
+    pub session: String,
+    pub max_age: Duration,
+    pub return_to: String,
+}
+
+// Finishes the login the provider sent the browser back from: swaps the code for an ID token, checks it, and starts
+// a session with the roles it lists.
+pub async fn finish_login(code: &str, state: &str) -> Result<Login, ApiError> {
+    let config = config()?;
+    let login = LOGINS.lock().unwrap().pending.remove(state)
+        .filter(|login| login.started.elapsed() < LOGIN_TIMEOUT)
+        .ok_or_else(|| ApiError::BadRequest("Unknown or expired login, start again at /admin/login".to_string()))?;
+    let provider = provider(&config).await?;
+
+    let form = serde_urlencoded::to_string([
+        ("grant_type", "authorization_code"),
+        ("code", code),
+        ("redirect_uri", &config.redirect_url),
+        ("client_id", &config.client_id),
+        ("client_secret", &config.client_secret),
+        ("code_verifier", &login.verifier),
+    ]).map_err(|e| ApiError::Internal(format!("Failed to build the token request: {}", e)))?;
+    let headers = [("Content-Type", "application/x-www-form-urlencoded"), ("Accept", "application/json")];
+    let response = http_client::request("POST", &provider.token_endpoint, &headers, form.as_bytes(), PROVIDER_TIMEOUT).await
+        .map_err(|e| unavailable(format!("Couldn't reach the identity provider at {}: {}", provider.token_endpoint, e)))?;
+    if response.status != 200 {
+        debug!("Token endpoint returned {}: {}", response.status, String::from_utf8_lossy(&response.body));
+        return Err(ApiError::Unauthorized(format!("The identity provider refused the login with {}", response.status)));
+    }
+    let tokens: TokenResponse = serde_json::from_slice(&response.body)
+        .map_err(|e| unavailable(format!("Unexpected response from {}: {}", provider.token_endpoint, e)))?;
+
+    let key = signing_key(&config, &provider, &tokens.id_token).await?;
+    let checks = JwtConfig { key, issuer: Some(config.issuer.clone()), audience: Some(config.client_id.clone()) };
+    let claims = jwt::validate(&checks, &tokens.id_token).map_err(|reason| ApiError::Unauthorized(format!("Invalid ID token: {}", reason)))?;
+    if claims.nonce.as_deref() != Some(login.nonce.as_str()) {
+        return Err(ApiError::Unauthorized("Invalid ID token: it's for a different login".to_string()));
+    }
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |now| now.as_secs());
+    let max_age = claims.expires_at.map_or(MAX_SESSION, |exp| Duration::from_secs(exp.saturating_sub(now))).min(MAX_SESSION);
+    let role = claims.roles.iter().filter_map(|name| Role::from_name(name)).max();
+    info!("{:?} logged in with role {:?}", claims.subject, role);
+    let session = random_token();
+    let mut logins = LOGINS.lock().unwrap();
+    let now = Instant::now();
+    logins.sessions.retain(|_, session| session.expires > now);
+    logins.sessions.insert(session.clone(), Session { subject: claims.subject, role, expires: now + max_age });
+    Ok(Login { session, max_age, return_to: login.return_to })
+}
+
+fn session_id(headers: &HeaderMap) -> Option<String> {
+    headers.get_all(header::COOKIE).iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .find_map(|cookie| cookie.trim().strip_prefix(SESSION_COOKIE)?.strip_prefix('=').map(str::to_string))
+}
+
+// The caller behind the request's session cookie, if it has a live one.
+pub fn session_caller(headers: &HeaderMap) -> Option<Caller> {
+    let id = session_id(headers)?;
+    let logins = LOGINS.lock().unwrap();
+    let session = logins.sessions.get(&id).filter(|session| session.expires > Instant::now())?;
+    let credential = Some(auth::fingerprint("session", id.as_bytes()));
+    Some(Caller { subject: session.subject.clone(), role: session.role, authenticated: true, key: None, tenant: None, credential })
+}
+
+pub fn end_session(headers: &HeaderMap) {
+    if let Some(id) = session_id(headers) {
+        LOGINS.lock().unwrap().sessions.remove(&id);
+    }
+}
+
+// The Set-Cookie value for a session, or to clear the cookie with an empty id and no max age. It's only sent to the
+// admin routes, and Secure whenever the service is reached over https.
+pub fn session_cookie(id: &str, max_age: Duration) -> String {
+    let secure = LOGINS.lock().unwrap().config.as_ref().is_some_and(|config| config.redirect_url.starts_with("https://"));
+    format!("{}={}; Path=/admin; HttpOnly; SameSite=Lax; Max-Age={}{}", SESSION_COOKIE, id, max_age.as_secs(), if secure { "; Secure" } else { "" })
+}
@@ -0,0 +1,1985 @@
+use serde_json::{json, Value};
+
+use crate::changes::MAX_CHANGES;
+use crate::posters::{CONTENT_TYPES, MAX_POSTER_BYTES};
+use crate::locale::MAX_LANGUAGE_LEN;
+use crate::validation::{FIRST_MOVIE_YEAR, MAX_DIRECTOR_LEN, MAX_GENRES, MAX_GENRE_LEN, MAX_ID_LEN, MAX_NAME_LEN, MAX_POSTER_URL_LEN, MAX_REVIEW_LEN, MAX_RUNTIME_MINUTES, MAX_SCORE, MAX_SYNOPSIS_LEN, MAX_TAGS, MAX_TAG_LEN, MAX_TITLES, MAX_USER_LEN, MAX_FAVORITES, MAX_WATCHLIST_LEN, MAX_WEBHOOK_SECRET_LEN, MAX_WEBHOOK_URL_LEN, MIN_SCORE, MIN_WEBHOOK_SECRET_LEN};
+use crate::webhooks::{EVENTS, MAX_WEBHOOKS};
+
+// The OpenAPI 3 description of every route in build_router, served at /api-docs/openapi.json. Versioned routes are only
+// listed under /v1. Written out by hand, tests/openapi.rs checks the schemas against what the serde types actually
+// produce.
+pub fn document() -> Value {
+    let mut document = json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "Movies API",
+            "version": env!("CARGO_PKG_VERSION"),
+            "description": "The movie routes and GraphQL are also served without the /v1 prefix, as they were before there were versions. Those paths are deprecated and answer with a Deprecation header and a Link to their /v1 path.",
+        },
+        "paths": {
+            "/v1/movie": {
+                "post": {
+                    "summary": "Add a movie",
+                    "operationId": "createMovie",
+                    "description": "With a metadata provider configured, a movie sent with nothing but its name and year gets its runtime, genres and poster_url from the provider, if it knows the movie and can be reached.",
+                    "parameters": [{
+                        "name": "upsert", "in": "query", "required": false,
+                        "description": "Overwrite a movie with the same id instead of failing with 409.",
+                        "schema": { "type": "boolean", "default": false },
+                    }, allow_duplicate_parameter(), idempotency_key_parameter()],
+                    "requestBody": movie_body("NewMovie"),
+                    "responses": {
+                        "201": {
+                            "description": "Created",
+                            "headers": {
+                                "Location": { "description": "Path of the new movie", "schema": { "type": "string" } },
+                                "Warning": warning_header(),
+                            },
+                            "content": movie_content(schema_ref("Movie")),
+                        },
+                        "200": { "description": "Overwritten, with ?upsert=true", "headers": { "Warning": warning_header() }, "content": movie_content(schema_ref("Movie")) },
+                        "400": error_response("Malformed body or query"),
+                        "409": error_response("A movie with this id already exists, or with --duplicates=reject one with the same name and year (duplicate), see details.existing, or a request with the same Idempotency-Key is still running"),
+                        "415": error_response("Body isn't JSON, MessagePack or CBOR"),
+                        "422": error_response("Invalid field values, see details.fields, or the Idempotency-Key was used for a different request"),
+                    },
+                },
+            },
+            "/v1/movies": {
+                "get": {
+                    "summary": "List movies, in id order unless sorted otherwise",
+                    "operationId": "listMovies",
+                    "parameters": ([
+                        vec![
+                            query_parameter("limit", json!({ "type": "integer", "minimum": 1, "maximum": 100, "default": 20 }), "Page size"),
+                            query_parameter("offset", json!({ "type": "integer", "minimum": 0, "default": 0 }), "Movies to skip"),
+                        ],
+                        filter_parameters(),
+                        vec![query_parameter("sort", json!({ "type": "string", "example": "year:desc,name:asc" }),
+                            "Comma-separated keys to order by, of id, name, year and was_good, each with :asc or :desc. Ties are broken by id")],
+                        vec![query_parameter("cursor", json!({ "type": "string" }),
+                            "Page by cursor instead of offset: empty for the first page, then next_cursor from the one before. Pages then neither skip nor repeat movies when others are added or removed in between")],
+                        vec![query_parameter("updated_since", json!({ "type": "string", "format": "date-time" }),
+                            "Only movies changed at or after this time, e.g. the latest updated_at of the last sync. Movies that haven't changed since there were timestamps never match")],
+                    ].concat()),
+                    "responses": {
+                        "200": { "description": "One page of movies", "content": movie_content(schema_ref("MoviePage")) },
+                        "400": error_response("Malformed query, an unknown sort key or direction, a cursor that isn't valid, is for another sort or comes with an offset, or an updated_since that isn't RFC 3339"),
+                    },
+                },
+            },
+            "/v1/movies/search": {
+                "get": {
+                    "summary": "Search movie names",
+                    "operationId": "searchMovies",
+                    "description": "Matches the words of q against the words of movie names, ignoring case and accents, and also the words they're the start of. Movies matching any word are ranked by BM25, best first.",
+                    "parameters": [
+                        { "name": "q", "in": "query", "required": true, "description": "Words to search for", "schema": { "type": "string" } },
+                        query_parameter("limit", json!({ "type": "integer", "minimum": 1, "maximum": 100, "default": 20 }), "Page size"),
+                        query_parameter("offset", json!({ "type": "integer", "minimum": 0, "default": 0 }), "Results to skip"),
+                    ],
+                    "responses": {
+                        "200": { "description": "One page of results", "content": movie_content(schema_ref("SearchPage")) },
+                        "400": error_response("Missing q, one without any words, or a malformed query"),
+                    },
+                },
+            },
+            "/v1/movies/suggest": {
+                "get": {
+                    "summary": "Autocomplete movie names",
+                    "operationId": "suggestMovieNames",
+                    "parameters": [
+                        { "name": "prefix", "in": "query", "required": true, "description": "Start of the name, in any case and with or without accents", "schema": { "type": "string" } },
+                        query_parameter("limit", json!({ "type": "integer", "minimum": 1, "maximum": 100, "default": 10 }), "Most names to return"),
+                    ],
+                    "responses": {
+                        "200": { "description": "Distinct names starting with prefix, in alphabetical order", "content": movie_content(json!({
+                            "type": "object",
+                            "required": ["names"],
+                            "properties": { "names": { "type": "array", "items": { "type": "string" } } },
+                        })) },
+                        "400": error_response("Missing or empty prefix, or a malformed query"),
+                    },
+                },
+            },
+            "/v1/movies/similar": {
+                "get": {
+                    "summary": "Find movies that are probably the same as a name",
+                    "operationId": "findSimilarMovies",
+                    "description": "Names are compared by Jaro-Winkler similarity, ignoring case, accents, punctuation, a leading \"The\" and a trailing \"(year)\". Movies from another year than the one given score a little lower.",
+                    "parameters": [
+                        { "name": "name", "in": "query", "required": true, "description": "Name to match, e.g. The Matr1x (1999)", "schema": { "type": "string" } },
+                        query_parameter("year", json!({ "type": "integer" }), "Year of the movie, instead of one at the end of the name"),
+                        query_parameter("threshold", json!({ "type": "number", "minimum": 0, "maximum": 1, "default": 0.85 }), "Lowest score to include"),
+                        query_parameter("limit", json!({ "type": "integer", "minimum": 1, "maximum": 100, "default": 20 }), "Most movies to return"),
+                    ],
+                    "responses": {
+                        "200": { "description": "The matches, most similar first", "content": movie_content(json!({
+                            "type": "object",
+                            "required": ["items"],
+                            "properties": { "items": { "type": "array", "items": {
+                                "type": "object",
+                                "required": ["movie", "score", "distance"],
+                                "properties": {
+                                    "movie": schema_ref("Movie"),
+                                    "score": { "type": "number", "description": "Similarity from 0 to 1" },
+                                    "distance": { "type": "integer", "description": "Levenshtein distance between the normalized names" },
+                                },
+                            } } },
+                        })) },
+                        "400": error_response("Missing or empty name, a threshold outside 0 to 1, or a malformed query"),
+                    },
+                },
+            },
+            "/v1/movies/stats": {
+                "get": {
+                    "summary": "Summarize the catalog",
+                    "operationId": "movieStats",
+                    "responses": {
+                        "200": { "description": "Counts and year statistics over every movie", "content": movie_content(schema_ref("MovieStats")) },
+                    },
+                },
+            },
+            "/v1/movies/random": {
+                "get": {
+                    "summary": "Pick a random movie",
+                    "operationId": "randomMovie",
+                    "description": "Every movie matching the filters is equally likely.",
+                    "parameters": filter_parameters(),
+                    "responses": {
+                        "200": { "description": "The movie", "content": movie_content(schema_ref("Movie")) },
+                        "400": error_response("Malformed query"),
+                        "404": error_response("No movie matches the filters"),
+                    },
+                },
+            },
+            "/v1/movies/batch": {
+                "post": {
+                    "summary": "Add many movies at once",
+                    "operationId": "createMovies",
+                    "description": "Each item is handled like a POST /movie of its own, so some can fail while the rest go in.",
+                    "parameters": [{
+                        "name": "upsert", "in": "query", "required": false,
+                        "description": "Overwrite movies with the same id instead of reporting them as duplicates.",
+                        "schema": { "type": "boolean", "default": false },
+                    }, allow_duplicate_parameter(), idempotency_key_parameter()],
+                    "requestBody": {
+                        "required": true,
+                        "content": movie_content(json!({ "type": "array", "maxItems": 1000, "items": schema_ref("NewMovie") })),
+                    },
+                    "responses": {
+                        "200": { "description": "One result per item, in request order", "content": movie_content(schema_ref("BatchReport")) },
+                        "400": error_response("Not an array, or more than 1000 items"),
+                        "409": error_response("A request with the same Idempotency-Key is still running"),
+                        "415": error_response("Body isn't JSON, MessagePack or CBOR"),
+                        "422": error_response("The Idempotency-Key was used for a different request"),
+                    },
+                },
+            },
+            "/v1/movies/lookup": {
+                "post": {
+                    "summary": "Get many movies by id",
+                    "operationId": "lookupMovies",
+                    "description": "One request instead of a GET /movie/{id} for each, e.g. to fill in a watchlist. It only reads, so anyone who may read movies may call it.",
+                    "requestBody": {
+                        "required": true,
+                        "content": movie_content(json!({ "type": "array", "maxItems": 1000, "items": { "type": "string" } })),
+                    },
+                    "responses": {
+                        "200": { "description": "The movies found and the ids that weren't, each in request order with repeats left out", "content": movie_content(json!({
+                            "type": "object",
+                            "required": ["items", "missing"],
+                            "properties": {
+                                "items": { "type": "array", "items": schema_ref("Movie") },
+                                "missing": { "type": "array", "items": { "type": "string" } },
+                            },
+                        })) },
+                        "400": error_response("More than 1000 ids"),
+                        "415": error_response("Body isn't JSON, MessagePack or CBOR"),
+                        "422": error_response("Not an array of strings"),
+                    },
+                },
+            },
+            "/v1/movies/import": {
+                "post": {
+                    "summary": "Import movies from a CSV, JSON or NDJSON file",
+                    "operationId": "importMovies",
+                    "description": "For CSV, the first row names the columns: name, year and was_good, and optionally id, genres (separated by ;), director, runtime_minutes, synopsis and tags (separated by ;), which may be left empty. For JSON the body is an array of NewMovies, and for NDJSON it has one per line. Rows are added as they're read, so a broken upload keeps the rows before the break. The body may be gzipped, with Content-Encoding: gzip.\n\nJSON and NDJSON imports are answered while the body is still being read, with NDJSON lines: {\"progress\": {\"inserted\", \"updated\", \"rejected\"}} every 1000 records, then {\"done\": ImportReport}. If the body breaks off or isn't JSON the last line has an \"error\" too, since the status has already been sent.",
+                    "parameters": [
+                        query_parameter("format", json!({ "type": "string", "enum": ["csv", "json", "ndjson"], "default": "csv" }), "Format of the body"),
+                        query_parameter("upsert", json!({ "type": "boolean", "default": false }), "Overwrite movies with the same id instead of rejecting the row"),
+                        allow_duplicate_parameter(),
+                        query_parameter("dry_run", json!({ "type": "boolean", "default": false }), "Report what the import would add, update and reject without changing anything. It can't tell whether there's room for the movies"),
+                    ],
+                    "requestBody": {
+                        "required": true,
+                        "content": {
+                            "text/csv": { "schema": { "type": "string" } },
+                            "application/json": { "schema": { "type": "array", "items": schema_ref("NewMovie") } },
+                            "application/x-ndjson": { "schema": { "type": "string" } },
+                        },
+                    },
+                    "responses": {
+                        "200": {
+                            "description": "What was added and what wasn't: the ImportReport for CSV, progress lines then the report for JSON and NDJSON",
+                            "content": {
+                                "application/json": { "schema": schema_ref("ImportReport") },
+                                "application/x-ndjson": { "schema": { "type": "string" } },
+                            },
+                        },
+                        "400": error_response("Empty file, or a bad header row"),
+                        "415": error_response("Multipart upload, or a Content-Encoding other than gzip"),
+                    },
+                },
+            },
+            "/v1/movies/export": {
+                "get": {
+                    "summary": "Export every movie",
+                    "operationId": "exportMovies",
+                    "description": "Streamed in id order. Writes made during the export show up in it if they land after the point it has reached.",
+                    "parameters": [
+                        query_parameter("format", json!({ "type": "string", "enum": ["ndjson", "csv"], "default": "ndjson" }), "One Movie per line as JSON, or CSV with a header row that /movies/import accepts"),
+                    ],
+                    "responses": {
+                        "200": {
+                            "description": "The movies",
+                            "content": {
+                                "application/x-ndjson": { "schema": { "type": "string" } },
+                                "text/csv": { "schema": { "type": "string" } },
+                            },
+                        },
+                        "400": error_response("Unknown format"),
+                    },
+                },
+            },
+            "/v1/movies/events": {
+                "get": {
+                    "summary": "Stream changes as Server-Sent Events",
+                    "operationId": "movieEvents",
+                    "description": "Events are named created, updated or deleted and carry the movie as JSON data. A lagged event with {\"missed\": n} means the client fell behind and should reload.",
+                    "responses": { "200": { "description": "An event stream that stays open", "content": { "text/event-stream": { "schema": { "type": "string" } } } } },
+                },
+            },
+            "/v1/movies/subscribe": {
+                "get": {
+                    "summary": "Subscribe to changes over a WebSocket",
+                    "operationId": "subscribeMovies",
+                    "description": "Sends {\"event\": name, \"movie\": Movie} text messages for changes matching the filter. Send {\"filter\": {...}} with the same fields as the query to change it.",
+                    "parameters": [
+                        query_parameter("year", json!({ "type": "integer" }), "Only movies from this year"),
+                        query_parameter("min_year", json!({ "type": "integer" }), "Only movies from this year or later"),
+                        query_parameter("max_year", json!({ "type": "integer" }), "Only movies from this year or earlier"),
+                        query_parameter("was_good", json!({ "type": "boolean" }), "Only good, or only bad, movies"),
+                        query_parameter("name_contains", json!({ "type": "string" }), "Substring of the name, ignoring case and accents"),
+                    ],
+                    "responses": {
+                        "101": { "description": "Switched to the WebSocket protocol" },
+                        "400": error_response("Not a WebSocket upgrade request, or a malformed filter"),
+                    },
+                },
+            },
+            "/v1/movie/{id}": {
+                "parameters": [{ "name": "id", "in": "path", "required": true, "schema": { "type": "string" } }],
+                "get": {
+                    "summary": "Get a movie",
+                    "operationId": "getMovie",
+                    "parameters": [
+                        { "name": "If-None-Match", "in": "header", "schema": { "type": "string" }, "description": "ETags the client already has" },
+                        { "name": "If-Modified-Since", "in": "header", "schema": { "type": "string" }, "description": "Last-Modified of the client's copy, only looked at without If-None-Match" },
+                        query_parameter("as_of", json!({ "type": "string", "format": "date-time" }), "Get the movie as it was at this time instead, only with --store events://"),
+                        { "name": "Accept-Language", "in": "header", "schema": { "type": "string" }, "description": "Languages to pick the movie's title in, like ja, en;q=0.5. When it's given the movie comes with title and title_language" },
+                    ],
+                    "responses": {
+                        "200": { "description": "The movie", "headers": validator_headers(), "content": movie_content(json!({ "oneOf": [schema_ref("Movie"), schema_ref("LocalizedMovie")] })) },
+                        "304": { "description": "The movie still has an ETag listed in If-None-Match, or hasn't changed since If-Modified-Since", "headers": validator_headers() },
+                        "400": error_response("Malformed as_of"),
+                        "404": error_response("No such movie, or none at as_of"),
+                    },
+                },
+                "head": {
+                    "summary": "Check a movie",
+                    "operationId": "checkMovie",
+                    "description": "The same as GET without the body, to see whether a movie is there or whether its ETag has changed.",
+                    "parameters": [
+                        { "name": "If-None-Match", "in": "header", "schema": { "type": "string" }, "description": "ETags the client already has" },
+                        { "name": "If-Modified-Since", "in": "header", "schema": { "type": "string" }, "description": "Last-Modified of the client's copy, only looked at without If-None-Match" },
+                    ],
+                    "responses": {
+                        "200": { "description": "The movie is there, with the Content-Length a GET would get", "headers": validator_headers() },
+                        "304": { "description": "The movie still has an ETag listed in If-None-Match, or hasn't changed since If-Modified-Since", "headers": validator_headers() },
+                        "404": { "description": "No such movie" },
+                    },
+                },
+                "put": {
+                    "summary": "Replace a movie",
+                    "operationId": "replaceMovie",
+                    "parameters": [if_match_parameter()],
+                    "requestBody": movie_body("Movie"),
+                    "responses": {
+                        "200": { "description": "The movie as replaced, at its next version", "headers": etag_header(), "content": movie_content(schema_ref("Movie")) },
+                        "400": error_response("Malformed body, or the body's id doesn't match the path"),
+                        "404": error_response("No such movie"),
+                        "412": error_response("The movie is no longer at the version in the body, or no longer has the ETag in If-Match, see details.current"),
+                        "422": error_response("Invalid field values, see details.fields"),
+                    },
+                },
+                "patch": {
+                    "summary": "Merge-patch a movie",
+                    "operationId": "patchMovie",
+                    "requestBody": movie_body("MoviePatch"),
+                    "responses": {
+                        "200": { "description": "The movie after the patch", "headers": etag_header(), "content": movie_content(schema_ref("Movie")) },
+                        "400": error_response("Malformed patch, or its id doesn't match the path"),
+                        "404": error_response("No such movie"),
+                        "412": error_response("The movie is no longer at the version in the patch, see details.current"),
+                        "422": error_response("Invalid field values, see details.fields"),
+                    },
+                },
+                "delete": {
+                    "summary": "Remove a movie",
+                    "operationId": "deleteMovie",
+                    "description": "Moves the movie to the trash, where it can be restored from until it's purged. It's taken off watchlists and unlinked for good.",
+                    "parameters": [if_match_parameter()],
+                    "responses": {
+                        "204": { "description": "Removed" },
+                        "404": error_response("No such movie"),
+                        "412": error_response("The movie no longer has the ETag in If-Match, see details.current"),
+                    },
+                },
+            },
+            "/healthz": {
+                "get": {
+                    "summary": "Liveness",
+                    "operationId": "healthz",
+                    "responses": { "200": { "description": "The process is serving", "content": json_content("Status") } },
+                },
+            },
+            "/readyz": {
+                "get": {
+                    "summary": "Readiness",
+                    "operationId": "readyz",
+                    "responses": {
+                        "200": { "description": "The storage backend is usable and the seed data, if any, is in", "content": json_content("Status") },
+                        "503": error_response("The storage backend isn't usable, or the seed data is still being loaded"),
+                    },
+                },
+            },
+            "/metrics": {
+                "get": {
+                    "summary": "Prometheus metrics",
+                    "operationId": "metrics",
+                    "responses": { "200": { "description": "Text exposition format", "content": { "text/plain": { "schema": { "type": "string" } } } } },
+                },
+            },
+            "/v1/graphql": {
+                "get": {
+                    "summary": "GraphQL schema",
+                    "operationId": "graphqlSchema",
+                    "responses": { "200": { "description": "The schema in SDL", "content": { "text/plain": { "schema": { "type": "string" } } } } },
+                },
+                "post": {
+                    "summary": "Run a GraphQL query or mutation",
+                    "operationId": "graphql",
+                    "requestBody": {
+                        "required": true,
+                        "content": { "application/json": { "schema": {
+                            "type": "object",
+                            "required": ["query"],
+                            "properties": {
+                                "query": { "type": "string" },
+                                "variables": { "type": "object", "nullable": true },
+                                "operationName": { "type": "string", "nullable": true },
+                            },
+                        } } },
+                    },
+                    "responses": {
+                        "200": {
+                            "description": "The result. Problems with the query are reported in \"errors\", with the same codes as Error.",
+                            "content": { "application/json": { "schema": { "type": "object", "properties": { "data": { "nullable": true }, "errors": { "type": "array" } } } } },
+                        },
+                        "400": error_response("Malformed body"),
+                        "415": error_response("Body isn't application/json"),
+                    },
+                },
+            },
+            "/admin/login": {
+                "get": {
+                    "summary": "Log in through the OIDC provider",
+                    "operationId": "login",
+                    "parameters": [{ "name": "return_to", "in": "query", "description": "Path to go to once logged in, /admin/session by default", "schema": { "type": "string" } }],
+                    "responses": {
+                        "303": { "description": "Go to the provider's login page" },
+                        "400": error_response("return_to isn't a path on this server"),
+                        "404": error_response("OIDC login isn't configured"),
+                        "503": error_response("The provider can't be reached"),
+                    },
+                },
+            },
+            "/admin/callback": {
+                "get": {
+                    "summary": "Where the OIDC provider sends people back to",
+                    "operationId": "loginCallback",
+                    "parameters": [
+                        { "name": "code", "in": "query", "schema": { "type": "string" } },
+                        { "name": "state", "in": "query", "schema": { "type": "string" } },
+                        { "name": "error", "in": "query", "schema": { "type": "string" } },
+                    ],
+                    "responses": {
+                        "303": { "description": "Logged in, go back to return_to with a session cookie", "headers": { "Set-Cookie": { "schema": { "type": "string" } } } },
+                        "400": error_response("Unknown or expired login"),
+                        "401": error_response("The provider refused the login, or its ID token isn't valid"),
+                        "404": error_response("OIDC login isn't configured"),
+                        "503": error_response("The provider can't be reached"),
+                    },
+                },
+            },
+            "/admin/logout": {
+                "post": {
+                    "summary": "End the session",
+                    "operationId": "logout",
+                    "responses": { "204": { "description": "Logged out, the session cookie is cleared" } },
+                },
+            },
+            "/admin/session": {
+                "get": {
+                    "summary": "Who the caller is",
+                    "operationId": "getSession",
+                    "responses": { "200": { "description": "The caller", "content": json_content("Caller") } },
+                },
+            },
+        },
+        "components": {
+            "schemas": {
+                "Movie": {
+                    "type": "object",
+                    "required": ["id", "name", "year", "was_good", "version"],
+                    "properties": with_timestamps(with_version(with_ratings_and_reviews(movie_properties()), "Goes up by one with every change. PUT has to send back the version it replaces")),
+                },
+                "NewMovie": {
+                    "type": "object",
+                    "description": "A movie to add. The server generates a UUIDv7 id when none is given.",
+                    "required": ["name", "year", "was_good"],
+                    "properties": movie_properties(),
+                },
+                "MoviePatch": {
+                    "type": "object",
+                    "description": "JSON Merge Patch (RFC 7396). Fields that are left out stay as they are. null removes genres, director, runtime_minutes, synopsis, poster_url, tags or titles, and isn't allowed for the rest. titles replaces every title rather than being merged into them.",
+                    "additionalProperties": false,
+                    "properties": with_version(nullable_details(movie_properties()), "If given, the patch is only applied to this version of the movie"),
+                },
+                "MoviePage": {
+                    "type": "object",
+                    "required": ["items", "total", "next"],
+                    "properties": {
+                        "items": { "type": "array", "items": schema_ref("Movie") },
+                        "total": { "type": "integer", "description": "Movies matching the filters, across all pages" },
+                        "next": { "type": "string", "nullable": true, "description": "Link to the next page, null on the last one" },
+                        "next_cursor": { "type": "string", "description": "Only when paging by cursor and there's a next page: the cursor for it, as in next" },
+                    },
+                },
+                "SearchPage": {
+                    "type": "object",
+                    "required": ["items", "total", "next"],
+                    "properties": {
+                        "items": { "type": "array", "items": {
+                            "type": "object",
+                            "required": ["movie", "score", "highlighted"],
+                            "properties": {
+                                "movie": schema_ref("Movie"),
+                                "score": { "type": "number", "description": "Relevance, higher is better. Only comparable within one search" },
+                                "highlighted": { "type": "string", "description": "The name, or the title that matched best, HTML-escaped, with each matched word in <em></em>" },
+                                "language": { "type": "string", "description": "The language of the title that matched best, left out when it was the name" },
+                            },
+                        } },
+                        "total": { "type": "integer", "description": "Movies matching the search, across all pages" },
+                        "next": { "type": "string", "nullable": true, "description": "Link to the next page, null on the last one" },
+                    },
+                },
+                "MovieStats": {
+                    "type": "object",
+                    "required": ["total", "by_year", "by_was_good", "min_year", "max_year", "median_year"],
+                    "properties": {
+                        "total": { "type": "integer" },
+                        "by_year": { "type": "object", "description": "Movies per year, keyed by year", "additionalProperties": { "type": "integer" } },
+                        "by_was_good": {
+                            "type": "object",
+                            "required": ["true", "false"],
+                            "properties": { "true": { "type": "integer" }, "false": { "type": "integer" } },
+                        },
+                        "min_year": { "type": "integer", "nullable": true, "description": "Null when there are no movies, as are max_year and median_year" },
+                        "max_year": { "type": "integer", "nullable": true },
+                        "median_year": { "type": "number", "nullable": true, "description": "The mean of the middle two for an even number of movies" },
+                    },
+                },
+                "RatingPage": {
+                    "type": "object",
+                    "required": ["items", "total", "average_rating", "next"],
+                    "properties": {
+                        "items": { "type": "array", "items": {
+                            "type": "object",
+                            "required": ["user", "score"],
+                            "properties": { "user": { "type": "string" }, "score": { "type": "integer", "minimum": MIN_SCORE, "maximum": MAX_SCORE } },
+                        } },
+                        "total": { "type": "integer", "description": "Ratings across all pages" },
+                        "average_rating": { "type": "number", "nullable": true, "description": "Null when there are no ratings" },
+                        "next": { "type": "string", "nullable": true, "description": "Link to the next page, null on the last one" },
+                    },
+                },
+                "Review": {
+                    "type": "object",
+                    "required": ["id", "author", "text", "created_at"],
+                    "properties": {
+                        "id": { "type": "string" },
+                        "author": { "type": "string" },
+                        "text": { "type": "string" },
+                        "created_at": { "type": "string", "format": "date-time" },
+                    },
+                },
+                "ReviewPage": {
+                    "type": "object",
+                    "required": ["items", "total", "next"],
+                    "properties": {
+                        "items": { "type": "array", "items": schema_ref("Review") },
+                        "total": { "type": "integer", "description": "Reviews across all pages" },
+                        "next": { "type": "string", "nullable": true, "description": "Link to the next page, null on the last one" },
+                    },
+                },
+                "Label": {
+                    "type": "object",
+                    "description": "A genre or a tag",
+                    "required": ["name", "movie_count"],
+                    "properties": {
+                        "name": { "type": "string", "description": "As the movie with the lowest id spells it" },
+                        "movie_count": { "type": "integer", "description": "How many movies have it" },
+                    },
+                },
+                "BatchReport": {
+                    "type": "object",
+                    "required": ["created", "updated", "duplicate", "invalid", "failed", "results"],
+                    "properties": {
+                        "created": { "type": "integer" },
+                        "updated": { "type": "integer" },
+                        "duplicate": { "type": "integer" },
+                        "invalid": { "type": "integer" },
+                        "failed": { "type": "integer" },
+                        "results": { "type": "array", "items": {
+                            "type": "object",
+                            "required": ["index", "status"],
+                            "properties": {
+                                "index": { "type": "integer" },
+                                "status": { "type": "string", "enum": ["created", "updated", "duplicate", "invalid", "failed"] },
+                                "id": { "type": "string", "description": "Set unless the item was rejected" },
+                                "error": {
+                                    "type": "object",
+                                    "description": "The error's code, message and details, as in Error, for rejected items",
+                                    "required": ["code", "message", "details"],
+                                },
+                            },
+                        } },
+                    },
+                },
+                "ImportReport": {
+                    "type": "object",
+                    "required": ["inserted", "updated", "rejected", "rejections"],
+                    "properties": {
+                        "inserted": { "type": "integer" },
+                        "updated": { "type": "integer" },
+                        "rejected": { "type": "integer" },
+                        "dry_run": { "type": "boolean", "description": "Only there for dry runs, whose counts are what the import would have done" },
+                        "rejections": {
+                            "type": "array",
+                            "description": "The first 1000 rejected rows",
+                            "items": {
+                                "type": "object",
+                                "required": ["line", "error"],
+                                "properties": {
+                                    "line": { "type": "integer", "description": "Line the row starts on, the header is line 1" },
+                                    "error": { "type": "object", "description": "The error's code, message and details, as in Error" },
+                                },
+                            },
+                        },
+                    },
+                },
+                "Status": {
+                    "type": "object",
+                    "required": ["status"],
+                    "properties": { "status": { "type": "string" } },
+                },
+                "Caller": {
+                    "type": "object",
+                    "required": ["subject", "role", "authenticated"],
+                    "properties": {
+                        "subject": { "type": "string", "nullable": true, "description": "The sub of the bearer token or login" },
+                        "role": { "type": "string", "enum": ["reader", "editor", "admin"], "nullable": true },
+                        "authenticated": { "type": "boolean", "description": "Whether the caller sent a key, token or session cookie" },
+                    },
+                },
+                "FieldError": {
+                    "type": "object",
+                    "required": ["field", "message"],
+                    "properties": {
+                        "field": { "type": "string" },
+                        "message": { "type": "string" },
+                    },
+                },
+                "Error": {
+                    "type": "object",
+                    "required": ["error"],
+                    "properties": {
+                        "error": {
+                            "type": "object",
+                            "required": ["code", "message", "details", "request_id"],
+                            "properties": {
+                                "code": {
+                                    "type": "string",
+                                    "enum": ["bad_request", "not_found", "method_not_allowed", "unauthorized", "forbidden", "already_exists", "duplicate", "conflict", "precondition_failed", "idempotency_key_reused", "request_in_progress", "rate_limited", "payload_too_large", "invalid_body", "invalid_query", "invalid_path", "validation_failed", "internal", "quota_exceeded", "gone", "unavailable", "timeout"],
+                                },
+                                "message": { "type": "string" },
+                                "details": {
+                                    "nullable": true,
+                                    "description": "{\"existing\": Movie} for already_exists and duplicate, {\"current\": Movie} for precondition_failed, {\"fields\": [FieldError]} for validation_failed, {\"allowed\": [method]} for method_not_allowed, {\"limit\": bytes} for payload_too_large, QuotaExceeded for quota_exceeded, null otherwise",
+                                },
+                                "request_id": { "type": "string", "nullable": true, "description": "Same as the x-request-id response header" },
+                            },
+                        },
+                    },
+                },
+            },
+            "securitySchemes": {
+                "sessionCookie": { "type": "apiKey", "in": "cookie", "name": "movies_session", "description": "Set by logging in at /admin/login. Only counts on the /admin routes" },
+                "apiKey": { "type": "apiKey", "in": "header", "name": "X-Api-Key", "description": "Only checked when the server has --api-keys set. A valid key may do anything" },
+                "bearerAuth": {
+                    "type": "http",
+                    "scheme": "bearer",
+                    "bearerFormat": "JWT",
+                    "description": "Only accepted when the server has a JWT key set, and then needed for reads too. The roles claim (reader, editor or admin) says what the token may do",
+                },
+            },
+        },
+    });
+    for extra_paths in [genre_paths(), rating_paths(), review_paths(), revision_paths(), tag_paths(), related_paths(), poster_paths(), user_paths(), history_paths(), webhook_paths(), audit_paths(), trash_paths(), backup_paths(), tenant_paths(), quota_paths(), change_paths()] {
+        if let (Some(paths), Value::Object(extra_paths)) = (document["paths"].as_object_mut(), extra_paths) {
+            paths.extend(extra_paths);
+        }
+    }
+    for extra_schemas in [revision_schemas(), user_schemas(), webhook_schemas(), audit_schemas(), trash_schemas(), backup_schemas(), tenant_schemas(), quota_schemas(), locale_schemas(), change_schemas()] {
+        if let (Some(schemas), Value::Object(extra_schemas)) = (document["components"]["schemas"].as_object_mut(), extra_schemas) {
+            schemas.extend(extra_schemas);
+        }
+    }
+    // Everything but the health checks and these docs needs a key or token, see auth::authorize. Whether reads do
+    // depends on what the server has configured, so they list the schemes too.
+    for operations in document["paths"].as_object_mut().unwrap().values_mut() {
+        for (method, operation) in operations.as_object_mut().unwrap() {
+            let id = operation["operationId"].as_str().map(str::to_string);
+            let id = id.as_deref();
+            if method == "parameters" || matches!(id, Some("healthz" | "readyz" | "login" | "loginCallback" | "logout")) {
+                continue;
+            }
+            operation["security"] = match id {
+                Some("getSession" | "createWebhook" | "listWebhooks" | "deleteWebhook" | "listDeadLetters" | "listAuditEntries" | "createSnapshot" | "restoreSnapshot" | "mergeMovies" | "listTenants" | "getQuotas") => json!([{ "apiKey": [] }, { "bearerAuth": [] }, { "sessionCookie": [] }]),
+                _ => json!([{ "apiKey": [] }, { "bearerAuth": [] }]),
+            };
+            operation["responses"]["401"] = error_response("No valid X-Api-Key or bearer token");
+            operation["responses"]["403"] = match (method.as_str(), id) {
+                // Followers refuse writes, see replication.rs.
+                ("get", _) | (_, Some("lookupMovies" | "graphql")) => error_response("The bearer token's roles don't allow this, or the API key is for another tenant"),
+                _ => error_response("The bearer token's roles don't allow this, the API key is for another tenant, or this instance is a read-only follower"),
+            };
+        }
+    }
+    // Bodies have a size limit, see body_limit.rs.
+    for operations in document["paths"].as_object_mut().unwrap().values_mut() {
+        for (method, operation) in operations.as_object_mut().unwrap() {
+            if method == "parameters" || operation.get("requestBody").is_none() || operation["responses"].get("413").is_some() {
+                continue;
+            }
+            operation["responses"]["413"] = match operation["operationId"].as_str() {
+                Some("importMovies" | "restoreSnapshot") => error_response("The body is bigger than --max-upload-bytes"),
+                _ => error_response("The body is bigger than --max-body-bytes"),
+            };
+        }
+    }
+    // Writes that add movies may run into --max-movies or the tenant's quota, see quota.rs. Batches and imports report
+    // it per item instead.
+    for operations in document["paths"].as_object_mut().unwrap().values_mut() {
+        for (method, operation) in operations.as_object_mut().unwrap() {
+            if method != "parameters" && matches!(operation["operationId"].as_str(), Some("createMovie" | "restoreMovie" | "restoreSnapshot")) {
+                operation["responses"]["507"] = error_response("The server or the tenant already has as many movies as it may, the details say which");
+            }
+        }
+    }
+    // The movie routes, backing up and restoring, and merging, work on the catalog of one tenant, see tenant.rs.
+    for (path, operations) in document["paths"].as_object_mut().unwrap() {
+        for (method, operation) in operations.as_object_mut().unwrap() {
+            if method == "parameters" || !(path.starts_with("/v1/") || matches!(operation["operationId"].as_str(), Some("createSnapshot" | "restoreSnapshot" | "mergeMovies"))) {
+                continue;
+            }
+            match operation["parameters"].as_array_mut() {
+                Some(parameters) => parameters.push(tenant_parameter()),
+                None => operation["parameters"] = json!([tenant_parameter()]),
+            }
+        }
+    }
+    // With --rate-limit-per-sec set, everything but the health checks counts against the caller's rate limit. With
+    // --max-in-flight set, the same can be turned away when the server is too busy. Everything but imports is given up
+    // on after --request-timeout-secs.
+    for operations in document["paths"].as_object_mut().unwrap().values_mut() {
+        for (method, operation) in operations.as_object_mut().unwrap() {
+            if method == "parameters" || matches!(operation["operationId"].as_str(), Some("healthz" | "readyz" | "metrics")) {
+                continue;
+            }
+            let mut rate_limited = error_response("The caller has used up its rate limit");
+            rate_limited["headers"] = json!({ "Retry-After": { "description": "Seconds until the next request will be let through", "schema": { "type": "integer" } } });
+            operation["responses"]["429"] = rate_limited;
+            if operation["responses"].get("503").is_none() {
+                operation["responses"]["503"] = error_response("The server is handling as many requests as it can");
+            }
+            if operation["operationId"] != "importMovies" {
+                operation["responses"]["504"] = error_response("The request didn't finish within --request-timeout-secs");
+            }
+        }
+    }
+    document
+}
+
+// The /genres paths. Split out of document(), which has grown past what json! can expand in one go, as are the ones after it.
+fn genre_paths() -> Value {
+    json!({
+        "/v1/genres": {
+            "get": {
+                "summary": "List genres",
+                "operationId": "listGenres",
+                "description": "Every genre some movie has. Genres aren't stored apart from the movies, one exists for as long as a movie has it.",
+                "responses": {
+                    "200": { "description": "The genres, in alphabetical order ignoring case", "content": movie_content(json!({
+                        "type": "object",
+                        "required": ["items"],
+                        "properties": { "items": { "type": "array", "items": schema_ref("Label") } },
+                    })) },
+                },
+            },
+            "post": {
+                "summary": "Add a genre",
+                "operationId": "createGenre",
+                "description": "Gives the genre to each of the movies, all of which have to exist.",
+                "requestBody": { "required": true, "content": movie_content(json!({
+                    "type": "object",
+                    "required": ["name", "movies"],
+                    "properties": {
+                        "name": { "type": "string", "minLength": 1, "maxLength": MAX_GENRE_LEN },
+                        "movies": { "type": "array", "minItems": 1, "items": { "type": "string" }, "description": "Ids of the movies to give the genre to" },
+                    },
+                })) },
+                "responses": {
+                    "201": {
+                        "description": "Created",
+                        "headers": { "Location": { "description": "Path of the new genre", "schema": { "type": "string" } } },
+                        "content": movie_content(schema_ref("Label")),
+                    },
+                    "400": error_response("Malformed body"),
+                    "409": error_response("A movie already has the genre"),
+                    "422": error_response("An invalid name, no movies, or movies that don't exist or already have as many genres as they can, see details.fields"),
+                },
+            },
+        },
+        "/v1/genres/{genre}": {
+            "parameters": [{ "name": "genre", "in": "path", "required": true, "schema": { "type": "string" }, "description": "Matched ignoring case" }],
+            "get": {
+                "summary": "Get a genre",
+                "operationId": "getGenre",
+                "responses": {
+                    "200": { "description": "The genre", "content": movie_content(schema_ref("Label")) },
+                    "404": error_response("No movie has the genre"),
+                },
+            },
+            "put": {
+                "summary": "Rename a genre",
+                "operationId": "renameGenre",
+                "description": "Renames the genre on every movie that has it. Renaming it to a genre a movie already has merges the two.",
+                "requestBody": { "required": true, "content": movie_content(json!({
+                    "type": "object",
+                    "required": ["name"],
+                    "properties": { "name": { "type": "string", "minLength": 1, "maxLength": MAX_GENRE_LEN } },
+                })) },
+                "responses": {
+                    "200": { "description": "The genre under its new name", "content": movie_content(schema_ref("Label")) },
+                    "400": error_response("Malformed body"),
+                    "404": error_response("No movie has the genre"),
+                    "422": error_response("Invalid name, see details.fields"),
+                },
+            },
+            "delete": {
+                "summary": "Remove a genre",
+                "operationId": "deleteGenre",
+                "description": "Takes the genre off every movie that has it.",
+                "responses": {
+                    "204": { "description": "Removed" },
+                    "404": error_response("No movie has the genre"),
+                },
+            },
+        },
+        "/v1/genres/{genre}/movies": {
+            "get": {
+                "summary": "List the movies in a genre",
+                "operationId": "listGenreMovies",
+                "parameters": [
+                    { "name": "genre", "in": "path", "required": true, "schema": { "type": "string" }, "description": "Matched ignoring case" },
+                    query_parameter("limit", json!({ "type": "integer", "minimum": 1, "maximum": 100, "default": 20 }), "Page size"),
+                    query_parameter("offset", json!({ "type": "integer", "minimum": 0, "default": 0 }), "Movies to skip"),
+                ],
+                "responses": {
+                    "200": { "description": "A page of the movies with the genre, in id order", "content": movie_content(schema_ref("MoviePage")) },
+                    "400": error_response("Malformed query"),
+                    "404": error_response("No movie has the genre"),
+                },
+            },
+        },
+    })
+}
+
+fn rating_paths() -> Value {
+    json!({
+        "/v1/movie/{id}/ratings": {
+            "parameters": [{ "name": "id", "in": "path", "required": true, "schema": { "type": "string" } }],
+            "get": {
+                "summary": "List a movie's ratings",
+                "operationId": "listRatings",
+                "parameters": [
+                    query_parameter("limit", json!({ "type": "integer", "minimum": 1, "maximum": 100, "default": 20 }), "Page size"),
+                    query_parameter("offset", json!({ "type": "integer", "minimum": 0, "default": 0 }), "Ratings to skip"),
+                ],
+                "responses": {
+                    "200": { "description": "A page of the ratings, in user order", "content": movie_content(schema_ref("RatingPage")) },
+                    "400": error_response("Malformed query"),
+                    "404": error_response("No such movie"),
+                },
+            },
+            "post": {
+                "summary": "Rate a movie",
+                "operationId": "rateMovie",
+                "description": "Sets the user's score, replacing any they gave before. Callers with a bearer token rate as its subject. Only needs the reader role.",
+                "requestBody": { "required": true, "content": movie_content(json!({
+                    "type": "object",
+                    "required": ["score"],
+                    "properties": {
+                        "user": { "type": "string", "minLength": 1, "maxLength": MAX_USER_LEN, "description": "Required without a bearer token, and has to be its subject with one" },
+                        "score": { "type": "integer", "minimum": MIN_SCORE, "maximum": MAX_SCORE },
+                    },
+                })) },
+                "responses": {
+                    "200": { "description": "The movie with its new average rating", "headers": etag_header(), "content": movie_content(schema_ref("Movie")) },
+                    "400": error_response("Malformed body"),
+                    "404": error_response("No such movie"),
+                    "422": error_response("Invalid or missing user, or a score out of range, see details.fields"),
+                },
+            },
+        },
+    })
+}
+
+fn review_paths() -> Value {
+    json!({
+        "/v1/movie/{id}/reviews": {
+            "parameters": [{ "name": "id", "in": "path", "required": true, "schema": { "type": "string" } }],
+            "get": {
+                "summary": "List a movie's reviews",
+                "operationId": "listReviews",
+                "parameters": [
+                    query_parameter("limit", json!({ "type": "integer", "minimum": 1, "maximum": 100, "default": 20 }), "Page size"),
+                    query_parameter("offset", json!({ "type": "integer", "minimum": 0, "default": 0 }), "Reviews to skip"),
+                ],
+                "responses": {
+                    "200": { "description": "A page of the reviews, newest first", "content": movie_content(schema_ref("ReviewPage")) },
+                    "400": error_response("Malformed query"),
+                    "404": error_response("No such movie"),
+                },
+            },
+            "post": {
+                "summary": "Review a movie",
+                "operationId": "reviewMovie",
+                "description": "Callers with a bearer token write as its subject. Only needs the reader role. Reviews are deleted along with their movie.",
+                "requestBody": { "required": true, "content": movie_content(json!({
+                    "type": "object",
+                    "required": ["text"],
+                    "properties": {
+                        "author": { "type": "string", "minLength": 1, "maxLength": MAX_USER_LEN, "description": "Required without a bearer token, and has to be its subject with one" },
+                        "text": { "type": "string", "minLength": 1, "maxLength": MAX_REVIEW_LEN },
+                    },
+                })) },
+                "responses": {
+                    "201": { "description": "The review as added", "content": movie_content(schema_ref("Review")) },
+                    "400": error_response("Malformed body"),
+                    "404": error_response("No such movie"),
+                    "422": error_response("Invalid or missing author, or empty or overlong text, see details.fields"),
+                },
+            },
+        },
+    })
+}
+
+fn revision_schemas() -> Value {
+    json!({
+        "Revision": {
+            "type": "object",
+            "required": ["seq", "event", "at", "movie"],
+            "properties": {
+                "seq": { "type": "integer", "description": "Where the change is in the event log, across all movies" },
+                "event": { "type": "string", "enum": ["created", "updated", "deleted", "restored"] },
+                "at": { "type": "string", "format": "date-time" },
+                "movie": { "allOf": [schema_ref("Movie")], "description": "The movie as the change left it, or as it was when deleted" },
+            },
+        },
+        "RevisionPage": {
+            "type": "object",
+            "required": ["items", "total", "next"],
+            "properties": {
+                "items": { "type": "array", "items": schema_ref("Revision") },
+                "total": { "type": "integer", "description": "Revisions across all pages" },
+                "next": { "type": "string", "nullable": true, "description": "Link to the next page, null on the last one" },
+            },
+        },
+    })
+}
+
+fn revision_paths() -> Value {
+    json!({
+        "/v1/movie/{id}/history": {
+            "parameters": [{ "name": "id", "in": "path", "required": true, "schema": { "type": "string" } }],
+            "get": {
+                "summary": "List a movie's revisions",
+                "operationId": "listRevisions",
+                "description": "Only with --store events://, which keeps every change. Deleted movies keep their history.",
+                "parameters": [
+                    query_parameter("limit", json!({ "type": "integer", "minimum": 1, "maximum": 100, "default": 20 }), "Page size"),
+                    query_parameter("offset", json!({ "type": "integer", "minimum": 0, "default": 0 }), "Revisions to skip"),
+                ],
+                "responses": {
+                    "200": { "description": "A page of the revisions, oldest first", "content": movie_content(schema_ref("RevisionPage")) },
+                    "400": error_response("Malformed query"),
+                    "404": error_response("No movie by this id ever, or the store doesn't keep history"),
+                },
+            },
+        },
+    })
+}
+
+fn related_paths() -> Value {
+    let relation = json!({ "type": "string", "enum": ["sequel-of", "remake-of", "part-of-franchise"] });
+    let link = json!({
+        "type": "object",
+        "required": ["from", "relation", "to"],
+        "description": "from is the relation of to. Part-of-franchise links go both ways and always point from the lower id.",
+        "properties": {
+            "from": { "type": "string" },
+            "relation": relation,
+            "to": { "type": "string" },
+        },
+    });
+    json!({
+        "/v1/movie/{id}/related": {
+            "parameters": [{ "name": "id", "in": "path", "required": true, "schema": { "type": "string" } }],
+            "get": {
+                "summary": "List the movies linked to a movie",
+                "operationId": "listRelated",
+                "responses": {
+                    "200": { "description": "The movies linked either way", "content": movie_content(json!({
+                        "type": "object",
+                        "required": ["items"],
+                        "properties": { "items": { "type": "array", "items": {
+                            "type": "object",
+                            "required": ["relation", "movie"],
+                            "properties": {
+                                "relation": { "type": "string", "enum": ["sequel-of", "followed-by", "remake-of", "remade-as", "part-of-franchise"], "description": "As seen from this movie" },
+                                "movie": schema_ref("Movie"),
+                            },
+                        } } },
+                    })) },
+                    "404": error_response("No such movie"),
+                },
+            },
+            "post": {
+                "summary": "Link a movie to another",
+                "operationId": "linkMovies",
+                "description": "Links are deleted along with either movie.",
+                "requestBody": { "required": true, "content": movie_content(json!({
+                    "type": "object",
+                    "required": ["movie_id", "relation"],
+                    "properties": { "movie_id": { "type": "string" }, "relation": relation },
+                })) },
+                "responses": {
+                    "200": { "description": "The link, which was already there", "content": movie_content(link.clone()) },
+                    "201": { "description": "The link as added", "content": movie_content(link) },
+                    "400": error_response("Malformed body"),
+                    "404": error_response("No such movie, or no such other movie"),
+                    "409": error_response("The link would make a movie a sequel of itself"),
+                    "422": error_response("A link to the movie itself, or an unknown relation, see details.fields"),
+                },
+            },
+        },
+        "/v1/movie/{id}/related/{relation}/{movie_id}": {
+            "parameters": [
+                { "name": "id", "in": "path", "required": true, "schema": { "type": "string" } },
+                { "name": "relation", "in": "path", "required": true, "schema": relation },
+                { "name": "movie_id", "in": "path", "required": true, "schema": { "type": "string" } },
+            ],
+            "delete": {
+                "summary": "Unlink movies",
+                "operationId": "unlinkMovies",
+                "description": "Only needs the editor role.",
+                "responses": {
+                    "204": { "description": "They aren't linked, whether or not they were" },
+                    "400": error_response("Unknown relation"),
+                    "404": error_response("No such movie"),
+                },
+            },
+        },
+    })
+}
+
+fn poster_paths() -> Value {
+    let image = json!({ "type": "string", "format": "binary", "maxLength": MAX_POSTER_BYTES });
+    let mut images = serde_json::Map::new();
+    for content_type in CONTENT_TYPES {
+        images.insert(content_type.to_string(), json!({ "schema": image }));
+    }
+    let mut uploads = images.clone();
+    uploads.insert("multipart/form-data".to_string(), json!({ "schema": {
+        "type": "object",
+        "properties": { "poster": image },
+        "description": "The first part with a filename is taken, whatever its name",
+    } }));
+    json!({
+        "/v1/movie/{id}/poster": {
+            "parameters": [{ "name": "id", "in": "path", "required": true, "schema": { "type": "string" } }],
+            "get": {
+                "summary": "Get a movie's poster",
+                "operationId": "getPoster",
+                "parameters": [{ "name": "If-None-Match", "in": "header", "required": false, "schema": { "type": "string" } }],
+                "responses": {
+                    "200": {
+                        "description": "The image, with an ETag that changes with it and a Cache-Control allowing caches to keep it for an hour",
+                        "content": images,
+                    },
+                    "304": { "description": "The image still has the ETag given in If-None-Match" },
+                    "404": error_response("No such movie, or it has no poster"),
+                },
+            },
+            "put": {
+                "summary": "Set a movie's poster",
+                "operationId": "putPoster",
+                "description": "The image type is read from the image itself, and has to agree with the Content-Type unless that's application/octet-stream. Posters are deleted along with their movie.",
+                "requestBody": { "required": true, "content": uploads },
+                "responses": {
+                    "200": { "description": "The poster as stored", "content": movie_content(json!({
+                        "type": "object",
+                        "required": ["content_type", "size", "sha256"],
+                        "properties": {
+                            "content_type": { "type": "string", "enum": CONTENT_TYPES },
+                            "size": { "type": "integer", "description": "In bytes" },
+                            "sha256": { "type": "string", "description": "Hex, and the image's ETag" },
+                        },
+                    })) },
+                    "400": error_response("A malformed multipart body, or one without a file"),
+                    "404": error_response("No such movie"),
+                    "413": error_response("An image over the size limit"),
+                    "415": error_response("Not a JPEG, PNG or WebP image, or not the type the Content-Type says"),
+                },
+            },
+        },
+    })
+}
+
+fn tag_paths() -> Value {
+    json!({
+        "/v1/tags": {
+            "get": {
+                "summary": "List tags",
+                "operationId": "listTags",
+                "description": "Every tag some movie has. Like genres, tags aren't stored apart from the movies.",
+                "responses": {
+                    "200": { "description": "The tags, in alphabetical order ignoring case", "content": movie_content(json!({
+                        "type": "object",
+                        "required": ["items"],
+                        "properties": { "items": { "type": "array", "items": schema_ref("Label") } },
+                    })) },
+                },
+            },
+        },
+        "/v1/movie/{id}/tags": {
+            "parameters": [{ "name": "id", "in": "path", "required": true, "schema": { "type": "string" } }],
+            "put": {
+                "summary": "Replace a movie's tags",
+                "operationId": "tagMovie",
+                "description": "An empty array takes all of them off.",
+                "requestBody": { "required": true, "content": movie_content(json!({
+                    "type": "array", "maxItems": MAX_TAGS, "uniqueItems": true, "items": { "type": "string", "minLength": 1, "maxLength": MAX_TAG_LEN },
+                })) },
+                "responses": {
+                    "200": { "description": "The movie with its new tags", "headers": etag_header(), "content": movie_content(schema_ref("Movie")) },
+                    "400": error_response("Malformed body"),
+                    "404": error_response("No such movie"),
+                    "422": error_response("Empty, overlong, repeated or too many tags, see details.fields"),
+                },
+            },
+        },
+    })
+}
+
+// Out of document() for the same reason as the paths.
+fn user_schemas() -> Value {
+    json!({
+        "User": {
+            "type": "object",
+            "required": ["id", "created_at", "watchlist", "favorites"],
+            "properties": {
+                "id": { "type": "string", "minLength": 1, "maxLength": MAX_USER_LEN },
+                "name": { "type": "string", "minLength": 1, "maxLength": MAX_NAME_LEN, "description": "Left out if they didn't give one" },
+                "created_at": { "type": "string", "format": "date-time" },
+                "watchlist": { "type": "array", "maxItems": MAX_WATCHLIST_LEN, "items": { "type": "string" }, "description": "Ids of the movies on their watchlist, in the order they were added" },
+                "favorites": { "type": "array", "maxItems": MAX_FAVORITES, "items": { "type": "string" }, "description": "Ids of their favorite movies, in the order they were marked" },
+            },
+        },
+        "Watch": {
+            "type": "object",
+            "required": ["movie_id", "watched_at", "recorded_at"],
+            "properties": {
+                "movie_id": { "type": "string", "description": "Kept after the movie is deleted" },
+                "watched_at": { "type": "string", "format": "date-time" },
+                "recorded_at": { "type": "string", "format": "date-time", "description": "When the watch was recorded, which since= goes by" },
+            },
+        },
+        "HistoryPage": {
+            "type": "object",
+            "required": ["items", "total", "next"],
+            "properties": {
+                "items": { "type": "array", "items": schema_ref("Watch") },
+                "total": { "type": "integer", "description": "Watches recorded after since across all pages" },
+                "next": { "type": "string", "nullable": true, "description": "Link to the next page, null on the last one" },
+            },
+        },
+    })
+}
+
+fn user_paths() -> Value {
+    let user_id = json!({ "name": "id", "in": "path", "required": true, "schema": { "type": "string" } });
+    json!({
+        "/v1/users": {
+            "post": {
+                "summary": "Register a user",
+                "operationId": "createUser",
+                "description": "Callers with a bearer token register as its subject, and needn't register at all: they are the first time they change their watchlist. Only needs the reader role.",
+                "requestBody": { "required": true, "content": movie_content(json!({
+                    "type": "object",
+                    "properties": {
+                        "id": { "type": "string", "minLength": 1, "maxLength": MAX_USER_LEN, "description": "A UUIDv7 if left out without a bearer token, and has to be its subject with one" },
+                        "name": { "type": "string", "minLength": 1, "maxLength": MAX_NAME_LEN },
+                    },
+                })) },
+                "responses": {
+                    "201": {
+                        "description": "The user as registered",
+                        "headers": { "Location": { "description": "Path of the new user", "schema": { "type": "string" } } },
+                        "content": movie_content(schema_ref("User")),
+                    },
+                    "400": error_response("Malformed body"),
+                    "403": error_response("The id isn't the subject of the bearer token"),
+                    "409": error_response("The id is taken"),
+                    "422": error_response("Invalid id or name, see details.fields"),
+                },
+            },
+        },
+        "/v1/users/{id}": {
+            "parameters": [user_id],
+            "get": {
+                "summary": "Get a user",
+                "operationId": "getUser",
+                "responses": {
+                    "200": { "description": "The user", "content": movie_content(schema_ref("User")) },
+                    "404": error_response("No such user"),
+                },
+            },
+        },
+        "/v1/users/{id}/watchlist": {
+            "parameters": [user_id],
+            "get": {
+                "summary": "List the movies on a watchlist",
+                "operationId": "listWatchlist",
+                "parameters": [
+                    query_parameter("limit", json!({ "type": "integer", "minimum": 1, "maximum": 100, "default": 20 }), "Page size"),
+                    query_parameter("offset", json!({ "type": "integer", "minimum": 0, "default": 0 }), "Movies to skip"),
+                ],
+                "responses": {
+                    "200": { "description": "A page of the movies, in the order they were added", "content": movie_content(schema_ref("MoviePage")) },
+                    "400": error_response("Malformed query"),
+                    "404": error_response("No such user"),
+                },
+            },
+            "post": {
+                "summary": "Add a movie to a watchlist",
+                "operationId": "addToWatchlist",
+                "description": "Adds it at the end, unless it's there already. Callers with a bearer token may only change their own watchlist, unless they're an admin.",
+                "requestBody": { "required": true, "content": movie_content(json!({
+                    "type": "object",
+                    "required": ["movie_id"],
+                    "properties": { "movie_id": { "type": "string" } },
+                })) },
+                "responses": {
+                    "200": { "description": "The user with their new watchlist", "content": movie_content(schema_ref("User")) },
+                    "400": error_response("Malformed body"),
+                    "403": error_response("Someone else's watchlist"),
+                    "404": error_response("No such user or movie"),
+                    "422": error_response("The watchlist is full, see details.fields"),
+                },
+            },
+        },
+        "/v1/users/{id}/watchlist/{movie_id}": {
+            "parameters": [user_id, { "name": "movie_id", "in": "path", "required": true, "schema": { "type": "string" } }],
+            "delete": {
+                "summary": "Take a movie off a watchlist",
+                "operationId": "removeFromWatchlist",
+                "description": "Succeeds whether or not the movie was on it.",
+                "responses": {
+                    "204": { "description": "The movie isn't on the watchlist" },
+                    "403": error_response("Someone else's watchlist"),
+                    "404": error_response("No such user"),
+                },
+            },
+        },
+    })
+}
+
+fn history_paths() -> Value {
+    let user_id = json!({ "name": "id", "in": "path", "required": true, "schema": { "type": "string" } });
+    let movie_id = json!({ "name": "movie_id", "in": "path", "required": true, "schema": { "type": "string" } });
+    json!({
+        "/v1/users/{id}/favorites": {
+            "parameters": [user_id],
+            "get": {
+                "summary": "List a user's favorite movies",
+                "operationId": "listFavorites",
+                "parameters": [
+                    query_parameter("limit", json!({ "type": "integer", "minimum": 1, "maximum": 100, "default": 20 }), "Page size"),
+                    query_parameter("offset", json!({ "type": "integer", "minimum": 0, "default": 0 }), "Movies to skip"),
+                ],
+                "responses": {
+                    "200": { "description": "A page of the movies, in the order they were marked", "content": movie_content(schema_ref("MoviePage")) },
+                    "400": error_response("Malformed query"),
+                    "404": error_response("No such user"),
+                },
+            },
+        },
+        "/v1/users/{id}/favorites/{movie_id}": {
+            "parameters": [user_id, movie_id],
+            "put": {
+                "summary": "Mark a favorite",
+                "operationId": "addFavorite",
+                "description": "Callers with a bearer token may only mark their own, unless they're an admin.",
+                "responses": {
+                    "204": { "description": "The movie is a favorite" },
+                    "403": error_response("Someone else's favorites"),
+                    "404": error_response("No such user or movie"),
+                    "422": error_response("There are already as many favorites as there may be, see details.fields"),
+                },
+            },
+            "delete": {
+                "summary": "Unmark a favorite",
+                "operationId": "removeFavorite",
+                "responses": {
+                    "204": { "description": "The movie isn't a favorite, whether or not it was" },
+                    "403": error_response("Someone else's favorites"),
+                    "404": error_response("No such user"),
+                },
+            },
+        },
+        "/v1/users/{id}/recommendations": {
+            "parameters": [user_id],
+            "get": {
+                "summary": "Recommend movies to a user",
+                "operationId": "recommendMovies",
+                "description": "Each genre and tag of their favorites counts for the share of their favorites that have it, and a movie scores the sum of what its own count for. Favorites and movies in their history are left out. Callers with a bearer token may only see their own, unless they're an admin.",
+                "parameters": [
+                    query_parameter("limit", json!({ "type": "integer", "minimum": 1, "maximum": 100, "default": 20 }), "Most movies to return"),
+                ],
+                "responses": {
+                    "200": { "description": "The recommendations, best first, or none without favorites to go by", "content": movie_content(json!({
+                        "type": "object",
+                        "required": ["items"],
+                        "properties": { "items": { "type": "array", "items": {
+                            "type": "object",
+                            "required": ["movie", "score", "because"],
+                            "properties": {
+                                "movie": schema_ref("Movie"),
+                                "score": { "type": "number", "description": "Above 0, higher for a better match" },
+                                "because": { "type": "array", "items": { "type": "string" }, "description": "The movie's genres and tags that some favorite also has" },
+                            },
+                        } } },
+                    })) },
+                    "400": error_response("Malformed query"),
+                    "403": error_response("Someone else's recommendations"),
+                    "404": error_response("No such user"),
+                },
+            },
+        },
+        "/v1/users/{id}/history": {
+            "parameters": [user_id],
+            "get": {
+                "summary": "List the movies a user watched",
+                "operationId": "listHistory",
+                "description": "For syncing: clients pass the recorded_at of the last watch they got as since to get only what's new. Callers with a bearer token may only see their own, unless they're an admin.",
+                "parameters": [
+                    query_parameter("since", json!({ "type": "string", "format": "date-time" }), "Only watches recorded after this"),
+                    query_parameter("limit", json!({ "type": "integer", "minimum": 1, "maximum": 100, "default": 20 }), "Page size"),
+                    query_parameter("offset", json!({ "type": "integer", "minimum": 0, "default": 0 }), "Watches to skip"),
+                ],
+                "responses": {
+                    "200": { "description": "A page of the watches, in the order they were recorded", "content": movie_content(schema_ref("HistoryPage")) },
+                    "400": error_response("Malformed query"),
+                    "403": error_response("Someone else's history"),
+                    "404": error_response("No such user"),
+                },
+            },
+            "post": {
+                "summary": "Record a watch",
+                "operationId": "recordWatch",
+                "description": "Callers with a bearer token may only record their own, unless they're an admin. Only needs the reader role.",
+                "requestBody": { "required": true, "content": movie_content(json!({
+                    "type": "object",
+                    "required": ["movie_id"],
+                    "properties": {
+                        "movie_id": { "type": "string" },
+                        "watched_at": { "type": "string", "format": "date-time", "description": "Now if left out, and may not be later" },
+                    },
+                })) },
+                "responses": {
+                    "201": { "description": "The watch as recorded", "content": movie_content(schema_ref("Watch")) },
+                    "400": error_response("Malformed body"),
+                    "403": error_response("Someone else's history"),
+                    "404": error_response("No such user or movie"),
+                    "422": error_response("An unreadable or future watched_at, see details.fields"),
+                },
+            },
+        },
+    })
+}
+
+fn webhook_schemas() -> Value {
+    json!({
+        "Webhook": {
+            "type": "object",
+            "required": ["id", "url", "events", "created_at"],
+            "properties": {
+                "id": { "type": "string" },
+                "url": { "type": "string", "format": "uri", "maxLength": MAX_WEBHOOK_URL_LEN },
+                "events": { "type": "array", "items": { "type": "string", "enum": EVENTS } },
+                "created_at": { "type": "string", "format": "date-time" },
+            },
+        },
+        "DeadLetter": {
+            "type": "object",
+            "description": "A delivery that failed for good, after its retries or straight away if the webhook had been failing.",
+            "required": ["delivery_id", "webhook_id", "url", "event", "movie_id", "attempts", "error", "failed_at"],
+            "properties": {
+                "delivery_id": { "type": "string", "description": "The id in the payload and X-Webhook-Delivery" },
+                "webhook_id": { "type": "string" },
+                "url": { "type": "string", "format": "uri" },
+                "event": { "type": "string", "enum": EVENTS },
+                "movie_id": { "type": "string" },
+                "attempts": { "type": "integer", "description": "0 if the webhook's circuit breaker was open" },
+                "error": { "type": "string" },
+                "failed_at": { "type": "string", "format": "date-time" },
+            },
+        },
+    })
+}
+
+fn webhook_paths() -> Value {
+    json!({
+        "/admin/webhooks": {
+            "post": {
+                "summary": "Register a webhook",
+                "operationId": "createWebhook",
+                "description": "From then on every change to a movie the webhook wants is POSTed to its URL as {id, event, occurred_at, movie}, with X-Webhook-Event, X-Webhook-Delivery and X-Webhook-Timestamp headers, and an X-Webhook-Signature of sha256= and the hex HMAC-SHA256 of \"<timestamp>.<body>\" with the secret. Anything but a 2xx is retried with backoff, except other 4xx responses, and deliveries that still fail are kept as dead letters. Webhooks are forgotten on restart.",
+                "requestBody": { "required": true, "content": { "application/json": { "schema": {
+                    "type": "object",
+                    "required": ["url"],
+                    "properties": {
+                        "url": { "type": "string", "format": "uri", "maxLength": MAX_WEBHOOK_URL_LEN, "description": "Only http://" },
+                        "events": { "type": "array", "minItems": 1, "items": { "type": "string", "enum": EVENTS }, "description": "Every event if left out" },
+                        "secret": { "type": "string", "minLength": MIN_WEBHOOK_SECRET_LEN, "maxLength": MAX_WEBHOOK_SECRET_LEN, "description": "A random one if left out" },
+                    },
+                } } } },
+                "responses": {
+                    "201": {
+                        "description": "The webhook as registered, with its secret, which isn't shown again",
+                        "headers": { "Location": { "description": "Path of the new webhook", "schema": { "type": "string" } } },
+                        "content": { "application/json": { "schema": { "allOf": [schema_ref("Webhook"), {
+                            "type": "object", "required": ["secret"], "properties": { "secret": { "type": "string" } },
+                        }] } } },
+                    },
+                    "400": error_response("Malformed body"),
+                    "409": error_response(&format!("There are already {} webhooks", MAX_WEBHOOKS)),
+                    "422": error_response("Invalid url, events or secret, see details.fields"),
+                },
+            },
+            "get": {
+                "summary": "List webhooks",
+                "operationId": "listWebhooks",
+                "responses": {
+                    "200": { "description": "Every webhook, oldest first, without their secrets", "content": { "application/json": { "schema": {
+                        "type": "object", "required": ["items"], "properties": { "items": { "type": "array", "items": schema_ref("Webhook") } },
+                    } } } },
+                },
+            },
+        },
+        "/admin/webhooks/{id}": {
+            "parameters": [{ "name": "id", "in": "path", "required": true, "schema": { "type": "string" } }],
+            "delete": {
+                "summary": "Delete a webhook",
+                "operationId": "deleteWebhook",
+                "description": "Deliveries already under way still go out.",
+                "responses": {
+                    "204": { "description": "Deleted" },
+                    "404": error_response("No such webhook"),
+                },
+            },
+        },
+        "/admin/webhooks/dead-letters": {
+            "get": {
+                "summary": "List failed deliveries",
+                "operationId": "listDeadLetters",
+                "responses": {
+                    "200": { "description": "The most recent failed deliveries, oldest first", "content": { "application/json": { "schema": {
+                        "type": "object", "required": ["items"], "properties": { "items": { "type": "array", "items": schema_ref("DeadLetter") } },
+                    } } } },
+                },
+            },
+        },
+    })
+}
+
+fn audit_schemas() -> Value {
+    json!({
+        "AuditEntry": {
+            "type": "object",
+            "required": ["seq", "at", "actor", "action", "entity", "id", "changes"],
+            "properties": {
+                "seq": { "type": "integer" },
+                "at": { "type": "string", "format": "date-time" },
+                "actor": { "type": "string", "description": "user:<subject> for tokens and logins, key:<first 8 hex digits of the key's SHA-256>, anonymous, or system", "example": "key:5e884898" },
+                "action": { "type": "string", "enum": ["created", "updated", "deleted"] },
+                "entity": { "type": "string", "enum": ["movie", "user", "link", "store"] },
+                "id": { "type": "string", "description": "Links are \"<from> <relation> <to>\". Restoring a backup is one entry for the store, with how many movies, users, links and movies in the trash it held before and after" },
+                "changes": {
+                    "type": "object",
+                    "description": "Every top-level field that changed, by name. Fields that weren't there are null",
+                    "additionalProperties": {
+                        "type": "object",
+                        "required": ["before", "after"],
+                        "properties": { "before": { "nullable": true }, "after": { "nullable": true } },
+                    },
+                },
+            },
+        },
+        "AuditPage": {
+            "type": "object",
+            "required": ["items", "total", "next"],
+            "properties": {
+                "items": { "type": "array", "items": schema_ref("AuditEntry") },
+                "total": { "type": "integer", "description": "Matching entries across all pages" },
+                "next": { "type": "string", "nullable": true, "description": "Link to the next page, null on the last one" },
+            },
+        },
+    })
+}
+
+fn audit_paths() -> Value {
+    json!({
+        "/admin/audit": {
+            "get": {
+                "summary": "List changes to the store",
+                "operationId": "listAuditEntries",
+                "description": "Who created, updated or deleted each movie, user and link, and what they changed. Only the newest entries are kept in memory, stores on disk keep them all in a file next to the store.",
+                "parameters": [
+                    query_parameter("since", json!({ "type": "string", "format": "date-time" }), "Only changes made after this"),
+                    query_parameter("actor", json!({ "type": "string" }), "Only changes made by this actor"),
+                    query_parameter("limit", json!({ "type": "integer", "minimum": 1, "maximum": 100, "default": 20 }), "Page size"),
+                    query_parameter("offset", json!({ "type": "integer", "minimum": 0, "default": 0 }), "Entries to skip"),
+                ],
+                "responses": {
+                    "200": { "description": "A page of the changes, oldest first", "content": movie_content(schema_ref("AuditPage")) },
+                    "400": error_response("Malformed query"),
+                },
+            },
+        },
+    })
+}
+
+fn trash_schemas() -> Value {
+    json!({
+        "Trashed": {
+            "type": "object",
+            "required": ["deleted_at", "movie"],
+            "properties": {
+                "deleted_at": { "type": "string", "format": "date-time" },
+                "movie": { "allOf": [schema_ref("Movie")], "description": "As it was when deleted" },
+            },
+        },
+        "PurgeReport": {
+            "type": "object",
+            "required": ["purged"],
+            "properties": {
+                "purged": { "type": "array", "items": { "type": "string" }, "description": "Ids of the movies purged, or that would have been for a dry run" },
+                "dry_run": { "type": "boolean", "description": "Only there for dry runs" },
+            },
+        },
+        "TrashPage": {
+            "type": "object",
+            "required": ["items", "total", "next"],
+            "properties": {
+                "items": { "type": "array", "items": schema_ref("Trashed") },
+                "total": { "type": "integer", "description": "Movies in the trash across all pages" },
+                "next": { "type": "string", "nullable": true, "description": "Link to the next page, null on the last one" },
+            },
+        },
+    })
+}
+
+fn trash_paths() -> Value {
+    json!({
+        "/v1/movies/trash": {
+            "get": {
+                "summary": "List deleted movies",
+                "operationId": "listTrash",
+                "description": "Movies are purged from the trash once they've been in it for --trash-retention-secs.",
+                "parameters": [
+                    query_parameter("limit", json!({ "type": "integer", "minimum": 1, "maximum": 100, "default": 20 }), "Page size"),
+                    query_parameter("offset", json!({ "type": "integer", "minimum": 0, "default": 0 }), "Movies to skip"),
+                ],
+                "responses": {
+                    "200": { "description": "A page of the deleted movies, by id", "content": movie_content(schema_ref("TrashPage")) },
+                    "400": error_response("Malformed query"),
+                },
+            },
+            "delete": {
+                "summary": "Purge deleted movies",
+                "operationId": "purgeTrash",
+                "description": "Purges movies from the trash for good, without waiting for --trash-retention-secs.",
+                "parameters": [
+                    query_parameter("deleted_before", json!({ "type": "string", "format": "date-time" }), "Only purge movies deleted before then, rather than everything in the trash"),
+                    query_parameter("dry_run", json!({ "type": "boolean", "default": false }), "List what would be purged without purging it"),
+                ],
+                "responses": {
+                    "200": { "description": "What was purged", "content": movie_content(schema_ref("PurgeReport")) },
+                    "400": error_response("Malformed query"),
+                },
+            },
+        },
+        "/v1/movie/{id}/restore": {
+            "parameters": [{ "name": "id", "in": "path", "required": true, "schema": { "type": "string" } }],
+            "post": {
+                "summary": "Restore a deleted movie",
+                "operationId": "restoreMovie",
+                "parameters": [
+                    query_parameter("dry_run", json!({ "type": "boolean", "default": false }), "Answer with the movie as it would be restored, or the error restoring it would get, without restoring it"),
+                ],
+                "responses": {
+                    "200": { "description": "The movie, back out of the trash", "headers": etag_header(), "content": movie_content(schema_ref("Movie")) },
+                    "404": error_response("No such movie in the trash"),
+                    "409": error_response("Another movie has the id now"),
+                },
+            },
+        },
+    })
+}
+
+fn backup_schemas() -> Value {
+    json!({
+        "Backup": {
+            "type": "object",
+            "required": ["taken_at", "movies", "users", "links", "trash"],
+            "description": "Everything in the store, with the ratings, reviews and watch history that aren't otherwise sent. Poster images aren't in it",
+            "properties": {
+                "taken_at": { "type": "string", "format": "date-time" },
+                "movies": { "type": "array", "items": { "type": "object" } },
+                "users": { "type": "array", "items": { "type": "object" } },
+                "links": { "type": "array", "items": { "type": "object" } },
+                "trash": { "type": "array", "items": { "type": "object" } },
+            },
+        },
+        "BackupSummary": {
+            "type": "object",
+            "required": ["taken_at", "movies", "users", "links", "trash"],
+            "properties": {
+                "name": { "type": "string", "description": "The file in --backup-dir, left out when there wasn't one", "example": "movies-20261015T093000.250Z.json" },
+                "taken_at": { "type": "string", "format": "date-time" },
+                "movies": { "type": "integer" },
+                "users": { "type": "integer" },
+                "links": { "type": "integer" },
+                "trash": { "type": "integer", "description": "Movies in the trash" },
+            },
+        },
+        "RestorePreview": {
+            "description": "The BackupSummary of the backup, and what restoring it would do to the movies in the store",
+            "allOf": [schema_ref("BackupSummary")],
+            "type": "object",
+            "required": ["added", "changed", "removed", "dry_run"],
+            "properties": {
+                "added": { "type": "integer", "description": "Movies in the backup that the store doesn't have" },
+                "changed": { "type": "integer", "description": "Movies in both that differ" },
+                "removed": { "type": "integer", "description": "Movies in the store that the backup doesn't have" },
+                "dry_run": { "type": "boolean" },
+            },
+        },
+    })
+}
+
+fn backup_paths() -> Value {
+    json!({
+        "/admin/snapshot": {
+            "post": {
+                "summary": "Back up the store",
+                "operationId": "createSnapshot",
+                "description": "Takes a backup of everything in the store as of one moment. It's written to --backup-dir when that's set, and otherwise sent back.",
+                "parameters": [
+                    query_parameter("download", json!({ "type": "boolean", "default": false }), "Send the backup back even with --backup-dir set"),
+                ],
+                "responses": {
+                    "200": { "description": "The backup, as an attachment", "content": json_content("Backup") },
+                    "201": { "description": "The backup was written to --backup-dir", "content": movie_content(schema_ref("BackupSummary")) },
+                },
+            },
+        },
+        "/admin/merge": {
+            "post": {
+                "summary": "Merge a duplicate movie into another",
+                "operationId": "mergeMovies",
+                "description": "The canonical movie takes in the duplicate's ratings, reviews and tags, and its titles in languages the canonical movie has none in, and the duplicate is moved to the trash. A user who rated both keeps the score they gave the canonical movie.",
+                "requestBody": {
+                    "required": true,
+                    "content": movie_content(json!({
+                        "type": "object",
+                        "required": ["canonical", "duplicate"],
+                        "properties": {
+                            "canonical": { "type": "string", "description": "Id of the movie to keep" },
+                            "duplicate": { "type": "string", "description": "Id of the movie to fold into it" },
+                        },
+                    })),
+                },
+                "responses": {
+                    "200": { "description": "The canonical movie, merged", "headers": etag_header(), "content": movie_content(schema_ref("Movie")) },
+                    "404": error_response("No movie with one of the ids"),
+                    "412": error_response("One of the movies changed while they were being merged"),
+                    "422": error_response("The same id twice, or the merged movie would have too many tags"),
+                },
+            },
+        },
+        "/admin/restore": {
+            "post": {
+                "summary": "Restore the store from a backup",
+                "operationId": "restoreSnapshot",
+                "description": "Replaces everything in the store with the backup, all at once. Subscribers and webhooks get a change for each movie it adds, changes or removes.",
+                "parameters": [
+                    query_parameter("name", json!({ "type": "string" }), "A backup in --backup-dir to restore, instead of the body"),
+                    query_parameter("dry_run", json!({ "type": "boolean", "default": false }), "Check the backup and answer with a RestorePreview of what restoring it would change, without restoring it"),
+                ],
+                "requestBody": { "required": false, "content": json_content("Backup") },
+                "responses": {
+                    "200": {
+                        "description": "What was restored, or would be for a dry run",
+                        "content": movie_content(json!({ "oneOf": [schema_ref("BackupSummary"), schema_ref("RestorePreview")] })),
+                    },
+                    "400": error_response("Malformed backup, or a name without --backup-dir"),
+                    "404": error_response("No backup by that name"),
+                    "422": error_response("The backup has users or links pointing at movies that aren't in it"),
+                },
+            },
+        },
+    })
+}
+
+fn tenant_schemas() -> Value {
+    json!({
+        "TenantStats": {
+            "type": "object",
+            "required": ["id", "movies", "trashed"],
+            "properties": {
+                "id": { "type": "string", "example": "team-a", "description": "default for the catalog of requests without a tenant" },
+                "movies": { "type": "integer" },
+                "trashed": { "type": "integer", "description": "Movies in the tenant's trash, which don't count against its quota" },
+                "max_movies": { "type": "integer", "description": "The tenant's quota, left out when it has none" },
+            },
+        },
+    })
+}
+
+fn tenant_paths() -> Value {
+    json!({
+        "/admin/tenants": {
+            "get": {
+                "summary": "List tenants",
+                "operationId": "listTenants",
+                "description": "How many movies the default catalog and each tenant declared with --tenants has, and its quota. Keys for a tenant can't call this.",
+                "responses": {
+                    "200": { "description": "The default catalog, then the tenants in id order", "content": movie_content(json!({
+                        "type": "object",
+                        "required": ["items"],
+                        "properties": { "items": { "type": "array", "items": schema_ref("TenantStats") } },
+                    })) },
+                },
+            },
+        },
+    })
+}
+
+fn quota_schemas() -> Value {
+    json!({
+        "QuotaExceeded": {
+            "type": "object",
+            "required": ["quota", "limit", "used"],
+            "properties": {
+                "quota": { "type": "string", "enum": ["global", "tenant"], "description": "global for --max-movies, tenant for the tenant's own quota" },
+                "tenant": { "type": "string", "description": "The tenant whose quota it is, left out for global" },
+                "limit": { "type": "integer" },
+                "used": { "type": "integer", "description": "Movies stored already" },
+            },
+        },
+        "Usage": {
+            "type": "object",
+            "required": ["movies", "tenants"],
+            "properties": {
+                "movies": { "type": "integer", "description": "Movies in every catalog together, not counting the trash" },
+                "max_movies": { "type": "integer", "description": "--max-movies, left out when there's no limit" },
+                "tenants": { "type": "array", "items": schema_ref("TenantStats"), "description": "The default catalog, then the tenants in id order" },
+            },
+        },
+    })
+}
+
+fn quota_paths() -> Value {
+    json!({
+        "/admin/quotas": {
+            "get": {
+                "summary": "Show quota usage",
+                "operationId": "getQuotas",
+                "description": "How many movies are stored against --max-movies, and against each tenant's quota. Keys for a tenant can't call this.",
+                "responses": {
+                    "200": { "description": "The usage", "content": movie_content(schema_ref("Usage")) },
+                },
+            },
+        },
+    })
+}
+
+fn locale_schemas() -> Value {
+    json!({
+        "LocalizedMovie": {
+            "description": "A movie, answering a request with Accept-Language, with the title to show besides its name. The title is for the most preferred language that has one, falling back from pt-BR to pt, or the name if no language asked for has one.",
+            "allOf": [schema_ref("Movie"), {
+                "type": "object",
+                "required": ["title"],
+                "properties": {
+                    "title": { "type": "string" },
+                    "title_language": { "type": "string", "description": "The key of the title in titles, left out when the title is the name" },
+                },
+            }],
+        },
+    })
+}
+
+fn change_schemas() -> Value {
+    json!({
+        "Change": {
+            "type": "object",
+            "required": ["seq", "event", "id", "at", "movie"],
+            "properties": {
+                "seq": { "type": "integer", "description": "1 for the store's first change, and one more for each after it. Starts over with a store kept in memory when the server restarts, and with any store after a restart that didn't shut down cleanly" },
+                "event": { "type": "string", "enum": ["created", "updated", "deleted"] },
+                "id": { "type": "string" },
+                "at": { "type": "string", "format": "date-time" },
+                "movie": { "allOf": [schema_ref("Movie")], "description": "As the change left it, or as it was before a delete" },
+            },
+        },
+        "ChangePage": {
+            "type": "object",
+            "required": ["items", "cursor", "has_more"],
+            "properties": {
+                "items": { "type": "array", "items": schema_ref("Change") },
+                "cursor": { "type": "string", "description": "Opaque. Send it as since to get the changes after these, or the same changes again if there were none" },
+                "has_more": { "type": "boolean", "description": "More changes were made than fit on the page, ask again straight away" },
+            },
+        },
+    })
+}
+
+fn change_paths() -> Value {
+    json!({
+        "/v1/movies/changes": {
+            "get": {
+                "summary": "List changes since a cursor",
+                "operationId": "listChanges",
+                "description": format!("Every change made to a movie after the cursor, oldest first, for clients catching up after being offline. Only the newest {} changes are kept. Stores kept on disk keep them across clean restarts, stores in memory and ones that crashed start over: a cursor whose changes are gone gets a 410, and the client has to download every movie again.", MAX_CHANGES),
+                "parameters": [
+                    query_parameter("since", json!({ "type": "string" }), "The cursor from the last page, or latest for an empty page with a cursor for the changes from now on. Without one the feed starts from the oldest change kept"),
+                    query_parameter("limit", json!({ "type": "integer", "minimum": 1, "maximum": 100, "default": 20 }), "Page size"),
+                ],
+                "responses": {
+                    "200": { "description": "A page of the changes, oldest first", "content": movie_content(schema_ref("ChangePage")) },
+                    "400": error_response("Malformed query, or since isn't a cursor from this endpoint"),
+                    "410": error_response("The changes after the cursor are no longer kept"),
+                },
+            },
+        },
+    })
+}
+
+fn movie_properties() -> Value {
+    json!({
+        "id": { "type": "string", "minLength": 1, "maxLength": MAX_ID_LEN, "pattern": "^[A-Za-z0-9_-]+$" },
+        "name": { "type": "string", "minLength": 1, "maxLength": MAX_NAME_LEN },
+        "year": { "type": "integer", "minimum": FIRST_MOVIE_YEAR, "description": "At most five years from now" },
+        "was_good": { "type": "boolean", "description": "On its way out in favour of ratings, see average_rating" },
+        "genres": { "type": "array", "maxItems": MAX_GENRES, "uniqueItems": true, "items": { "type": "string", "minLength": 1, "maxLength": MAX_GENRE_LEN },
+            "description": "Left out when there are none" },
+        "director": { "type": "string", "minLength": 1, "maxLength": MAX_DIRECTOR_LEN, "description": "Left out when not known, as are the other details" },
+        "runtime_minutes": { "type": "integer", "minimum": 1, "maximum": MAX_RUNTIME_MINUTES },
+        "synopsis": { "type": "string", "minLength": 1, "maxLength": MAX_SYNOPSIS_LEN },
+        "poster_url": { "type": "string", "format": "uri", "maxLength": MAX_POSTER_URL_LEN, "description": "An http:// or https:// URL of a poster kept elsewhere" },
+        "tags": { "type": "array", "maxItems": MAX_TAGS, "uniqueItems": true, "items": { "type": "string", "minLength": 1, "maxLength": MAX_TAG_LEN },
+            "description": "Free-form, matched ignoring case. Left out when there are none" },
+        "titles": { "type": "object", "maxProperties": MAX_TITLES, "propertyNames": { "type": "string", "maxLength": MAX_LANGUAGE_LEN, "pattern": "^[A-Za-z]{1,8}(-[A-Za-z0-9]{1,8})*$" },
+            "additionalProperties": { "type": "string", "minLength": 1, "maxLength": MAX_NAME_LEN },
+            "description": "What the movie is called in other languages, keyed by language tag, like {\"ja\": \"七人の侍\"}. One per language, ignoring case. Left out when there are none" },
+    })
+}
+
+// In a merge patch null removes a detail.
+fn nullable_details(mut properties: Value) -> Value {
+    for detail in ["genres", "director", "runtime_minutes", "synopsis", "poster_url", "tags", "titles"] {
+        properties[detail]["nullable"] = json!(true);
+    }
+    properties
+}
+
+// Only stores set these, from POST /movie/{id}/ratings and /reviews. A PUT keeps them whatever it says about them.
+fn with_ratings_and_reviews(mut properties: Value) -> Value {
+    properties["average_rating"] = json!({ "type": "number", "minimum": MIN_SCORE, "maximum": MAX_SCORE, "description": "Mean of the ratings, left out until there are any" });
+    properties["rating_count"] = json!({ "type": "integer", "minimum": 1, "description": "Left out until there are ratings" });
+    properties["review_count"] = json!({ "type": "integer", "minimum": 1, "description": "Left out until there are reviews" });
+    properties
+}
+
+// Set by the store, whatever clients send.
+fn with_timestamps(mut properties: Value) -> Value {
+    properties["created_at"] = json!({ "type": "string", "format": "date-time", "readOnly": true, "description": "When the movie was added. Left out for movies added before this was kept" });
+    properties["updated_at"] = json!({ "type": "string", "format": "date-time", "readOnly": true, "description": "When the movie last changed. Left out for movies that haven't since this was kept" });
+    properties
+}
+
+fn with_version(mut properties: Value, description: &str) -> Value {
+    properties["version"] = json!({ "type": "integer", "minimum": 0, "description": description });
+    properties
+}
+
+fn schema_ref(name: &str) -> Value {
+    json!({ "$ref": format!("#/components/schemas/{}", name) })
+}
+
+fn json_content(schema: &str) -> Value {
+    json!({ "application/json": { "schema": schema_ref(schema) } })
+}
+
+// For the routes that negotiate between JSON, MessagePack and CBOR, see content.rs.
+fn movie_content(schema: Value) -> Value {
+    json!({
+        "application/json": { "schema": schema },
+        "application/msgpack": { "schema": schema },
+        "application/cbor": { "schema": schema },
+    })
+}
+
+fn movie_body(schema: &str) -> Value {
+    json!({ "required": true, "content": movie_content(schema_ref(schema)) })
+}
+
+fn error_response(description: &str) -> Value {
+    json!({ "description": description, "content": json_content("Error") })
+}
+
+fn etag_header() -> Value {
+    json!({ "ETag": { "description": "Strong ETag of the movie's current content, as sent: each format and title language has its own", "schema": { "type": "string" } } })
+}
+
+// For GET /movie/{id}, which has both.
+fn validator_headers() -> Value {
+    let mut headers = etag_header();
+    headers["Last-Modified"] = json!({ "description": "updated_at, to the second, when the movie has one", "schema": { "type": "string" } });
+    headers
+}
+
+fn idempotency_key_parameter() -> Value {
+    json!({
+        "name": "Idempotency-Key", "in": "header", "required": false,
+        "description": "Retries with the same key get the first response again, marked Idempotent-Replayed: true, instead of running twice",
+        "schema": { "type": "string", "minLength": 1, "maxLength": 255 },
+    })
+}
+
+fn allow_duplicate_parameter() -> Value {
+    query_parameter("allow_duplicate", json!({ "type": "boolean", "default": false }), "Add movies with the same name and year as another even with --duplicates=reject")
+}
+
+fn warning_header() -> Value {
+    json!({ "description": "299 with the id of a movie that has the same name and year, when there is one", "schema": { "type": "string" } })
+}
+
+fn tenant_parameter() -> Value {
+    json!({
+        "name": "X-Tenant-Id", "in": "header", "required": false,
+        "description": "The tenant whose movies to work on, default or left out for the default catalog. Keys for a tenant can only name their own",
+        "schema": { "type": "string", "minLength": 1, "maxLength": 63, "pattern": "^[a-z0-9-]+$" },
+    })
+}
+
+fn if_match_parameter() -> Value {
+    json!({ "name": "If-Match", "in": "header", "required": false, "description": "Only apply the change if the movie still has one of these ETags", "schema": { "type": "string" } })
+}
+
+// The filters of GET /movies, see MovieFilter.
+fn filter_parameters() -> Vec<Value> {
+    vec![
+        query_parameter("year", json!({ "type": "integer" }), "Only movies from this year"),
+        query_parameter("year_gte", json!({ "type": "integer" }), "Only movies from this year or later"),
+        query_parameter("year_lte", json!({ "type": "integer" }), "Only movies from this year or earlier"),
+        query_parameter("was_good", json!({ "type": "boolean" }), "Only good, or only bad, movies"),
+        query_parameter("name_contains", json!({ "type": "string" }), "Substring of the name, ignoring case and accents"),
+        query_parameter("genre", json!({ "type": "string" }), "Only movies with this genre, ignoring case"),
+        query_parameter("director", json!({ "type": "string" }), "Only movies by this director, ignoring case"),
+        query_parameter("runtime_gte", json!({ "type": "integer" }), "Only movies at least this many minutes long"),
+        query_parameter("runtime_lte", json!({ "type": "integer" }), "Only movies at most this many minutes long"),
+        query_parameter("tag", json!({ "type": "string" }), "Only movies with this tag, ignoring case"),
+    ]
+}
+
+fn query_parameter(name: &str, schema: Value, description: &str) -> Value {
+    json!({ "name": name, "in": "query", "required": false, "description": description, "schema": schema })
+}
+
+// Swagger UI pointed at the document. The assets come from a CDN, there's no bundled copy in this build.
+pub const SWAGGER_UI: &str = r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+  <meta charset="utf-8">
+  <title>Movies API</title>
+  <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css">
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js"></script>
+  <script>
+    window.ui = SwaggerUIBundle({ url: "/api-docs/openapi.json", dom_id: "#swagger-ui" });
+  </script>
+</body>
+</html>
+"##;
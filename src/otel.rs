@@ -0,0 +1,164 @@
+use std::{sync::{atomic::{AtomicBool, AtomicU64, Ordering}, Arc, Mutex}, time::{Duration, SystemTime, UNIX_EPOCH}};
+use serde_json::{json, Value};
+use tracing::warn;
+
+use crate::http_client;
+use crate::resilience::{self, BreakerPolicy, Integration, RetryPolicy};
+
+const SERVICE_NAME: &str = "syndica-rust";
+const EXPORT_INTERVAL: Duration = Duration::from_secs(5);
+const EXPORT_TIMEOUT: Duration = Duration::from_secs(10);
+// Past this, finished spans are dropped rather than piling up while the collector is unreachable.
+const MAX_QUEUED_SPANS: usize = 4096;
+// Retries have to be done before the next export is due.
+const RETRY: RetryPolicy = RetryPolicy { attempts: 3, base_delay: Duration::from_millis(500), max_delay: Duration::from_secs(1) };
+const BREAKER: BreakerPolicy = BreakerPolicy { failures_to_open: 5, open_for: Duration::from_secs(60) };
+
+// A span that has closed, as handed over by the tracing subscriber in telemetry.rs.
+pub struct FinishedSpan {
+    pub name: &'static str,
+    pub trace_id: [u8; 16],
+    pub span_id: [u8; 8],
+    pub parent_span_id: Option<[u8; 8]>,
+    pub start: SystemTime,
+    pub end: SystemTime,
+    pub attributes: Vec<(&'static str, Value)>,
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static QUEUE: Mutex<Vec<FinishedSpan>> = Mutex::new(Vec::new());
+static DROPPED: AtomicU64 = AtomicU64::new(0);
+
+// Whether spans need trace ids and timing, which the subscriber skips when nothing is going to be exported.
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+pub fn record(span: FinishedSpan) {
+    let mut queue = QUEUE.lock().unwrap();
+    if queue.len() < MAX_QUEUED_SPANS {
+        queue.push(span);
+    }
+    else {
+        DROPPED.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+// Ships finished spans to an OpenTelemetry collector with OTLP over HTTP, using the JSON encoding.
+pub struct OtelExporter {
+    // e.g. http://localhost:4318/v1/traces
+    traces_url: String,
+    // Only one export at a time, so the periodic one and the shutdown one can't send the same batch in two halves.
+    sending: tokio::sync::Mutex<()>,
+    collector: Arc<Integration>,
+}
+
+impl OtelExporter {
+    // Starts recording spans and exporting them every few seconds. `endpoint` is the collector's base URL, e.g.
+    // http://localhost:4318.
+    pub fn start(endpoint: &str) -> Arc<OtelExporter> {
+        let exporter = Arc::new(OtelExporter {
+            traces_url: format!("{}/v1/traces", endpoint.trim_end_matches('/')),
+            sending: tokio::sync::Mutex::new(()),
+            collector: Integration::register("otel", RETRY, BREAKER),
+        });
+        ENABLED.store(true, Ordering::Relaxed);
+        let periodic = exporter.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(EXPORT_INTERVAL);
+            loop {
+                ticker.tick().await;
+                periodic.flush().await;
+            }
+        });
+        exporter
+    }
+
+    // Sends everything recorded so far. Failures are logged and the batch is dropped, tracing is best effort.
+    pub async fn flush(&self) {
+        let _sending = self.sending.lock().await;
+        let spans = std::mem::take(&mut *QUEUE.lock().unwrap());
+        let dropped = DROPPED.swap(0, Ordering::Relaxed);
+        if dropped > 0 {
+            warn!("Dropped {} spans because the OpenTelemetry export queue was full", dropped);
+        }
+        if spans.is_empty() {
+            return;
+        }
+        let body = export_request(&spans).to_string();
+        let exported = self.collector.call(|| async {
+            let response = http_client::post(&self.traces_url, "application/json", body.as_bytes(), EXPORT_TIMEOUT).await?;
+            if !(200..300).contains(&response.status) {
+                return Err(resilience::status_error(response.status));
+            }
+            Ok(())
+        }).await;
+        if let Err(e) = exported {
+            warn!("Failed to export {} spans, the collector at {} {}", spans.len(), self.traces_url, e);
+        }
+    }
+}
+
+// An ExportTraceServiceRequest, see opentelemetry-proto's JSON mapping.
+fn export_request(spans: &[FinishedSpan]) -> Value {
+    let spans: Vec<Value> = spans.iter().map(span_json).collect();
+    json!({
+        "resourceSpans": [{
+            "resource": { "attributes": [attribute("service.name", &Value::from(SERVICE_NAME))] },
+            "scopeSpans": [{
+                "scope": { "name": SERVICE_NAME, "version": env!("CARGO_PKG_VERSION") },
+                "spans": spans,
+            }],
+        }],
+    })
+}
+
+fn span_json(span: &FinishedSpan) -> Value {
+    let field = |name: &str| span.attributes.iter().find(|(key, _)| *key == name).map(|(_, value)| value);
+    // Request spans are named after the route, the way OpenTelemetry's HTTP conventions want, and are the server side
+    // of the call. Everything else happens inside one.
+    let (name, kind) = match (span.name, field("method").and_then(Value::as_str), field("route").and_then(Value::as_str)) {
+        ("request", Some(method), Some(route)) => (format!("{} {}", method, route), 2),
+        ("request", _, _) => (span.name.to_string(), 2),
+        _ => (span.name.to_string(), 1),
+    };
+    // Unset unless the request ended in a server error.
+    let status = match field("status").and_then(Value::as_u64) {
+        Some(status) if status >= 500 => json!({ "code": 2 }),
+        _ => json!({ "code": 0 }),
+    };
+    let mut json = json!({
+        "traceId": hex(&span.trace_id),
+        "spanId": hex(&span.span_id),
+        "name": name,
+        "kind": kind,
+        "startTimeUnixNano": unix_nanos(span.start),
+        "endTimeUnixNano": unix_nanos(span.end),
+        "attributes": span.attributes.iter().map(|(key, value)| attribute(key, value)).collect::<Vec<Value>>(),
+        "status": status,
+    });
+    if let Some(parent) = &span.parent_span_id {
+        json["parentSpanId"] = Value::from(hex(parent));
+    }
+    json
+}
+
+fn attribute(key: &str, value: &Value) -> Value {
+    // 64-bit integers are strings in OTLP's JSON encoding.
+    let value = match value {
+        Value::Bool(value) => json!({ "boolValue": value }),
+        Value::Number(number) if number.is_f64() => json!({ "doubleValue": number }),
+        Value::Number(number) => json!({ "intValue": number.to_string() }),
+        Value::String(value) => json!({ "stringValue": value }),
+        other => json!({ "stringValue": other.to_string() }),
+    };
+    json!({ "key": key, "value": value })
+}
+
+fn unix_nanos(time: SystemTime) -> String {
+    time.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos().to_string()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
@@ -0,0 +1,165 @@
+use std::{collections::HashMap, io, path::PathBuf, sync::{Arc, LazyLock, RwLock}};
+use axum::body::Bytes;
+
+use crate::crypto;
+use crate::model::Poster;
+use crate::store::StoreFuture;
+
+// Poster images for PUT and GET /movie/{id}/poster. A PosterStore keeps the images under the SHA-256 of their contents,
+// in a directory with --poster-dir and otherwise in memory, and the movie keeps a model::Poster pointing at its one.
+// So a movie's poster goes with it, and two movies with the same poster share one image. Images nothing points at any
+// more, e.g. after a new poster is put, are left where they are, since another movie may have the same one.
+
+// Plenty for a poster, and little enough that holding one in memory while it's checked is fine.
+pub const MAX_POSTER_BYTES: usize = 5 * 1024 * 1024;
+// Room for the multipart boundaries and part headers around a poster of the largest size.
+pub const MULTIPART_OVERHEAD: usize = 16 * 1024;
+// What browsers show without help. Checked against the image itself, not just what the client says it is.
+pub const CONTENT_TYPES: &[&str] = &["image/jpeg", "image/png", "image/webp"];
+
+pub trait PosterStore: Send + Sync {
+    // None if there's no image with that hash.
+    fn get<'a>(&'a self, sha256: &'a str) -> StoreFuture<'a, io::Result<Option<Bytes>>>;
+    // Storing an image that's already there changes nothing.
+    fn put(&self, sha256: String, image: Bytes) -> StoreFuture<'_, io::Result<()>>;
+}
+
+// The default: images are gone on restart, even when the movies aren't.
+#[derive(Default)]
+pub struct MemoryPosterStore {
+    images: RwLock<HashMap<String, Bytes>>,
+}
+
+impl PosterStore for MemoryPosterStore {
+    fn get<'a>(&'a self, sha256: &'a str) -> StoreFuture<'a, io::Result<Option<Bytes>>> {
+        Box::pin(async move { Ok(self.images.read().unwrap().get(sha256).cloned()) })
+    }
+
+    fn put(&self, sha256: String, image: Bytes) -> StoreFuture<'_, io::Result<()>> {
+        Box::pin(async move {
+            self.images.write().unwrap().insert(sha256, image);
+            Ok(())
+        })
+    }
+}
+
+// One file per image, named by its hash.
+pub struct DiskPosterStore {
+    dir: PathBuf,
+}
+
+impl DiskPosterStore {
+    pub fn new(dir: PathBuf) -> DiskPosterStore {
+        DiskPosterStore { dir }
+    }
+}
+
+impl PosterStore for DiskPosterStore {
+    fn get<'a>(&'a self, sha256: &'a str) -> StoreFuture<'a, io::Result<Option<Bytes>>> {
+        Box::pin(async move {
+            match tokio::fs::read(self.dir.join(sha256)).await {
+                Ok(image) => Ok(Some(Bytes::from(image))),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(e),
+            }
+        })
+    }
+
+    fn put(&self, sha256: String, image: Bytes) -> StoreFuture<'_, io::Result<()>> {
+        Box::pin(async move {
+            let path = self.dir.join(&sha256);
+            if tokio::fs::try_exists(&path).await? {
+                return Ok(());
+            }
+            tokio::fs::create_dir_all(&self.dir).await?;
+            // Written next to where it goes and renamed into place, so a crash never leaves half an image under a hash.
+            let temp_path = self.dir.join(format!("{}.tmp", sha256));
+            tokio::fs::write(&temp_path, &image).await?;
+            tokio::fs::rename(&temp_path, &path).await
+        })
+    }
+}
+
+static STORE: LazyLock<RwLock<Arc<dyn PosterStore>>> = LazyLock::new(|| RwLock::new(Arc::new(MemoryPosterStore::default())));
+
+pub fn set_store(store: Arc<dyn PosterStore>) {
+    *STORE.write().unwrap() = store;
+}
+
+pub fn store() -> Arc<dyn PosterStore> {
+    STORE.read().unwrap().clone()
+}
+
+// What the image is, by its first few bytes, if it's one of CONTENT_TYPES.
+pub fn sniff(image: &[u8]) -> Option<&'static str> {
+    if image.starts_with(&[0xff, 0xd8, 0xff]) {
+        Some("image/jpeg")
+    }
+    else if image.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    }
+    else if image.len() >= 12 && image.starts_with(b"RIFF") && &image[8..12] == b"WEBP" {
+        Some("image/webp")
+    }
+    else {
+        None
+    }
+}
+
+// Checks the image is one we take, and is what `declared` says it is if that's more than application/octet-stream.
+pub fn describe(image: &[u8], declared: Option<&str>) -> Result<Poster, String> {
+    let content_type = sniff(image).ok_or_else(|| format!("Posters have to be one of {}", CONTENT_TYPES.join(", ")))?;
+    let declared = declared.map(|declared| media_type(declared).to_ascii_lowercase());
+    if let Some(declared) = declared
+        && declared != "application/octet-stream" && declared != content_type {
+        return Err(format!("The image is {}, not {}", content_type, declared));
+    }
+    let sha256 = crypto::sha256(image).iter().map(|byte| format!("{:02x}", byte)).collect();
+    Ok(Poster { content_type: content_type.to_string(), size: image.len(), sha256 })
+}
+
+// The type/subtype of a Content-Type, without its parameters.
+fn media_type(content_type: &str) -> &str {
+    content_type.split(';').next().unwrap_or_default().trim()
+}
+
+pub fn is_multipart(content_type: &str) -> bool {
+    media_type(content_type).eq_ignore_ascii_case("multipart/form-data")
+}
+
+// The first file in a multipart/form-data body (RFC 7578), and the Content-Type of its part if it has one. Parts
+// without a filename are form fields, and skipped.
+pub fn multipart_file(content_type: &str, body: &Bytes) -> Result<(Option<String>, Bytes), String> {
+    let boundary = content_type.split(';').skip(1)
+        .filter_map(|param| param.trim().split_once('='))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("boundary"))
+        .map(|(_, value)| value.trim().trim_matches('"'))
+        .filter(|boundary| !boundary.is_empty())
+        .ok_or("The multipart Content-Type has no boundary")?;
+    let delimiter = format!("--{}", boundary);
+    let mut at = find(body, delimiter.as_bytes(), 0).ok_or("The multipart body has no parts")? + delimiter.len();
+    loop {
+        // The last delimiter has -- after it.
+        if body[at..].starts_with(b"--") {
+            return Err("The multipart body has no file in it".to_string());
+        }
+        let headers_start = find(body, b"\r\n", at).ok_or("The multipart body ends early")? + 2;
+        let headers_end = find(body, b"\r\n\r\n", headers_start - 2).ok_or("A multipart part has no end to its headers")?;
+        let content_start = headers_end + 4;
+        let next = find(body, format!("\r\n{}", delimiter).as_bytes(), content_start).ok_or("A multipart part has no end")?;
+        // A part with no headers at all has its blank line straight after the delimiter's.
+        let headers = String::from_utf8_lossy(&body[headers_start..headers_end.max(headers_start)]);
+        let header = |name: &str| headers.split("\r\n")
+            .filter_map(|line| line.split_once(':'))
+            .find(|(key, _)| key.trim().eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.trim().to_string());
+        if header("content-disposition").is_some_and(|disposition| disposition.contains("filename=")) {
+            return Ok((header("content-type"), body.slice(content_start..next)));
+        }
+        at = next + 2 + delimiter.len();
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8], from: usize) -> Option<usize> {
+    haystack.get(from..)?.windows(needle.len()).position(|window| window == needle).map(|position| from + position)
+}
@@ -0,0 +1,226 @@
+use std::{fmt, io, sync::Arc, time::Duration};
+use serde_json::json;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use tokio::{io::{AsyncBufReadExt, AsyncWriteExt, BufReader}, net::{tcp::{OwnedReadHalf, OwnedWriteHalf}, TcpStream}, sync::{broadcast::{self, error::{RecvError, TryRecvError}}, Mutex}};
+use tracing::{debug, info, warn};
+
+use crate::events::{ChangeMessage, MovieEvent};
+use crate::http_client;
+use crate::random;
+use crate::resilience::{self, BreakerPolicy, CallError, Integration, RetryPolicy};
+
+// Publishes every change the store makes to a NATS subject or a Kafka topic, one message each, so pipelines further
+// along can follow the changes without polling. Messages are the same JSON as webhook deliveries, see
+// events::ChangeMessage, and on Kafka are keyed by the movie's id, so one movie's changes stay in order on one
+// partition. Changes go out one batch at a time in the order they were made. Publishing is best effort: a batch that
+// still can't be sent after its retries is dropped and logged, and while the broker is down resilience.rs stops trying
+// it for a while, so the API never waits on it.
+// Kafka is reached through its REST Proxy, since like everything else here this only speaks plain TCP and http.
+
+pub const DEFAULT_TOPIC: &str = "movies.changes";
+pub const DEFAULT_NATS_PORT: u16 = 4222;
+// Changes that came in while a batch was being sent go out together in the next one, up to this many.
+const MAX_BATCH: usize = 100;
+const PUBLISH_TIMEOUT: Duration = Duration::from_secs(5);
+const RETRY: RetryPolicy = RetryPolicy { attempts: 3, base_delay: Duration::from_millis(200), max_delay: Duration::from_secs(1) };
+const BREAKER: BreakerPolicy = BreakerPolicy { failures_to_open: 5, open_for: Duration::from_secs(30) };
+
+#[derive(Clone, PartialEq, Eq)]
+pub enum Broker {
+    // host:port of a NATS server, with the user and password to connect as if it wants them.
+    Nats { address: String, credentials: Option<(String, String)> },
+    // http:// URL of a Kafka REST Proxy, without a trailing slash.
+    KafkaRest { url: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PublishConfig {
+    pub broker: Broker,
+    // The NATS subject or Kafka topic.
+    pub topic: String,
+}
+
+// Keeps the password out of logs.
+impl fmt::Debug for Broker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Broker::Nats { address, .. } => write!(f, "nats://{}", address),
+            Broker::KafkaRest { url } => write!(f, "the Kafka REST Proxy at {}", url),
+        }
+    }
+}
+
+impl Broker {
+    // nats://[user:password@]host[:port]
+    pub fn nats(url: &str) -> Result<Broker, String> {
+        let rest = url.strip_prefix("nats://").ok_or_else(|| format!("expected nats://host:port, got {:?}", url))?;
+        let rest = rest.trim_end_matches('/');
+        let (credentials, authority) = match rest.rsplit_once('@') {
+            Some((userinfo, authority)) => {
+                let (user, password) = userinfo.split_once(':').ok_or_else(|| "expected user:password before the @".to_string())?;
+                (Some((user.to_string(), password.to_string())), authority)
+            },
+            None => (None, rest),
+        };
+        if authority.is_empty() || authority.contains('/') {
+            return Err(format!("expected nats://host:port, got {:?}", url));
+        }
+        let address = if authority.rsplit_once(':').is_some_and(|(_, port)| !port.contains(']')) {
+            authority.to_string()
+        }
+        else {
+            format!("{}:{}", authority, DEFAULT_NATS_PORT)
+        };
+        Ok(Broker::Nats { address, credentials })
+    }
+}
+
+// Starts publishing the changes that come through `changes`, until the store goes away.
+pub fn start(config: PublishConfig, mut changes: broadcast::Receiver<MovieEvent>) {
+    let publisher = Publisher {
+        integration: Integration::register("publish", RETRY, BREAKER),
+        connection: Mutex::new(None),
+        config,
+    };
+    info!("Publishing changes to {:?} on {}", publisher.config.broker, publisher.config.topic);
+    tokio::spawn(async move {
+        loop {
+            let first = match changes.recv().await {
+                Ok(change) => change,
+                Err(RecvError::Lagged(missed)) => {
+                    warn!("Publishing fell behind and missed {} changes", missed);
+                    continue;
+                },
+                Err(RecvError::Closed) => return,
+            };
+            let mut batch = vec![first];
+            while batch.len() < MAX_BATCH {
+                match changes.try_recv() {
+                    Ok(change) => batch.push(change),
+                    Err(TryRecvError::Lagged(missed)) => warn!("Publishing fell behind and missed {} changes", missed),
+                    Err(_) => break,
+                }
+            }
+            publisher.publish(&batch).await;
+        }
+    });
+}
+
+struct Publisher {
+    config: PublishConfig,
+    integration: Arc<Integration>,
+    // Kept between batches for NATS, and dropped whenever something goes wrong with it.
+    connection: Mutex<Option<NatsConnection>>,
+}
+
+// A change as it goes out: the movie's id, for Kafka's key, and the message.
+struct Outgoing {
+    key: String,
+    message: serde_json::Value,
+}
+
+impl Publisher {
+    async fn publish(&self, batch: &[MovieEvent]) {
+        let occurred_at = OffsetDateTime::now_utc().format(&Rfc3339).unwrap_or_default();
+        let outgoing: Vec<Outgoing> = batch.iter().map(|change| {
+            let id = random::uuid_v7();
+            let message = ChangeMessage { id: &id, event: change.name(), occurred_at: &occurred_at, movie: change.movie() };
+            Outgoing { key: change.movie().id.clone(), message: serde_json::to_value(&message).expect("messages always serialize") }
+        }).collect();
+        let published = self.integration.call(|| async {
+            match &self.config.broker {
+                Broker::Nats { address, credentials } => self.publish_nats(address, credentials.as_ref(), &outgoing).await,
+                Broker::KafkaRest { url } => self.publish_kafka(url, &outgoing).await,
+            }
+        }).await;
+        match published {
+            Ok(()) => debug!("Published {} changes to {}", outgoing.len(), self.config.topic),
+            Err(CallError::Open) => debug!("Dropped {} changes, the broker is failing", outgoing.len()),
+            Err(e) => warn!("Dropped {} changes: the broker {}", outgoing.len(), e),
+        }
+    }
+
+    async fn publish_nats(&self, address: &str, credentials: Option<&(String, String)>, outgoing: &[Outgoing]) -> Result<(), CallError> {
+        let mut connection = self.connection.lock().await;
+        let published = tokio::time::timeout(PUBLISH_TIMEOUT, async {
+            if connection.is_none() {
+                *connection = Some(NatsConnection::connect(address, credentials).await?);
+            }
+            let messages: Vec<Vec<u8>> = outgoing.iter().map(|outgoing| outgoing.message.to_string().into_bytes()).collect();
+            connection.as_mut().unwrap().publish(&self.config.topic, &messages).await
+        }).await.unwrap_or_else(|_| Err(CallError::Transient("timed out".to_string())));
+        if published.is_err() {
+            *connection = None;
+        }
+        published
+    }
+
+    // The REST Proxy's v2 API, which takes the whole batch at once.
+    async fn publish_kafka(&self, url: &str, outgoing: &[Outgoing]) -> Result<(), CallError> {
+        let records: Vec<_> = outgoing.iter().map(|outgoing| json!({ "key": outgoing.key, "value": outgoing.message })).collect();
+        let body = json!({ "records": records }).to_string();
+        let url = format!("{}/topics/{}", url, self.config.topic);
+        let headers = [("Content-Type", "application/vnd.kafka.json.v2+json"), ("Accept", "application/vnd.kafka.v2+json")];
+        let response = http_client::request("POST", &url, &headers, body.as_bytes(), PUBLISH_TIMEOUT).await?;
+        if !(200..300).contains(&response.status) {
+            return Err(resilience::status_error(response.status));
+        }
+        Ok(())
+    }
+}
+
+// Just enough of the NATS client protocol to publish: CONNECT, then PUBs, each batch followed by a PING whose PONG
+// says the server has taken everything before it.
+struct NatsConnection {
+    reader: BufReader<OwnedReadHalf>,
+    writer: OwnedWriteHalf,
+}
+
+impl NatsConnection {
+    async fn connect(address: &str, credentials: Option<&(String, String)>) -> Result<NatsConnection, CallError> {
+        let (reader, writer) = TcpStream::connect(address).await?.into_split();
+        let mut connection = NatsConnection { reader: BufReader::new(reader), writer };
+        let info = connection.read_line().await?;
+        if !info.starts_with("INFO") {
+            return Err(CallError::Permanent(format!("doesn't look like a NATS server, it said {:?}", info)));
+        }
+        let mut options = json!({ "verbose": false, "pedantic": false, "name": "syndica-rust", "lang": "rust", "version": env!("CARGO_PKG_VERSION") });
+        if let Some((user, password)) = credentials {
+            options["user"] = json!(user);
+            options["pass"] = json!(password);
+        }
+        connection.writer.write_all(format!("CONNECT {}\r\n", options).as_bytes()).await?;
+        Ok(connection)
+    }
+
+    async fn read_line(&mut self) -> Result<String, CallError> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line).await? == 0 {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        Ok(line.trim_end().to_string())
+    }
+
+    async fn publish(&mut self, subject: &str, messages: &[Vec<u8>]) -> Result<(), CallError> {
+        let mut out = Vec::new();
+        for message in messages {
+            out.extend_from_slice(format!("PUB {} {}\r\n", subject, message.len()).as_bytes());
+            out.extend_from_slice(message);
+            out.extend_from_slice(b"\r\n");
+        }
+        out.extend_from_slice(b"PING\r\n");
+        self.writer.write_all(&out).await?;
+        loop {
+            let line = self.read_line().await?;
+            match line.as_str() {
+                "PONG" => return Ok(()),
+                // The server checking on an idle connection.
+                "PING" => self.writer.write_all(b"PONG\r\n").await?,
+                // e.g. a wrong password, or a message bigger than the server takes.
+                error if error.starts_with("-ERR") => return Err(CallError::Permanent(format!("said {}", error))),
+                // +OK and fresh INFO.
+                _ => {},
+            }
+        }
+    }
+}
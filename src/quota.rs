@@ -0,0 +1,259 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use serde::Serialize;
+use time::OffsetDateTime;
+use tokio::sync::{broadcast, Mutex, MutexGuard};
+
+use crate::changes::{ChangePage, CursorExpired, Since};
+use crate::events::MovieEvent;
+use crate::labels::Label;
+use crate::model::{Link, Movie, MoviePatch, Revision, Trashed, User, UserChange};
+use crate::state::StateWrapper;
+use crate::store::{Backup, MovieFilter, MovieStore, Precondition, StoreError, StoreFuture};
+use crate::tenant::{self, TenantConfig, TenantStats};
+
+// Limits on how many movies are stored: --max-movies for all of them together, across every tenant, and a tenant's
+// own from --tenants. Writes that would add a movie past either fail with 507, and movies in the trash don't count
+// until they're restored. GET /admin/quotas shows how close each one is.
+
+// Which limit a write ran into, and how full it is. Sent as the details of the 507.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct QuotaExceeded {
+    // "global" for --max-movies, "tenant" for the tenant's own.
+    pub quota: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+    pub limit: usize,
+    pub used: usize,
+}
+
+impl QuotaExceeded {
+    pub fn message(&self) -> String {
+        match &self.tenant {
+            Some(tenant) => format!("The tenant {} already has {} movies, its quota is {}", tenant, self.used, self.limit),
+            None => format!("The server already holds {} movies, its limit is {}", self.used, self.limit),
+        }
+    }
+}
+
+// How full the store is, for GET /admin/quotas.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Usage {
+    // Movies in every catalog together, which --max-movies limits.
+    pub movies: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_movies: Option<usize>,
+    // The default catalog, then each tenant's.
+    pub tenants: Vec<TenantStats>,
+}
+
+pub async fn usage(store: &dyn MovieStore) -> Usage {
+    let tenants = tenant::stats(store).await;
+    Usage { movies: tenants.iter().map(|stats| stats.movies).sum(), max_movies: store.max_movies(), tenants }
+}
+
+// Checks the quotas in front of another store, a TenantMovieStore when there are tenants. Only writes that may add a
+// movie are held up, everything else goes straight through.
+pub struct QuotaMovieStore {
+    inner: StateWrapper,
+    // --max-movies, 0 for no limit.
+    max_movies: AtomicUsize,
+    // Held by writes that may add a movie while there's a quota, so two of them can't both take the last place.
+    adding: Mutex<()>,
+}
+
+impl QuotaMovieStore {
+    pub fn new(inner: StateWrapper, max_movies: Option<usize>) -> QuotaMovieStore {
+        QuotaMovieStore { inner, max_movies: AtomicUsize::new(max_movies.unwrap_or(0)), adding: Mutex::new(()) }
+    }
+
+    // For reloads. Stores that are already over the new limit keep their movies, they just can't add more.
+    pub fn set_max_movies(&self, max_movies: Option<usize>) {
+        self.max_movies.store(max_movies.unwrap_or(0), Ordering::Relaxed);
+    }
+
+    // Movies in every catalog together. Each count is started inside its scope, since TenantMovieStore picks the
+    // tenant's store when it's called.
+    async fn total(&self) -> usize {
+        let mut total = tenant::scope(None, async { self.inner.count().await }).await;
+        for tenant in self.inner.tenants() {
+            total += tenant::scope(Some(tenant.id), async { self.inner.count().await }).await;
+        }
+        total
+    }
+
+    // Fails with QuotaExceeded unless the current tenant, and the store as a whole, have room for `adding` more movies
+    // once `replacing` of the tenant's are gone, usize::MAX for all of them. Nothing is needed when `overwriting` is
+    // already stored. The guard is to be held until the write is done.
+    async fn make_room(&self, adding: usize, replacing: usize, overwriting: Option<&str>) -> Result<Option<MutexGuard<'_, ()>>, StoreError> {
+        let current = tenant::current();
+        let tenant_max = current.as_ref().and_then(|id| self.inner.tenants().into_iter().find(|tenant| tenant.id == *id)?.max_movies);
+        let global_max = self.max_movies();
+        if tenant_max.is_none() && global_max.is_none() {
+            return Ok(None);
+        }
+        let guard = self.adding.lock().await;
+        if let Some(id) = overwriting
+            && self.inner.get(id).await.is_some() {
+            return Ok(Some(guard));
+        }
+        let used = self.inner.count().await;
+        let kept = used - replacing.min(used);
+        if let Some(limit) = tenant_max
+            && kept + adding > limit {
+            return Err(StoreError::QuotaExceeded(QuotaExceeded { quota: "tenant", tenant: current, limit, used }));
+        }
+        if let Some(limit) = global_max {
+            let total = self.total().await;
+            if total - (used - kept) + adding > limit {
+                return Err(StoreError::QuotaExceeded(QuotaExceeded { quota: "global", tenant: None, limit, used: total }));
+            }
+        }
+        Ok(Some(guard))
+    }
+}
+
+impl MovieStore for QuotaMovieStore {
+    fn get<'a>(&'a self, id: &'a str) -> StoreFuture<'a, Option<Movie>> {
+        self.inner.get(id)
+    }
+
+    fn insert(&self, movie: Movie) -> StoreFuture<'_, Result<(), StoreError>> {
+        Box::pin(async move {
+            let _adding = self.make_room(1, 0, None).await?;
+            self.inner.insert(movie).await
+        })
+    }
+
+    fn upsert(&self, movie: Movie) -> StoreFuture<'_, Result<bool, StoreError>> {
+        Box::pin(async move {
+            let _adding = self.make_room(1, 0, Some(&movie.id)).await?;
+            self.inner.upsert(movie).await
+        })
+    }
+
+    fn put(&self, movie: Movie) -> StoreFuture<'_, Result<bool, StoreError>> {
+        Box::pin(async move {
+            let _adding = self.make_room(1, 0, Some(&movie.id)).await?;
+            self.inner.put(movie).await
+        })
+    }
+
+    fn update_if<'a>(&'a self, movie: Movie, precondition: Precondition<'a>) -> StoreFuture<'a, Result<(), StoreError>> {
+        self.inner.update_if(movie, precondition)
+    }
+
+    fn patch<'a>(&'a self, id: &'a str, patch: MoviePatch) -> StoreFuture<'a, Result<Movie, StoreError>> {
+        self.inner.patch(id, patch)
+    }
+
+    fn delete_if<'a>(&'a self, id: &'a str, precondition: Precondition<'a>) -> StoreFuture<'a, Result<Movie, StoreError>> {
+        self.inner.delete_if(id, precondition)
+    }
+
+    fn trash(&self) -> StoreFuture<'_, Vec<Trashed>> {
+        self.inner.trash()
+    }
+
+    fn restore<'a>(&'a self, id: &'a str) -> StoreFuture<'a, Result<Movie, StoreError>> {
+        Box::pin(async move {
+            let _adding = self.make_room(1, 0, None).await?;
+            self.inner.restore(id).await
+        })
+    }
+
+    fn purge(&self, deleted_before: OffsetDateTime) -> StoreFuture<'_, Result<Vec<String>, StoreError>> {
+        self.inner.purge(deleted_before)
+    }
+
+    fn backup(&self) -> StoreFuture<'_, Backup> {
+        self.inner.backup()
+    }
+
+    // The backup's movies take the place of all of the tenant's.
+    fn replace(&self, backup: Backup) -> StoreFuture<'_, Result<(), StoreError>> {
+        Box::pin(async move {
+            let _adding = self.make_room(backup.movies.len(), usize::MAX, None).await?;
+            self.inner.replace(backup).await
+        })
+    }
+
+    fn list<'a>(&'a self, filter: &'a MovieFilter) -> StoreFuture<'a, Vec<Movie>> {
+        self.inner.list(filter)
+    }
+
+    fn scan<'a>(&'a self, after: Option<&'a str>, limit: usize) -> StoreFuture<'a, Vec<Movie>> {
+        self.inner.scan(after, limit)
+    }
+
+    fn suggest<'a>(&'a self, prefix: &'a str, limit: usize) -> StoreFuture<'a, Vec<String>> {
+        self.inner.suggest(prefix, limit)
+    }
+
+    fn genres(&self) -> StoreFuture<'_, Vec<Label>> {
+        self.inner.genres()
+    }
+
+    fn tags(&self) -> StoreFuture<'_, Vec<Label>> {
+        self.inner.tags()
+    }
+
+    fn genre_movie_ids<'a>(&'a self, genre: &'a str) -> StoreFuture<'a, Vec<String>> {
+        self.inner.genre_movie_ids(genre)
+    }
+
+    fn count(&self) -> StoreFuture<'_, usize> {
+        self.inner.count()
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<MovieEvent> {
+        self.inner.subscribe()
+    }
+
+    fn changes(&self, since: &Since, limit: usize) -> Result<ChangePage, CursorExpired> {
+        self.inner.changes(since, limit)
+    }
+
+    fn check_ready(&self) -> StoreFuture<'_, Result<(), StoreError>> {
+        self.inner.check_ready()
+    }
+
+    fn close(&self) -> StoreFuture<'_, Result<(), StoreError>> {
+        self.inner.close()
+    }
+
+    fn history<'a>(&'a self, id: &'a str) -> StoreFuture<'a, Option<Vec<Revision>>> {
+        self.inner.history(id)
+    }
+
+    fn tenants(&self) -> Vec<TenantConfig> {
+        self.inner.tenants()
+    }
+
+    fn max_movies(&self) -> Option<usize> {
+        Some(self.max_movies.load(Ordering::Relaxed)).filter(|max| *max > 0)
+    }
+
+    fn get_user<'a>(&'a self, id: &'a str) -> StoreFuture<'a, Option<User>> {
+        self.inner.get_user(id)
+    }
+
+    fn insert_user(&self, user: User) -> StoreFuture<'_, Result<(), StoreError>> {
+        self.inner.insert_user(user)
+    }
+
+    fn change_user<'a>(&'a self, user_id: &'a str, change: UserChange) -> StoreFuture<'a, Result<User, StoreError>> {
+        self.inner.change_user(user_id, change)
+    }
+
+    fn links<'a>(&'a self, movie_id: &'a str) -> StoreFuture<'a, Vec<Link>> {
+        self.inner.links(movie_id)
+    }
+
+    fn link(&self, link: Link) -> StoreFuture<'_, Result<bool, StoreError>> {
+        self.inner.link(link)
+    }
+
+    fn unlink<'a>(&'a self, link: &'a Link) -> StoreFuture<'a, Result<bool, StoreError>> {
+        self.inner.unlink(link)
+    }
+}
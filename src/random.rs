@@ -0,0 +1,50 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Fills the buffer from the kernel's CSPRNG.
+pub fn fill_bytes(buffer: &mut [u8]) {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        let remaining = &mut buffer[filled..];
+        let read = unsafe { libc::getrandom(remaining.as_mut_ptr().cast(), remaining.len(), 0) };
+        if read < 0 {
+            let error = std::io::Error::last_os_error();
+            if error.kind() == std::io::ErrorKind::Interrupted {
+                continue;
+            }
+            panic!("getrandom failed: {}", error);
+        }
+        filled += read as usize;
+    }
+}
+
+// Uniformly random in 0..bound, which mustn't be 0. Draws again rather than taking the remainder of a draw that
+// would favour the low numbers.
+pub fn below(bound: usize) -> usize {
+    assert!(bound > 0, "no number is below 0");
+    let bound = bound as u64;
+    // The largest multiple of bound that fits, so every remainder is equally likely below it.
+    let zone = u64::MAX - u64::MAX % bound;
+    loop {
+        let mut bytes = [0u8; 8];
+        fill_bytes(&mut bytes);
+        let draw = u64::from_ne_bytes(bytes);
+        if draw < zone {
+            return (draw % bound) as usize;
+        }
+    }
+}
+
+// A new UUIDv7 (RFC 9562) as a lowercase hyphenated string. These start with a millisecond timestamp, so ids handed
+// out later sort after ids handed out earlier, which keeps newly created movies at the end of the listing.
+pub fn uuid_v7() -> String {
+    let mut bytes = [0u8; 16];
+    fill_bytes(&mut bytes[6..]);
+    let millis = SystemTime::now().duration_since(UNIX_EPOCH).expect("clock is before 1970").as_millis() as u64;
+    bytes[..6].copy_from_slice(&millis.to_be_bytes()[2..]);
+    // Version 7 in the high nibble of byte 6, RFC 4122 variant in the top two bits of byte 8.
+    bytes[6] = (bytes[6] & 0x0f) | 0x70;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+    let hex: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("{}-{}-{}-{}-{}", &hex[0..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..32])
+}
@@ -0,0 +1,106 @@
+use std::{collections::HashMap, net::SocketAddr, time::{Duration, Instant}};
+use axum::{extract::{ConnectInfo, Extension, MatchedPath, Request, State}, http::StatusCode, middleware::Next, response::{IntoResponse, Response}};
+use tracing::debug;
+
+use crate::auth::Caller;
+use crate::error::ApiError;
+use crate::settings::Settings;
+
+// Token buckets per client, so one busy client can't take all of the server for itself. A client is whoever's API key,
+// bearer token or login let them in, or the address the request came from when nothing did.
+//
+// Requests that fail authentication never get that far, so they count against their address in buckets of their own
+// instead: once an address has used up its burst of 401s, everything it sends gets a 429 until the rate gives some
+// back, which keeps keys and tokens from being guessed at full speed.
+
+// Past this many clients, the ones whose buckets have filled up again are forgotten, which changes nothing for them.
+const MAX_BUCKETS: usize = 100_000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    // Requests per second a client gets back.
+    pub rate: f64,
+    // Requests a client can make at once before it has to wait for the rate.
+    pub burst: u32,
+}
+
+pub(crate) struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+// The limit and every client's bucket, kept in the router's Settings.
+#[derive(Default)]
+pub(crate) struct Limiter {
+    pub(crate) limit: Option<RateLimit>,
+    pub(crate) buckets: HashMap<String, Bucket>,
+}
+
+// Takes one token from the client's bucket, or says how long until there is one. Without spending, it only checks.
+fn take(settings: &Settings, client: &str, now: Instant, spend: bool) -> Result<(), Duration> {
+    let mut limiter = settings.limiter().lock().unwrap();
+    let Some(limit) = limiter.limit else { return Ok(()) };
+    let burst = limit.burst as f64;
+    if limiter.buckets.len() >= MAX_BUCKETS {
+        limiter.buckets.retain(|_, bucket| bucket.tokens + now.duration_since(bucket.updated).as_secs_f64() * limit.rate < burst);
+    }
+    let bucket = limiter.buckets.entry(client.to_string()).or_insert(Bucket { tokens: burst, updated: now });
+    bucket.tokens = (bucket.tokens + now.duration_since(bucket.updated).as_secs_f64() * limit.rate).min(burst);
+    bucket.updated = now;
+    if bucket.tokens >= 1.0 {
+        if spend {
+            bucket.tokens -= 1.0;
+        }
+        Ok(())
+    }
+    else {
+        Err(Duration::from_secs_f64((1.0 - bucket.tokens) / limit.rate))
+    }
+}
+
+fn address(request: &Request) -> String {
+    match request.extensions().get::<ConnectInfo<SocketAddr>>() {
+        Some(ConnectInfo(address)) => address.ip().to_string(),
+        None => "unknown".to_string(),
+    }
+}
+
+fn is_exempt(exempt: &[&str], request: &Request) -> bool {
+    let route = request.extensions().get::<MatchedPath>().map_or(request.uri().path(), MatchedPath::as_str);
+    exempt.contains(&route)
+}
+
+// Middleware for the whole router, inside auth::authorize so it knows which credential was accepted. Routes in the
+// state, like the health checks, aren't limited.
+pub async fn limit_requests(State(exempt): State<&'static [&'static str]>, Extension(settings): Extension<Settings>, request: Request, next: Next) -> Response {
+    if is_exempt(exempt, &request) {
+        return next.run(request).await;
+    }
+    let client = match request.extensions().get::<Caller>().and_then(|caller| caller.credential.clone()) {
+        Some(credential) => credential,
+        None => address(&request),
+    };
+    if let Err(retry_after) = take(&settings, &client, Instant::now(), true) {
+        debug!("Rate limiting {} on {} {}", client, request.method(), request.uri().path());
+        return ApiError::RateLimited(retry_after).into_response();
+    }
+    next.run(request).await
+}
+
+// Middleware for the whole router, outside auth::authorize, counting the 401s it gives each address.
+pub async fn limit_failed_authentication(State(exempt): State<&'static [&'static str]>, Extension(settings): Extension<Settings>, request: Request, next: Next) -> Response {
+    if is_exempt(exempt, &request) {
+        return next.run(request).await;
+    }
+    let client = format!("unauthenticated:{}", address(&request));
+    if let Err(retry_after) = take(&settings, &client, Instant::now(), false) {
+        debug!("Rate limiting {} after failed authentication on {} {}", client, request.method(), request.uri().path());
+        return ApiError::RateLimited(retry_after).into_response();
+    }
+    let response = next.run(request).await;
+    if response.status() == StatusCode::UNAUTHORIZED {
+        // The check above left at least one token, so this can't fail.
+        let _ = take(&settings, &client, Instant::now(), true);
+    }
+    response
+}
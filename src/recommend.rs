@@ -0,0 +1,63 @@
+use std::collections::{BTreeMap, BTreeSet};
+use serde::Serialize;
+
+use crate::model::{Movie, User};
+
+// Suggests movies for GET /users/{id}/recommendations from what their favorites have in common. Each genre and tag of
+// a favorite counts for the share of their favorites that have it, and a movie scores the sum of what its own genres
+// and tags count for, so one that shares a genre with every favorite beats one that shares a tag with a single one.
+// Favorites and anything they've watched are left out, the first being known to them and the second seen already.
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Recommendation {
+    pub movie: Movie,
+    // Above 0, and at most the number of genres and tags the movie has.
+    pub score: f64,
+    // The movie's genres and tags that some favorite also has, as the movie spells them.
+    pub because: Vec<String>,
+}
+
+// Genres and tags are kept apart, so the genre Noir and the tag noir don't count for each other.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Feature {
+    Genre(String),
+    Tag(String),
+}
+
+// The movie's genres and tags, lowercased, each once, with how the movie spells them.
+fn features(movie: &Movie) -> BTreeMap<Feature, &str> {
+    let genres = movie.genres.iter().map(|genre| (Feature::Genre(genre.to_lowercase()), genre.as_str()));
+    let tags = movie.tags.iter().map(|tag| (Feature::Tag(tag.to_lowercase()), tag.as_str()));
+    genres.chain(tags).collect()
+}
+
+// The movies to recommend to the user out of `movies`, best first and then in id order. Empty without favorites, or
+// when none of them have a genre or tag.
+pub fn recommend(user: &User, movies: Vec<Movie>) -> Vec<Recommendation> {
+    let favorites: BTreeSet<&str> = user.favorites.iter().map(String::as_str).collect();
+    let watched: BTreeSet<&str> = user.history.iter().map(|watch| watch.movie_id.as_str()).collect();
+    let mut weights: BTreeMap<Feature, f64> = BTreeMap::new();
+    // Deleted favorites are already off the list, but one could go between reading the user and listing the movies.
+    let liked: Vec<&Movie> = movies.iter().filter(|movie| favorites.contains(movie.id.as_str())).collect();
+    for movie in &liked {
+        for feature in features(movie).into_keys() {
+            *weights.entry(feature).or_default() += 1.0 / liked.len() as f64;
+        }
+    }
+    let mut recommendations: Vec<Recommendation> = movies.iter()
+        .filter(|movie| !favorites.contains(movie.id.as_str()) && !watched.contains(movie.id.as_str()))
+        .filter_map(|movie| {
+            let shared: Vec<(f64, &str)> = features(movie).into_iter()
+                .filter_map(|(feature, name)| Some((*weights.get(&feature)?, name)))
+                .collect();
+            (!shared.is_empty()).then(|| Recommendation {
+                movie: movie.clone(),
+                score: shared.iter().map(|(weight, _)| weight).sum(),
+                because: shared.into_iter().map(|(_, name)| name.to_string()).collect(),
+            })
+        })
+        .collect();
+    // Stable, so equal scores stay in the id order the store lists them in.
+    recommendations.sort_by(|a, b| b.score.total_cmp(&a.score));
+    recommendations
+}
@@ -0,0 +1,236 @@
+use std::{fmt, sync::{atomic::{AtomicBool, AtomicU64, Ordering}, RwLock}, time::Duration};
+use axum::{extract::{MatchedPath, Request, State}, http::Method, middleware::Next, response::{IntoResponse, Response}};
+use serde::{de::DeserializeOwned, Deserialize};
+use time::OffsetDateTime;
+use tracing::{debug, info, warn};
+
+use crate::error::ApiError;
+use crate::http_client;
+use crate::model::{Movie, MoviePage};
+use crate::state::StateWrapper;
+use crate::store::StoreError;
+use crate::versioning;
+
+// With --follow, an instance is a read-only follower of another, its leader: it copies the leader's movies when it
+// starts, then keeps up with them through the leader's change feed, GET /movies/changes, so it can take read traffic
+// off the leader. To copy the movies it takes a cursor for the feed first and then pages through GET /movies, so
+// nothing changed while it copies is missed: changes made during the copy are applied again on top of it. If the
+// leader restarts, or the follower falls so far behind that the leader has dropped the changes it needs, the feed
+// answers 410 and the follower copies everything again. A follower that restarts does the same, so it needs no state
+// of its own to catch up. Only the default catalog's movies are followed, not users, links or the trash.
+// Everything that would write, bar the few POSTs that only read, gets a 403 pointing at the leader, and /readyz says
+// unready until the first copy is in. The copy replaces the follower's movies as a whole, like restoring a backup.
+// After that each change is applied on its own, with MovieStore::put or delete, so a poll costs as much as the changes
+// it brings. Either way the movies are kept exactly as the leader has them, versions and all. Movies the leader
+// deletes go in the follower's own trash.
+
+pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(1);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+// Movies or changes fetched a request, the most GET /movies and GET /movies/changes give.
+const PAGE_LIMIT: usize = 100;
+
+#[derive(Clone, PartialEq, Eq)]
+pub struct FollowConfig {
+    // http://host[:port], without a trailing slash.
+    pub leader: String,
+    // Sent as X-Api-Key, for leaders that need one to read.
+    pub api_key: Option<String>,
+    // How long to wait before asking the leader for changes again when it had none.
+    pub interval: Duration,
+}
+
+// Keeps the API key out of logs.
+impl fmt::Debug for FollowConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FollowConfig")
+            .field("leader", &self.leader)
+            .field("interval", &self.interval)
+            .finish_non_exhaustive()
+    }
+}
+
+static LEADER: RwLock<Option<String>> = RwLock::new(None);
+// Whether the first copy of the leader's movies is still being made, for /readyz.
+static COPYING: AtomicBool = AtomicBool::new(false);
+// Unix seconds of the last time the follower was up to date with the leader, 0 for not yet.
+static LAST_SYNC: AtomicU64 = AtomicU64::new(0);
+
+// The leader this instance follows, or None if it takes writes itself.
+pub fn leader() -> Option<String> {
+    LEADER.read().unwrap().clone()
+}
+
+pub fn is_copying() -> bool {
+    COPYING.load(Ordering::Relaxed)
+}
+
+// When the follower last caught up with the leader, for /metrics, or None if this isn't a follower.
+pub fn last_sync() -> Option<u64> {
+    leader().map(|_| LAST_SYNC.load(Ordering::Relaxed))
+}
+
+// Fails on a follower, for writes that don't go through refuse_writes, like GraphQL mutations.
+pub fn check_writable() -> Result<(), ApiError> {
+    match leader() {
+        Some(leader) => Err(ApiError::Forbidden(format!("This instance is a read-only follower, send writes to {}", leader))),
+        None => Ok(()),
+    }
+}
+
+// Middleware for the whole router. On a follower only reads are let through: GET, HEAD and OPTIONS, and the routes
+// in the state, which take a POST without writing anything.
+pub async fn refuse_writes(State(reads): State<&'static [(Method, &'static str)]>, request: Request, next: Next) -> Response {
+    let method = request.method();
+    if matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS) {
+        return next.run(request).await;
+    }
+    let route = request.extensions().get::<MatchedPath>().map_or(request.uri().path(), MatchedPath::as_str);
+    let route = versioning::unversioned_route(route);
+    if reads.iter().any(|(read_method, path)| read_method == method && *path == route) {
+        return next.run(request).await;
+    }
+    if let Err(error) = check_writable() {
+        debug!("Refusing {} {} on a follower", method, route);
+        return error.into_response();
+    }
+    next.run(request).await
+}
+
+// A page of the leader's GET /movies/changes, with only what a follower needs.
+#[derive(Deserialize)]
+struct ChangePage {
+    items: Vec<Change>,
+    cursor: String,
+    has_more: bool,
+}
+
+#[derive(Deserialize)]
+struct Change {
+    event: String,
+    movie: Movie,
+}
+
+#[derive(Debug)]
+enum SyncError {
+    // The leader no longer has the changes after the cursor, so the movies have to be copied again.
+    Gone,
+    Failed(String),
+}
+
+struct Follower {
+    config: FollowConfig,
+    store: StateWrapper,
+}
+
+// Makes this instance a follower: from now on writes are refused, and `store` is filled with the leader's movies and
+// kept up with them until the process exits.
+pub fn start(config: FollowConfig, store: StateWrapper) {
+    info!("Following {}, writes are refused", config.leader);
+    *LEADER.write().unwrap() = Some(config.leader.clone());
+    COPYING.store(true, Ordering::Relaxed);
+    let follower = Follower { config, store };
+    tokio::spawn(async move {
+        loop {
+            let cursor = match follower.copy().await {
+                Ok(cursor) => cursor,
+                Err(e) => {
+                    warn!("Failed to copy the movies of {}, trying again: {:?}", follower.config.leader, e);
+                    tokio::time::sleep(follower.config.interval).await;
+                    continue;
+                },
+            };
+            COPYING.store(false, Ordering::Relaxed);
+            follower.follow(cursor).await;
+            info!("{} no longer has the changes this follower needs, copying its movies again", follower.config.leader);
+        }
+    });
+}
+
+impl Follower {
+    // Replaces the store's movies with the leader's, and returns the cursor to follow its changes from.
+    async fn copy(&self) -> Result<String, SyncError> {
+        let start: ChangePage = self.get("/v1/movies/changes?since=latest").await?;
+        let mut movies = Vec::new();
+        let mut cursor = String::new();
+        loop {
+            let page: MoviePage = self.get(&format!("/v1/movies?limit={}&cursor={}", PAGE_LIMIT, cursor)).await?;
+            movies.extend(page.items);
+            match page.next_cursor {
+                Some(next) => cursor = next,
+                None => break,
+            }
+        }
+        let count = movies.len();
+        self.replace_movies(movies).await?;
+        info!("Copied {} movies from {}", count, self.config.leader);
+        let mut cursor = start.cursor;
+        // The changes made while the movies were being copied, some of which the copy may have missed.
+        self.catch_up(&mut cursor).await?;
+        Ok(cursor)
+    }
+
+    // Applies the leader's changes as they come until the leader no longer has the ones after the cursor. Other
+    // failures, like the leader being down, are waited out.
+    async fn follow(&self, mut cursor: String) {
+        loop {
+            match self.catch_up(&mut cursor).await {
+                Ok(()) => tokio::time::sleep(self.config.interval).await,
+                Err(SyncError::Gone) => return,
+                Err(SyncError::Failed(message)) => {
+                    warn!("Failed to follow the changes of {}: {}", self.config.leader, message);
+                    tokio::time::sleep(self.config.interval).await;
+                },
+            }
+        }
+    }
+
+    // Applies every change after the cursor, moving it along with them, until there are no more.
+    async fn catch_up(&self, cursor: &mut String) -> Result<(), SyncError> {
+        loop {
+            let page: ChangePage = self.get(&format!("/v1/movies/changes?since={}&limit={}", cursor, PAGE_LIMIT)).await?;
+            for change in page.items {
+                self.apply(change).await?;
+            }
+            *cursor = page.cursor;
+            if !page.has_more {
+                LAST_SYNC.store(OffsetDateTime::now_utc().unix_timestamp() as u64, Ordering::Relaxed);
+                return Ok(());
+            }
+        }
+    }
+
+    // Applies one of the leader's changes. The page it's on is applied again if a later one fails, and changes made
+    // during a copy may already be in it, so applying one twice has to come out the same.
+    async fn apply(&self, change: Change) -> Result<(), SyncError> {
+        let result = match change.event.as_str() {
+            "deleted" => match self.store.delete(&change.movie.id).await {
+                Err(StoreError::NotFound) => Ok(()),
+                result => result.map(drop),
+            },
+            _ => self.store.put(change.movie).await.map(drop),
+        };
+        result.map_err(|e| SyncError::Failed(format!("Failed to apply a change from the leader: {:?}", e)))
+    }
+
+    // Swaps the store's movies for `movies`, keeping the rest of what it holds.
+    async fn replace_movies(&self, movies: Vec<Movie>) -> Result<(), SyncError> {
+        let mut backup = self.store.backup().await;
+        backup.movies = movies;
+        self.store.replace(backup).await.map_err(|e| SyncError::Failed(format!("Failed to store the leader's movies: {:?}", e)))
+    }
+
+    async fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T, SyncError> {
+        let url = format!("{}{}", self.config.leader, path);
+        let mut headers = vec![("Accept", "application/json")];
+        if let Some(api_key) = &self.config.api_key {
+            headers.push(("X-Api-Key", api_key));
+        }
+        let response = http_client::request("GET", &url, &headers, &[], REQUEST_TIMEOUT).await
+            .map_err(|e| SyncError::Failed(format!("GET {} failed: {}", url, e)))?;
+        match response.status {
+            200 => serde_json::from_slice(&response.body).map_err(|e| SyncError::Failed(format!("GET {} answered with something unexpected: {}", url, e))),
+            410 => Err(SyncError::Gone),
+            status => Err(SyncError::Failed(format!("GET {} answered {}", url, status))),
+        }
+    }
+}
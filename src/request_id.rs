@@ -0,0 +1,37 @@
+use axum::{extract::Request, http::{HeaderName, HeaderValue}, middleware::Next, response::Response};
+
+use crate::random;
+
+pub static REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+// Longer inbound ids are replaced rather than echoed into every log line.
+const MAX_INBOUND_LEN: usize = 128;
+
+// The id of the request being handled, in the request's extensions.
+#[derive(Debug, Clone)]
+pub struct RequestId(pub String);
+
+tokio::task_local! {
+    // So that code far from the request, like ApiError's IntoResponse, can tell which one it is part of.
+    static CURRENT: String;
+}
+
+// The id of the request currently being handled on this task, if any.
+pub fn current() -> Option<String> {
+    CURRENT.try_with(|id| id.clone()).ok()
+}
+
+// Middleware giving every request an id: the caller's x-request-id if it sent a sensible one, a fresh UUIDv7
+// otherwise. It goes back out on the response, so both sides can refer to the request by it.
+pub async fn propagate_request_id(mut request: Request, next: Next) -> Response {
+    let id = request.headers().get(&REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| !id.is_empty() && id.len() <= MAX_INBOUND_LEN && id.bytes().all(|byte| byte.is_ascii_graphic()))
+        .map(str::to_string)
+        .unwrap_or_else(random::uuid_v7);
+    request.extensions_mut().insert(RequestId(id.clone()));
+    let mut response = CURRENT.scope(id.clone(), next.run(request)).await;
+    // Only visible ASCII made it this far, so this can't fail.
+    response.headers_mut().insert(REQUEST_ID_HEADER.clone(), HeaderValue::from_str(&id).unwrap());
+    response
+}
@@ -0,0 +1,224 @@
+use std::{fmt, future::Future, io, sync::{atomic::{AtomicU64, Ordering}, Arc, Mutex}, time::{Duration, Instant}};
+use tracing::{info, warn};
+
+use crate::random;
+
+// What every call out to another service goes through: retries with exponential backoff for failures that might go
+// away, and a circuit breaker that stops calling a service once enough calls to it have failed in a row, then lets
+// one call through every so often to see if it's back. Each service is an Integration, registered once, and
+// /metrics reports each one's breaker state and calls (see metrics.rs), whoever makes them.
+
+// How often to try a call, and how long to wait in between.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    // Tries in all, so 1 for no retries.
+    pub attempts: u32,
+    // Doubling before each retry after the first, up to max_delay.
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    // Somewhere between half the backoff and all of it, so callers that failed together don't all come back together.
+    fn delay(&self, retry: u32) -> Duration {
+        let backoff = self.base_delay.saturating_mul(1 << retry.min(16)).min(self.max_delay);
+        let half = backoff / 2;
+        half + Duration::from_micros(random::below(half.as_micros() as usize + 1) as u64)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BreakerPolicy {
+    // Failed calls in a row, each after its retries, before the breaker opens.
+    pub failures_to_open: u32,
+    // How long it stays open before a call may try the service again.
+    pub open_for: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BreakerState {
+    Closed,
+    // Has been open for long enough that the next call goes through as a trial.
+    HalfOpen,
+    Open,
+}
+
+// Why an outbound call failed.
+#[derive(Debug)]
+pub enum CallError {
+    // Worth another try: the service couldn't be reached, was too slow or had trouble of its own.
+    Transient(String),
+    // Won't go any better next time, e.g. a 401 for a wrong API key. Still counts against the breaker.
+    Permanent(String),
+    // The breaker is open, so the service wasn't called.
+    Open,
+}
+
+impl fmt::Display for CallError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CallError::Transient(message) | CallError::Permanent(message) => f.write_str(message),
+            CallError::Open => f.write_str("isn't being called after failing too often"),
+        }
+    }
+}
+
+impl From<io::Error> for CallError {
+    fn from(error: io::Error) -> CallError {
+        CallError::Transient(format!("couldn't be reached: {}", error))
+    }
+}
+
+// For a response that wasn't a success: timeouts, rate limits and server errors may pass, the rest won't.
+pub fn status_error(status: u16) -> CallError {
+    let message = format!("returned {}", status);
+    if status == 408 || status == 429 || status >= 500 { CallError::Transient(message) } else { CallError::Permanent(message) }
+}
+
+#[derive(Default)]
+struct Breaker {
+    failures: u32,
+    // Set once the breaker opens, until a call succeeds again.
+    open_until: Option<Instant>,
+}
+
+pub struct Integration {
+    name: String,
+    retry: RetryPolicy,
+    policy: BreakerPolicy,
+    breaker: Mutex<Breaker>,
+    successes: AtomicU64,
+    failures: AtomicU64,
+    rejections: AtomicU64,
+    retries: AtomicU64,
+    trips: AtomicU64,
+}
+
+// Every Integration there is, for /metrics.
+static INTEGRATIONS: Mutex<Vec<Arc<Integration>>> = Mutex::new(Vec::new());
+
+impl Integration {
+    // `name` labels it in logs and metrics, so should be short, e.g. "enrich".
+    pub fn register(name: impl Into<String>, retry: RetryPolicy, policy: BreakerPolicy) -> Arc<Integration> {
+        let integration = Arc::new(Integration {
+            name: name.into(),
+            retry,
+            policy,
+            breaker: Mutex::new(Breaker::default()),
+            successes: AtomicU64::new(0),
+            failures: AtomicU64::new(0),
+            rejections: AtomicU64::new(0),
+            retries: AtomicU64::new(0),
+            trips: AtomicU64::new(0),
+        });
+        INTEGRATIONS.lock().unwrap().push(integration.clone());
+        integration
+    }
+
+    // Takes it out of /metrics, for services that come and go, like webhooks.
+    pub fn unregister(&self) {
+        INTEGRATIONS.lock().unwrap().retain(|integration| !std::ptr::eq(integration.as_ref(), self));
+    }
+
+    pub fn state(&self) -> BreakerState {
+        match self.breaker.lock().unwrap().open_until {
+            None => BreakerState::Closed,
+            Some(until) if Instant::now() < until => BreakerState::Open,
+            Some(_) => BreakerState::HalfOpen,
+        }
+    }
+
+    // Closes the breaker, e.g. when the service is swapped for another, which has yet to fail.
+    pub fn reset(&self) {
+        *self.breaker.lock().unwrap() = Breaker::default();
+    }
+
+    // Makes the call, and again while it fails with CallError::Transient and there are attempts left. Fails with
+    // CallError::Open straight away if the breaker is open.
+    pub async fn call<T, F, Fut>(&self, mut attempt: F) -> Result<T, CallError>
+    where F: FnMut() -> Fut, Fut: Future<Output = Result<T, CallError>> {
+        if !self.allow() {
+            self.rejections.fetch_add(1, Ordering::Relaxed);
+            return Err(CallError::Open);
+        }
+        let mut retry = 0;
+        loop {
+            match attempt().await {
+                Ok(value) => {
+                    self.succeeded();
+                    return Ok(value);
+                },
+                Err(CallError::Transient(_)) if retry + 1 < self.retry.attempts => {
+                    tokio::time::sleep(self.retry.delay(retry)).await;
+                    retry += 1;
+                    self.retries.fetch_add(1, Ordering::Relaxed);
+                },
+                Err(error) => {
+                    self.failed();
+                    return Err(error);
+                },
+            }
+        }
+    }
+
+    // While the breaker is open that's one call every open_for, which keeps it open for everyone else while it's tried.
+    fn allow(&self) -> bool {
+        let mut breaker = self.breaker.lock().unwrap();
+        let now = Instant::now();
+        match breaker.open_until {
+            Some(until) if now < until => false,
+            Some(_) => {
+                breaker.open_until = Some(now + self.policy.open_for);
+                true
+            },
+            None => true,
+        }
+    }
+
+    fn succeeded(&self) {
+        self.successes.fetch_add(1, Ordering::Relaxed);
+        let mut breaker = self.breaker.lock().unwrap();
+        if breaker.open_until.take().is_some() {
+            info!("{} is answering again", self.name);
+        }
+        breaker.failures = 0;
+    }
+
+    fn failed(&self) {
+        self.failures.fetch_add(1, Ordering::Relaxed);
+        let mut breaker = self.breaker.lock().unwrap();
+        breaker.failures += 1;
+        if breaker.failures >= self.policy.failures_to_open {
+            if breaker.open_until.is_none() {
+                self.trips.fetch_add(1, Ordering::Relaxed);
+                warn!("{} failed {} times in a row, leaving it alone for {:?}", self.name, breaker.failures, self.policy.open_for);
+            }
+            breaker.open_until = Some(Instant::now() + self.policy.open_for);
+        }
+    }
+}
+
+pub struct IntegrationStats {
+    pub name: String,
+    pub state: BreakerState,
+    pub successes: u64,
+    // Calls that failed after their retries, not counting rejections.
+    pub failures: u64,
+    // Calls not made because the breaker was open.
+    pub rejections: u64,
+    pub retries: u64,
+    // Times the breaker has opened.
+    pub trips: u64,
+}
+
+pub fn stats() -> Vec<IntegrationStats> {
+    INTEGRATIONS.lock().unwrap().iter().map(|integration| IntegrationStats {
+        name: integration.name.clone(),
+        state: integration.state(),
+        successes: integration.successes.load(Ordering::Relaxed),
+        failures: integration.failures.load(Ordering::Relaxed),
+        rejections: integration.rejections.load(Ordering::Relaxed),
+        retries: integration.retries.load(Ordering::Relaxed),
+        trips: integration.trips.load(Ordering::Relaxed),
+    }).collect()
+}
@@ -0,0 +1,2416 @@
+use std::{collections::{BTreeMap, HashMap, HashSet, VecDeque}, convert::Infallible, io, time::Duration};
+use axum::{body::{Body, BodyDataStream}, extract::{DefaultBodyLimit, Request, State}, http::{header, HeaderMap, HeaderValue, Method, StatusCode}, middleware, response::{sse::{Event, KeepAlive, Sse}, Html, IntoResponse, Redirect, Response}, routing::{delete, get, post, put}, Extension, Json, Router};
+use tracing::{debug, error, warn};
+use futures_util::{Stream, StreamExt};
+use hyper_util::rt::TokioIo;
+use serde::{Serialize, Deserialize};
+use time::{format_description::well_known::Rfc3339, macros::date, Date, OffsetDateTime};
+
+use crate::admin_ui;
+use crate::audit::{self, AuditEntry};
+use crate::auth::{self, Caller, Role};
+use crate::backup::{self, BackupFile};
+use crate::body_limit::{self, BodyLimit};
+use crate::changes::{ChangeCursor, CursorExpired, Since};
+use crate::compression;
+use crate::content::{ApiBody, Format};
+use crate::cors;
+use crate::cursor::Cursor;
+use crate::csv::{self, CsvReader, CsvRecord};
+use crate::duplicates::{self, DuplicatePolicy};
+use crate::enrich;
+use crate::event_sourced;
+use crate::error::{ApiError, ApiJson, ApiPath, ApiQuery};
+use crate::events::{self, SubscriptionFilter};
+use crate::fallback;
+use crate::graphql::{self, GraphQLRequest, GraphQLResponse};
+use crate::grpc;
+use crate::idempotency;
+use crate::json_stream::{Framing, JsonReader, JsonRecord};
+use crate::labels::{self, Label};
+use crate::load_shed;
+use crate::locale::{self, LocalizedMovie};
+use crate::metrics;
+use crate::model::{self, Link, Movie, MoviePage, MoviePatch, NewMovie, Relation, Review, Revision, Trashed, User, UserChange, Watch};
+use crate::oidc;
+use crate::openapi;
+use crate::posters::{self, MAX_POSTER_BYTES, MULTIPART_OVERHEAD};
+use crate::quota;
+use crate::random;
+use crate::rate_limit;
+use crate::request_id;
+use crate::search::{self, SearchHit};
+use crate::seed;
+use crate::settings::Settings;
+use crate::sort::Sort;
+use crate::recommend::{self, Recommendation};
+use crate::replication;
+use crate::similar::{self, SimilarMovie};
+use crate::state::StateWrapper;
+use crate::stats::MovieStats;
+use crate::store::{MovieFilter, StoreError};
+use crate::telemetry;
+use crate::tenant;
+use crate::timeout;
+use crate::validation::{validate_genre, validate_movie, validate_patch, validate_rating, validate_review, validate_user, validate_watched_at, FieldError, MAX_FAVORITES, MAX_WATCHLIST_LEN};
+use crate::versioning::{self, Deprecation, VersionedRouter};
+use crate::webhooks::{self, NewWebhook};
+use crate::websocket;
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+struct PostParams { 
+    // Overwrite a movie that already has this id instead of failing with 409, for idempotent ingestion.
+    #[serde(default)]
+    pub upsert: bool,
+    // Add the movie even when --duplicates=reject would turn it away for having the same name and year as another.
+    #[serde(default)]
+    pub allow_duplicate: bool,
+    // --duplicates, which comes from the router's Settings rather than the query.
+    #[serde(skip)]
+    pub duplicates: DuplicatePolicy,
+}
+
+// Routes that don't need the role auth::Role::required_for gives them, see auth::authorize. None means anyone may call
+// them, with or without a token.
+const ACCESS_OVERRIDES: &[(Method, &str, Option<Role>)] = &[
+    (Method::GET, "/healthz", None),
+    (Method::GET, "/readyz", None),
+    (Method::GET, "/api-docs/openapi.json", None),
+    (Method::GET, "/swagger-ui", None),
+    // GraphQL has to run the query to know whether it writes, so mutations check the caller's role themselves. So do
+    // the gRPC methods, which all take a POST.
+    (Method::POST, "/graphql", Some(Role::Reader)),
+    (Method::POST, "/movies.v1.MovieService/{method}", Some(Role::Reader)),
+    // The OIDC login itself, and logging out, which only ever ends the caller's own session.
+    (Method::GET, "/admin/login", None),
+    (Method::GET, "/admin/callback", None),
+    (Method::POST, "/admin/logout", None),
+    // Looking movies up by id only reads, it's a POST to take more ids than fit in a URL.
+    (Method::POST, "/movies/lookup", Some(Role::Reader)),
+    // Anyone logged in may see who they are logged in as, to work out why they can't do something.
+    (Method::GET, "/admin/session", Some(Role::Reader)),
+    // The admin UI's page and files, which only hold what every build has. What it shows and changes goes through the
+    // movie routes, with whatever key or token is entered on it.
+    (Method::GET, "/admin/ui", None),
+    (Method::GET, "/admin/ui/{name}", None),
+    // Anyone who may see a movie may say what they thought of it, which changes nothing but their own rating or review.
+    (Method::POST, "/movie/{id}/ratings", Some(Role::Reader)),
+    (Method::POST, "/movie/{id}/reviews", Some(Role::Reader)),
+    // Likewise anyone may register and keep a watchlist, favorites and history, but only their own once they have a
+    // bearer token, see check_owner.
+    (Method::POST, "/users", Some(Role::Reader)),
+    (Method::POST, "/users/{id}/watchlist", Some(Role::Reader)),
+    (Method::DELETE, "/users/{id}/watchlist/{movie_id}", Some(Role::Reader)),
+    (Method::PUT, "/users/{id}/favorites/{movie_id}", Some(Role::Reader)),
+    (Method::DELETE, "/users/{id}/favorites/{movie_id}", Some(Role::Reader)),
+    (Method::POST, "/users/{id}/history", Some(Role::Reader)),
+    // Unlinking movies is an edit, not deleting one.
+    (Method::DELETE, "/movie/{id}/related/{relation}/{movie_id}", Some(Role::Editor)),
+    // The trash is where deletes go, so it's for whoever may delete.
+    (Method::GET, "/movies/trash", Some(Role::Admin)),
+    (Method::POST, "/movie/{id}/restore", Some(Role::Admin)),
+];
+
+// Probes and scrapes come often and from one place, and shouldn't fail because of a rate limit.
+const RATE_LIMIT_EXEMPT: &[&str] = &["/healthz", "/readyz", "/metrics"];
+
+// Liveness has to answer however busy we are, and so does /metrics, to show how busy that is.
+const SHED_EXEMPT: &[&str] = &["/healthz", "/metrics"];
+
+// What a read-only follower still takes besides GETs, see replication.rs: POSTs that only read, and logging out, which
+// only ends the caller's own session.
+const FOLLOWER_READS: &[(Method, &str)] = &[
+    (Method::POST, "/movies/lookup"),
+    (Method::POST, "/graphql"),
+    (Method::POST, "/movies.v1.MovieService/{method}"),
+    (Method::POST, "/admin/logout"),
+];
+
+// Imports and restores read their body as it arrives, which takes as long as the client takes to send it.
+const TIMEOUT_EXEMPT: &[&str] = &["/movies/import", "/admin/restore"];
+
+// Bodies bigger than --max-body-bytes, see body_limit.rs. Posters are checked more closely by their handler.
+const BODY_LIMITS: &[(&str, BodyLimit)] = &[
+    ("/movies/import", BodyLimit::Upload),
+    ("/admin/restore", BodyLimit::Upload),
+    ("/movie/{id}/poster", BodyLimit::Fixed(MAX_POSTER_BYTES + MULTIPART_OVERHEAD)),
+];
+
+// When the paths without a version prefix were superseded by /v1.
+const UNVERSIONED_DEPRECATED_SINCE: Date = date!(2026-10-15);
+
+// Past this a readiness probe would have given up on us anyway.
+const READY_CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+pub(crate) const DEFAULT_PAGE_LIMIT: usize = 20;
+pub(crate) const MAX_PAGE_LIMIT: usize = 100;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct ListParams {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offset: Option<usize>,
+    // Filters, see MovieFilter.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub year: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub year_gte: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub year_lte: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub was_good: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name_contains: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub genre: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub director: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub runtime_gte: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub runtime_lte: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+    // RFC 3339.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub updated_since: Option<String>,
+    // e.g. year:desc,name:asc, see sort.rs. Id order if left out.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sort: Option<String>,
+    // Instead of offset, from the previous page or empty for the first one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<String>,
+}
+
+impl ListParams { 
+    fn filter(&self) -> Result<MovieFilter, ApiError> { 
+        let updated_since = self.updated_since.as_deref()
+            .map(|since| OffsetDateTime::parse(since, &Rfc3339).map_err(|_| ApiError::InvalidQuery(format!("updated_since must be an RFC 3339 time, like 2026-10-15T20:30:00Z, got {:?}", since))))
+            .transpose()?;
+        Ok(MovieFilter { 
+            year: self.year,
+            year_gte: self.year_gte,
+            year_lte: self.year_lte,
+            was_good: self.was_good,
+            name_contains: self.name_contains.clone(),
+            genre: self.genre.clone(),
+            director: self.director.clone(),
+            runtime_gte: self.runtime_gte,
+            runtime_lte: self.runtime_lte,
+            tag: self.tag.clone(),
+            updated_since,
+        })
+    }
+}
+
+// The same filters as ListParams, without the paging.
+#[derive(Debug, Deserialize)]
+struct RandomParams {
+    pub year: Option<u16>,
+    pub year_gte: Option<u16>,
+    pub year_lte: Option<u16>,
+    pub was_good: Option<bool>,
+    pub name_contains: Option<String>,
+    pub genre: Option<String>,
+    pub director: Option<String>,
+    pub runtime_gte: Option<u16>,
+    pub runtime_lte: Option<u16>,
+    pub tag: Option<String>,
+}
+
+impl RandomParams {
+    fn filter(self) -> MovieFilter {
+        MovieFilter {
+            year: self.year,
+            year_gte: self.year_gte,
+            year_lte: self.year_lte,
+            was_good: self.was_good,
+            name_contains: self.name_contains,
+            genre: self.genre,
+            director: self.director,
+            runtime_gte: self.runtime_gte,
+            runtime_lte: self.runtime_lte,
+            tag: self.tag,
+            updated_since: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct SearchParams {
+    // Words to look for in movie names, see search.rs.
+    #[serde(default)]
+    pub q: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offset: Option<usize>,
+}
+
+// A typeahead wants a handful, not a page.
+const DEFAULT_SUGGEST_LIMIT: usize = 10;
+
+#[derive(Debug, Deserialize)]
+struct SuggestParams {
+    #[serde(default)]
+    pub prefix: String,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+struct Suggestions {
+    pub names: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SimilarParams {
+    #[serde(default)]
+    pub name: String,
+    // Overrides a year at the end of the name.
+    pub year: Option<u16>,
+    pub threshold: Option<f64>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+struct SimilarMovies {
+    pub items: Vec<SimilarMovie>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RecommendationParams {
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+struct Recommendations {
+    pub items: Vec<Recommendation>,
+}
+
+#[derive(Debug, Serialize)]
+struct SearchPage {
+    pub items: Vec<SearchHit>,
+    pub total: usize,
+    pub next: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct PageParams {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offset: Option<usize>,
+}
+
+// GET /genres and GET /tags.
+#[derive(Debug, Serialize)]
+struct Labels {
+    pub items: Vec<Label>,
+}
+
+// Body of POST /genres.
+#[derive(Debug, Deserialize)]
+struct NewGenre {
+    pub name: String,
+    // Who to give the genre to. It only exists while some movie has it, so there has to be at least one.
+    pub movies: Vec<String>,
+}
+
+// Body of PUT /genres/{genre}, which renames it.
+#[derive(Debug, Deserialize)]
+struct GenreUpdate {
+    pub name: String,
+}
+
+// Body of POST /admin/merge.
+#[derive(Debug, Deserialize)]
+struct MergeRequest {
+    // The movie to keep.
+    pub canonical: String,
+    // The one folded into it.
+    pub duplicate: String,
+}
+
+// Body of POST /movie/{id}/ratings.
+#[derive(Debug, Deserialize)]
+struct NewRating {
+    // Who the score is from. Callers with a bearer token are whoever it says they are, and may leave this out.
+    pub user: Option<String>,
+    pub score: u8,
+}
+
+#[derive(Debug, Serialize)]
+struct Rating {
+    pub user: String,
+    pub score: u8,
+}
+
+#[derive(Debug, Serialize)]
+struct RatingPage {
+    // In user order.
+    pub items: Vec<Rating>,
+    pub total: usize,
+    pub average_rating: Option<f64>,
+    pub next: Option<String>,
+}
+
+// Body of POST /movie/{id}/reviews.
+#[derive(Debug, Deserialize)]
+struct NewReview {
+    // Like the user of a rating, the subject of the bearer token when there is one.
+    pub author: Option<String>,
+    pub text: String,
+}
+
+#[derive(Debug, Serialize)]
+struct ReviewPage {
+    // Newest first.
+    pub items: Vec<Review>,
+    pub total: usize,
+    pub next: Option<String>,
+}
+
+// POST /admin/snapshot and POST /admin/restore.
+#[derive(Debug, Serialize)]
+struct BackupSummary {
+    // The file in --backup-dir it was written to or read from, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub taken_at: String,
+    pub movies: usize,
+    pub users: usize,
+    pub links: usize,
+    pub trash: usize,
+}
+
+impl BackupSummary {
+    fn new(name: Option<String>, file: &BackupFile) -> BackupSummary {
+        BackupSummary {
+            name,
+            taken_at: file.taken_at.clone(),
+            movies: file.movies.len(),
+            users: file.users.len(),
+            links: file.links.len(),
+            trash: file.trash.len(),
+        }
+    }
+}
+
+// POST /admin/restore?dry_run=true: the backup, and what restoring it would do to the movies in the store.
+#[derive(Debug, Serialize)]
+struct RestorePreview {
+    #[serde(flatten)]
+    pub backup: BackupSummary,
+    pub added: usize,
+    pub changed: usize,
+    pub removed: usize,
+    pub dry_run: bool,
+}
+
+// DELETE /movies/trash.
+#[derive(Debug, Serialize)]
+struct PurgeReport {
+    // Those that would have been purged, for a dry run.
+    pub purged: Vec<String>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub dry_run: bool,
+}
+
+// GET /movies/trash.
+#[derive(Debug, Serialize)]
+struct TrashPage {
+    pub items: Vec<Trashed>,
+    pub total: usize,
+    pub next: Option<String>,
+}
+
+// GET /movie/{id}/history.
+#[derive(Debug, Serialize)]
+struct RevisionPage {
+    // Oldest first.
+    pub items: Vec<Revision>,
+    pub total: usize,
+    pub next: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct GetParams {
+    // RFC 3339. The movie as it was then rather than now, for stores that keep their history.
+    #[serde(default)]
+    pub as_of: Option<String>,
+}
+
+// Body of POST /users.
+#[derive(Debug, Deserialize)]
+struct NewUser {
+    // Callers with a bearer token register as its subject, and may leave this out. Without one, a UUIDv7 is picked.
+    pub id: Option<String>,
+    pub name: Option<String>,
+}
+
+// Body of POST /users/{id}/watchlist.
+#[derive(Debug, Deserialize)]
+struct WatchlistAddition {
+    pub movie_id: String,
+}
+
+// Body of POST /users/{id}/history.
+#[derive(Debug, Deserialize)]
+struct NewWatch {
+    pub movie_id: String,
+    // RFC 3339, now if left out.
+    pub watched_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct HistoryParams {
+    // RFC 3339. Only the watches recorded after it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub since: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offset: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+struct HistoryPage {
+    // In the order they were recorded.
+    pub items: Vec<Watch>,
+    pub total: usize,
+    pub next: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct SnapshotParams {
+    // Sends the backup back even when --backup-dir is set.
+    #[serde(default)]
+    pub download: bool,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct RestoreBackupParams {
+    // A backup in --backup-dir to restore, instead of one sent as the body.
+    pub name: Option<String>,
+    // Only check the backup and report what restoring it would change.
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+// For the routes where that's all a dry run needs.
+#[derive(Debug, Default, Deserialize)]
+struct DryRunParams {
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct PurgeParams {
+    // RFC 3339. Without it everything in the trash is purged.
+    pub deleted_before: Option<String>,
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct AuditParams {
+    // RFC 3339. Only the changes made after it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub since: Option<String>,
+    // Only the changes made by them, see Caller::actor.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub actor: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offset: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+struct AuditPage {
+    // Oldest first.
+    pub items: Vec<AuditEntry>,
+    pub total: usize,
+    pub next: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChangesParams {
+    // The cursor from the last page, latest for one to follow the changes from now on, or none to start from the oldest
+    // change kept.
+    pub since: Option<String>,
+    pub limit: Option<usize>,
+}
+
+// Body of POST /movie/{id}/related, linking the movie to the other one.
+#[derive(Debug, Deserialize)]
+struct NewLink {
+    pub movie_id: String,
+    pub relation: Relation,
+}
+
+#[derive(Debug, Serialize)]
+struct RelatedMovie {
+    // As seen from the movie asked about, so the inverse for links to it, e.g. alien is followed-by aliens.
+    pub relation: &'static str,
+    pub movie: Movie,
+}
+
+#[derive(Debug, Serialize)]
+struct RelatedMovies {
+    pub items: Vec<RelatedMovie>,
+}
+
+// Big enough for loading a catalog in a few requests, small enough that one request can't hold up writers for long.
+const MAX_BATCH_SIZE: usize = 1000;
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum BatchStatus { 
+    Created,
+    // Only with ?upsert=true.
+    Updated,
+    Duplicate,
+    Invalid,
+    // The storage backend failed on this item. Later items are still attempted.
+    Failed,
+}
+
+#[derive(Debug, Serialize)]
+struct BatchItemResult { 
+    // Position in the request array.
+    pub index: usize,
+    pub status: BatchStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    // Same shape as the "error" of a single POST /movie, without the request id.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct BatchReport { 
+    pub created: usize,
+    pub updated: usize,
+    pub duplicate: usize,
+    pub invalid: usize,
+    pub failed: usize,
+    pub results: Vec<BatchItemResult>,
+}
+
+// Adds one movie of a batch or import the same way post_handler would.
+async fn insert_one(state: &StateWrapper, new_movie: NewMovie, params: PostParams) -> Result<(BatchStatus, String), ApiError> { 
+    let movie = new_movie.into_movie();
+    validate_movie(&movie)?;
+    duplicates::check(state.as_ref(), params.duplicates, &movie, params.allow_duplicate).await?;
+    let id = movie.id.clone();
+    if params.upsert {
+        let created = state.upsert(movie).await?;
+        Ok((if created { BatchStatus::Created } else { BatchStatus::Updated }, id))
+    }
+    else { 
+        state.insert(movie).await?;
+        Ok((BatchStatus::Created, id))
+    }
+}
+
+#[axum::debug_handler]
+async fn batch_handler(State(state): State<StateWrapper>, Extension(settings): Extension<Settings>, ApiQuery(params): ApiQuery<PostParams>, format: Format, ApiBody(items): ApiBody<Vec<serde_json::Value>>) -> Result<Response, ApiError> { 
+    let params = PostParams { duplicates: settings.duplicates(), ..params };
+    if items.len() > MAX_BATCH_SIZE {
+        return Err(ApiError::BadRequest(format!("A batch can have at most {} movies, this one has {}", MAX_BATCH_SIZE, items.len())));
+    }
+    // Items are added one by one, not atomically: each gets its own result and the good ones go in regardless.
+    let mut report = BatchReport::default();
+    for (index, item) in items.into_iter().enumerate() {
+        let inserted = match serde_json::from_value::<NewMovie>(item) {
+            Ok(new_movie) => insert_one(&state, new_movie, params).await,
+            Err(e) => Err(ApiError::InvalidBody(StatusCode::UNPROCESSABLE_ENTITY, format!("Invalid movie: {}", e))),
+        };
+        let result = match inserted {
+            Ok((status, id)) => BatchItemResult { index, status, id: Some(id), error: None },
+            Err(error) => {
+                let status = match &error {
+                    ApiError::AlreadyExists(_) | ApiError::Duplicate(_) => BatchStatus::Duplicate,
+                    ApiError::Internal(message) => {
+                        error!("Internal error on batch item {}: {}", index, message);
+                        BatchStatus::Failed
+                    },
+                    _ => BatchStatus::Invalid,
+                };
+                BatchItemResult { index, status, id: None, error: Some(error.body()) }
+            },
+        };
+        match result.status {
+            BatchStatus::Created => report.created += 1,
+            BatchStatus::Updated => report.updated += 1,
+            BatchStatus::Duplicate => report.duplicate += 1,
+            BatchStatus::Invalid => report.invalid += 1,
+            BatchStatus::Failed => report.failed += 1,
+        }
+        report.results.push(result);
+    }
+    debug!("Batch of {}: {} created, {} updated, {} duplicate, {} invalid, {} failed",
+        report.results.len(), report.created, report.updated, report.duplicate, report.invalid, report.failed);
+    format.respond(&report)
+}
+
+#[derive(Debug, Serialize)]
+struct LookupResult { 
+    // The movies found, in the order their ids were asked for.
+    pub items: Vec<Movie>,
+    // The ids with no movie, in the same order.
+    pub missing: Vec<String>,
+}
+
+// Looks up many movies at once, e.g. everything on a watchlist, rather than a GET /movie/{id} for each. Ids asked for
+// more than once are only looked up and returned once.
+async fn lookup_handler(State(state): State<StateWrapper>, format: Format, ApiBody(ids): ApiBody<Vec<String>>) -> Result<Response, ApiError> { 
+    if ids.len() > MAX_BATCH_SIZE {
+        return Err(ApiError::BadRequest(format!("A lookup can have at most {} ids, this one has {}", MAX_BATCH_SIZE, ids.len())));
+    }
+    let mut result = LookupResult { items: Vec::new(), missing: Vec::new() };
+    let mut seen = HashSet::new();
+    for id in ids {
+        if !seen.insert(id.clone()) {
+            continue;
+        }
+        match state.get(&id).await {
+            Some(movie) => result.items.push(movie),
+            None => result.missing.push(id),
+        }
+    }
+    format.respond(&result)
+}
+
+// Most rejections an import lists. Any past this are only counted, so a badly broken file can't make the report huge.
+const MAX_LISTED_REJECTIONS: usize = 1000;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum ImportFormat { 
+    #[default]
+    Csv,
+    // An array of movies, in the same form as POST /movies/batch takes.
+    Json,
+    // One movie per line, in the form GET /movies/export sends.
+    Ndjson,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ImportParams { 
+    #[serde(default)]
+    pub format: ImportFormat,
+    #[serde(default)]
+    pub upsert: bool,
+    #[serde(default)]
+    pub allow_duplicate: bool,
+    // Only report what the import would do, without adding anything.
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+impl ImportParams { 
+    // What each record is added with.
+    fn post_params(&self, duplicates: DuplicatePolicy) -> PostParams { 
+        PostParams { upsert: self.upsert, allow_duplicate: self.allow_duplicate, duplicates }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct ImportRejection { 
+    pub line: usize,
+    pub error: serde_json::Value,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct ImportReport { 
+    pub inserted: usize,
+    pub updated: usize,
+    pub rejected: usize,
+    pub rejections: Vec<ImportRejection>,
+    // Left out of real imports, whose reports were the same before there were dry runs.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub dry_run: bool,
+}
+
+impl ImportReport { 
+    // Counts what became of the record on `line`.
+    fn add(&mut self, line: usize, inserted: Result<(BatchStatus, String), ApiError>) { 
+        match inserted {
+            Ok((BatchStatus::Updated, _)) => self.updated += 1,
+            Ok(_) => self.inserted += 1,
+            Err(error) => {
+                if let ApiError::Internal(message) = &error {
+                    error!("Internal error on import line {}: {}", line, message);
+                }
+                self.rejected += 1;
+                if self.rejections.len() < MAX_LISTED_REJECTIONS {
+                    self.rejections.push(ImportRejection { line, error: error.body() });
+                }
+            },
+        }
+    }
+
+    fn records(&self) -> usize { 
+        self.inserted + self.updated + self.rejected
+    }
+}
+
+// Where each column is, going by the header row. The id column and the details are optional, the others aren't.
+struct CsvColumns { 
+    id: Option<usize>,
+    name: usize,
+    year: usize,
+    was_good: usize,
+    genres: Option<usize>,
+    director: Option<usize>,
+    runtime_minutes: Option<usize>,
+    synopsis: Option<usize>,
+    tags: Option<usize>,
+    count: usize,
+}
+
+// Between the genres, or the tags, in their one CSV column.
+const CSV_LABEL_SEPARATOR: char = ';';
+const CSV_COLUMNS: [&str; 9] = ["id", "name", "year", "was_good", "genres", "director", "runtime_minutes", "synopsis", "tags"];
+
+impl CsvColumns { 
+    fn from_header(header: &[String]) -> Result<CsvColumns, String> { 
+        if let Some(unknown) = header.iter().find(|name| !CSV_COLUMNS.iter().any(|column| name.trim().eq_ignore_ascii_case(column))) {
+            return Err(format!("Unknown column {:?}, expected {}", unknown, CSV_COLUMNS.join(", ")));
+        }
+        let position = |column: &str| header.iter().position(|name| name.trim().eq_ignore_ascii_case(column));
+        let required = |column: &str| position(column).ok_or_else(|| format!("The header row has no {} column", column));
+        Ok(CsvColumns { 
+            id: position("id"),
+            name: required("name")?,
+            year: required("year")?,
+            was_good: required("was_good")?,
+            genres: position("genres"),
+            director: position("director"),
+            runtime_minutes: position("runtime_minutes"),
+            synopsis: position("synopsis"),
+            tags: position("tags"),
+            count: header.len(),
+        })
+    }
+
+    fn new_movie(&self, fields: Vec<String>) -> Result<NewMovie, String> { 
+        if fields.len() != self.count {
+            return Err(format!("Expected {} fields, found {}", self.count, fields.len()));
+        }
+        let year = fields[self.year].trim();
+        let year = year.parse().map_err(|_| format!("year {:?} isn't a number", year))?;
+        let was_good = match fields[self.was_good].trim() {
+            value if value.eq_ignore_ascii_case("true") => true,
+            value if value.eq_ignore_ascii_case("false") => false,
+            value => return Err(format!("was_good {:?} isn't true or false", value)),
+        };
+        // Empty cells are details the movie doesn't have.
+        let optional = |column: Option<usize>| column.map(|column| fields[column].clone()).filter(|value| !value.trim().is_empty());
+        let labels = |column: Option<usize>| optional(column)
+            .map_or_else(Vec::new, |labels| labels.split(CSV_LABEL_SEPARATOR).map(|label| label.trim().to_string()).collect());
+        let runtime_minutes = optional(self.runtime_minutes)
+            .map(|runtime| runtime.trim().parse().map_err(|_| format!("runtime_minutes {:?} isn't a number", runtime)))
+            .transpose()?;
+        Ok(NewMovie { 
+            // An empty id means the server should pick one, same as leaving it out of a POST /movie.
+            id: self.id.map(|id| fields[id].clone()).filter(|id| !id.is_empty()),
+            name: fields[self.name].clone(),
+            year,
+            was_good,
+            genres: labels(self.genres),
+            director: optional(self.director),
+            runtime_minutes,
+            synopsis: optional(self.synopsis),
+            poster_url: None,
+            tags: labels(self.tags),
+            titles: BTreeMap::new(),
+        })
+    }
+}
+
+// Whether an import adds its movies, or with dry_run only works out what adding them would do. A dry run keeps the ids
+// it would have added, so a movie repeated further down the file is caught the same way as one already stored.
+enum ImportMode { 
+    Write,
+    DryRun(HashSet<String>),
+}
+
+impl ImportMode { 
+    fn new(dry_run: bool) -> ImportMode { 
+        if dry_run { ImportMode::DryRun(HashSet::new()) } else { ImportMode::Write }
+    }
+
+    // Like insert_one, which it calls unless this is a dry run. A dry run can't tell whether the store would have room
+    // for the movie, see quota.rs, so it only turns away the ones that are invalid or whose id is taken.
+    async fn insert(&mut self, state: &StateWrapper, new_movie: NewMovie, params: PostParams) -> Result<(BatchStatus, String), ApiError> { 
+        let ImportMode::DryRun(added) = self else {
+            return insert_one(state, new_movie, params).await;
+        };
+        let movie = new_movie.into_movie();
+        validate_movie(&movie)?;
+        duplicates::check(state.as_ref(), params.duplicates, &movie, params.allow_duplicate).await?;
+        let upsert = params.upsert;
+        if added.contains(&movie.id) {
+            return match upsert {
+                true => Ok((BatchStatus::Updated, movie.id)),
+                false => Err(ApiError::Conflict(format!("Movie {:?} is already earlier in the import", movie.id))),
+            };
+        }
+        match state.get(&movie.id).await {
+            Some(_) if upsert => Ok((BatchStatus::Updated, movie.id)),
+            Some(existing) => Err(ApiError::AlreadyExists(Box::new(existing))),
+            None => {
+                added.insert(movie.id.clone());
+                Ok((BatchStatus::Created, movie.id))
+            },
+        }
+    }
+}
+
+struct CsvImport { 
+    columns: Option<CsvColumns>,
+    params: PostParams,
+    mode: ImportMode,
+    report: ImportReport,
+}
+
+impl CsvImport { 
+    async fn record(&mut self, state: &StateWrapper, record: CsvRecord) -> Result<(), ApiError> { 
+        let Some(columns) = &self.columns else {
+            let header = record.fields.map_err(|e| ApiError::BadRequest(format!("Line {}: {}", record.line, e)))?;
+            self.columns = Some(CsvColumns::from_header(&header).map_err(ApiError::BadRequest)?);
+            return Ok(());
+        };
+        let new_movie = record.fields.and_then(|fields| columns.new_movie(fields))
+            .map_err(|e| ApiError::InvalidBody(StatusCode::UNPROCESSABLE_ENTITY, e));
+        let inserted = match new_movie {
+            Ok(new_movie) => self.mode.insert(state, new_movie, self.params).await,
+            Err(error) => Err(error),
+        };
+        self.report.add(record.line, inserted);
+        Ok(())
+    }
+}
+
+// Records a JSON or NDJSON import goes through between the progress lines of its response.
+const IMPORT_PROGRESS_EVERY: usize = 1000;
+
+// A JSON or NDJSON import, run as its response is sent: the body is read while the progress lines go out, so a client
+// uploading a huge file can see how far it has got.
+struct JsonImport { 
+    state: StateWrapper,
+    params: PostParams,
+    mode: ImportMode,
+    chunks: BodyDataStream,
+    reader: JsonReader,
+    // Records read but not added yet, when a chunk holds more than one.
+    pending: VecDeque<JsonRecord>,
+    report: ImportReport,
+    ended: bool,
+    done: bool,
+}
+
+impl JsonImport { 
+    async fn record(&mut self, record: JsonRecord) { 
+        let new_movie = record.json
+            .and_then(|json| serde_json::from_slice::<NewMovie>(&json).map_err(|e| format!("Invalid movie: {}", e)))
+            .map_err(|e| ApiError::InvalidBody(StatusCode::UNPROCESSABLE_ENTITY, e));
+        let inserted = match new_movie {
+            Ok(new_movie) => self.mode.insert(&self.state, new_movie, self.params).await,
+            Err(error) => Err(error),
+        };
+        self.report.add(record.line, inserted);
+    }
+
+    // The next line of the response, None once the report has been sent.
+    async fn next_line(&mut self) -> Option<String> { 
+        if self.done {
+            return None;
+        }
+        loop {
+            if let Some(record) = self.pending.pop_front() {
+                self.record(record).await;
+                if self.report.records().is_multiple_of(IMPORT_PROGRESS_EVERY) {
+                    let report = &self.report;
+                    let progress = serde_json::json!({ "progress": { "inserted": report.inserted, "updated": report.updated, "rejected": report.rejected } });
+                    return Some(progress.to_string() + "\n");
+                }
+                continue;
+            }
+            if self.ended {
+                return Some(self.finish(None));
+            }
+            if let Some(error) = self.reader.error() {
+                let error = ApiError::BadRequest(error.to_string());
+                return Some(self.finish(Some(error)));
+            }
+            match self.chunks.next().await {
+                Some(Ok(chunk)) => self.pending.extend(self.reader.feed(&chunk)),
+                Some(Err(e)) => return Some(self.finish(Some(body_limit::read_error(e)))),
+                None => {
+                    self.ended = true;
+                    match self.reader.finish() {
+                        Ok(record) => self.pending.extend(record),
+                        Err(e) => return Some(self.finish(Some(ApiError::BadRequest(e)))),
+                    }
+                },
+            }
+        }
+    }
+
+    // The last line: the report, and the error that cut the import short if one did.
+    fn finish(&mut self, error: Option<ApiError>) -> String { 
+        self.done = true;
+        let report = &self.report;
+        debug!("Imported {} movies, updated {} and rejected {}", report.inserted, report.updated, report.rejected);
+        let mut line = serde_json::json!({ "done": report });
+        if let Some(error) = error {
+            line["error"] = error.body();
+        }
+        line.to_string() + "\n"
+    }
+}
+
+#[axum::debug_handler]
+async fn import_handler(State(state): State<StateWrapper>, Extension(settings): Extension<Settings>, ApiQuery(params): ApiQuery<ImportParams>, headers: HeaderMap, body: Body) -> Result<Response, ApiError> { 
+    if headers.get(header::CONTENT_TYPE).and_then(|value| value.to_str().ok()).is_some_and(|value| value.starts_with("multipart/")) {
+        return Err(ApiError::InvalidBody(StatusCode::UNSUPPORTED_MEDIA_TYPE, "Multipart uploads aren't supported, send the file as the body".to_string()));
+    }
+    let framing = match params.format {
+        ImportFormat::Csv => return import_csv(state, params.post_params(settings.duplicates()), params.dry_run, body).await,
+        ImportFormat::Json => Framing::Array,
+        ImportFormat::Ndjson => Framing::Lines,
+    };
+
+    // Once the response has started its status can't change, so the records are reported a line at a time instead:
+    // {"progress": {...}} every so often, then {"done": <ImportReport>}, with "error" next to it if the body broke off
+    // or turned out not to be JSON. The records before that point are kept either way.
+    let import = JsonImport {
+        state,
+        params: params.post_params(settings.duplicates()),
+        mode: ImportMode::new(params.dry_run),
+        chunks: body.into_data_stream(),
+        reader: JsonReader::new(framing),
+        pending: VecDeque::new(),
+        report: ImportReport { dry_run: params.dry_run, ..ImportReport::default() },
+        ended: false,
+        done: false,
+    };
+    // The response is sent after the handler has returned, outside the tenant and actor the middleware ran it as.
+    let (tenant, actor) = (tenant::current(), audit::current_actor());
+    let lines = futures_util::stream::unfold(import, move |mut import| {
+        let (tenant, actor) = (tenant.clone(), actor.clone());
+        async move {
+            let line = tenant::scope(tenant, audit::acting_as(actor, import.next_line())).await?;
+            Some((Ok::<_, Infallible>(line), import))
+        }
+    });
+    Ok(([(header::CONTENT_TYPE, "application/x-ndjson")], Body::from_stream(lines)).into_response())
+}
+
+async fn import_csv(state: StateWrapper, params: PostParams, dry_run: bool, body: Body) -> Result<Response, ApiError> { 
+    // Rows are added as they arrive, so only the current chunk and record are ever held in memory. Like a batch this
+    // isn't atomic: if the upload breaks off, the rows before that point are kept.
+    let mut import = CsvImport { columns: None, params, mode: ImportMode::new(dry_run), report: ImportReport { dry_run, ..ImportReport::default() } };
+    let mut reader = CsvReader::new();
+    let mut chunks = body.into_data_stream();
+    while let Some(chunk) = chunks.next().await {
+        let chunk = chunk.map_err(body_limit::read_error)?;
+        for record in reader.feed(&chunk) {
+            import.record(&state, record).await?;
+        }
+    }
+    if let Some(record) = reader.finish() {
+        import.record(&state, record).await?;
+    }
+    if import.columns.is_none() {
+        return Err(ApiError::BadRequest("The CSV is empty, expected a header row".to_string()));
+    }
+    let report = import.report;
+    debug!("Imported {} movies, updated {} and rejected {}", report.inserted, report.updated, report.rejected);
+    Ok(serde_json::to_string_pretty(&report)?.into_response())
+}
+
+// Movies read from the store per chunk of an export. The store is only locked while a chunk is read, so writes carry on
+// during a long export, and show up in it if they land after the point it has reached.
+const EXPORT_CHUNK: usize = 500;
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum ExportFormat { 
+    #[default]
+    Ndjson,
+    Csv,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ExportParams { 
+    #[serde(default)]
+    pub format: ExportFormat,
+}
+
+fn export_line(movie: &Movie, format: ExportFormat) -> Result<String, serde_json::Error> { 
+    match format {
+        ExportFormat::Ndjson => serde_json::to_string(movie).map(|json| json + "\n"),
+        ExportFormat::Csv => Ok(csv::write_record(&[
+            &movie.id,
+            &movie.name,
+            &movie.year.to_string(),
+            &movie.was_good.to_string(),
+            &movie.genres.join(&CSV_LABEL_SEPARATOR.to_string()),
+            movie.director.as_deref().unwrap_or_default(),
+            &movie.runtime_minutes.map(|runtime| runtime.to_string()).unwrap_or_default(),
+            movie.synopsis.as_deref().unwrap_or_default(),
+            &movie.tags.join(&CSV_LABEL_SEPARATOR.to_string()),
+        ])),
+    }
+}
+
+#[axum::debug_handler]
+async fn export_handler(State(state): State<StateWrapper>, ApiQuery(params): ApiQuery<ExportParams>) -> Response { 
+    let format = params.format;
+    let (content_type, filename, header_row) = match format {
+        ExportFormat::Ndjson => ("application/x-ndjson", "movies.ndjson", None),
+        // The same columns POST /movies/import expects, so an export can be imported again as it is.
+        ExportFormat::Csv => ("text/csv; charset=utf-8", "movies.csv", Some(csv::write_record(&CSV_COLUMNS))),
+    };
+    // The cursor is the last id sent, with None once there's nothing left. The body is read after the handler has
+    // returned, outside the tenant the middleware ran it as, so each chunk is scanned in that tenant again. The scan is
+    // started inside the scope, as TenantMovieStore picks the tenant's store when it's called rather than when polled.
+    let tenant = tenant::current();
+    let chunks = futures_util::stream::unfold(Some(None::<String>), move |cursor| { 
+        let (state, tenant) = (state.clone(), tenant.clone());
+        async move {
+            let after = cursor?;
+            let movies = tenant::scope(tenant, async { state.scan(after.as_deref(), EXPORT_CHUNK).await }).await;
+            let last = movies.last()?.id.clone();
+            let next = (movies.len() == EXPORT_CHUNK).then_some(Some(last));
+            let chunk = movies.iter().map(|movie| export_line(movie, format)).collect::<Result<String, _>>();
+            Some((chunk, next))
+        }
+    });
+    let body = Body::from_stream(futures_util::stream::iter(header_row.map(Ok)).chain(chunks));
+    let disposition = format!("attachment; filename=\"{}\"", filename);
+    ([(header::CONTENT_TYPE, content_type.to_string()), (header::CONTENT_DISPOSITION, disposition)], body).into_response()
+}
+
+#[axum::debug_handler]
+async fn post_handler(State(state): State<StateWrapper>, Extension(settings): Extension<Settings>, ApiQuery(params): ApiQuery<PostParams>, format: Format, ApiBody(new_movie): ApiBody<NewMovie>) -> Result<Response, ApiError> { 
+    let params = PostParams { duplicates: settings.duplicates(), ..params };
+    let mut movie = new_movie.into_movie();
+    validate_movie(&movie)?;
+    let duplicate = duplicates::check(state.as_ref(), params.duplicates, &movie, params.allow_duplicate).await?;
+    enrich::enrich(&mut movie).await;
+    debug!("Adding movie {}", movie.name);
+    let location = format!("/movie/{}", movie.id);
+    let created = if params.upsert {
+        state.upsert(movie.clone()).await?
+    }
+    else { 
+        state.insert(movie.clone()).await?;
+        true
+    };
+    let mut response = if created {
+        (StatusCode::CREATED, [(header::LOCATION, location)], format.respond(&movie)?).into_response()
+    }
+    else { 
+        // Overwriting a movie moves it on from that movie's version, so the one we made isn't quite what was stored.
+        let stored = state.get(&movie.id).await.unwrap_or(movie);
+        format.respond(&stored)?
+    };
+    // 299 is the code for a warning that stays with the response, see RFC 7234.
+    if let Some(duplicate) = duplicate
+        && let Ok(warning) = HeaderValue::from_str(&format!("299 - \"Movie {} has the same name and year\"", duplicate.id)) {
+        response.headers_mut().insert(header::WARNING, warning);
+    }
+    Ok(response)
+}
+
+// Whether an If-Match or If-None-Match header lists the ETag. If-None-Match uses the weak comparison, where W/"x"
+// matches "x", and If-Match the strong one, where weak tags never match. A missing header never matches.
+fn etag_matches(headers: &HeaderMap, name: header::HeaderName, etag: &str, weak: bool) -> bool { 
+    etag_listed(headers, name, weak, |tag| tag == etag)
+}
+
+// etag_matches, with any tag `matches` is true for counting as the ETag.
+fn etag_listed(headers: &HeaderMap, name: header::HeaderName, weak: bool, matches: impl Fn(&str) -> bool) -> bool { 
+    headers.get_all(name).iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .any(|tag| match tag.strip_prefix("W/") {
+            _ if tag == "*" => true,
+            Some(tag) => weak && matches(tag),
+            None => matches(tag),
+        })
+}
+
+// Whether a write's If-Match precondition holds, i.e. the movie is still the one the client read, so two clients
+// editing the same movie can't silently overwrite each other. Always true without the header. The ETag of any of the
+// movie's representations will do, see representation_etag, since they're all of the version the client read.
+fn if_match_holds(headers: &HeaderMap, current: &Movie) -> bool { 
+    let etag = current.etag();
+    let representations = format!("{}-", etag.trim_end_matches('"'));
+    !headers.contains_key(header::IF_MATCH) || etag_listed(headers, header::IF_MATCH, false, |tag| tag == etag || tag.starts_with(&representations))
+}
+
+// The ETag of the movie as it's sent in `format`, with the title in `language` when it's localized, see
+// localized_language. Plain JSON has the movie's own ETag, and every other representation the same with what sets it
+// apart after it, like "<hash>-cbor" or "<hash>-json-fr", so that caches never answer for one with another.
+fn representation_etag(movie: &Movie, format: Format, language: Option<Option<&str>>) -> String {
+    let etag = movie.etag();
+    let mut suffix = match format {
+        Format::Json => String::new(),
+        format => format!("-{}", format.token()),
+    };
+    if let Some(language) = language {
+        // "und", for undetermined, when none of the languages asked for has a title and the name stands in for it.
+        suffix = format!("-{}-{}", format.token(), language.unwrap_or("und"));
+    }
+    if suffix.is_empty() {
+        return etag;
+    }
+    format!("{}{}\"", etag.trim_end_matches('"'), suffix)
+}
+
+#[axum::debug_handler]
+async fn get_handler(ApiPath(id): ApiPath<String>, ApiQuery(params): ApiQuery<GetParams>, State(state): State<StateWrapper>, format: Format, headers: HeaderMap) -> Result<Response, ApiError> { 
+    if let Some(as_of) = params.as_of {
+        let at = OffsetDateTime::parse(&as_of, &Rfc3339).map_err(|_| ApiError::InvalidQuery(format!("as_of must be an RFC 3339 time, like 2026-10-15T20:30:00Z, got {:?}", as_of)))?;
+        let revisions = history(&state, &id).await?;
+        let movie = event_sourced::movie_as_of(&revisions, at).ok_or_else(|| ApiError::NotFound(format!("Movie {} didn't exist at {}", id, as_of)))?;
+        return respond_localized(format, movie, &headers);
+    }
+    let movie = state.get(&id).await.ok_or(StoreError::NotFound)?;
+    let etag = representation_etag(&movie, format, localized_language(&movie, &headers));
+    let mut validators = HeaderMap::new();
+    validators.insert(header::ETAG, HeaderValue::from_str(&etag).map_err(|e| ApiError::Internal(format!("Bad ETag {}: {}", etag, e)))?);
+    if let Some(updated) = movie.updated() {
+        validators.insert(header::LAST_MODIFIED, HeaderValue::from_str(&versioning::http_date(updated)).expect("dates are ASCII"));
+    }
+    if not_modified(&headers, &etag, &movie) {
+        // With the Vary the full response has, so caches know which requests the ETag goes with.
+        validators.insert(header::VARY, HeaderValue::from_static("accept, accept-language"));
+        return Ok((StatusCode::NOT_MODIFIED, validators).into_response());
+    }
+    Ok((validators, respond_localized(format, &movie, &headers)?).into_response())
+}
+
+// Whether the client's copy of the movie is still current, by its ETag in If-None-Match or, only when that isn't sent
+// (RFC 9110), by If-Modified-Since. Dates are only to the second, so a movie changed within the second the client's
+// copy is from counts as unmodified: the ETag is the better validator, and clients that have it should send it.
+fn not_modified(headers: &HeaderMap, etag: &str, movie: &Movie) -> bool {
+    if headers.contains_key(header::IF_NONE_MATCH) {
+        return etag_matches(headers, header::IF_NONE_MATCH, etag, true);
+    }
+    let since = headers.get(header::IF_MODIFIED_SINCE).and_then(|value| value.to_str().ok()).and_then(versioning::parse_http_date);
+    match (since, movie.updated()) {
+        (Some(since), Some(updated)) => updated.unix_timestamp() <= since.unix_timestamp(),
+        _ => false,
+    }
+}
+
+// The language of the title respond_localized gives the movie: None when there's no Accept-Language to localize it
+// for, and Some(None) when none of its languages has a title and the name stands in.
+fn localized_language<'a>(movie: &'a Movie, headers: &HeaderMap) -> Option<Option<&'a str>> {
+    headers.contains_key(header::ACCEPT_LANGUAGE).then(|| LocalizedMovie::new(movie, &locale::accepted_languages(headers)).title_language)
+}
+
+// The movie with its title for the languages in Accept-Language, or as it is without one.
+fn respond_localized(format: Format, movie: &Movie, headers: &HeaderMap) -> Result<Response, ApiError> {
+    let mut response = if headers.contains_key(header::ACCEPT_LANGUAGE) {
+        format.respond(&LocalizedMovie::new(movie, &locale::accepted_languages(headers)))?
+    }
+    else {
+        format.respond(movie)?
+    };
+    response.headers_mut().append(header::VARY, HeaderValue::from_static("accept-language"));
+    Ok(response)
+}
+
+#[axum::debug_handler]
+async fn search_handler(State(state): State<StateWrapper>, ApiQuery(params): ApiQuery<SearchParams>, format: Format) -> Result<Response, ApiError> {
+    let terms = search::query_terms(&params.q);
+    if terms.is_empty() {
+        return Err(ApiError::BadRequest("q must contain at least one word".to_string()));
+    }
+    let limit = params.limit.unwrap_or(DEFAULT_PAGE_LIMIT).clamp(1, MAX_PAGE_LIMIT);
+    let offset = params.offset.unwrap_or(0);
+
+    let hits = search::search(state.list(&MovieFilter::default()).await, &terms);
+    let total = hits.len();
+    let items: Vec<SearchHit> = hits.into_iter().skip(offset).take(limit).collect();
+    let next = if offset + items.len() < total {
+        let next_params = SearchParams {
+            limit: Some(limit),
+            offset: Some(offset + items.len()),
+            ..params.clone()
+        };
+        let query = serde_urlencoded::to_string(&next_params)
+            .map_err(|e| ApiError::Internal(format!("Failed to build next page link: {}", e)))?;
+        Some(format!("/v1/movies/search?{query}"))
+    }
+    else {
+        None
+    };
+    format.respond(&SearchPage { items, total, next })
+}
+
+#[axum::debug_handler]
+async fn suggest_handler(State(state): State<StateWrapper>, ApiQuery(params): ApiQuery<SuggestParams>, format: Format) -> Result<Response, ApiError> {
+    if params.prefix.trim().is_empty() {
+        return Err(ApiError::BadRequest("prefix mustn't be empty".to_string()));
+    }
+    let limit = params.limit.unwrap_or(DEFAULT_SUGGEST_LIMIT).clamp(1, MAX_PAGE_LIMIT);
+    format.respond(&Suggestions { names: state.suggest(&params.prefix, limit).await })
+}
+
+#[axum::debug_handler]
+async fn similar_handler(State(state): State<StateWrapper>, ApiQuery(params): ApiQuery<SimilarParams>, format: Format) -> Result<Response, ApiError> {
+    if similar::normalize(&params.name).0.is_empty() {
+        return Err(ApiError::BadRequest("name must contain at least one letter or digit".to_string()));
+    }
+    let threshold = params.threshold.unwrap_or(similar::DEFAULT_THRESHOLD);
+    if !(0.0..=1.0).contains(&threshold) {
+        return Err(ApiError::BadRequest("threshold must be between 0 and 1".to_string()));
+    }
+    let limit = params.limit.unwrap_or(DEFAULT_PAGE_LIMIT).clamp(1, MAX_PAGE_LIMIT);
+    let mut items = similar::find_similar(state.list(&MovieFilter::default()).await, &params.name, params.year, threshold);
+    items.truncate(limit);
+    format.respond(&SimilarMovies { items })
+}
+
+#[axum::debug_handler]
+async fn stats_handler(State(state): State<StateWrapper>, format: Format) -> Result<Response, ApiError> {
+    format.respond(&MovieStats::of(&state.list(&MovieFilter::default()).await))
+}
+
+// A different movie every time, so nothing in between may cache it.
+#[axum::debug_handler]
+async fn random_handler(State(state): State<StateWrapper>, ApiQuery(params): ApiQuery<RandomParams>, format: Format) -> Result<Response, ApiError> {
+    let mut matching = state.list(&params.filter()).await;
+    if matching.is_empty() {
+        return Err(ApiError::NotFound("No movie matches the filters".to_string()));
+    }
+    let movie = matching.swap_remove(random::below(matching.len()));
+    Ok(([(header::CACHE_CONTROL, "no-store")], format.respond(&movie)?).into_response())
+}
+
+fn same_genre(a: &str, b: &str) -> bool {
+    a.to_lowercase() == b.to_lowercase()
+}
+
+async fn find_genre(state: &StateWrapper, name: &str) -> Result<Label, ApiError> {
+    state.genres().await.into_iter()
+        .find(|genre| same_genre(&genre.name, name))
+        .ok_or_else(|| ApiError::NotFound(format!("No movie has the genre {:?}", name)))
+}
+
+// Changes the genres of one movie, going through PATCH like any other change so every store persists it and
+// subscribers hear about it. If someone else writes the movie in between, it's read again and changed again. A movie
+// deleted in the meantime is skipped.
+async fn retag(state: &StateWrapper, id: &str, change: impl Fn(&mut Vec<String>)) -> Result<(), ApiError> {
+    loop {
+        let Some(movie) = state.get(id).await else { return Ok(()) };
+        let mut genres = movie.genres.clone();
+        change(&mut genres);
+        if genres == movie.genres {
+            return Ok(());
+        }
+        let patch = MoviePatch { genres: Some(Some(genres)), version: Some(movie.version), ..MoviePatch::default() };
+        validate_patch(&patch)?;
+        match state.patch(id, patch).await {
+            Ok(_) | Err(StoreError::NotFound) => return Ok(()),
+            Err(StoreError::PreconditionFailed(_)) => continue,
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+#[axum::debug_handler]
+async fn genres_handler(State(state): State<StateWrapper>, format: Format) -> Result<Response, ApiError> {
+    format.respond(&Labels { items: state.genres().await })
+}
+
+#[axum::debug_handler]
+async fn genre_handler(ApiPath(genre): ApiPath<String>, State(state): State<StateWrapper>, format: Format) -> Result<Response, ApiError> {
+    format.respond(&find_genre(&state, &genre).await?)
+}
+
+#[axum::debug_handler]
+async fn post_genre_handler(State(state): State<StateWrapper>, format: Format, ApiBody(new_genre): ApiBody<NewGenre>) -> Result<Response, ApiError> {
+    validate_genre(&new_genre.name)?;
+    if !state.genre_movie_ids(&new_genre.name).await.is_empty() {
+        return Err(ApiError::Conflict(format!("Genre {:?} already exists", new_genre.name)));
+    }
+    if new_genre.movies.is_empty() {
+        return Err(ApiError::Validation(vec![FieldError { field: "movies", message: "must list at least one movie".to_string() }]));
+    }
+    // Check every movie can take the genre before giving it to any of them.
+    let mut errors = Vec::new();
+    for id in &new_genre.movies {
+        match state.get(id).await {
+            Some(movie) => {
+                let genres = [movie.genres, vec![new_genre.name.clone()]].concat();
+                if let Err(invalid) = validate_patch(&MoviePatch { genres: Some(Some(genres)), ..MoviePatch::default() }) {
+                    errors.extend(invalid.into_iter().map(|error| FieldError { field: "movies", message: format!("{}: {}", id, error.message) }));
+                }
+            },
+            None => errors.push(FieldError { field: "movies", message: format!("no movie has the id {:?}", id) }),
+        }
+    }
+    if !errors.is_empty() {
+        return Err(ApiError::Validation(errors));
+    }
+    for id in &new_genre.movies {
+        retag(&state, id, |genres| if !genres.iter().any(|genre| same_genre(genre, &new_genre.name)) {
+            genres.push(new_genre.name.clone());
+        }).await?;
+    }
+    debug!("Added genre {} to {} movies", new_genre.name, new_genre.movies.len());
+    let genre = find_genre(&state, &new_genre.name).await?;
+    let location = format!("/genres/{}", labels::path_segment(&genre.name));
+    Ok((StatusCode::CREATED, [(header::LOCATION, location)], format.respond(&genre)?).into_response())
+}
+
+// Renames the genre on every movie that has it. Renaming it to one those movies already have merges the two.
+#[axum::debug_handler]
+async fn put_genre_handler(ApiPath(genre): ApiPath<String>, State(state): State<StateWrapper>, format: Format, ApiBody(update): ApiBody<GenreUpdate>) -> Result<Response, ApiError> {
+    validate_genre(&update.name)?;
+    let ids = state.genre_movie_ids(&genre).await;
+    if ids.is_empty() {
+        return Err(ApiError::NotFound(format!("No movie has the genre {:?}", genre)));
+    }
+    for id in &ids {
+        retag(&state, id, |genres| {
+            let Some(at) = genres.iter().position(|other| same_genre(other, &genre)) else { return };
+            genres.retain(|other| !same_genre(other, &genre) && !same_genre(other, &update.name));
+            genres.insert(at.min(genres.len()), update.name.clone());
+        }).await?;
+    }
+    debug!("Renamed genre {} to {} on {} movies", genre, update.name, ids.len());
+    format.respond(&find_genre(&state, &update.name).await?)
+}
+
+// Takes the genre away from every movie that has it, which is all it takes for it to be gone.
+#[axum::debug_handler]
+async fn delete_genre_handler(ApiPath(genre): ApiPath<String>, State(state): State<StateWrapper>) -> Result<StatusCode, ApiError> {
+    let ids = state.genre_movie_ids(&genre).await;
+    if ids.is_empty() {
+        return Err(ApiError::NotFound(format!("No movie has the genre {:?}", genre)));
+    }
+    for id in &ids {
+        retag(&state, id, |genres| genres.retain(|other| !same_genre(other, &genre))).await?;
+    }
+    debug!("Removed genre {} from {} movies", genre, ids.len());
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// The movies with the genre in id order, looked up by id from the genre index rather than filtered out of every movie.
+#[axum::debug_handler]
+async fn genre_movies_handler(ApiPath(genre): ApiPath<String>, State(state): State<StateWrapper>, ApiQuery(params): ApiQuery<PageParams>, format: Format) -> Result<Response, ApiError> {
+    let limit = params.limit.unwrap_or(DEFAULT_PAGE_LIMIT).clamp(1, MAX_PAGE_LIMIT);
+    let offset = params.offset.unwrap_or(0);
+    let ids = state.genre_movie_ids(&genre).await;
+    if ids.is_empty() {
+        return Err(ApiError::NotFound(format!("No movie has the genre {:?}", genre)));
+    }
+    let total = ids.len();
+    let page: Vec<&String> = ids.iter().skip(offset).take(limit).collect();
+    let mut items = Vec::with_capacity(page.len());
+    for id in &page {
+        // Gone since the ids were read.
+        if let Some(movie) = state.get(id).await {
+            items.push(movie);
+        }
+    }
+    let next = if offset + page.len() < total {
+        let next_params = PageParams { limit: Some(limit), offset: Some(offset + page.len()) };
+        let query = serde_urlencoded::to_string(&next_params)
+            .map_err(|e| ApiError::Internal(format!("Failed to build next page link: {}", e)))?;
+        Some(format!("/v1/genres/{}/movies?{query}", labels::path_segment(&genre)))
+    }
+    else {
+        None
+    };
+    format.respond(&MoviePage { items, total, next, next_cursor: None })
+}
+
+#[axum::debug_handler]
+async fn tags_handler(State(state): State<StateWrapper>, format: Format) -> Result<Response, ApiError> {
+    format.respond(&Labels { items: state.tags().await })
+}
+
+// Replaces every tag the movie has with the ones in the body, which may be none to clear them.
+#[axum::debug_handler]
+async fn put_tags_handler(ApiPath(id): ApiPath<String>, State(state): State<StateWrapper>, format: Format, ApiBody(tags): ApiBody<Vec<String>>) -> Result<Response, ApiError> {
+    let patch = MoviePatch { tags: Some(Some(tags)), ..MoviePatch::default() };
+    validate_patch(&patch)?;
+    let movie = state.patch(&id, patch).await?;
+    debug!("Tagged movie {} with {:?}", movie.name, movie.tags);
+    Ok(([(header::ETAG, representation_etag(&movie, format, None))], format.respond(&movie)?).into_response())
+}
+
+#[axum::debug_handler]
+async fn list_handler(State(state): State<StateWrapper>, ApiQuery(params): ApiQuery<ListParams>, format: Format) -> Result<Response, ApiError> { 
+    let limit = params.limit.unwrap_or(DEFAULT_PAGE_LIMIT).clamp(1, MAX_PAGE_LIMIT);
+    let sort: Sort = params.sort.as_deref().map(str::parse).transpose().map_err(ApiError::InvalidQuery)?.unwrap_or_default();
+    // With a cursor the page starts after the movie it was made after, wherever that now is. See cursor.rs.
+    let after = match params.cursor.as_deref() {
+        _ if params.cursor.is_some() && params.offset.is_some() => return Err(ApiError::InvalidQuery("cursor and offset can't be used together".to_string())),
+        None | Some("") => None,
+        Some(token) => {
+            let cursor = Cursor::decode(token).map_err(ApiError::InvalidQuery)?;
+            if cursor.sort != sort.to_string() {
+                return Err(ApiError::InvalidQuery(format!("The cursor is for sort={:?}, not {:?}", cursor.sort, sort.to_string())));
+            }
+            Some(cursor.last())
+        },
+    };
+
+    let mut matching = state.list(&params.filter()?).await;
+    if !sort.0.is_empty() {
+        sort.apply(&mut matching);
+    }
+    let total = matching.len();
+    let start = match &after {
+        Some(last) => matching.partition_point(|movie| sort.compare(movie, last).is_le()),
+        None => params.offset.unwrap_or(0),
+    };
+    let items: Vec<Movie> = matching.into_iter().skip(start).take(limit).collect();
+    let (next, next_cursor) = match items.last() {
+        Some(last) if start + items.len() < total => {
+            let next_cursor = params.cursor.is_some().then(|| Cursor::after(last, &sort).encode());
+            let next_params = ListParams { 
+                limit: Some(limit),
+                offset: next_cursor.is_none().then_some(start + items.len()),
+                cursor: next_cursor.clone(),
+                ..params.clone()
+            };
+            let query = serde_urlencoded::to_string(&next_params)
+                .map_err(|e| ApiError::Internal(format!("Failed to build next page link: {}", e)))?;
+            (Some(format!("/v1/movies?{query}")), next_cursor)
+        },
+        _ => (None, None),
+    };
+    format.respond(&MoviePage { items, total, next, next_cursor })
+}
+
+#[axum::debug_handler]
+async fn put_handler(ApiPath(id): ApiPath<String>, State(state): State<StateWrapper>, format: Format, headers: HeaderMap, ApiBody(mut movie): ApiBody<Movie>) -> Result<Response, ApiError> { 
+    if movie.id != id {
+        // The body has to describe the same movie the path points at, otherwise we'd be silently re-keying it.
+        return Err(ApiError::BadRequest(format!("Movie id {:?} in the body doesn't match {:?} in the path", movie.id, id)));
+    }
+    validate_movie(&movie)?;
+    debug!("Updating movie {}", movie.name);
+    // The body carries the version it was based on, and replacing any other version would lose someone's change.
+    let expected = movie.version;
+    // The store keeps the timestamps, not the client. Stamped here so the movie responded with has the same stamp.
+    movie.updated_at = Some(model::timestamp());
+    // Kept to respond with the movie as the store made it: at the next version, with the ratings of the one replaced.
+    let replaced = std::sync::Mutex::new(None);
+    let precondition = |current: &Movie| {
+        *replaced.lock().unwrap() = Some(current.clone());
+        current.version == expected && if_match_holds(&headers, current)
+    };
+    state.update_if(movie.clone(), Some(&precondition)).await?;
+    movie.succeed(replaced.into_inner().unwrap().as_ref());
+    Ok(([(header::ETAG, representation_etag(&movie, format, None))], format.respond(&movie)?).into_response())
+}
+
+#[axum::debug_handler]
+async fn patch_handler(ApiPath(id): ApiPath<String>, State(state): State<StateWrapper>, format: Format, ApiBody(patch): ApiBody<MoviePatch>) -> Result<Response, ApiError> { 
+    if let Some(patch_id) = patch.id.as_ref().filter(|patch_id| **patch_id != id) {
+        return Err(ApiError::BadRequest(format!("Movie id {:?} in the patch doesn't match {:?} in the path", patch_id, id)));
+    }
+    validate_patch(&patch)?;
+    let movie = state.patch(&id, patch).await?;
+    debug!("Patched movie {}", movie.name);
+    Ok(([(header::ETAG, representation_etag(&movie, format, None))], format.respond(&movie)?).into_response())
+}
+
+#[axum::debug_handler]
+async fn delete_handler(ApiPath(id): ApiPath<String>, State(state): State<StateWrapper>, headers: HeaderMap) -> Result<StatusCode, ApiError> { 
+    let precondition = |current: &Movie| if_match_holds(&headers, current);
+    let movie = state.delete_if(&id, Some(&precondition)).await?;
+    debug!("Removed movie {}", movie.name);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[axum::debug_handler]
+async fn trash_handler(State(state): State<StateWrapper>, ApiQuery(params): ApiQuery<PageParams>, format: Format) -> Result<Response, ApiError> {
+    let limit = params.limit.unwrap_or(DEFAULT_PAGE_LIMIT).clamp(1, MAX_PAGE_LIMIT);
+    let offset = params.offset.unwrap_or(0);
+    let trash = state.trash().await;
+    let total = trash.len();
+    let items: Vec<Trashed> = trash.into_iter().skip(offset).take(limit).collect();
+    let next = if offset + items.len() < total {
+        let next_params = PageParams { limit: Some(limit), offset: Some(offset + items.len()) };
+        let query = serde_urlencoded::to_string(&next_params)
+            .map_err(|e| ApiError::Internal(format!("Failed to build next page link: {}", e)))?;
+        Some(format!("/v1/movies/trash?{query}"))
+    }
+    else {
+        None
+    };
+    format.respond(&TrashPage { items, total, next })
+}
+
+#[axum::debug_handler]
+async fn restore_handler(ApiPath(id): ApiPath<String>, ApiQuery(params): ApiQuery<DryRunParams>, State(state): State<StateWrapper>, format: Format) -> Result<Response, ApiError> {
+    let not_in_trash = || ApiError::NotFound(format!("Movie {} isn't in the trash", id));
+    if params.dry_run {
+        // The movie as it would come back, failing the way restoring it would.
+        let trashed = state.trash().await.into_iter().find(|trashed| trashed.movie.id == id).ok_or_else(not_in_trash)?;
+        if let Some(existing) = state.get(&id).await {
+            return Err(ApiError::AlreadyExists(Box::new(existing)));
+        }
+        return Ok(([(header::ETAG, representation_etag(&trashed.movie, format, None))], format.respond(&trashed.movie)?).into_response());
+    }
+    let movie = state.restore(&id).await.map_err(|e| match e {
+        StoreError::NotFound => not_in_trash(),
+        e => e.into(),
+    })?;
+    debug!("Restored movie {}", movie.name);
+    Ok(([(header::ETAG, representation_etag(&movie, format, None))], format.respond(&movie)?).into_response())
+}
+
+// Purges movies from the trash for good now, rather than once they've been in it for --trash-retention-secs.
+#[axum::debug_handler]
+async fn purge_trash_handler(State(state): State<StateWrapper>, ApiQuery(params): ApiQuery<PurgeParams>, format: Format) -> Result<Response, ApiError> {
+    let deleted_before = match &params.deleted_before {
+        Some(deleted_before) => OffsetDateTime::parse(deleted_before, &Rfc3339)
+            .map_err(|_| ApiError::InvalidQuery(format!("deleted_before must be an RFC 3339 time, like 2026-10-15T20:30:00Z, got {:?}", deleted_before)))?,
+        None => OffsetDateTime::now_utc(),
+    };
+    let mut purged = if params.dry_run {
+        state.trash().await.into_iter()
+            .filter(|trashed| OffsetDateTime::parse(&trashed.deleted_at, &Rfc3339).is_ok_and(|deleted_at| deleted_at < deleted_before))
+            .map(|trashed| trashed.movie.id)
+            .collect()
+    }
+    else {
+        state.purge(deleted_before).await?
+    };
+    purged.sort();
+    if !params.dry_run {
+        debug!("Purged {} movies from the trash", purged.len());
+    }
+    format.respond(&PurgeReport { purged, dry_run: params.dry_run })
+}
+
+// Folds a duplicate into the movie it duplicates: the canonical movie takes in its ratings, reviews, tags and titles,
+// and the duplicate is deleted, to the trash like any other delete. Neither may change while that happens, or it's a
+// 412 and nothing is merged.
+#[axum::debug_handler]
+async fn merge_handler(State(state): State<StateWrapper>, format: Format, ApiBody(request): ApiBody<MergeRequest>) -> Result<Response, ApiError> {
+    if request.canonical == request.duplicate {
+        return Err(ApiError::Validation(vec![FieldError { field: "duplicate", message: "must be another movie than canonical".to_string() }]));
+    }
+    let no_movie = |id: &str| ApiError::NotFound(format!("There's no movie {:?}", id));
+    let canonical = state.get(&request.canonical).await.ok_or_else(|| no_movie(&request.canonical))?;
+    let duplicate = state.get(&request.duplicate).await.ok_or_else(|| no_movie(&request.duplicate))?;
+    let mut merged = canonical.clone();
+    merged.absorb(duplicate.clone());
+    validate_movie(&merged)?;
+
+    let unchanged = |movie: &Movie| movie.version == duplicate.version;
+    let duplicate = state.delete_if(&duplicate.id, Some(&unchanged)).await?;
+    let patch = MoviePatch { merge: Some(Box::new(duplicate.clone())), version: Some(canonical.version), ..MoviePatch::default() };
+    let merged = match state.patch(&canonical.id, patch).await {
+        Ok(merged) => merged,
+        Err(e) => {
+            // Back out of the trash, so a merge that fails leaves both as they were.
+            if let Err(restore_error) = state.restore(&duplicate.id).await {
+                warn!("Failed to restore movie {} after merging it into {} failed: {:?}", duplicate.id, canonical.id, restore_error);
+            }
+            return Err(e.into());
+        },
+    };
+    debug!("Merged movie {} into {}", duplicate.id, merged.id);
+    Ok(([(header::ETAG, representation_etag(&merged, format, None))], format.respond(&merged)?).into_response())
+}
+
+// RFC 3339, in UTC, for stamping reviews and users with.
+fn now() -> Result<String, ApiError> {
+    OffsetDateTime::now_utc().format(&Rfc3339).map_err(|e| ApiError::Internal(format!("Failed to format the time: {}", e)))
+}
+
+// Who a rating or review is from: the subject of the caller's bearer token, or with no token whoever the body says.
+fn speaker(caller: Caller, claimed: Option<String>, field: &'static str) -> Result<String, ApiError> {
+    match (caller.subject, claimed) {
+        (Some(subject), Some(claimed)) if subject != claimed => Err(ApiError::Forbidden(format!("The {} has to be the subject of the bearer token", field))),
+        (Some(subject), _) | (None, Some(subject)) => Ok(subject),
+        (None, None) => Err(ApiError::Validation(vec![FieldError { field, message: "is required without a bearer token".to_string() }])),
+    }
+}
+
+// Sets the caller's score for the movie, replacing any they gave it before, and returns the movie with its new average.
+#[axum::debug_handler]
+async fn post_rating_handler(ApiPath(id): ApiPath<String>, State(state): State<StateWrapper>, Extension(caller): Extension<Caller>, format: Format, ApiBody(rating): ApiBody<NewRating>) -> Result<Response, ApiError> {
+    let user = speaker(caller, rating.user, "user")?;
+    validate_rating(&user, rating.score)?;
+    let movie = state.patch(&id, MoviePatch { rating: Some((user.clone(), rating.score)), ..MoviePatch::default() }).await?;
+    debug!("{} rated movie {} {}", user, movie.name, rating.score);
+    Ok(([(header::ETAG, representation_etag(&movie, format, None))], format.respond(&movie)?).into_response())
+}
+
+#[axum::debug_handler]
+async fn ratings_handler(ApiPath(id): ApiPath<String>, State(state): State<StateWrapper>, ApiQuery(params): ApiQuery<PageParams>, format: Format) -> Result<Response, ApiError> {
+    let limit = params.limit.unwrap_or(DEFAULT_PAGE_LIMIT).clamp(1, MAX_PAGE_LIMIT);
+    let offset = params.offset.unwrap_or(0);
+    let movie = state.get(&id).await.ok_or(StoreError::NotFound)?;
+    let total = movie.ratings.len();
+    let items: Vec<Rating> = movie.ratings.into_iter().skip(offset).take(limit).map(|(user, score)| Rating { user, score }).collect();
+    let next = if offset + items.len() < total {
+        let next_params = PageParams { limit: Some(limit), offset: Some(offset + items.len()) };
+        let query = serde_urlencoded::to_string(&next_params)
+            .map_err(|e| ApiError::Internal(format!("Failed to build next page link: {}", e)))?;
+        Some(format!("/v1/movie/{}/ratings?{query}", movie.id))
+    }
+    else {
+        None
+    };
+    format.respond(&RatingPage { items, total, average_rating: movie.average_rating, next })
+}
+
+#[axum::debug_handler]
+async fn post_review_handler(ApiPath(id): ApiPath<String>, State(state): State<StateWrapper>, Extension(caller): Extension<Caller>, format: Format, ApiBody(new_review): ApiBody<NewReview>) -> Result<Response, ApiError> {
+    let author = speaker(caller, new_review.author, "author")?;
+    validate_review(&author, &new_review.text)?;
+    let review = Review { id: random::uuid_v7(), author, text: new_review.text, created_at: now()? };
+    let movie = state.patch(&id, MoviePatch { review: Some(review.clone()), ..MoviePatch::default() }).await?;
+    debug!("{} reviewed movie {}", review.author, movie.name);
+    Ok((StatusCode::CREATED, format.respond(&review)?).into_response())
+}
+
+#[axum::debug_handler]
+async fn reviews_handler(ApiPath(id): ApiPath<String>, State(state): State<StateWrapper>, ApiQuery(params): ApiQuery<PageParams>, format: Format) -> Result<Response, ApiError> {
+    let limit = params.limit.unwrap_or(DEFAULT_PAGE_LIMIT).clamp(1, MAX_PAGE_LIMIT);
+    let offset = params.offset.unwrap_or(0);
+    let movie = state.get(&id).await.ok_or(StoreError::NotFound)?;
+    let total = movie.reviews.len();
+    let items: Vec<Review> = movie.reviews.iter().rev().skip(offset).take(limit).cloned().collect();
+    let next = if offset + items.len() < total {
+        let next_params = PageParams { limit: Some(limit), offset: Some(offset + items.len()) };
+        let query = serde_urlencoded::to_string(&next_params)
+            .map_err(|e| ApiError::Internal(format!("Failed to build next page link: {}", e)))?;
+        Some(format!("/v1/movie/{}/reviews?{query}", movie.id))
+    }
+    else {
+        None
+    };
+    format.respond(&ReviewPage { items, total, next })
+}
+
+// Every revision of the movie, for stores that keep them. Movies that have been deleted still have theirs.
+async fn history(state: &StateWrapper, id: &str) -> Result<Vec<Revision>, ApiError> {
+    let revisions = state.history(id).await
+        .ok_or_else(|| ApiError::NotFound("This server doesn't keep the history of movies, that needs --store events://<path>".to_string()))?;
+    if revisions.is_empty() {
+        return Err(StoreError::NotFound.into());
+    }
+    Ok(revisions)
+}
+
+#[axum::debug_handler]
+async fn movie_history_handler(ApiPath(id): ApiPath<String>, State(state): State<StateWrapper>, ApiQuery(params): ApiQuery<PageParams>, format: Format) -> Result<Response, ApiError> {
+    let limit = params.limit.unwrap_or(DEFAULT_PAGE_LIMIT).clamp(1, MAX_PAGE_LIMIT);
+    let offset = params.offset.unwrap_or(0);
+    let revisions = history(&state, &id).await?;
+    let total = revisions.len();
+    let items: Vec<Revision> = revisions.into_iter().skip(offset).take(limit).collect();
+    let next = if offset + items.len() < total {
+        let next_params = PageParams { limit: Some(limit), offset: Some(offset + items.len()) };
+        let query = serde_urlencoded::to_string(&next_params)
+            .map_err(|e| ApiError::Internal(format!("Failed to build next page link: {}", e)))?;
+        Some(format!("/v1/movie/{}/history?{query}", id))
+    }
+    else {
+        None
+    };
+    format.respond(&RevisionPage { items, total, next })
+}
+
+// The movies linked to this one either way, in link order.
+#[axum::debug_handler]
+async fn related_handler(ApiPath(id): ApiPath<String>, State(state): State<StateWrapper>, format: Format) -> Result<Response, ApiError> {
+    state.get(&id).await.ok_or(StoreError::NotFound)?;
+    let mut items = Vec::new();
+    for link in state.links(&id).await {
+        let (relation, other) = if link.from == id { (link.relation.name(), &link.to) } else { (link.relation.inverse_name(), &link.from) };
+        // Gone since the links were read.
+        if let Some(movie) = state.get(other).await {
+            items.push(RelatedMovie { relation, movie });
+        }
+    }
+    format.respond(&RelatedMovies { items })
+}
+
+// Links the movie to another, 201 with the link, or 200 if it was already there.
+#[axum::debug_handler]
+async fn post_related_handler(ApiPath(id): ApiPath<String>, State(state): State<StateWrapper>, format: Format, ApiBody(new_link): ApiBody<NewLink>) -> Result<Response, ApiError> {
+    if new_link.movie_id == id {
+        let message = "can't be the movie itself".to_string();
+        return Err(ApiError::Validation(vec![FieldError { field: "movie_id", message }]));
+    }
+    let link = Link::new(id, new_link.relation, new_link.movie_id);
+    let created = state.link(link.clone()).await?;
+    debug!("Linked movie {} as {} movie {}", link.from, link.relation.name(), link.to);
+    let status = if created { StatusCode::CREATED } else { StatusCode::OK };
+    Ok((status, format.respond(&link)?).into_response())
+}
+
+// Unlinks them, whether or not they were linked. Links are named from the movie they're from, apart from
+// part-of-franchise, which goes both ways.
+#[axum::debug_handler]
+async fn delete_related_handler(ApiPath((id, relation, movie_id)): ApiPath<(String, Relation, String)>, State(state): State<StateWrapper>) -> Result<StatusCode, ApiError> {
+    state.get(&id).await.ok_or(StoreError::NotFound)?;
+    if state.unlink(&Link::new(id.clone(), relation, movie_id.clone())).await? {
+        debug!("Unlinked movie {} as {} movie {}", id, relation.name(), movie_id);
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// Takes the image as the body, or as the first file of a multipart/form-data one, as browsers send forms.
+#[axum::debug_handler]
+async fn put_poster_handler(ApiPath(id): ApiPath<String>, State(state): State<StateWrapper>, headers: HeaderMap, format: Format, body: Body) -> Result<Response, ApiError> {
+    let content_type = headers.get(header::CONTENT_TYPE).and_then(|value| value.to_str().ok()).map(str::to_string);
+    let multipart = content_type.as_deref().is_some_and(posters::is_multipart);
+    let too_large = || ApiError::PayloadTooLarge(MAX_POSTER_BYTES);
+    // Read as it arrives, so an oversized upload is turned away without holding all of it.
+    let limit = if multipart { MAX_POSTER_BYTES + MULTIPART_OVERHEAD } else { MAX_POSTER_BYTES };
+    let mut body_bytes = Vec::new();
+    let mut chunks = body.into_data_stream();
+    while let Some(chunk) = chunks.next().await {
+        let chunk = chunk.map_err(body_limit::read_error)?;
+        if body_bytes.len() + chunk.len() > limit {
+            return Err(too_large());
+        }
+        body_bytes.extend_from_slice(&chunk);
+    }
+    let body_bytes = axum::body::Bytes::from(body_bytes);
+    let (declared, image) = match content_type {
+        Some(content_type) if multipart => posters::multipart_file(&content_type, &body_bytes).map_err(|e| ApiError::InvalidBody(StatusCode::BAD_REQUEST, e))?,
+        content_type => (content_type, body_bytes),
+    };
+    if image.len() > MAX_POSTER_BYTES {
+        return Err(too_large());
+    }
+    let poster = posters::describe(&image, declared.as_deref()).map_err(|e| ApiError::InvalidBody(StatusCode::UNSUPPORTED_MEDIA_TYPE, e))?;
+    // Checked first so that images for movies that aren't there don't pile up. One deleted in between only leaves an
+    // image behind.
+    state.get(&id).await.ok_or(StoreError::NotFound)?;
+    posters::store().put(poster.sha256.clone(), image).await
+        .map_err(|e| ApiError::Internal(format!("Failed to store poster {}: {}", poster.sha256, e)))?;
+    let movie = state.patch(&id, MoviePatch { poster: Some(poster.clone()), ..MoviePatch::default() }).await?;
+    debug!("Stored a {} byte {} poster for movie {}", poster.size, poster.content_type, movie.name);
+    format.respond(&poster)
+}
+
+// The image is only ever replaced by a different one under a different ETag, so caches only have to check back now
+// and then, and get a 304 when they do if it hasn't changed.
+#[axum::debug_handler]
+async fn poster_handler(ApiPath(id): ApiPath<String>, State(state): State<StateWrapper>, headers: HeaderMap) -> Result<Response, ApiError> {
+    let movie = state.get(&id).await.ok_or(StoreError::NotFound)?;
+    let poster = movie.poster.ok_or_else(|| ApiError::NotFound("The movie has no poster".to_string()))?;
+    let etag = format!("\"{}\"", poster.sha256);
+    let cache_headers = [(header::ETAG, etag.clone()), (header::CACHE_CONTROL, "public, max-age=3600".to_string())];
+    if etag_matches(&headers, header::IF_NONE_MATCH, &etag, true) {
+        return Ok((StatusCode::NOT_MODIFIED, cache_headers).into_response());
+    }
+    let image = posters::store().get(&poster.sha256).await
+        .map_err(|e| ApiError::Internal(format!("Failed to read poster {}: {}", poster.sha256, e)))?
+        .ok_or_else(|| ApiError::Internal(format!("Poster {} of movie {} is missing", poster.sha256, movie.id)))?;
+    Ok((cache_headers, [(header::CONTENT_TYPE, poster.content_type)], image).into_response())
+}
+
+#[axum::debug_handler]
+async fn post_user_handler(State(state): State<StateWrapper>, Extension(caller): Extension<Caller>, format: Format, ApiBody(new_user): ApiBody<NewUser>) -> Result<Response, ApiError> {
+    let id = match (caller.subject, new_user.id) {
+        (Some(subject), Some(claimed)) if subject != claimed => return Err(ApiError::Forbidden("The id has to be the subject of the bearer token".to_string())),
+        (Some(id), _) | (None, Some(id)) => id,
+        (None, None) => random::uuid_v7(),
+    };
+    validate_user(&id, new_user.name.as_deref())?;
+    let user = User { id, name: new_user.name, created_at: now()?, ..User::default() };
+    state.insert_user(user.clone()).await?;
+    debug!("Registered user {}", user.id);
+    let location = format!("/users/{}", labels::path_segment(&user.id));
+    Ok((StatusCode::CREATED, [(header::LOCATION, location)], format.respond(&user)?).into_response())
+}
+
+#[axum::debug_handler]
+async fn user_handler(ApiPath(id): ApiPath<String>, State(state): State<StateWrapper>, format: Format) -> Result<Response, ApiError> {
+    format.respond(&state.get_user(&id).await.ok_or(StoreError::UserNotFound)?)
+}
+
+// Callers with a bearer token may only change their own lists, or see their own history, unless they're an admin.
+fn check_owner(caller: &Caller, user_id: &str) -> Result<(), ApiError> {
+    match &caller.subject {
+        Some(subject) if subject != user_id && caller.role < Some(Role::Admin) => {
+            Err(ApiError::Forbidden(format!("Only {} may do this, or an admin", user_id)))
+        },
+        _ => Ok(()),
+    }
+}
+
+// Changes the user, first registering them if that's the caller, known only by their bearer token so far.
+async fn change_user(state: &StateWrapper, caller: &Caller, user_id: &str, change: UserChange) -> Result<User, ApiError> {
+    check_owner(caller, user_id)?;
+    if caller.subject.as_deref() == Some(user_id) && state.get_user(user_id).await.is_none() {
+        let user = User { id: user_id.to_string(), created_at: now()?, ..User::default() };
+        validate_user(&user.id, None)?;
+        match state.insert_user(user).await {
+            // Someone else's request got there first.
+            Ok(()) | Err(StoreError::UserAlreadyExists(_)) => debug!("Registered user {} from their bearer token", user_id),
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(state.change_user(user_id, change).await?)
+}
+
+// A page of the movies with the ids, in the order given, for watchlists and favorites.
+async fn movie_id_page(state: &StateWrapper, movie_ids: &[String], params: PageParams, path: &str) -> Result<MoviePage, ApiError> {
+    let limit = params.limit.unwrap_or(DEFAULT_PAGE_LIMIT).clamp(1, MAX_PAGE_LIMIT);
+    let offset = params.offset.unwrap_or(0);
+    let total = movie_ids.len();
+    let page: Vec<&String> = movie_ids.iter().skip(offset).take(limit).collect();
+    let mut items = Vec::with_capacity(page.len());
+    for movie_id in &page {
+        // Gone since the user was read.
+        if let Some(movie) = state.get(movie_id).await {
+            items.push(movie);
+        }
+    }
+    let next = if offset + page.len() < total {
+        let next_params = PageParams { limit: Some(limit), offset: Some(offset + page.len()) };
+        let query = serde_urlencoded::to_string(&next_params)
+            .map_err(|e| ApiError::Internal(format!("Failed to build next page link: {}", e)))?;
+        Some(format!("{path}?{query}"))
+    }
+    else {
+        None
+    };
+    Ok(MoviePage { items, total, next, next_cursor: None })
+}
+
+// The movies on the watchlist in the order they were added.
+#[axum::debug_handler]
+async fn watchlist_handler(ApiPath(id): ApiPath<String>, State(state): State<StateWrapper>, ApiQuery(params): ApiQuery<PageParams>, format: Format) -> Result<Response, ApiError> {
+    let user = state.get_user(&id).await.ok_or(StoreError::UserNotFound)?;
+    let path = format!("/v1/users/{}/watchlist", labels::path_segment(&user.id));
+    format.respond(&movie_id_page(&state, &user.watchlist, params, &path).await?)
+}
+
+// Fails if the list is full and the movie isn't already on it, which adding it again wouldn't change.
+fn check_room(movie_ids: &[String], movie_id: &str, max: usize, list: &str) -> Result<(), ApiError> {
+    if movie_ids.len() >= max && !movie_ids.iter().any(|other| other == movie_id) {
+        let message = format!("can't be added to {} of {} movies, the most there may be", list, max);
+        return Err(ApiError::Validation(vec![FieldError { field: "movie_id", message }]));
+    }
+    Ok(())
+}
+
+// Adds the movie to the end of the watchlist, unless it's on it already, and returns the user.
+#[axum::debug_handler]
+async fn post_watchlist_handler(ApiPath(id): ApiPath<String>, State(state): State<StateWrapper>, Extension(caller): Extension<Caller>, format: Format, ApiBody(addition): ApiBody<WatchlistAddition>) -> Result<Response, ApiError> {
+    if let Some(user) = state.get_user(&id).await {
+        check_room(&user.watchlist, &addition.movie_id, MAX_WATCHLIST_LEN, "a watchlist")?;
+    }
+    let user = change_user(&state, &caller, &id, UserChange::AddToWatchlist(addition.movie_id.clone())).await?;
+    debug!("Added movie {} to the watchlist of {}", addition.movie_id, user.id);
+    format.respond(&user)
+}
+
+// Whether or not the movie was on it.
+#[axum::debug_handler]
+async fn delete_watchlist_handler(ApiPath((id, movie_id)): ApiPath<(String, String)>, State(state): State<StateWrapper>, Extension(caller): Extension<Caller>) -> Result<StatusCode, ApiError> {
+    change_user(&state, &caller, &id, UserChange::RemoveFromWatchlist(movie_id.clone())).await?;
+    debug!("Removed movie {} from the watchlist of {}", movie_id, id);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// The user's favorite movies in the order they were marked.
+#[axum::debug_handler]
+async fn favorites_handler(ApiPath(id): ApiPath<String>, State(state): State<StateWrapper>, ApiQuery(params): ApiQuery<PageParams>, format: Format) -> Result<Response, ApiError> {
+    let user = state.get_user(&id).await.ok_or(StoreError::UserNotFound)?;
+    let path = format!("/v1/users/{}/favorites", labels::path_segment(&user.id));
+    format.respond(&movie_id_page(&state, &user.favorites, params, &path).await?)
+}
+
+// Marks the movie as a favorite. Doing it again changes nothing.
+#[axum::debug_handler]
+async fn put_favorite_handler(ApiPath((id, movie_id)): ApiPath<(String, String)>, State(state): State<StateWrapper>, Extension(caller): Extension<Caller>) -> Result<StatusCode, ApiError> {
+    if let Some(user) = state.get_user(&id).await {
+        check_room(&user.favorites, &movie_id, MAX_FAVORITES, "favorites")?;
+    }
+    change_user(&state, &caller, &id, UserChange::AddFavorite(movie_id.clone())).await?;
+    debug!("User {} marked movie {} as a favorite", id, movie_id);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[axum::debug_handler]
+async fn delete_favorite_handler(ApiPath((id, movie_id)): ApiPath<(String, String)>, State(state): State<StateWrapper>, Extension(caller): Extension<Caller>) -> Result<StatusCode, ApiError> {
+    change_user(&state, &caller, &id, UserChange::RemoveFavorite(movie_id.clone())).await?;
+    debug!("User {} unmarked movie {} as a favorite", id, movie_id);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// The watches recorded after `since`, oldest first, for clients to sync from: they send the recorded_at of the last one
+// they got as the next since.
+#[axum::debug_handler]
+async fn history_handler(ApiPath(id): ApiPath<String>, State(state): State<StateWrapper>, Extension(caller): Extension<Caller>, ApiQuery(params): ApiQuery<HistoryParams>, format: Format) -> Result<Response, ApiError> {
+    check_owner(&caller, &id)?;
+    let limit = params.limit.unwrap_or(DEFAULT_PAGE_LIMIT).clamp(1, MAX_PAGE_LIMIT);
+    let offset = params.offset.unwrap_or(0);
+    let since = params.since.as_deref()
+        .map(|since| OffsetDateTime::parse(since, &Rfc3339).map_err(|_| ApiError::InvalidQuery(format!("since {:?} isn't an RFC 3339 time", since))))
+        .transpose()?;
+    let user = state.get_user(&id).await.ok_or(StoreError::UserNotFound)?;
+    // Stamped by us, so they always parse.
+    let recorded_after = |watch: &&Watch| since.is_none_or(|since| OffsetDateTime::parse(&watch.recorded_at, &Rfc3339).is_ok_and(|recorded_at| recorded_at > since));
+    let matching: Vec<&Watch> = user.history.iter().filter(recorded_after).collect();
+    let total = matching.len();
+    let items: Vec<Watch> = matching.into_iter().skip(offset).take(limit).cloned().collect();
+    let next = if offset + items.len() < total {
+        let next_params = HistoryParams { limit: Some(limit), offset: Some(offset + items.len()), ..params };
+        let query = serde_urlencoded::to_string(&next_params)
+            .map_err(|e| ApiError::Internal(format!("Failed to build next page link: {}", e)))?;
+        Some(format!("/v1/users/{}/history?{query}", labels::path_segment(&user.id)))
+    }
+    else {
+        None
+    };
+    format.respond(&HistoryPage { items, total, next })
+}
+
+// Records that the user watched the movie, just now unless the body says when.
+#[axum::debug_handler]
+async fn post_history_handler(ApiPath(id): ApiPath<String>, State(state): State<StateWrapper>, Extension(caller): Extension<Caller>, format: Format, ApiBody(new_watch): ApiBody<NewWatch>) -> Result<Response, ApiError> {
+    let now = OffsetDateTime::now_utc();
+    let watched_at = match new_watch.watched_at.as_deref() {
+        Some(watched_at) => validate_watched_at(watched_at, now)?,
+        None => now,
+    };
+    let format_time = |time: OffsetDateTime| time.format(&Rfc3339).map_err(|e| ApiError::Internal(format!("Failed to format the time: {}", e)));
+    let watch = Watch { movie_id: new_watch.movie_id, watched_at: format_time(watched_at)?, recorded_at: format_time(now)? };
+    change_user(&state, &caller, &id, UserChange::Watched(watch.clone())).await?;
+    debug!("User {} watched movie {}", id, watch.movie_id);
+    Ok((StatusCode::CREATED, format.respond(&watch)?).into_response())
+}
+
+// Movies like their favorites that they haven't watched, best first. Private like the history, which it gives away.
+#[axum::debug_handler]
+async fn recommendations_handler(ApiPath(id): ApiPath<String>, State(state): State<StateWrapper>, Extension(caller): Extension<Caller>, ApiQuery(params): ApiQuery<RecommendationParams>, format: Format) -> Result<Response, ApiError> {
+    check_owner(&caller, &id)?;
+    let user = state.get_user(&id).await.ok_or(StoreError::UserNotFound)?;
+    let limit = params.limit.unwrap_or(DEFAULT_PAGE_LIMIT).clamp(1, MAX_PAGE_LIMIT);
+    let mut items = recommend::recommend(&user, state.list(&MovieFilter::default()).await);
+    items.truncate(limit);
+    format.respond(&Recommendations { items })
+}
+
+#[axum::debug_handler]
+async fn events_handler(State(state): State<StateWrapper>) -> Sse<impl Stream<Item = Result<Event, Infallible>>> { 
+    // Comments every 15s keep proxies from closing a quiet stream.
+    Sse::new(events::sse_stream(state.subscribe())).keep_alive(KeepAlive::default())
+}
+
+// The changes made after the client's cursor, for catching up after being offline, see changes.rs.
+#[axum::debug_handler]
+async fn changes_handler(State(state): State<StateWrapper>, ApiQuery(params): ApiQuery<ChangesParams>, format: Format) -> Result<Response, ApiError> {
+    let limit = params.limit.unwrap_or(DEFAULT_PAGE_LIMIT).clamp(1, MAX_PAGE_LIMIT);
+    let since = match params.since.as_deref() {
+        None => Since::Oldest,
+        Some("latest") => Since::Latest,
+        Some(since) => Since::Cursor(ChangeCursor::decode(since).ok_or_else(|| ApiError::InvalidQuery(format!("since {:?} isn't a cursor from /movies/changes", since)))?),
+    };
+    let page = state.changes(&since, limit)
+        .map_err(|CursorExpired| ApiError::Gone("The changes since that cursor are no longer kept: take a new one with since=latest, then download every movie again".to_string()))?;
+    format.respond(&page)
+}
+
+#[axum::debug_handler]
+async fn subscribe_handler(State(state): State<StateWrapper>, ApiQuery(filter): ApiQuery<SubscriptionFilter>, request: Request) -> Result<Response, ApiError> { 
+    let (response, upgrade) = websocket::accept(request).map_err(ApiError::BadRequest)?;
+    // Subscribe before answering, so nothing that happens between the handshake and the task starting is missed.
+    let changes = state.subscribe();
+    tokio::spawn(async move {
+        match upgrade.await {
+            Ok(upgraded) => events::run_subscription(TokioIo::new(upgraded), changes, filter).await,
+            Err(e) => warn!("WebSocket upgrade failed: {}", e),
+        }
+    });
+    Ok(response)
+}
+
+#[axum::debug_handler]
+async fn healthz_handler() -> String { 
+    // The process is up and answering requests, which is all liveness is about.
+    json_status("ok")
+}
+
+#[axum::debug_handler]
+async fn readyz_handler(State(state): State<StateWrapper>) -> Result<String, ApiError> { 
+    if seed::is_pending() {
+        return Err(ApiError::Unavailable("Still loading the seed data".to_string()));
+    }
+    if replication::is_copying() {
+        return Err(ApiError::Unavailable("Still copying the movies of the instance this one follows".to_string()));
+    }
+    let ready = tokio::time::timeout(READY_CHECK_TIMEOUT, state.check_ready()).await
+        .map_err(|_| format!("Storage backend didn't answer within {:?}", READY_CHECK_TIMEOUT))
+        .and_then(|result| result.map_err(|e| match e {
+            StoreError::Backend(message) => format!("Storage backend isn't ready: {}", message),
+            other => format!("Storage backend isn't ready: {:?}", other),
+        }));
+    if let Err(message) = ready {
+        warn!("{}", message);
+        return Err(ApiError::Unavailable(message));
+    }
+    Ok(json_status("ready"))
+}
+
+#[axum::debug_handler]
+async fn metrics_handler(State(state): State<StateWrapper>, Extension(settings): Extension<Settings>) -> Response { 
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], metrics::render(state.as_ref(), &settings).await).into_response()
+}
+
+#[axum::debug_handler]
+async fn openapi_handler() -> Json<serde_json::Value> { 
+    Json(openapi::document())
+}
+
+#[axum::debug_handler]
+async fn admin_ui_handler() -> Response { 
+    admin_ui::serve("index.html").expect("the admin page is always there")
+}
+
+#[axum::debug_handler]
+async fn admin_ui_asset_handler(ApiPath(name): ApiPath<String>) -> Result<Response, ApiError> { 
+    admin_ui::serve(&name).ok_or_else(|| ApiError::NotFound(format!("The admin UI has no {}", name)))
+}
+
+#[axum::debug_handler]
+async fn swagger_ui_handler() -> Html<&'static str> { 
+    Html(openapi::SWAGGER_UI)
+}
+
+#[axum::debug_handler]
+async fn graphql_handler(State(state): State<StateWrapper>, Extension(caller): Extension<Caller>, Extension(settings): Extension<Settings>, ApiJson(request): ApiJson<GraphQLRequest>) -> Json<GraphQLResponse> { 
+    // Errors from the query itself come back as 200 with an "errors" list, as GraphQL clients expect.
+    Json(graphql::execute(state.as_ref(), settings.duplicates(), request, &caller).await)
+}
+
+#[axum::debug_handler]
+async fn graphql_schema_handler() -> &'static str { 
+    graphql::SCHEMA
+}
+
+#[derive(Debug, Deserialize)]
+struct LoginParams { 
+    // Where to end up once logged in, a path on this server.
+    pub return_to: Option<String>,
+}
+
+#[axum::debug_handler]
+async fn login_handler(ApiQuery(params): ApiQuery<LoginParams>) -> Result<Redirect, ApiError> { 
+    Ok(Redirect::to(&oidc::begin_login(params.return_to).await?))
+}
+
+#[derive(Debug, Deserialize)]
+struct CallbackParams { 
+    pub code: Option<String>,
+    pub state: Option<String>,
+    // Set instead of code when the provider didn't log the person in.
+    pub error: Option<String>,
+    pub error_description: Option<String>,
+}
+
+#[axum::debug_handler]
+async fn callback_handler(ApiQuery(params): ApiQuery<CallbackParams>) -> Result<Response, ApiError> { 
+    if let Some(error) = params.error {
+        let description = params.error_description.map(|description| format!(": {}", description)).unwrap_or_default();
+        return Err(ApiError::Unauthorized(format!("The identity provider refused the login with {}{}", error, description)));
+    }
+    let (Some(code), Some(state)) = (params.code, params.state) else {
+        return Err(ApiError::BadRequest("Expected code and state from the identity provider".to_string()));
+    };
+    let login = oidc::finish_login(&code, &state).await?;
+    let cookie = oidc::session_cookie(&login.session, login.max_age);
+    Ok(([(header::SET_COOKIE, cookie)], Redirect::to(&login.return_to)).into_response())
+}
+
+#[axum::debug_handler]
+async fn logout_handler(headers: HeaderMap) -> Response { 
+    oidc::end_session(&headers);
+    (StatusCode::NO_CONTENT, [(header::SET_COOKIE, oidc::session_cookie("", Duration::ZERO))]).into_response()
+}
+
+#[axum::debug_handler]
+async fn session_handler(Extension(caller): Extension<Caller>) -> Result<String, ApiError> { 
+    Ok(serde_json::to_string_pretty(&caller)?)
+}
+
+#[axum::debug_handler]
+async fn create_webhook_handler(ApiJson(new_webhook): ApiJson<NewWebhook>) -> Result<Response, ApiError> {
+    let created = webhooks::register(new_webhook)?;
+    debug!("Registered webhook {} for {}", created.webhook.id, created.webhook.url);
+    let location = format!("/admin/webhooks/{}", created.webhook.id);
+    Ok((StatusCode::CREATED, [(header::LOCATION, location)], Json(created)).into_response())
+}
+
+#[axum::debug_handler]
+async fn list_webhooks_handler() -> Json<serde_json::Value> {
+    Json(serde_json::json!({ "items": webhooks::list() }))
+}
+
+#[axum::debug_handler]
+async fn delete_webhook_handler(ApiPath(id): ApiPath<String>) -> Result<StatusCode, ApiError> {
+    if !webhooks::remove(&id) {
+        return Err(ApiError::NotFound(format!("No webhook has the id {}", id)));
+    }
+    debug!("Removed webhook {}", id);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[axum::debug_handler]
+async fn dead_letters_handler() -> Json<serde_json::Value> {
+    Json(serde_json::json!({ "items": webhooks::dead_letters() }))
+}
+
+// The changes made to the store, oldest first, for operators to find out who changed what.
+#[axum::debug_handler]
+async fn audit_handler(ApiQuery(params): ApiQuery<AuditParams>, format: Format) -> Result<Response, ApiError> {
+    let limit = params.limit.unwrap_or(DEFAULT_PAGE_LIMIT).clamp(1, MAX_PAGE_LIMIT);
+    let offset = params.offset.unwrap_or(0);
+    let since = params.since.as_deref()
+        .map(|since| OffsetDateTime::parse(since, &Rfc3339).map_err(|_| ApiError::InvalidQuery(format!("since {:?} isn't an RFC 3339 time", since))))
+        .transpose()?;
+    let matching = audit::entries(since, params.actor.as_deref());
+    let total = matching.len();
+    let items: Vec<AuditEntry> = matching.into_iter().skip(offset).take(limit).collect();
+    let next = if offset + items.len() < total {
+        let next_params = AuditParams { limit: Some(limit), offset: Some(offset + items.len()), ..params };
+        let query = serde_urlencoded::to_string(&next_params)
+            .map_err(|e| ApiError::Internal(format!("Failed to build next page link: {}", e)))?;
+        Some(format!("/admin/audit?{query}"))
+    }
+    else {
+        None
+    };
+    format.respond(&AuditPage { items, total, next })
+}
+
+// Writes the backup to --backup-dir if there is one, and otherwise sends it back as a download.
+#[axum::debug_handler]
+async fn snapshot_handler(State(state): State<StateWrapper>, ApiQuery(params): ApiQuery<SnapshotParams>, format: Format) -> Result<Response, ApiError> {
+    let taken_at = OffsetDateTime::now_utc();
+    let file = BackupFile::new(state.backup().await, taken_at);
+    let name = backup::file_name(taken_at);
+    match backup::dir() {
+        Some(dir) if !params.download => {
+            backup::write(&dir, &name, &file).await
+                .map_err(|e| ApiError::Internal(format!("Failed to write backup {} to {}: {}", name, dir.display(), e)))?;
+            debug!("Wrote backup {} of {} movies", name, file.movies.len());
+            Ok((StatusCode::CREATED, format.respond(&BackupSummary::new(Some(name), &file))?).into_response())
+        },
+        _ => {
+            let disposition = format!("attachment; filename=\"{}\"", name);
+            Ok(([(header::CONTENT_TYPE, "application/json".to_string()), (header::CONTENT_DISPOSITION, disposition)], serde_json::to_vec(&file)?).into_response())
+        },
+    }
+}
+
+// Replaces everything in the store with the backup named in the query, or else the one sent as the body.
+#[axum::debug_handler]
+async fn restore_backup_handler(State(state): State<StateWrapper>, ApiQuery(params): ApiQuery<RestoreBackupParams>, format: Format, body: Body) -> Result<Response, ApiError> {
+    let (name, file) = match params.name {
+        Some(name) => {
+            let dir = backup::dir().ok_or_else(|| ApiError::BadRequest("Restoring by name needs --backup-dir to be set".to_string()))?;
+            if !backup::is_valid_name(&name) {
+                return Err(ApiError::InvalidQuery(format!("{:?} isn't the name of a backup", name)));
+            }
+            let file = backup::read(&dir, &name).await.map_err(|e| match e.kind() {
+                io::ErrorKind::NotFound => ApiError::NotFound(format!("There's no backup named {}", name)),
+                io::ErrorKind::InvalidData => ApiError::BadRequest(format!("Backup {} isn't readable: {}", name, e)),
+                _ => ApiError::Internal(format!("Failed to read backup {} from {}: {}", name, dir.display(), e)),
+            })?;
+            (Some(name), file)
+        },
+        None => {
+            // A backup is as big as the store, so it's up to --max-upload-bytes like an import.
+            let bytes = axum::body::to_bytes(body, usize::MAX).await.map_err(body_limit::read_error)?;
+            let file = serde_json::from_slice(&bytes)
+                .map_err(|e| ApiError::InvalidBody(StatusCode::BAD_REQUEST, format!("The body isn't a backup: {}", e)))?;
+            (None, file)
+        },
+    };
+    let summary = BackupSummary::new(name, &file);
+    let backup = file.into_backup();
+    backup::check(&backup).map_err(|e| ApiError::InvalidBody(StatusCode::UNPROCESSABLE_ENTITY, e))?;
+    if params.dry_run {
+        let mut current: HashMap<String, String> = state.backup().await.movies.iter().map(|movie| (movie.id.clone(), movie.etag())).collect();
+        let mut preview = RestorePreview { backup: summary, added: 0, changed: 0, removed: 0, dry_run: true };
+        for movie in &backup.movies {
+            match current.remove(&movie.id) {
+                None => preview.added += 1,
+                Some(etag) if etag != movie.etag() => preview.changed += 1,
+                Some(_) => {},
+            }
+        }
+        preview.removed = current.len();
+        return format.respond(&preview);
+    }
+    state.replace(backup).await?;
+    debug!("Restored backup taken at {} with {} movies", summary.taken_at, summary.movies);
+    format.respond(&summary)
+}
+
+// How full the default catalog and each tenant's are.
+#[axum::debug_handler]
+async fn tenants_handler(State(state): State<StateWrapper>, format: Format) -> Result<Response, ApiError> {
+    format.respond(&serde_json::json!({ "items": tenant::stats(state.as_ref()).await }))
+}
+
+// How many movies are stored against each quota.
+#[axum::debug_handler]
+async fn quotas_handler(State(state): State<StateWrapper>, format: Format) -> Result<Response, ApiError> {
+    format.respond(&quota::usage(state.as_ref()).await)
+}
+
+fn json_status(status: &str) -> String { 
+    serde_json::to_string_pretty(&serde_json::json!({ "status": status })).unwrap()
+}
+
+// The routes of /v1, as they were before there were versions. Their responses mustn't change, schema changes go in a
+// new version.
+fn v1_routes(state: StateWrapper) -> Router<StateWrapper> { 
+    Router::new()
+        .route("/movie", post(post_handler).layer(middleware::from_fn(idempotency::replay_responses)))
+        .route("/movies", get(list_handler))
+        .route("/movies/search", get(search_handler))
+        .route("/movies/suggest", get(suggest_handler))
+        .route("/movies/similar", get(similar_handler))
+        .route("/movies/stats", get(stats_handler))
+        .route("/movies/random", get(random_handler))
+        .route("/movies/trash", get(trash_handler).delete(purge_trash_handler))
+        .route("/genres", get(genres_handler).post(post_genre_handler))
+        .route("/genres/{genre}", get(genre_handler).put(put_genre_handler).delete(delete_genre_handler))
+        .route("/genres/{genre}/movies", get(genre_movies_handler))
+        .route("/tags", get(tags_handler))
+        .route("/movies/lookup", post(lookup_handler))
+        .route("/movies/batch", post(batch_handler).layer(middleware::from_fn(idempotency::replay_responses)))
+        .route("/movies/import", post(import_handler))
+        .route("/movies/export", get(export_handler))
+        .route("/movies/events", get(events_handler))
+        .route("/movies/changes", get(changes_handler))
+        .route("/movies/subscribe", get(subscribe_handler))
+        .route("/graphql", post(graphql_handler).get(graphql_schema_handler))
+        .route("/movie/{id}",
+            get({
+                move |path, query, format, headers| get_handler(path, query, State(state), format, headers)
+            })
+            .put(put_handler)
+            .patch(patch_handler)
+            .delete(delete_handler),
+        )
+        .route("/movie/{id}/ratings", get(ratings_handler).post(post_rating_handler))
+        .route("/movie/{id}/reviews", get(reviews_handler).post(post_review_handler))
+        .route("/movie/{id}/tags", put(put_tags_handler))
+        .route("/movie/{id}/related", get(related_handler).post(post_related_handler))
+        .route("/movie/{id}/poster", get(poster_handler).put(put_poster_handler))
+        .route("/movie/{id}/history", get(movie_history_handler))
+        .route("/movie/{id}/restore", post(restore_handler))
+        .route("/movie/{id}/related/{relation}/{movie_id}", delete(delete_related_handler))
+        .route("/users", post(post_user_handler))
+        .route("/users/{id}", get(user_handler))
+        .route("/users/{id}/watchlist", get(watchlist_handler).post(post_watchlist_handler))
+        .route("/users/{id}/watchlist/{movie_id}", delete(delete_watchlist_handler))
+        .route("/users/{id}/favorites", get(favorites_handler))
+        .route("/users/{id}/favorites/{movie_id}", put(put_favorite_handler).delete(delete_favorite_handler))
+        .route("/users/{id}/history", get(history_handler).post(post_history_handler))
+        .route("/users/{id}/recommendations", get(recommendations_handler))
+}
+
+pub fn build_router(state: StateWrapper, settings: Settings) -> Router { 
+    // GET /api-docs/openapi.json describes every route, see openapi.rs, and the module each handler and layer comes
+    // from says what it does.
+
+    // The movie routes and GraphQL are versioned, see versioning.rs: they're served under /v1, and at their old paths
+    // without it, which get Deprecation and Link headers pointing at /v1. gRPC has its version in its package instead,
+    // movies.v1. The rest are for operators and stay put.
+    let api = VersionedRouter::new()
+        .version("v1", v1_routes(state.clone()))
+        .unversioned("v1", Deprecation { since: UNVERSIONED_DEPRECATED_SINCE.midnight().assume_utc(), sunset: None, successor: "/v1" })
+        .build();
+    let routes = Router::new()
+        .merge(api)
+        .route("/healthz", get(healthz_handler))
+        .route("/readyz", get(readyz_handler))
+        .route("/metrics", get(metrics_handler))
+        .route("/api-docs/openapi.json", get(openapi_handler))
+        .route("/swagger-ui", get(swagger_ui_handler))
+        .route("/admin/login", get(login_handler))
+        .route("/admin/callback", get(callback_handler))
+        .route("/admin/logout", post(logout_handler))
+        .route("/admin/session", get(session_handler))
+        .route("/admin/webhooks", post(create_webhook_handler).get(list_webhooks_handler))
+        .route("/admin/webhooks/dead-letters", get(dead_letters_handler))
+        .route("/admin/webhooks/{id}", delete(delete_webhook_handler))
+        .route("/admin/audit", get(audit_handler))
+        .route("/admin/snapshot", post(snapshot_handler))
+        .route("/admin/restore", post(restore_backup_handler))
+        .route("/admin/merge", post(merge_handler))
+        .route("/admin/tenants", get(tenants_handler))
+        .route("/admin/quotas", get(quotas_handler))
+        .route("/admin/ui", get(admin_ui_handler))
+        .route("/admin/ui/{name}", get(admin_ui_asset_handler))
+        .route("/movies.v1.MovieService/{method}", post(grpc::call))
+        .fallback(fallback::no_route)
+        // A request goes through the layers from the last one added to the first, so each one only sees what the ones
+        // after it let through, and a response goes back the other way.
+        // Times the handler alone, not waits in the layers.
+        .layer(middleware::from_fn_with_state(TIMEOUT_EXEMPT, timeout::time_out_requests))
+        // Inside authorize, to limit per credential.
+        .layer(middleware::from_fn_with_state(RATE_LIMIT_EXEMPT, rate_limit::limit_requests))
+        // After authorize, so anonymous writes still get its 401.
+        .layer(middleware::from_fn_with_state(FOLLOWER_READS, replication::refuse_writes))
+        // Needs the Caller authorize puts in the extensions.
+        .layer(middleware::from_fn_with_state(state.clone(), tenant::select_tenant))
+        // Decides who the caller is for the layers above it.
+        .layer(middleware::from_fn_with_state(ACCESS_OVERRIDES, auth::authorize))
+        // Sees authorize's 401s.
+        .layer(middleware::from_fn_with_state(RATE_LIMIT_EXEMPT, rate_limit::limit_failed_authentication))
+        // Before any work is done on a request it turns away.
+        .layer(middleware::from_fn_with_state(SHED_EXEMPT, load_shed::shed_load))
+        // Counts what the layers above answer, shed requests included.
+        .layer(middleware::from_fn(metrics::track_requests))
+        // Limits the body as decompressed.
+        .layer(middleware::from_fn_with_state(BODY_LIMITS, body_limit::limit_bodies))
+        // limit_bodies takes over from axum's own limit.
+        .layer(DefaultBodyLimit::disable())
+        // So every layer inside reads plain bodies.
+        .layer(middleware::from_fn(compression::decompress_requests))
+        // Compresses every response, errors included.
+        .layer(middleware::from_fn(compression::compress_responses))
+        // Answers preflights before anything asks for credentials.
+        .layer(middleware::from_fn(cors::handle_cors))
+        // Outside the rest, so its span covers all of them.
+        .layer(middleware::from_fn(telemetry::trace_requests))
+        .with_state(state.clone());
+    Router::new()
+        .fallback_service(routes)
+        // 405s are only finished outside the layers above.
+        .layer(middleware::from_fn(fallback::answer_methods))
+        // The id is there for every layer to log with.
+        .layer(middleware::from_fn(request_id::propagate_request_id))
+        // Outside everything that reads them.
+        .layer(Extension(settings))
+}
@@ -0,0 +1,257 @@
+use std::{fmt, sync::{atomic::{AtomicBool, AtomicU64, Ordering}, Arc}, time::Duration};
+use time::{macros::format_description, OffsetDateTime};
+use tokio::time::MissedTickBehavior;
+use tracing::{debug, info, warn};
+
+use crate::backup::{self, BackupFile};
+use crate::crypto;
+use crate::http_client::{self, HttpResponse};
+use crate::resilience::{self, BreakerPolicy, CallError, Integration, RetryPolicy};
+use crate::state::StateWrapper;
+
+// Takes a backup every so often, the same one POST /admin/snapshot does, and uploads it to a bucket on S3 or anything
+// that speaks its API (MinIO, Ceph, R2 behind a proxy, ...), then deletes all but the newest few backups there.
+// Requests are signed with AWS Signature Version 4 and address the bucket path-style, http://endpoint/bucket/key,
+// since that's what works everywhere. Like the rest of the outbound calls it's plain http only, so AWS itself needs a
+// proxy in front of it. A backup that fails is logged and tried again at the next interval, and /metrics has when the
+// last one went through, to alert on.
+
+pub const DEFAULT_PREFIX: &str = "movies/";
+pub const DEFAULT_REGION: &str = "us-east-1";
+pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(60 * 60);
+pub const DEFAULT_KEEP: usize = 24;
+const UPLOAD_TIMEOUT: Duration = Duration::from_secs(60);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const RETRY: RetryPolicy = RetryPolicy { attempts: 3, base_delay: Duration::from_secs(1), max_delay: Duration::from_secs(10) };
+const BREAKER: BreakerPolicy = BreakerPolicy { failures_to_open: 5, open_for: Duration::from_secs(60) };
+// What the hash of a request without a body is.
+const EMPTY_PAYLOAD_SHA256: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+// Unix seconds of the last backup that was uploaded, 0 for none yet.
+static LAST_SUCCESS: AtomicU64 = AtomicU64::new(0);
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+#[derive(Clone, PartialEq, Eq)]
+pub struct S3BackupConfig {
+    // http://host[:port], without a trailing slash.
+    pub endpoint: String,
+    pub bucket: String,
+    // Put in front of every backup's name, e.g. movies/ for movies/movies-20261015T093000.250Z.json.
+    pub prefix: String,
+    pub region: String,
+    pub access_key: String,
+    pub secret_key: String,
+    pub interval: Duration,
+    // Backups to leave in the bucket, 0 to never delete any.
+    pub keep: usize,
+}
+
+// Keeps the secret key out of logs.
+impl fmt::Debug for S3BackupConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("S3BackupConfig")
+            .field("endpoint", &self.endpoint)
+            .field("bucket", &self.bucket)
+            .field("prefix", &self.prefix)
+            .field("region", &self.region)
+            .field("access_key", &self.access_key)
+            .field("interval", &self.interval)
+            .field("keep", &self.keep)
+            .finish_non_exhaustive()
+    }
+}
+
+// When the last backup was uploaded, for /metrics, or None if backups aren't going to S3.
+pub fn last_success() -> Option<u64> {
+    RUNNING.load(Ordering::Relaxed).then(|| LAST_SUCCESS.load(Ordering::Relaxed))
+}
+
+// Starts backing `store` up every interval, the first one an interval after startup, until the process exits.
+pub fn start(config: S3BackupConfig, store: StateWrapper) {
+    let bucket = Bucket { integration: Integration::register("s3-backup", RETRY, BREAKER), config };
+    info!("Backing up to s3://{}/{} at {} every {:?}", bucket.config.bucket, bucket.config.prefix, bucket.config.endpoint, bucket.config.interval);
+    RUNNING.store(true, Ordering::Relaxed);
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval_at(tokio::time::Instant::now() + bucket.config.interval, bucket.config.interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            bucket.back_up(&store).await;
+        }
+    });
+}
+
+struct Bucket {
+    config: S3BackupConfig,
+    integration: Arc<Integration>,
+}
+
+impl Bucket {
+    async fn back_up(&self, store: &StateWrapper) {
+        let taken_at = OffsetDateTime::now_utc();
+        let file = BackupFile::new(store.backup().await, taken_at);
+        let body = match serde_json::to_vec(&file) {
+            Ok(body) => body,
+            Err(e) => {
+                warn!("Failed to serialize a backup: {}", e);
+                return;
+            },
+        };
+        let key = format!("{}{}", self.config.prefix, backup::file_name(taken_at));
+        match self.integration.call(|| self.put(&key, &body)).await {
+            Ok(()) => {
+                LAST_SUCCESS.store(taken_at.unix_timestamp() as u64, Ordering::Relaxed);
+                info!("Backed up {} movies to {} ({} bytes)", file.movies.len(), key, body.len());
+            },
+            // Tried again at the next interval, with nothing deleted until then.
+            Err(e) => {
+                warn!("Failed to upload backup {}: the bucket {}", key, e);
+                return;
+            },
+        }
+        if let Err(e) = self.rotate().await {
+            warn!("Failed to delete old backups: the bucket {}", e);
+        }
+    }
+
+    // Deletes all but the newest `keep` backups, which are the last ones by name. Anything else under the prefix is left
+    // alone.
+    async fn rotate(&self) -> Result<(), CallError> {
+        if self.config.keep == 0 {
+            return Ok(());
+        }
+        let mut keys = self.integration.call(|| self.list()).await?;
+        keys.retain(|key| key.strip_prefix(&self.config.prefix).is_some_and(is_backup_name));
+        keys.sort();
+        let expired = keys.len().saturating_sub(self.config.keep);
+        for key in &keys[..expired] {
+            self.integration.call(|| self.delete(key)).await?;
+            debug!("Deleted old backup {}", key);
+        }
+        Ok(())
+    }
+
+    async fn put(&self, key: &str, body: &[u8]) -> Result<(), CallError> {
+        let response = self.send("PUT", &object_path(&self.config.bucket, key), &[], body, UPLOAD_TIMEOUT).await?;
+        expect_success(response).map(drop)
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), CallError> {
+        let response = self.send("DELETE", &object_path(&self.config.bucket, key), &[], &[], REQUEST_TIMEOUT).await?;
+        // Deleted already by someone else is just as good.
+        if response.status == 404 {
+            return Ok(());
+        }
+        expect_success(response).map(drop)
+    }
+
+    // Every key under the prefix, a page of ListObjectsV2 at a time.
+    async fn list(&self) -> Result<Vec<String>, CallError> {
+        let path = format!("/{}", uri_encode(&self.config.bucket, false));
+        let mut keys = Vec::new();
+        let mut token: Option<String> = None;
+        loop {
+            let mut query = vec![("list-type", "2"), ("prefix", self.config.prefix.as_str())];
+            if let Some(token) = &token {
+                query.push(("continuation-token", token));
+            }
+            let body = expect_success(self.send("GET", &path, &query, &[], REQUEST_TIMEOUT).await?)?;
+            let body = String::from_utf8_lossy(&body);
+            keys.extend(elements(&body, "Key"));
+            match elements(&body, "NextContinuationToken").pop() {
+                Some(next) if elements(&body, "IsTruncated").first().is_some_and(|truncated| truncated == "true") => token = Some(next),
+                _ => return Ok(keys),
+            }
+        }
+    }
+
+    async fn send(&self, method: &str, path: &str, query: &[(&str, &str)], body: &[u8], timeout: Duration) -> Result<HttpResponse, CallError> {
+        let host = self.config.endpoint.trim_start_matches("http://");
+        let payload_hash = if body.is_empty() { EMPTY_PAYLOAD_SHA256.to_string() } else { hex(&crypto::sha256(body)) };
+        let now = OffsetDateTime::now_utc();
+        let amz_date = now.format(format_description!("[year][month][day]T[hour][minute][second]Z")).unwrap_or_default();
+        let signed = [("host", host), ("x-amz-content-sha256", payload_hash.as_str()), ("x-amz-date", amz_date.as_str())];
+        let authorization = authorization(&self.config, method, path, query, &signed, &payload_hash, now);
+        let headers = [("x-amz-content-sha256", payload_hash.as_str()), ("x-amz-date", amz_date.as_str()), ("Authorization", authorization.as_str())];
+        let url = format!("{}{}{}", self.config.endpoint, path, query_string(query).map(|query| format!("?{}", query)).unwrap_or_default());
+        Ok(http_client::request(method, &url, &headers, body, timeout).await?)
+    }
+}
+
+// The Authorization header for a request, signed with Signature Version 4. `headers` are the ones to sign, with
+// lowercase names, and have to include host and x-amz-date; `path` is already URI-encoded.
+pub fn authorization(config: &S3BackupConfig, method: &str, path: &str, query: &[(&str, &str)], headers: &[(&str, &str)], payload_hash: &str, now: OffsetDateTime) -> String {
+    let date = now.format(format_description!("[year][month][day]")).unwrap_or_default();
+    let amz_date = now.format(format_description!("[year][month][day]T[hour][minute][second]Z")).unwrap_or_default();
+    let mut headers = headers.to_vec();
+    headers.sort();
+    let canonical_headers: String = headers.iter().map(|(name, value)| format!("{}:{}\n", name, value.trim())).collect();
+    let signed_headers = headers.iter().map(|(name, _)| *name).collect::<Vec<_>>().join(";");
+    let canonical_request = format!("{}\n{}\n{}\n{}\n{}\n{}", method, path, query_string(query).unwrap_or_default(), canonical_headers, signed_headers, payload_hash);
+
+    let scope = format!("{}/{}/s3/aws4_request", date, config.region);
+    let string_to_sign = format!("AWS4-HMAC-SHA256\n{}\n{}\n{}", amz_date, scope, hex(&crypto::sha256(canonical_request.as_bytes())));
+    let key = crypto::hmac_sha256(format!("AWS4{}", config.secret_key).as_bytes(), date.as_bytes());
+    let key = crypto::hmac_sha256(&key, config.region.as_bytes());
+    let key = crypto::hmac_sha256(&key, b"s3");
+    let key = crypto::hmac_sha256(&key, b"aws4_request");
+    let signature = hex(&crypto::hmac_sha256(&key, string_to_sign.as_bytes()));
+    format!("AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}", config.access_key, scope, signed_headers, signature)
+}
+
+fn object_path(bucket: &str, key: &str) -> String {
+    format!("/{}/{}", uri_encode(bucket, false), uri_encode(key, true))
+}
+
+// Sorted by name and encoded the way signing needs it, which S3 is happy to be sent as well.
+fn query_string(query: &[(&str, &str)]) -> Option<String> {
+    if query.is_empty() {
+        return None;
+    }
+    let mut pairs: Vec<(String, String)> = query.iter().map(|(name, value)| (uri_encode(name, false), uri_encode(value, false))).collect();
+    pairs.sort();
+    Some(pairs.iter().map(|(name, value)| format!("{}={}", name, value)).collect::<Vec<_>>().join("&"))
+}
+
+// Percent-encodes everything but the unreserved characters, and slashes too unless they separate the parts of a key.
+fn uri_encode(value: &str, keep_slashes: bool) -> String {
+    value.bytes().map(|byte| match byte {
+        b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (byte as char).to_string(),
+        b'/' if keep_slashes => "/".to_string(),
+        _ => format!("%{:02X}", byte),
+    }).collect()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// The names backup::file_name gives, so rotation never touches anything else someone keeps next to the backups.
+fn is_backup_name(name: &str) -> bool {
+    name.starts_with("movies-") && name.ends_with(".json") && !name.contains('/')
+}
+
+fn expect_success(response: HttpResponse) -> Result<Vec<u8>, CallError> {
+    if !(200..300).contains(&response.status) {
+        return Err(resilience::status_error(response.status));
+    }
+    Ok(response.body)
+}
+
+// The text of every <name> element, which is as much XML as a ListObjectsV2 response needs.
+fn elements(xml: &str, name: &str) -> Vec<String> {
+    let (open, close) = (format!("<{}>", name), format!("</{}>", name));
+    let mut found = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find(&open) {
+        rest = &rest[start + open.len()..];
+        let Some(end) = rest.find(&close) else { break };
+        found.push(unescape(&rest[..end]));
+        rest = &rest[end + close.len()..];
+    }
+    found
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<").replace("&gt;", ">").replace("&quot;", "\"").replace("&apos;", "'").replace("&amp;", "&")
+}
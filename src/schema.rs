@@ -0,0 +1,132 @@
+use std::{io, path::Path, sync::atomic::{AtomicBool, Ordering}};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use tracing::warn;
+
+// Versions of what the stores keep on disk, and the migrations between them. Each kind of file has a schema: the
+// version it's written in is stored with it, and a migration takes one stored record (a whole snapshot, or one line of
+// a log) from the version before to the next. Records written by an older version are brought up to date as they're
+// read, and rewritten in the current version by `syndica-rust migrate` or whenever the store next rewrites them.
+// Anything written by a newer version is refused, since there's no telling what it means, and so is anything older
+// when --auto-migrate is off.
+//
+// Logs are only ever appended to, so their version is stamped with a line of its own, {"schema": 2}, which every line
+// after it is written in. Lines before the first stamp are version 1.
+
+static AUTO_MIGRATE: AtomicBool = AtomicBool::new(true);
+
+pub fn set_auto_migrate(auto_migrate: bool) {
+    AUTO_MIGRATE.store(auto_migrate, Ordering::Relaxed);
+}
+
+pub fn auto_migrate() -> bool {
+    AUTO_MIGRATE.load(Ordering::Relaxed)
+}
+
+pub struct Migration {
+    // The version this migrates to, from the one before it.
+    pub to: u32,
+    pub description: &'static str,
+    pub apply: fn(&mut Value) -> Result<(), String>,
+}
+
+pub struct Schema {
+    // What kind of file it is, for messages.
+    pub name: &'static str,
+    pub first: u32,
+    // In order, one for each version after the first.
+    pub migrations: &'static [Migration],
+}
+
+impl Schema {
+    pub fn current(&self) -> u32 {
+        self.migrations.last().map_or(self.first, |migration| migration.to)
+    }
+
+    // Whether the file at `path`, written in `found`, may be opened.
+    pub fn check(&self, path: &Path, found: u32) -> io::Result<()> {
+        let current = self.current();
+        if found > current {
+            return Err(invalid(format!("{} {} is in schema version {}, but this version only knows up to {}, it was written by a newer one",
+                self.name, path.display(), found, current)));
+        }
+        if found < current && !auto_migrate() {
+            return Err(invalid(format!("{} {} is in schema version {} rather than {}, run `syndica-rust migrate` or turn on auto_migrate",
+                self.name, path.display(), found, current)));
+        }
+        Ok(())
+    }
+
+    // Brings a record written in `found` up to the current version.
+    pub fn upgrade(&self, found: u32, record: &mut Value) -> Result<(), String> {
+        for migration in self.migrations.iter().filter(|migration| migration.to > found) {
+            (migration.apply)(record).map_err(|e| format!("migrating to schema version {} ({}): {}", migration.to, migration.description, e))?;
+        }
+        Ok(())
+    }
+
+    // The line that stamps a log with the current version.
+    pub fn stamp(&self) -> Vec<u8> {
+        let mut line = json!({ "schema": self.current() }).to_string().into_bytes();
+        line.push(b'\n');
+        line
+    }
+}
+
+// Passes each record of a log in `schema` to `apply`, brought up to the current version, and returns how many bytes of
+// it are intact and the version it was last stamped with. A torn last line means the process died while appending it,
+// before the write was acknowledged, so it's dropped.
+pub fn read_log<T: DeserializeOwned>(schema: &Schema, path: &Path, contents: &[u8], mut apply: impl FnMut(T)) -> io::Result<(u64, u32)> {
+    let current = schema.current();
+    let mut version = schema.first;
+    let mut valid_len = 0;
+    let mut lines = contents.split_inclusive(|byte| *byte == b'\n').peekable();
+    while let Some(line) = lines.next() {
+        // Most lines are in the current version, and can skip the detour through a Value.
+        if version == current
+            && let Ok(record) = serde_json::from_slice::<T>(line) {
+            apply(record);
+            valid_len += line.len() as u64;
+            continue;
+        }
+        let mut value = match serde_json::from_slice::<Value>(line) {
+            Ok(value) => value,
+            Err(e) if lines.peek().is_none() => {
+                warn!("Dropping incomplete last line of {} {}: {}", schema.name, path.display(), e);
+                break;
+            },
+            Err(e) => return Err(invalid(e.to_string())),
+        };
+        if let Some(stamp) = read_stamp(&value) {
+            if stamp > current {
+                return Err(invalid(format!("{} {} has lines in schema version {}, but this version only knows up to {}, they were written by a newer one",
+                    schema.name, path.display(), stamp, current)));
+            }
+            version = stamp;
+        }
+        else {
+            schema.upgrade(version, &mut value).map_err(invalid)?;
+            apply(serde_json::from_value(value).map_err(|e| invalid(e.to_string()))?);
+        }
+        valid_len += line.len() as u64;
+    }
+    if valid_len > 0 {
+        schema.check(path, version)?;
+    }
+    Ok((valid_len, version))
+}
+
+// The version a log line stamps the lines after it with, if it's a stamp.
+fn read_stamp(line: &Value) -> Option<u32> {
+    let object = line.as_object().filter(|object| object.len() == 1)?;
+    object.get("schema")?.as_u64().and_then(|version| u32::try_from(version).ok())
+}
+
+// A migration that leaves records as they are, for versions that only add to what's stored.
+pub fn unchanged(_: &mut Value) -> Result<(), String> {
+    Ok(())
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
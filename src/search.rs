@@ -0,0 +1,167 @@
+use std::collections::HashMap;
+use serde::Serialize;
+
+use crate::model::Movie;
+use crate::title;
+
+// Full-text search over movie names for GET /movies/search. Names and queries are split into words folded by
+// title::fold, so "amelie" finds "Amélie", and movies are ranked with BM25, so rare words count for more than common
+// ones like "the" and a word in a short name counts for more than in a long one. A movie's titles in other languages
+// are searched as well as its name, and it scores what the best of them does. A query word also matches longer words it starts with, at a discount, so
+// searches work while they're still being typed. There's no index: every search scores every movie, which is fine for
+// the catalog sizes this serves.
+
+// The usual BM25 parameters: how quickly repeats of a word stop adding to the score, and how much the name's length
+// counts.
+const K1: f64 = 1.2;
+const B: f64 = 0.75;
+// What a word the query word is only the start of counts for, against an exact match.
+const PREFIX_WEIGHT: f64 = 0.5;
+
+pub const HIGHLIGHT_START: &str = "<em>";
+pub const HIGHLIGHT_END: &str = "</em>";
+
+#[derive(Debug, Clone, PartialEq)]
+struct Token {
+    term: String,
+    // Byte range in the original text.
+    start: usize,
+    end: usize,
+}
+
+// Words are runs of letters, combining marks and digits. Everything else, punctuation included, separates them.
+fn tokenize(text: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    for (i, c) in text.char_indices().chain([(text.len(), ' ')]) {
+        match (c.is_alphanumeric() || ('\u{300}'..='\u{36f}').contains(&c), start) {
+            (true, None) => start = Some(i),
+            (false, Some(begin)) => {
+                tokens.push(Token { term: title::fold(&text[begin..i]), start: begin, end: i });
+                start = None;
+            },
+            _ => {},
+        }
+    }
+    tokens
+}
+
+// The distinct words of a query, which is empty if it has none.
+pub fn query_terms(query: &str) -> Vec<String> {
+    let mut terms: Vec<String> = tokenize(query).into_iter().map(|token| token.term).collect();
+    terms.sort();
+    terms.dedup();
+    terms
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchHit {
+    pub movie: Movie,
+    pub score: f64,
+    // The name, or the title that matched best, with every matched word wrapped in <em></em>, and the rest HTML-escaped,
+    // so it can go straight into a page.
+    pub highlighted: String,
+    // The language of the title that matched best, left out when it was the name.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+}
+
+// The name, or one of the titles under its language.
+struct Variant<'a> {
+    language: Option<&'a str>,
+    text: &'a str,
+    tokens: Vec<Token>,
+}
+
+fn variants(movie: &Movie) -> Vec<Variant<'_>> {
+    let titles = movie.titles.iter().map(|(language, title)| (Some(language.as_str()), title.as_str()));
+    [(None, movie.name.as_str())].into_iter().chain(titles)
+        .map(|(language, text)| Variant { language, text, tokens: tokenize(text) })
+        .collect()
+}
+
+// How well each token of a name matches a term, 0 for not at all.
+fn match_weight(token: &Token, term: &str) -> f64 {
+    if token.term == term {
+        1.0
+    }
+    else if token.term.starts_with(term) {
+        PREFIX_WEIGHT
+    }
+    else {
+        0.0
+    }
+}
+
+// Every movie that matches at least one of the terms, best first. Movies that score the same stay in id order, so
+// paging through the results is stable.
+pub fn search(movies: Vec<Movie>, terms: &[String]) -> Vec<SearchHit> {
+    let names: Vec<Vec<Variant>> = movies.iter().map(variants).collect();
+    let count = names.len() as f64;
+    let all_lens: Vec<usize> = names.iter().flatten().map(|variant| variant.tokens.len()).collect();
+    let average_len = all_lens.iter().sum::<usize>() as f64 / (all_lens.len() as f64).max(1.0);
+    // In how many movies each term appears, in any of their names, for its inverse document frequency.
+    let mut frequencies: HashMap<&str, f64> = HashMap::new();
+    for variants in &names {
+        for term in terms {
+            if variants.iter().any(|variant| variant.tokens.iter().any(|token| match_weight(token, term) > 0.0)) {
+                *frequencies.entry(term.as_str()).or_default() += 1.0;
+            }
+        }
+    }
+    let score = |tokens: &[Token]| -> f64 {
+        let length_norm = 1.0 - B + B * tokens.len() as f64 / average_len.max(1.0);
+        terms.iter().map(|term| {
+            let tf: f64 = tokens.iter().map(|token| match_weight(token, term)).sum();
+            if tf == 0.0 {
+                return 0.0;
+            }
+            let df = frequencies[term.as_str()];
+            let idf = (1.0 + (count - df + 0.5) / (df + 0.5)).ln();
+            idf * tf * (K1 + 1.0) / (tf + K1 * length_norm)
+        }).sum()
+    };
+
+    let mut hits: Vec<SearchHit> = Vec::new();
+    for (variants, movie) in names.iter().zip(&movies) {
+        // The name wins a tie, and then the titles in language order.
+        let best = variants.iter()
+            .map(|variant| (score(&variant.tokens), variant))
+            .reduce(|best, other| if other.0 > best.0 { other } else { best });
+        let Some((score, variant)) = best.filter(|best| best.0 > 0.0) else {
+            continue;
+        };
+        let highlighted = highlight(variant.text, &variant.tokens, terms);
+        hits.push(SearchHit { movie: movie.clone(), score, highlighted, language: variant.language.map(str::to_string) });
+    }
+    // A stable sort, and the store lists movies in id order.
+    hits.sort_by(|a, b| b.score.total_cmp(&a.score));
+    hits
+}
+
+fn highlight(name: &str, tokens: &[Token], terms: &[String]) -> String {
+    let mut highlighted = String::with_capacity(name.len() + 16);
+    let mut written = 0;
+    for token in tokens.iter().filter(|token| terms.iter().any(|term| match_weight(token, term) > 0.0)) {
+        escape_html(&name[written..token.start], &mut highlighted);
+        highlighted.push_str(HIGHLIGHT_START);
+        escape_html(&name[token.start..token.end], &mut highlighted);
+        highlighted.push_str(HIGHLIGHT_END);
+        written = token.end;
+    }
+    escape_html(&name[written..], &mut highlighted);
+    highlighted
+}
+
+fn escape_html(text: &str, out: &mut String) {
+    for c in text.chars() {
+        match c {
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '&' => out.push_str("&amp;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+}
@@ -0,0 +1,78 @@
+use std::{path::Path, sync::atomic::{AtomicBool, Ordering}};
+use tracing::{debug, info, warn};
+
+use crate::audit;
+use crate::model::NewMovie;
+use crate::state::StateWrapper;
+use crate::store::StoreError;
+use crate::validation::validate_movie;
+
+// Preloads a catalog at startup from --seed, a JSON array of movies as POST /movie takes them. The file is read before
+// the server starts listening, so one that can't be read or parsed stops it from starting at all, and the movies go in
+// afterwards, with /readyz answering 503 until they have, so no traffic is sent to an instance that's still filling up.
+// Movies that are already there, from the store's own files or earlier in the seed, are reported as duplicates and
+// left as they are, so seeding a store that survives restarts again every time is harmless.
+
+static PENDING: AtomicBool = AtomicBool::new(false);
+
+// Whether seeding is still going, for /readyz.
+pub fn is_pending() -> bool {
+    PENDING.load(Ordering::Relaxed)
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct SeedReport {
+    pub inserted: usize,
+    // Ids of the movies that were there already.
+    pub duplicates: Vec<String>,
+    // Index in the file of each entry that couldn't be added, and why.
+    pub failures: Vec<(usize, String)>,
+}
+
+pub fn read(path: &Path) -> Result<Vec<serde_json::Value>, String> {
+    let contents = std::fs::read(path).map_err(|e| e.to_string())?;
+    serde_json::from_slice(&contents).map_err(|e| format!("expected a JSON array of movies: {}", e))
+}
+
+// Adds each entry in turn, carrying on past the ones that can't be.
+pub async fn load(store: &StateWrapper, entries: Vec<serde_json::Value>) -> SeedReport {
+    let mut report = SeedReport::default();
+    for (index, entry) in entries.into_iter().enumerate() {
+        let movie = match serde_json::from_value::<NewMovie>(entry) {
+            Ok(new_movie) => new_movie.into_movie(),
+            Err(e) => {
+                report.failures.push((index, format!("Invalid movie: {}", e)));
+                continue;
+            },
+        };
+        if let Err(errors) = validate_movie(&movie) {
+            let errors: Vec<String> = errors.iter().map(|error| format!("{}: {}", error.field, error.message)).collect();
+            report.failures.push((index, errors.join("; ")));
+            continue;
+        }
+        let id = movie.id.clone();
+        match audit::acting_as("seed".to_string(), store.insert(movie)).await {
+            Ok(()) => report.inserted += 1,
+            Err(StoreError::AlreadyExists(_)) => report.duplicates.push(id),
+            Err(e) => report.failures.push((index, format!("{:?}", e))),
+        }
+    }
+    report
+}
+
+// Loads `entries` in the background, with the server unready until they're all in.
+pub fn start(store: StateWrapper, entries: Vec<serde_json::Value>) {
+    PENDING.store(true, Ordering::Relaxed);
+    tokio::spawn(async move {
+        let count = entries.len();
+        let report = load(&store, entries).await;
+        for (index, error) in &report.failures {
+            warn!("Seed entry {} wasn't added: {}", index, error);
+        }
+        if !report.duplicates.is_empty() {
+            debug!("Seed movies that were already there: {}", report.duplicates.join(", "));
+        }
+        info!("Seeded {} of {} movies, {} were already there and {} failed", report.inserted, count, report.duplicates.len(), report.failures.len());
+        PENDING.store(false, Ordering::Relaxed);
+    });
+}
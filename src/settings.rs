@@ -0,0 +1,137 @@
+use std::{sync::{atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering}, Arc, Mutex, RwLock, RwLockReadGuard}, time::Duration};
+
+use crate::auth::Policy;
+use crate::body_limit::{DEFAULT_MAX_BODY_BYTES, DEFAULT_MAX_UPLOAD_BYTES};
+use crate::cors::CorsConfig;
+use crate::duplicates::DuplicatePolicy;
+use crate::jwt::JwtConfig;
+use crate::rate_limit::{Limiter, RateLimit};
+use crate::timeout::DEFAULT_TIMEOUT;
+
+// What the layers and handlers of a router go by: who may do what, how many requests it takes and how big, CORS and
+// what to do about duplicate movies. build_router is given them, so two routers in one process can each have their
+// own. Clones share the same settings, which is how a reload reaches a router that's already serving.
+//
+// Settings of the process as a whole, like logging, and of the jobs that run outside of requests stay in their own
+// modules. Which tenants there are and the quotas are the store's, see tenant.rs and quota.rs.
+#[derive(Clone, Default)]
+pub struct Settings(Arc<Shared>);
+
+struct Shared {
+    policy: RwLock<Policy>,
+    limiter: Mutex<Limiter>,
+    // 0 for no limit.
+    max_in_flight: AtomicUsize,
+    in_flight: AtomicUsize,
+    // In milliseconds, 0 for no limit.
+    timeout_millis: AtomicU64,
+    max_body_bytes: AtomicUsize,
+    max_upload_bytes: AtomicUsize,
+    cors: RwLock<Option<CorsConfig>>,
+    reject_duplicates: AtomicBool,
+}
+
+impl Default for Shared {
+    fn default() -> Shared {
+        Shared {
+            policy: RwLock::default(),
+            limiter: Mutex::default(),
+            max_in_flight: AtomicUsize::new(0),
+            in_flight: AtomicUsize::new(0),
+            timeout_millis: AtomicU64::new(DEFAULT_TIMEOUT.as_millis() as u64),
+            max_body_bytes: AtomicUsize::new(DEFAULT_MAX_BODY_BYTES),
+            max_upload_bytes: AtomicUsize::new(DEFAULT_MAX_UPLOAD_BYTES),
+            cors: RwLock::new(None),
+            reject_duplicates: AtomicBool::new(false),
+        }
+    }
+}
+
+impl Settings {
+    pub fn set_api_keys(&self, hashes: Vec<[u8; 32]>) {
+        self.0.policy.write().unwrap().api_keys = hashes;
+    }
+
+    pub fn set_tenant_keys(&self, keys: Vec<([u8; 32], String)>) {
+        self.0.policy.write().unwrap().tenant_keys = keys;
+    }
+
+    pub fn set_jwt(&self, jwt: Option<JwtConfig>) {
+        self.0.policy.write().unwrap().jwt = jwt;
+    }
+
+    pub(crate) fn policy(&self) -> RwLockReadGuard<'_, Policy> {
+        self.0.policy.read().unwrap()
+    }
+
+    // None turns limiting off. Clients keep what they have left of their buckets, up to the new burst.
+    pub fn set_rate_limit(&self, limit: Option<RateLimit>) {
+        let mut limiter = self.0.limiter.lock().unwrap();
+        limiter.limit = limit;
+        if limit.is_none() {
+            limiter.buckets.clear();
+        }
+    }
+
+    pub(crate) fn limiter(&self) -> &Mutex<Limiter> {
+        &self.0.limiter
+    }
+
+    // 0 for no limit.
+    pub fn set_max_in_flight(&self, max: usize) {
+        self.0.max_in_flight.store(max, Ordering::Relaxed);
+    }
+
+    pub(crate) fn max_in_flight(&self) -> usize {
+        self.0.max_in_flight.load(Ordering::Relaxed)
+    }
+
+    // Requests being handled right now, for /metrics. Streaming responses only count until their headers are sent.
+    pub fn in_flight(&self) -> usize {
+        self.0.in_flight.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn in_flight_count(&self) -> &AtomicUsize {
+        &self.0.in_flight
+    }
+
+    // Zero for no limit.
+    pub fn set_timeout(&self, timeout: Duration) {
+        self.0.timeout_millis.store(timeout.as_millis() as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn timeout(&self) -> Duration {
+        Duration::from_millis(self.0.timeout_millis.load(Ordering::Relaxed))
+    }
+
+    // `max_body` for most routes, `max_upload` for the ones given BodyLimit::Upload.
+    pub fn set_body_limits(&self, max_body: usize, max_upload: usize) {
+        self.0.max_body_bytes.store(max_body, Ordering::Relaxed);
+        self.0.max_upload_bytes.store(max_upload, Ordering::Relaxed);
+    }
+
+    pub(crate) fn max_body_bytes(&self) -> usize {
+        self.0.max_body_bytes.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn max_upload_bytes(&self) -> usize {
+        self.0.max_upload_bytes.load(Ordering::Relaxed)
+    }
+
+    // None turns CORS off.
+    pub fn set_cors(&self, config: Option<CorsConfig>) {
+        *self.0.cors.write().unwrap() = config;
+    }
+
+    pub(crate) fn cors(&self) -> Option<CorsConfig> {
+        self.0.cors.read().unwrap().clone()
+    }
+
+    pub fn set_duplicates(&self, policy: DuplicatePolicy) {
+        self.0.reject_duplicates.store(policy == DuplicatePolicy::Reject, Ordering::Relaxed);
+    }
+
+    pub fn duplicates(&self) -> DuplicatePolicy {
+        if self.0.reject_duplicates.load(Ordering::Relaxed) { DuplicatePolicy::Reject } else { DuplicatePolicy::Warn }
+    }
+}
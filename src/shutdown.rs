@@ -0,0 +1,106 @@
+use std::{future::Future, io, sync::{atomic::{AtomicI32, Ordering}, LazyLock}};
+use tokio::sync::watch;
+use tracing::warn;
+
+// Write end of the self-pipe. Signal handlers can't do much safely, so all on_signal does is write the signal number
+// here and a regular thread picks it up from the read end.
+static SIGNAL_PIPE: AtomicI32 = AtomicI32::new(-1);
+
+extern "C" fn on_signal(signal: libc::c_int) {
+    let byte = signal as u8;
+    unsafe {
+        libc::write(SIGNAL_PIPE.load(Ordering::Relaxed), &byte as *const u8 as *const libc::c_void, 1);
+    }
+}
+
+// Installs SIGINT and SIGTERM handlers and returns a future that resolves with the name of the first one received.
+// A second signal exits straight away, for when draining gets stuck.
+pub fn shutdown_signal() -> io::Result<impl Future<Output = &'static str>> {
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let [read_fd, write_fd] = fds;
+    SIGNAL_PIPE.store(write_fd, Ordering::Relaxed);
+
+    for signal in [libc::SIGINT, libc::SIGTERM] {
+        catch(signal)?;
+    }
+
+    let (sender, receiver) = tokio::sync::oneshot::channel();
+    std::thread::Builder::new().name("signals".to_string()).spawn(move || {
+        let mut sender = Some(sender);
+        loop {
+            let mut byte = 0u8;
+            let read = unsafe { libc::read(read_fd, &mut byte as *mut u8 as *mut libc::c_void, 1) };
+            if read != 1 {
+                if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return;
+            }
+            if libc::c_int::from(byte) == libc::SIGHUP {
+                HANGUPS.send_replace(());
+                continue;
+            }
+            let name = signal_name(byte.into());
+            match sender.take() {
+                Some(sender) => { let _ = sender.send(name); },
+                None => {
+                    warn!("Received {} again, exiting without waiting for requests to finish", name);
+                    std::process::exit(128 + byte as i32);
+                },
+            }
+        }
+    })?;
+
+    Ok(async move { receiver.await.unwrap_or("a closed signal pipe") })
+}
+
+fn catch(signal: libc::c_int) -> io::Result<()> {
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = on_signal as *const () as libc::sighandler_t;
+        action.sa_flags = libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+        if libc::sigaction(signal, &action, std::ptr::null_mut()) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+// Bumped on every SIGHUP, once catch_hangups has been called.
+static HANGUPS: LazyLock<watch::Sender<()>> = LazyLock::new(|| watch::channel(()).0);
+
+// Has SIGHUP tell whatever's watching hangups() to reload, rather than ending the process as it otherwise would. Goes
+// through the same pipe as SIGINT and SIGTERM, so shutdown_signal has to have been called first.
+pub fn catch_hangups() -> io::Result<()> {
+    catch(libc::SIGHUP)
+}
+
+// Changes on every SIGHUP from now on.
+pub fn hangups() -> watch::Receiver<()> {
+    HANGUPS.subscribe()
+}
+
+fn signal_name(signal: libc::c_int) -> &'static str {
+    match signal {
+        libc::SIGINT => "SIGINT",
+        libc::SIGTERM => "SIGTERM",
+        _ => "signal",
+    }
+}
+
+// Set once the server starts draining, for responses that never finish on their own, like /movies/events, so that
+// they end rather than holding up shutdown until the timeout.
+static DRAINING: LazyLock<watch::Sender<bool>> = LazyLock::new(|| watch::channel(false).0);
+
+pub fn start_draining() {
+    DRAINING.send_replace(true);
+}
+
+// Resolves once start_draining has been called, straight away if it already has.
+pub async fn draining() {
+    let _ = DRAINING.subscribe().wait_for(|draining| *draining).await;
+}
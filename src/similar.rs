@@ -0,0 +1,105 @@
+use serde::Serialize;
+
+use crate::model::Movie;
+use crate::title;
+
+// Finds movies whose names are close to a given one, for GET /movies/similar, so that importers can catch "The Matr1x
+// (1999)" before it goes in next to "The Matrix". Names are compared after normalizing away case, accents, punctuation,
+// a leading "The" and a trailing "(year)", mostly by Jaro-Winkler similarity, which forgives typos and favours names
+// that agree at the start.
+
+// Below this a match is more likely a different movie than a typo of the same one.
+pub const DEFAULT_THRESHOLD: f64 = 0.85;
+// A remake often has the same name, so a match from another year counts for less. The year comes from the year
+// parameter or a trailing "(year)" in the name.
+const OTHER_YEAR_FACTOR: f64 = 0.9;
+// How much a shared start counts for in Jaro-Winkler, and over how many characters. The usual values.
+const WINKLER_SCALE: f64 = 0.1;
+const WINKLER_PREFIX: usize = 4;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SimilarMovie {
+    pub movie: Movie,
+    // 1 for names that are the same once normalized.
+    pub score: f64,
+    // Levenshtein distance between the normalized names, i.e. how many characters differ.
+    pub distance: usize,
+}
+
+// The name as title::normalize has it, and the year if the name ends in one in parentheses.
+pub fn normalize(name: &str) -> (String, Option<u16>) {
+    let trimmed = name.trim_end();
+    let year = trimmed.strip_suffix(')')
+        .and_then(|rest| rest.rsplit_once('('))
+        .and_then(|(before, year)| Some((before, year.trim().parse::<u16>().ok()?)));
+    let (name, year) = match year {
+        Some((before, year)) => (before, Some(year)),
+        None => (trimmed, None),
+    };
+    (title::normalize(name), year)
+}
+
+// Every movie at least `threshold` similar to `name`, most similar first and then in id order.
+pub fn find_similar(movies: Vec<Movie>, name: &str, year: Option<u16>, threshold: f64) -> Vec<SimilarMovie> {
+    let (wanted, name_year) = normalize(name);
+    let wanted: Vec<char> = wanted.chars().collect();
+    let year = year.or(name_year);
+    let mut similar: Vec<SimilarMovie> = movies.into_iter().filter_map(|movie| {
+        let candidate: Vec<char> = normalize(&movie.name).0.chars().collect();
+        let mut score = jaro_winkler(&wanted, &candidate);
+        if year.is_some_and(|year| year != movie.year) {
+            score *= OTHER_YEAR_FACTOR;
+        }
+        (score >= threshold).then(|| SimilarMovie { distance: levenshtein(&wanted, &candidate), movie, score })
+    }).collect();
+    // Stable, so equal scores stay in the id order the store lists them in.
+    similar.sort_by(|a, b| b.score.total_cmp(&a.score));
+    similar
+}
+
+pub fn levenshtein(a: &[char], b: &[char]) -> usize {
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    for (i, a_char) in a.iter().enumerate() {
+        current[0] = i + 1;
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != b_char);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+pub fn jaro_winkler(a: &[char], b: &[char]) -> f64 {
+    let jaro = jaro(a, b);
+    let prefix = a.iter().zip(b).take(WINKLER_PREFIX).take_while(|(a, b)| a == b).count();
+    jaro + prefix as f64 * WINKLER_SCALE * (1.0 - jaro)
+}
+
+fn jaro(a: &[char], b: &[char]) -> f64 {
+    if a.is_empty() && b.is_empty() {
+        return 1.0;
+    }
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+    // Characters only match if they're at most this far apart.
+    let window = (a.len().max(b.len()) / 2).saturating_sub(1);
+    let mut b_matched = vec![false; b.len()];
+    let mut a_matches = Vec::new();
+    for (i, a_char) in a.iter().enumerate() {
+        let range = i.saturating_sub(window)..(i + window + 1).min(b.len());
+        if let Some(j) = range.into_iter().find(|&j| !b_matched[j] && b[j] == *a_char) {
+            b_matched[j] = true;
+            a_matches.push(*a_char);
+        }
+    }
+    if a_matches.is_empty() {
+        return 0.0;
+    }
+    let b_matches = b.iter().zip(&b_matched).filter(|(_, matched)| **matched).map(|(c, _)| c);
+    let transpositions = a_matches.iter().zip(b_matches).filter(|(a, b)| a != b).count() / 2;
+    let matches = a_matches.len() as f64;
+    (matches / a.len() as f64 + matches / b.len() as f64 + (matches - transpositions as f64) / matches) / 3.0
+}
@@ -0,0 +1,315 @@
+use std::{io, path::{Path, PathBuf}, sync::{atomic::{AtomicBool, Ordering}, Arc}, time::Duration};
+use tracing::{error, info, info_span, Instrument};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use time::OffsetDateTime;
+use tokio::sync::{broadcast, Mutex};
+
+use crate::changes::{self, ChangeLog, ChangePage, CursorExpired, Since};
+use crate::events::MovieEvent;
+use crate::labels::Label;
+use crate::model::{Link, Movie, MoviePatch, StoredMovie, StoredTrashed, StoredUser, Trashed, User, UserChange};
+use crate::schema::{self, Migration, Schema};
+use crate::store::{Backup, MemoryMovieStore, MovieFilter, MovieStore, Precondition, StoreError, StoreFuture};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SnapshotConfig {
+    pub path: PathBuf,
+    // How often the snapshot is rewritten, if anything changed.
+    pub interval: Duration,
+}
+
+// What the file holds, in the current schema version. See SCHEMA for the older ones.
+#[derive(Debug, Serialize, Deserialize)]
+struct SnapshotFile {
+    schema_version: u32,
+    movies: Vec<StoredMovie>,
+    users: Vec<StoredUser>,
+    #[serde(default)]
+    links: Vec<Link>,
+    #[serde(default)]
+    trash: Vec<StoredTrashed>,
+}
+
+// Version 1 snapshots are just the array of movies, from before there were users. Version 2 added the users, and later
+// the links and the trash, which are left out of the ones from before them. Version 3 says which version it is.
+pub static SCHEMA: Schema = Schema {
+    name: "Snapshot",
+    first: 1,
+    migrations: &[
+        Migration { to: 2, description: "movies and users in tables of their own", apply: |snapshot| {
+            let movies = snapshot.take();
+            *snapshot = json!({ "movies": movies, "users": [] });
+            Ok(())
+        } },
+        Migration { to: 3, description: "snapshots say their schema version", apply: schema::unchanged },
+    ],
+};
+
+// Keeps everything in memory like MemoryMovieStore, but periodically writes the whole table out to a JSON file
+// and loads it back on startup, so data survives a restart (minus whatever changed since the last flush).
+pub struct SnapshotMovieStore {
+    inner: MemoryMovieStore,
+    path: PathBuf,
+    // Set by every successful write, so we don't rewrite the file when nothing changed.
+    dirty: AtomicBool,
+    // Keeps the periodic flush and the shutdown flush from writing the temp file at the same time.
+    flush_lock: Mutex<()>,
+}
+
+impl SnapshotMovieStore {
+    pub async fn open(path: PathBuf) -> io::Result<SnapshotMovieStore> {
+        let (movies, users, links, trash, outdated) = load_snapshot(&path).await?;
+        info!("Loaded {} movies, {} users, {} links and {} movies in the trash from snapshot {}", movies.len(), users.len(), links.len(), trash.len(), path.display());
+        if outdated {
+            info!("Snapshot {} will be written in schema version {} from now on", path.display(), SCHEMA.current());
+        }
+        Ok(SnapshotMovieStore {
+            inner: MemoryMovieStore::from_tables(movies, users, links, trash).with_change_log(ChangeLog::open(&changes::path_beside(&path))?),
+            path,
+            // An older snapshot gets written out in the current version at the next flush.
+            dirty: AtomicBool::new(outdated),
+            flush_lock: Mutex::new(()),
+        })
+    }
+
+    pub async fn flush(&self) -> io::Result<()> {
+        let _guard = self.flush_lock.lock().await;
+        if !self.dirty.swap(false, Ordering::AcqRel) {
+            return Ok(());
+        }
+        let Backup { movies, users, links, trash } = self.inner.backup().await;
+        let span = info_span!("snapshot.flush", movies = movies.len(), users = users.len(), links = links.len(), trash = trash.len());
+        let result = write_snapshot(&self.path, movies, users, links, trash).instrument(span).await;
+        if result.is_err() {
+            // Try again next time around.
+            self.dirty.store(true, Ordering::Release);
+        }
+        result
+    }
+
+    // Flushes every `interval` until the process exits.
+    pub fn spawn_flush_task(self: &Arc<Self>, interval: Duration) {
+        let store = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                if let Err(e) = store.flush().await {
+                    error!("Failed to write snapshot to {}: {}", store.path.display(), e);
+                }
+            }
+        });
+    }
+
+    fn mark_dirty<T>(&self, result: Result<T, StoreError>) -> Result<T, StoreError> {
+        if result.is_ok() {
+            self.dirty.store(true, Ordering::Release);
+        }
+        result
+    }
+}
+
+// Also says whether the snapshot was in an older schema version, and so should be written out again.
+async fn load_snapshot(path: &Path) -> io::Result<(Vec<Movie>, Vec<User>, Vec<Link>, Vec<Trashed>, bool)> {
+    let contents = match tokio::fs::read(path).await {
+        Ok(contents) => contents,
+        // No snapshot yet, first run.
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok((Vec::new(), Vec::new(), Vec::new(), Vec::new(), false)),
+        Err(e) => return Err(e),
+    };
+    let mut snapshot: Value = serde_json::from_slice(&contents)?;
+    let found = match &snapshot {
+        Value::Array(_) => 1,
+        snapshot => snapshot.get("schema_version").map_or(Some(2), |version| version.as_u64().and_then(|version| u32::try_from(version).ok()))
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "schema_version isn't a version number"))?,
+    };
+    SCHEMA.check(path, found)?;
+    SCHEMA.upgrade(found, &mut snapshot).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    if let Some(object) = snapshot.as_object_mut() {
+        object.insert("schema_version".to_string(), json!(SCHEMA.current()));
+    }
+    let SnapshotFile { movies, users, links, trash, .. } = serde_json::from_value(snapshot)?;
+    let trash = trash.into_iter().map(Trashed::from).collect();
+    let outdated = found < SCHEMA.current();
+    Ok((movies.into_iter().map(Movie::from).collect(), users.into_iter().map(User::from).collect(), links, trash, outdated))
+}
+
+async fn write_snapshot(path: &Path, movies: Vec<Movie>, users: Vec<User>, links: Vec<Link>, trash: Vec<Trashed>) -> io::Result<()> {
+    // Write next to the real file and rename over it, so a crash mid-write never leaves a truncated snapshot.
+    let temp_path = temp_path(path);
+    let movies = movies.into_iter().map(StoredMovie::from).collect();
+    let users = users.into_iter().map(StoredUser::from).collect();
+    let trash = trash.into_iter().map(StoredTrashed::from).collect();
+    let contents = serde_json::to_vec_pretty(&SnapshotFile { schema_version: SCHEMA.current(), movies, users, links, trash })?;
+    tokio::fs::write(&temp_path, contents).await?;
+    tokio::fs::rename(&temp_path, path).await
+}
+
+fn temp_path(path: &Path) -> PathBuf {
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+    PathBuf::from(temp_path)
+}
+
+impl MovieStore for SnapshotMovieStore {
+    fn get<'a>(&'a self, id: &'a str) -> StoreFuture<'a, Option<Movie>> {
+        self.inner.get(id)
+    }
+
+    fn insert(&self, movie: Movie) -> StoreFuture<'_, Result<(), StoreError>> {
+        Box::pin(async move {
+            self.mark_dirty(self.inner.insert(movie).await)
+        })
+    }
+
+    fn upsert(&self, movie: Movie) -> StoreFuture<'_, Result<bool, StoreError>> {
+        Box::pin(async move {
+            self.mark_dirty(self.inner.upsert(movie).await)
+        })
+    }
+
+    fn put(&self, movie: Movie) -> StoreFuture<'_, Result<bool, StoreError>> {
+        Box::pin(async move {
+            self.mark_dirty(self.inner.put(movie).await)
+        })
+    }
+
+    fn update_if<'a>(&'a self, movie: Movie, precondition: Precondition<'a>) -> StoreFuture<'a, Result<(), StoreError>> {
+        Box::pin(async move {
+            self.mark_dirty(self.inner.update_if(movie, precondition).await)
+        })
+    }
+
+    fn patch<'a>(&'a self, id: &'a str, patch: MoviePatch) -> StoreFuture<'a, Result<Movie, StoreError>> {
+        Box::pin(async move {
+            self.mark_dirty(self.inner.patch(id, patch).await)
+        })
+    }
+
+    fn delete_if<'a>(&'a self, id: &'a str, precondition: Precondition<'a>) -> StoreFuture<'a, Result<Movie, StoreError>> {
+        Box::pin(async move {
+            self.mark_dirty(self.inner.delete_if(id, precondition).await)
+        })
+    }
+
+    fn trash(&self) -> StoreFuture<'_, Vec<Trashed>> {
+        self.inner.trash()
+    }
+
+    fn restore<'a>(&'a self, id: &'a str) -> StoreFuture<'a, Result<Movie, StoreError>> {
+        Box::pin(async move {
+            self.mark_dirty(self.inner.restore(id).await)
+        })
+    }
+
+    fn purge(&self, deleted_before: OffsetDateTime) -> StoreFuture<'_, Result<Vec<String>, StoreError>> {
+        Box::pin(async move {
+            let purged = self.inner.purge(deleted_before).await?;
+            if !purged.is_empty() {
+                self.dirty.store(true, Ordering::Release);
+            }
+            Ok(purged)
+        })
+    }
+
+    fn backup(&self) -> StoreFuture<'_, Backup> {
+        self.inner.backup()
+    }
+
+    fn replace(&self, backup: Backup) -> StoreFuture<'_, Result<(), StoreError>> {
+        Box::pin(async move {
+            self.mark_dirty(self.inner.replace(backup).await)
+        })
+    }
+
+    fn list<'a>(&'a self, filter: &'a MovieFilter) -> StoreFuture<'a, Vec<Movie>> {
+        self.inner.list(filter)
+    }
+
+    fn scan<'a>(&'a self, after: Option<&'a str>, limit: usize) -> StoreFuture<'a, Vec<Movie>> {
+        self.inner.scan(after, limit)
+    }
+
+    fn suggest<'a>(&'a self, prefix: &'a str, limit: usize) -> StoreFuture<'a, Vec<String>> {
+        self.inner.suggest(prefix, limit)
+    }
+
+    fn genres(&self) -> StoreFuture<'_, Vec<Label>> {
+        self.inner.genres()
+    }
+
+    fn tags(&self) -> StoreFuture<'_, Vec<Label>> {
+        self.inner.tags()
+    }
+
+    fn genre_movie_ids<'a>(&'a self, genre: &'a str) -> StoreFuture<'a, Vec<String>> {
+        self.inner.genre_movie_ids(genre)
+    }
+
+    fn count(&self) -> StoreFuture<'_, usize> {
+        self.inner.count()
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<MovieEvent> {
+        self.inner.subscribe()
+    }
+
+    fn changes(&self, since: &Since, limit: usize) -> Result<ChangePage, CursorExpired> {
+        self.inner.changes(since, limit)
+    }
+
+    fn get_user<'a>(&'a self, id: &'a str) -> StoreFuture<'a, Option<User>> {
+        self.inner.get_user(id)
+    }
+
+    fn insert_user(&self, user: User) -> StoreFuture<'_, Result<(), StoreError>> {
+        Box::pin(async move {
+            self.mark_dirty(self.inner.insert_user(user).await)
+        })
+    }
+
+    fn change_user<'a>(&'a self, user_id: &'a str, change: UserChange) -> StoreFuture<'a, Result<User, StoreError>> {
+        Box::pin(async move {
+            self.mark_dirty(self.inner.change_user(user_id, change).await)
+        })
+    }
+
+    fn links<'a>(&'a self, movie_id: &'a str) -> StoreFuture<'a, Vec<Link>> {
+        self.inner.links(movie_id)
+    }
+
+    fn link(&self, link: Link) -> StoreFuture<'_, Result<bool, StoreError>> {
+        Box::pin(async move {
+            self.mark_dirty(self.inner.link(link).await)
+        })
+    }
+
+    fn unlink<'a>(&'a self, link: &'a Link) -> StoreFuture<'a, Result<bool, StoreError>> {
+        Box::pin(async move {
+            self.mark_dirty(self.inner.unlink(link).await)
+        })
+    }
+
+    // The change log is only closed once the snapshot has every change in it.
+    fn close(&self) -> StoreFuture<'_, Result<(), StoreError>> {
+        Box::pin(async move {
+            self.flush().await.map_err(|e| StoreError::Backend(format!("snapshot {}: {}", self.path.display(), e)))?;
+            self.inner.close_change_log()
+        })
+    }
+
+    // The next flush has to be able to create the temp file next to the snapshot, so try exactly that.
+    fn check_ready(&self) -> StoreFuture<'_, Result<(), StoreError>> {
+        Box::pin(async move {
+            let _guard = self.flush_lock.lock().await;
+            let temp_path = temp_path(&self.path);
+            let probe = async {
+                tokio::fs::OpenOptions::new().write(true).create(true).truncate(true).open(&temp_path).await?;
+                tokio::fs::remove_file(&temp_path).await
+            }.await;
+            probe.map_err(|e| StoreError::Backend(format!("snapshot {} isn't writable: {}", self.path.display(), e)))
+        })
+    }
+}
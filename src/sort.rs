@@ -0,0 +1,92 @@
+use std::{cmp::Ordering, fmt, str::FromStr};
+
+use crate::model::Movie;
+
+// Orders for GET /movies?sort=year:desc,name:asc. Later keys break ties in earlier ones, and the id breaks any ties
+// left, so every sort is a total order and paging through it is stable.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortField {
+    Id,
+    Name,
+    Year,
+    WasGood,
+}
+
+impl SortField {
+    const ALL: [(&'static str, SortField); 4] = [("id", SortField::Id), ("name", SortField::Name), ("year", SortField::Year), ("was_good", SortField::WasGood)];
+
+    fn compare(self, a: &Movie, b: &Movie) -> Ordering {
+        match self {
+            SortField::Id => a.id.cmp(&b.id),
+            // Alphabetical, so "the Matrix" doesn't come after every capitalized name.
+            SortField::Name => a.name.to_lowercase().cmp(&b.name.to_lowercase()).then_with(|| a.name.cmp(&b.name)),
+            SortField::Year => a.year.cmp(&b.year),
+            SortField::WasGood => a.was_good.cmp(&b.was_good),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SortKey {
+    pub field: SortField,
+    pub descending: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Sort(pub Vec<SortKey>);
+
+impl FromStr for Sort {
+    type Err = String;
+
+    // Comma-separated field:direction pairs, where the direction is asc or desc and asc when left out.
+    fn from_str(value: &str) -> Result<Sort, String> {
+        let mut keys: Vec<SortKey> = Vec::new();
+        for key in value.split(',').map(str::trim) {
+            let (name, direction) = key.split_once(':').unwrap_or((key, "asc"));
+            let field = SortField::ALL.iter().find(|(field, _)| *field == name).map(|(_, field)| *field).ok_or_else(|| {
+                let fields: Vec<&str> = SortField::ALL.iter().map(|(field, _)| *field).collect();
+                format!("Unknown sort key {:?}, expected one of {}", name, fields.join(", "))
+            })?;
+            let descending = match direction {
+                "asc" => false,
+                "desc" => true,
+                _ => return Err(format!("Unknown sort direction {:?} for {}, expected asc or desc", direction, name)),
+            };
+            if keys.iter().any(|key| key.field == field) {
+                return Err(format!("Sort key {} is given more than once", name));
+            }
+            keys.push(SortKey { field, descending });
+        }
+        Ok(Sort(keys))
+    }
+}
+
+// The same keys back in their canonical form, with every direction spelled out.
+impl fmt::Display for Sort {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, key) in self.0.iter().enumerate() {
+            let name = SortField::ALL.iter().find(|(_, field)| *field == key.field).map(|(name, _)| *name).unwrap();
+            let direction = if key.descending { "desc" } else { "asc" };
+            write!(f, "{}{}:{}", if i == 0 { "" } else { "," }, name, direction)?;
+        }
+        Ok(())
+    }
+}
+
+impl Sort {
+    // Only Equal for movies with the same id.
+    pub fn compare(&self, a: &Movie, b: &Movie) -> Ordering {
+        self.0.iter()
+            .map(|key| match key.descending {
+                true => key.field.compare(b, a),
+                false => key.field.compare(a, b),
+            })
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or_else(|| a.id.cmp(&b.id))
+    }
+
+    pub fn apply(&self, movies: &mut [Movie]) {
+        movies.sort_by(|a, b| self.compare(a, b));
+    }
+}
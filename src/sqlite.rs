@@ -0,0 +1,566 @@
+use std::{ffi::{c_char, c_int, c_void, CStr, CString}, io, os::unix::ffi::OsStrExt, path::{Path, PathBuf}, ptr, sync::{Arc, Mutex as SyncMutex}, time::Instant};
+use tracing::{info, info_span, Instrument};
+use serde::{de::DeserializeOwned, Serialize};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use tokio::sync::{broadcast, Mutex, MutexGuard};
+
+use crate::metrics::{self, Lock};
+use crate::changes::{self, ChangeLog, ChangePage, CursorExpired, Since};
+use crate::events::MovieEvent;
+use crate::labels::Label;
+use crate::model::{self, Link, Movie, MoviePatch, StoredMovie, StoredTrashed, StoredUser, Trashed, User, UserChange};
+use crate::schema::Schema;
+use crate::store::{check_precondition, Backup, MemoryMovieStore, MovieFilter, MovieStore, Precondition, StoreError, StoreFuture};
+
+// Each row holds one movie, user, link or movie in the trash as JSON, in the version the database's user_version says.
+pub static SCHEMA: Schema = Schema {
+    name: "SQLite database",
+    first: 1,
+    migrations: &[],
+};
+
+// Created on every start, so a new file, or one from before a table was added, gets them.
+const CREATE_TABLES: &str = "
+    CREATE TABLE IF NOT EXISTS movies (id TEXT PRIMARY KEY, movie TEXT NOT NULL);
+    CREATE TABLE IF NOT EXISTS users (id TEXT PRIMARY KEY, user TEXT NOT NULL);
+    CREATE TABLE IF NOT EXISTS links (link TEXT PRIMARY KEY, from_id TEXT NOT NULL, to_id TEXT NOT NULL);
+    CREATE INDEX IF NOT EXISTS links_from ON links (from_id);
+    CREATE INDEX IF NOT EXISTS links_to ON links (to_id);
+    CREATE TABLE IF NOT EXISTS trash (id TEXT PRIMARY KEY, deleted_at TEXT NOT NULL, movie TEXT NOT NULL);
+";
+const UPSERT_MOVIE: &str = "INSERT INTO movies (id, movie) VALUES (?1, ?2) ON CONFLICT (id) DO UPDATE SET movie = excluded.movie";
+const UPSERT_USER: &str = "INSERT INTO users (id, user) VALUES (?1, ?2) ON CONFLICT (id) DO UPDATE SET user = excluded.user";
+const INSERT_LINK: &str = "INSERT OR IGNORE INTO links (link, from_id, to_id) VALUES (?1, ?2, ?3)";
+const INSERT_TRASHED: &str = "INSERT INTO trash (id, deleted_at, movie) VALUES (?1, ?2, ?3) ON CONFLICT (id) DO UPDATE SET deleted_at = excluded.deleted_at, movie = excluded.movie";
+// How long a write waits for another process that has the database locked, e.g. sqlite3 reading it, before failing.
+const BUSY_TIMEOUT_MILLIS: c_int = 5000;
+
+// As much of libsqlite3 as the store needs.
+#[link(name = "sqlite3")]
+unsafe extern "C" {
+    fn sqlite3_open_v2(filename: *const c_char, db: *mut *mut c_void, flags: c_int, vfs: *const c_char) -> c_int;
+    fn sqlite3_close(db: *mut c_void) -> c_int;
+    fn sqlite3_errmsg(db: *mut c_void) -> *const c_char;
+    fn sqlite3_busy_timeout(db: *mut c_void, millis: c_int) -> c_int;
+    fn sqlite3_exec(db: *mut c_void, sql: *const c_char, callback: *const c_void, argument: *mut c_void, error: *mut *mut c_char) -> c_int;
+    fn sqlite3_prepare_v2(db: *mut c_void, sql: *const c_char, len: c_int, statement: *mut *mut c_void, tail: *mut *const c_char) -> c_int;
+    // The destructor is a function pointer, or -1 for SQLite to take its own copy of the text.
+    fn sqlite3_bind_text(statement: *mut c_void, index: c_int, text: *const c_char, len: c_int, destructor: isize) -> c_int;
+    fn sqlite3_step(statement: *mut c_void) -> c_int;
+    fn sqlite3_column_text(statement: *mut c_void, column: c_int) -> *const u8;
+    fn sqlite3_column_bytes(statement: *mut c_void, column: c_int) -> c_int;
+    fn sqlite3_column_int64(statement: *mut c_void, column: c_int) -> i64;
+    fn sqlite3_finalize(statement: *mut c_void) -> c_int;
+}
+
+const SQLITE_OK: c_int = 0;
+const SQLITE_ROW: c_int = 100;
+const SQLITE_DONE: c_int = 101;
+const SQLITE_OPEN_READWRITE: c_int = 0x2;
+const SQLITE_OPEN_CREATE: c_int = 0x4;
+const SQLITE_TRANSIENT: isize = -1;
+
+struct Connection(*mut c_void);
+
+// The handle is only ever used by one thread at a time, under the store's lock.
+unsafe impl Send for Connection {}
+
+impl Connection {
+    fn open(path: &Path) -> Result<Connection, String> {
+        let filename = CString::new(path.as_os_str().as_bytes()).map_err(|e| e.to_string())?;
+        let mut db = ptr::null_mut();
+        let code = unsafe { sqlite3_open_v2(filename.as_ptr(), &mut db, SQLITE_OPEN_READWRITE | SQLITE_OPEN_CREATE, ptr::null()) };
+        // Even a failed open hands back a handle, to get the message from and close.
+        let connection = Connection(db);
+        if code != SQLITE_OK {
+            return Err(connection.error());
+        }
+        unsafe { sqlite3_busy_timeout(db, BUSY_TIMEOUT_MILLIS) };
+        Ok(connection)
+    }
+
+    fn error(&self) -> String {
+        if self.0.is_null() {
+            return "out of memory".to_string();
+        }
+        unsafe { CStr::from_ptr(sqlite3_errmsg(self.0)) }.to_string_lossy().into_owned()
+    }
+
+    // Runs statements that take no parameters and return nothing, separated by semicolons.
+    fn batch(&self, sql: &str) -> Result<(), String> {
+        let sql = CString::new(sql).map_err(|e| e.to_string())?;
+        match unsafe { sqlite3_exec(self.0, sql.as_ptr(), ptr::null(), ptr::null_mut(), ptr::null_mut()) } {
+            SQLITE_OK => Ok(()),
+            _ => Err(self.error()),
+        }
+    }
+
+    fn prepare(&self, sql: &str, parameters: &[&str]) -> Result<Statement<'_>, String> {
+        let mut raw = ptr::null_mut();
+        let code = unsafe { sqlite3_prepare_v2(self.0, sql.as_ptr().cast(), sql.len() as c_int, &mut raw, ptr::null_mut()) };
+        let statement = Statement { connection: self, raw };
+        if code != SQLITE_OK {
+            return Err(self.error());
+        }
+        for (index, parameter) in parameters.iter().enumerate() {
+            let code = unsafe { sqlite3_bind_text(raw, index as c_int + 1, parameter.as_ptr().cast(), parameter.len() as c_int, SQLITE_TRANSIENT) };
+            if code != SQLITE_OK {
+                return Err(self.error());
+            }
+        }
+        Ok(statement)
+    }
+
+    fn execute(&self, sql: &str, parameters: &[&str]) -> Result<(), String> {
+        let mut statement = self.prepare(sql, parameters)?;
+        while statement.step()? {}
+        Ok(())
+    }
+
+    // Runs `write` in a transaction, which is only committed if it returns Ok. IMMEDIATE takes the write lock up front,
+    // so another process can't get in between its reads and writes.
+    fn transaction<T>(&self, write: impl FnOnce(&Connection) -> Result<T, String>) -> Result<T, String> {
+        self.batch("BEGIN IMMEDIATE")?;
+        match write(self).and_then(|result| self.batch("COMMIT").map(|()| result)) {
+            Ok(result) => Ok(result),
+            Err(e) => {
+                let _ = self.batch("ROLLBACK");
+                Err(e)
+            },
+        }
+    }
+
+    fn user_version(&self) -> Result<u32, String> {
+        let mut statement = self.prepare("PRAGMA user_version", &[])?;
+        statement.step()?;
+        Ok(statement.int(0) as u32)
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        unsafe { sqlite3_close(self.0) };
+    }
+}
+
+struct Statement<'a> {
+    connection: &'a Connection,
+    raw: *mut c_void,
+}
+
+impl Statement<'_> {
+    // Moves on to the next row, and says whether there was one.
+    fn step(&mut self) -> Result<bool, String> {
+        match unsafe { sqlite3_step(self.raw) } {
+            SQLITE_ROW => Ok(true),
+            SQLITE_DONE => Ok(false),
+            _ => Err(self.connection.error()),
+        }
+    }
+
+    fn text(&self, column: c_int) -> String {
+        unsafe {
+            let text = sqlite3_column_text(self.raw, column);
+            if text.is_null() {
+                return String::new();
+            }
+            let len = sqlite3_column_bytes(self.raw, column) as usize;
+            String::from_utf8_lossy(std::slice::from_raw_parts(text, len)).into_owned()
+        }
+    }
+
+    fn int(&self, column: c_int) -> i64 {
+        unsafe { sqlite3_column_int64(self.raw, column) }
+    }
+}
+
+impl Drop for Statement<'_> {
+    fn drop(&mut self) {
+        unsafe { sqlite3_finalize(self.raw) };
+    }
+}
+
+fn to_json(record: &impl Serialize) -> Result<String, String> {
+    serde_json::to_string(record).map_err(|e| e.to_string())
+}
+
+// A row's record, brought up to the current version from the one the database is in.
+fn from_json<T: DeserializeOwned>(version: u32, json: &str) -> Result<T, String> {
+    if version == SCHEMA.current() {
+        return serde_json::from_str(json).map_err(|e| e.to_string());
+    }
+    let mut value = serde_json::from_str(json).map_err(|e| e.to_string())?;
+    SCHEMA.upgrade(version, &mut value)?;
+    serde_json::from_value(value).map_err(|e| e.to_string())
+}
+
+// Everything in the database, in the current version.
+fn read_tables(db: &Connection, version: u32) -> Result<Backup, String> {
+    let mut backup = Backup::default();
+    let mut rows = db.prepare("SELECT movie FROM movies ORDER BY id", &[])?;
+    while rows.step()? {
+        backup.movies.push(from_json::<StoredMovie>(version, &rows.text(0))?.into());
+    }
+    let mut rows = db.prepare("SELECT user FROM users ORDER BY id", &[])?;
+    while rows.step()? {
+        backup.users.push(from_json::<StoredUser>(version, &rows.text(0))?.into());
+    }
+    let mut rows = db.prepare("SELECT link FROM links ORDER BY link", &[])?;
+    while rows.step()? {
+        backup.links.push(from_json::<Link>(version, &rows.text(0))?);
+    }
+    let mut rows = db.prepare("SELECT deleted_at, movie FROM trash ORDER BY id", &[])?;
+    while rows.step()? {
+        let movie = from_json::<StoredMovie>(version, &rows.text(1))?;
+        backup.trash.push(StoredTrashed { deleted_at: rows.text(0), movie }.into());
+    }
+    Ok(backup)
+}
+
+// Swaps everything in the database for what's in the backup, in the current version. Called in a transaction.
+fn write_tables(db: &Connection, backup: &Backup) -> Result<(), String> {
+    db.batch("DELETE FROM movies; DELETE FROM users; DELETE FROM links; DELETE FROM trash")?;
+    for movie in &backup.movies {
+        db.execute(UPSERT_MOVIE, &[&movie.id, &to_json(&StoredMovie::from(movie.clone()))?])?;
+    }
+    for user in &backup.users {
+        db.execute(UPSERT_USER, &[&user.id, &to_json(&StoredUser::from(user.clone()))?])?;
+    }
+    for link in &backup.links {
+        db.execute(INSERT_LINK, &[&to_json(link)?, &link.from, &link.to])?;
+    }
+    for trashed in &backup.trash {
+        db.execute(INSERT_TRASHED, &[&trashed.movie.id, &trashed.deleted_at, &to_json(&StoredMovie::from(trashed.movie.clone()))?])?;
+    }
+    db.batch(&format!("PRAGMA user_version = {}", SCHEMA.current()))
+}
+
+// Opens the database, creating it and its tables if they aren't there, and reads everything in it. One from an older
+// version is rewritten in the current one, if auto_migrate allows opening it at all.
+fn load(path: &Path) -> io::Result<(Connection, Backup)> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), message));
+    let db = Connection::open(path).map_err(io::Error::other)?;
+    // Zero until something stamps it, which is a database this store has never written to.
+    let found = db.user_version().map_err(invalid)?;
+    if found > 0 {
+        SCHEMA.check(path, found)?;
+    }
+    db.batch(CREATE_TABLES).map_err(invalid)?;
+    if found == 0 {
+        db.batch(&format!("PRAGMA user_version = {}", SCHEMA.current())).map_err(invalid)?;
+    }
+    let version = found.max(SCHEMA.first);
+    let tables = read_tables(&db, version).map_err(invalid)?;
+    if version < SCHEMA.current() {
+        db.transaction(|db| write_tables(db, &tables)).map_err(invalid)?;
+        info!("Migrated SQLite database {} from schema version {} to {}", path.display(), version, SCHEMA.current());
+    }
+    Ok((db, tables))
+}
+
+// Keeps everything in memory like MemoryMovieStore, and writes every mutation to an SQLite database, in a transaction
+// of its own, before applying it. The database is read back whole on startup, so reads never touch it. Mutations are
+// serialized on the connection, so the database never has one the memory doesn't, or the other way round.
+pub struct SqliteMovieStore {
+    inner: MemoryMovieStore,
+    db: Arc<SyncMutex<Connection>>,
+    // Held across the check, the write to the database and applying it in memory, like the WAL's log lock.
+    writes: Mutex<()>,
+}
+
+impl SqliteMovieStore {
+    pub async fn open(path: PathBuf) -> io::Result<SqliteMovieStore> {
+        let (db, tables) = {
+            let path = path.clone();
+            tokio::task::spawn_blocking(move || load(&path)).await.map_err(io::Error::other)??
+        };
+        info!("Read {} movies and {} users from SQLite database {}", tables.movies.len(), tables.users.len(), path.display());
+        Ok(SqliteMovieStore {
+            inner: MemoryMovieStore::from_tables(tables.movies, tables.users, tables.links, tables.trash)
+                .with_change_log(ChangeLog::open(&changes::path_beside(&path))?),
+            db: Arc::new(SyncMutex::new(db)),
+            writes: Mutex::new(()),
+        })
+    }
+
+    async fn lock_writes(&self) -> MutexGuard<'_, ()> {
+        let started = Instant::now();
+        let writes = self.writes.lock().await;
+        metrics::record_lock_wait(Lock::SqliteWrites, started.elapsed());
+        writes
+    }
+
+    // Runs `write` in a transaction on a blocking thread, since SQLite waits on the disk.
+    async fn write<F>(&self, write: F) -> Result<(), StoreError>
+    where F: FnOnce(&Connection) -> Result<(), String> + Send + 'static {
+        let db = self.db.clone();
+        tokio::task::spawn_blocking(move || db.lock().unwrap().transaction(write))
+            .instrument(info_span!("sqlite.write"))
+            .await
+            .map_err(backend_error)?
+            .map_err(backend_error)
+    }
+
+    async fn write_movie(&self, movie: &Movie) -> Result<(), StoreError> {
+        let (id, json) = (movie.id.clone(), to_json(&StoredMovie::from(movie.clone())).map_err(backend_error)?);
+        self.write(move |db| db.execute(UPSERT_MOVIE, &[&id, &json])).await
+    }
+
+    async fn write_user(&self, user: &User) -> Result<(), StoreError> {
+        let (id, json) = (user.id.clone(), to_json(&StoredUser::from(user.clone())).map_err(backend_error)?);
+        self.write(move |db| db.execute(UPSERT_USER, &[&id, &json])).await
+    }
+}
+
+fn backend_error(e: impl std::fmt::Display) -> StoreError {
+    StoreError::Backend(format!("sqlite: {}", e))
+}
+
+impl MovieStore for SqliteMovieStore {
+    fn get<'a>(&'a self, id: &'a str) -> StoreFuture<'a, Option<Movie>> {
+        self.inner.get(id)
+    }
+
+    fn insert(&self, movie: Movie) -> StoreFuture<'_, Result<(), StoreError>> {
+        Box::pin(async move {
+            let _writes = self.lock_writes().await;
+            if let Some(existing) = self.inner.get(&movie.id).await {
+                return Err(StoreError::AlreadyExists(Box::new(existing)));
+            }
+            self.write_movie(&movie).await?;
+            self.inner.insert(movie).await
+        })
+    }
+
+    // Written with the version inner is about to give it, as the WAL logs it.
+    fn upsert(&self, mut movie: Movie) -> StoreFuture<'_, Result<bool, StoreError>> {
+        Box::pin(async move {
+            let _writes = self.lock_writes().await;
+            let existing = self.inner.get(&movie.id).await;
+            movie.succeed(existing.as_ref());
+            self.write_movie(&movie).await?;
+            self.inner.upsert(movie).await
+        })
+    }
+
+    fn put(&self, movie: Movie) -> StoreFuture<'_, Result<bool, StoreError>> {
+        Box::pin(async move {
+            let _writes = self.lock_writes().await;
+            self.write_movie(&movie).await?;
+            self.inner.put(movie).await
+        })
+    }
+
+    fn update_if<'a>(&'a self, mut movie: Movie, precondition: Precondition<'a>) -> StoreFuture<'a, Result<(), StoreError>> {
+        Box::pin(async move {
+            let _writes = self.lock_writes().await;
+            let existing = self.inner.get(&movie.id).await.ok_or(StoreError::NotFound)?;
+            check_precondition(precondition, &existing)?;
+            movie.succeed(Some(&existing));
+            self.write_movie(&movie).await?;
+            self.inner.update(movie).await
+        })
+    }
+
+    fn patch<'a>(&'a self, id: &'a str, mut patch: MoviePatch) -> StoreFuture<'a, Result<Movie, StoreError>> {
+        Box::pin(async move {
+            let _writes = self.lock_writes().await;
+            let mut movie = self.inner.get(id).await.ok_or(StoreError::NotFound)?;
+            if !patch.expects(&movie) {
+                return Err(StoreError::PreconditionFailed(Box::new(movie)));
+            }
+            // Applied twice, so stamped once.
+            patch.updated_at.get_or_insert_with(model::timestamp);
+            patch.clone().apply(&mut movie);
+            self.write_movie(&movie).await?;
+            self.inner.patch(id, patch).await
+        })
+    }
+
+    // Deleting a movie takes it off every watchlist and favorites, and unlinks it, so those rows change with it.
+    fn delete_if<'a>(&'a self, id: &'a str, precondition: Precondition<'a>) -> StoreFuture<'a, Result<Movie, StoreError>> {
+        Box::pin(async move {
+            let _writes = self.lock_writes().await;
+            let existing = self.inner.get(id).await.ok_or(StoreError::NotFound)?;
+            check_precondition(precondition, &existing)?;
+            let deleted_at = OffsetDateTime::now_utc().format(&Rfc3339).map_err(backend_error)?;
+            let (id_owned, json, deleted) = (id.to_string(), to_json(&StoredMovie::from(existing)).map_err(backend_error)?, deleted_at.clone());
+            self.write(move |db| {
+                db.execute("DELETE FROM movies WHERE id = ?1", &[&id_owned])?;
+                db.execute(INSERT_TRASHED, &[&id_owned, &deleted, &json])?;
+                db.execute("DELETE FROM links WHERE from_id = ?1 OR to_id = ?1", &[&id_owned])?;
+                let mut changed = Vec::new();
+                let mut rows = db.prepare("SELECT user FROM users WHERE instr(user, ?1) > 0", &[&to_json(&id_owned)?])?;
+                while rows.step()? {
+                    let mut user: StoredUser = from_json(SCHEMA.current(), &rows.text(0))?;
+                    user.user.forget(&id_owned);
+                    changed.push((user.user.id.clone(), to_json(&user)?));
+                }
+                drop(rows);
+                for (user_id, json) in changed {
+                    db.execute(UPSERT_USER, &[&user_id, &json])?;
+                }
+                Ok(())
+            }).await?;
+            self.inner.delete_at(id, None, deleted_at).await
+        })
+    }
+
+    fn trash(&self) -> StoreFuture<'_, Vec<Trashed>> {
+        self.inner.trash()
+    }
+
+    fn restore<'a>(&'a self, id: &'a str) -> StoreFuture<'a, Result<Movie, StoreError>> {
+        Box::pin(async move {
+            let _writes = self.lock_writes().await;
+            if !self.inner.is_trashed(id) {
+                return Err(StoreError::NotFound);
+            }
+            if let Some(existing) = self.inner.get(id).await {
+                return Err(StoreError::AlreadyExists(Box::new(existing)));
+            }
+            let id_owned = id.to_string();
+            self.write(move |db| {
+                db.execute("INSERT INTO movies (id, movie) SELECT id, movie FROM trash WHERE id = ?1", &[&id_owned])?;
+                db.execute("DELETE FROM trash WHERE id = ?1", &[&id_owned])
+            }).await?;
+            self.inner.restore(id).await
+        })
+    }
+
+    fn purge(&self, deleted_before: OffsetDateTime) -> StoreFuture<'_, Result<Vec<String>, StoreError>> {
+        Box::pin(async move {
+            let _writes = self.lock_writes().await;
+            let ids = self.inner.trashed_before(deleted_before);
+            if ids.is_empty() {
+                return Ok(ids);
+            }
+            self.write(move |db| ids.iter().try_for_each(|id| db.execute("DELETE FROM trash WHERE id = ?1", &[id]))).await?;
+            self.inner.purge(deleted_before).await
+        })
+    }
+
+    fn backup(&self) -> StoreFuture<'_, Backup> {
+        self.inner.backup()
+    }
+
+    // One transaction, so the database is swapped all at once, as memory is.
+    fn replace(&self, backup: Backup) -> StoreFuture<'_, Result<(), StoreError>> {
+        Box::pin(async move {
+            let _writes = self.lock_writes().await;
+            let tables = backup.clone();
+            self.write(move |db| write_tables(db, &tables)).await?;
+            self.inner.replace(backup).await
+        })
+    }
+
+    fn list<'a>(&'a self, filter: &'a MovieFilter) -> StoreFuture<'a, Vec<Movie>> {
+        self.inner.list(filter)
+    }
+
+    fn scan<'a>(&'a self, after: Option<&'a str>, limit: usize) -> StoreFuture<'a, Vec<Movie>> {
+        self.inner.scan(after, limit)
+    }
+
+    fn suggest<'a>(&'a self, prefix: &'a str, limit: usize) -> StoreFuture<'a, Vec<String>> {
+        self.inner.suggest(prefix, limit)
+    }
+
+    fn genres(&self) -> StoreFuture<'_, Vec<Label>> {
+        self.inner.genres()
+    }
+
+    fn tags(&self) -> StoreFuture<'_, Vec<Label>> {
+        self.inner.tags()
+    }
+
+    fn genre_movie_ids<'a>(&'a self, genre: &'a str) -> StoreFuture<'a, Vec<String>> {
+        self.inner.genre_movie_ids(genre)
+    }
+
+    fn count(&self) -> StoreFuture<'_, usize> {
+        self.inner.count()
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<MovieEvent> {
+        self.inner.subscribe()
+    }
+
+    fn changes(&self, since: &Since, limit: usize) -> Result<ChangePage, CursorExpired> {
+        self.inner.changes(since, limit)
+    }
+
+    fn get_user<'a>(&'a self, id: &'a str) -> StoreFuture<'a, Option<User>> {
+        self.inner.get_user(id)
+    }
+
+    fn insert_user(&self, user: User) -> StoreFuture<'_, Result<(), StoreError>> {
+        Box::pin(async move {
+            let _writes = self.lock_writes().await;
+            if let Some(existing) = self.inner.get_user(&user.id).await {
+                return Err(StoreError::UserAlreadyExists(Box::new(existing)));
+            }
+            self.write_user(&user).await?;
+            self.inner.insert_user(user).await
+        })
+    }
+
+    fn change_user<'a>(&'a self, user_id: &'a str, change: UserChange) -> StoreFuture<'a, Result<User, StoreError>> {
+        Box::pin(async move {
+            let _writes = self.lock_writes().await;
+            let mut user = self.inner.get_user(user_id).await.ok_or(StoreError::UserNotFound)?;
+            if let Some(movie_id) = change.added_movie() {
+                self.inner.get(movie_id).await.ok_or(StoreError::NotFound)?;
+            }
+            user.apply(change.clone());
+            self.write_user(&user).await?;
+            self.inner.change_user(user_id, change).await
+        })
+    }
+
+    fn links<'a>(&'a self, movie_id: &'a str) -> StoreFuture<'a, Vec<Link>> {
+        self.inner.links(movie_id)
+    }
+
+    fn link(&self, link: Link) -> StoreFuture<'_, Result<bool, StoreError>> {
+        Box::pin(async move {
+            let _writes = self.lock_writes().await;
+            if !self.inner.check_link(&link).await? {
+                return Ok(false);
+            }
+            let (json, from, to) = (to_json(&link).map_err(backend_error)?, link.from.clone(), link.to.clone());
+            self.write(move |db| db.execute(INSERT_LINK, &[&json, &from, &to])).await?;
+            self.inner.link(link).await
+        })
+    }
+
+    fn unlink<'a>(&'a self, link: &'a Link) -> StoreFuture<'a, Result<bool, StoreError>> {
+        Box::pin(async move {
+            let _writes = self.lock_writes().await;
+            if !self.inner.links(&link.from).await.contains(link) {
+                return Ok(false);
+            }
+            let json = to_json(link).map_err(backend_error)?;
+            self.write(move |db| db.execute("DELETE FROM links WHERE link = ?1", &[&json])).await?;
+            self.inner.unlink(link).await
+        })
+    }
+
+    // Every write is committed as it's made, so only the change log is left.
+    fn close(&self) -> StoreFuture<'_, Result<(), StoreError>> {
+        Box::pin(async move {
+            let _writes = self.lock_writes().await;
+            self.inner.close_change_log()
+        })
+    }
+
+    // Writes user_version over with what it already is, which needs everything a real write does: the write lock, which
+    // another process may be holding, and a journal next to the database, which can't be made if its directory has
+    // gone or is read-only.
+    fn check_ready(&self) -> StoreFuture<'_, Result<(), StoreError>> {
+        Box::pin(async move {
+            let _writes = self.lock_writes().await;
+            self.write(|db| db.batch(&format!("PRAGMA user_version = {}", SCHEMA.current()))).await
+        })
+    }
+}
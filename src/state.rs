@@ -0,0 +1,9 @@
+use std::sync::Arc;
+
+use crate::store::{MemoryMovieStore, MovieStore};
+
+pub type StateWrapper = Arc<dyn MovieStore>;
+
+pub fn state_init() -> StateWrapper { 
+    Arc::new(MemoryMovieStore::new())
+}
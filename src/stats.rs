@@ -0,0 +1,69 @@
+use std::collections::BTreeMap;
+use serde::Serialize;
+
+use crate::model::Movie;
+
+// Summary of the catalog for GET /movies/stats, so dashboards don't have to page through every movie to draw it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MovieStats {
+    pub total: usize,
+    // Only years that have movies, in order.
+    pub by_year: BTreeMap<u16, usize>,
+    pub by_was_good: WasGoodCounts,
+    // The year statistics are null when there are no movies.
+    pub min_year: Option<u16>,
+    pub max_year: Option<u16>,
+    // The mean of the middle two years when there's an even number of movies.
+    pub median_year: Option<f64>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct WasGoodCounts {
+    #[serde(rename = "true")]
+    pub good: usize,
+    #[serde(rename = "false")]
+    pub bad: usize,
+}
+
+impl MovieStats {
+    pub fn of(movies: &[Movie]) -> MovieStats {
+        let mut by_year = BTreeMap::new();
+        let mut by_was_good = WasGoodCounts::default();
+        for movie in movies {
+            *by_year.entry(movie.year).or_default() += 1;
+            match movie.was_good {
+                true => by_was_good.good += 1,
+                false => by_was_good.bad += 1,
+            }
+        }
+        MovieStats {
+            total: movies.len(),
+            min_year: by_year.keys().next().copied(),
+            max_year: by_year.keys().next_back().copied(),
+            median_year: median(&by_year, movies.len()),
+            by_year,
+            by_was_good,
+        }
+    }
+}
+
+// Walks the counts rather than sorting every year.
+fn median(by_year: &BTreeMap<u16, usize>, total: usize) -> Option<f64> {
+    if total == 0 {
+        return None;
+    }
+    // The 0-based positions of the middle one or two years in sorted order.
+    let (low, high) = ((total - 1) / 2, total / 2);
+    let mut seen = 0;
+    let mut low_year = None;
+    for (&year, &count) in by_year {
+        seen += count;
+        if low_year.is_none() && seen > low {
+            low_year = Some(year);
+        }
+        if seen > high {
+            return Some((f64::from(low_year?) + f64::from(year)) / 2.0);
+        }
+    }
+    None
+}
@@ -0,0 +1,707 @@
+use std::{borrow::Borrow, collections::{BTreeMap, BTreeSet}, future::Future, ops::Bound, pin::Pin, sync::{Arc, RwLock as SyncRwLock}, time::Instant};
+use serde::Serialize;
+use tracing::{debug, info_span, Instrument};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use tokio::sync::{broadcast, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use crate::audit;
+use crate::changes::{ChangeLog, ChangePage, CursorExpired, Since};
+use crate::events::{MovieEvent, EVENT_BUFFER};
+use crate::labels::{Label, LabelIndex};
+use crate::metrics::{self, Lock};
+use crate::model::{Link, Movie, MoviePatch, Relation, Revision, StoredUser, Trashed, User, UserChange};
+use crate::quota::QuotaExceeded;
+use crate::suggest::NameIndex;
+use crate::tenant::TenantConfig;
+use crate::title;
+
+// Boxed so that MovieStore stays object-safe and handlers can hold an Arc<dyn MovieStore>.
+pub type StoreFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+#[derive(Debug)]
+pub enum StoreError {
+    NotFound,
+    // Carries the movie that's already stored under that id.
+    AlreadyExists(Box<Movie>),
+    // A write's precondition didn't hold. Carries the movie as it is now.
+    PreconditionFailed(Box<Movie>),
+    // Carries the user that's already registered under that id.
+    UserAlreadyExists(Box<User>),
+    // Movies go missing with NotFound, users with this, so callers can tell which one it was.
+    UserNotFound,
+    // Linking the movies as sequels would make one a sequel of itself, directly or down the chain.
+    SequelCycle,
+    // Adding the movie would go past the tenant's quota or the limit on all movies, see quota.rs.
+    QuotaExceeded(QuotaExceeded),
+    // The backend itself failed, e.g. couldn't write to disk.
+    Backend(String),
+}
+
+// Checked against the stored movie atomically with the write it guards, which only goes ahead if it returns true.
+// Backs If-Match on PUT and DELETE.
+pub type Precondition<'a> = Option<&'a (dyn Fn(&Movie) -> bool + Sync)>;
+
+// For implementations: fails with PreconditionFailed unless the movie passes.
+pub fn check_precondition(precondition: Precondition<'_>, movie: &Movie) -> Result<(), StoreError> {
+    match precondition {
+        Some(precondition) if !precondition(movie) => Err(StoreError::PreconditionFailed(Box::new(movie.clone()))),
+        _ => Ok(()),
+    }
+}
+
+// Everything a store holds, as of one moment. Backs POST /admin/snapshot and POST /admin/restore, see backup.rs.
+#[derive(Debug, Clone, Default)]
+pub struct Backup {
+    pub movies: Vec<Movie>,
+    pub users: Vec<User>,
+    pub links: Vec<Link>,
+    pub trash: Vec<Trashed>,
+}
+
+// What swapping the `old` movies for `new` ones does to each of them, in id order, as subscribers would have seen it
+// done one by one. Movies with the same ETag on both sides are left out.
+pub fn movie_changes<M: Borrow<Movie>>(old: &BTreeMap<String, M>, new: &BTreeMap<String, M>) -> Vec<MovieEvent> {
+    let deleted = old.values().map(Borrow::borrow).filter(|movie| !new.contains_key(&movie.id)).map(|movie| MovieEvent::Deleted(movie.clone()));
+    let written = new.values().map(Borrow::borrow).filter_map(|movie| match old.get(&movie.id).map(Borrow::borrow) {
+        None => Some(MovieEvent::Created(movie.clone())),
+        Some(previous) if previous.etag() != movie.etag() => Some(MovieEvent::Updated(movie.clone())),
+        Some(_) => None,
+    });
+    let mut changes: Vec<MovieEvent> = deleted.chain(written).collect();
+    changes.sort_by(|a, b| a.movie().id.cmp(&b.movie().id));
+    changes
+}
+
+// Server-side filters for listing movies. Each one that is set narrows the result further.
+#[derive(Debug, Clone, Default)]
+pub struct MovieFilter {
+    pub year: Option<u16>,
+    // Inclusive bounds on the year.
+    pub year_gte: Option<u16>,
+    pub year_lte: Option<u16>,
+    pub was_good: Option<bool>,
+    // Substring match on the movie's name, ignoring case and accents.
+    pub name_contains: Option<String>,
+    // Case-insensitive, one of the movie's genres.
+    pub genre: Option<String>,
+    // Case-insensitive, the whole name of the director.
+    pub director: Option<String>,
+    // Inclusive bounds on the runtime. Movies without one never match them.
+    pub runtime_gte: Option<u16>,
+    pub runtime_lte: Option<u16>,
+    // Case-insensitive, one of the movie's tags.
+    pub tag: Option<String>,
+    // Movies changed at or after this time, for clients keeping a copy in sync. Movies that haven't changed since
+    // there were timestamps never match it.
+    pub updated_since: Option<OffsetDateTime>,
+}
+
+impl MovieFilter {
+    pub fn matches(&self, movie: &Movie) -> bool {
+        self.year.is_none_or(|year| movie.year == year)
+            && self.year_gte.is_none_or(|year_gte| movie.year >= year_gte)
+            && self.year_lte.is_none_or(|year_lte| movie.year <= year_lte)
+            && self.was_good.is_none_or(|was_good| movie.was_good == was_good)
+            && self.name_contains.as_ref().is_none_or(|needle| title::fold(&movie.name).contains(&title::fold(needle)))
+            && self.genre.as_ref().is_none_or(|genre| movie.genres.iter().any(|other| other.to_lowercase() == genre.to_lowercase()))
+            && self.director.as_ref().is_none_or(|director| movie.director.as_ref().is_some_and(|other| other.to_lowercase() == director.to_lowercase()))
+            && self.runtime_gte.is_none_or(|runtime_gte| movie.runtime_minutes.is_some_and(|runtime| runtime >= runtime_gte))
+            && self.runtime_lte.is_none_or(|runtime_lte| movie.runtime_minutes.is_some_and(|runtime| runtime <= runtime_lte))
+            && self.tag.as_ref().is_none_or(|tag| movie.tags.iter().any(|other| other.to_lowercase() == tag.to_lowercase()))
+            && self.updated_since.is_none_or(|since| movie.updated().is_some_and(|updated| updated >= since))
+    }
+}
+
+// Everything the handlers need from a place that keeps movies around.
+pub trait MovieStore: Send + Sync {
+    fn get<'a>(&'a self, id: &'a str) -> StoreFuture<'a, Option<Movie>>;
+    // Fails with AlreadyExists if there is already a movie with this id.
+    fn insert(&self, movie: Movie) -> StoreFuture<'_, Result<(), StoreError>>;
+    // Inserts or overwrites. Returns true if the movie is new.
+    fn upsert(&self, movie: Movie) -> StoreFuture<'_, Result<bool, StoreError>>;
+    // Inserts or overwrites the movie as it is, without giving it a new version or updated_at. For copying movies from
+    // another store, as a follower does its leader's changes. Returns true if the movie is new.
+    fn put(&self, movie: Movie) -> StoreFuture<'_, Result<bool, StoreError>>;
+    // Replaces the movie with the same id. Fails with NotFound if there isn't one.
+    fn update(&self, movie: Movie) -> StoreFuture<'_, Result<(), StoreError>> {
+        self.update_if(movie, None)
+    }
+    fn update_if<'a>(&'a self, movie: Movie, precondition: Precondition<'a>) -> StoreFuture<'a, Result<(), StoreError>>;
+    // Applies the patch atomically and returns the movie as it is afterwards.
+    fn patch<'a>(&'a self, id: &'a str, patch: MoviePatch) -> StoreFuture<'a, Result<Movie, StoreError>>;
+    // Moves the movie to the trash, and returns it.
+    fn delete<'a>(&'a self, id: &'a str) -> StoreFuture<'a, Result<Movie, StoreError>> {
+        self.delete_if(id, None)
+    }
+    fn delete_if<'a>(&'a self, id: &'a str, precondition: Precondition<'a>) -> StoreFuture<'a, Result<Movie, StoreError>>;
+    // Every movie in the trash, in id order. Nothing else sees them.
+    fn trash(&self) -> StoreFuture<'_, Vec<Trashed>>;
+    // Takes the movie back out of the trash and returns it. Fails with NotFound if it isn't there, or with
+    // AlreadyExists if another movie has been added under its id since.
+    fn restore<'a>(&'a self, id: &'a str) -> StoreFuture<'a, Result<Movie, StoreError>>;
+    // Deletes the movies that went in the trash before `deleted_before` for good, and returns their ids.
+    fn purge(&self, deleted_before: OffsetDateTime) -> StoreFuture<'_, Result<Vec<String>, StoreError>>;
+    // Everything in the store, as it was at one moment between writes.
+    fn backup(&self) -> StoreFuture<'_, Backup>;
+    // Swaps everything in the store for what's in the backup, all at once. Subscribers are sent the changes to movies
+    // it comes to, see movie_changes.
+    fn replace(&self, backup: Backup) -> StoreFuture<'_, Result<(), StoreError>>;
+    // Every movie matching the filter, ordered by id so that paging through the list is stable between requests.
+    fn list<'a>(&'a self, filter: &'a MovieFilter) -> StoreFuture<'a, Vec<Movie>>;
+    // Up to `limit` movies with ids after `after` (or from the start), in id order. For walking the whole store a bit
+    // at a time without holding it locked throughout.
+    fn scan<'a>(&'a self, after: Option<&'a str>, limit: usize) -> StoreFuture<'a, Vec<Movie>> {
+        Box::pin(async move {
+            self.list(&MovieFilter::default()).await.into_iter()
+                .filter(|movie| after.is_none_or(|after| movie.id.as_str() > after))
+                .take(limit)
+                .collect()
+        })
+    }
+    // Up to `limit` distinct names starting with `prefix`, ignoring case, in alphabetical order. Backs GET
+    // /movies/suggest, which is called on every keystroke, so stores should keep a suggest::NameIndex up to date
+    // rather than listing everything like this does.
+    fn suggest<'a>(&'a self, prefix: &'a str, limit: usize) -> StoreFuture<'a, Vec<String>> {
+        Box::pin(async move {
+            NameIndex::new(&self.list(&MovieFilter::default()).await).suggest(prefix, limit)
+        })
+    }
+    // Every genre some movie has, with how many movies have it, in case-insensitive alphabetical order. Like suggest,
+    // stores should keep a labels::LabelIndex up to date rather than listing everything.
+    fn genres(&self) -> StoreFuture<'_, Vec<Label>> {
+        Box::pin(async move {
+            LabelIndex::genres(&self.list(&MovieFilter::default()).await).labels()
+        })
+    }
+    // The ids of the movies with the genre, ignoring case, in id order.
+    fn genre_movie_ids<'a>(&'a self, genre: &'a str) -> StoreFuture<'a, Vec<String>> {
+        Box::pin(async move {
+            LabelIndex::genres(&self.list(&MovieFilter::default()).await).movie_ids(genre)
+        })
+    }
+    // The same for tags.
+    fn tags(&self) -> StoreFuture<'_, Vec<Label>> {
+        Box::pin(async move {
+            LabelIndex::tags(&self.list(&MovieFilter::default()).await).labels()
+        })
+    }
+    // How many movies there are. Stores that can count without listing everything should.
+    fn count(&self) -> StoreFuture<'_, usize> {
+        Box::pin(async move { self.list(&MovieFilter::default()).await.len() })
+    }
+    // Checks that the backend could serve a request right now, e.g. that its files are still writable. Backed by
+    // /readyz. Nothing can go wrong with memory, so by default this always succeeds.
+    fn check_ready(&self) -> StoreFuture<'_, Result<(), StoreError>> {
+        Box::pin(async { Ok(()) })
+    }
+    // Called once on shutdown, after the last write, to leave the store's files as a clean restart expects them. Nothing
+    // to do for stores in memory.
+    fn close(&self) -> StoreFuture<'_, Result<(), StoreError>> {
+        Box::pin(async { Ok(()) })
+    }
+    // The tenants whose catalogs the store keeps apart, see tenant.rs. Only a TenantMovieStore has any.
+    fn tenants(&self) -> Vec<TenantConfig> {
+        Vec::new()
+    }
+    // Most movies the store may hold across every catalog, see quota.rs. Only a QuotaMovieStore has a limit.
+    fn max_movies(&self) -> Option<usize> {
+        None
+    }
+    // Every change made from now on, in the order they were applied. Backs GET /movies/events.
+    fn subscribe(&self) -> broadcast::Receiver<MovieEvent>;
+    // Up to `limit` of the changes made since then, in the order they were applied. Backs GET /movies/changes.
+    fn changes(&self, since: &Since, limit: usize) -> Result<ChangePage, CursorExpired>;
+    // Every change ever made to the movie, oldest first, including any before it was last deleted. Backs GET
+    // /movie/{id}/history and ?as_of=. None if the store doesn't keep its history, which only event-sourced ones do.
+    fn history<'a>(&'a self, _id: &'a str) -> StoreFuture<'a, Option<Vec<Revision>>> {
+        Box::pin(async { None })
+    }
+    // Users are kept by the same store as the movies on their lists, and saved the same way.
+    fn get_user<'a>(&'a self, id: &'a str) -> StoreFuture<'a, Option<User>>;
+    // Fails with UserAlreadyExists if the id is taken.
+    fn insert_user(&self, user: User) -> StoreFuture<'_, Result<(), StoreError>>;
+    // Returns the user as they are afterwards. Fails with UserNotFound, or with NotFound when adding a movie that
+    // doesn't exist.
+    fn change_user<'a>(&'a self, user_id: &'a str, change: UserChange) -> StoreFuture<'a, Result<User, StoreError>>;
+    // Links between movies are kept by the store too. Every link from or to the movie, in order.
+    fn links<'a>(&'a self, movie_id: &'a str) -> StoreFuture<'a, Vec<Link>>;
+    // Returns true if the link is new. Fails with NotFound unless both movies exist, or with SequelCycle.
+    fn link(&self, link: Link) -> StoreFuture<'_, Result<bool, StoreError>>;
+    // Returns true if there was such a link.
+    fn unlink<'a>(&'a self, link: &'a Link) -> StoreFuture<'a, Result<bool, StoreError>>;
+}
+
+// Whether `link` can be added to `links` between `movies`, and if so whether it's new.
+fn check_link<M>(movies: &BTreeMap<String, M>, links: &BTreeSet<Link>, link: &Link) -> Result<bool, StoreError> {
+    if !movies.contains_key(&link.from) || !movies.contains_key(&link.to) {
+        return Err(StoreError::NotFound);
+    }
+    if link.relation == Relation::SequelOf && is_sequel_of(links, &link.to, &link.from) {
+        return Err(StoreError::SequelCycle);
+    }
+    Ok(!links.contains(link))
+}
+
+// Whether `later` follows `earlier` down a chain of sequels, or is the same movie.
+fn is_sequel_of(links: &BTreeSet<Link>, later: &str, earlier: &str) -> bool {
+    let mut seen = BTreeSet::new();
+    let mut next = vec![later];
+    while let Some(movie_id) = next.pop() {
+        if movie_id == earlier {
+            return true;
+        }
+        if seen.insert(movie_id) {
+            next.extend(links.iter().filter(|link| link.relation == Relation::SequelOf && link.from == movie_id).map(|link| link.to.as_str()));
+        }
+    }
+    false
+}
+
+type Movies = BTreeMap<String, Arc<Movie>>;
+
+// The default store: everything lives in a map in memory and is gone on restart.
+//
+// Locks are only ever taken in this order: the movies, then users, links, the trash, the indexes, and the audit log last.
+// The std ones are never held across an await (the futures wouldn't be Send if they were), so no two writes can
+// deadlock. A write holds the movies only for its change to the map and for `committed`, which is quick.
+pub struct MemoryMovieStore {
+    // Readers share the lock, so GETs only ever wait on writers, not on each other. The movies are behind Arcs so that
+    // readers only copy pointers with the lock held, and copy the movies themselves once they've let go of it.
+    movies: RwLock<Movies>,
+    // Sent to while the write lock is still held, so subscribers see changes in the same order the map does.
+    events: broadcast::Sender<MovieEvent>,
+    // Likewise, the same changes kept for GET /movies/changes.
+    changes: SyncRwLock<ChangeLog>,
+    // Changed only while the write lock is held, so they always match the map.
+    names: SyncRwLock<NameIndex>,
+    genres: SyncRwLock<LabelIndex>,
+    tags: SyncRwLock<LabelIndex>,
+    // Users are only changed with the movies locked too, so their watchlists and favorites never name a movie that has
+    // been deleted.
+    users: SyncRwLock<BTreeMap<String, User>>,
+    // Likewise for links, which go along with either of their movies.
+    links: SyncRwLock<BTreeSet<Link>>,
+    // Deleted movies by id, also only changed with the movies locked. Their watchlist places and links are gone with
+    // the delete, and don't come back when they're restored.
+    trash: SyncRwLock<BTreeMap<String, Trashed>>,
+}
+
+impl MemoryMovieStore {
+    pub fn new() -> MemoryMovieStore {
+        MemoryMovieStore::from_movies(Vec::new())
+    }
+
+    // Starts out holding the given movies, e.g. ones loaded back from disk.
+    pub fn from_movies(movies: Vec<Movie>) -> MemoryMovieStore {
+        MemoryMovieStore::from_tables(movies, Vec::new(), Vec::new(), Vec::new())
+    }
+
+    pub fn from_tables(movies: Vec<Movie>, users: Vec<User>, links: Vec<Link>, trash: Vec<Trashed>) -> MemoryMovieStore {
+        MemoryMovieStore {
+            names: SyncRwLock::new(NameIndex::new(&movies)),
+            genres: SyncRwLock::new(LabelIndex::genres(&movies)),
+            tags: SyncRwLock::new(LabelIndex::tags(&movies)),
+            movies: RwLock::new(movies.into_iter().map(|movie| (movie.id.clone(), Arc::new(movie))).collect()),
+            events: broadcast::channel(EVENT_BUFFER).0,
+            changes: SyncRwLock::new(ChangeLog::new()),
+            users: SyncRwLock::new(users.into_iter().map(|user| (user.id.clone(), user)).collect()),
+            links: SyncRwLock::new(links.into_iter().collect()),
+            trash: SyncRwLock::new(trash.into_iter().map(|trashed| (trashed.movie.id.clone(), trashed)).collect()),
+        }
+    }
+
+    // Keeps the changes for GET /movies/changes in `changes`, e.g. a log opened from a file, rather than a new one.
+    pub fn with_change_log(self, changes: ChangeLog) -> MemoryMovieStore {
+        MemoryMovieStore { changes: SyncRwLock::new(changes), ..self }
+    }
+
+    // Marks the change log's file closed, for stores that keep it in one. See changes.rs.
+    pub fn close_change_log(&self) -> Result<(), StoreError> {
+        self.changes.write().unwrap().close().map_err(|e| StoreError::Backend(format!("change log: {}", e)))
+    }
+
+    // delete_if, with the time to put in the trash with the movie, for stores that have to know it before they write.
+    pub async fn delete_at(&self, id: &str, precondition: Precondition<'_>, deleted_at: String) -> Result<Movie, StoreError> {
+        let mut movies = self.write().await;
+        check_precondition(precondition, movies.get(id).ok_or(StoreError::NotFound)?)?;
+        let movie = Arc::unwrap_or_clone(movies.remove(id).ok_or(StoreError::NotFound)?);
+        for user in self.users.write().unwrap().values_mut() {
+            user.forget(id);
+        }
+        self.links.write().unwrap().retain(|link| !link.touches(id));
+        // A movie deleted again after being added back under the same id takes the older one's place.
+        self.trash.write().unwrap().insert(id.to_string(), Trashed { deleted_at, movie: movie.clone() });
+        self.committed(Some(&movie), None);
+        Ok(movie)
+    }
+
+    pub fn is_trashed(&self, id: &str) -> bool {
+        self.trash.read().unwrap().contains_key(id)
+    }
+
+    // The ids of the movies that went in the trash before `deleted_before`, which purge would delete for good.
+    pub fn trashed_before(&self, deleted_before: OffsetDateTime) -> Vec<String> {
+        self.trash.read().unwrap().values()
+            // Stamped by us, so they always parse.
+            .filter(|trashed| OffsetDateTime::parse(&trashed.deleted_at, &Rfc3339).is_ok_and(|deleted_at| deleted_at < deleted_before))
+            .map(|trashed| trashed.movie.id.clone())
+            .collect()
+    }
+
+    // Fails like link would, without linking anything, for stores that have to know before they write.
+    pub async fn check_link(&self, link: &Link) -> Result<bool, StoreError> {
+        check_link(&*self.read().await, &self.links.read().unwrap(), link)
+    }
+}
+
+impl MemoryMovieStore {
+    // Every lock acquisition goes through these two, so time spent waiting shows up in /metrics.
+    async fn read(&self) -> RwLockReadGuard<'_, Movies> {
+        let started = Instant::now();
+        let movies = self.movies.read().await;
+        metrics::record_lock_wait(Lock::MoviesRead, started.elapsed());
+        movies
+    }
+
+    async fn write(&self) -> RwLockWriteGuard<'_, Movies> {
+        let started = Instant::now();
+        let movies = self.movies.write().await;
+        metrics::record_lock_wait(Lock::MoviesWrite, started.elapsed());
+        movies
+    }
+
+    fn reindex(&self, old: Option<&Movie>, new: Option<&Movie>) {
+        self.names.write().unwrap().replace(old, new);
+        self.genres.write().unwrap().replace(old, new);
+        self.tags.write().unwrap().replace(old, new);
+    }
+
+    // Everything that has to follow a movie going from `old` to `new` in the same order as the map does, and so with
+    // the write lock still held: the indexes, the audit log and the event.
+    fn committed(&self, old: Option<&Movie>, new: Option<&Movie>) {
+        self.reindex(old, new);
+        let event = match (old, new) {
+            (None, Some(new)) => MovieEvent::Created(new.clone()),
+            (Some(_), Some(new)) => MovieEvent::Updated(new.clone()),
+            (Some(old), None) => MovieEvent::Deleted(old.clone()),
+            (None, None) => return,
+        };
+        audit::record("movie", &event.movie().id, old, new);
+        self.publish(event);
+    }
+
+    fn publish(&self, event: MovieEvent) {
+        self.changes.write().unwrap().record(&event);
+        // Fails only when nobody is subscribed, which is fine.
+        let _ = self.events.send(event);
+    }
+}
+
+// A store in the audit log, when it's replaced with a backup.
+#[derive(Serialize)]
+struct BackupSize {
+    movies: usize,
+    users: usize,
+    links: usize,
+    trash: usize,
+}
+
+// A link in the audit log, e.g. "aliens sequel-of alien".
+fn audit_id(link: &Link) -> String {
+    format!("{} {} {}", link.from, link.relation.name(), link.to)
+}
+
+impl Default for MemoryMovieStore {
+    fn default() -> MemoryMovieStore {
+        MemoryMovieStore::new()
+    }
+}
+
+impl MovieStore for MemoryMovieStore {
+    fn get<'a>(&'a self, id: &'a str) -> StoreFuture<'a, Option<Movie>> {
+        Box::pin(async move {
+            let movie = self.read().await.get(id).cloned();
+            movie.map(Arc::unwrap_or_clone)
+        }.instrument(info_span!("memory_store.get")))
+    }
+
+    fn insert(&self, movie: Movie) -> StoreFuture<'_, Result<(), StoreError>> {
+        Box::pin(async move {
+            let mut movies = self.write().await;
+            if let Some(existing) = movies.get(&movie.id) {
+                return Err(StoreError::AlreadyExists(Box::new(Movie::clone(existing))));
+            }
+            debug!("Inserting movie {}, {} movies stored", movie.id, movies.len());
+            let movie = Arc::new(movie);
+            movies.insert(movie.id.clone(), movie.clone());
+            self.committed(None, Some(&movie));
+            Ok(())
+        }.instrument(info_span!("memory_store.insert")))
+    }
+
+    fn upsert(&self, mut movie: Movie) -> StoreFuture<'_, Result<bool, StoreError>> {
+        Box::pin(async move {
+            let mut movies = self.write().await;
+            movie.succeed(movies.get(&movie.id).map(Arc::as_ref));
+            let movie = Arc::new(movie);
+            let previous = movies.insert(movie.id.clone(), movie.clone());
+            self.committed(previous.as_deref(), Some(&movie));
+            Ok(previous.is_none())
+        }.instrument(info_span!("memory_store.upsert")))
+    }
+
+    fn put(&self, movie: Movie) -> StoreFuture<'_, Result<bool, StoreError>> {
+        Box::pin(async move {
+            let mut movies = self.write().await;
+            let movie = Arc::new(movie);
+            let previous = movies.insert(movie.id.clone(), movie.clone());
+            self.committed(previous.as_deref(), Some(&movie));
+            Ok(previous.is_none())
+        }.instrument(info_span!("memory_store.put")))
+    }
+
+    fn update_if<'a>(&'a self, mut movie: Movie, precondition: Precondition<'a>) -> StoreFuture<'a, Result<(), StoreError>> {
+        Box::pin(async move {
+            match self.write().await.get_mut(&movie.id) {
+                Some(existing) => {
+                    check_precondition(precondition, existing)?;
+                    movie.succeed(Some(existing));
+                    let previous = std::mem::replace(existing, Arc::new(movie));
+                    self.committed(Some(&previous), Some(existing));
+                    Ok(())
+                },
+                None => Err(StoreError::NotFound),
+            }
+        }.instrument(info_span!("memory_store.update")))
+    }
+
+    fn patch<'a>(&'a self, id: &'a str, patch: MoviePatch) -> StoreFuture<'a, Result<Movie, StoreError>> {
+        Box::pin(async move {
+            // Hold the lock across read-modify-write so concurrent patches can't interleave.
+            let mut movies = self.write().await;
+            let movie = movies.get_mut(id).ok_or(StoreError::NotFound)?;
+            if !patch.expects(movie) {
+                return Err(StoreError::PreconditionFailed(Box::new(Movie::clone(movie))));
+            }
+            // Copied on write, since `before` still points at the movie as it was.
+            let before = movie.clone();
+            patch.apply(Arc::make_mut(movie));
+            self.committed(Some(&before), Some(movie));
+            Ok(Movie::clone(movie))
+        }.instrument(info_span!("memory_store.patch")))
+    }
+
+    fn delete_if<'a>(&'a self, id: &'a str, precondition: Precondition<'a>) -> StoreFuture<'a, Result<Movie, StoreError>> {
+        Box::pin(async move {
+            let deleted_at = OffsetDateTime::now_utc().format(&Rfc3339).unwrap_or_default();
+            self.delete_at(id, precondition, deleted_at).await
+        }.instrument(info_span!("memory_store.delete")))
+    }
+
+    fn trash(&self) -> StoreFuture<'_, Vec<Trashed>> {
+        Box::pin(async move {
+            self.trash.read().unwrap().values().cloned().collect()
+        }.instrument(info_span!("memory_store.trash")))
+    }
+
+    // Comes back as it was deleted, at the same version, so ETags from before still match it.
+    fn restore<'a>(&'a self, id: &'a str) -> StoreFuture<'a, Result<Movie, StoreError>> {
+        Box::pin(async move {
+            let mut movies = self.write().await;
+            if !self.is_trashed(id) {
+                return Err(StoreError::NotFound);
+            }
+            if let Some(existing) = movies.get(id) {
+                return Err(StoreError::AlreadyExists(Box::new(Movie::clone(existing))));
+            }
+            let movie = self.trash.write().unwrap().remove(id).ok_or(StoreError::NotFound)?.movie;
+            movies.insert(movie.id.clone(), Arc::new(movie.clone()));
+            self.committed(None, Some(&movie));
+            Ok(movie)
+        }.instrument(info_span!("memory_store.restore")))
+    }
+
+    // Purging isn't audited, the delete that put the movie in the trash already was.
+    fn purge(&self, deleted_before: OffsetDateTime) -> StoreFuture<'_, Result<Vec<String>, StoreError>> {
+        Box::pin(async move {
+            let _movies = self.write().await;
+            let ids = self.trashed_before(deleted_before);
+            let mut trash = self.trash.write().unwrap();
+            for id in &ids {
+                trash.remove(id);
+            }
+            Ok(ids)
+        }.instrument(info_span!("memory_store.purge")))
+    }
+
+    // Users, links and the trash only change with the movies locked or on their own, so with all of them read-locked
+    // at once nothing can be halfway through a write.
+    fn backup(&self) -> StoreFuture<'_, Backup> {
+        Box::pin(async move {
+            let (movies, users, links, trash) = {
+                let movies = self.read().await;
+                let users = self.users.read().unwrap();
+                let links = self.links.read().unwrap();
+                let trash = self.trash.read().unwrap();
+                (movies.values().cloned().collect::<Vec<_>>(), users.values().cloned().collect(), links.iter().cloned().collect(), trash.values().cloned().collect())
+            };
+            Backup { movies: movies.into_iter().map(Arc::unwrap_or_clone).collect(), users, links, trash }
+        }.instrument(info_span!("memory_store.backup")))
+    }
+
+    // Audited as a single change to the store, with how many of each thing it held before and after.
+    fn replace(&self, backup: Backup) -> StoreFuture<'_, Result<(), StoreError>> {
+        Box::pin(async move {
+            let mut movies = self.write().await;
+            let mut users = self.users.write().unwrap();
+            let mut links = self.links.write().unwrap();
+            let mut trash = self.trash.write().unwrap();
+            let before = BackupSize { movies: movies.len(), users: users.len(), links: links.len(), trash: trash.len() };
+            let new: Movies = backup.movies.into_iter().map(|movie| (movie.id.clone(), Arc::new(movie))).collect();
+            let changes = movie_changes(&movies, &new);
+            *self.names.write().unwrap() = NameIndex::new(new.values().map(Arc::as_ref));
+            *self.genres.write().unwrap() = LabelIndex::genres(new.values().map(Arc::as_ref));
+            *self.tags.write().unwrap() = LabelIndex::tags(new.values().map(Arc::as_ref));
+            *movies = new;
+            *users = backup.users.into_iter().map(|user| (user.id.clone(), user)).collect();
+            *links = backup.links.into_iter().collect();
+            *trash = backup.trash.into_iter().map(|trashed| (trashed.movie.id.clone(), trashed)).collect();
+            let after = BackupSize { movies: movies.len(), users: users.len(), links: links.len(), trash: trash.len() };
+            audit::record("store", "backup", Some(&before), Some(&after));
+            for change in changes {
+                self.publish(change);
+            }
+            Ok(())
+        }.instrument(info_span!("memory_store.replace")))
+    }
+
+    fn list<'a>(&'a self, filter: &'a MovieFilter) -> StoreFuture<'a, Vec<Movie>> {
+        Box::pin(async move {
+            let matching: Vec<Arc<Movie>> = {
+                let movies = self.read().await;
+                // With a tag the index narrows it down to the movies that have it, the rest of the filter still applies.
+                match &filter.tag {
+                    Some(tag) => {
+                        let ids = self.tags.read().unwrap().movie_ids(tag);
+                        ids.iter().filter_map(|id| movies.get(id)).filter(|movie| filter.matches(movie)).cloned().collect()
+                    },
+                    None => movies.values().filter(|movie| filter.matches(movie)).cloned().collect(),
+                }
+            };
+            matching.into_iter().map(Arc::unwrap_or_clone).collect()
+        }.instrument(info_span!("memory_store.list")))
+    }
+
+    fn scan<'a>(&'a self, after: Option<&'a str>, limit: usize) -> StoreFuture<'a, Vec<Movie>> {
+        Box::pin(async move {
+            let start = after.map_or(Bound::Unbounded, Bound::Excluded);
+            let movies: Vec<Arc<Movie>> = self.read().await.range::<str, _>((start, Bound::Unbounded))
+                .take(limit)
+                .map(|(_, movie)| movie.clone())
+                .collect();
+            movies.into_iter().map(Arc::unwrap_or_clone).collect()
+        }.instrument(info_span!("memory_store.scan")))
+    }
+
+    fn suggest<'a>(&'a self, prefix: &'a str, limit: usize) -> StoreFuture<'a, Vec<String>> {
+        Box::pin(async move {
+            self.names.read().unwrap().suggest(prefix, limit)
+        }.instrument(info_span!("memory_store.suggest")))
+    }
+
+    fn genres(&self) -> StoreFuture<'_, Vec<Label>> {
+        Box::pin(async move {
+            self.genres.read().unwrap().labels()
+        }.instrument(info_span!("memory_store.genres")))
+    }
+
+    fn genre_movie_ids<'a>(&'a self, genre: &'a str) -> StoreFuture<'a, Vec<String>> {
+        Box::pin(async move {
+            self.genres.read().unwrap().movie_ids(genre)
+        }.instrument(info_span!("memory_store.genre_movie_ids")))
+    }
+
+    fn tags(&self) -> StoreFuture<'_, Vec<Label>> {
+        Box::pin(async move {
+            self.tags.read().unwrap().labels()
+        }.instrument(info_span!("memory_store.tags")))
+    }
+
+    fn count(&self) -> StoreFuture<'_, usize> {
+        Box::pin(async move {
+            self.read().await.len()
+        }.instrument(info_span!("memory_store.count")))
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<MovieEvent> {
+        self.events.subscribe()
+    }
+
+    fn changes(&self, since: &Since, limit: usize) -> Result<ChangePage, CursorExpired> {
+        self.changes.read().unwrap().page(since, limit)
+    }
+
+    fn get_user<'a>(&'a self, id: &'a str) -> StoreFuture<'a, Option<User>> {
+        Box::pin(async move {
+            self.users.read().unwrap().get(id).cloned()
+        }.instrument(info_span!("memory_store.get_user")))
+    }
+
+    fn insert_user(&self, user: User) -> StoreFuture<'_, Result<(), StoreError>> {
+        Box::pin(async move {
+            let mut users = self.users.write().unwrap();
+            if let Some(existing) = users.get(&user.id) {
+                return Err(StoreError::UserAlreadyExists(Box::new(existing.clone())));
+            }
+            audit::record("user", &user.id, None, Some(&StoredUser::from(user.clone())));
+            users.insert(user.id.clone(), user);
+            Ok(())
+        }.instrument(info_span!("memory_store.insert_user")))
+    }
+
+    fn change_user<'a>(&'a self, user_id: &'a str, change: UserChange) -> StoreFuture<'a, Result<User, StoreError>> {
+        Box::pin(async move {
+            // Held until the user has changed, so the movie can't be deleted in between.
+            let movies = self.read().await;
+            let mut users = self.users.write().unwrap();
+            let user = users.get_mut(user_id).ok_or(StoreError::UserNotFound)?;
+            if change.added_movie().is_some_and(|movie_id| !movies.contains_key(movie_id)) {
+                return Err(StoreError::NotFound);
+            }
+            let before = StoredUser::from(user.clone());
+            user.apply(change);
+            audit::record("user", user_id, Some(&before), Some(&StoredUser::from(user.clone())));
+            Ok(user.clone())
+        }.instrument(info_span!("memory_store.change_user")))
+    }
+
+    fn links<'a>(&'a self, movie_id: &'a str) -> StoreFuture<'a, Vec<Link>> {
+        Box::pin(async move {
+            self.links.read().unwrap().iter().filter(|link| link.touches(movie_id)).cloned().collect()
+        }.instrument(info_span!("memory_store.links")))
+    }
+
+    fn link(&self, link: Link) -> StoreFuture<'_, Result<bool, StoreError>> {
+        Box::pin(async move {
+            // Held until it's linked, so neither movie can be deleted in between.
+            let movies = self.read().await;
+            let mut links = self.links.write().unwrap();
+            let created = check_link(&movies, &links, &link)?;
+            if created {
+                audit::record("link", &audit_id(&link), None, Some(&link));
+            }
+            links.insert(link);
+            Ok(created)
+        }.instrument(info_span!("memory_store.link")))
+    }
+
+    fn unlink<'a>(&'a self, link: &'a Link) -> StoreFuture<'a, Result<bool, StoreError>> {
+        Box::pin(async move {
+            let removed = self.links.write().unwrap().remove(link);
+            if removed {
+                audit::record("link", &audit_id(link), Some(link), None);
+            }
+            Ok(removed)
+        }.instrument(info_span!("memory_store.unlink")))
+    }
+}
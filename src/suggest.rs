@@ -0,0 +1,58 @@
+use std::collections::BTreeMap;
+
+use crate::model::Movie;
+use crate::title;
+
+// Movie names in order ignoring case and accents, for autocompleting them from the start as they're typed. Stores keep one up to
+// date as they write, so a suggestion is a range lookup instead of a pass over every movie.
+#[derive(Debug, Default)]
+pub struct NameIndex {
+    // Keyed by the name as title::fold has it and then the id, since several movies can share a name.
+    names: BTreeMap<(String, String), String>,
+}
+
+impl NameIndex {
+    pub fn new<'a>(movies: impl IntoIterator<Item = &'a Movie>) -> NameIndex {
+        let mut index = NameIndex::default();
+        for movie in movies {
+            index.insert(movie);
+        }
+        index
+    }
+
+    pub fn insert(&mut self, movie: &Movie) {
+        self.names.insert((title::fold(&movie.name), movie.id.clone()), movie.name.clone());
+    }
+
+    pub fn remove(&mut self, movie: &Movie) {
+        self.names.remove(&(title::fold(&movie.name), movie.id.clone()));
+    }
+
+    // Replaces what was indexed for `old` with `new`, either of which may be missing for creates and deletes.
+    pub fn replace(&mut self, old: Option<&Movie>, new: Option<&Movie>) {
+        if let Some(old) = old {
+            self.remove(old);
+        }
+        if let Some(new) = new {
+            self.insert(new);
+        }
+    }
+
+    // Up to `limit` names starting with `prefix`, ignoring case and accents, in alphabetical order. Names that differ
+    // only in case or accents are suggested once.
+    pub fn suggest(&self, prefix: &str, limit: usize) -> Vec<String> {
+        let prefix = title::fold(prefix);
+        let mut suggestions: Vec<String> = Vec::new();
+        let mut last: Option<&str> = None;
+        for ((key, _), name) in self.names.range((prefix.clone(), String::new())..) {
+            if suggestions.len() == limit || !key.starts_with(&prefix) {
+                break;
+            }
+            if last != Some(key.as_str()) {
+                suggestions.push(name.clone());
+                last = Some(key);
+            }
+        }
+        suggestions
+    }
+}
@@ -0,0 +1,335 @@
+use std::{cell::RefCell, collections::HashMap, fmt, io::Write, str::FromStr, sync::{atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering}, Mutex}, time::{Instant, SystemTime}};
+use axum::{extract::{MatchedPath, Request}, middleware::Next, response::Response};
+use serde_json::{Map, Value};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use tracing::{field::{Empty, Field, Visit}, info, info_span, level_filters::LevelFilter, span::{Attributes, Id, Record}, subscriber::Interest, Event, Instrument, Level, Metadata, Subscriber};
+
+use crate::otel::{self, FinishedSpan};
+use crate::random;
+use crate::request_id::RequestId;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogFormat {
+    // One human-readable line per event.
+    Text,
+    // One JSON object per line, for log aggregation.
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<LogFormat, String> {
+        match format {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            other => Err(format!("expected text or json, got {:?}", other)),
+        }
+    }
+}
+
+// Both can change while the server runs, see set_level and set_format. The level is stored as level_rank.
+static MAX_LEVEL: AtomicU8 = AtomicU8::new(3);
+static JSON: AtomicBool = AtomicBool::new(false);
+
+// Installs the subscriber that writes every enabled event to stdout, along with the spans it happened in.
+pub fn init(level: LevelFilter, format: LogFormat) {
+    set_level(level);
+    set_format(format);
+    let subscriber = LogSubscriber {
+        spans: Mutex::new(HashMap::new()),
+        next_id: AtomicU64::new(1),
+    };
+    tracing::subscriber::set_global_default(subscriber).expect("a tracing subscriber was already installed");
+}
+
+pub fn set_level(level: LevelFilter) {
+    MAX_LEVEL.store(level_rank(level), Ordering::Relaxed);
+}
+
+pub fn set_format(format: LogFormat) {
+    JSON.store(format == LogFormat::Json, Ordering::Relaxed);
+}
+
+fn level_rank(level: LevelFilter) -> u8 {
+    match level.into_level() {
+        None => 0,
+        Some(Level::ERROR) => 1,
+        Some(Level::WARN) => 2,
+        Some(Level::INFO) => 3,
+        Some(Level::DEBUG) => 4,
+        Some(Level::TRACE) => 5,
+    }
+}
+
+// Middleware running each request in a span with its id, method, path and matched route. The status and latency are added
+// to the span once the response is ready, and logged along with it.
+pub async fn trace_requests(request: Request, next: Next) -> Response {
+    let route = request.extensions().get::<MatchedPath>().map(|path| path.as_str().to_string());
+    let request_id = request.extensions().get::<RequestId>().map(|id| id.0.clone());
+    let span = info_span!("request", request_id = request_id, method = %request.method(), path = %request.uri().path(), route = route, status = Empty, latency_ms = Empty);
+    let started = Instant::now();
+    let response = next.run(request).instrument(span.clone()).await;
+    span.record("status", response.status().as_u16());
+    span.record("latency_ms", started.elapsed().as_micros() as f64 / 1000.0);
+    span.in_scope(|| info!("finished processing request"));
+    response
+}
+
+type Fields = Vec<(&'static str, Value)>;
+
+struct SpanData {
+    name: &'static str,
+    fields: Fields,
+    parent: Option<Id>,
+    // Handles to this span plus children still pointing at it. The span is dropped when this gets to zero.
+    refs: usize,
+    // Only filled in when spans are exported, see otel::enabled.
+    trace: Option<TraceContext>,
+}
+
+struct TraceContext {
+    trace_id: [u8; 16],
+    span_id: [u8; 8],
+    start: SystemTime,
+}
+
+struct LogSubscriber {
+    spans: Mutex<HashMap<u64, SpanData>>,
+    next_id: AtomicU64,
+}
+
+thread_local! {
+    // Spans entered on this thread, innermost last. Instrumented futures enter on every poll and exit after, so this
+    // is always right for whatever is currently running.
+    static CURRENT: RefCell<Vec<Id>> = const { RefCell::new(Vec::new()) };
+}
+
+fn current_span() -> Option<Id> {
+    CURRENT.with(|current| current.borrow().last().cloned())
+}
+
+impl LogSubscriber {
+    // The span and all its ancestors, outermost first.
+    fn span_chain(&self, mut id: Option<Id>) -> Vec<(&'static str, Fields)> {
+        let spans = self.spans.lock().unwrap();
+        let mut chain = Vec::new();
+        while let Some(data) = id.and_then(|id| spans.get(&id.into_u64())) {
+            chain.push((data.name, data.fields.clone()));
+            id = data.parent.clone();
+        }
+        chain.reverse();
+        chain
+    }
+
+    fn release(&self, id: &Id) -> bool {
+        let mut spans = self.spans.lock().unwrap();
+        let mut next = Some(id.clone());
+        let mut closed = false;
+        while let Some(id) = next.take() {
+            let Some(data) = spans.get_mut(&id.into_u64()) else { break };
+            data.refs -= 1;
+            if data.refs > 0 {
+                break;
+            }
+            // The parent loses the reference this span held on it.
+            let data = spans.remove(&id.into_u64()).unwrap();
+            if let Some(trace) = data.trace {
+                let parent_span_id = data.parent.as_ref()
+                    .and_then(|parent| spans.get(&parent.into_u64()))
+                    .and_then(|parent| parent.trace.as_ref())
+                    .map(|parent| parent.span_id);
+                otel::record(FinishedSpan {
+                    name: data.name,
+                    trace_id: trace.trace_id,
+                    span_id: trace.span_id,
+                    parent_span_id,
+                    start: trace.start,
+                    end: SystemTime::now(),
+                    attributes: data.fields,
+                });
+            }
+            next = data.parent;
+            closed = true;
+        }
+        closed
+    }
+}
+
+impl Subscriber for LogSubscriber {
+    // "sometimes" keeps tracing asking enabled() every time, so level changes take effect straight away.
+    fn register_callsite(&self, _: &'static Metadata<'static>) -> Interest {
+        Interest::sometimes()
+    }
+
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        level_rank(LevelFilter::from_level(*metadata.level())) <= MAX_LEVEL.load(Ordering::Relaxed)
+    }
+
+    fn new_span(&self, attributes: &Attributes<'_>) -> Id {
+        let mut fields = Vec::new();
+        attributes.record(&mut FieldVisitor(&mut fields));
+        let parent = if attributes.is_root() { None } else { attributes.parent().cloned().or_else(current_span) };
+        let id = Id::from_u64(self.next_id.fetch_add(1, Ordering::Relaxed));
+        let mut spans = self.spans.lock().unwrap();
+        let mut parent_trace_id = None;
+        if let Some(parent) = parent.as_ref().and_then(|parent| spans.get_mut(&parent.into_u64())) {
+            parent.refs += 1;
+            parent_trace_id = parent.trace.as_ref().map(|trace| trace.trace_id);
+        }
+        let trace = otel::enabled().then(|| {
+            // Children carry on their parent's trace, root spans start a new one.
+            let trace_id = parent_trace_id.unwrap_or_else(|| {
+                let mut trace_id = [0; 16];
+                random::fill_bytes(&mut trace_id);
+                trace_id
+            });
+            let mut span_id = [0; 8];
+            random::fill_bytes(&mut span_id);
+            TraceContext { trace_id, span_id, start: SystemTime::now() }
+        });
+        spans.insert(id.into_u64(), SpanData { name: attributes.metadata().name(), fields, parent, refs: 1, trace });
+        id
+    }
+
+    fn record(&self, span: &Id, values: &Record<'_>) {
+        if let Some(data) = self.spans.lock().unwrap().get_mut(&span.into_u64()) {
+            values.record(&mut FieldVisitor(&mut data.fields));
+        }
+    }
+
+    fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut fields = Vec::new();
+        event.record(&mut FieldVisitor(&mut fields));
+        let parent = if event.is_root() { None } else { event.parent().cloned().or_else(current_span) };
+        let spans = self.span_chain(parent);
+        let metadata = event.metadata();
+        let timestamp = OffsetDateTime::now_utc().format(&Rfc3339).unwrap_or_default();
+        let mut line = if JSON.load(Ordering::Relaxed) {
+            json_line(&timestamp, metadata, fields, spans)
+        }
+        else {
+            text_line(&timestamp, metadata, fields, spans)
+        };
+        line.push('\n');
+        // One write per line keeps lines from different threads from interleaving.
+        let _ = std::io::stdout().lock().write_all(line.as_bytes());
+    }
+
+    fn enter(&self, span: &Id) {
+        CURRENT.with(|current| current.borrow_mut().push(span.clone()));
+    }
+
+    fn exit(&self, span: &Id) {
+        CURRENT.with(|current| {
+            let mut current = current.borrow_mut();
+            if let Some(position) = current.iter().rposition(|id| id == span) {
+                current.remove(position);
+            }
+        });
+    }
+
+    fn clone_span(&self, span: &Id) -> Id {
+        if let Some(data) = self.spans.lock().unwrap().get_mut(&span.into_u64()) {
+            data.refs += 1;
+        }
+        span.clone()
+    }
+
+    fn try_close(&self, span: Id) -> bool {
+        self.release(&span)
+    }
+}
+
+// e.g. 2025-01-01T12:00:00.123456Z INFO  [syndica_rust::routes] request{method=GET path=/movie/a}: Adding movie A
+fn text_line(timestamp: &str, metadata: &Metadata<'_>, fields: Fields, spans: Vec<(&'static str, Fields)>) -> String {
+    let mut line = format!("{} {:<5} [{}] ", timestamp, metadata.level(), metadata.target());
+    for (name, fields) in &spans {
+        line.push_str(name);
+        if !fields.is_empty() {
+            let fields: Vec<String> = fields.iter().map(|(key, value)| format!("{}={}", key, text_value(value))).collect();
+            line.push_str(&format!("{{{}}}", fields.join(" ")));
+        }
+        line.push_str(": ");
+    }
+    let mut rest = Vec::new();
+    for (key, value) in &fields {
+        if *key == "message" {
+            line.push_str(&text_value(value));
+        }
+        else {
+            rest.push(format!("{}={}", key, text_value(value)));
+        }
+    }
+    if !rest.is_empty() {
+        line.push(' ');
+        line.push_str(&rest.join(" "));
+    }
+    line
+}
+
+fn text_value(value: &Value) -> String {
+    match value {
+        Value::String(string) => string.clone(),
+        other => other.to_string(),
+    }
+}
+
+// {"timestamp": ..., "level": "INFO", "target": ..., "fields": {"message": ...}, "spans": [{"name": "request", ...}]}
+fn json_line(timestamp: &str, metadata: &Metadata<'_>, fields: Fields, spans: Vec<(&'static str, Fields)>) -> String {
+    let spans: Vec<Value> = spans.into_iter()
+        .map(|(name, fields)| {
+            let mut span: Map<String, Value> = fields.into_iter().map(|(key, value)| (key.to_string(), value)).collect();
+            span.insert("name".to_string(), Value::from(name));
+            Value::Object(span)
+        })
+        .collect();
+    let fields: Map<String, Value> = fields.into_iter().map(|(key, value)| (key.to_string(), value)).collect();
+    serde_json::json!({
+        "timestamp": timestamp,
+        "level": metadata.level().as_str(),
+        "target": metadata.target(),
+        "fields": fields,
+        "spans": spans,
+    }).to_string()
+}
+
+struct FieldVisitor<'a>(&'a mut Fields);
+
+impl FieldVisitor<'_> {
+    // Recording a field again, e.g. a span's status once it is known, replaces the old value.
+    fn set(&mut self, field: &Field, value: Value) {
+        match self.0.iter_mut().find(|(name, _)| *name == field.name()) {
+            Some((_, existing)) => *existing = value,
+            None => self.0.push((field.name(), value)),
+        }
+    }
+}
+
+impl Visit for FieldVisitor<'_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.set(field, Value::String(format!("{:?}", value)));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.set(field, Value::from(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.set(field, Value::from(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.set(field, Value::from(value));
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.set(field, Value::from(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.set(field, Value::from(value));
+    }
+}
@@ -0,0 +1,312 @@
+use std::{collections::BTreeMap, future::Future};
+use axum::{extract::{Request, State}, http::HeaderName, middleware::Next, response::{IntoResponse, Response}};
+use serde::Serialize;
+use time::OffsetDateTime;
+use tokio::sync::broadcast;
+
+use crate::auth::{Caller, Role};
+use crate::error::ApiError;
+use crate::changes::{ChangePage, CursorExpired, Since};
+use crate::events::MovieEvent;
+use crate::labels::Label;
+use crate::model::{Link, Movie, MoviePatch, Revision, Trashed, User, UserChange};
+use crate::state::StateWrapper;
+use crate::store::{Backup, MovieFilter, MovieStore, Precondition, StoreError, StoreFuture};
+
+// One deployment serving several teams' catalogs. Each tenant declared with --tenants gets a store of its own, and
+// every request works on one of them: the tenant its API key is for, see --tenant-api-keys, or the one named by its
+// X-Tenant-Id header. Requests with neither get the default catalog, which is all there is without --tenants.
+//
+// Jobs that run outside of requests, like the seed file, webhooks, publishing changes and scheduled S3 backups, only
+// see the default catalog. Purging the trash goes through every tenant's.
+
+pub static TENANT_HEADER: HeaderName = HeaderName::from_static("x-tenant-id");
+
+// The catalog requests without a tenant get. Reserved, so that it can be listed alongside the others.
+pub const DEFAULT_TENANT: &str = "default";
+
+const MAX_ID_LEN: usize = 63;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TenantConfig {
+    pub id: String,
+    // Most movies the tenant may have at once, see quota.rs. None for no limit.
+    pub max_movies: Option<usize>,
+}
+
+impl TenantConfig {
+    // Parses one entry of --tenants: the id, with :<max movies> after it for a quota.
+    pub fn parse(entry: &str) -> Result<TenantConfig, String> {
+        let (id, max_movies) = match entry.split_once(':') {
+            Some((id, max)) => (id, Some(max.trim().parse::<usize>().map_err(|e| format!("can't parse the quota of {:?}: {}", id, e))?)),
+            None => (entry, None),
+        };
+        let id = id.trim();
+        check_id(id)?;
+        Ok(TenantConfig { id: id.to_string(), max_movies })
+    }
+}
+
+// Tenant ids go in file names and headers, so they're kept to what's safe in both.
+pub fn check_id(id: &str) -> Result<(), String> {
+    if id.is_empty() || id.len() > MAX_ID_LEN || !id.bytes().all(|byte| byte.is_ascii_lowercase() || byte.is_ascii_digit() || byte == b'-') {
+        return Err(format!("expected a tenant id of 1 to {} lowercase letters, digits and '-', got {:?}", MAX_ID_LEN, id));
+    }
+    if id == DEFAULT_TENANT {
+        return Err(format!("{:?} is the catalog of requests without a tenant, so can't be declared", DEFAULT_TENANT));
+    }
+    Ok(())
+}
+
+tokio::task_local! {
+    // The tenant the request being handled on this task works on. None, or unset, for the default catalog.
+    static TENANT: Option<String>;
+}
+
+// Runs `future` against the tenant's movies, or the default catalog's for None.
+pub async fn scope<F: Future>(tenant: Option<String>, future: F) -> F::Output {
+    TENANT.scope(tenant, future).await
+}
+
+pub fn current() -> Option<String> {
+    TENANT.try_with(Clone::clone).ok().flatten()
+}
+
+// `key` made unique across tenants, for things kept by id or key outside of the stores, like the cache. A space can't
+// be in movie ids or Idempotency-Keys, so no key of the default catalog can be mistaken for a tenant's.
+pub fn namespaced(key: &str) -> String {
+    match current() {
+        Some(tenant) => format!("{} {}", tenant, key),
+        None => key.to_string(),
+    }
+}
+
+// Middleware inside auth::authorize, which has put the Caller in the extensions. Runs the rest of the request in the
+// tenant the caller's key is for, or else the one their X-Tenant-Id names. A key's tenant can't be swapped for
+// another one with the header. When the server has keys or tokens at all, only callers who showed one may pick a
+// tenant by header, so anonymous readers don't get into anyone's catalog. The tenants there are to pick are the store's.
+pub async fn select_tenant(State(state): State<StateWrapper>, request: Request, next: Next) -> Response {
+    let requested = match request.headers().get(&TENANT_HEADER).map(|value| value.to_str()) {
+        None => None,
+        Some(Ok(DEFAULT_TENANT)) => Some(None),
+        Some(Ok(id)) if check_id(id).is_ok() => Some(Some(id.to_string())),
+        Some(_) => return ApiError::BadRequest(format!("{} must be a tenant id of 1 to {} lowercase letters, digits and '-'", TENANT_HEADER, MAX_ID_LEN)).into_response(),
+    };
+    // Routes anyone may call hold nothing of any tenant's.
+    let Some(caller) = request.extensions().get::<Caller>() else {
+        return next.run(request).await;
+    };
+    let tenant = match (&caller.tenant, requested) {
+        (Some(bound), Some(requested)) if requested.as_ref() != Some(bound) => {
+            return ApiError::Forbidden(format!("This API key is only for the tenant {}", bound)).into_response();
+        },
+        (Some(bound), _) => Some(bound.clone()),
+        (None, Some(Some(_))) if !caller.authenticated && caller.role != Some(Role::Admin) => {
+            return ApiError::Unauthorized(format!("Picking a tenant with {} needs a bearer token or API key", TENANT_HEADER)).into_response();
+        },
+        (None, Some(requested)) => requested,
+        (None, None) => None,
+    };
+    if let Some(tenant) = &tenant
+        && !state.tenants().iter().any(|declared| declared.id == *tenant) {
+        return ApiError::NotFound(format!("There's no tenant {}", tenant)).into_response();
+    }
+    scope(tenant, next.run(request)).await
+}
+
+// How full each catalog is. Backs GET /admin/tenants and the per-tenant gauge in /metrics.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TenantStats {
+    pub id: String,
+    pub movies: usize,
+    pub trashed: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_movies: Option<usize>,
+}
+
+// The default catalog's stats, then every declared tenant's in id order.
+pub async fn stats(store: &dyn MovieStore) -> Vec<TenantStats> {
+    let counts = async |tenant: Option<String>| scope(tenant, async { (store.count().await, store.trash().await.len()) }).await;
+    let (movies, trashed) = counts(None).await;
+    let mut stats = vec![TenantStats { id: DEFAULT_TENANT.to_string(), movies, trashed, max_movies: None }];
+    let mut tenants = store.tenants();
+    tenants.sort_by(|a, b| a.id.cmp(&b.id));
+    for tenant in tenants {
+        let (movies, trashed) = counts(Some(tenant.id.clone())).await;
+        stats.push(TenantStats { id: tenant.id, movies, trashed, max_movies: tenant.max_movies });
+    }
+    stats
+}
+
+// Sends every call on to the store of the tenant the request is for, see select_tenant. Tenants' movies, users, links
+// and trash are kept apart entirely, and can be in stores of different kinds.
+pub struct TenantMovieStore {
+    default: StateWrapper,
+    tenants: BTreeMap<String, StateWrapper>,
+    configs: Vec<TenantConfig>,
+}
+
+impl TenantMovieStore {
+    // Takes the default catalog's store, and each tenant's with its settings. Quotas are up to quota::QuotaMovieStore.
+    pub fn new(default: StateWrapper, tenants: Vec<(TenantConfig, StateWrapper)>) -> TenantMovieStore {
+        let configs = tenants.iter().map(|(config, _)| config.clone()).collect();
+        TenantMovieStore { default, tenants: tenants.into_iter().map(|(config, store)| (config.id, store)).collect(), configs }
+    }
+
+    fn store(&self) -> &StateWrapper {
+        match current() {
+            // select_tenant only ever picks declared tenants, which all have a store from startup on.
+            Some(tenant) => self.tenants.get(&tenant).unwrap_or_else(|| panic!("no store for the tenant {}", tenant)),
+            None => &self.default,
+        }
+    }
+}
+
+impl MovieStore for TenantMovieStore {
+    fn get<'a>(&'a self, id: &'a str) -> StoreFuture<'a, Option<Movie>> {
+        self.store().get(id)
+    }
+
+    fn insert(&self, movie: Movie) -> StoreFuture<'_, Result<(), StoreError>> {
+        self.store().insert(movie)
+    }
+
+    fn upsert(&self, movie: Movie) -> StoreFuture<'_, Result<bool, StoreError>> {
+        self.store().upsert(movie)
+    }
+
+    fn put(&self, movie: Movie) -> StoreFuture<'_, Result<bool, StoreError>> {
+        self.store().put(movie)
+    }
+
+    fn update_if<'a>(&'a self, movie: Movie, precondition: Precondition<'a>) -> StoreFuture<'a, Result<(), StoreError>> {
+        self.store().update_if(movie, precondition)
+    }
+
+    fn patch<'a>(&'a self, id: &'a str, patch: MoviePatch) -> StoreFuture<'a, Result<Movie, StoreError>> {
+        self.store().patch(id, patch)
+    }
+
+    fn delete_if<'a>(&'a self, id: &'a str, precondition: Precondition<'a>) -> StoreFuture<'a, Result<Movie, StoreError>> {
+        self.store().delete_if(id, precondition)
+    }
+
+    fn trash(&self) -> StoreFuture<'_, Vec<Trashed>> {
+        self.store().trash()
+    }
+
+    fn restore<'a>(&'a self, id: &'a str) -> StoreFuture<'a, Result<Movie, StoreError>> {
+        self.store().restore(id)
+    }
+
+    // The trash purge runs outside of any request, and empties every tenant's trash.
+    fn purge(&self, deleted_before: OffsetDateTime) -> StoreFuture<'_, Result<Vec<String>, StoreError>> {
+        Box::pin(async move {
+            if current().is_some() {
+                return self.store().purge(deleted_before).await;
+            }
+            let mut purged = self.default.purge(deleted_before).await?;
+            for store in self.tenants.values() {
+                purged.extend(store.purge(deleted_before).await?);
+            }
+            Ok(purged)
+        })
+    }
+
+    fn backup(&self) -> StoreFuture<'_, Backup> {
+        self.store().backup()
+    }
+
+    fn replace(&self, backup: Backup) -> StoreFuture<'_, Result<(), StoreError>> {
+        self.store().replace(backup)
+    }
+
+    fn list<'a>(&'a self, filter: &'a MovieFilter) -> StoreFuture<'a, Vec<Movie>> {
+        self.store().list(filter)
+    }
+
+    fn scan<'a>(&'a self, after: Option<&'a str>, limit: usize) -> StoreFuture<'a, Vec<Movie>> {
+        self.store().scan(after, limit)
+    }
+
+    fn suggest<'a>(&'a self, prefix: &'a str, limit: usize) -> StoreFuture<'a, Vec<String>> {
+        self.store().suggest(prefix, limit)
+    }
+
+    fn genres(&self) -> StoreFuture<'_, Vec<Label>> {
+        self.store().genres()
+    }
+
+    fn tags(&self) -> StoreFuture<'_, Vec<Label>> {
+        self.store().tags()
+    }
+
+    fn genre_movie_ids<'a>(&'a self, genre: &'a str) -> StoreFuture<'a, Vec<String>> {
+        self.store().genre_movie_ids(genre)
+    }
+
+    fn count(&self) -> StoreFuture<'_, usize> {
+        self.store().count()
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<MovieEvent> {
+        self.store().subscribe()
+    }
+
+    fn changes(&self, since: &Since, limit: usize) -> Result<ChangePage, CursorExpired> {
+        self.store().changes(since, limit)
+    }
+
+    fn close(&self) -> StoreFuture<'_, Result<(), StoreError>> {
+        Box::pin(async move {
+            self.default.close().await?;
+            for store in self.tenants.values() {
+                store.close().await?;
+            }
+            Ok(())
+        })
+    }
+
+    // Every tenant's store has to be usable for the server to be ready.
+    fn check_ready(&self) -> StoreFuture<'_, Result<(), StoreError>> {
+        Box::pin(async move {
+            self.default.check_ready().await?;
+            for store in self.tenants.values() {
+                store.check_ready().await?;
+            }
+            Ok(())
+        })
+    }
+
+    fn history<'a>(&'a self, id: &'a str) -> StoreFuture<'a, Option<Vec<Revision>>> {
+        self.store().history(id)
+    }
+
+    fn tenants(&self) -> Vec<TenantConfig> {
+        self.configs.clone()
+    }
+
+    fn get_user<'a>(&'a self, id: &'a str) -> StoreFuture<'a, Option<User>> {
+        self.store().get_user(id)
+    }
+
+    fn insert_user(&self, user: User) -> StoreFuture<'_, Result<(), StoreError>> {
+        self.store().insert_user(user)
+    }
+
+    fn change_user<'a>(&'a self, user_id: &'a str, change: UserChange) -> StoreFuture<'a, Result<User, StoreError>> {
+        self.store().change_user(user_id, change)
+    }
+
+    fn links<'a>(&'a self, movie_id: &'a str) -> StoreFuture<'a, Vec<Link>> {
+        self.store().links(movie_id)
+    }
+
+    fn link(&self, link: Link) -> StoreFuture<'_, Result<bool, StoreError>> {
+        self.store().link(link)
+    }
+
+    fn unlink<'a>(&'a self, link: &'a Link) -> StoreFuture<'a, Result<bool, StoreError>> {
+        self.store().unlink(link)
+    }
+}
@@ -0,0 +1,30 @@
+use std::time::Duration;
+use axum::{extract::{Extension, MatchedPath, Request, State}, middleware::Next, response::{IntoResponse, Response}};
+use tracing::warn;
+
+use crate::error::ApiError;
+use crate::settings::Settings;
+use crate::versioning;
+
+// Bounds how long a request may take to get its response, so a stuck storage backend can't hold connections open
+// forever. A request that takes longer gets a 504 and its handler is dropped wherever it was.
+
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Middleware for the whole router. Routes in the state aren't timed, in any API version, for handlers that read a body
+// for as long as the client takes to send it. Streamed responses are only timed until their headers are sent.
+pub async fn time_out_requests(State(exempt): State<&'static [&'static str]>, Extension(settings): Extension<Settings>, request: Request, next: Next) -> Response {
+    let timeout = settings.timeout();
+    let route = request.extensions().get::<MatchedPath>().map_or(request.uri().path(), MatchedPath::as_str).to_string();
+    if timeout.is_zero() || exempt.contains(&versioning::unversioned_route(&route)) {
+        return next.run(request).await;
+    }
+    let method = request.method().clone();
+    match tokio::time::timeout(timeout, next.run(request)).await {
+        Ok(response) => response,
+        Err(_) => {
+            warn!("{} {} didn't finish within {:?}", method, route, timeout);
+            ApiError::Timeout(timeout).into_response()
+        },
+    }
+}
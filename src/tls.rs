@@ -0,0 +1,344 @@
+use std::{ffi::{c_char, c_int, c_long, c_uint, c_ulong, c_void, CStr, CString}, io, os::unix::ffi::OsStrExt, path::{Path, PathBuf}, pin::Pin, ptr,
+    sync::{Arc, RwLock}, task::{ready, Context, Poll}};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tracing::{error, info};
+
+use crate::shutdown;
+
+// The certificate chain and private key HTTPS is served with, as PEM files. Both are read again on SIGHUP, so a renewed
+// certificate can be put in place without a restart.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TlsConfig {
+    // The server's certificate first, followed by any intermediates.
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
+
+// ALPN protocol lists, each name prefixed with its length, in the order the server prefers them.
+static HTTP2_AND_HTTP1: &[u8] = b"\x02h2\x08http/1.1";
+static HTTP1: &[u8] = b"\x08http/1.1";
+// As much as a TLS record holds, so one read from the socket is at most one record.
+const READ_CHUNK: usize = 16 * 1024;
+
+// As much of libssl and libcrypto as the listener needs.
+#[link(name = "ssl")]
+unsafe extern "C" {
+    fn TLS_server_method() -> *const c_void;
+    fn SSL_CTX_new(method: *const c_void) -> *mut c_void;
+    fn SSL_CTX_free(ctx: *mut c_void);
+    fn SSL_CTX_ctrl(ctx: *mut c_void, command: c_int, larg: c_long, parg: *mut c_void) -> c_long;
+    fn SSL_CTX_use_certificate_chain_file(ctx: *mut c_void, file: *const c_char) -> c_int;
+    fn SSL_CTX_use_PrivateKey_file(ctx: *mut c_void, file: *const c_char, file_type: c_int) -> c_int;
+    fn SSL_CTX_check_private_key(ctx: *const c_void) -> c_int;
+    fn SSL_CTX_set_alpn_select_cb(ctx: *mut c_void, callback: AlpnCallback, argument: *mut c_void);
+    fn SSL_select_next_proto(out: *mut *mut u8, out_len: *mut u8, server: *const u8, server_len: c_uint, client: *const u8, client_len: c_uint) -> c_int;
+    fn SSL_new(ctx: *mut c_void) -> *mut c_void;
+    fn SSL_free(ssl: *mut c_void);
+    fn SSL_set_accept_state(ssl: *mut c_void);
+    // The SSL takes ownership of both BIOs.
+    fn SSL_set_bio(ssl: *mut c_void, read: *mut c_void, write: *mut c_void);
+    fn SSL_read(ssl: *mut c_void, buf: *mut c_void, len: c_int) -> c_int;
+    fn SSL_write(ssl: *mut c_void, buf: *const c_void, len: c_int) -> c_int;
+    fn SSL_shutdown(ssl: *mut c_void) -> c_int;
+    fn SSL_get_error(ssl: *const c_void, result: c_int) -> c_int;
+}
+
+#[link(name = "crypto")]
+unsafe extern "C" {
+    fn BIO_s_mem() -> *const c_void;
+    fn BIO_new(method: *const c_void) -> *mut c_void;
+    fn BIO_free(bio: *mut c_void) -> c_int;
+    fn BIO_read(bio: *mut c_void, buf: *mut c_void, len: c_int) -> c_int;
+    fn BIO_write(bio: *mut c_void, buf: *const c_void, len: c_int) -> c_int;
+    fn ERR_get_error() -> c_ulong;
+    fn ERR_clear_error();
+    fn ERR_error_string_n(error: c_ulong, buf: *mut c_char, len: usize);
+}
+
+type AlpnCallback = extern "C" fn(ssl: *mut c_void, out: *mut *const u8, out_len: *mut u8, client: *const u8, client_len: c_uint, argument: *mut c_void) -> c_int;
+
+const SSL_CTRL_SET_MIN_PROTO_VERSION: c_int = 123;
+const TLS1_2_VERSION: c_long = 0x0303;
+const SSL_FILETYPE_PEM: c_int = 1;
+const OPENSSL_NPN_NEGOTIATED: c_int = 1;
+const SSL_TLSEXT_ERR_OK: c_int = 0;
+const SSL_TLSEXT_ERR_NOACK: c_int = 3;
+const SSL_ERROR_WANT_READ: c_int = 2;
+const SSL_ERROR_ZERO_RETURN: c_int = 6;
+
+// Everything on OpenSSL's error queue for this thread, which empties it.
+fn openssl_error(what: &str) -> io::Error {
+    let mut messages = Vec::new();
+    loop {
+        let code = unsafe { ERR_get_error() };
+        if code == 0 {
+            break;
+        }
+        let mut buf = [0 as c_char; 256];
+        unsafe { ERR_error_string_n(code, buf.as_mut_ptr(), buf.len()) };
+        messages.push(unsafe { CStr::from_ptr(buf.as_ptr()) }.to_string_lossy().into_owned());
+    }
+    match messages.is_empty() {
+        true => io::Error::other(what.to_string()),
+        false => io::Error::other(format!("{}: {}", what, messages.join(", "))),
+    }
+}
+
+// Picks the first protocol in the server's list that the client offered. A client that offers none of them still gets
+// to connect, and hyper works out which HTTP it's speaking.
+extern "C" fn select_alpn(_ssl: *mut c_void, out: *mut *const u8, out_len: *mut u8, client: *const u8, client_len: c_uint, argument: *mut c_void) -> c_int {
+    let server = unsafe { *(argument as *const &'static [u8]) };
+    let selected = unsafe { SSL_select_next_proto(out.cast(), out_len, server.as_ptr(), server.len() as c_uint, client, client_len) };
+    match selected {
+        OPENSSL_NPN_NEGOTIATED => SSL_TLSEXT_ERR_OK,
+        _ => SSL_TLSEXT_ERR_NOACK,
+    }
+}
+
+fn path_to_c(path: &Path) -> io::Result<CString> {
+    CString::new(path.as_os_str().as_bytes()).map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, format!("{} has a NUL byte in it", path.display())))
+}
+
+// An SSL_CTX with the certificate and key loaded, which every connection accepted while it's current is made from.
+struct SslContext(*mut c_void);
+
+// OpenSSL locks what it needs to internally, so a context can make connections on any number of threads at once.
+unsafe impl Send for SslContext {}
+unsafe impl Sync for SslContext {}
+
+impl SslContext {
+    fn load(config: &TlsConfig, protocols: &'static &'static [u8]) -> io::Result<SslContext> {
+        let (cert, key) = (path_to_c(&config.cert)?, path_to_c(&config.key)?);
+        unsafe {
+            ERR_clear_error();
+            let context = SslContext(SSL_CTX_new(TLS_server_method()));
+            if context.0.is_null() {
+                return Err(openssl_error("creating a TLS context"));
+            }
+            SSL_CTX_ctrl(context.0, SSL_CTRL_SET_MIN_PROTO_VERSION, TLS1_2_VERSION, ptr::null_mut());
+            // Key first: loaded after the certificate, a key that doesn't match fails as unreadable instead of below.
+            if SSL_CTX_use_PrivateKey_file(context.0, key.as_ptr(), SSL_FILETYPE_PEM) != 1 {
+                return Err(openssl_error(&format!("reading the private key {}", config.key.display())));
+            }
+            if SSL_CTX_use_certificate_chain_file(context.0, cert.as_ptr()) != 1 {
+                return Err(openssl_error(&format!("reading the certificate chain {}", config.cert.display())));
+            }
+            if SSL_CTX_check_private_key(context.0) != 1 {
+                return Err(openssl_error(&format!("{} isn't the key for {}", config.key.display(), config.cert.display())));
+            }
+            SSL_CTX_set_alpn_select_cb(context.0, select_alpn, protocols as *const &[u8] as *mut c_void);
+            Ok(context)
+        }
+    }
+}
+
+// Each connection holds a reference to the context it was made from, so a replaced one lives on until they've closed.
+impl Drop for SslContext {
+    fn drop(&mut self) {
+        unsafe { SSL_CTX_free(self.0) };
+    }
+}
+
+// Turns accepted TCP connections into TLS ones, with whichever certificate was loaded last.
+pub struct TlsAcceptor {
+    config: TlsConfig,
+    protocols: &'static &'static [u8],
+    context: RwLock<Arc<SslContext>>,
+}
+
+impl TlsAcceptor {
+    // Offers HTTP/2 through ALPN if the server speaks it.
+    pub fn new(config: TlsConfig, http2: bool) -> io::Result<TlsAcceptor> {
+        let protocols = if http2 { &HTTP2_AND_HTTP1 } else { &HTTP1 };
+        let context = SslContext::load(&config, protocols)?;
+        Ok(TlsAcceptor { config, protocols, context: RwLock::new(Arc::new(context)) })
+    }
+
+    // Reads the certificate and key again for the connections accepted from now on. If either can't be read, or they
+    // don't match, the ones loaded before stay in use.
+    pub fn reload(&self) -> io::Result<()> {
+        let context = SslContext::load(&self.config, self.protocols)?;
+        *self.context.write().unwrap() = Arc::new(context);
+        Ok(())
+    }
+
+    // Nothing is sent or received until the stream is first read from, which is when the handshake happens.
+    pub fn accept<S>(&self, inner: S) -> io::Result<TlsStream<S>> {
+        let context = self.context.read().unwrap().clone();
+        unsafe {
+            ERR_clear_error();
+            let ssl = SSL_new(context.0);
+            if ssl.is_null() {
+                return Err(openssl_error("starting a TLS connection"));
+            }
+            let (read, write) = (BIO_new(BIO_s_mem()), BIO_new(BIO_s_mem()));
+            if read.is_null() || write.is_null() {
+                for bio in [read, write].into_iter().filter(|bio| !bio.is_null()) {
+                    BIO_free(bio);
+                }
+                SSL_free(ssl);
+                return Err(openssl_error("starting a TLS connection"));
+            }
+            SSL_set_bio(ssl, read, write);
+            SSL_set_accept_state(ssl);
+            Ok(TlsStream { ssl, read, write, inner, outgoing: Vec::new(), shut_down: false })
+        }
+    }
+}
+
+// Reloads the certificate on every SIGHUP, until the process exits.
+pub fn spawn_reload_task(acceptor: Arc<TlsAcceptor>) {
+    let mut hangups = shutdown::hangups();
+    tokio::spawn(async move {
+        while hangups.changed().await.is_ok() {
+            match acceptor.reload() {
+                Ok(()) => info!("Received SIGHUP, reloaded the TLS certificate {}", acceptor.config.cert.display()),
+                Err(e) => error!("Received SIGHUP, but failed to reload the TLS certificate, still using the old one: {}", e),
+            }
+        }
+    });
+}
+
+// OpenSSL never touches the socket itself. It reads the client's records from one memory buffer and writes its own to
+// another, and the stream moves bytes between those and the socket as they're asked for.
+pub struct TlsStream<S> {
+    ssl: *mut c_void,
+    // Both owned by the SSL.
+    read: *mut c_void,
+    write: *mut c_void,
+    inner: S,
+    // Records OpenSSL has written that the socket hasn't taken yet.
+    outgoing: Vec<u8>,
+    shut_down: bool,
+}
+
+// The SSL is only ever used through &mut self.
+unsafe impl<S: Send> Send for TlsStream<S> {}
+
+impl<S> Drop for TlsStream<S> {
+    fn drop(&mut self) {
+        unsafe { SSL_free(self.ssl) };
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> TlsStream<S> {
+    // Sends everything OpenSSL has written so far.
+    fn poll_send(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let mut chunk = [0u8; READ_CHUNK];
+        loop {
+            let read = unsafe { BIO_read(self.write, chunk.as_mut_ptr().cast(), chunk.len() as c_int) };
+            if read <= 0 {
+                break;
+            }
+            self.outgoing.extend_from_slice(&chunk[..read as usize]);
+        }
+        while !self.outgoing.is_empty() {
+            let sent = ready!(Pin::new(&mut self.inner).poll_write(cx, &self.outgoing))?;
+            if sent == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.outgoing.drain(..sent);
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    // Hands OpenSSL what's arrived on the socket, after sending what it's written, which the client may be waiting on
+    // before it sends any more. False once the client has closed the connection.
+    fn poll_receive(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<bool>> {
+        ready!(self.poll_send(cx))?;
+        let mut chunk = [0u8; READ_CHUNK];
+        let mut buf = ReadBuf::new(&mut chunk);
+        ready!(Pin::new(&mut self.inner).poll_read(cx, &mut buf))?;
+        let received = buf.filled();
+        if received.is_empty() {
+            return Poll::Ready(Ok(false));
+        }
+        unsafe { BIO_write(self.read, received.as_ptr().cast(), received.len() as c_int) };
+        Poll::Ready(Ok(true))
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncRead for TlsStream<S> {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if buf.remaining() == 0 {
+            return Poll::Ready(Ok(()));
+        }
+        loop {
+            let unfilled = buf.initialize_unfilled();
+            let result = unsafe {
+                ERR_clear_error();
+                SSL_read(this.ssl, unfilled.as_mut_ptr().cast(), unfilled.len().min(c_int::MAX as usize) as c_int)
+            };
+            if result > 0 {
+                buf.advance(result as usize);
+                return Poll::Ready(Ok(()));
+            }
+            match unsafe { SSL_get_error(this.ssl, result) } {
+                // A client that closes the connection without saying so first is treated the same as one that does,
+                // since HTTP knows where its messages end.
+                SSL_ERROR_ZERO_RETURN => return Poll::Ready(Ok(())),
+                SSL_ERROR_WANT_READ => {
+                    if !ready!(this.poll_receive(cx))? {
+                        return Poll::Ready(Ok(()));
+                    }
+                },
+                _ => return Poll::Ready(Err(openssl_error("TLS"))),
+            }
+        }
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncWrite for TlsStream<S> {
+    // Waits for what's already been written to be sent before taking more, so a client that stops reading holds up
+    // the response rather than having it pile up in memory.
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, data: &[u8]) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if data.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        ready!(this.poll_send(cx))?;
+        loop {
+            let result = unsafe {
+                ERR_clear_error();
+                SSL_write(this.ssl, data.as_ptr().cast(), data.len().min(c_int::MAX as usize) as c_int)
+            };
+            if result > 0 {
+                // Pending is fine here, the rest goes with the next write or flush.
+                if let Poll::Ready(Err(e)) = this.poll_send(cx) {
+                    return Poll::Ready(Err(e));
+                }
+                return Poll::Ready(Ok(result as usize));
+            }
+            match unsafe { SSL_get_error(this.ssl, result) } {
+                SSL_ERROR_WANT_READ => {
+                    if !ready!(this.poll_receive(cx))? {
+                        return Poll::Ready(Err(io::ErrorKind::UnexpectedEof.into()));
+                    }
+                },
+                _ => return Poll::Ready(Err(openssl_error("TLS"))),
+            }
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_send(cx))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    // Tells the client the connection is closing, without waiting for it to say the same, then closes the socket.
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if !this.shut_down {
+            unsafe {
+                ERR_clear_error();
+                SSL_shutdown(this.ssl);
+                ERR_clear_error();
+            }
+            this.shut_down = true;
+        }
+        ready!(this.poll_send(cx))?;
+        Pin::new(&mut this.inner).poll_shutdown(cx)
+    }
+}
@@ -0,0 +1,53 @@
+use std::{sync::atomic::{AtomicU64, Ordering}, time::Duration};
+use time::OffsetDateTime;
+use tracing::{info, warn};
+
+use crate::state::StateWrapper;
+
+// Deleting a movie only moves it to the trash, see MovieStore::delete, where GET /movies/trash lists it and POST
+// /movie/{id}/restore takes it back out. Movies that have been in there longer than the retention are purged for good
+// once a minute, so the trash doesn't grow forever.
+
+pub const DEFAULT_RETENTION: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+const PURGE_INTERVAL: Duration = Duration::from_secs(60);
+
+// In seconds, 0 to keep movies in the trash until they're restored.
+static RETENTION_SECS: AtomicU64 = AtomicU64::new(DEFAULT_RETENTION.as_secs());
+
+pub fn set_retention(retention: Duration) {
+    RETENTION_SECS.store(retention.as_secs(), Ordering::Relaxed);
+}
+
+// Purges the movies that have been in the trash longer than the retention, and returns their ids.
+pub async fn purge_expired(store: &StateWrapper) -> Vec<String> {
+    let retention = RETENTION_SECS.load(Ordering::Relaxed);
+    if retention == 0 {
+        return Vec::new();
+    }
+    let deleted_before = OffsetDateTime::now_utc() - Duration::from_secs(retention);
+    match store.purge(deleted_before).await {
+        Ok(purged) => {
+            if !purged.is_empty() {
+                info!("Purged {} movies that were in the trash for more than {}s", purged.len(), retention);
+            }
+            purged
+        },
+        // Still in the trash, so they'll be tried again next time.
+        Err(e) => {
+            warn!("Failed to purge the trash: {:?}", e);
+            Vec::new()
+        },
+    }
+}
+
+// Purges expired movies every PURGE_INTERVAL until the process exits.
+pub fn spawn_purge_task(store: StateWrapper) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(PURGE_INTERVAL);
+        loop {
+            ticker.tick().await;
+            purge_expired(&store).await;
+        }
+    });
+}
@@ -0,0 +1,268 @@
+use serde::Serialize;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime, UtcOffset};
+
+use std::collections::BTreeMap;
+
+use crate::locale;
+use crate::model::{Movie, MoviePatch};
+use crate::webhooks::{NewWebhook, EVENTS};
+
+// The year of the first surviving motion picture. Nothing can have come out before that.
+pub const FIRST_MOVIE_YEAR: u16 = 1888;
+// Announced movies can be entered a few years ahead of release.
+const MAX_YEARS_AHEAD: u16 = 5;
+pub const MAX_ID_LEN: usize = 64;
+pub const MAX_NAME_LEN: usize = 300;
+pub const MAX_GENRES: usize = 10;
+pub const MAX_GENRE_LEN: usize = 50;
+pub const MAX_DIRECTOR_LEN: usize = 200;
+// Longer than any release, short of a few art installations.
+pub const MAX_RUNTIME_MINUTES: u16 = 1440;
+pub const MAX_SYNOPSIS_LEN: usize = 5000;
+pub const MAX_POSTER_URL_LEN: usize = 2000;
+pub const MAX_TAGS: usize = 20;
+pub const MAX_TAG_LEN: usize = 50;
+pub const MAX_TITLES: usize = 50;
+pub const MIN_SCORE: u8 = 1;
+pub const MAX_SCORE: u8 = 10;
+pub const MAX_USER_LEN: usize = 200;
+pub const MAX_REVIEW_LEN: usize = 10_000;
+pub const MAX_WATCHLIST_LEN: usize = 1000;
+pub const MAX_FAVORITES: usize = 1000;
+pub const MAX_WEBHOOK_URL_LEN: usize = 2000;
+pub const MIN_WEBHOOK_SECRET_LEN: usize = 16;
+pub const MAX_WEBHOOK_SECRET_LEN: usize = 200;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldError {
+    pub field: &'static str,
+    pub message: String,
+}
+
+impl FieldError {
+    fn new(field: &'static str, message: impl Into<String>) -> FieldError {
+        FieldError { field, message: message.into() }
+    }
+}
+
+// Collects every problem with the movie rather than stopping at the first, so clients can fix them all in one go.
+pub fn validate_movie(movie: &Movie) -> Result<(), Vec<FieldError>> {
+    let mut errors = Vec::new();
+    check_id(&movie.id, &mut errors);
+    check_name(&movie.name, &mut errors);
+    check_year(movie.year, &mut errors);
+    check_labels("genres", &movie.genres, MAX_GENRES, MAX_GENRE_LEN, &mut errors);
+    check_labels("tags", &movie.tags, MAX_TAGS, MAX_TAG_LEN, &mut errors);
+    check_titles(&movie.titles, &mut errors);
+    if let Some(director) = &movie.director {
+        check_text("director", director, MAX_DIRECTOR_LEN, &mut errors);
+    }
+    if let Some(runtime_minutes) = movie.runtime_minutes {
+        check_runtime(runtime_minutes, &mut errors);
+    }
+    if let Some(synopsis) = &movie.synopsis {
+        check_text("synopsis", synopsis, MAX_SYNOPSIS_LEN, &mut errors);
+    }
+    if let Some(poster_url) = &movie.poster_url {
+        check_poster_url(poster_url, &mut errors);
+    }
+    if errors.is_empty() { Ok(()) } else { Err(errors) }
+}
+
+// Only the fields the patch actually sets are checked, the rest of the movie was validated when it was stored.
+pub fn validate_patch(patch: &MoviePatch) -> Result<(), Vec<FieldError>> {
+    let mut errors = Vec::new();
+    if let Some(id) = &patch.id {
+        check_id(id, &mut errors);
+    }
+    if let Some(name) = &patch.name {
+        check_name(name, &mut errors);
+    }
+    if let Some(year) = patch.year {
+        check_year(year, &mut errors);
+    }
+    if let Some(Some(genres)) = &patch.genres {
+        check_labels("genres", genres, MAX_GENRES, MAX_GENRE_LEN, &mut errors);
+    }
+    if let Some(Some(tags)) = &patch.tags {
+        check_labels("tags", tags, MAX_TAGS, MAX_TAG_LEN, &mut errors);
+    }
+    if let Some(Some(titles)) = &patch.titles {
+        check_titles(titles, &mut errors);
+    }
+    if let Some(Some(director)) = &patch.director {
+        check_text("director", director, MAX_DIRECTOR_LEN, &mut errors);
+    }
+    if let Some(Some(runtime_minutes)) = patch.runtime_minutes {
+        check_runtime(runtime_minutes, &mut errors);
+    }
+    if let Some(Some(synopsis)) = &patch.synopsis {
+        check_text("synopsis", synopsis, MAX_SYNOPSIS_LEN, &mut errors);
+    }
+    if let Some(Some(poster_url)) = &patch.poster_url {
+        check_poster_url(poster_url, &mut errors);
+    }
+    if errors.is_empty() { Ok(()) } else { Err(errors) }
+}
+
+// A genre on its own, as given to POST /genres or PUT /genres/{genre}.
+pub fn validate_genre(name: &str) -> Result<(), Vec<FieldError>> {
+    if name.trim().is_empty() || name.chars().count() > MAX_GENRE_LEN {
+        return Err(vec![FieldError::new("name", format!("must be between 1 and {} characters long", MAX_GENRE_LEN))]);
+    }
+    Ok(())
+}
+
+// A score for POST /movie/{id}/ratings, and who it's from.
+pub fn validate_rating(user: &str, score: u8) -> Result<(), Vec<FieldError>> {
+    let mut errors = Vec::new();
+    if user.trim().is_empty() || user.chars().count() > MAX_USER_LEN {
+        errors.push(FieldError::new("user", format!("must be between 1 and {} characters long", MAX_USER_LEN)));
+    }
+    if !(MIN_SCORE..=MAX_SCORE).contains(&score) {
+        errors.push(FieldError::new("score", format!("must be between {} and {}", MIN_SCORE, MAX_SCORE)));
+    }
+    if errors.is_empty() { Ok(()) } else { Err(errors) }
+}
+
+// Someone registering with POST /users. The id may be the subject of a bearer token, so it's held to what a user
+// of a rating is rather than to a movie id.
+pub fn validate_user(id: &str, name: Option<&str>) -> Result<(), Vec<FieldError>> {
+    let mut errors = Vec::new();
+    if id.trim().is_empty() || id.chars().count() > MAX_USER_LEN {
+        errors.push(FieldError::new("id", format!("must be between 1 and {} characters long", MAX_USER_LEN)));
+    }
+    if let Some(name) = name {
+        check_text("name", name, MAX_NAME_LEN, &mut errors);
+    }
+    if errors.is_empty() { Ok(()) } else { Err(errors) }
+}
+
+// When a movie was watched, as given to POST /users/{id}/history. Returns it parsed.
+pub fn validate_watched_at(watched_at: &str, now: OffsetDateTime) -> Result<OffsetDateTime, Vec<FieldError>> {
+    match OffsetDateTime::parse(watched_at, &Rfc3339) {
+        Ok(watched_at) if watched_at <= now => Ok(watched_at.to_offset(UtcOffset::UTC)),
+        Ok(_) => Err(vec![FieldError::new("watched_at", "must not be in the future")]),
+        Err(_) => Err(vec![FieldError::new("watched_at", "must be an RFC 3339 time, like 2026-10-15T20:30:00Z")]),
+    }
+}
+
+// A review for POST /movie/{id}/reviews, and who wrote it.
+pub fn validate_review(author: &str, text: &str) -> Result<(), Vec<FieldError>> {
+    let mut errors = Vec::new();
+    if author.trim().is_empty() || author.chars().count() > MAX_USER_LEN {
+        errors.push(FieldError::new("author", format!("must be between 1 and {} characters long", MAX_USER_LEN)));
+    }
+    check_text("text", text, MAX_REVIEW_LEN, &mut errors);
+    if errors.is_empty() { Ok(()) } else { Err(errors) }
+}
+
+// A webhook for POST /admin/webhooks. Only plain http:// URLs, since that's all deliveries can be sent over, see
+// http_client.rs.
+pub fn validate_webhook(webhook: &NewWebhook) -> Result<(), Vec<FieldError>> {
+    let mut errors = Vec::new();
+    let rest = webhook.url.strip_prefix("http://").unwrap_or_default();
+    if rest.is_empty() || webhook.url.contains(char::is_whitespace) {
+        errors.push(FieldError::new("url", "must be an http:// URL"));
+    }
+    else if webhook.url.len() > MAX_WEBHOOK_URL_LEN {
+        errors.push(FieldError::new("url", format!("must be at most {} characters long", MAX_WEBHOOK_URL_LEN)));
+    }
+    if let Some(events) = &webhook.events {
+        if events.is_empty() {
+            errors.push(FieldError::new("events", "must not be empty, leave it out for every event"));
+        }
+        else if let Some(other) = events.iter().find(|event| !EVENTS.contains(&event.as_str())) {
+            errors.push(FieldError::new("events", format!("must each be one of {}, not {:?}", EVENTS.join(", "), other)));
+        }
+    }
+    if let Some(secret) = &webhook.secret
+        && !(MIN_WEBHOOK_SECRET_LEN..=MAX_WEBHOOK_SECRET_LEN).contains(&secret.chars().count()) {
+        errors.push(FieldError::new("secret", format!("must be between {} and {} characters long", MIN_WEBHOOK_SECRET_LEN, MAX_WEBHOOK_SECRET_LEN)));
+    }
+    if errors.is_empty() { Ok(()) } else { Err(errors) }
+}
+
+fn check_id(id: &str, errors: &mut Vec<FieldError>) {
+    if id.is_empty() || id.len() > MAX_ID_LEN {
+        errors.push(FieldError::new("id", format!("must be between 1 and {} characters long", MAX_ID_LEN)));
+    }
+    // Ids end up in URL paths, so keep them to characters that never need escaping.
+    else if !id.bytes().all(|byte| byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_') {
+        errors.push(FieldError::new("id", "may only contain ASCII letters, digits, '-' and '_'"));
+    }
+}
+
+fn check_name(name: &str, errors: &mut Vec<FieldError>) {
+    if name.trim().is_empty() {
+        errors.push(FieldError::new("name", "must not be empty"));
+    }
+    else if name.chars().count() > MAX_NAME_LEN {
+        errors.push(FieldError::new("name", format!("must be at most {} characters long", MAX_NAME_LEN)));
+    }
+}
+
+fn check_year(year: u16, errors: &mut Vec<FieldError>) {
+    let latest = OffsetDateTime::now_utc().year() as u16 + MAX_YEARS_AHEAD;
+    if !(FIRST_MOVIE_YEAR..=latest).contains(&year) {
+        errors.push(FieldError::new("year", format!("must be between {} and {}", FIRST_MOVIE_YEAR, latest)));
+    }
+}
+
+// Genres or tags.
+fn check_labels(field: &'static str, labels: &[String], max: usize, max_len: usize, errors: &mut Vec<FieldError>) {
+    if labels.len() > max {
+        errors.push(FieldError::new(field, format!("must have at most {} {}", max, field)));
+    }
+    else if labels.iter().any(|label| label.trim().is_empty() || label.chars().count() > max_len) {
+        errors.push(FieldError::new(field, format!("must each be between 1 and {} characters long", max_len)));
+    }
+    // Filtering by them ignores case, so neither may the labels of one movie.
+    else if labels.iter().enumerate().any(|(i, label)| labels[..i].iter().any(|other| other.to_lowercase() == label.to_lowercase())) {
+        errors.push(FieldError::new(field, "must not list the same one twice, ignoring case"));
+    }
+}
+
+// Titles are held to what the name is, under a well-formed language tag that only one of them has, ignoring case.
+fn check_titles(titles: &BTreeMap<String, String>, errors: &mut Vec<FieldError>) {
+    let languages: Vec<String> = titles.keys().map(|language| language.to_ascii_lowercase()).collect();
+    if titles.len() > MAX_TITLES {
+        errors.push(FieldError::new("titles", format!("must have at most {} titles", MAX_TITLES)));
+    }
+    else if let Some(language) = titles.keys().find(|language| !locale::valid_language(language)) {
+        errors.push(FieldError::new("titles", format!("must be keyed by language tags like en or pt-BR, not {:?}", language)));
+    }
+    else if languages.iter().enumerate().any(|(i, language)| languages[..i].contains(language)) {
+        errors.push(FieldError::new("titles", "must not have two titles in the same language, ignoring case"));
+    }
+    else if titles.values().any(|title| title.trim().is_empty() || title.chars().count() > MAX_NAME_LEN) {
+        errors.push(FieldError::new("titles", format!("must each be between 1 and {} characters long", MAX_NAME_LEN)));
+    }
+}
+
+// Optional text has to say something when it's there. Leaving it out is how to say nothing.
+fn check_text(field: &'static str, text: &str, max_len: usize, errors: &mut Vec<FieldError>) {
+    if text.trim().is_empty() {
+        errors.push(FieldError::new(field, "must not be empty, leave it out instead"));
+    }
+    else if text.chars().count() > max_len {
+        errors.push(FieldError::new(field, format!("must be at most {} characters long", max_len)));
+    }
+}
+
+// Something a browser can load, so an http:// or https:// URL.
+fn check_poster_url(url: &str, errors: &mut Vec<FieldError>) {
+    let rest = url.strip_prefix("http://").or_else(|| url.strip_prefix("https://")).unwrap_or_default();
+    if rest.is_empty() || url.contains(char::is_whitespace) {
+        errors.push(FieldError::new("poster_url", "must be an http:// or https:// URL"));
+    }
+    else if url.len() > MAX_POSTER_URL_LEN {
+        errors.push(FieldError::new("poster_url", format!("must be at most {} characters long", MAX_POSTER_URL_LEN)));
+    }
+}
+
+fn check_runtime(runtime_minutes: u16, errors: &mut Vec<FieldError>) {
+    if !(1..=MAX_RUNTIME_MINUTES).contains(&runtime_minutes) {
+        errors.push(FieldError::new("runtime_minutes", format!("must be between 1 and {}", MAX_RUNTIME_MINUTES)));
+    }
+}
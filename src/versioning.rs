@@ -0,0 +1,122 @@
+use axum::{extract::{Request, State}, http::{header, HeaderName, HeaderValue}, middleware::{self, Next}, response::Response, Router};
+use time::{macros::format_description, OffsetDateTime, PrimitiveDateTime};
+
+use crate::state::StateWrapper;
+
+// The API lives under a version prefix, /v1 and so on, so a /v2 with a different schema can be added next to it while
+// clients of /v1 keep getting exactly the responses they always have. Each version is its own set of handlers, and
+// VersionedRouter mounts them and marks the ones on their way out.
+
+static DEPRECATION: HeaderName = HeaderName::from_static("deprecation");
+static SUNSET: HeaderName = HeaderName::from_static("sunset");
+
+// Why clients should stop using some routes, sent on each of their responses.
+#[derive(Debug, Clone)]
+pub struct Deprecation {
+    // Sent as Deprecation (RFC 9745).
+    pub since: OffsetDateTime,
+    // When the routes go away, sent as Sunset (RFC 8594), if that's been decided.
+    pub sunset: Option<OffsetDateTime>,
+    // The prefix that replaces the deprecated one, so the rest of the path is kept for the successor-version Link.
+    pub successor: &'static str,
+}
+
+struct ApiVersion {
+    name: &'static str,
+    routes: Router<StateWrapper>,
+    deprecation: Option<Deprecation>,
+}
+
+#[derive(Default)]
+pub struct VersionedRouter {
+    versions: Vec<ApiVersion>,
+    // A version also served at the old paths without a prefix, and why those are deprecated.
+    unversioned: Option<(&'static str, Deprecation)>,
+}
+
+impl VersionedRouter {
+    pub fn new() -> VersionedRouter {
+        VersionedRouter::default()
+    }
+
+    // Serves `routes` under /<name>.
+    pub fn version(mut self, name: &'static str, routes: Router<StateWrapper>) -> VersionedRouter {
+        self.versions.push(ApiVersion { name, routes, deprecation: None });
+        self
+    }
+
+    // Marks a version added with `version` as deprecated.
+    pub fn deprecate(mut self, name: &'static str, deprecation: Deprecation) -> VersionedRouter {
+        let version = self.versions.iter_mut().find(|version| version.name == name).expect("only versions that were added can be deprecated");
+        version.deprecation = Some(deprecation);
+        self
+    }
+
+    // Also serves a version at the paths it had before there were versions, deprecated in favour of the prefixed ones.
+    pub fn unversioned(mut self, name: &'static str, deprecation: Deprecation) -> VersionedRouter {
+        assert!(self.versions.iter().any(|version| version.name == name), "only versions that were added can be unversioned");
+        self.unversioned = Some((name, deprecation));
+        self
+    }
+
+    pub fn build(self) -> Router<StateWrapper> {
+        let mut router = Router::new();
+        for version in &self.versions {
+            if let Some((name, deprecation)) = &self.unversioned
+                && *name == version.name {
+                router = router.merge(deprecated(version.routes.clone(), deprecation.clone()));
+            }
+            let prefix = format!("/{}", version.name);
+            let routes = match &version.deprecation {
+                Some(deprecation) => deprecated(version.routes.clone(), deprecation.clone()),
+                None => version.routes.clone(),
+            };
+            router = router.nest(&prefix, routes);
+        }
+        router
+    }
+}
+
+fn deprecated(routes: Router<StateWrapper>, deprecation: Deprecation) -> Router<StateWrapper> {
+    routes.layer(middleware::from_fn_with_state(deprecation, mark_deprecated))
+}
+
+// Routes nested under a prefix see their path without it, which is the part the successor keeps.
+async fn mark_deprecated(State(deprecation): State<Deprecation>, request: Request, next: Next) -> Response {
+    let path = request.uri().path().to_string();
+    let mut response = next.run(request).await;
+    let headers = response.headers_mut();
+    headers.insert(DEPRECATION.clone(), HeaderValue::from_str(&format!("@{}", deprecation.since.unix_timestamp())).unwrap());
+    if let Some(sunset) = deprecation.sunset {
+        headers.insert(SUNSET.clone(), HeaderValue::from_str(&http_date(sunset)).unwrap());
+    }
+    if let Ok(link) = HeaderValue::try_from(format!("<{}{}>; rel=\"successor-version\"", deprecation.successor, path)) {
+        headers.append(header::LINK, link);
+    }
+    response
+}
+
+// A route without the version prefix it's mounted under, e.g. /movie/{id} for /v1/movie/{id}, for middleware that
+// treats some routes specially whichever version they're in.
+pub fn unversioned_route(route: &str) -> &str {
+    let Some(rest) = route.strip_prefix("/v") else { return route };
+    let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+    match rest[digits..].strip_prefix('/') {
+        Some(_) if digits > 0 => &rest[digits..],
+        _ => route,
+    }
+}
+
+// IMF-fixdate (RFC 9110), e.g. Sun, 06 Nov 1994 08:49:37 GMT.
+pub fn http_date(time: OffsetDateTime) -> String {
+    let time = time.to_offset(time::UtcOffset::UTC);
+    format!("{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+        &time.weekday().to_string()[..3], time.day(), &time.month().to_string()[..3], time.year(), time.hour(), time.minute(), time.second())
+}
+
+// The time in an IMF-fixdate, or None for anything else. RFC 9110 asks recipients to take two obsolete formats too,
+// but nothing still sends them.
+pub fn parse_http_date(date: &str) -> Option<OffsetDateTime> {
+    let format = format_description!("[weekday repr:short], [day] [month repr:short] [year] [hour]:[minute]:[second] GMT");
+    PrimitiveDateTime::parse(date.trim(), format).ok().map(PrimitiveDateTime::assume_utc)
+}
@@ -0,0 +1,495 @@
+use std::{collections::{BTreeMap, BTreeSet}, io, path::{Path, PathBuf}, time::Instant};
+use tracing::{info, info_span, warn, Instrument};
+use serde::{Deserialize, Serialize};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use tokio::{fs::{File, OpenOptions}, io::AsyncWriteExt, sync::{broadcast, Mutex, MutexGuard}};
+
+use crate::metrics::{self, Lock};
+use crate::changes::{self, ChangeLog, ChangePage, CursorExpired, Since};
+use crate::events::MovieEvent;
+use crate::labels::Label;
+use crate::model::{self, Link, Movie, MoviePatch, StoredMovie, StoredUser, Trashed, User, UserChange};
+use crate::schema::{self, Migration, Schema};
+use crate::store::{check_precondition, Backup, MemoryMovieStore, MovieFilter, MovieStore, Precondition, StoreError, StoreFuture};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WalConfig {
+    pub path: PathBuf,
+    // Once the log grows past this it gets compacted down to one entry per live movie.
+    pub max_bytes: u64,
+}
+
+// Version 1 logs are from before logs were stamped with their version, and version 2 stamps them. See schema.rs.
+pub static SCHEMA: Schema = Schema {
+    name: "Write-ahead log",
+    first: 1,
+    migrations: &[Migration { to: 2, description: "logs say their schema version", apply: schema::unchanged }],
+};
+
+// One line of the log. Patches are logged as the full movie they produced so replay doesn't depend on patch logic,
+// and users are logged whole after every change for the same reason.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum WalEntry {
+    Insert { movie: StoredMovie },
+    Update { movie: StoredMovie },
+    // Moves the movie to the trash. Logs from before there was a trash don't say when, and those deletes are for good.
+    Delete {
+        id: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        deleted_at: Option<String>,
+    },
+    Restore { id: String },
+    Purge { ids: Vec<String> },
+    // A movie that was already in the trash, as compaction writes it.
+    Trash { deleted_at: String, movie: StoredMovie },
+    User { user: StoredUser },
+    Link { link: Link },
+    Unlink { link: Link },
+}
+
+// What replaying the log comes to.
+#[derive(Debug, Default)]
+struct Tables {
+    movies: BTreeMap<String, Movie>,
+    users: BTreeMap<String, User>,
+    links: BTreeSet<Link>,
+    trash: BTreeMap<String, Trashed>,
+}
+
+impl WalEntry {
+    fn replay(self, tables: &mut Tables) {
+        match self {
+            WalEntry::Insert { movie } | WalEntry::Update { movie } => {
+                tables.movies.insert(movie.movie.id.clone(), movie.into());
+            },
+            // Deleting a movie takes it off every watchlist and favorites, and unlinks it, without logging each of them.
+            WalEntry::Delete { id, deleted_at } => {
+                if let (Some(movie), Some(deleted_at)) = (tables.movies.remove(&id), deleted_at) {
+                    tables.trash.insert(id.clone(), Trashed { deleted_at, movie });
+                }
+                for user in tables.users.values_mut() {
+                    user.forget(&id);
+                }
+                tables.links.retain(|link| !link.touches(&id));
+            },
+            WalEntry::Restore { id } => {
+                if let Some(trashed) = tables.trash.remove(&id) {
+                    tables.movies.insert(id, trashed.movie);
+                }
+            },
+            WalEntry::Purge { ids } => {
+                for id in ids {
+                    tables.trash.remove(&id);
+                }
+            },
+            WalEntry::Trash { deleted_at, movie } => {
+                tables.trash.insert(movie.movie.id.clone(), Trashed { deleted_at, movie: movie.into() });
+            },
+            WalEntry::User { user } => {
+                tables.users.insert(user.user.id.clone(), user.into());
+            },
+            WalEntry::Link { link } => {
+                tables.links.insert(link);
+            },
+            WalEntry::Unlink { link } => {
+                tables.links.remove(&link);
+            },
+        }
+    }
+}
+
+struct LogFile {
+    file: File,
+    size: u64,
+    // Size right after the last compaction, so a table that is simply big doesn't get compacted on every write.
+    compacted_size: u64,
+}
+
+// Keeps everything in memory like MemoryMovieStore, but appends every mutation to a log file before applying it and
+// replays that log on startup. Mutations are serialized on the log, so the log order is the order they happened in.
+pub struct WalMovieStore {
+    inner: MemoryMovieStore,
+    path: PathBuf,
+    max_bytes: u64,
+    log: Mutex<LogFile>,
+}
+
+impl WalMovieStore {
+    pub async fn open(config: WalConfig) -> io::Result<WalMovieStore> {
+        let (tables, mut size, version) = replay_log(&config.path).await?;
+        info!("Replayed {} movies and {} users from write-ahead log {}", tables.movies.len(), tables.users.len(), config.path.display());
+        let mut file = OpenOptions::new().create(true).append(true).open(&config.path).await?;
+        // Get rid of a torn last entry, if there was one, before appending after it.
+        file.set_len(size).await?;
+        // What's appended from now on is in the current version, so a log from an older one says so first.
+        if version < SCHEMA.current() {
+            let stamp = SCHEMA.stamp();
+            file.write_all(&stamp).await?;
+            file.sync_data().await?;
+            size += stamp.len() as u64;
+        }
+        Ok(WalMovieStore {
+            inner: MemoryMovieStore::from_tables(
+                tables.movies.into_values().collect(),
+                tables.users.into_values().collect(),
+                tables.links.into_iter().collect(),
+                tables.trash.into_values().collect(),
+            ).with_change_log(ChangeLog::open(&changes::path_beside(&config.path))?),
+            path: config.path,
+            max_bytes: config.max_bytes,
+            log: Mutex::new(LogFile { file, size, compacted_size: 0 }),
+        })
+    }
+
+    async fn lock_log(&self) -> MutexGuard<'_, LogFile> {
+        let started = Instant::now();
+        let log = self.log.lock().await;
+        metrics::record_lock_wait(Lock::WalLog, started.elapsed());
+        log
+    }
+
+    async fn append(&self, log: &mut LogFile, entry: &WalEntry) -> Result<(), StoreError> {
+        let mut line = serde_json::to_vec(entry).map_err(backend_error)?;
+        line.push(b'\n');
+        // Only acknowledge the write once it is actually on disk.
+        let written = async {
+            log.file.write_all(&line).await?;
+            log.file.sync_data().await
+        }.instrument(info_span!("wal.append", bytes = line.len())).await;
+        if let Err(e) = written {
+            // Cut off whatever part of the line made it out, or the next entry would get glued onto it.
+            let _ = log.file.set_len(log.size).await;
+            return Err(backend_error(e));
+        }
+        log.size += line.len() as u64;
+        Ok(())
+    }
+
+    // Called with the log locked after a mutation has been applied in memory.
+    async fn maybe_compact(&self, log: &mut LogFile) {
+        if log.size <= self.max_bytes || log.size <= log.compacted_size * 2 {
+            return;
+        }
+        match self.compact(log).instrument(info_span!("wal.compact")).await {
+            Ok(()) => info!("Compacted write-ahead log {} down to {} bytes", self.path.display(), log.size),
+            // The old log is still intact and still correct, so just carry on appending to it.
+            Err(e) => warn!("Failed to compact write-ahead log {}: {}", self.path.display(), e),
+        }
+    }
+
+    async fn compact(&self, log: &mut LogFile) -> io::Result<()> {
+        let backup = self.inner.backup().await;
+        self.rewrite(log, &backup).await
+    }
+
+    // Swaps the log for one that replays to just what's in the backup, one entry per thing in it.
+    async fn rewrite(&self, log: &mut LogFile, backup: &Backup) -> io::Result<()> {
+        let mut contents = SCHEMA.stamp();
+        for movie in &backup.movies {
+            serde_json::to_writer(&mut contents, &WalEntry::Insert { movie: movie.clone().into() })?;
+            contents.push(b'\n');
+        }
+        for user in &backup.users {
+            serde_json::to_writer(&mut contents, &WalEntry::User { user: user.clone().into() })?;
+            contents.push(b'\n');
+        }
+        for link in &backup.links {
+            serde_json::to_writer(&mut contents, &WalEntry::Link { link: link.clone() })?;
+            contents.push(b'\n');
+        }
+        for trashed in &backup.trash {
+            serde_json::to_writer(&mut contents, &WalEntry::Trash { deleted_at: trashed.deleted_at.clone(), movie: trashed.movie.clone().into() })?;
+            contents.push(b'\n');
+        }
+        let mut temp_path = self.path.as_os_str().to_owned();
+        temp_path.push(".compact");
+        let mut temp = File::create(&temp_path).await?;
+        temp.write_all(&contents).await?;
+        temp.sync_all().await?;
+        tokio::fs::rename(&temp_path, &self.path).await?;
+
+        log.file = OpenOptions::new().append(true).open(&self.path).await?;
+        log.size = contents.len() as u64;
+        log.compacted_size = log.size;
+        Ok(())
+    }
+}
+
+fn backend_error(e: impl std::fmt::Display) -> StoreError {
+    StoreError::Backend(format!("write-ahead log: {}", e))
+}
+
+// Returns the replayed tables, how many bytes of the log are intact, and the schema version it was last stamped with.
+async fn replay_log(path: &Path) -> io::Result<(Tables, u64, u32)> {
+    let contents = match tokio::fs::read(path).await {
+        Ok(contents) => contents,
+        // No log yet, first run.
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok((Tables::default(), 0, SCHEMA.first)),
+        Err(e) => return Err(e),
+    };
+    let mut tables = Tables::default();
+    let (valid_len, version) = schema::read_log(&SCHEMA, path, &contents, |entry: WalEntry| entry.replay(&mut tables))?;
+    Ok((tables, valid_len, version))
+}
+
+impl MovieStore for WalMovieStore {
+    fn get<'a>(&'a self, id: &'a str) -> StoreFuture<'a, Option<Movie>> {
+        self.inner.get(id)
+    }
+
+    fn insert(&self, movie: Movie) -> StoreFuture<'_, Result<(), StoreError>> {
+        Box::pin(async move {
+            let mut log = self.lock_log().await;
+            if let Some(existing) = self.inner.get(&movie.id).await {
+                return Err(StoreError::AlreadyExists(Box::new(existing)));
+            }
+            self.append(&mut log, &WalEntry::Insert { movie: movie.clone().into() }).await?;
+            self.inner.insert(movie).await?;
+            self.maybe_compact(&mut log).await;
+            Ok(())
+        })
+    }
+
+    // Replay takes logged movies as they are, so they're logged with the version inner is about to give them.
+    fn upsert(&self, mut movie: Movie) -> StoreFuture<'_, Result<bool, StoreError>> {
+        Box::pin(async move {
+            let mut log = self.lock_log().await;
+            let existing = self.inner.get(&movie.id).await;
+            movie.succeed(existing.as_ref());
+            // Replay treats inserts and updates the same way, so which one we log only matters to someone reading it.
+            let entry = match existing {
+                Some(_) => WalEntry::Update { movie: movie.clone().into() },
+                None => WalEntry::Insert { movie: movie.clone().into() },
+            };
+            self.append(&mut log, &entry).await?;
+            let created = self.inner.upsert(movie).await?;
+            self.maybe_compact(&mut log).await;
+            Ok(created)
+        })
+    }
+
+    fn put(&self, movie: Movie) -> StoreFuture<'_, Result<bool, StoreError>> {
+        Box::pin(async move {
+            let mut log = self.lock_log().await;
+            let entry = match self.inner.get(&movie.id).await {
+                Some(_) => WalEntry::Update { movie: movie.clone().into() },
+                None => WalEntry::Insert { movie: movie.clone().into() },
+            };
+            self.append(&mut log, &entry).await?;
+            let created = self.inner.put(movie).await?;
+            self.maybe_compact(&mut log).await;
+            Ok(created)
+        })
+    }
+
+    // Every write holds the log lock, so checking the precondition under it is as good as checking it in inner.
+    fn update_if<'a>(&'a self, mut movie: Movie, precondition: Precondition<'a>) -> StoreFuture<'a, Result<(), StoreError>> {
+        Box::pin(async move {
+            let mut log = self.lock_log().await;
+            let existing = self.inner.get(&movie.id).await.ok_or(StoreError::NotFound)?;
+            check_precondition(precondition, &existing)?;
+            movie.succeed(Some(&existing));
+            self.append(&mut log, &WalEntry::Update { movie: movie.clone().into() }).await?;
+            self.inner.update(movie).await?;
+            self.maybe_compact(&mut log).await;
+            Ok(())
+        })
+    }
+
+    fn patch<'a>(&'a self, id: &'a str, mut patch: MoviePatch) -> StoreFuture<'a, Result<Movie, StoreError>> {
+        Box::pin(async move {
+            let mut log = self.lock_log().await;
+            let mut movie = self.inner.get(id).await.ok_or(StoreError::NotFound)?;
+            if !patch.expects(&movie) {
+                return Err(StoreError::PreconditionFailed(Box::new(movie)));
+            }
+            // Applied twice, so stamped once.
+            patch.updated_at.get_or_insert_with(model::timestamp);
+            patch.clone().apply(&mut movie);
+            self.append(&mut log, &WalEntry::Update { movie: movie.into() }).await?;
+            // Not an update with the movie we logged, which would carry the old ratings over. Nothing else can have
+            // written in between, so this comes out the same as what was logged.
+            let movie = self.inner.patch(id, patch).await?;
+            self.maybe_compact(&mut log).await;
+            Ok(movie)
+        })
+    }
+
+    fn delete_if<'a>(&'a self, id: &'a str, precondition: Precondition<'a>) -> StoreFuture<'a, Result<Movie, StoreError>> {
+        Box::pin(async move {
+            let mut log = self.lock_log().await;
+            let existing = self.inner.get(id).await.ok_or(StoreError::NotFound)?;
+            check_precondition(precondition, &existing)?;
+            let deleted_at = OffsetDateTime::now_utc().format(&Rfc3339).map_err(backend_error)?;
+            self.append(&mut log, &WalEntry::Delete { id: id.to_string(), deleted_at: Some(deleted_at.clone()) }).await?;
+            let movie = self.inner.delete_at(id, None, deleted_at).await?;
+            self.maybe_compact(&mut log).await;
+            Ok(movie)
+        })
+    }
+
+    fn trash(&self) -> StoreFuture<'_, Vec<Trashed>> {
+        self.inner.trash()
+    }
+
+    fn restore<'a>(&'a self, id: &'a str) -> StoreFuture<'a, Result<Movie, StoreError>> {
+        Box::pin(async move {
+            let mut log = self.lock_log().await;
+            if !self.inner.is_trashed(id) {
+                return Err(StoreError::NotFound);
+            }
+            if let Some(existing) = self.inner.get(id).await {
+                return Err(StoreError::AlreadyExists(Box::new(existing)));
+            }
+            self.append(&mut log, &WalEntry::Restore { id: id.to_string() }).await?;
+            let movie = self.inner.restore(id).await?;
+            self.maybe_compact(&mut log).await;
+            Ok(movie)
+        })
+    }
+
+    fn purge(&self, deleted_before: OffsetDateTime) -> StoreFuture<'_, Result<Vec<String>, StoreError>> {
+        Box::pin(async move {
+            let mut log = self.lock_log().await;
+            let ids = self.inner.trashed_before(deleted_before);
+            if ids.is_empty() {
+                return Ok(ids);
+            }
+            self.append(&mut log, &WalEntry::Purge { ids }).await?;
+            let purged = self.inner.purge(deleted_before).await?;
+            self.maybe_compact(&mut log).await;
+            Ok(purged)
+        })
+    }
+
+    fn backup(&self) -> StoreFuture<'_, Backup> {
+        self.inner.backup()
+    }
+
+    // Rather than logging the whole backup as one entry, the log is rewritten as compaction would leave it. Renaming
+    // the new log over the old one is what makes the replace happen all at once on disk, as it does in memory.
+    fn replace(&self, backup: Backup) -> StoreFuture<'_, Result<(), StoreError>> {
+        Box::pin(async move {
+            let mut log = self.lock_log().await;
+            self.rewrite(&mut log, &backup).instrument(info_span!("wal.rewrite")).await.map_err(backend_error)?;
+            self.inner.replace(backup).await
+        })
+    }
+
+    fn list<'a>(&'a self, filter: &'a MovieFilter) -> StoreFuture<'a, Vec<Movie>> {
+        self.inner.list(filter)
+    }
+
+    fn scan<'a>(&'a self, after: Option<&'a str>, limit: usize) -> StoreFuture<'a, Vec<Movie>> {
+        self.inner.scan(after, limit)
+    }
+
+    fn suggest<'a>(&'a self, prefix: &'a str, limit: usize) -> StoreFuture<'a, Vec<String>> {
+        self.inner.suggest(prefix, limit)
+    }
+
+    fn genres(&self) -> StoreFuture<'_, Vec<Label>> {
+        self.inner.genres()
+    }
+
+    fn tags(&self) -> StoreFuture<'_, Vec<Label>> {
+        self.inner.tags()
+    }
+
+    fn genre_movie_ids<'a>(&'a self, genre: &'a str) -> StoreFuture<'a, Vec<String>> {
+        self.inner.genre_movie_ids(genre)
+    }
+
+    fn count(&self) -> StoreFuture<'_, usize> {
+        self.inner.count()
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<MovieEvent> {
+        self.inner.subscribe()
+    }
+
+    fn changes(&self, since: &Since, limit: usize) -> Result<ChangePage, CursorExpired> {
+        self.inner.changes(since, limit)
+    }
+
+    fn get_user<'a>(&'a self, id: &'a str) -> StoreFuture<'a, Option<User>> {
+        self.inner.get_user(id)
+    }
+
+    fn insert_user(&self, user: User) -> StoreFuture<'_, Result<(), StoreError>> {
+        Box::pin(async move {
+            let mut log = self.lock_log().await;
+            if let Some(existing) = self.inner.get_user(&user.id).await {
+                return Err(StoreError::UserAlreadyExists(Box::new(existing)));
+            }
+            self.append(&mut log, &WalEntry::User { user: user.clone().into() }).await?;
+            self.inner.insert_user(user).await?;
+            self.maybe_compact(&mut log).await;
+            Ok(())
+        })
+    }
+
+    fn change_user<'a>(&'a self, user_id: &'a str, change: UserChange) -> StoreFuture<'a, Result<User, StoreError>> {
+        Box::pin(async move {
+            let mut log = self.lock_log().await;
+            let mut user = self.inner.get_user(user_id).await.ok_or(StoreError::UserNotFound)?;
+            if let Some(movie_id) = change.added_movie() {
+                self.inner.get(movie_id).await.ok_or(StoreError::NotFound)?;
+            }
+            user.apply(change.clone());
+            self.append(&mut log, &WalEntry::User { user: user.into() }).await?;
+            let user = self.inner.change_user(user_id, change).await?;
+            self.maybe_compact(&mut log).await;
+            Ok(user)
+        })
+    }
+
+    fn links<'a>(&'a self, movie_id: &'a str) -> StoreFuture<'a, Vec<Link>> {
+        self.inner.links(movie_id)
+    }
+
+    // Links already there aren't logged again.
+    fn link(&self, link: Link) -> StoreFuture<'_, Result<bool, StoreError>> {
+        Box::pin(async move {
+            let mut log = self.lock_log().await;
+            if !self.inner.check_link(&link).await? {
+                return Ok(false);
+            }
+            self.append(&mut log, &WalEntry::Link { link: link.clone() }).await?;
+            let created = self.inner.link(link).await?;
+            self.maybe_compact(&mut log).await;
+            Ok(created)
+        })
+    }
+
+    fn unlink<'a>(&'a self, link: &'a Link) -> StoreFuture<'a, Result<bool, StoreError>> {
+        Box::pin(async move {
+            let mut log = self.lock_log().await;
+            if !self.inner.links(&link.from).await.contains(link) {
+                return Ok(false);
+            }
+            self.append(&mut log, &WalEntry::Unlink { link: link.clone() }).await?;
+            let removed = self.inner.unlink(link).await?;
+            self.maybe_compact(&mut log).await;
+            Ok(removed)
+        })
+    }
+
+    // Every write is synced as it's logged, so only the change log is left.
+    fn close(&self) -> StoreFuture<'_, Result<(), StoreError>> {
+        Box::pin(async move {
+            let _log = self.lock_log().await;
+            self.inner.close_change_log()
+        })
+    }
+
+    // Catches the log having been deleted or made read-only underneath us, which would make the next write fail.
+    fn check_ready(&self) -> StoreFuture<'_, Result<(), StoreError>> {
+        Box::pin(async move {
+            let _log = self.lock_log().await;
+            OpenOptions::new().append(true).open(&self.path).await.map_err(backend_error)?;
+            Ok(())
+        })
+    }
+}
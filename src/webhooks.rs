@@ -0,0 +1,211 @@
+use std::{collections::{BTreeMap, VecDeque}, sync::{atomic::{AtomicU32, Ordering}, Arc, LazyLock, Mutex}, time::{Duration, SystemTime, UNIX_EPOCH}};
+use serde::{Deserialize, Serialize};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::{debug, warn};
+
+use crate::crypto;
+use crate::error::ApiError;
+use crate::events::{ChangeMessage, MovieEvent};
+use crate::http_client;
+use crate::random;
+use crate::resilience::{self, BreakerPolicy, CallError, Integration, RetryPolicy};
+use crate::validation::validate_webhook;
+
+// Tells other systems about changes to movies: operators register a URL with POST /admin/webhooks, and every create,
+// update and delete the store makes is POSTed to it as JSON, signed with a secret only the two of them know. Each
+// delivery is retried with backoff, and each webhook has its own circuit breaker (see resilience.rs), so one endpoint
+// that's down doesn't hold up the others. Deliveries that still fail are kept as dead letters for operators to look
+// at. Webhooks are only kept in memory, like logins, so they have to be registered again after a restart.
+// Deliveries run side by side, so a receiver can get two changes to a movie out of order: the version says which is
+// newer.
+
+pub const EVENTS: [&str; 3] = ["created", "updated", "deleted"];
+pub const MAX_WEBHOOKS: usize = 100;
+// The oldest go first past this.
+const MAX_DEAD_LETTERS: usize = 1000;
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+// Up to half a minute of tries, enough to ride out a receiver's restart.
+const RETRY: RetryPolicy = RetryPolicy { attempts: 5, base_delay: Duration::from_secs(2), max_delay: Duration::from_secs(30) };
+const BREAKER: BreakerPolicy = BreakerPolicy { failures_to_open: 5, open_for: Duration::from_secs(5 * 60) };
+
+// Body of POST /admin/webhooks.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NewWebhook {
+    pub url: String,
+    // Which of EVENTS to send, all of them if left out.
+    #[serde(default)]
+    pub events: Option<Vec<String>>,
+    // To sign deliveries with. One is made up if left out.
+    #[serde(default)]
+    pub secret: Option<String>,
+}
+
+// A webhook as listed, which leaves the secret out.
+#[derive(Debug, Clone, Serialize)]
+pub struct Webhook {
+    pub id: String,
+    pub url: String,
+    pub events: Vec<String>,
+    pub created_at: String,
+}
+
+// The response to registering a webhook, the only time its secret is sent back.
+#[derive(Debug, Serialize)]
+pub struct CreatedWebhook {
+    #[serde(flatten)]
+    pub webhook: Webhook,
+    pub secret: String,
+}
+
+// A delivery that failed for good.
+#[derive(Debug, Clone, Serialize)]
+pub struct DeadLetter {
+    pub delivery_id: String,
+    pub webhook_id: String,
+    pub url: String,
+    pub event: &'static str,
+    pub movie_id: String,
+    pub attempts: u32,
+    pub error: String,
+    pub failed_at: String,
+}
+
+struct Registered {
+    webhook: Webhook,
+    secret: String,
+    endpoint: Arc<Integration>,
+}
+
+static WEBHOOKS: LazyLock<Mutex<BTreeMap<String, Arc<Registered>>>> = LazyLock::new(|| Mutex::new(BTreeMap::new()));
+static DEAD_LETTERS: Mutex<VecDeque<DeadLetter>> = Mutex::new(VecDeque::new());
+
+fn now() -> String {
+    OffsetDateTime::now_utc().format(&Rfc3339).unwrap_or_default()
+}
+
+pub fn register(new: NewWebhook) -> Result<CreatedWebhook, ApiError> {
+    validate_webhook(&new)?;
+    let mut webhooks = WEBHOOKS.lock().unwrap();
+    if webhooks.len() >= MAX_WEBHOOKS {
+        return Err(ApiError::Conflict(format!("There are already {} webhooks, delete one first", MAX_WEBHOOKS)));
+    }
+    let events = new.events.map_or_else(|| EVENTS.map(str::to_string).to_vec(), |events| {
+        EVENTS.iter().filter(|event| events.iter().any(|wanted| wanted == *event)).map(|event| event.to_string()).collect()
+    });
+    let secret = new.secret.unwrap_or_else(|| {
+        let mut bytes = [0u8; 32];
+        random::fill_bytes(&mut bytes);
+        crypto::base64url_encode(&bytes)
+    });
+    let webhook = Webhook { id: random::uuid_v7(), url: new.url, events, created_at: now() };
+    let endpoint = Integration::register(format!("webhook:{}", webhook.id), RETRY, BREAKER);
+    webhooks.insert(webhook.id.clone(), Arc::new(Registered { webhook: webhook.clone(), secret: secret.clone(), endpoint }));
+    Ok(CreatedWebhook { webhook, secret })
+}
+
+// Oldest first.
+pub fn list() -> Vec<Webhook> {
+    WEBHOOKS.lock().unwrap().values().map(|registered| registered.webhook.clone()).collect()
+}
+
+// Deliveries already on their way still go out, but nothing new does. False if there's no such webhook.
+pub fn remove(id: &str) -> bool {
+    let removed = WEBHOOKS.lock().unwrap().remove(id);
+    removed.inspect(|registered| registered.endpoint.unregister()).is_some()
+}
+
+// Oldest first.
+pub fn dead_letters() -> Vec<DeadLetter> {
+    DEAD_LETTERS.lock().unwrap().iter().cloned().collect()
+}
+
+// Sends every change the store makes from now on to the webhooks that want it, until the store goes away.
+pub fn spawn_dispatcher(mut changes: broadcast::Receiver<MovieEvent>) {
+    tokio::spawn(async move {
+        loop {
+            match changes.recv().await {
+                Ok(change) => dispatch(&change),
+                Err(RecvError::Lagged(missed)) => warn!("Webhooks fell behind and missed {} changes", missed),
+                Err(RecvError::Closed) => return,
+            }
+        }
+    });
+}
+
+fn dispatch(change: &MovieEvent) {
+    let event = change.name();
+    let webhooks: Vec<Arc<Registered>> = WEBHOOKS.lock().unwrap().values()
+        .filter(|registered| registered.webhook.events.iter().any(|wanted| wanted == event))
+        .cloned()
+        .collect();
+    if webhooks.is_empty() {
+        return;
+    }
+    let occurred_at = now();
+    for registered in webhooks {
+        let id = random::uuid_v7();
+        let body = serde_json::to_vec(&ChangeMessage { id: &id, event, occurred_at: &occurred_at, movie: change.movie() }).expect("payloads always serialize");
+        let movie_id = change.movie().id.clone();
+        tokio::spawn(async move { deliver(registered, id, event, movie_id, body).await });
+    }
+}
+
+// The X-Webhook-Signature for a body sent at `timestamp`: sha256= and the hex HMAC-SHA256 of "<timestamp>.<body>"
+// with the webhook's secret. The timestamp is in X-Webhook-Timestamp, so receivers can turn away old deliveries
+// being replayed.
+pub fn signature(secret: &str, timestamp: u64, body: &[u8]) -> String {
+    let mut message = format!("{}.", timestamp).into_bytes();
+    message.extend_from_slice(body);
+    let mac = crypto::hmac_sha256(secret.as_bytes(), &message);
+    format!("sha256={}", mac.iter().map(|byte| format!("{:02x}", byte)).collect::<String>())
+}
+
+async fn deliver(registered: Arc<Registered>, id: String, event: &'static str, movie_id: String, body: Vec<u8>) {
+    let webhook = &registered.webhook;
+    let attempts = AtomicU32::new(0);
+    let delivered = registered.endpoint.call(|| async {
+        attempts.fetch_add(1, Ordering::Relaxed);
+        // Signed again for every attempt, since the timestamp moves on.
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        let timestamp_header = timestamp.to_string();
+        let signature = signature(&registered.secret, timestamp, &body);
+        let headers = [
+            ("Content-Type", "application/json"),
+            ("X-Webhook-Id", webhook.id.as_str()),
+            ("X-Webhook-Delivery", id.as_str()),
+            ("X-Webhook-Event", event),
+            ("X-Webhook-Timestamp", timestamp_header.as_str()),
+            ("X-Webhook-Signature", signature.as_str()),
+        ];
+        let response = http_client::request("POST", &webhook.url, &headers, &body, DELIVERY_TIMEOUT).await?;
+        if !(200..300).contains(&response.status) {
+            return Err(resilience::status_error(response.status));
+        }
+        Ok(())
+    }).await;
+    let error = match delivered {
+        Ok(()) => {
+            debug!("Delivered {} of {} to webhook {}", event, movie_id, webhook.id);
+            return;
+        },
+        Err(CallError::Open) => "wasn't tried, the webhook has been failing".to_string(),
+        Err(e) => e.to_string(),
+    };
+    warn!("Couldn't deliver {} of {} to webhook {} at {}: {}", event, movie_id, webhook.id, webhook.url, error);
+    let mut dead_letters = DEAD_LETTERS.lock().unwrap();
+    if dead_letters.len() >= MAX_DEAD_LETTERS {
+        dead_letters.pop_front();
+    }
+    dead_letters.push_back(DeadLetter {
+        delivery_id: id,
+        webhook_id: webhook.id.clone(),
+        url: webhook.url.clone(),
+        event,
+        movie_id,
+        attempts: attempts.load(Ordering::Relaxed),
+        error,
+        failed_at: now(),
+    });
+}
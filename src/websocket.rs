@@ -0,0 +1,271 @@
+use std::io;
+use axum::{body::Body, extract::Request, http::{header, HeaderValue, StatusCode}, response::Response};
+use hyper::upgrade::OnUpgrade;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+// The server side of RFC 6455: answering the handshake and reading and writing frames. axum's WebSocket support
+// isn't available to this build, so this is just enough for the subscription endpoint, without extensions.
+
+const ACCEPT_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+pub const CLOSE_NORMAL: u16 = 1000;
+pub const CLOSE_GOING_AWAY: u16 = 1001;
+pub const CLOSE_PROTOCOL_ERROR: u16 = 1002;
+pub const CLOSE_UNSUPPORTED_DATA: u16 = 1003;
+pub const CLOSE_INVALID_DATA: u16 = 1007;
+pub const CLOSE_TOO_BIG: u16 = 1009;
+
+const OPCODE_CONTINUATION: u8 = 0x0;
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_BINARY: u8 = 0x2;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xa;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Message {
+    Text(String),
+    Binary(Vec<u8>),
+    Ping(Vec<u8>),
+    Pong(Vec<u8>),
+    // The close code and reason, if the peer gave one.
+    Close(Option<(u16, String)>),
+}
+
+#[derive(Debug)]
+pub enum WebSocketError {
+    Io(io::Error),
+    // The peer broke the protocol. The connection should be closed with this code and reason.
+    Protocol(u16, String),
+}
+
+impl From<io::Error> for WebSocketError {
+    fn from(error: io::Error) -> WebSocketError {
+        WebSocketError::Io(error)
+    }
+}
+
+// Checks that the request asks for a WebSocket and returns the 101 response to send back, along with the future that
+// resolves to the connection once hyper has handed it over.
+pub fn accept(mut request: Request) -> Result<(Response, OnUpgrade), String> {
+    let headers = request.headers();
+    let has_token = |name: header::HeaderName, token: &str| headers.get_all(name).iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|value| value.trim().eq_ignore_ascii_case(token));
+    if !has_token(header::UPGRADE, "websocket") || !has_token(header::CONNECTION, "upgrade") {
+        return Err("Expected a WebSocket upgrade request".to_string());
+    }
+    if headers.get(header::SEC_WEBSOCKET_VERSION).is_none_or(|version| version != "13") {
+        return Err("Only WebSocket version 13 is supported".to_string());
+    }
+    let key = headers.get(header::SEC_WEBSOCKET_KEY).and_then(|key| key.to_str().ok())
+        .ok_or("Sec-WebSocket-Key is missing")?;
+    let accept = HeaderValue::from_str(&accept_key(key)).expect("base64 is a valid header value");
+
+    let upgrade = hyper::upgrade::on(&mut request);
+    let response = Response::builder()
+        .status(StatusCode::SWITCHING_PROTOCOLS)
+        .header(header::UPGRADE, "websocket")
+        .header(header::CONNECTION, "upgrade")
+        .header(header::SEC_WEBSOCKET_ACCEPT, accept)
+        .body(Body::empty())
+        .expect("static response parts are valid");
+    Ok((response, upgrade))
+}
+
+// Sec-WebSocket-Accept for a client's Sec-WebSocket-Key.
+pub fn accept_key(key: &str) -> String {
+    base64(&sha1(format!("{}{}", key.trim(), ACCEPT_GUID).as_bytes()))
+}
+
+// Reads whole messages from the client, joining fragmented ones. Not cancel safe, so it should get a task of its own.
+pub struct MessageReader<R> {
+    reader: R,
+    max_len: usize,
+    // Opcode and payload so far of a fragmented message.
+    partial: Option<(u8, Vec<u8>)>,
+}
+
+impl<R: AsyncRead + Unpin> MessageReader<R> {
+    pub fn new(reader: R, max_len: usize) -> MessageReader<R> {
+        MessageReader { reader, max_len, partial: None }
+    }
+
+    // The next message. Control frames are returned as soon as they arrive, even in the middle of a fragmented one.
+    pub async fn next(&mut self) -> Result<Message, WebSocketError> {
+        loop {
+            let (fin, opcode, payload) = self.read_frame().await?;
+            match opcode {
+                OPCODE_CLOSE => {
+                    let close = match payload.len() {
+                        0 => None,
+                        1 => return Err(protocol_error("Close payload is one byte long")),
+                        _ => Some((u16::from_be_bytes([payload[0], payload[1]]), String::from_utf8_lossy(&payload[2..]).into_owned())),
+                    };
+                    return Ok(Message::Close(close));
+                },
+                OPCODE_PING => return Ok(Message::Ping(payload)),
+                OPCODE_PONG => return Ok(Message::Pong(payload)),
+                OPCODE_TEXT | OPCODE_BINARY if self.partial.is_some() => return Err(protocol_error("Expected a continuation frame")),
+                OPCODE_TEXT | OPCODE_BINARY if fin => return finish(opcode, payload),
+                OPCODE_TEXT | OPCODE_BINARY => self.partial = Some((opcode, payload)),
+                OPCODE_CONTINUATION => {
+                    let (_, partial) = self.partial.as_mut().ok_or_else(|| protocol_error("Continuation frame without a message to continue"))?;
+                    if partial.len() + payload.len() > self.max_len {
+                        return Err(too_big(self.max_len));
+                    }
+                    partial.extend_from_slice(&payload);
+                    if fin {
+                        let (opcode, payload) = self.partial.take().unwrap();
+                        return finish(opcode, payload);
+                    }
+                },
+                other => return Err(protocol_error(&format!("Unknown opcode {:#x}", other))),
+            }
+        }
+    }
+
+    async fn read_frame(&mut self) -> Result<(bool, u8, Vec<u8>), WebSocketError> {
+        let mut header = [0u8; 2];
+        self.reader.read_exact(&mut header).await?;
+        let fin = header[0] & 0x80 != 0;
+        let opcode = header[0] & 0x0f;
+        if header[0] & 0x70 != 0 {
+            return Err(protocol_error("Reserved bits are set but no extension was negotiated"));
+        }
+        if header[1] & 0x80 == 0 {
+            return Err(protocol_error("Frames from the client must be masked"));
+        }
+        let len = match header[1] & 0x7f {
+            126 => self.reader.read_u16().await? as u64,
+            127 => self.reader.read_u64().await?,
+            len => len as u64,
+        };
+        if opcode & 0x8 != 0 && (len > 125 || !fin) {
+            return Err(protocol_error("Control frames must be unfragmented and at most 125 bytes"));
+        }
+        if len > self.max_len as u64 {
+            return Err(too_big(self.max_len));
+        }
+        let mut mask = [0u8; 4];
+        self.reader.read_exact(&mut mask).await?;
+        let mut payload = vec![0; len as usize];
+        self.reader.read_exact(&mut payload).await?;
+        for (i, byte) in payload.iter_mut().enumerate() {
+            *byte ^= mask[i % 4];
+        }
+        Ok((fin, opcode, payload))
+    }
+}
+
+fn finish(opcode: u8, payload: Vec<u8>) -> Result<Message, WebSocketError> {
+    match opcode {
+        OPCODE_TEXT => String::from_utf8(payload).map(Message::Text)
+            .map_err(|_| WebSocketError::Protocol(CLOSE_INVALID_DATA, "Text messages must be UTF-8".to_string())),
+        _ => Ok(Message::Binary(payload)),
+    }
+}
+
+fn protocol_error(reason: &str) -> WebSocketError {
+    WebSocketError::Protocol(CLOSE_PROTOCOL_ERROR, reason.to_string())
+}
+
+fn too_big(max_len: usize) -> WebSocketError {
+    WebSocketError::Protocol(CLOSE_TOO_BIG, format!("Messages are limited to {} bytes", max_len))
+}
+
+// Sends one message as a single unmasked frame, as servers do.
+pub async fn write_message<W: AsyncWrite + Unpin>(writer: &mut W, message: &Message) -> io::Result<()> {
+    let close_payload;
+    let (opcode, payload): (u8, &[u8]) = match message {
+        Message::Text(text) => (OPCODE_TEXT, text.as_bytes()),
+        Message::Binary(data) => (OPCODE_BINARY, data),
+        Message::Ping(data) => (OPCODE_PING, data),
+        Message::Pong(data) => (OPCODE_PONG, data),
+        Message::Close(None) => (OPCODE_CLOSE, &[]),
+        Message::Close(Some((code, reason))) => {
+            close_payload = [code.to_be_bytes().as_slice(), reason.as_bytes()].concat();
+            (OPCODE_CLOSE, &close_payload)
+        },
+    };
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        len @ 0..=125 => frame.push(len as u8),
+        len @ 126..=0xffff => {
+            frame.push(126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        },
+        len => {
+            frame.push(127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        },
+    }
+    frame.extend_from_slice(payload);
+    writer.write_all(&frame).await?;
+    writer.flush().await
+}
+
+// SHA-1 (RFC 3174). Only used for the handshake, where the protocol requires it, not for anything that needs to be
+// secure.
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut state: [u32; 5] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476, 0xc3d2e1f0];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&(data.len() as u64 * 8).to_be_bytes());
+
+    for block in message.chunks(64) {
+        let mut words = [0u32; 80];
+        for (word, bytes) in words.iter_mut().zip(block.chunks(4)) {
+            *word = u32::from_be_bytes(bytes.try_into().unwrap());
+        }
+        for i in 16..80 {
+            words[i] = (words[i - 3] ^ words[i - 8] ^ words[i - 14] ^ words[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = state;
+        for (i, word) in words.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5a827999),
+                20..=39 => (b ^ c ^ d, 0x6ed9eba1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8f1bbcdc),
+                _ => (b ^ c ^ d, 0xca62c1d6),
+            };
+            let temp = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        for (value, add) in state.iter_mut().zip([a, b, c, d, e]) {
+            *value = value.wrapping_add(add);
+        }
+    }
+
+    let mut digest = [0u8; 20];
+    for (bytes, value) in digest.chunks_mut(4).zip(state) {
+        bytes.copy_from_slice(&value.to_be_bytes());
+    }
+    digest
+}
+
+// Standard base64 with padding.
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::new();
+    for chunk in bytes.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, &byte)| bits | (byte as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(bits >> (18 - 6 * i)) as usize & 63] as char);
+            }
+            else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
@@ -0,0 +1,57 @@
+use axum::{body::Body, http::{Request, StatusCode}, response::Response, Router};
+use http_body_util::BodyExt;
+use syndica_rust::{crypto, settings::Settings, test_app, test_app_with};
+use tower::ServiceExt;
+
+async fn get(app: &Router, uri: &str) -> Response {
+    app.clone().oneshot(Request::get(uri).body(Body::empty()).unwrap()).await.unwrap()
+}
+
+async fn text(response: Response) -> String {
+    String::from_utf8(response.into_body().collect().await.unwrap().to_bytes().to_vec()).unwrap()
+}
+
+#[tokio::test]
+async fn the_page_and_its_files_are_served() {
+    let app = test_app();
+    let page = get(&app, "/admin/ui").await;
+    assert_eq!(page.status(), StatusCode::OK);
+    assert_eq!(page.headers()["content-type"], "text/html; charset=utf-8");
+    assert!(page.headers()["content-security-policy"].to_str().unwrap().contains("script-src 'self'"));
+    let page = text(page).await;
+
+    for (file, content_type) in [("/admin/ui/app.js", "text/javascript; charset=utf-8"), ("/admin/ui/style.css", "text/css; charset=utf-8")] {
+        assert!(page.contains(&format!("\"{}\"", file)), "the page doesn't load {}", file);
+        let response = get(&app, file).await;
+        assert_eq!((response.status(), response.headers()["content-type"].to_str().unwrap()), (StatusCode::OK, content_type));
+        assert_eq!(response.headers()["cache-control"], "no-cache");
+    }
+    // What the script calls is there.
+    let script = text(get(&app, "/admin/ui/app.js").await).await;
+    for path in ["/v1/movies/stats", "/v1/movies?", "/v1/movie"] {
+        assert!(script.contains(&format!("\"{}", path)));
+    }
+    assert_eq!(get(&app, "/v1/movies/stats").await.status(), StatusCode::OK);
+    assert_eq!(get(&app, "/admin/ui/nope.js").await.status(), StatusCode::NOT_FOUND);
+    assert_eq!(get(&app, "/admin/ui/..%2Fmain.rs").await.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn anyone_may_load_it_but_writes_still_need_a_key() {
+    let settings = Settings::default();
+    settings.set_api_keys(vec![crypto::sha256(b"admin-ui-key")]);
+    let app = test_app_with(settings);
+    assert_eq!(get(&app, "/admin/ui").await.status(), StatusCode::OK);
+    assert_eq!(get(&app, "/admin/ui/app.js").await.status(), StatusCode::OK);
+    let movie = r#"{ "name": "Alien", "year": 1979, "was_good": true }"#;
+    let create = |key: Option<&str>| {
+        let request = Request::post("/v1/movie").header("content-type", "application/json");
+        let request = match key {
+            Some(key) => request.header("x-api-key", key),
+            None => request,
+        };
+        app.clone().oneshot(request.body(Body::from(movie)).unwrap())
+    };
+    assert_eq!(create(None).await.unwrap().status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(create(Some("admin-ui-key")).await.unwrap().status(), StatusCode::CREATED);
+}
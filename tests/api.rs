@@ -0,0 +1,74 @@
+mod common;
+
+use axum::{http::{header, StatusCode}, Router};
+use serde_json::{json, Value};
+use syndica_rust::test_app;
+use common::{json, send_request};
+
+// The basic movie flows end to end, through every layer of the router, as a client would see them.
+
+async fn send(app: &Router, method: &str, uri: &str, body: Option<Value>) -> (StatusCode, Option<String>, Value) {
+    let response = send_request(app, method, uri, &[], body).await;
+    let location = response.headers().get(header::LOCATION).map(|location| location.to_str().unwrap().to_string());
+    (response.status(), location, json(response).await)
+}
+
+#[tokio::test]
+async fn a_posted_movie_can_be_read_back() {
+    let app = test_app();
+    let alien = json!({ "id": "alien", "name": "Alien", "year": 1979, "was_good": true });
+    let (status, location, created) = send(&app, "POST", "/v1/movie", Some(alien)).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(location.as_deref(), Some("/movie/alien"));
+    assert_eq!((created["id"].as_str(), created["name"].as_str()), (Some("alien"), Some("Alien")));
+
+    let (status, _, movie) = send(&app, "GET", "/v1/movie/alien", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(movie, created);
+    // Unversioned paths reach the same movies.
+    assert_eq!(send(&app, "GET", "/movie/alien", None).await.2, created);
+    assert_eq!(send(&app, "GET", "/v1/movies", None).await.2["total"], 1);
+
+    // Without an id, one is made up.
+    let (status, location, heat) = send(&app, "POST", "/v1/movie", Some(json!({ "name": "Heat", "year": 1995, "was_good": true }))).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(location, Some(format!("/movie/{}", heat["id"].as_str().unwrap())));
+}
+
+#[tokio::test]
+async fn a_duplicate_id_is_a_conflict() {
+    let app = test_app();
+    let alien = json!({ "id": "alien", "name": "Alien", "year": 1979, "was_good": true });
+    assert_eq!(send(&app, "POST", "/v1/movie", Some(alien)).await.0, StatusCode::CREATED);
+
+    let (status, _, body) = send(&app, "POST", "/v1/movie", Some(json!({ "id": "alien", "name": "Aliens", "year": 1986, "was_good": true }))).await;
+    assert_eq!((status, body["error"]["code"].as_str()), (StatusCode::CONFLICT, Some("already_exists")));
+    // The first one is left as it was, unless the second asks to replace it.
+    assert_eq!(send(&app, "GET", "/v1/movie/alien", None).await.2["name"], "Alien");
+    let (status, _, _) = send(&app, "POST", "/v1/movie?upsert=true", Some(json!({ "id": "alien", "name": "Aliens", "year": 1986, "was_good": true }))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(send(&app, "GET", "/v1/movie/alien", None).await.2["name"], "Aliens");
+}
+
+#[tokio::test]
+async fn missing_movies_are_not_found() {
+    let app = test_app();
+    let (status, _, body) = send(&app, "GET", "/v1/movie/nope", None).await;
+    assert_eq!((status, body["error"]["code"].as_str()), (StatusCode::NOT_FOUND, Some("not_found")));
+    assert!(body["error"]["request_id"].is_string());
+    assert_eq!(send(&app, "DELETE", "/v1/movie/nope", None).await.0, StatusCode::NOT_FOUND);
+
+    assert_eq!(send(&app, "POST", "/v1/movie", Some(json!({ "id": "ran", "name": "Ran", "year": 1985, "was_good": true }))).await.0, StatusCode::CREATED);
+    assert_eq!(send(&app, "DELETE", "/v1/movie/ran", None).await.0, StatusCode::NO_CONTENT);
+    assert_eq!(send(&app, "GET", "/v1/movie/ran", None).await.0, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn bad_movies_are_turned_away() {
+    let app = test_app();
+    let (status, _, body) = send(&app, "POST", "/v1/movie", Some(json!({ "name": "Alien", "year": "1979", "was_good": true }))).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{}", body);
+    let (status, _, _) = send(&app, "POST", "/v1/movie", Some(json!({ "name": "", "year": 1979, "was_good": true }))).await;
+    assert!(status.is_client_error());
+    assert_eq!(send(&app, "GET", "/v1/movies", None).await.2["total"], 0);
+}
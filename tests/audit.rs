@@ -0,0 +1,68 @@
+mod common;
+
+use axum::{http::StatusCode, Router};
+use serde_json::{json, Value};
+use syndica_rust::{audit, crypto, settings::Settings, test_app_with};
+use common::send_with;
+
+const KEY: &str = "an-audited-key";
+
+async fn send(app: &Router, method: &str, uri: &str, key: Option<&str>, body: Option<Value>) -> (StatusCode, Value) {
+    let headers: Vec<_> = key.map(|key| ("x-api-key", key)).into_iter().collect();
+    send_with(app, method, uri, &headers, body).await
+}
+
+// The audit log is process-wide, so everything that depends on them is in this one test.
+#[tokio::test]
+async fn writes_are_audited_with_their_actor_and_changes() {
+    let path = std::env::temp_dir().join(format!("syndica-audit-{}.audit", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    audit::open(&path).unwrap();
+    let settings = Settings::default();
+    settings.set_api_keys(vec![crypto::sha256(KEY.as_bytes())]);
+    let actor = format!("key:{}", crypto::sha256(KEY.as_bytes())[..4].iter().map(|byte| format!("{:02x}", byte)).collect::<String>());
+    let app = test_app_with(settings);
+
+    let alien = json!({ "id": "alien", "name": "Alien", "year": 1979, "was_good": true });
+    assert_eq!(send(&app, "POST", "/v1/movie", Some(KEY), Some(alien)).await.0, StatusCode::CREATED);
+    let aliens = json!({ "id": "aliens", "name": "Aliens", "year": 1986, "was_good": true });
+    assert_eq!(send(&app, "POST", "/v1/movie", Some(KEY), Some(aliens)).await.0, StatusCode::CREATED);
+    assert_eq!(send(&app, "PATCH", "/v1/movie/alien", Some(KEY), Some(json!({ "director": "Ridley Scott" }))).await.0, StatusCode::OK);
+    let link = json!({ "movie_id": "alien", "relation": "sequel-of" });
+    assert_eq!(send(&app, "POST", "/v1/movie/aliens/related", Some(KEY), Some(link)).await.0, StatusCode::CREATED);
+    assert_eq!(send(&app, "DELETE", "/v1/movie/alien", Some(KEY), None).await.0, StatusCode::NO_CONTENT);
+
+    assert_eq!(send(&app, "GET", "/admin/audit", None, None).await.0, StatusCode::UNAUTHORIZED);
+    let (status, page) = send(&app, "GET", &format!("/admin/audit?actor={actor}"), Some(KEY), None).await;
+    assert_eq!((status, page["total"].as_u64()), (StatusCode::OK, Some(5)));
+    let entries = page["items"].as_array().unwrap();
+    let summary: Vec<(&str, &str, &str)> = entries.iter().map(|entry| (entry["action"].as_str().unwrap(), entry["entity"].as_str().unwrap(), entry["id"].as_str().unwrap())).collect();
+    assert_eq!(summary, [
+        ("created", "movie", "alien"),
+        ("created", "movie", "aliens"),
+        ("updated", "movie", "alien"),
+        ("created", "link", "aliens sequel-of alien"),
+        ("deleted", "movie", "alien"),
+    ]);
+    assert_eq!(entries[0]["changes"]["name"], json!({ "before": null, "after": "Alien" }));
+    assert_eq!(entries[2]["changes"]["director"], json!({ "before": null, "after": "Ridley Scott" }));
+    assert!(entries[2]["changes"].get("name").is_none());
+    assert_eq!(entries[4]["changes"]["director"], json!({ "before": "Ridley Scott", "after": null }));
+    assert!(entries.windows(2).all(|pair| pair[0]["seq"].as_u64() < pair[1]["seq"].as_u64()));
+
+    let (_, later) = send(&app, "GET", &format!("/admin/audit?actor={actor}&since={}&limit=2", entries[1]["at"].as_str().unwrap()), Some(KEY), None).await;
+    assert_eq!(later["items"][0]["seq"], entries[2]["seq"]);
+    assert!(later["next"].as_str().unwrap().contains("offset=2"));
+    assert_eq!(send(&app, "GET", "/admin/audit?actor=key:nobody", Some(KEY), None).await.1["total"], 0);
+    assert_eq!(send(&app, "GET", "/admin/audit?since=yesterday", Some(KEY), None).await.0, StatusCode::BAD_REQUEST);
+
+    // The entries are in the file, and come back from it.
+    let lines: Vec<Value> = std::fs::read_to_string(&path).unwrap().lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    assert_eq!(lines.len(), 5);
+    audit::open(&path).unwrap();
+    let reloaded = audit::entries(None, Some(&actor));
+    assert_eq!(serde_json::to_value(&reloaded).unwrap(), json!(entries));
+    send(&app, "PATCH", "/v1/movie/aliens", Some(KEY), Some(json!({ "year": 1987 }))).await;
+    assert_eq!(audit::entries(None, Some(&actor)).last().unwrap().seq, 6);
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,76 @@
+mod common;
+
+use axum::{body::Body, http::{Request, StatusCode}, Router};
+use serde_json::{json, Value};
+use syndica_rust::{auth, crypto, settings::Settings, test_app, test_app_with};
+use tower::ServiceExt;
+use common::send_with;
+
+// SHA-256 of "secret".
+const SECRET_HASH: &str = "2bb80d537b1da3e38bd30361aa855686bde0eacd7162fef6a25fe97bf527a25b";
+
+async fn send(app: &Router, method: &str, uri: &str, key: Option<&str>, body: Option<Value>) -> (StatusCode, Value) {
+    let headers: Vec<_> = key.map(|key| ("x-api-key", key)).into_iter().collect();
+    send_with(app, method, uri, &headers, body).await
+}
+
+#[tokio::test]
+async fn writes_need_a_configured_key() {
+    let settings = Settings::default();
+    let app = test_app_with(settings.clone());
+    let movie = json!({ "id": "alien", "name": "Alien", "year": 1979, "was_good": true });
+    // No keys, no authentication.
+    assert_eq!(send(&app, "POST", "/movie", None, Some(movie.clone())).await.0, StatusCode::CREATED);
+
+    settings.set_api_keys(vec![auth::parse_key_hash(SECRET_HASH).unwrap()]);
+    let aliens = json!({ "id": "aliens", "name": "Aliens", "year": 1986, "was_good": true });
+    let (status, error) = send(&app, "POST", "/movie", None, Some(aliens.clone())).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(error["error"]["code"], "unauthorized");
+    assert_eq!(send(&app, "POST", "/movie", Some("guess"), Some(aliens.clone())).await.0, StatusCode::UNAUTHORIZED);
+    assert_eq!(send(&app, "DELETE", "/movie/alien", None, None).await.0, StatusCode::UNAUTHORIZED);
+    assert_eq!(send(&app, "POST", "/movie", Some("secret"), Some(aliens)).await.0, StatusCode::CREATED);
+    assert_eq!(send(&app, "DELETE", "/movie/alien", Some("secret"), None).await.0, StatusCode::NO_CONTENT);
+
+    // Reads stay open.
+    assert_eq!(send(&app, "GET", "/movie/aliens", None, None).await.0, StatusCode::OK);
+    let (status, response) = send(&app, "POST", "/graphql", None, Some(json!({ "query": "{ movie(id: \"aliens\") { name } }" }))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(response["data"]["movie"]["name"], "Aliens");
+
+    // GraphQL lets queries through without a key, but not mutations.
+    let mutation = json!({ "query": "mutation { deleteMovie(id: \"aliens\") { id } }" });
+    let (_, response) = send(&app, "POST", "/graphql", None, Some(mutation.clone())).await;
+    assert_eq!(response["errors"][0]["extensions"]["code"], "unauthorized");
+    let (_, response) = send(&app, "POST", "/graphql", Some("secret"), Some(mutation)).await;
+    assert_eq!(response["data"]["deleteMovie"]["id"], "aliens");
+
+    // Idempotency-Keys are the caller's own: another key that happens to pick the same one doesn't get its response.
+    settings.set_api_keys(vec![auth::parse_key_hash(SECRET_HASH).unwrap(), crypto::sha256(b"other")]);
+    let heat = json!({ "name": "Heat", "year": 1995, "was_good": true });
+    let post = |key: &'static str| {
+        let request = Request::post("/movie").header("content-type", "application/json").header("x-api-key", key).header("idempotency-key", "shared");
+        app.clone().oneshot(request.body(Body::from(heat.to_string())).unwrap())
+    };
+    let first = post("secret").await.unwrap();
+    let theirs = first.headers()["location"].clone();
+    assert_eq!(post("secret").await.unwrap().headers()["location"], theirs);
+    let other = post("other").await.unwrap();
+    assert_eq!(other.status(), StatusCode::CREATED);
+    assert!(other.headers().get("idempotent-replayed").is_none());
+    assert_ne!(other.headers()["location"], theirs);
+
+    assert!(auth::parse_key_hash("secret").is_err());
+}
+
+#[tokio::test]
+async fn each_router_has_its_own_keys() {
+    let settings = Settings::default();
+    settings.set_api_keys(vec![auth::parse_key_hash(SECRET_HASH).unwrap()]);
+    let locked = test_app_with(settings);
+    let open = test_app();
+    let movie = json!({ "id": "alien", "name": "Alien", "year": 1979, "was_good": true });
+    assert_eq!(send(&locked, "POST", "/movie", None, Some(movie.clone())).await.0, StatusCode::UNAUTHORIZED);
+    assert_eq!(send(&open, "POST", "/movie", None, Some(movie.clone())).await.0, StatusCode::CREATED);
+    assert_eq!(send(&locked, "POST", "/movie", Some("secret"), Some(movie)).await.0, StatusCode::CREATED);
+}
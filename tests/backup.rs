@@ -0,0 +1,143 @@
+mod common;
+
+use std::{path::Path, sync::Arc};
+
+use axum::{body::Body, http::{header, Request, StatusCode}, Router};
+use http_body_util::BodyExt;
+use serde_json::{json, Value};
+use syndica_rust::{audit, backup, build_router, cache::{CacheConfig, CachedMovieStore}, event_sourced::EventSourcedMovieStore, settings::Settings, state::{state_init, StateWrapper}, wal::{WalConfig, WalMovieStore}};
+use tower::ServiceExt;
+use common::send;
+
+async fn create(app: &Router, id: &str, name: &str) {
+    let movie = json!({ "id": id, "name": name, "year": 1979, "was_good": true });
+    assert_eq!(send(app, "POST", "/v1/movie", Some(movie)).await.0, StatusCode::CREATED);
+}
+
+// Everything a backup should bring back, as clients see it.
+async fn everything(app: &Router) -> Value {
+    json!({
+        "movies": send(app, "GET", "/v1/movies", None).await.1["items"],
+        "trash": send(app, "GET", "/v1/movies/trash", None).await.1["items"],
+        "ratings": send(app, "GET", "/v1/movie/alien/ratings", None).await.1,
+        "related": send(app, "GET", "/v1/movie/aliens/related", None).await.1,
+        "watchlist": send(app, "GET", "/v1/users/ripley/watchlist", None).await.1,
+    })
+}
+
+async fn fill(app: &Router) {
+    create(app, "alien", "Alien").await;
+    create(app, "aliens", "Aliens").await;
+    create(app, "heat", "Heat").await;
+    send(app, "POST", "/v1/movie/alien/ratings", Some(json!({ "user": "ripley", "score": 9 }))).await;
+    send(app, "POST", "/v1/movie/aliens/related", Some(json!({ "movie_id": "alien", "relation": "sequel-of" }))).await;
+    send(app, "POST", "/v1/users", Some(json!({ "id": "ripley" }))).await;
+    send(app, "POST", "/v1/users/ripley/watchlist", Some(json!({ "movie_id": "aliens" }))).await;
+    send(app, "DELETE", "/v1/movie/heat", None).await;
+}
+
+// Changes everything fill did.
+async fn spoil(app: &Router) {
+    send(app, "PATCH", "/v1/movie/alien", Some(json!({ "name": "Alien 3" }))).await;
+    send(app, "DELETE", "/v1/movie/aliens", None).await;
+    send(app, "POST", "/v1/movie/heat/restore", None).await;
+    create(app, "ran", "Ran").await;
+}
+
+#[tokio::test]
+async fn a_downloaded_backup_restores_everything() {
+    let state: StateWrapper = Arc::new(CachedMovieStore::new(state_init(), CacheConfig { capacity: 10, ttl: None }));
+    let app = build_router(state.clone(), Settings::default());
+    fill(&app).await;
+    let before = everything(&app).await;
+
+    let request = Request::builder().method("POST").uri("/admin/snapshot?download=true").body(Body::empty()).unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let disposition = response.headers()[header::CONTENT_DISPOSITION].to_str().unwrap().to_string();
+    assert!(disposition.starts_with("attachment; filename=\"movies-") && disposition.ends_with(".json\""));
+    let backup: Value = serde_json::from_slice(&response.into_body().collect().await.unwrap().to_bytes()).unwrap();
+    assert_eq!(backup["movies"][0]["ratings"], json!({ "ripley": 9 }));
+
+    // Cached before the restore, so it has to be dropped by it.
+    assert_eq!(send(&app, "GET", "/v1/movie/alien", None).await.1["name"], "Alien");
+    spoil(&app).await;
+    assert_ne!(everything(&app).await, before);
+    let mut changes = state.subscribe();
+    let (status, summary) = send(&app, "POST", "/admin/restore", Some(backup.clone())).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!((summary["movies"].as_u64(), summary["users"].as_u64(), summary["links"].as_u64(), summary["trash"].as_u64()), (Some(2), Some(1), Some(1), Some(1)));
+    assert_eq!(summary["taken_at"], backup["taken_at"]);
+    assert_eq!(everything(&app).await, before);
+    assert_eq!(send(&app, "GET", "/v1/movie/alien", None).await.1["name"], "Alien");
+    let mut published = Vec::new();
+    while let Ok(change) = changes.try_recv() {
+        published.push((change.name(), change.movie().id.clone()));
+    }
+    assert_eq!(published, [("updated", "alien".to_string()), ("created", "aliens".to_string()), ("deleted", "heat".to_string()), ("deleted", "ran".to_string())]);
+    assert!(audit::entries(None, None).iter().any(|entry| entry.entity == "store" && entry.changes["movies"].after == json!(2)));
+
+    // Nothing is replaced by a backup that doesn't hold together.
+    let mut broken = backup.clone();
+    broken["links"][0]["to"] = json!("nope");
+    assert_eq!(send(&app, "POST", "/admin/restore", Some(broken)).await.0, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(send(&app, "POST", "/admin/restore", Some(json!({ "movies": [] }))).await.0, StatusCode::BAD_REQUEST);
+    assert_eq!(everything(&app).await, before);
+}
+
+async fn open_wal(path: &Path) -> Router {
+    build_router(Arc::new(WalMovieStore::open(WalConfig { path: path.to_path_buf(), max_bytes: u64::MAX }).await.unwrap()), Settings::default())
+}
+
+// The backup directory is process-wide, so only this test sets it.
+#[tokio::test]
+async fn backups_in_the_backup_dir_survive_a_restart() {
+    let dir = std::env::temp_dir().join(format!("syndica-backups-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let log = dir.join("movies.wal");
+    backup::set_dir(Some(dir.clone()));
+    let app = open_wal(&log).await;
+    fill(&app).await;
+    let before = everything(&app).await;
+
+    let (status, summary) = send(&app, "POST", "/admin/snapshot", None).await;
+    assert_eq!((status, summary["movies"].as_u64()), (StatusCode::CREATED, Some(2)));
+    let name = summary["name"].as_str().unwrap().to_string();
+    assert!(dir.join(&name).exists());
+    spoil(&app).await;
+    assert_eq!(send(&app, "POST", &format!("/admin/restore?name={name}"), None).await.1["name"], name.as_str());
+    assert_eq!(everything(&app).await, before);
+    // The log was rewritten to match, so it's still restored once replayed.
+    assert_eq!(everything(&open_wal(&log).await).await, before);
+
+    assert_eq!(send(&app, "POST", "/admin/restore?name=nope.json", None).await.0, StatusCode::NOT_FOUND);
+    assert_eq!(send(&app, "POST", "/admin/restore?name=../movies.wal", None).await.0, StatusCode::BAD_REQUEST);
+    backup::set_dir(None);
+    assert_eq!(send(&app, "POST", &format!("/admin/restore?name={name}"), None).await.0, StatusCode::BAD_REQUEST);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn restoring_into_an_event_log_keeps_history() {
+    let path = std::env::temp_dir().join(format!("syndica-backup-{}.events", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let open = || async { build_router(Arc::new(EventSourcedMovieStore::open(path.clone()).await.unwrap()), Settings::default()) };
+    let app = open().await;
+    create(&app, "alien", "Alien").await;
+    let request = Request::builder().method("POST").uri("/admin/snapshot?download=true").body(Body::empty()).unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let backup: Value = serde_json::from_slice(&response.into_body().collect().await.unwrap().to_bytes()).unwrap();
+    send(&app, "PATCH", "/v1/movie/alien", Some(json!({ "name": "Alien 3" }))).await;
+    create(&app, "ran", "Ran").await;
+    assert_eq!(send(&app, "POST", "/admin/restore", Some(backup)).await.0, StatusCode::OK);
+
+    for app in [app, open().await] {
+        let (_, history) = send(&app, "GET", "/v1/movie/alien/history", None).await;
+        let events: Vec<&str> = history["items"].as_array().unwrap().iter().map(|revision| revision["event"].as_str().unwrap()).collect();
+        assert_eq!(events, ["created", "updated", "updated"]);
+        assert_eq!(history["items"][2]["movie"]["name"], "Alien");
+        assert_eq!(send(&app, "GET", "/v1/movie/ran", None).await.0, StatusCode::NOT_FOUND);
+        assert_eq!(send(&app, "GET", "/v1/movie/ran/history", None).await.1["items"][1]["event"], "deleted");
+    }
+    std::fs::remove_file(&path).unwrap();
+}
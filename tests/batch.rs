@@ -0,0 +1,79 @@
+mod common;
+
+use axum::http::StatusCode;
+use serde_json::{json, Value};
+use syndica_rust::test_app;
+use common::send;
+
+fn statuses(report: &Value) -> Vec<&str> {
+    report["results"].as_array().unwrap().iter().map(|result| result["status"].as_str().unwrap()).collect()
+}
+
+#[tokio::test]
+async fn each_item_gets_its_own_result() {
+    let app = test_app();
+    send(&app, "POST", "/movie", Some(json!({ "id": "alien", "name": "Alien", "year": 1979, "was_good": true }))).await;
+
+    let batch = json!([
+        { "id": "cats", "name": "Cats", "year": 2019, "was_good": false },
+        { "name": "Heat", "year": 1995, "was_good": true },
+        { "id": "alien", "name": "Alien again", "year": 1979, "was_good": true },
+        { "id": "cats", "name": "Cats twice", "year": 2019, "was_good": false },
+        { "id": "bad id", "name": "", "year": 1979, "was_good": true },
+        { "name": "No year", "was_good": true },
+    ]);
+    let (status, report) = send(&app, "POST", "/movies/batch", Some(batch)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(statuses(&report), ["created", "created", "duplicate", "duplicate", "invalid", "invalid"]);
+    assert_eq!((report["created"].as_u64(), report["duplicate"].as_u64(), report["invalid"].as_u64()), (Some(2), Some(2), Some(2)));
+    assert_eq!(report["results"][0]["id"], "cats");
+    assert_eq!(report["results"][1]["id"].as_str().unwrap().len(), 36);
+    assert_eq!(report["results"][2]["error"]["code"], "already_exists");
+    assert_eq!(report["results"][2]["error"]["details"]["existing"]["name"], "Alien");
+    assert_eq!(report["results"][4]["error"]["code"], "validation_failed");
+    assert_eq!(report["results"][5]["error"]["code"], "invalid_body");
+
+    let (_, page) = send(&app, "GET", "/movies", None).await;
+    assert_eq!(page["total"], 3);
+    assert_eq!(send(&app, "GET", "/movie/cats", None).await.1["name"], "Cats");
+}
+
+#[tokio::test]
+async fn upsert_and_limits() {
+    let app = test_app();
+    send(&app, "POST", "/movie", Some(json!({ "id": "alien", "name": "Alien", "year": 1979, "was_good": true }))).await;
+    let batch = json!([
+        { "id": "alien", "name": "Aliens", "year": 1986, "was_good": true },
+        { "id": "heat", "name": "Heat", "year": 1995, "was_good": true },
+    ]);
+    let (_, report) = send(&app, "POST", "/movies/batch?upsert=true", Some(batch)).await;
+    assert_eq!(statuses(&report), ["updated", "created"]);
+    assert_eq!(send(&app, "GET", "/movie/alien", None).await.1["name"], "Aliens");
+
+    let too_many: Vec<Value> = (0..1001).map(|i| json!({ "name": format!("Movie {}", i), "year": 2000, "was_good": true })).collect();
+    let (status, body) = send(&app, "POST", "/movies/batch", Some(Value::Array(too_many))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"]["code"], "bad_request");
+    assert_eq!(send(&app, "GET", "/movies", None).await.1["total"], 2);
+
+    let (status, _) = send(&app, "POST", "/movies/batch", Some(json!({ "not": "an array" }))).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[tokio::test]
+async fn lookup_returns_what_was_found_and_what_wasnt() {
+    let app = test_app();
+    for (id, name) in [("alien", "Alien"), ("heat", "Heat")] {
+        send(&app, "POST", "/movie", Some(json!({ "id": id, "name": name, "year": 1979, "was_good": true }))).await;
+    }
+    let (status, found) = send(&app, "POST", "/movies/lookup", Some(json!(["heat", "ran", "alien", "heat", "cats"]))).await;
+    assert_eq!(status, StatusCode::OK);
+    let names: Vec<&str> = found["items"].as_array().unwrap().iter().map(|movie| movie["name"].as_str().unwrap()).collect();
+    assert_eq!(names, ["Heat", "Alien"]);
+    assert_eq!(found["missing"], json!(["ran", "cats"]));
+    assert_eq!(send(&app, "POST", "/v1/movies/lookup", Some(json!([]))).await.1, json!({ "items": [], "missing": [] }));
+
+    let too_many: Vec<String> = (0..1001).map(|i| format!("movie-{}", i)).collect();
+    assert_eq!(send(&app, "POST", "/movies/lookup", Some(json!(too_many))).await.0, StatusCode::BAD_REQUEST);
+    assert_eq!(send(&app, "POST", "/movies/lookup", Some(json!([1, 2]))).await.0, StatusCode::UNPROCESSABLE_ENTITY);
+}
@@ -0,0 +1,54 @@
+use axum::{body::Body, http::{Request, StatusCode}, Router};
+use futures_util::stream;
+use http_body_util::BodyExt;
+use serde_json::{json, Value};
+use syndica_rust::{body_limit, settings::Settings, test_app_with};
+use tower::ServiceExt;
+
+async fn send(app: &Router, uri: &str, content_type: &str, body: Body, length: Option<usize>) -> (StatusCode, Value) {
+    let mut request = Request::post(uri).header("content-type", content_type);
+    if let Some(length) = length {
+        request = request.header("content-length", length);
+    }
+    let response = app.clone().oneshot(request.body(body).unwrap()).await.unwrap();
+    let status = response.status();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+// Sent a chunk at a time with no Content-Length, so the limit can only be noticed as it's read.
+fn streamed(body: String) -> Body {
+    let chunks: Vec<Result<String, std::io::Error>> = body.into_bytes().chunks(100).map(|chunk| Ok(String::from_utf8(chunk.to_vec()).unwrap())).collect();
+    Body::from_stream(stream::iter(chunks))
+}
+
+#[tokio::test]
+async fn bodies_past_their_routes_limit_get_a_413() {
+    let settings = Settings::default();
+    settings.set_body_limits(1000, 5000);
+    let app = test_app_with(settings.clone());
+    let movie = |synopsis_len: usize| json!({ "name": "Alien", "year": 1979, "was_good": true, "synopsis": "x".repeat(synopsis_len) }).to_string();
+
+    assert_eq!(send(&app, "/v1/movie", "application/json", Body::from(movie(100)), None).await.0, StatusCode::CREATED);
+    let big = movie(2000);
+    let (status, body) = send(&app, "/v1/movie", "application/json", Body::from(big.clone()), Some(big.len())).await;
+    assert_eq!((status, body["error"]["code"].as_str()), (StatusCode::PAYLOAD_TOO_LARGE, Some("payload_too_large")));
+    assert_eq!(body["error"]["details"], json!({ "limit": 1000 }));
+    let (status, body) = send(&app, "/v1/movie", "application/json", streamed(big.clone()), None).await;
+    assert_eq!((status, body["error"]["details"]["limit"].as_u64()), (StatusCode::PAYLOAD_TOO_LARGE, Some(1000)));
+    let (status, body) = send(&app, "/v1/movie", "application/cbor", streamed(big.clone()), None).await;
+    assert_eq!((status, body["error"]["code"].as_str()), (StatusCode::PAYLOAD_TOO_LARGE, Some("payload_too_large")));
+    // With an Idempotency-Key the body is read before the handler gets it.
+    let request = Request::post("/v1/movie").header("content-type", "application/json").header("idempotency-key", "big");
+    assert_eq!(app.clone().oneshot(request.body(streamed(big)).unwrap()).await.unwrap().status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+    // Imports take more.
+    let csv = |rows: usize| format!("name,year,was_good\n{}", "Alien,1979,true\n".repeat(rows));
+    let (status, report) = send(&app, "/v1/movies/import", "text/csv", streamed(csv(100)), None).await;
+    assert_eq!((status, report["inserted"].as_u64()), (StatusCode::OK, Some(100)));
+    let (status, body) = send(&app, "/v1/movies/import", "text/csv", streamed(csv(1000)), None).await;
+    assert_eq!((status, body["error"]["details"]["limit"].as_u64()), (StatusCode::PAYLOAD_TOO_LARGE, Some(5000)));
+
+    settings.set_body_limits(body_limit::DEFAULT_MAX_BODY_BYTES, body_limit::DEFAULT_MAX_UPLOAD_BYTES);
+    assert_eq!(send(&app, "/v1/movie", "application/json", Body::from(movie(2000)), None).await.0, StatusCode::CREATED);
+}
@@ -0,0 +1,100 @@
+mod common;
+
+use std::sync::Arc;
+
+use axum::http::StatusCode;
+use serde_json::{json, Value};
+use syndica_rust::{build_router, changes::{self, ChangeCursor, ChangeLog, Since, MAX_CHANGES}, events::MovieEvent, model::Movie, settings::Settings, state::StateWrapper, test_app, wal::{WalConfig, WalMovieStore}};
+use common::{movie, seed, send};
+
+fn summary(page: &Value) -> Vec<(u64, String, String)> {
+    page["items"].as_array().unwrap().iter()
+        .map(|change| (change["seq"].as_u64().unwrap(), change["event"].as_str().unwrap().to_string(), change["id"].as_str().unwrap().to_string()))
+        .collect()
+}
+
+#[tokio::test]
+async fn clients_catch_up_from_their_cursor() {
+    let app = test_app();
+    let (status, page) = send(&app, "GET", "/v1/movies/changes", None).await;
+    assert_eq!((status, summary(&page), &page["has_more"]), (StatusCode::OK, vec![], &json!(false)));
+    let start = page["cursor"].as_str().unwrap().to_string();
+
+    seed(&app, [movie("alien", "Alien", 1979), movie("heat", "Heat", 1995)]).await;
+    send(&app, "PATCH", "/v1/movie/alien", Some(json!({ "was_good": false }))).await;
+    send(&app, "DELETE", "/v1/movie/heat", None).await;
+
+    // A page at a time, each picking up where the last left off.
+    let (_, page) = send(&app, "GET", &format!("/v1/movies/changes?since={}&limit=3", start), None).await;
+    assert_eq!(summary(&page), [(1, "created".into(), "alien".into()), (2, "created".into(), "heat".into()), (3, "updated".into(), "alien".into())]);
+    assert_eq!((&page["has_more"], &page["items"][2]["movie"]["was_good"]), (&json!(true), &json!(false)));
+    let (_, page) = send(&app, "GET", &format!("/v1/movies/changes?since={}", page["cursor"].as_str().unwrap()), None).await;
+    assert_eq!((summary(&page), &page["has_more"]), (vec![(4, "deleted".into(), "heat".into())], &json!(false)));
+    // With nothing new the cursor stays where it was.
+    let cursor = page["cursor"].as_str().unwrap().to_string();
+    let (_, page) = send(&app, "GET", &format!("/v1/movies/changes?since={}", cursor), None).await;
+    assert_eq!((summary(&page), page["cursor"].as_str()), (vec![], Some(cursor.as_str())));
+}
+
+#[tokio::test]
+async fn cursors_the_feed_cant_follow_are_refused() {
+    let app = test_app();
+    let (status, error) = send(&app, "GET", "/v1/movies/changes?since=not-a-cursor", None).await;
+    assert_eq!((status, error["error"]["code"].as_str()), (StatusCode::BAD_REQUEST, Some("invalid_query")));
+
+    // One from another store, as after a restart of one kept in memory.
+    let (_, page) = send(&app, "GET", "/v1/movies/changes", None).await;
+    let restarted = test_app();
+    let (status, error) = send(&restarted, "GET", &format!("/v1/movies/changes?since={}", page["cursor"].as_str().unwrap()), None).await;
+    assert_eq!((status, error["error"]["code"].as_str()), (StatusCode::GONE, Some("gone")));
+}
+
+#[tokio::test]
+async fn cursors_survive_a_clean_restart() {
+    let path = std::env::temp_dir().join(format!("syndica-changes-{}.wal", std::process::id()));
+    let open = async || Arc::new(WalMovieStore::open(WalConfig { path: path.clone(), max_bytes: 1 << 20 }).await.unwrap()) as StateWrapper;
+    let store = open().await;
+    let app = build_router(store.clone(), Settings::default());
+    seed(&app, [movie("alien", "Alien", 1979)]).await;
+    let (_, page) = send(&app, "GET", "/v1/movies/changes", None).await;
+    let cursor = page["cursor"].as_str().unwrap().to_string();
+    store.close().await.unwrap();
+    drop((app, store));
+
+    let store = open().await;
+    let app = build_router(store.clone(), Settings::default());
+    seed(&app, [movie("heat", "Heat", 1995)]).await;
+    let (status, page) = send(&app, "GET", &format!("/v1/movies/changes?since={}", cursor), None).await;
+    assert_eq!((status, summary(&page)), (StatusCode::OK, vec![(2, "created".into(), "heat".into())]));
+    let (_, page) = send(&app, "GET", "/v1/movies/changes", None).await;
+    assert_eq!(summary(&page), [(1, "created".into(), "alien".into()), (2, "created".into(), "heat".into())]);
+    drop((app, store));
+
+    // Without being closed, as after a crash, the store may hold changes the log missed, so it starts over.
+    let store = open().await;
+    let app = build_router(store.clone(), Settings::default());
+    let (status, _) = send(&app, "GET", &format!("/v1/movies/changes?since={}", cursor), None).await;
+    assert_eq!(status, StatusCode::GONE);
+    assert_eq!(store.count().await, 2);
+    drop((app, store));
+    std::fs::remove_file(&path).unwrap();
+    std::fs::remove_file(changes::path_beside(&path)).unwrap();
+}
+
+#[test]
+fn only_the_newest_changes_are_kept() {
+    let mut log = ChangeLog::new();
+    let start = ChangeCursor::decode(&log.page(&Since::Oldest, 1).unwrap().cursor).unwrap();
+    for _ in 0..=MAX_CHANGES {
+        log.record(&MovieEvent::Created(Movie { id: "alien".into(), ..Movie::default() }));
+    }
+    assert!(log.page(&Since::Cursor(start.clone()), 1).is_err());
+    // Without a cursor the feed starts from the oldest change it still has.
+    let page = log.page(&Since::Oldest, 1).unwrap();
+    assert_eq!((page.items[0].seq, page.has_more), (2, true));
+    let after_oldest = ChangeCursor { seq: 1, ..start };
+    assert_eq!(log.page(&Since::Cursor(after_oldest), 1).unwrap().items[0].seq, 2);
+    // And latest after the newest.
+    let latest = ChangeCursor::decode(&log.page(&Since::Latest, 1).unwrap().cursor).unwrap();
+    assert_eq!(latest.seq, MAX_CHANGES as u64 + 1);
+}
@@ -0,0 +1,84 @@
+#![cfg(feature = "client")]
+
+use std::future::pending;
+use syndica_rust::{
+    client::{ClientError, MoviesClient},
+    events::MovieEvent,
+    listener::{HttpConfig, Listener},
+    model::NewMovie,
+    store::MovieFilter,
+    test_app,
+};
+
+// Run with `cargo test --features client`.
+
+async fn serve() -> MoviesClient {
+    let listener = Listener::bind(&"127.0.0.1:0".parse().unwrap()).await.unwrap();
+    let Listener::Tcp(tcp) = &listener else { unreachable!() };
+    let address = tcp.local_addr().unwrap();
+    tokio::spawn(listener.serve(test_app(), HttpConfig::default(), pending()));
+    MoviesClient::new(&format!("http://{}", address)).unwrap()
+}
+
+fn new_movie(id: &str, name: &str, year: u16, genres: &[&str]) -> NewMovie {
+    NewMovie {
+        id: Some(id.to_string()),
+        name: name.to_string(),
+        year,
+        was_good: true,
+        genres: genres.iter().map(|genre| genre.to_string()).collect(),
+        director: None,
+        runtime_minutes: None,
+        synopsis: None,
+        poster_url: None,
+        tags: Vec::new(),
+        titles: Default::default(),
+    }
+}
+
+#[tokio::test]
+async fn creates_reads_lists_and_deletes() {
+    let client = serve().await;
+    let alien = client.create(&new_movie("alien", "Alien", 1979, &["Horror"])).await.unwrap();
+    assert_eq!((alien.id.as_str(), alien.version), ("alien", 1));
+    client.create(&new_movie("heat", "Heat", 1995, &["Crime"])).await.unwrap();
+
+    assert_eq!(client.get("alien").await.unwrap().map(|movie| movie.name), Some("Alien".to_string()));
+    assert!(client.get("nope").await.unwrap().is_none());
+    // Escaped, so it's still looked up as one id rather than sent to another route.
+    assert!(client.get("alien/reviews").await.unwrap().is_none());
+
+    let page = client.list(&MovieFilter::default(), 1, 0).await.unwrap();
+    assert_eq!((page.total, page.items.len(), page.next.is_some()), (2, 1, true));
+    let horror = MovieFilter { genre: Some("horror".to_string()), year_lte: Some(1990), ..Default::default() };
+    let page = client.list(&horror, 10, 0).await.unwrap();
+    assert_eq!(page.items.iter().map(|movie| movie.id.as_str()).collect::<Vec<_>>(), ["alien"]);
+
+    // Errors come back as the server's code.
+    match client.create(&new_movie("alien", "Aliens", 1986, &[])).await {
+        Err(ClientError::Api { status, code, .. }) => assert_eq!((status.as_u16(), code.as_str()), (409, "already_exists")),
+        other => panic!("{:?}", other),
+    }
+
+    client.delete("alien").await.unwrap();
+    assert!(client.get("alien").await.unwrap().is_none());
+    assert!(matches!(client.delete("alien").await, Err(ClientError::Api { status, .. }) if status.as_u16() == 404));
+}
+
+#[tokio::test]
+async fn watches_changes() {
+    let client = serve().await;
+    let mut watch = client.watch().await.unwrap();
+    client.create(&new_movie("alien", "Alien", 1979, &[])).await.unwrap();
+    client.delete("alien").await.unwrap();
+
+    assert!(matches!(watch.next().await, Some(Ok(MovieEvent::Created(movie))) if movie.name == "Alien"));
+    assert!(matches!(watch.next().await, Some(Ok(MovieEvent::Deleted(movie))) if movie.id == "alien"));
+}
+
+#[tokio::test]
+async fn fails_without_a_server() {
+    assert!(MoviesClient::new("https://localhost").is_err());
+    let client = MoviesClient::new("http://127.0.0.1:1").unwrap();
+    assert!(matches!(client.get("alien").await, Err(ClientError::Io(_))));
+}
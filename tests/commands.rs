@@ -0,0 +1,75 @@
+use std::{path::{Path, PathBuf}, process::{Command, Output}};
+use serde_json::Value;
+
+// The binary's import, export and migrate commands, run as a user would, against stores on disk.
+
+fn run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_syndica-rust")).args(args).env("MOVIES_LOG_LEVEL", "warn").output().unwrap()
+}
+
+fn dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("syndica-commands-{}-{}", std::process::id(), name));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn store(scheme: &str, path: &Path) -> String {
+    format!("--store={}://{}", scheme, path.display())
+}
+
+fn read_json(path: &Path) -> Value {
+    serde_json::from_slice(&std::fs::read(path).unwrap()).unwrap()
+}
+
+#[test]
+fn migrate_rewrites_an_old_snapshot() {
+    let dir = dir("migrate");
+    let snapshot = dir.join("movies.json");
+    // Snapshots were once just the array of movies.
+    std::fs::write(&snapshot, r#"[{"id":"alien","name":"Alien","year":1979,"was_good":true}]"#).unwrap();
+
+    assert!(run(&["migrate", &store("snapshot", &snapshot)]).status.success());
+    let migrated = read_json(&snapshot);
+    assert_eq!(migrated["movies"][0]["id"], "alien");
+    assert_eq!(migrated["users"], Value::Array(Vec::new()));
+    assert_eq!(migrated["schema_version"], 3);
+
+    // Logs are stamped with the version what's appended to them is written in.
+    let wal = dir.join("movies.wal");
+    std::fs::write(&wal, "{\"op\":\"insert\",\"movie\":{\"id\":\"alien\",\"name\":\"Alien\",\"year\":1979,\"was_good\":true,\"version\":1}}\n").unwrap();
+    assert!(run(&["migrate", &store("wal", &wal)]).status.success());
+    assert!(std::fs::read_to_string(&wal).unwrap().starts_with("{\"schema\":2}\n"));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn export_then_import_moves_a_store() {
+    let dir = dir("move");
+    let snapshot = dir.join("movies.json");
+    std::fs::write(&snapshot, r#"{"movies":[{"id":"alien","name":"Alien","year":1979,"was_good":true,"version":3}],"users":[]}"#).unwrap();
+    let backup = dir.join("backup.json");
+    assert!(run(&["export", backup.to_str().unwrap(), &store("snapshot", &snapshot)]).status.success());
+    assert_eq!(read_json(&backup)["movies"][0]["version"], 3);
+
+    // Into a store of another kind, which then holds the same movies.
+    let wal = dir.join("movies.wal");
+    assert!(run(&["import", backup.to_str().unwrap(), &store("wal", &wal)]).status.success());
+    let again = dir.join("again.json");
+    assert!(run(&["export", again.to_str().unwrap(), &store("wal", &wal)]).status.success());
+    assert_eq!(read_json(&again)["movies"], read_json(&backup)["movies"]);
+
+    // It won't import over what's already there.
+    let output = run(&["import", backup.to_str().unwrap(), &store("wal", &wal)]);
+    assert!(!output.status.success());
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn rejects_unknown_commands_and_missing_files() {
+    assert_eq!(run(&["frobnicate"]).status.code(), Some(2));
+    assert_eq!(run(&["import"]).status.code(), Some(2));
+    assert_eq!(run(&["export", "--store=memory://"]).status.code(), Some(2));
+    let help = run(&["migrate", "--help"]);
+    assert!(String::from_utf8_lossy(&help.stdout).contains("syndica-rust import <file>"));
+}
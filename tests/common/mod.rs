@@ -0,0 +1,55 @@
+// What the integration tests share: sending requests to the app in-process with tower::ServiceExt::oneshot, reading
+// JSON back, and apps with movies already in them. Each test file is a crate of its own that uses only some of these.
+#![allow(dead_code)]
+
+use axum::{body::Body, http::{Request, StatusCode}, response::Response, Router};
+use http_body_util::BodyExt;
+use serde_json::{json, Value};
+use syndica_rust::test_app;
+use tower::ServiceExt;
+
+// Sends a request with a JSON body, if it has one, and these headers on top of its content type.
+pub async fn send_request(app: &Router, method: &str, uri: &str, headers: &[(&str, &str)], body: Option<Value>) -> Response {
+    let mut request = Request::builder().method(method).uri(uri).header("content-type", "application/json");
+    for (name, value) in headers {
+        request = request.header(*name, *value);
+    }
+    let request = request.body(body.map(|body| Body::from(body.to_string())).unwrap_or_default()).unwrap();
+    app.clone().oneshot(request).await.unwrap()
+}
+
+// The status and the JSON that came back.
+pub async fn send_with(app: &Router, method: &str, uri: &str, headers: &[(&str, &str)], body: Option<Value>) -> (StatusCode, Value) {
+    let response = send_request(app, method, uri, headers, body).await;
+    (response.status(), json(response).await)
+}
+
+pub async fn send(app: &Router, method: &str, uri: &str, body: Option<Value>) -> (StatusCode, Value) {
+    send_with(app, method, uri, &[], body).await
+}
+
+// The response's body as JSON, or Null when it's empty or isn't JSON.
+pub async fn json(response: Response) -> Value {
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    serde_json::from_slice(&body).unwrap_or(Value::Null)
+}
+
+// A movie with only the fields every movie needs.
+pub fn movie(id: &str, name: &str, year: u32) -> Value {
+    json!({ "id": id, "name": name, "year": year, "was_good": true })
+}
+
+// Adds the movies through POST /v1/movie, so they go through everything a client's would.
+pub async fn seed(app: &Router, movies: impl IntoIterator<Item = Value>) {
+    for movie in movies {
+        let (status, body) = send(app, "POST", "/v1/movie", Some(movie)).await;
+        assert_eq!(status, StatusCode::CREATED, "{}", body);
+    }
+}
+
+// test_app() with the movies in it.
+pub async fn seeded_app(movies: impl IntoIterator<Item = Value>) -> Router {
+    let app = test_app();
+    seed(&app, movies).await;
+    app
+}
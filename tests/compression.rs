@@ -0,0 +1,140 @@
+use axum::{body::Body, http::{Request, StatusCode}, response::Response, Router};
+use http_body_util::BodyExt;
+use serde_json::Value;
+use syndica_rust::{brotli, gzip, test_app};
+use tower::ServiceExt;
+
+// What Python's gzip.compress(rows, 9) makes of the ten rows below, with dynamic Huffman codes, which the encoder
+// here never writes.
+const PYTHON_GZIP: &[&str] = &[
+    "1f8b080000000000020355cccd09c3300c40e17ba6c8003a58fef7109da1b8c42d813a06a769e8f6954139e8fcf1deba",
+    "c0966b815fc91dcebcdf5fad2d5355706bdfb5ccdb511fa5cf0a303905cffcdecb5451220e0cf0e9079996a6c9bc6533",
+    "d20c59c06b6a25da81914327cd9145c7e6a579b2a4af69901806260ea3b4085a29cf96a42532343cfd037f9db4e73201",
+    "0000",
+];
+
+// What the brotli encoder makes of the same rows, which libbrotlidec decodes back to them. There's no brotli decoder
+// here to check it with.
+const ROWS_BROTLI: &[&str] = &[
+    "8b98000000c01b0270000070000c5b553500001c00c075ed17afa8f73ba8270000000000000000000000000000000080",
+    "94013a00000e80030040000000000000000000000000000000000000000043c7010080bd6ec8096a2c02bfc40e5f1cfb",
+    "d55a5a0ac2d6de2c6b9de590be22105b8433de4396424128781e9e3e5d94a3bcceb86847fb7a3a1613c40413c6621deb",
+    "0dd6c539ce97d5b1f8203e181e4bf02f8042742eecb097f4b10103",
+];
+
+fn unhex(lines: &[&str]) -> Vec<u8> {
+    let hex = lines.concat();
+    (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap()).collect()
+}
+
+fn python_gzip() -> Vec<u8> {
+    unhex(PYTHON_GZIP)
+}
+
+fn rows(ids: std::ops::Range<usize>) -> String {
+    let mut csv = String::from("id,name,year,was_good\n");
+    for i in ids {
+        csv += &format!("m{},Movie number {},{},{}\n", i, i, 1950 + i * 7 % 70, i % 3 != 0);
+    }
+    csv
+}
+
+async fn send(app: &Router, request: Request<Body>) -> (Response, Vec<u8>) {
+    let response = app.clone().oneshot(request).await.unwrap();
+    let (parts, body) = response.into_parts();
+    (Response::from_parts(parts, Body::empty()), body.collect().await.unwrap().to_bytes().to_vec())
+}
+
+fn import(body: Vec<u8>, encoding: &str) -> Request<Body> {
+    Request::post("/movies/import").header("content-type", "text/csv").header("content-encoding", encoding).body(Body::from(body)).unwrap()
+}
+
+#[test]
+fn gzip_round_trips() {
+    assert_eq!(gzip::decompress(&python_gzip()).unwrap(), rows(0..10).as_bytes());
+
+    // Fed a byte at a time, the decoder has to stop and pick up again in the middle of everything.
+    let mut decoder = gzip::GzipDecoder::new();
+    let mut out = Vec::new();
+    for byte in python_gzip() {
+        out.extend(decoder.chunk(&[byte]).unwrap());
+    }
+    decoder.finish().unwrap();
+    assert_eq!(out, rows(0..10).as_bytes());
+
+    let csv = rows(0..500);
+    let compressed = gzip::compress(csv.as_bytes());
+    assert!(compressed.len() < csv.len() / 3, "{} bytes", compressed.len());
+    assert_eq!(gzip::decompress(&compressed).unwrap(), csv.as_bytes());
+
+    let mut corrupt = compressed.clone();
+    let last = corrupt.len() - 5;
+    corrupt[last] ^= 1;
+    assert!(gzip::decompress(&corrupt).is_err());
+    assert!(gzip::decompress(&compressed[..compressed.len() - 1]).is_err());
+}
+
+#[test]
+fn brotli_streams_are_well_formed() {
+    assert_eq!(brotli::compress(rows(0..10).as_bytes()), unhex(ROWS_BROTLI));
+    // A 22 bit window, then an empty last meta-block.
+    assert_eq!(brotli::compress(b""), [0x3b]);
+
+    // Every chunk ends on a byte boundary, and the stream only ends with finish.
+    let mut encoder = brotli::BrotliEncoder::new();
+    let first = encoder.chunk(rows(0..10).as_bytes());
+    assert!(first.len() > 1);
+    assert!(encoder.chunk(b"").is_empty());
+    assert_eq!(encoder.finish(), [0x03]);
+
+    let csv = rows(0..500);
+    let compressed = brotli::compress(csv.as_bytes());
+    assert!(compressed.len() < gzip::compress(csv.as_bytes()).len(), "{} bytes", compressed.len());
+}
+
+#[tokio::test]
+async fn bodies_are_compressed_both_ways() {
+    let app = test_app();
+    let (response, body) = send(&app, import(python_gzip(), "gzip")).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let report: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(report["inserted"], 10);
+    let (response, _) = send(&app, import(gzip::compress(rows(10..50).as_bytes()), "x-gzip")).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let (plain, expected) = send(&app, Request::get("/movies?limit=50").body(Body::empty()).unwrap()).await;
+    assert!(plain.headers().get("content-encoding").is_none());
+    assert!(plain.headers().get_all("vary").iter().any(|value| value == "accept-encoding"));
+
+    let request = Request::get("/movies?limit=50").header("accept-encoding", "br;q=0.5, gzip;q=1.0").body(Body::empty()).unwrap();
+    let (response, body) = send(&app, request).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["content-encoding"], "gzip");
+    assert!(response.headers().get("content-length").is_none());
+    assert!(body.len() < expected.len());
+    assert_eq!(gzip::decompress(&body).unwrap(), expected);
+
+    // br wins a tie, and * stands for it when it isn't named.
+    for accept in ["gzip, br", "gzip;q=0.5, *", "br;q=1.0, gzip;q=0.5"] {
+        let request = Request::get("/movies?limit=50").header("accept-encoding", accept).body(Body::empty()).unwrap();
+        let (response, body) = send(&app, request).await;
+        assert_eq!(response.headers()["content-encoding"], "br", "{}", accept);
+        assert!(response.headers().get("content-length").is_none());
+        // The page comes in one chunk, so it's what compressing it in one go makes.
+        assert_eq!(body, brotli::compress(&expected));
+    }
+    let request = Request::get("/movies?limit=50").header("accept-encoding", "br;q=0, *").body(Body::empty()).unwrap();
+    assert_eq!(send(&app, request).await.0.headers()["content-encoding"], "gzip");
+
+    let request = Request::get("/movies?limit=50").header("accept-encoding", "gzip;q=0").body(Body::empty()).unwrap();
+    assert!(send(&app, request).await.0.headers().get("content-encoding").is_none());
+    let request = Request::get("/healthz").header("accept-encoding", "gzip").body(Body::empty()).unwrap();
+    assert!(send(&app, request).await.0.headers().get("content-encoding").is_none());
+
+    let (response, body) = send(&app, import(b"not gzip".to_vec(), "br")).await;
+    assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    let error: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(error["error"]["code"], "invalid_body");
+    let (response, _) = send(&app, import(b"not gzip".to_vec(), "gzip")).await;
+    assert_ne!(response.status(), StatusCode::OK);
+}
@@ -0,0 +1,137 @@
+use std::{sync::{atomic::{AtomicBool, Ordering}, Arc}, time::Duration};
+
+use syndica_rust::{
+    cache::{CacheConfig, CachedMovieStore},
+    model::{Movie, User, UserChange},
+    state::{state_init, StateWrapper},
+    store::MovieFilter,
+    wal::{WalConfig, WalMovieStore},
+};
+
+// Hundreds of writers and readers at once against the stores, on several threads, to catch lost updates, readers
+// seeing a write half done, and deadlocks. A run that takes longer than this has deadlocked.
+const DEADLINE: Duration = Duration::from_secs(60);
+const WRITERS: usize = 200;
+const READERS: usize = 200;
+// Upserts each writer makes to the one movie they all fight over.
+const UPSERTS: usize = 5;
+
+fn movie(id: &str) -> Movie {
+    Movie { id: id.to_string(), name: format!("Movie {}", id), year: 2000, was_good: true, genres: vec!["Drama".to_string()], version: 1, ..Default::default() }
+}
+
+async fn stores(name: &str) -> Vec<(&'static str, StateWrapper)> {
+    let path = std::env::temp_dir().join(format!("syndica-concurrency-{}-{}.wal", std::process::id(), name));
+    let _ = std::fs::remove_file(&path);
+    let wal = WalMovieStore::open(WalConfig { path, max_bytes: 64 * 1024 }).await.unwrap();
+    vec![
+        ("memory", state_init()),
+        // Small enough that readers keep pushing each other's movies out.
+        ("cached", Arc::new(CachedMovieStore::new(state_init(), CacheConfig { capacity: 8, ttl: None }))),
+        ("wal", Arc::new(wal)),
+    ]
+}
+
+// Reads until told to stop, checking that nothing it sees could only be there halfway through a write.
+async fn read_until(store: StateWrapper, stop: Arc<AtomicBool>) {
+    let mut last_version = 0;
+    while !stop.load(Ordering::Relaxed) {
+        if let Some(contended) = store.get("contended").await {
+            assert!(contended.version >= last_version, "the contended movie went from version {} back to {}", last_version, contended.version);
+            last_version = contended.version;
+        }
+        let page = store.scan(Some("movie-050"), 20).await;
+        assert!(page.windows(2).all(|pair| pair[0].id < pair[1].id), "the page isn't in id order, or has an id twice");
+        assert!(store.count().await <= WRITERS + 1);
+        // Or the readers alone would keep every thread busy, and the WAL, which writes one at a time, would crawl.
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+async fn concurrent_writes_are_never_lost() {
+    for (name, store) in stores("writes").await {
+        let stop = Arc::new(AtomicBool::new(false));
+        let readers: Vec<_> = (0..READERS).map(|_| tokio::spawn(read_until(store.clone(), stop.clone()))).collect();
+        // Each writer adds a movie of its own, and half of them delete it again, while all of them write the one
+        // they share over and over.
+        let writers: Vec<_> = (0..WRITERS).map(|i| {
+            let store = store.clone();
+            tokio::spawn(async move {
+                let own = format!("movie-{:03}", i);
+                store.insert(movie(&own)).await.unwrap();
+                for _ in 0..UPSERTS {
+                    store.upsert(movie("contended")).await.unwrap();
+                }
+                if i % 2 == 0 {
+                    store.delete(&own).await.unwrap();
+                }
+            })
+        }).collect();
+
+        tokio::time::timeout(DEADLINE, async {
+            for writer in writers {
+                writer.await.unwrap();
+            }
+            stop.store(true, Ordering::Relaxed);
+            for reader in readers {
+                reader.await.unwrap();
+            }
+        }).await.unwrap_or_else(|_| panic!("{}: deadlocked", name));
+
+        // Every upsert numbered the movie one past the last, so any lost to a race would show.
+        assert_eq!(store.get("contended").await.unwrap().version as usize, WRITERS * UPSERTS, "{}", name);
+        let mut expected: Vec<String> = (0..WRITERS).filter(|i| i % 2 == 1).map(|i| format!("movie-{:03}", i)).collect();
+        expected.insert(0, "contended".to_string());
+        let listed: Vec<String> = store.list(&MovieFilter::default()).await.into_iter().map(|movie| movie.id).collect();
+        assert_eq!(listed, expected, "{}", name);
+        assert_eq!(store.count().await, expected.len(), "{}", name);
+        // The indexes kept up with the map.
+        let genres = store.genres().await;
+        assert_eq!((genres.len(), genres[0].movie_count), (1, expected.len()), "{}", name);
+        assert_eq!(store.trash().await.len(), WRITERS / 2, "{}", name);
+        for id in &expected {
+            assert_eq!(store.get(id).await.map(|movie| movie.id), Some(id.clone()), "{}", name);
+        }
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+async fn watchlists_never_keep_a_deleted_movie() {
+    for (name, store) in stores("watchlists").await {
+        for i in 0..WRITERS {
+            store.insert(movie(&format!("movie-{:03}", i))).await.unwrap();
+        }
+        for i in 0..READERS {
+            store.insert_user(User { id: format!("user-{}", i), created_at: "2026-01-01T00:00:00Z".to_string(), ..Default::default() }).await.unwrap();
+        }
+        // Users add movies while the same movies are being deleted. Whichever goes first, no movie may be on a
+        // watchlist after it's gone.
+        let deleters: Vec<_> = (0..WRITERS).map(|i| {
+            let store = store.clone();
+            tokio::spawn(async move { store.delete(&format!("movie-{:03}", i)).await.unwrap(); })
+        }).collect();
+        let users: Vec<_> = (0..READERS).map(|i| {
+            let store = store.clone();
+            tokio::spawn(async move {
+                let user = format!("user-{}", i);
+                for movie in 0..WRITERS {
+                    // Fails once the movie is gone, which is fine.
+                    let _ = store.change_user(&user, UserChange::AddToWatchlist(format!("movie-{:03}", (movie + i) % WRITERS))).await;
+                }
+            })
+        }).collect();
+
+        tokio::time::timeout(DEADLINE, async {
+            for task in deleters.into_iter().chain(users) {
+                task.await.unwrap();
+            }
+        }).await.unwrap_or_else(|_| panic!("{}: deadlocked", name));
+
+        assert_eq!(store.count().await, 0, "{}", name);
+        for i in 0..READERS {
+            let user = store.get_user(&format!("user-{}", i)).await.unwrap();
+            assert_eq!(user.watchlist, Vec::<String>::new(), "{}", name);
+        }
+    }
+}
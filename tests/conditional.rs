@@ -0,0 +1,125 @@
+mod common;
+
+use axum::{body::Body, http::{Response, StatusCode}};
+use http_body_util::BodyExt;
+use serde_json::{json, Value};
+use syndica_rust::test_app;
+use common::send_request;
+
+fn etag(response: &Response<Body>) -> String {
+    response.headers()["etag"].to_str().unwrap().to_string()
+}
+
+#[tokio::test]
+async fn get_honors_if_none_match() {
+    let app = test_app();
+    send_request(&app, "POST", "/movie", &[], Some(json!({ "id": "alien", "name": "Alien", "year": 1979, "was_good": true }))).await;
+
+    let response = send_request(&app, "GET", "/movie/alien", &[], None).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let tag = etag(&response);
+    assert!(tag.starts_with('"') && tag.ends_with('"'), "{}", tag);
+
+    let response = send_request(&app, "GET", "/movie/alien", &[("if-none-match", &format!("\"other\", W/{}", tag))], None).await;
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(etag(&response), tag);
+    assert!(response.into_body().collect().await.unwrap().to_bytes().is_empty());
+
+    // A change gets a new tag, so the old one stops matching.
+    let response = send_request(&app, "PATCH", "/movie/alien", &[], Some(json!({ "was_good": false }))).await;
+    let patched = etag(&response);
+    assert_ne!(patched, tag);
+    let response = send_request(&app, "GET", "/movie/alien", &[("if-none-match", &tag)], None).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(etag(&response), patched);
+}
+
+#[tokio::test]
+async fn writes_honor_if_match() {
+    let app = test_app();
+    send_request(&app, "POST", "/movie", &[], Some(json!({ "id": "alien", "name": "Alien", "year": 1979, "was_good": true }))).await;
+    let tag = etag(&send_request(&app, "GET", "/movie/alien", &[], None).await);
+
+    let replacement = json!({ "id": "alien", "name": "Aliens", "year": 1986, "was_good": true, "version": 1 });
+    let response = send_request(&app, "PUT", "/movie/alien", &[("if-match", &tag)], Some(replacement)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let replaced = etag(&response);
+
+    // The old tag, and weak tags, no longer match, even with the right version.
+    let replacement = json!({ "id": "alien", "name": "Aliens", "year": 1986, "was_good": false, "version": 2 });
+    for stale in [tag.clone(), format!("W/{}", replaced)] {
+        let response = send_request(&app, "PUT", "/movie/alien", &[("if-match", &stale)], Some(replacement.clone())).await;
+        assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+        let error: Value = serde_json::from_slice(&response.into_body().collect().await.unwrap().to_bytes()).unwrap();
+        assert_eq!(error["error"]["code"], "precondition_failed");
+        assert_eq!(error["error"]["details"]["current"]["name"], "Aliens");
+    }
+
+    assert_eq!(send_request(&app, "DELETE", "/movie/alien", &[("if-match", &tag)], None).await.status(), StatusCode::PRECONDITION_FAILED);
+    assert_eq!(send_request(&app, "DELETE", "/movie/alien", &[("if-match", &replaced)], None).await.status(), StatusCode::NO_CONTENT);
+    assert_eq!(send_request(&app, "DELETE", "/movie/alien", &[("if-match", "*")], None).await.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn updates_have_to_name_the_current_version() {
+    let app = test_app();
+    send_request(&app, "POST", "/movie", &[], Some(json!({ "id": "alien", "name": "Alien", "year": 1979, "was_good": true }))).await;
+    let body = |response: Response<Body>| async { serde_json::from_slice::<Value>(&response.into_body().collect().await.unwrap().to_bytes()).unwrap() };
+    assert_eq!(body(send_request(&app, "GET", "/movie/alien", &[], None).await).await["version"], 1);
+
+    // Two editors both read version 1, and only the first one to write gets to.
+    let first = json!({ "id": "alien", "name": "Aliens", "year": 1986, "was_good": true, "version": 1 });
+    let second = json!({ "id": "alien", "name": "Alien 3", "year": 1992, "was_good": false, "version": 1 });
+    let response = send_request(&app, "PUT", "/movie/alien", &[], Some(first)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(body(response).await["version"], 2);
+    let response = send_request(&app, "PUT", "/movie/alien", &[], Some(second)).await;
+    assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+    assert_eq!(body(response).await["error"]["details"]["current"]["name"], "Aliens");
+    // Leaving the version out doesn't get around it either.
+    let unversioned = json!({ "id": "alien", "name": "Alien 3", "year": 1992, "was_good": false });
+    assert_eq!(send_request(&app, "PUT", "/movie/alien", &[], Some(unversioned)).await.status(), StatusCode::PRECONDITION_FAILED);
+
+    // Patches may name a version, and move the movie on to the next one either way.
+    assert_eq!(send_request(&app, "PATCH", "/movie/alien", &[], Some(json!({ "was_good": false, "version": 1 }))).await.status(), StatusCode::PRECONDITION_FAILED);
+    assert_eq!(body(send_request(&app, "PATCH", "/movie/alien", &[], Some(json!({ "was_good": false, "version": 2 }))).await).await["version"], 3);
+    assert_eq!(body(send_request(&app, "PATCH", "/movie/alien", &[], Some(json!({ "year": 1986 }))).await).await["version"], 4);
+}
+
+#[tokio::test]
+async fn each_representation_has_its_own_etag() {
+    let app = test_app();
+    let movie = json!({ "id": "seven-samurai", "name": "Seven Samurai", "year": 1954, "was_good": true, "titles": { "fr": "Les Sept Samouraïs" } });
+    send_request(&app, "POST", "/movie", &[], Some(movie)).await;
+
+    let mut tags = Vec::new();
+    for headers in [
+        &[][..],
+        &[("accept", "application/msgpack")][..],
+        &[("accept", "application/cbor")][..],
+        &[("accept-language", "fr")][..],
+        &[("accept-language", "de")][..],
+        &[("accept", "application/cbor"), ("accept-language", "fr")][..],
+    ] {
+        let response = send_request(&app, "GET", "/movie/seven-samurai", headers, None).await;
+        assert_eq!(response.status(), StatusCode::OK, "{headers:?}");
+        let tag = etag(&response);
+        assert!(!tags.contains(&tag), "{headers:?} {tag}");
+        // Each one only ever answers for itself.
+        let mut conditional = headers.to_vec();
+        conditional.push(("if-none-match", &tag));
+        let response = send_request(&app, "GET", "/movie/seven-samurai", &conditional, None).await;
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED, "{headers:?}");
+        let vary = response.headers()["vary"].to_str().unwrap();
+        assert!(vary.contains("accept") && vary.contains("accept-language"), "{vary}");
+        for other in &tags {
+            conditional.pop();
+            conditional.push(("if-none-match", other));
+            assert_eq!(send_request(&app, "GET", "/movie/seven-samurai", &conditional, None).await.status(), StatusCode::OK, "{headers:?} {other}");
+        }
+        tags.push(tag);
+    }
+
+    // Any of them is good for If-Match, since they're all of the same version.
+    assert_eq!(send_request(&app, "DELETE", "/movie/seven-samurai", &[("if-match", &tags[1])], None).await.status(), StatusCode::NO_CONTENT);
+}
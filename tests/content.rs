@@ -0,0 +1,72 @@
+use axum::{body::Body, http::{Request, StatusCode}, Router};
+use http_body_util::BodyExt;
+use serde_json::{json, Value};
+use syndica_rust::{content::Format, model::Movie, test_app};
+use tower::ServiceExt;
+
+async fn send(app: &Router, request: Request<Body>) -> (StatusCode, String, Vec<u8>) {
+    let response = app.clone().oneshot(request).await.unwrap();
+    let content_type = response.headers().get("content-type").map(|value| value.to_str().unwrap().to_string()).unwrap_or_default();
+    (response.status(), content_type, response.into_body().collect().await.unwrap().to_bytes().to_vec())
+}
+
+fn cbor(value: &Value) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    ciborium::into_writer(value, &mut bytes).unwrap();
+    bytes
+}
+
+#[tokio::test]
+async fn bodies_and_responses_follow_the_headers() {
+    let app = test_app();
+    let movie = json!({ "id": "dune", "name": "Dune", "year": 2021, "was_good": true });
+    let request = Request::post("/movie").header("content-type", "application/msgpack").header("accept", "application/cbor");
+    let (status, content_type, body) = send(&app, request.body(Body::from(rmp_serde::to_vec_named(&movie).unwrap())).unwrap()).await;
+    assert_eq!((status, content_type.as_str()), (StatusCode::CREATED, "application/cbor"));
+    assert_eq!(ciborium::from_reader::<Movie, _>(&body[..]).unwrap().version, 1);
+
+    let request = Request::get("/movie/dune").header("accept", "application/json;q=0.5, application/x-msgpack");
+    let (_, content_type, body) = send(&app, request.body(Body::empty()).unwrap()).await;
+    assert_eq!(content_type, "application/msgpack");
+    assert_eq!(rmp_serde::from_slice::<Movie>(&body).unwrap().name, "Dune");
+
+    // Anything else gets the JSON it always has.
+    for accept in [None, Some("*/*"), Some("text/html"), Some("application/cbor;q=0, */*;q=0.1")] {
+        let request = Request::get("/movies").header("accept", accept.unwrap_or_default());
+        let (_, content_type, body) = send(&app, request.body(Body::empty()).unwrap()).await;
+        assert!(!content_type.contains("cbor") && !content_type.contains("msgpack"), "{:?} got {}", accept, content_type);
+        assert_eq!(serde_json::from_slice::<Value>(&body).unwrap()["total"], 1, "{:?}", accept);
+    }
+
+    let patch = Request::patch("/movie/dune").header("content-type", "application/cbor").body(Body::from(cbor(&json!({ "was_good": false })))).unwrap();
+    let (status, _, body) = send(&app, patch).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(serde_json::from_slice::<Value>(&body).unwrap()["was_good"], false);
+}
+
+#[tokio::test]
+async fn undecodable_bodies_are_rejected() {
+    let app = test_app();
+    // A map that breaks off before its first key is over.
+    let garbage = Request::post("/movie").header("content-type", "application/cbor").body(Body::from(vec![0xa1, 0x64])).unwrap();
+    let (status, content_type, _) = send(&app, garbage).await;
+    assert_eq!((status, content_type.as_str()), (StatusCode::BAD_REQUEST, "application/json"));
+
+    let missing_year = cbor(&json!({ "name": "Dune", "was_good": true }));
+    let request = Request::post("/movie").header("content-type", "application/cbor").body(Body::from(missing_year)).unwrap();
+    assert_eq!(send(&app, request).await.0, StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[test]
+fn accept_header_preferences() {
+    let format = |accept: &str| {
+        let mut headers = axum::http::HeaderMap::new();
+        headers.insert("accept", accept.parse().unwrap());
+        Format::from_accept(&headers)
+    };
+    assert_eq!(format("application/cbor, application/json"), Format::Json);
+    assert_eq!(format("application/cbor, application/json;q=0.9"), Format::Cbor);
+    assert_eq!(format("APPLICATION/VND.MSGPACK"), Format::MessagePack);
+    assert_eq!(format("application/*;q=0.2, application/cbor;q=0.3"), Format::Cbor);
+    assert_eq!(format("application/json;q=0"), Format::Json);
+}
@@ -0,0 +1,71 @@
+use std::time::Duration;
+use axum::{body::Body, http::{Request, StatusCode}, response::Response, Router};
+use syndica_rust::{cors::{self, AllowedOrigins, CorsConfig}, settings::Settings, test_app_with};
+use tower::ServiceExt;
+
+async fn send(app: &Router, request: Request<Body>) -> Response {
+    app.clone().oneshot(request).await.unwrap()
+}
+
+fn preflight(origin: &str) -> Request<Body> {
+    Request::options("/movie/alien")
+        .header("origin", origin)
+        .header("access-control-request-method", "PUT")
+        .header("access-control-request-headers", "content-type, x-custom")
+        .body(Body::empty())
+        .unwrap()
+}
+
+fn get(origin: &str) -> Request<Body> {
+    Request::get("/movies").header("origin", origin).body(Body::empty()).unwrap()
+}
+
+#[tokio::test]
+async fn cross_origin_requests_follow_the_settings() {
+    let settings = Settings::default();
+    let app = test_app_with(settings.clone());
+
+    // Off by default: no CORS headers, and preflights get what any other OPTIONS request would.
+    let response = send(&app, get("https://movies.example.com")).await;
+    assert!(response.headers().get("access-control-allow-origin").is_none());
+    let response = send(&app, preflight("https://movies.example.com")).await;
+    assert_eq!((response.status(), response.headers().get("access-control-allow-origin")), (StatusCode::NO_CONTENT, None));
+
+    settings.set_cors(Some(CorsConfig {
+        origins: AllowedOrigins::List(vec!["https://movies.example.com".to_string()]),
+        methods: None,
+        headers: None,
+        max_age: Duration::from_secs(120),
+    }));
+    let response = send(&app, preflight("https://movies.example.com")).await;
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    let headers = response.headers();
+    assert_eq!(headers["access-control-allow-origin"], "https://movies.example.com");
+    assert!(headers["access-control-allow-methods"].to_str().unwrap().contains("PUT"));
+    let allowed_headers = headers["access-control-allow-headers"].to_str().unwrap();
+    assert!(allowed_headers.contains("x-api-key") && !allowed_headers.contains("x-custom"));
+    assert_eq!(headers["access-control-max-age"], "120");
+
+    let response = send(&app, get("https://movies.example.com")).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["access-control-allow-origin"], "https://movies.example.com");
+    assert!(response.headers()["access-control-expose-headers"].to_str().unwrap().contains("etag"));
+    assert!(response.headers().get_all("vary").iter().any(|value| value == "origin"));
+    // Errors are readable cross-origin too.
+    let response = send(&app, Request::get("/movie/missing").header("origin", "https://movies.example.com").body(Body::empty()).unwrap()).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_eq!(response.headers()["access-control-allow-origin"], "https://movies.example.com");
+
+    let response = send(&app, preflight("https://evil.example.com")).await;
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    assert!(response.headers().get("access-control-allow-origin").is_none());
+    assert!(send(&app, get("https://evil.example.com")).await.headers().get("access-control-allow-origin").is_none());
+
+    // Development mode lets anything through.
+    settings.set_cors(Some(CorsConfig { origins: AllowedOrigins::Any, methods: None, headers: None, max_age: cors::DEFAULT_MAX_AGE }));
+    let response = send(&app, preflight("http://localhost:3000")).await;
+    assert_eq!(response.headers()["access-control-allow-origin"], "*");
+    assert_eq!(response.headers()["access-control-allow-methods"], "PUT");
+    assert_eq!(response.headers()["access-control-allow-headers"], "content-type, x-custom");
+    assert_eq!(send(&app, get("http://localhost:3000")).await.headers()["access-control-allow-origin"], "*");
+}
@@ -0,0 +1,94 @@
+mod common;
+
+use axum::{http::StatusCode, Router};
+use syndica_rust::test_app;
+use common::{movie, seed, send};
+
+async fn add(app: &Router, id: &str, year: u32) {
+    seed(app, [movie(id, id, year)]).await;
+}
+
+async fn delete(app: &Router, id: &str) {
+    assert_eq!(send(app, "DELETE", &format!("/v1/movie/{id}"), None).await.0, StatusCode::NO_CONTENT);
+}
+
+async fn list(app: &Router, query: &str) -> (StatusCode, serde_json::Value) {
+    send(app, "GET", &format!("/v1/movies?{query}"), None).await
+}
+
+fn ids(page: &serde_json::Value) -> Vec<&str> {
+    page["items"].as_array().unwrap().iter().map(|movie| movie["id"].as_str().unwrap()).collect()
+}
+
+fn next_query(page: &serde_json::Value) -> String {
+    page["next"].as_str().unwrap().strip_prefix("/v1/movies?").unwrap().to_string()
+}
+
+#[tokio::test]
+async fn pages_hold_their_place_across_writes() {
+    let app = test_app();
+    for id in ["b", "d", "f", "h"] {
+        add(&app, id, 2000).await;
+    }
+    let (status, first) = list(&app, "cursor=&limit=2").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(ids(&first), ["b", "d"]);
+    let cursor = first["next_cursor"].as_str().unwrap();
+    assert!(first["next"].as_str().unwrap().contains(cursor));
+    assert!(!first["next"].as_str().unwrap().contains("offset"));
+
+    // An offset of 2 would now repeat d, and then skip f once d is gone.
+    add(&app, "a", 2000).await;
+    add(&app, "c", 2000).await;
+    delete(&app, "d").await;
+    let (_, second) = list(&app, &next_query(&first)).await;
+    assert_eq!(ids(&second), ["f", "h"]);
+    assert!(second["next"].is_null());
+    assert!(second.get("next_cursor").is_none());
+}
+
+#[tokio::test]
+async fn cursors_follow_the_sort() {
+    let app = test_app();
+    for (id, year) in [("a", 2001), ("b", 1999), ("c", 2010), ("d", 1999)] {
+        add(&app, id, year).await;
+    }
+    let (_, first) = list(&app, "cursor=&limit=3&sort=year:desc").await;
+    assert_eq!(ids(&first), ["c", "a", "b"]);
+    add(&app, "e", 2005).await;
+    let (_, second) = list(&app, &next_query(&first)).await;
+    assert_eq!(ids(&second), ["d"]);
+
+    // The cursor only makes sense in the order it was made for.
+    let cursor = first["next_cursor"].as_str().unwrap();
+    let (status, _) = list(&app, &format!("cursor={cursor}&sort=year:asc")).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn offset_pages_are_unchanged() {
+    let app = test_app();
+    for id in ["a", "b", "c"] {
+        add(&app, id, 2000).await;
+    }
+    let (_, page) = list(&app, "limit=2").await;
+    assert_eq!(page["next"], "/v1/movies?limit=2&offset=2");
+    assert!(page.get("next_cursor").is_none());
+}
+
+#[tokio::test]
+async fn bad_cursors_are_rejected() {
+    let app = test_app();
+    for id in ["a", "b", "c"] {
+        add(&app, id, 2000).await;
+    }
+    let (_, page) = list(&app, "cursor=&limit=1").await;
+    let cursor = page["next_cursor"].as_str().unwrap();
+    let (payload, signature) = cursor.split_once('.').unwrap();
+    let forged = format!("{}.{}", &payload[1..], signature);
+    for query in [format!("cursor={forged}"), "cursor=garbage".to_string(), format!("cursor={cursor}&offset=1")] {
+        let (status, body) = list(&app, &query).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{query}");
+        assert_eq!(body["error"]["code"], "invalid_query");
+    }
+}
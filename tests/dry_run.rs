@@ -0,0 +1,109 @@
+use axum::{body::Body, http::{Request, StatusCode}, Router};
+use http_body_util::BodyExt;
+use serde_json::{json, Value};
+use syndica_rust::test_app;
+use tower::ServiceExt;
+
+// dry_run=true on imports, restores and purging the trash answers with what they'd do, and changes nothing.
+
+async fn send(app: &Router, method: &str, uri: &str, content_type: &str, body: String) -> (StatusCode, Value) {
+    let request = Request::builder().method(method).uri(uri).header("content-type", content_type).body(Body::from(body)).unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+async fn send_json(app: &Router, method: &str, uri: &str, body: Option<Value>) -> (StatusCode, Value) {
+    send(app, method, uri, "application/json", body.map(|body| body.to_string()).unwrap_or_default()).await
+}
+
+async fn create(app: &Router, id: &str, name: &str) {
+    let movie = json!({ "id": id, "name": name, "year": 1979, "was_good": true });
+    assert_eq!(send_json(app, "POST", "/v1/movie", Some(movie)).await.0, StatusCode::CREATED);
+}
+
+async fn ids(app: &Router) -> Vec<String> {
+    let page = send_json(app, "GET", "/v1/movies", None).await.1;
+    page["items"].as_array().unwrap().iter().map(|movie| movie["id"].as_str().unwrap().to_string()).collect()
+}
+
+#[tokio::test]
+async fn imports_report_what_they_would_add() {
+    let app = test_app();
+    create(&app, "alien", "Alien").await;
+    let csv = "id,name,year,was_good\nheat,Heat,1995,true\nalien,Alien,1979,true\nheat,Heat,1995,true\nran,Ran,nope,true\n".to_string();
+
+    let (status, report) = send(&app, "POST", "/v1/movies/import?dry_run=true", "text/csv", csv.clone()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!((report["inserted"].as_u64(), report["rejected"].as_u64(), report["dry_run"].as_bool()), (Some(1), Some(3), Some(true)));
+    let rejections: Vec<_> = report["rejections"].as_array().unwrap().iter().map(|rejection| (rejection["line"].as_u64().unwrap(), rejection["error"]["code"].as_str().unwrap())).collect();
+    assert_eq!(rejections, [(3, "already_exists"), (4, "conflict"), (5, "invalid_body")]);
+    assert_eq!(ids(&app).await, ["alien"]);
+
+    // With upsert the repeats would be updates.
+    let (_, report) = send(&app, "POST", "/v1/movies/import?dry_run=true&upsert=true", "text/csv", csv.clone()).await;
+    assert_eq!((report["inserted"].as_u64(), report["updated"].as_u64(), report["rejected"].as_u64()), (Some(1), Some(2), Some(1)));
+
+    // NDJSON says so in its last line.
+    let ndjson = "{\"id\":\"heat\",\"name\":\"Heat\",\"year\":1995,\"was_good\":true}\n".to_string();
+    let (_, done) = send(&app, "POST", "/v1/movies/import?format=ndjson&dry_run=true", "application/x-ndjson", ndjson).await;
+    assert_eq!((done["done"]["inserted"].as_u64(), done["done"]["dry_run"].as_bool()), (Some(1), Some(true)));
+    assert_eq!(ids(&app).await, ["alien"]);
+
+    // And a real import's report is as it was.
+    let (_, report) = send(&app, "POST", "/v1/movies/import", "text/csv", csv).await;
+    assert_eq!((report["inserted"].as_u64(), report.get("dry_run")), (Some(1), None));
+    assert_eq!(ids(&app).await, ["alien", "heat"]);
+}
+
+#[tokio::test]
+async fn restoring_and_purging_report_what_they_would_do() {
+    let app = test_app();
+    create(&app, "alien", "Alien").await;
+    create(&app, "heat", "Heat").await;
+    create(&app, "ran", "Ran").await;
+    for id in ["alien", "ran"] {
+        assert_eq!(send_json(&app, "DELETE", &format!("/v1/movie/{}", id), None).await.0, StatusCode::NO_CONTENT);
+    }
+
+    let (status, movie) = send_json(&app, "POST", "/v1/movie/alien/restore?dry_run=true", None).await;
+    assert_eq!((status, movie["name"].as_str()), (StatusCode::OK, Some("Alien")));
+    assert_eq!(send_json(&app, "POST", "/v1/movie/heat/restore?dry_run=true", None).await.0, StatusCode::NOT_FOUND);
+    assert_eq!(ids(&app).await, ["heat"]);
+    create(&app, "ran", "Ran (remastered)").await;
+    assert_eq!(send_json(&app, "POST", "/v1/movie/ran/restore?dry_run=true", None).await.0, StatusCode::CONFLICT);
+
+    let (status, report) = send_json(&app, "DELETE", "/v1/movies/trash?dry_run=true", None).await;
+    assert_eq!((status, report), (StatusCode::OK, json!({ "purged": ["alien", "ran"], "dry_run": true })));
+    let (_, report) = send_json(&app, "DELETE", "/v1/movies/trash?deleted_before=2000-01-01T00:00:00Z", None).await;
+    assert_eq!(report, json!({ "purged": [] }));
+    assert_eq!(send_json(&app, "DELETE", "/v1/movies/trash?deleted_before=yesterday", None).await.0, StatusCode::BAD_REQUEST);
+    assert_eq!(send_json(&app, "GET", "/v1/movies/trash", None).await.1["total"], 2);
+    let (_, report) = send_json(&app, "DELETE", "/v1/movies/trash", None).await;
+    assert_eq!(report, json!({ "purged": ["alien", "ran"] }));
+    assert_eq!(send_json(&app, "GET", "/v1/movies/trash", None).await.1["total"], 0);
+}
+
+#[tokio::test]
+async fn restoring_a_backup_reports_what_it_would_change() {
+    let app = test_app();
+    create(&app, "alien", "Alien").await;
+    create(&app, "heat", "Heat").await;
+    create(&app, "ran", "Ran").await;
+    let mut backup = send_json(&app, "POST", "/admin/snapshot", None).await.1;
+    // Heat as it is, Alien renamed, Ran gone and Up new.
+    let movies = backup["movies"].as_array_mut().unwrap();
+    movies.retain(|movie| movie["id"] != "ran");
+    movies.iter_mut().filter(|movie| movie["id"] == "alien").for_each(|movie| movie["name"] = json!("Aliens"));
+    movies.push(json!({ "id": "up", "name": "Up", "year": 2009, "was_good": true, "version": 1 }));
+
+    let (status, preview) = send_json(&app, "POST", "/admin/restore?dry_run=true", Some(backup.clone())).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!((&preview["movies"], &preview["added"], &preview["changed"], &preview["removed"], &preview["dry_run"]), (&json!(3), &json!(1), &json!(1), &json!(1), &json!(true)));
+    assert_eq!(ids(&app).await, ["alien", "heat", "ran"]);
+
+    // A backup that wouldn't restore fails the same way.
+    backup["users"] = json!([{ "id": "ann", "created_at": "2026-10-15T09:30:00Z", "watchlist": ["nope"] }]);
+    assert_eq!(send_json(&app, "POST", "/admin/restore?dry_run=true", Some(backup)).await.0, StatusCode::UNPROCESSABLE_ENTITY);
+}
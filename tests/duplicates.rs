@@ -0,0 +1,81 @@
+mod common;
+
+use axum::{http::{header, StatusCode}, Router};
+use serde_json::{json, Value};
+use syndica_rust::{duplicates::DuplicatePolicy, settings::Settings, test_app, test_app_with};
+use common::{json, movie, send_request};
+
+async fn send(app: &Router, method: &str, uri: &str, body: Option<Value>) -> (StatusCode, Option<String>, Value) {
+    let response = send_request(app, method, uri, &[], body).await;
+    let warning = response.headers().get(header::WARNING).map(|warning| warning.to_str().unwrap().to_string());
+    (response.status(), warning, json(response).await)
+}
+
+#[tokio::test]
+async fn same_name_and_year_is_warned_about_or_rejected() {
+    let settings = Settings::default();
+    let app = test_app_with(settings.clone());
+    let (status, warning, _) = send(&app, "POST", "/v1/movie", Some(movie("alien", "Alien", 1979))).await;
+    assert_eq!((status, warning), (StatusCode::CREATED, None));
+
+    // Case, punctuation and a trailing year don't make it another movie, but another year does.
+    let (status, warning, _) = send(&app, "POST", "/v1/movie", Some(movie("alien-2", "ALIEN! (1979)", 1979))).await;
+    assert_eq!((status, warning.as_deref()), (StatusCode::CREATED, Some("299 - \"Movie alien has the same name and year\"")));
+    let (status, warning, _) = send(&app, "POST", "/v1/movie", Some(movie("alien-remake", "Alien", 2030))).await;
+    assert_eq!((status, warning), (StatusCode::CREATED, None));
+    // Nor do accents or a leading "The".
+    assert_eq!(send(&app, "POST", "/v1/movie", Some(movie("amelie", "Amélie", 2001))).await.0, StatusCode::CREATED);
+    let (_, warning, _) = send(&app, "POST", "/v1/movie", Some(movie("amelie-2", "The Amelie", 2001))).await;
+    assert_eq!(warning.as_deref(), Some("299 - \"Movie amelie has the same name and year\""));
+    // Nor does a movie duplicate itself when it's overwritten.
+    let (status, warning, _) = send(&app, "POST", "/v1/movie?upsert=true", Some(movie("alien-remake", "Alien", 2030))).await;
+    assert_eq!((status, warning), (StatusCode::OK, None));
+
+    settings.set_duplicates(DuplicatePolicy::Reject);
+    let (status, _, error) = send(&app, "POST", "/v1/movie", Some(movie("alien-3", "alien", 1979))).await;
+    assert_eq!((status, error["error"]["code"].as_str(), error["error"]["details"]["existing"]["id"].as_str()), (StatusCode::CONFLICT, Some("duplicate"), Some("alien")));
+    let (status, _, _) = send(&app, "POST", "/v1/movie?allow_duplicate=true", Some(movie("alien-3", "alien", 1979))).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let batch = json!([movie("heat", "Heat", 1995), movie("heat-2", "Heat", 1995)]);
+    let (_, _, report) = send(&app, "POST", "/v1/movies/batch", Some(batch)).await;
+    assert_eq!((report["created"].as_u64(), report["duplicate"].as_u64()), (Some(1), Some(1)));
+    assert_eq!(report["results"][1]["error"]["code"], "duplicate");
+
+    let input = "mutation { createMovie(input: {id: \"heat-3\", name: \"heat\", year: 1995, wasGood: true}) { id } }";
+    let (_, _, response) = send(&app, "POST", "/graphql", Some(json!({ "query": input }))).await;
+    assert_eq!(response["errors"][0]["extensions"]["code"], "duplicate");
+}
+
+#[tokio::test]
+async fn merging_folds_a_duplicate_into_the_canonical_movie() {
+    let app = test_app();
+    for (id, tags) in [("alien", json!(["space", "Horror"])), ("alien-2", json!(["horror", "classic"]))] {
+        assert_eq!(send(&app, "POST", "/v1/movie", Some(movie(id, "Alien", 1979))).await.0, StatusCode::CREATED);
+        assert_eq!(send(&app, "PUT", &format!("/v1/movie/{}/tags", id), Some(tags)).await.0, StatusCode::OK);
+    }
+    let rate = |id: &str, user: &str, score: u8| (format!("/v1/movie/{}/ratings", id), json!({ "user": user, "score": score }));
+    for (uri, rating) in [rate("alien", "ann", 9), rate("alien-2", "ann", 2), rate("alien-2", "bob", 7)] {
+        assert_eq!(send(&app, "POST", &uri, Some(rating)).await.0, StatusCode::OK);
+    }
+    for (id, author) in [("alien-2", "cat"), ("alien", "dan")] {
+        let review = json!({ "author": author, "text": "In space no one can hear you scream." });
+        assert_eq!(send(&app, "POST", &format!("/v1/movie/{}/reviews", id), Some(review)).await.0, StatusCode::CREATED);
+    }
+
+    let (status, _, merged) = send(&app, "POST", "/admin/merge", Some(json!({ "canonical": "alien", "duplicate": "alien-2" }))).await;
+    assert_eq!(status, StatusCode::OK);
+    // Ann keeps the score she gave the canonical movie.
+    assert_eq!((merged["rating_count"].as_u64(), merged["average_rating"].as_f64()), (Some(2), Some(8.0)));
+    assert_eq!((&merged["tags"], merged["review_count"].as_u64()), (&json!(["space", "Horror", "classic"]), Some(2)));
+    let (_, _, reviews) = send(&app, "GET", "/v1/movie/alien/reviews", None).await;
+    let authors: Vec<&str> = reviews["items"].as_array().unwrap().iter().map(|review| review["author"].as_str().unwrap()).collect();
+    assert_eq!(authors, ["dan", "cat"]);
+
+    // The duplicate went to the trash.
+    assert_eq!(send(&app, "GET", "/v1/movie/alien-2", None).await.0, StatusCode::NOT_FOUND);
+    assert_eq!(send(&app, "GET", "/v1/movies/trash", None).await.2["items"][0]["movie"]["id"], "alien-2");
+
+    assert_eq!(send(&app, "POST", "/admin/merge", Some(json!({ "canonical": "alien", "duplicate": "alien-2" }))).await.0, StatusCode::NOT_FOUND);
+    assert_eq!(send(&app, "POST", "/admin/merge", Some(json!({ "canonical": "alien", "duplicate": "alien" }))).await.0, StatusCode::UNPROCESSABLE_ENTITY);
+}
@@ -0,0 +1,125 @@
+mod common;
+
+use std::{collections::HashMap, sync::{atomic::{AtomicBool, AtomicUsize, Ordering}, Arc}};
+use axum::{body::Body, extract::{Path, Query, State}, http::{Request, StatusCode}, routing::get, Json, Router};
+use http_body_util::BodyExt;
+use serde_json::{json, Value};
+use syndica_rust::{enrich::{self, EnrichConfig, Provider}, test_app};
+use tower::ServiceExt;
+use common::send;
+
+// Answers like OMDb at / and like TMDB under /3, and fails everything while it's down.
+#[derive(Default)]
+struct FakeProvider {
+    calls: AtomicUsize,
+    down: AtomicBool,
+}
+
+type Answer = Result<Json<Value>, StatusCode>;
+
+fn answer(provider: &FakeProvider, query: &HashMap<String, String>, key_name: &str, body: Value) -> Answer {
+    provider.calls.fetch_add(1, Ordering::SeqCst);
+    if provider.down.load(Ordering::SeqCst) {
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+    if query.get(key_name).map(String::as_str) != Some("k3y") {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+    Ok(Json(body))
+}
+
+async fn omdb(State(provider): State<Arc<FakeProvider>>, Query(query): Query<HashMap<String, String>>) -> Answer {
+    let body = match (query["t"].as_str(), query["y"].as_str()) {
+        ("Alien", "1979") => json!({ "Response": "True", "Title": "Alien", "Runtime": "117 min", "Genre": "Horror, Sci-Fi", "Poster": "https://img.example.com/alien.jpg" }),
+        ("Heat", "1995") => json!({ "Response": "True", "Title": "Heat", "Runtime": "N/A", "Genre": "Crime, crime, Drama", "Poster": "N/A" }),
+        // Not something a movie can have.
+        ("Broken", _) => json!({ "Response": "True", "Runtime": "0 min", "Genre": "Drama", "Poster": "N/A" }),
+        _ => json!({ "Response": "False", "Error": "Movie not found!" }),
+    };
+    answer(&provider, &query, "apikey", body)
+}
+
+async fn tmdb_search(State(provider): State<Arc<FakeProvider>>, Query(query): Query<HashMap<String, String>>) -> Answer {
+    let results = if query["query"] == "Aliens" && query["year"] == "1986" { json!([{ "id": 679 }, { "id": 1 }]) } else { json!([]) };
+    answer(&provider, &query, "api_key", json!({ "page": 1, "results": results }))
+}
+
+async fn tmdb_movie(State(provider): State<Arc<FakeProvider>>, Path(id): Path<u64>, Query(query): Query<HashMap<String, String>>) -> Answer {
+    assert_eq!(id, 679);
+    answer(&provider, &query, "api_key", json!({ "id": 679, "runtime": 137, "genres": [{ "id": 28, "name": "Action" }, { "id": 878, "name": "Science Fiction" }], "poster_path": "/aliens.jpg" }))
+}
+
+async fn spawn_provider() -> (String, Arc<FakeProvider>) {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let provider = Arc::new(FakeProvider::default());
+    let app = Router::new()
+        .route("/", get(omdb))
+        .route("/3/search/movie", get(tmdb_search))
+        .route("/3/movie/{id}", get(tmdb_movie))
+        .with_state(provider.clone());
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    (url, provider)
+}
+
+async fn create(app: &Router, movie: Value) -> Value {
+    let (status, created) = send(app, "POST", "/v1/movie", Some(movie)).await;
+    assert_eq!(status, StatusCode::CREATED);
+    created
+}
+
+fn bare(id: &str, name: &str, year: u16) -> Value {
+    json!({ "id": id, "name": name, "year": year, "was_good": true })
+}
+
+// The provider settings are process-wide, so everything that depends on them is in this one test.
+#[tokio::test]
+async fn bare_movies_are_filled_in_by_the_provider() {
+    let app = test_app();
+    let (url, provider) = spawn_provider().await;
+    let calls = || provider.calls.load(Ordering::SeqCst);
+    assert!(create(&app, bare("alien-0", "Alien", 1979)).await.get("genres").is_none());
+
+    enrich::set_config(Some(EnrichConfig { provider: Provider::Omdb, url: url.clone(), api_key: "k3y".to_string() }));
+    let movie = create(&app, bare("alien", "Alien", 1979)).await;
+    assert_eq!((&movie["runtime_minutes"], &movie["genres"]), (&json!(117), &json!(["Horror", "Sci-Fi"])));
+    assert_eq!(movie["poster_url"], "https://img.example.com/alien.jpg");
+    // Answers are cached, and movies with any details of their own are left alone.
+    assert_eq!(create(&app, bare("alien-2", "alien", 1979)).await["runtime_minutes"], 117);
+    let movie = create(&app, json!({ "id": "alien-3", "name": "Alien", "year": 1979, "was_good": true, "director": "Ridley Scott" })).await;
+    assert!(movie.get("genres").is_none());
+    assert_eq!(calls(), 1);
+    let movie = create(&app, bare("heat", "Heat", 1995)).await;
+    assert_eq!((movie["genres"].clone(), movie.get("runtime_minutes"), movie.get("poster_url")), (json!(["Crime", "Drama"]), None, None));
+    assert!(create(&app, bare("broken", "Broken", 2000)).await.get("genres").is_none());
+    assert!(create(&app, bare("unknown", "Unknown", 2000)).await.get("genres").is_none());
+    assert_eq!(calls(), 4);
+
+    enrich::set_config(Some(EnrichConfig { provider: Provider::Tmdb, url: url.clone(), api_key: "k3y".to_string() }));
+    let movie = create(&app, bare("aliens", "Aliens", 1986)).await;
+    assert_eq!((&movie["runtime_minutes"], &movie["genres"]), (&json!(137), &json!(["Action", "Science Fiction"])));
+    assert_eq!(movie["poster_url"], "https://image.tmdb.org/t/p/w500/aliens.jpg");
+    assert!(create(&app, bare("unknown-2", "Unknown", 2000)).await.get("genres").is_none());
+    assert_eq!(calls(), 7);
+
+    // Once the provider has failed enough times in a row, each after a retry, it isn't asked for a while, and movies
+    // still go in.
+    provider.down.store(true, Ordering::SeqCst);
+    for i in 0..8 {
+        assert!(create(&app, bare(&format!("down-{i}"), &format!("Down {i}"), 2000)).await.get("genres").is_none());
+    }
+    assert_eq!(calls(), 17);
+    let response = app.clone().oneshot(Request::get("/metrics").body(Body::empty()).unwrap()).await.unwrap();
+    let metrics = String::from_utf8(response.into_body().collect().await.unwrap().to_bytes().to_vec()).unwrap();
+    assert!(metrics.lines().any(|line| line == r#"outbound_breaker_state{integration="enrich"} 2"#));
+    assert!(metrics.lines().any(|line| line == r#"outbound_calls_total{integration="enrich",outcome="rejected"} 3"#));
+    // A wrong key is a failure like any other, but not one worth retrying.
+    enrich::set_config(Some(EnrichConfig { provider: Provider::Omdb, url, api_key: "wrong".to_string() }));
+    provider.down.store(false, Ordering::SeqCst);
+    assert!(create(&app, bare("alien-4", "Alien", 1979)).await.get("genres").is_none());
+    assert_eq!(calls(), 18);
+
+    enrich::set_config(None);
+    assert!(create(&app, bare("alien-5", "Alien", 1979)).await.get("genres").is_none());
+    assert_eq!(calls(), 18);
+}
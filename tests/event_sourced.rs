@@ -0,0 +1,65 @@
+mod common;
+
+use std::{io::Write, path::Path, sync::Arc};
+
+use axum::{http::StatusCode, Router};
+use serde_json::json;
+use syndica_rust::{build_router, event_sourced::EventSourcedMovieStore, settings::Settings, test_app};
+use common::send;
+
+async fn open(path: &Path) -> Router {
+    build_router(Arc::new(EventSourcedMovieStore::open(path.to_path_buf()).await.unwrap()), Settings::default())
+}
+
+#[tokio::test]
+async fn every_revision_is_kept_and_survives_a_restart() {
+    let path = std::env::temp_dir().join(format!("syndica-events-{}.events", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let app = open(&path).await;
+    let alien = json!({ "id": "alien", "name": "Alien", "year": 1979, "was_good": true });
+    assert_eq!(send(&app, "POST", "/v1/movie", Some(alien)).await.0, StatusCode::CREATED);
+    assert_eq!(send(&app, "PATCH", "/v1/movie/alien", Some(json!({ "director": "Ridley Scott" }))).await.0, StatusCode::OK);
+    let heat = json!({ "id": "heat", "name": "Heat", "year": 1995, "was_good": true });
+    assert_eq!(send(&app, "POST", "/v1/movie", Some(heat)).await.0, StatusCode::CREATED);
+    assert_eq!(send(&app, "DELETE", "/v1/movie/alien", None).await.0, StatusCode::NO_CONTENT);
+
+    let (status, history) = send(&app, "GET", "/v1/movie/alien/history", None).await;
+    assert_eq!((status, history["total"].as_u64()), (StatusCode::OK, Some(3)));
+    let events: Vec<(u64, &str)> = history["items"].as_array().unwrap().iter().map(|revision| (revision["seq"].as_u64().unwrap(), revision["event"].as_str().unwrap())).collect();
+    assert_eq!(events, [(1, "created"), (2, "updated"), (4, "deleted")]);
+    assert_eq!(history["items"][2]["movie"]["director"], "Ridley Scott");
+    let (_, page) = send(&app, "GET", "/v1/movie/alien/history?limit=2", None).await;
+    assert_eq!(page["next"], "/v1/movie/alien/history?limit=2&offset=2");
+    assert_eq!(send(&app, "GET", "/v1/movie/nope/history", None).await.0, StatusCode::NOT_FOUND);
+
+    // Each revision's time gets the movie as that revision left it.
+    let at = |index: usize| history["items"][index]["at"].as_str().unwrap().to_string();
+    let (status, created) = send(&app, "GET", &format!("/v1/movie/alien?as_of={}", at(0)), None).await;
+    assert_eq!((status, created.get("director")), (StatusCode::OK, None));
+    let (_, updated) = send(&app, "GET", &format!("/v1/movie/alien?as_of={}", at(1)), None).await;
+    assert_eq!(updated["director"], "Ridley Scott");
+    assert_eq!(send(&app, "GET", &format!("/v1/movie/alien?as_of={}", at(2)), None).await.0, StatusCode::NOT_FOUND);
+    assert_eq!(send(&app, "GET", "/v1/movie/alien?as_of=2000-01-01T00:00:00Z", None).await.0, StatusCode::NOT_FOUND);
+    assert_eq!(send(&app, "GET", "/v1/movie/heat?as_of=yesterday", None).await.0, StatusCode::BAD_REQUEST);
+
+    // A torn last line, as a crash mid-append leaves, is dropped and written over.
+    std::fs::OpenOptions::new().append(true).open(&path).unwrap().write_all(b"{\"seq\":5,\"at\":").unwrap();
+    let app = open(&path).await;
+    assert_eq!(send(&app, "GET", "/v1/movie/alien", None).await.0, StatusCode::NOT_FOUND);
+    assert_eq!(send(&app, "GET", "/v1/movie/heat", None).await.1["name"], "Heat");
+    assert_eq!(send(&app, "GET", "/v1/movie/alien/history", None).await.1, history);
+    assert_eq!(send(&app, "PATCH", "/v1/movie/heat", Some(json!({ "year": 1996 }))).await.0, StatusCode::OK);
+    let app = open(&path).await;
+    let (_, history) = send(&app, "GET", "/v1/movie/heat/history", None).await;
+    assert_eq!((history["items"][1]["seq"].as_u64(), history["items"][1]["movie"]["year"].as_u64()), (Some(5), Some(1996)));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn other_stores_have_no_history() {
+    let app = test_app();
+    let movie = json!({ "id": "alien", "name": "Alien", "year": 1979, "was_good": true });
+    assert_eq!(send(&app, "POST", "/v1/movie", Some(movie)).await.0, StatusCode::CREATED);
+    assert_eq!(send(&app, "GET", "/v1/movie/alien/history", None).await.0, StatusCode::NOT_FOUND);
+    assert_eq!(send(&app, "GET", "/v1/movie/alien?as_of=2026-01-01T00:00:00Z", None).await.0, StatusCode::NOT_FOUND);
+}
@@ -0,0 +1,59 @@
+mod common;
+
+use std::time::Duration;
+use axum::{body::Body, http::{Request, StatusCode}};
+use http_body_util::BodyExt;
+use serde_json::{json, Value};
+use syndica_rust::test_app;
+use tower::ServiceExt;
+use common::send;
+
+// Reads from the stream until it has `count` events, as (event, data) pairs. Keep-alive comments are skipped.
+async fn next_events(body: &mut Body, count: usize) -> Vec<(String, Value)> {
+    let mut text = String::new();
+    let mut events = Vec::new();
+    while events.len() < count {
+        let frame = tokio::time::timeout(Duration::from_secs(5), body.frame()).await.expect("no event within 5s");
+        text.push_str(std::str::from_utf8(&frame.unwrap().unwrap().into_data().unwrap()).unwrap());
+        while let Some(end) = text.find("\n\n") {
+            let block: String = text.drain(..end + 2).collect();
+            let field = |name: &str| block.lines().find_map(|line| line.strip_prefix(name)).map(str::to_string);
+            if let (Some(event), Some(data)) = (field("event: "), field("data: ")) {
+                events.push((event, serde_json::from_str(&data).unwrap()));
+            }
+        }
+    }
+    events
+}
+
+#[tokio::test]
+async fn changes_are_streamed_in_order() {
+    let app = test_app();
+    send(&app, "POST", "/movie", Some(json!({ "id": "before", "name": "Before", "year": 2000, "was_good": true }))).await;
+
+    let response = app.clone().oneshot(Request::get("/movies/events").body(Body::empty()).unwrap()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["content-type"], "text/event-stream");
+    let mut body = response.into_body();
+
+    assert_eq!(send(&app, "POST", "/movie", Some(json!({ "id": "alien", "name": "Alien", "year": 1979, "was_good": true }))).await.0, StatusCode::CREATED);
+    assert_eq!(send(&app, "PATCH", "/movie/alien", Some(json!({ "was_good": false }))).await.0, StatusCode::OK);
+    assert_eq!(send(&app, "DELETE", "/movie/alien", None).await.0, StatusCode::NO_CONTENT);
+    // Failed writes don't produce events.
+    assert_eq!(send(&app, "DELETE", "/movie/alien", None).await.0, StatusCode::NOT_FOUND);
+    assert_eq!(send(&app, "POST", "/movie?upsert=true", Some(json!({ "id": "before", "name": "After", "year": 2000, "was_good": true }))).await.0, StatusCode::OK);
+
+    let events = next_events(&mut body, 4).await;
+    let names: Vec<_> = events.iter().map(|(event, _)| event.as_str()).collect();
+    assert_eq!(names, ["created", "updated", "deleted", "updated"]);
+    let mut created = events[0].1.clone();
+    let stamps = (created.as_object_mut().unwrap().remove("created_at"), created.as_object_mut().unwrap().remove("updated_at"));
+    assert!(stamps.0.is_some() && stamps.0 == stamps.1, "{stamps:?}");
+    assert_eq!(created, json!({ "id": "alien", "name": "Alien", "year": 1979, "was_good": true, "version": 1 }));
+    assert_eq!(events[1].1["was_good"], false);
+    assert_eq!(events[2].1["id"], "alien");
+    assert_eq!(events[3].1["name"], "After");
+    // Overwriting a movie moves it on to its next version, it doesn't start it over.
+    assert_eq!(events[1].1["version"], 2);
+    assert_eq!(events[3].1["version"], 2);
+}
@@ -0,0 +1,81 @@
+use axum::{body::Body, http::{Request, StatusCode}, Router};
+use http_body_util::BodyExt;
+use serde_json::{json, Value};
+use syndica_rust::{model::Movie, test_app};
+use tower::ServiceExt;
+
+async fn request(app: &Router, request: Request<Body>) -> (StatusCode, String, String) {
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let content_type = response.headers().get("content-type").map(|value| value.to_str().unwrap().to_string()).unwrap_or_default();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, content_type, String::from_utf8(body.to_vec()).unwrap())
+}
+
+async fn add_movies(app: &Router, count: usize) {
+    let movies: Vec<Value> = (0..count)
+        .map(|i| {
+            let mut movie = json!({ "id": format!("movie-{:04}", i), "name": format!("Movie, \"number\" {}", i), "year": 1950 + i % 70, "was_good": i % 2 == 0 });
+            if i % 3 == 1 {
+                movie["genres"] = json!(["Drama", "Film noir"]);
+                movie["director"] = json!(format!("Director {}", i % 7));
+                movie["runtime_minutes"] = json!(80 + i % 60);
+                movie["synopsis"] = json!("Someone, somewhere,\ndoes \"something\".");
+                movie["tags"] = json!(["noir", "rainy"]);
+            }
+            movie
+        })
+        .collect();
+    for batch in movies.chunks(1000) {
+        let body = Body::from(Value::from(batch.to_vec()).to_string());
+        let (status, _, _) = request(app, Request::post("/movies/batch").header("content-type", "application/json").body(body).unwrap()).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+}
+
+#[tokio::test]
+async fn ndjson_export_has_every_movie_in_order() {
+    let app = test_app();
+    // More than two chunks' worth, ending in a partial one.
+    add_movies(&app, 1203).await;
+    let (status, content_type, body) = request(&app, Request::get("/movies/export").body(Body::empty()).unwrap()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(content_type, "application/x-ndjson");
+    let movies: Vec<Movie> = body.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    assert_eq!(movies.len(), 1203);
+    assert!(movies.windows(2).all(|pair| pair[0].id < pair[1].id));
+    assert_eq!(movies[1202].id, "movie-1202");
+
+    let (status, _, body) = request(&app, Request::get("/movies/export?format=xml").body(Body::empty()).unwrap()).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+}
+
+#[tokio::test]
+async fn csv_export_imports_back_unchanged() {
+    let app = test_app();
+    add_movies(&app, 500).await;
+    let (_, content_type, csv) = request(&app, Request::get("/movies/export?format=csv").body(Body::empty()).unwrap()).await;
+    assert!(content_type.starts_with("text/csv"));
+    assert!(csv.starts_with(concat!(
+        "id,name,year,was_good,genres,director,runtime_minutes,synopsis,tags\r\n",
+        "movie-0000,\"Movie, \"\"number\"\" 0\",1950,true,,,,,\r\n",
+        "movie-0001,\"Movie, \"\"number\"\" 1\",1951,false,Drama;Film noir,Director 1,81,\"Someone, somewhere,\ndoes \"\"something\"\".\",noir;rainy\r\n",
+    )), "{}", &csv[..300]);
+
+    let copy = test_app();
+    let import = Request::post("/movies/import").header("content-type", "text/csv").body(Body::from(csv)).unwrap();
+    let (status, _, report) = request(&copy, import).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(serde_json::from_str::<Value>(&report).unwrap()["inserted"], 500);
+
+    // All but when they were added, which CSV doesn't carry.
+    let export = |app: Router| async move {
+        let ndjson = request(&app, Request::get("/movies/export").body(Body::empty()).unwrap()).await.2;
+        ndjson.lines().map(|line| {
+            let mut movie: Value = serde_json::from_str(line).unwrap();
+            movie.as_object_mut().unwrap().retain(|key, _| !key.ends_with("_at"));
+            movie
+        }).collect::<Vec<_>>()
+    };
+    assert_eq!(export(app).await, export(copy).await);
+}
@@ -0,0 +1,66 @@
+mod common;
+
+use axum::http::StatusCode;
+use http_body_util::BodyExt;
+use serde_json::json;
+use syndica_rust::test_app;
+use common::{json, send_request};
+
+#[tokio::test]
+async fn head_answers_like_get_without_the_body() {
+    let app = test_app();
+    send_request(&app, "POST", "/movie", &[], Some(json!({ "id": "alien", "name": "Alien", "year": 1979, "was_good": true }))).await;
+    let get = send_request(&app, "GET", "/v1/movie/alien", &[], None).await;
+    let head = send_request(&app, "HEAD", "/v1/movie/alien", &[], None).await;
+    assert_eq!(head.status(), StatusCode::OK);
+    for name in ["etag", "content-length", "content-type"] {
+        assert_eq!(head.headers()[name], get.headers()[name], "{} differs", name);
+    }
+    assert!(head.into_body().collect().await.unwrap().to_bytes().is_empty());
+
+    let etag = get.headers()["etag"].to_str().unwrap().to_string();
+    assert_eq!(send_request(&app, "HEAD", "/v1/movie/alien", &[("if-none-match", &etag)], None).await.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(send_request(&app, "HEAD", "/v1/movie/heat", &[], None).await.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn options_lists_the_methods_of_a_route() {
+    let app = test_app();
+    for (uri, allow) in [("/v1/movie/alien", "GET,HEAD,PUT,PATCH,DELETE,OPTIONS"), ("/movies", "GET,HEAD,OPTIONS"), ("/v1/movie", "POST,OPTIONS")] {
+        let response = send_request(&app, "OPTIONS", uri, &[], None).await;
+        assert_eq!((response.status(), response.headers()["allow"].to_str().unwrap()), (StatusCode::NO_CONTENT, allow), "{}", uri);
+    }
+    assert_eq!(send_request(&app, "OPTIONS", "/v1/nothing", &[], None).await.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn other_methods_a_route_lacks_get_a_json_error() {
+    let app = test_app();
+    let response = send_request(&app, "GET", "/movie", &[], None).await;
+    assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(response.headers()["allow"], "POST,OPTIONS");
+    assert_eq!(response.headers()["content-type"], "application/json");
+    assert!(response.headers().contains_key("x-request-id"));
+    let body = json(response).await;
+    assert_eq!(body["error"]["code"], "method_not_allowed");
+    assert_eq!(body["error"]["details"], serde_json::json!({ "allowed": ["POST", "OPTIONS"] }));
+    assert!(body["error"]["message"].as_str().unwrap().contains("GET /movie isn't allowed"));
+    assert!(body["error"]["request_id"].is_string());
+}
+
+#[tokio::test]
+async fn unknown_paths_get_a_json_error_with_a_hint() {
+    let app = test_app();
+    for (path, hint) in [("/v1/movei/alien", Some("/v1/movie/{id}")), ("/moviess", Some("/movies")), ("/v2/movies", Some("/v1/movies")), ("/nothing/here/at/all", None)] {
+        let response = send_request(&app, "GET", path, &[], None).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND, "{}", path);
+        let body = json(response).await;
+        assert_eq!(body["error"]["code"], "not_found");
+        let message = body["error"]["message"].as_str().unwrap();
+        assert!(message.contains("/api-docs/openapi.json"));
+        match hint {
+            Some(route) => assert!(message.contains(&format!("did you mean {}?", route)), "{}", message),
+            None => assert!(!message.contains("did you mean"), "{}", message),
+        }
+    }
+}
@@ -0,0 +1,136 @@
+mod common;
+
+use axum::{body::Body, http::{Request, StatusCode}, Router};
+use serde_json::{json, Value};
+use tower::ServiceExt;
+use common::{seeded_app, send};
+
+fn catalog() -> Vec<Value> {
+    let movies = [
+        ("alien", "Alien", vec!["Horror", "Sci-Fi"]),
+        ("blade-runner", "Blade Runner", vec!["sci-fi", "Noir"]),
+        ("heat", "Heat", vec!["Crime"]),
+        ("the-thing", "The Thing", vec!["Horror"]),
+    ];
+    movies.into_iter().map(|(id, name, genres)| json!({ "id": id, "name": name, "year": 1982, "was_good": true, "genres": genres })).collect()
+}
+
+async fn genres_of(app: &Router, id: &str) -> Value {
+    send(app, "GET", &format!("/v1/movie/{id}"), None).await.1["genres"].clone()
+}
+
+#[tokio::test]
+async fn lists_genres_with_counts_ignoring_case() {
+    let app = seeded_app(catalog()).await;
+    let (status, body) = send(&app, "GET", "/v1/genres", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["items"], json!([
+        { "name": "Crime", "movie_count": 1 },
+        { "name": "Horror", "movie_count": 2 },
+        { "name": "Noir", "movie_count": 1 },
+        { "name": "Sci-Fi", "movie_count": 2 },
+    ]));
+
+    let (status, body) = send(&app, "GET", "/v1/genres/SCI-FI", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, json!({ "name": "Sci-Fi", "movie_count": 2 }));
+    let (status, body) = send(&app, "GET", "/v1/genres/western", None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body["error"]["code"], "not_found");
+}
+
+#[tokio::test]
+async fn pages_through_the_movies_in_a_genre() {
+    let app = seeded_app(catalog()).await;
+    let (status, body) = send(&app, "GET", "/v1/genres/horror/movies?limit=1", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["total"], 2);
+    assert_eq!(body["items"][0]["id"], "alien");
+    assert_eq!(body["next"], "/v1/genres/horror/movies?limit=1&offset=1");
+
+    let (_, body) = send(&app, "GET", body["next"].as_str().unwrap(), None).await;
+    assert_eq!(body["items"][0]["id"], "the-thing");
+    assert_eq!(body["next"], Value::Null);
+
+    let (status, _) = send(&app, "GET", "/v1/genres/western/movies", None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn index_follows_movie_writes() {
+    let app = seeded_app(catalog()).await;
+    let (status, _) = send(&app, "PATCH", "/v1/movie/heat", Some(json!({ "genres": ["Thriller"] }))).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = send(&app, "DELETE", "/v1/movie/the-thing", None).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    let (_, body) = send(&app, "GET", "/v1/genres", None).await;
+    let names: Vec<&str> = body["items"].as_array().unwrap().iter().map(|genre| genre["name"].as_str().unwrap()).collect();
+    assert_eq!(names, ["Horror", "Noir", "Sci-Fi", "Thriller"]);
+    assert_eq!(send(&app, "GET", "/v1/genres/horror", None).await.1["movie_count"], 1);
+}
+
+#[tokio::test]
+async fn creating_a_genre_gives_it_to_the_movies() {
+    let app = seeded_app(catalog()).await;
+    let body = json!({ "name": "Classic", "movies": ["alien", "heat"] });
+    let response = app.clone().oneshot(Request::post("/v1/genres")
+        .header("content-type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    assert_eq!(response.headers()["location"], "/genres/Classic");
+    assert_eq!(genres_of(&app, "alien").await, json!(["Horror", "Sci-Fi", "Classic"]));
+    assert_eq!(genres_of(&app, "heat").await, json!(["Crime", "Classic"]));
+
+    let (status, body) = send(&app, "POST", "/v1/genres", Some(json!({ "name": "classic", "movies": ["the-thing"] }))).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(body["error"]["code"], "conflict");
+}
+
+#[tokio::test]
+async fn creating_a_genre_checks_every_movie_first() {
+    let app = seeded_app(catalog()).await;
+    for body in [
+        json!({ "name": "Cult", "movies": [] }),
+        json!({ "name": "", "movies": ["heat"] }),
+        json!({ "name": "Cult", "movies": ["heat", "missing"] }),
+    ] {
+        let (status, body) = send(&app, "POST", "/v1/genres", Some(body)).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["error"]["code"], "validation_failed");
+    }
+    // Heat comes before the missing movie, but didn't get the genre either.
+    assert_eq!(genres_of(&app, "heat").await, json!(["Crime"]));
+}
+
+#[tokio::test]
+async fn renaming_a_genre_changes_every_movie() {
+    let app = seeded_app(catalog()).await;
+    let (status, body) = send(&app, "PUT", "/v1/genres/sci-fi", Some(json!({ "name": "Science Fiction" }))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, json!({ "name": "Science Fiction", "movie_count": 2 }));
+    assert_eq!(genres_of(&app, "alien").await, json!(["Horror", "Science Fiction"]));
+    assert_eq!(genres_of(&app, "blade-runner").await, json!(["Science Fiction", "Noir"]));
+    assert_eq!(send(&app, "GET", "/v1/genres/sci-fi", None).await.0, StatusCode::NOT_FOUND);
+
+    // Into one a movie already has, which merges them.
+    let (_, body) = send(&app, "PUT", "/v1/genres/noir", Some(json!({ "name": "Science Fiction" }))).await;
+    assert_eq!(body["movie_count"], 2);
+    assert_eq!(genres_of(&app, "blade-runner").await, json!(["Science Fiction"]));
+
+    let (status, _) = send(&app, "PUT", "/v1/genres/western", Some(json!({ "name": "Western" }))).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn deleting_a_genre_takes_it_off_every_movie() {
+    let app = seeded_app(catalog()).await;
+    let (status, _) = send(&app, "DELETE", "/v1/genres/Horror", None).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    assert_eq!(genres_of(&app, "alien").await, json!(["Sci-Fi"]));
+    // Without any genres left they're left out altogether.
+    assert_eq!(genres_of(&app, "the-thing").await, Value::Null);
+    assert_eq!(send(&app, "GET", "/v1/genres/horror", None).await.0, StatusCode::NOT_FOUND);
+    assert_eq!(send(&app, "DELETE", "/v1/genres/horror", None).await.0, StatusCode::NOT_FOUND);
+}
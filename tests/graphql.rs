@@ -0,0 +1,108 @@
+mod common;
+
+use axum::{body::Body, http::{Request, StatusCode}, Router};
+use http_body_util::BodyExt;
+use serde_json::{json, Value};
+use syndica_rust::test_app;
+use tower::ServiceExt;
+use common::send;
+
+async fn graphql_text(app: &Router, body: Value) -> String {
+    let request = Request::post("/graphql").header("content-type", "application/json").body(Body::from(body.to_string())).unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    String::from_utf8(body.to_vec()).unwrap()
+}
+
+async fn graphql(app: &Router, body: Value) -> Value {
+    serde_json::from_str(&graphql_text(app, body).await).unwrap()
+}
+
+#[tokio::test]
+async fn mutations_and_queries_share_the_rest_store() {
+    let app = test_app();
+    let create = json!({
+        "query": "mutation Add($input: MovieInput!) { createMovie(input: $input) { id name year wasGood } }",
+        "variables": { "input": { "id": "alien", "name": "Alien", "year": 1979, "wasGood": true } },
+    });
+    let response = graphql(&app, create).await;
+    assert_eq!(response, json!({ "data": { "createMovie": { "id": "alien", "name": "Alien", "year": 1979, "wasGood": true } } }));
+
+    let (status, movie) = send(&app, "GET", "/movie/alien", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(movie["name"], "Alien");
+
+    send(&app, "POST", "/movie", Some(json!({ "id": "cats", "name": "Cats", "year": 2019, "was_good": false }))).await;
+    let query = json!({ "query": r#"
+        query {
+            good: movies(filter: { wasGood: true }) { total items { ...Title } }
+            all: movies(page: { limit: 1, offset: 1 }) { total items { id } }
+            cats: movie(id: "cats") { __typename wasGood }
+            nothing: movie(id: "missing") { id }
+        }
+        fragment Title on Movie { name }
+    "# });
+    let text = graphql_text(&app, query).await;
+    let response: Value = serde_json::from_str(&text).unwrap();
+    assert_eq!(response, json!({ "data": {
+        "good": { "total": 1, "items": [{ "name": "Alien" }] },
+        "all": { "total": 2, "items": [{ "id": "cats" }] },
+        "cats": { "__typename": "Movie", "wasGood": false },
+        "nothing": null,
+    } }));
+    // Keys come back in the order they were asked for.
+    assert!(text.find("\"good\"").unwrap() < text.find("\"all\"").unwrap());
+    assert!(text.find("\"total\"").unwrap() < text.find("\"items\"").unwrap());
+
+    let update = json!({ "query": r#"mutation { updateMovie(id: "cats", patch: { wasGood: true }) { wasGood } deleteMovie(id: "alien") { id } }"# });
+    let response = graphql(&app, update).await;
+    assert_eq!(response, json!({ "data": { "updateMovie": { "wasGood": true }, "deleteMovie": { "id": "alien" } } }));
+    assert_eq!(send(&app, "GET", "/movie/alien", None).await.0, StatusCode::NOT_FOUND);
+    assert_eq!(send(&app, "GET", "/movie/cats", None).await.1["was_good"], true);
+}
+
+#[tokio::test]
+async fn errors_carry_rest_error_codes() {
+    let app = test_app();
+    send(&app, "POST", "/movie", Some(json!({ "id": "alien", "name": "Alien", "year": 1979, "was_good": true }))).await;
+
+    let query = json!({ "query": r#"mutation {
+        duplicate: createMovie(input: { id: "alien", name: "Alien", year: 1979, wasGood: true }) { id }
+        invalid: createMovie(input: { name: "", year: 1979, wasGood: true }) { id }
+        missing: deleteMovie(id: "missing") { id }
+    }"# });
+    let response = graphql(&app, query).await;
+    assert_eq!(response["data"], json!({ "duplicate": null, "invalid": null, "missing": null }));
+    let errors = response["errors"].as_array().unwrap();
+    let codes: Vec<_> = errors.iter().map(|error| error["extensions"]["code"].as_str().unwrap()).collect();
+    assert_eq!(codes, ["already_exists", "validation_failed", "not_found"]);
+    assert_eq!(errors[0]["path"], json!(["duplicate"]));
+    assert_eq!(errors[0]["extensions"]["details"]["existing"]["id"], "alien");
+    assert_eq!(errors[1]["extensions"]["details"]["fields"][0]["field"], "name");
+
+    let response = graphql(&app, json!({ "query": "{ movie(id: \"alien\") { rating } }" })).await;
+    assert_eq!(response["data"], json!({ "movie": { "rating": null } }));
+    assert_eq!(response["errors"][0]["path"], json!(["movie", "rating"]));
+
+    let response = graphql(&app, json!({ "query": "{ movie(id: " })).await;
+    assert!(response.get("data").is_none());
+    assert!(response["errors"][0]["message"].as_str().unwrap().starts_with("Syntax error"));
+}
+
+#[tokio::test]
+async fn directives_and_schema() {
+    let app = test_app();
+    send(&app, "POST", "/movie", Some(json!({ "id": "alien", "name": "Alien", "year": 1979, "was_good": true }))).await;
+    let query = json!({
+        "query": "query ($full: Boolean = false) { movie(id: \"alien\") { id name @include(if: $full) ... @skip(if: $full) { year } } }",
+    });
+    assert_eq!(graphql(&app, query.clone()).await["data"]["movie"], json!({ "id": "alien", "year": 1979 }));
+    let mut full = query;
+    full["variables"] = json!({ "full": true });
+    assert_eq!(graphql(&app, full).await["data"]["movie"], json!({ "id": "alien", "name": "Alien" }));
+
+    let response = app.oneshot(Request::get("/graphql").body(Body::empty()).unwrap()).await.unwrap();
+    let schema = response.into_body().collect().await.unwrap().to_bytes();
+    assert!(std::str::from_utf8(&schema).unwrap().contains("createMovie(input: MovieInput!"));
+}
@@ -0,0 +1,220 @@
+use std::future::pending;
+use axum::{body::Bytes, http::{Request, StatusCode, Version}};
+use http_body_util::{BodyExt, Full};
+use hyper::client::conn::http2::SendRequest;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use syndica_rust::{build_router, listener::{HttpConfig, Listener}, model::Movie, settings::Settings, state::{state_init, StateWrapper}};
+use tokio::net::TcpStream;
+
+// gRPC clients speak HTTP/2 with prior knowledge, so these go through a real listener rather than the router alone.
+
+async fn serve(state: StateWrapper) -> SendRequest<Full<Bytes>> {
+    let listener = Listener::bind(&"127.0.0.1:0".parse().unwrap()).await.unwrap();
+    let Listener::Tcp(tcp) = &listener else { unreachable!() };
+    let address = tcp.local_addr().unwrap();
+    tokio::spawn(listener.serve(build_router(state, Settings::default()), HttpConfig::default(), pending()));
+    let stream = TokioIo::new(TcpStream::connect(address).await.unwrap());
+    let (sender, connection) = hyper::client::conn::http2::handshake(TokioExecutor::new(), stream).await.unwrap();
+    tokio::spawn(connection);
+    sender
+}
+
+fn framed(message: &[u8]) -> Bytes {
+    let mut body = vec![0];
+    body.extend_from_slice(&(message.len() as u32).to_be_bytes());
+    body.extend_from_slice(message);
+    body.into()
+}
+
+fn varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn uint_field(out: &mut Vec<u8>, field: u64, value: u64) {
+    varint(out, field << 3);
+    varint(out, value);
+}
+
+fn bytes_field(out: &mut Vec<u8>, field: u64, value: &[u8]) {
+    varint(out, field << 3 | 2);
+    varint(out, value.len() as u64);
+    out.extend_from_slice(value);
+}
+
+#[derive(Debug, PartialEq)]
+enum Field {
+    Uint(u64),
+    Bytes(Vec<u8>),
+}
+
+fn fields(mut message: &[u8]) -> Vec<(u64, Field)> {
+    fn read_varint(message: &mut &[u8]) -> u64 {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = message[0];
+            *message = &message[1..];
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte < 0x80 {
+                break;
+            }
+        }
+        value
+    }
+    let mut fields = Vec::new();
+    while !message.is_empty() {
+        let key = read_varint(&mut message);
+        let value = match key & 7 {
+            0 => Field::Uint(read_varint(&mut message)),
+            2 => {
+                let len = read_varint(&mut message) as usize;
+                let (value, rest) = message.split_at(len);
+                message = rest;
+                Field::Bytes(value.to_vec())
+            },
+            other => panic!("unexpected wire type {}", other),
+        };
+        fields.push((key >> 3, value));
+    }
+    fields
+}
+
+fn string(fields: &[(u64, Field)], number: u64) -> Option<String> {
+    fields.iter().find_map(|(field, value)| match value {
+        Field::Bytes(bytes) if *field == number => Some(String::from_utf8(bytes.clone()).unwrap()),
+        _ => None,
+    })
+}
+
+fn uint(fields: &[(u64, Field)], number: u64) -> Option<u64> {
+    fields.iter().find_map(|(field, value)| match value {
+        Field::Uint(value) if *field == number => Some(*value),
+        _ => None,
+    })
+}
+
+fn movie(id: &str, name: &str, version: Option<u64>) -> Vec<u8> {
+    let mut message = Vec::new();
+    bytes_field(&mut message, 1, id.as_bytes());
+    bytes_field(&mut message, 2, name.as_bytes());
+    uint_field(&mut message, 3, 1979);
+    uint_field(&mut message, 4, 1);
+    bytes_field(&mut message, 5, b"Horror");
+    let mut title = Vec::new();
+    bytes_field(&mut title, 1, b"fr");
+    bytes_field(&mut title, 2, b"Le Huitieme Passager");
+    bytes_field(&mut message, 11, &title);
+    if let Some(version) = version {
+        uint_field(&mut message, 12, version);
+    }
+    message
+}
+
+fn id(id: &str) -> Vec<u8> {
+    let mut message = Vec::new();
+    bytes_field(&mut message, 1, id.as_bytes());
+    message
+}
+
+// The grpc-status and the message a unary call answered with.
+async fn call(sender: &mut SendRequest<Full<Bytes>>, method: &str, message: &[u8]) -> (String, Vec<(u64, Field)>) {
+    let request = Request::post(format!("http://localhost/movies.v1.MovieService/{}", method))
+        .header("content-type", "application/grpc")
+        .header("te", "trailers")
+        .body(Full::new(framed(message)))
+        .unwrap();
+    let response = sender.send_request(request).await.unwrap();
+    assert_eq!((response.version(), response.status()), (Version::HTTP_2, StatusCode::OK));
+    assert_eq!(response.headers()["content-type"], "application/grpc");
+    let body = response.into_body().collect().await.unwrap();
+    let status = body.trailers().unwrap()["grpc-status"].to_str().unwrap().to_string();
+    let body = body.to_bytes();
+    let message = if body.is_empty() { Vec::new() } else { fields(&body[5..]) };
+    (status, message)
+}
+
+#[tokio::test]
+async fn unary_calls_share_the_store() {
+    let state = state_init();
+    let mut sender = serve(state.clone()).await;
+
+    // Without a version the movie is added.
+    let (status, added) = call(&mut sender, "Put", &movie("alien", "Alien", None)).await;
+    assert_eq!((status.as_str(), uint(&added, 12)), ("0", Some(1)));
+    assert!(string(&added, 13).is_some());
+    let stored = state.get("alien").await.unwrap();
+    assert_eq!((stored.name.as_str(), &stored.genres, stored.titles["fr"].as_str()), ("Alien", &vec!["Horror".to_string()], "Le Huitieme Passager"));
+    assert_eq!(call(&mut sender, "Put", &movie("alien", "Alien", None)).await.0, "6");
+    // With one, that version is replaced, and no other.
+    let (status, replaced) = call(&mut sender, "Put", &movie("alien", "Alien (Director's Cut)", Some(1))).await;
+    assert_eq!((status.as_str(), uint(&replaced, 12), string(&replaced, 13)), ("0", Some(2), string(&added, 13)));
+    assert_eq!(call(&mut sender, "Put", &movie("alien", "Alien", Some(1))).await.0, "9");
+    assert_eq!(call(&mut sender, "Put", &movie("alien", "", Some(2))).await.0, "3");
+
+    let (status, fetched) = call(&mut sender, "Get", &id("alien")).await;
+    assert_eq!((status.as_str(), string(&fetched, 2).as_deref()), ("0", Some("Alien (Director's Cut)")));
+    assert_eq!(call(&mut sender, "Get", &id("heat")).await.0, "5");
+
+    state.insert(Movie { id: "heat".into(), name: "Heat".into(), year: 1995, was_good: true, ..Movie::default() }).await.unwrap();
+    let mut request = Vec::new();
+    uint_field(&mut request, 1, 1);
+    let (status, page) = call(&mut sender, "List", &request).await;
+    let movies: Vec<_> = page.iter().filter_map(|(field, value)| match value {
+        Field::Bytes(movie) if *field == 1 => string(&fields(movie), 1),
+        _ => None,
+    }).collect();
+    assert_eq!((status.as_str(), movies, uint(&page, 2)), ("0", vec!["alien".to_string()], Some(2)));
+    uint_field(&mut request, 3, 1995);
+    let (_, page) = call(&mut sender, "List", &request).await;
+    assert_eq!(uint(&page, 2), Some(1));
+
+    let (status, deleted) = call(&mut sender, "Delete", &id("heat")).await;
+    assert_eq!((status.as_str(), string(&deleted, 2).as_deref()), ("0", Some("Heat")));
+    assert!(state.get("heat").await.is_none());
+    assert_eq!(call(&mut sender, "Delete", &id("heat")).await.0, "5");
+    assert_eq!(call(&mut sender, "Rename", &id("alien")).await.0, "12");
+}
+
+#[tokio::test]
+async fn watch_streams_changes() {
+    let state = state_init();
+    let mut sender = serve(state.clone()).await;
+    let request = Request::post("http://localhost/movies.v1.MovieService/Watch")
+        .header("content-type", "application/grpc")
+        .body(Full::new(framed(&[])))
+        .unwrap();
+    let response = sender.send_request(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let mut body = response.into_body();
+
+    state.insert(Movie { id: "alien".into(), name: "Alien".into(), year: 1979, was_good: true, ..Movie::default() }).await.unwrap();
+    state.delete("alien").await.unwrap();
+    let mut changes = Vec::new();
+    while changes.len() < 2 {
+        let frame = body.frame().await.unwrap().unwrap();
+        let data = frame.into_data().unwrap();
+        let change = fields(&data[5..]);
+        let Some((_, Field::Bytes(movie))) = change.iter().find(|(field, _)| *field == 2) else { panic!("no movie in {:?}", change) };
+        changes.push((string(&change, 1).unwrap(), string(&fields(movie), 1).unwrap()));
+    }
+    assert_eq!(changes, [("created".to_string(), "alien".to_string()), ("deleted".to_string(), "alien".to_string())]);
+}
+
+#[tokio::test]
+async fn malformed_calls_are_refused() {
+    let mut sender = serve(state_init()).await;
+    let request = Request::post("http://localhost/movies.v1.MovieService/Get").header("content-type", "application/json").body(Full::new(Bytes::from_static(b"{}"))).unwrap();
+    assert_eq!(sender.send_request(request).await.unwrap().status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+    for body in [&b"\0\0\0"[..], b"\0\0\0\0\x09\x0a", b"\x01\0\0\0\0"] {
+        let request = Request::post("http://localhost/movies.v1.MovieService/Get").header("content-type", "application/grpc").body(Full::new(Bytes::copy_from_slice(body))).unwrap();
+        let body = sender.send_request(request).await.unwrap().into_body().collect().await.unwrap();
+        let status = body.trailers().unwrap()["grpc-status"].to_str().unwrap().to_string();
+        assert!(status == "3" || status == "12", "{:?} got {}", body, status);
+    }
+    // A field that runs past the end of the message.
+    assert_eq!(call(&mut sender, "Get", b"\x0a\x05ali").await.0, "3");
+}
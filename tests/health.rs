@@ -0,0 +1,29 @@
+mod common;
+
+use std::sync::Arc;
+
+use axum::http::StatusCode;
+use syndica_rust::{build_router, settings::Settings, snapshot::SnapshotMovieStore, test_app};
+use common::send;
+
+#[tokio::test]
+async fn memory_store_is_live_and_ready() {
+    let app = test_app();
+    assert_eq!(send(&app, "GET", "/healthz", None).await, (StatusCode::OK, serde_json::json!({ "status": "ok" })));
+    assert_eq!(send(&app, "GET", "/readyz", None).await, (StatusCode::OK, serde_json::json!({ "status": "ready" })));
+}
+
+#[tokio::test]
+async fn snapshot_store_is_not_ready_without_its_directory() {
+    let dir = std::env::temp_dir().join(format!("syndica-readyz-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let store = SnapshotMovieStore::open(dir.join("movies.json")).await.unwrap();
+    let app = build_router(Arc::new(store), Settings::default());
+    assert_eq!(send(&app, "GET", "/readyz", None).await.0, StatusCode::OK);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+    let (status, body) = send(&app, "GET", "/readyz", None).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(body["error"]["code"], "unavailable");
+    assert_eq!(send(&app, "GET", "/healthz", None).await.0, StatusCode::OK);
+}
@@ -0,0 +1,59 @@
+mod common;
+
+use axum::{body::Body, http::{Request, StatusCode}, Router};
+use http_body_util::BodyExt;
+use serde_json::{json, Value};
+use syndica_rust::test_app;
+use tower::ServiceExt;
+use common::{json, send_request};
+
+// Status, whether the response was a replay, and the body.
+async fn post(app: &Router, uri: &str, key: &str, body: Value) -> (StatusCode, bool, Value) {
+    let response = send_request(app, "POST", uri, &[("idempotency-key", key)], Some(body)).await;
+    let replayed = response.headers().get("idempotent-replayed").is_some_and(|value| value == "true");
+    (response.status(), replayed, json(response).await)
+}
+
+#[tokio::test]
+async fn retries_replay_the_first_response() {
+    let app = test_app();
+    let movie = json!({ "name": "Alien", "year": 1979, "was_good": true });
+
+    // The server picks the id, so running the insert twice would add a second movie.
+    let (status, replayed, first) = post(&app, "/movie", "retry-1", movie.clone()).await;
+    assert_eq!((status, replayed), (StatusCode::CREATED, false));
+    let (status, replayed, second) = post(&app, "/movie", "retry-1", movie.clone()).await;
+    assert_eq!((status, replayed), (StatusCode::CREATED, true));
+    assert_eq!(first, second);
+
+    let page = app.clone().oneshot(Request::get("/movies").body(Body::empty()).unwrap()).await.unwrap();
+    let page: Value = serde_json::from_slice(&page.into_body().collect().await.unwrap().to_bytes()).unwrap();
+    assert_eq!(page["total"], 1);
+
+    // A different key is a different request.
+    let (status, replayed, _) = post(&app, "/movie", "retry-2", movie).await;
+    assert_eq!((status, replayed), (StatusCode::CREATED, false));
+}
+
+#[tokio::test]
+async fn keys_belong_to_one_request() {
+    let app = test_app();
+    let movie = json!({ "id": "alien", "name": "Alien", "year": 1979, "was_good": true });
+    post(&app, "/movie", "reuse-1", movie.clone()).await;
+
+    let (status, _, error) = post(&app, "/movie", "reuse-1", json!({ "id": "aliens", "name": "Aliens", "year": 1986, "was_good": true })).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(error["error"]["code"], "idempotency_key_reused");
+    let (status, _, error) = post(&app, "/movie?upsert=true", "reuse-1", movie.clone()).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(error["error"]["code"], "idempotency_key_reused");
+
+    // Client errors are replayed too, but nothing is recorded for a key that isn't valid.
+    let (status, replayed, _) = post(&app, "/movie", "reuse-2", movie.clone()).await;
+    assert_eq!((status, replayed), (StatusCode::CONFLICT, false));
+    let (status, replayed, _) = post(&app, "/movie", "reuse-2", movie.clone()).await;
+    assert_eq!((status, replayed), (StatusCode::CONFLICT, true));
+    let (status, _, error) = post(&app, "/movie", "not a key", movie).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(error["error"]["code"], "bad_request");
+}
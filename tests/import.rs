@@ -0,0 +1,136 @@
+mod common;
+
+use axum::{body::Body, http::{Request, StatusCode}, Router};
+use http_body_util::BodyExt;
+use serde_json::Value;
+use syndica_rust::test_app;
+use tower::ServiceExt;
+use common::send;
+
+async fn import(app: &Router, uri: &str, body: Body) -> (StatusCode, Value) {
+    let request = Request::post(uri).header("content-type", "text/csv").body(body).unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+#[tokio::test]
+async fn rows_are_imported_across_chunk_boundaries() {
+    let app = test_app();
+    let csv = "name,year,id,was_good\r\n\
+        Alien,1979,alien,true\r\n\
+        \"Crouching Tiger, Hidden Dragon\",2000,tiger,TRUE\r\n\
+        \"The \"\"Room\"\"\",2003,room,false\r\n\
+        \r\n\
+        Heat,1995,,true\r\n\
+        Cats,twenty,cats,false\r\n\
+        Alien,1979,alien,true\r\n\
+        \"Multi\nline\",2001,multi,true\r\n\
+        Short,2001\r\n\
+        Amélie,2001,amelie,true";
+    // Seven bytes at a time, so records, quotes and the é all get split between chunks.
+    let chunks: Vec<Result<Vec<u8>, std::io::Error>> = csv.as_bytes().chunks(7).map(|chunk| Ok(chunk.to_vec())).collect();
+    let (status, report) = import(&app, "/movies/import?format=csv", Body::from_stream(futures_util::stream::iter(chunks))).await;
+    assert_eq!(status, StatusCode::OK, "{}", report);
+    assert_eq!((report["inserted"].as_u64(), report["rejected"].as_u64()), (Some(6), Some(3)));
+
+    let rejections = report["rejections"].as_array().unwrap();
+    let lines: Vec<_> = rejections.iter().map(|rejection| rejection["line"].as_u64().unwrap()).collect();
+    assert_eq!(lines, [7, 8, 11]);
+    assert!(rejections[0]["error"]["message"].as_str().unwrap().contains("year"));
+    assert_eq!(rejections[1]["error"]["code"], "already_exists");
+    assert!(rejections[2]["error"]["message"].as_str().unwrap().contains("Expected 4 fields"));
+
+    assert_eq!(send(&app, "GET", "/movie/tiger", None).await.1["name"], "Crouching Tiger, Hidden Dragon");
+    assert_eq!(send(&app, "GET", "/movie/room", None).await.1["name"], "The \"Room\"");
+    assert_eq!(send(&app, "GET", "/movie/multi", None).await.1["name"], "Multi\nline");
+    assert_eq!(send(&app, "GET", "/movie/amelie", None).await.1["name"], "Amélie");
+    assert_eq!(send(&app, "GET", "/movies?name_contains=heat", None).await.1["items"][0]["id"].as_str().unwrap().len(), 36);
+}
+
+#[tokio::test]
+async fn bad_files_are_rejected_up_front() {
+    let app = test_app();
+    let (status, body) = import(&app, "/movies/import", Body::from("")).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(body["error"]["message"].as_str().unwrap().contains("header"));
+
+    let (status, body) = import(&app, "/movies/import", Body::from("name,year,rating\nAlien,1979,5\n")).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(body["error"]["message"].as_str().unwrap().contains("rating"));
+
+    let (status, body) = import(&app, "/movies/import?format=xml", Body::from("")).await;
+    assert_eq!((status, body["error"]["code"].as_str()), (StatusCode::BAD_REQUEST, Some("invalid_query")));
+
+    let request = Request::post("/movies/import").header("content-type", "multipart/form-data; boundary=x").body(Body::from("--x--")).unwrap();
+    assert_eq!(app.clone().oneshot(request).await.unwrap().status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+    let (_, report) = import(&app, "/movies/import?upsert=true", Body::from("id,name,year,was_good\nalien,Alien,1979,true\nalien,Aliens,1986,true\n")).await;
+    assert_eq!((report["inserted"].as_u64(), report["updated"].as_u64()), (Some(1), Some(1)));
+    assert_eq!(send(&app, "GET", "/movies", None).await.1["total"], 1);
+}
+
+async fn import_lines(app: &Router, uri: &str, body: Body) -> Vec<Value> {
+    let request = Request::post(uri).body(body).unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["content-type"], "application/x-ndjson");
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    std::str::from_utf8(&body).unwrap().lines().map(|line| serde_json::from_str(line).unwrap()).collect()
+}
+
+#[tokio::test]
+async fn json_and_ndjson_are_imported_as_they_stream_in() {
+    let app = test_app();
+    let ndjson = "{\"id\":\"alien\",\"name\":\"Alien\",\"year\":1979,\"was_good\":true}\r\n\
+        \n\
+        {\"id\":\"heat\",\"name\":\"Heat, \\\"the\\\" movie\",\"year\":1995,\"was_good\":true}\n\
+        {\"id\":\"cats\",\"name\":\"Cats\",\"year\":\"twenty\",\"was_good\":false}\n\
+        not json\n\
+        {\"id\":\"amelie\",\"name\":\"Amélie\",\"year\":2001,\"was_good\":true}";
+    let chunks: Vec<Result<Vec<u8>, std::io::Error>> = ndjson.as_bytes().chunks(7).map(|chunk| Ok(chunk.to_vec())).collect();
+    let lines = import_lines(&app, "/movies/import?format=ndjson", Body::from_stream(futures_util::stream::iter(chunks))).await;
+    assert_eq!(lines.len(), 1);
+    let report = &lines[0]["done"];
+    assert_eq!((report["inserted"].as_u64(), report["rejected"].as_u64()), (Some(3), Some(2)));
+    let rejected: Vec<_> = report["rejections"].as_array().unwrap().iter().map(|rejection| rejection["line"].as_u64().unwrap()).collect();
+    assert_eq!(rejected, [4, 5]);
+    assert!(lines[0].get("error").is_none());
+    assert_eq!(send(&app, "GET", "/movie/heat", None).await.1["name"], "Heat, \"the\" movie");
+    assert_eq!(send(&app, "GET", "/movie/amelie", None).await.1["name"], "Amélie");
+
+    // Commas and brackets inside the records don't split them.
+    let array = "[\n  {\"id\": \"ran\", \"name\": \"Ran, [1985]\", \"year\": 1985, \"was_good\": true, \"genres\": [\"war\", \"drama\"]},\n  {\"id\": \"alien\", \"name\": \"Alien\", \"year\": 1979, \"was_good\": true},\n  42\n]\n";
+    let chunks: Vec<Result<Vec<u8>, std::io::Error>> = array.as_bytes().chunks(5).map(|chunk| Ok(chunk.to_vec())).collect();
+    let lines = import_lines(&app, "/movies/import?format=json&upsert=true", Body::from_stream(futures_util::stream::iter(chunks))).await;
+    let report = &lines[0]["done"];
+    assert_eq!((report["inserted"].as_u64(), report["updated"].as_u64(), report["rejected"].as_u64()), (Some(1), Some(1), Some(1)));
+    assert_eq!(report["rejections"][0]["line"], 4);
+    assert_eq!(send(&app, "GET", "/movie/ran", None).await.1["genres"], serde_json::json!(["war", "drama"]));
+}
+
+#[tokio::test]
+async fn json_imports_report_progress_and_where_they_broke_off() {
+    let app = test_app();
+    let movies: Vec<String> = (0..2500).map(|i| format!("{{\"id\":\"movie-{}\",\"name\":\"Movie {}\",\"year\":2000,\"was_good\":true}}", i, i)).collect();
+    let lines = import_lines(&app, "/movies/import?format=json", Body::from(format!("[{}]", movies.join(",")))).await;
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[0]["progress"]["inserted"], 1000);
+    assert_eq!(lines[1]["progress"]["inserted"], 2000);
+    assert_eq!(lines[2]["done"]["inserted"], 2500);
+    assert_eq!(send(&app, "GET", "/movies", None).await.1["total"], 2500);
+
+    // The records before the break are kept.
+    let lines = import_lines(&app, "/movies/import?format=json", Body::from("[{\"name\":\"Heat\",\"year\":1995,\"was_good\":true}, {\"name\": ")).await;
+    assert_eq!(lines[0]["done"]["inserted"], 1);
+    assert_eq!(lines[0]["error"]["code"], "bad_request");
+    assert!(lines[0]["error"]["message"].as_str().unwrap().contains("never closed"));
+
+    let lines = import_lines(&app, "/movies/import?format=json", Body::from("{\"name\":\"Heat\"}")).await;
+    assert!(lines[0]["error"]["message"].as_str().unwrap().contains("expected a JSON array"));
+    let lines = import_lines(&app, "/movies/import?format=json", Body::from("[1,]")).await;
+    assert!(lines[0]["error"]["message"].as_str().unwrap().contains("expected a record"));
+    let lines = import_lines(&app, "/movies/import?format=json", Body::from(" [ ] ")).await;
+    assert_eq!((lines[0]["done"]["inserted"].as_u64(), lines[0].get("error")), (Some(0), None));
+}
@@ -0,0 +1,134 @@
+mod common;
+
+use axum::{body::Body, http::{Request, StatusCode}, Router};
+use serde_json::{json, Value};
+use tower::ServiceExt;
+use common::{seeded_app, send};
+
+fn catalog() -> Vec<Value> {
+    let movies = [
+        ("fight-club", "Fight Club", 1999, true),
+        ("inception", "Inception", 2010, true),
+        ("matrix", "The Matrix", 1999, true),
+        ("matrix-reloaded", "The Matrix Reloaded", 2003, false),
+        ("matrix-resurrections", "The Matrix Resurrections", 2021, false),
+        ("phantom-menace", "Star Wars: Episode I - The Phantom Menace", 1999, false),
+    ];
+    movies.into_iter().map(|(id, name, year, was_good)| json!({ "id": id, "name": name, "year": year, "was_good": was_good })).collect()
+}
+
+async fn list(app: &Router, query: &str) -> Value {
+    let (status, page) = send(app, "GET", &format!("/movies?{query}"), None).await;
+    assert_eq!(status, StatusCode::OK);
+    page
+}
+
+async fn list_ids(app: &Router, query: &str) -> Vec<String> { 
+    let page = list(app, query).await;
+    assert_eq!(page["total"].as_u64().unwrap() as usize, page["items"].as_array().unwrap().len());
+    page["items"].as_array().unwrap().iter()
+        .map(|movie| movie["id"].as_str().unwrap().to_string())
+        .collect()
+}
+
+#[tokio::test]
+async fn no_filters_returns_everything() { 
+    let app = seeded_app(catalog()).await;
+    assert_eq!(list_ids(&app, "").await.len(), 6);
+}
+
+#[tokio::test]
+async fn filter_by_year() { 
+    let app = seeded_app(catalog()).await;
+    assert_eq!(list_ids(&app, "year=1999").await, ["fight-club", "matrix", "phantom-menace"]);
+}
+
+#[tokio::test]
+async fn filter_by_was_good() { 
+    let app = seeded_app(catalog()).await;
+    assert_eq!(list_ids(&app, "was_good=true").await, ["fight-club", "inception", "matrix"]);
+}
+
+#[tokio::test]
+async fn filter_by_name_is_case_insensitive() { 
+    let app = seeded_app(catalog()).await;
+    assert_eq!(list_ids(&app, "name_contains=MATRIX").await, ["matrix", "matrix-reloaded", "matrix-resurrections"]);
+}
+
+#[tokio::test]
+async fn filter_by_year_and_was_good() { 
+    let app = seeded_app(catalog()).await;
+    assert_eq!(list_ids(&app, "year=1999&was_good=false").await, ["phantom-menace"]);
+}
+
+#[tokio::test]
+async fn filter_by_year_and_name() { 
+    let app = seeded_app(catalog()).await;
+    assert_eq!(list_ids(&app, "year=1999&name_contains=matrix").await, ["matrix"]);
+}
+
+#[tokio::test]
+async fn filter_by_was_good_and_name() { 
+    let app = seeded_app(catalog()).await;
+    assert_eq!(list_ids(&app, "was_good=false&name_contains=matrix").await, ["matrix-reloaded", "matrix-resurrections"]);
+}
+
+#[tokio::test]
+async fn filter_by_all_three() { 
+    let app = seeded_app(catalog()).await;
+    assert_eq!(list_ids(&app, "year=2003&was_good=false&name_contains=reloaded").await, ["matrix-reloaded"]);
+    assert!(list_ids(&app, "year=2003&was_good=true&name_contains=reloaded").await.is_empty());
+}
+
+#[tokio::test]
+async fn next_link_keeps_filters() { 
+    let app = seeded_app(catalog()).await;
+    let page = list(&app, "name_contains=the&limit=2").await;
+    assert_eq!(page["total"], 4);
+    assert_eq!(page["items"].as_array().unwrap().len(), 2);
+    let next = page["next"].as_str().unwrap();
+    assert_eq!(next, "/v1/movies?limit=2&offset=2&name_contains=the");
+
+    let page = list(&app, next.trim_start_matches("/v1/movies?")).await;
+    assert_eq!(page["items"].as_array().unwrap().len(), 2);
+    assert!(page["next"].is_null());
+}
+
+#[tokio::test]
+async fn filter_by_year_range() {
+    let app = seeded_app(catalog()).await;
+    assert_eq!(list_ids(&app, "year_gte=2003").await, ["inception", "matrix-reloaded", "matrix-resurrections"]);
+    assert_eq!(list_ids(&app, "year_gte=2000&year_lte=2010").await, ["inception", "matrix-reloaded"]);
+}
+
+#[tokio::test]
+async fn sort_by_several_keys() {
+    let app = seeded_app(catalog()).await;
+    assert_eq!(list_ids(&app, "sort=year:desc,name:asc").await,
+        ["matrix-resurrections", "inception", "matrix-reloaded", "fight-club", "phantom-menace", "matrix"]);
+    // Direction defaults to ascending, and ids break the ties left.
+    assert_eq!(list_ids(&app, "sort=year").await,
+        ["fight-club", "matrix", "phantom-menace", "matrix-reloaded", "inception", "matrix-resurrections"]);
+    assert_eq!(list_ids(&app, "sort=was_good:desc,year:asc&year=1999").await, ["fight-club", "matrix", "phantom-menace"]);
+}
+
+#[tokio::test]
+async fn next_link_keeps_sort() {
+    let app = seeded_app(catalog()).await;
+    let page = list(&app, "sort=name:desc&limit=4").await;
+    let next = page["next"].as_str().unwrap();
+    assert_eq!(next, "/v1/movies?limit=4&offset=4&sort=name%3Adesc");
+    let page = list(&app, next.trim_start_matches("/v1/movies?")).await;
+    let ids: Vec<&str> = page["items"].as_array().unwrap().iter().map(|movie| movie["id"].as_str().unwrap()).collect();
+    assert_eq!(ids, ["inception", "fight-club"]);
+}
+
+#[tokio::test]
+async fn bad_sort_keys_are_rejected() {
+    let app = seeded_app(catalog()).await;
+    for sort in ["rating", "year:sideways", "year,year:desc", ""] {
+        let request = Request::get(format!("/movies?sort={sort}")).body(Body::empty()).unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{sort}");
+    }
+}
@@ -0,0 +1,66 @@
+use std::future::pending;
+use axum::{body::Bytes, http::{Request, StatusCode, Version}};
+use http_body_util::Empty;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use syndica_rust::{listener::{HttpConfig, ListenAddr, Listener}, test_app};
+use tokio::{io::{AsyncReadExt, AsyncWriteExt}, net::{TcpStream, UnixStream}};
+
+#[tokio::test]
+async fn serves_over_a_unix_socket() {
+    let path = std::env::temp_dir().join(format!("syndica-listener-{}.sock", std::process::id()));
+    let address = ListenAddr::Unix(path.clone());
+    // A socket file left over from a previous run doesn't get in the way.
+    drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+    let listener = Listener::bind(&address).await.unwrap();
+    tokio::spawn(listener.serve(test_app(), HttpConfig::default(), pending()));
+
+    let mut stream = UnixStream::connect(&path).await.unwrap();
+    stream.write_all(b"GET /movies HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+
+    // Only one server gets the path at a time.
+    assert!(Listener::bind(&address).await.is_err());
+    std::fs::remove_file(&path).unwrap();
+}
+
+async fn serve_tcp(http: HttpConfig) -> std::net::SocketAddr {
+    let listener = Listener::bind(&"127.0.0.1:0".parse().unwrap()).await.unwrap();
+    let Listener::Tcp(tcp) = &listener else { unreachable!() };
+    let address = tcp.local_addr().unwrap();
+    tokio::spawn(listener.serve(test_app(), http, pending()));
+    address
+}
+
+#[tokio::test]
+async fn speaks_http2_with_prior_knowledge() {
+    let address = serve_tcp(HttpConfig::default()).await;
+    let stream = TokioIo::new(TcpStream::connect(address).await.unwrap());
+    let (mut sender, connection) = hyper::client::conn::http2::handshake(TokioExecutor::new(), stream).await.unwrap();
+    tokio::spawn(connection);
+    let request = Request::builder().uri(format!("http://{}/movies", address)).body(Empty::<Bytes>::new()).unwrap();
+    let response = sender.send_request(request).await.unwrap();
+    assert_eq!(response.version(), Version::HTTP_2);
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // Without HTTP/2 the preface is just a bad HTTP/1 request.
+    let address = serve_tcp(HttpConfig { http2: false, ..HttpConfig::default() }).await;
+    let stream = TokioIo::new(TcpStream::connect(address).await.unwrap());
+    let (mut sender, connection) = hyper::client::conn::http2::handshake(TokioExecutor::new(), stream).await.unwrap();
+    tokio::spawn(connection);
+    let request = Request::builder().uri(format!("http://{}/movies", address)).body(Empty::<Bytes>::new()).unwrap();
+    assert!(sender.send_request(request).await.is_err());
+}
+
+#[tokio::test]
+async fn closes_connections_without_keep_alive() {
+    let address = serve_tcp(HttpConfig { keep_alive: false, ..HttpConfig::default() }).await;
+    let mut stream = TcpStream::connect(address).await.unwrap();
+    stream.write_all(b"GET /movies HTTP/1.1\r\nHost: localhost\r\n\r\n").await.unwrap();
+    // Reading to the end only finishes because the server hangs up.
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+    assert!(response.to_lowercase().contains("connection: close"), "{}", response);
+}
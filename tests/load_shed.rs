@@ -0,0 +1,40 @@
+use std::convert::Infallible;
+use axum::{body::{Body, Bytes}, http::{Request, StatusCode}, Router};
+use futures_util::stream;
+use syndica_rust::{settings::Settings, test_app_with};
+use tokio::sync::mpsc;
+use tower::ServiceExt;
+
+async fn get(app: &Router, uri: &str) -> StatusCode {
+    app.clone().oneshot(Request::get(uri).body(Body::empty()).unwrap()).await.unwrap().status()
+}
+
+#[tokio::test]
+async fn requests_past_the_limit_are_shed() {
+    let settings = Settings::default();
+    settings.set_max_in_flight(1);
+    let app = test_app_with(settings.clone());
+
+    // An import whose body hasn't finished arriving holds the only slot.
+    let (rows, receiver) = mpsc::channel::<Bytes>(1);
+    let body = Body::from_stream(stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|row| (Ok::<_, Infallible>(row), receiver))
+    }));
+    let import = tokio::spawn({
+        let app = app.clone();
+        async move { app.oneshot(Request::post("/movies/import").body(body).unwrap()).await.unwrap().status() }
+    });
+    rows.send(Bytes::from("id,name,year,was_good\n")).await.unwrap();
+    while settings.in_flight() == 0 {
+        tokio::task::yield_now().await;
+    }
+
+    assert_eq!(get(&app, "/movies").await, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(get(&app, "/healthz").await, StatusCode::OK);
+
+    rows.send(Bytes::from("alien,Alien,1979,true\n")).await.unwrap();
+    drop(rows);
+    assert_eq!(import.await.unwrap(), StatusCode::OK);
+    assert_eq!(settings.in_flight(), 0);
+    assert_eq!(get(&app, "/movies").await, StatusCode::OK);
+}
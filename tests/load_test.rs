@@ -0,0 +1,53 @@
+use std::{future::pending, time::Duration};
+use syndica_rust::{
+    config::ConfigError,
+    listener::{HttpConfig, Listener},
+    load_test::{self, LoadTestConfig, Operation},
+    test_app,
+};
+
+fn args(args: &[&str]) -> Result<LoadTestConfig, ConfigError> {
+    LoadTestConfig::from_args(args.iter().map(|arg| arg.to_string()))
+}
+
+#[test]
+fn reads_its_flags() {
+    let config = args(&["--target", "http://localhost:1234/", "--rps=500", "--duration-secs", "5", "--api-key", "secret"]).unwrap();
+    assert_eq!(config, LoadTestConfig {
+        target: "http://localhost:1234".to_string(),
+        rps: 500,
+        duration: Duration::from_secs(5),
+        max_in_flight: 1000,
+        api_key: Some("secret".to_string()),
+    });
+    assert_eq!(args(&["--target", "http://localhost:1234"]).unwrap().rps, 100);
+
+    assert!(matches!(args(&["--help"]), Err(ConfigError::Help(_))));
+    for bad in [&["--rps", "500"][..], &["--target", "https://localhost"], &["--target", "http://localhost", "--rps", "0"], &["--target", "http://localhost", "--bind-addr", "x"]] {
+        assert!(matches!(args(bad), Err(ConfigError::Invalid(_))), "{:?}", bad);
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn measures_a_running_instance() {
+    let listener = Listener::bind(&"127.0.0.1:0".parse().unwrap()).await.unwrap();
+    let Listener::Tcp(tcp) = &listener else { unreachable!() };
+    let address = tcp.local_addr().unwrap();
+    tokio::spawn(listener.serve(test_app(), HttpConfig::default(), pending()));
+
+    let config = args(&["--target", &format!("http://{}", address), "--rps", "100", "--duration-secs", "1"]).unwrap();
+    let report = load_test::run(&config).await;
+    assert_eq!((report.total.requests, report.dropped), (100, 0));
+    assert_eq!(report.total.succeeded, 100, "{}", report);
+    let operations: Vec<Operation> = report.by_operation.iter().map(|(operation, _)| *operation).collect();
+    assert_eq!(operations, [Operation::Create, Operation::Get, Operation::List]);
+    // A fifth are creates, and a few more at the start while there's nothing to get yet.
+    assert!(report.by_operation[0].1.requests >= 20);
+    assert!(report.total.p50 <= report.total.p90 && report.total.p90 <= report.total.p99 && report.total.p99 <= report.total.max);
+    assert!(report.to_string().contains("total"));
+
+    // Nothing listening: every request fails, and is still counted.
+    let config = args(&["--target", "http://127.0.0.1:1", "--rps", "10", "--duration-secs", "1"]).unwrap();
+    let report = load_test::run(&config).await;
+    assert_eq!((report.total.requests, report.total.failed), (10, 10));
+}
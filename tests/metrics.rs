@@ -0,0 +1,32 @@
+use axum::{body::Body, http::{Request, StatusCode}, Router};
+use http_body_util::BodyExt;
+use syndica_rust::test_app;
+use tower::ServiceExt;
+
+async fn send(app: &Router, request: Request<Body>) -> (StatusCode, String) {
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, String::from_utf8(body.to_vec()).unwrap())
+}
+
+#[tokio::test]
+async fn requests_and_movies_are_counted() {
+    let app = test_app();
+    let movie = serde_json::json!({ "id": "alien", "name": "Alien", "year": 1979, "was_good": true });
+    let post = Request::post("/movie").header("content-type", "application/json").body(Body::from(movie.to_string())).unwrap();
+    assert_eq!(send(&app, post).await.0, StatusCode::CREATED);
+    assert_eq!(send(&app, Request::get("/movie/alien").body(Body::empty()).unwrap()).await.0, StatusCode::OK);
+    assert_eq!(send(&app, Request::get("/movie/nope").body(Body::empty()).unwrap()).await.0, StatusCode::NOT_FOUND);
+
+    let (status, metrics) = send(&app, Request::get("/metrics").body(Body::empty()).unwrap()).await;
+    assert_eq!(status, StatusCode::OK);
+    let lines: Vec<&str> = metrics.lines().collect();
+    assert!(lines.contains(&r#"http_requests_total{method="POST",route="/movie",status="201"} 1"#));
+    assert!(lines.contains(&r#"http_requests_total{method="GET",route="/movie/{id}",status="200"} 1"#));
+    assert!(lines.contains(&r#"http_requests_total{method="GET",route="/movie/{id}",status="404"} 1"#));
+    assert!(lines.contains(&r#"http_request_duration_seconds_count{method="GET",route="/movie/{id}"} 2"#));
+    assert!(lines.contains(&r#"http_request_duration_seconds_bucket{method="GET",route="/movie/{id}",le="+Inf"} 2"#));
+    assert!(lines.contains(&"movies_stored 1"));
+    assert!(lines.iter().any(|line| line.starts_with(r#"movies_lock_wait_seconds_count{lock="movies_write"}"#)));
+}
@@ -0,0 +1,89 @@
+mod common;
+
+use axum::http::StatusCode;
+use serde_json::{json, Value};
+use syndica_rust::test_app;
+use common::send;
+
+fn alien() -> Value {
+    json!({
+        "id": "alien",
+        "name": "Alien",
+        "year": 1979,
+        "was_good": true,
+        "genres": ["Horror", "Science fiction"],
+        "director": "Ridley Scott",
+        "runtime_minutes": 117,
+        "synopsis": "The crew of a towing spaceship answers a distress call.",
+    })
+}
+
+#[tokio::test]
+async fn movies_without_details_look_as_before() {
+    let app = test_app();
+    let (status, movie) = send(&app, "POST", "/v1/movie", Some(json!({ "id": "heat", "name": "Heat", "year": 1995, "was_good": true }))).await;
+    assert_eq!(status, StatusCode::CREATED);
+    // Besides the timestamps every movie has now.
+    let mut movie = movie;
+    assert!(movie.as_object_mut().unwrap().remove("created_at").is_some() && movie.as_object_mut().unwrap().remove("updated_at").is_some());
+    assert_eq!(movie, json!({ "id": "heat", "name": "Heat", "year": 1995, "was_good": true, "version": 1 }));
+}
+
+#[tokio::test]
+async fn details_are_stored_and_patched() {
+    let app = test_app();
+    let (status, movie) = send(&app, "POST", "/v1/movie", Some(alien())).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(movie["genres"], json!(["Horror", "Science fiction"]));
+    assert_eq!(movie["runtime_minutes"], 117);
+
+    // null removes a detail, and leaving one out keeps it.
+    let (status, movie) = send(&app, "PATCH", "/v1/movie/alien", Some(json!({ "synopsis": null, "genres": null, "runtime_minutes": 116 }))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(movie.get("synopsis").is_none());
+    assert!(movie.get("genres").is_none());
+    assert_eq!(movie["runtime_minutes"], 116);
+    assert_eq!(movie["director"], "Ridley Scott");
+
+    // The required fields still can't be removed.
+    let (status, _) = send(&app, "PATCH", "/v1/movie/alien", Some(json!({ "name": null }))).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[tokio::test]
+async fn details_are_validated() {
+    let app = test_app();
+    let mut movie = alien();
+    movie["genres"] = json!(["Horror", "horror"]);
+    movie["director"] = json!(" ");
+    movie["runtime_minutes"] = json!(0);
+    movie["synopsis"] = json!("x".repeat(5001));
+    movie["poster_url"] = json!("ftp://posters.example.com/alien.jpg");
+    let (status, body) = send(&app, "POST", "/v1/movie", Some(movie)).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let fields: Vec<&str> = body["error"]["details"]["fields"].as_array().unwrap().iter().map(|error| error["field"].as_str().unwrap()).collect();
+    assert_eq!(fields, ["genres", "director", "runtime_minutes", "synopsis", "poster_url"]);
+
+    send(&app, "POST", "/v1/movie", Some(alien())).await;
+    let (status, _) = send(&app, "PATCH", "/v1/movie/alien", Some(json!({ "genres": [""] }))).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[tokio::test]
+async fn filter_by_details() {
+    let app = test_app();
+    send(&app, "POST", "/v1/movie", Some(alien())).await;
+    send(&app, "POST", "/v1/movie", Some(json!({ "id": "blade-runner", "name": "Blade Runner", "year": 1982, "was_good": true,
+        "genres": ["Science fiction"], "director": "Ridley Scott", "runtime_minutes": 117 }))).await;
+    send(&app, "POST", "/v1/movie", Some(json!({ "id": "heat", "name": "Heat", "year": 1995, "was_good": true }))).await;
+
+    let ids = |page: &Value| page["items"].as_array().unwrap().iter().map(|movie| movie["id"].as_str().unwrap().to_string()).collect::<Vec<_>>();
+    let (_, page) = send(&app, "GET", "/v1/movies?genre=horror", None).await;
+    assert_eq!(ids(&page), ["alien"]);
+    let (_, page) = send(&app, "GET", "/v1/movies?director=ridley%20scott&genre=Science%20Fiction", None).await;
+    assert_eq!(ids(&page), ["alien", "blade-runner"]);
+    let (_, page) = send(&app, "GET", "/v1/movies?runtime_gte=100&runtime_lte=117", None).await;
+    assert_eq!(ids(&page), ["alien", "blade-runner"]);
+    let (_, page) = send(&app, "GET", "/v1/movies?runtime_lte=200", None).await;
+    assert!(!ids(&page).contains(&"heat".to_string()));
+}
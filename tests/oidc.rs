@@ -0,0 +1,122 @@
+use std::{collections::HashMap, sync::{Arc, Mutex}};
+use axum::{body::Body, extract::State, http::{Request, Response, StatusCode}, routing::{get, post}, Form, Json, Router};
+use http_body_util::BodyExt;
+use serde_json::{json, Value};
+use syndica_rust::{auth, crypto, oidc::{self, OidcConfig}, settings::Settings, test_app_with};
+use tower::ServiceExt;
+
+// SHA-256 of "secret".
+const SECRET_HASH: &str = "2bb80d537b1da3e38bd30361aa855686bde0eacd7162fef6a25fe97bf527a25b";
+const CLIENT_SECRET: &str = "client secret shared with the provider";
+
+// What the fake provider needs from the login that's in progress: the nonce to put in the ID token and the PKCE
+// challenge the code_verifier has to match.
+#[derive(Default)]
+struct Provider {
+    issuer: String,
+    nonce: String,
+    challenge: String,
+}
+
+fn sign(claims: Value) -> String {
+    let signed = format!("{}.{}", crypto::base64url_encode(br#"{"alg":"HS256","typ":"JWT"}"#), crypto::base64url_encode(claims.to_string().as_bytes()));
+    format!("{}.{}", signed, crypto::base64url_encode(&crypto::hmac_sha256(CLIENT_SECRET.as_bytes(), signed.as_bytes())))
+}
+
+async fn token(State(provider): State<Arc<Mutex<Provider>>>, Form(form): Form<HashMap<String, String>>) -> Result<Json<Value>, StatusCode> {
+    let provider = provider.lock().unwrap();
+    let verifier = crypto::base64url_encode(&crypto::sha256(form["code_verifier"].as_bytes()));
+    if form["code"] != "good-code" || form["client_secret"] != CLIENT_SECRET || verifier != provider.challenge {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let claims = json!({ "iss": provider.issuer, "aud": "movies", "sub": "alice", "roles": ["admin"], "nonce": provider.nonce, "exp": 4102444800u64 });
+    Ok(Json(json!({ "access_token": "unused", "token_type": "Bearer", "id_token": sign(claims) })))
+}
+
+async fn spawn_provider() -> (String, Arc<Mutex<Provider>>) {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let issuer = format!("http://{}", listener.local_addr().unwrap());
+    let provider = Arc::new(Mutex::new(Provider { issuer: issuer.clone(), ..Provider::default() }));
+    let discovery = json!({ "issuer": issuer, "authorization_endpoint": format!("{}/authorize", issuer), "token_endpoint": format!("{}/token", issuer) });
+    let app = Router::new()
+        .route("/.well-known/openid-configuration", get(move || async move { Json(discovery) }))
+        .route("/token", post(token))
+        .with_state(provider.clone());
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    (issuer, provider)
+}
+
+async fn send(app: &Router, method: &str, uri: &str, cookie: Option<&str>) -> Response<Body> {
+    let mut request = Request::builder().method(method).uri(uri);
+    if let Some(cookie) = cookie {
+        request = request.header("cookie", cookie);
+    }
+    app.clone().oneshot(request.body(Body::empty()).unwrap()).await.unwrap()
+}
+
+async fn session(app: &Router, cookie: Option<&str>) -> Value {
+    let response = send(app, "GET", "/admin/session", cookie).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    serde_json::from_slice(&response.into_body().collect().await.unwrap().to_bytes()).unwrap()
+}
+
+fn location(response: &Response<Body>) -> String {
+    response.headers()["location"].to_str().unwrap().to_string()
+}
+
+// The login settings are process-wide, so everything that depends on them is in this one test.
+#[tokio::test]
+async fn login_gives_a_session_for_the_admin_routes() {
+    let settings = Settings::default();
+    let app = test_app_with(settings.clone());
+    assert_eq!(send(&app, "GET", "/admin/login", None).await.status(), StatusCode::NOT_FOUND);
+
+    let (issuer, provider) = spawn_provider().await;
+    settings.set_api_keys(vec![auth::parse_key_hash(SECRET_HASH).unwrap()]);
+    oidc::set_config(Some(OidcConfig {
+        issuer: issuer.clone(),
+        client_id: "movies".to_string(),
+        client_secret: CLIENT_SECRET.to_string(),
+        redirect_url: "http://movies.example.com/admin/callback".to_string(),
+    }));
+    assert_eq!(session(&app, None).await, json!({ "subject": null, "role": "reader", "authenticated": false }));
+    assert_eq!(send(&app, "GET", "/admin/login?return_to=//elsewhere.example.com", None).await.status(), StatusCode::BAD_REQUEST);
+
+    let response = send(&app, "GET", "/admin/login?return_to=/admin/session", None).await;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    let (authorize, query) = location(&response).split_once('?').map(|(url, query)| (url.to_string(), query.to_string())).unwrap();
+    assert_eq!(authorize, format!("{}/authorize", issuer));
+    let query: HashMap<String, String> = serde_urlencoded::from_str(&query).unwrap();
+    assert_eq!(query["client_id"], "movies");
+    assert_eq!(query["code_challenge_method"], "S256");
+    {
+        let mut provider = provider.lock().unwrap();
+        provider.nonce = query["nonce"].clone();
+        provider.challenge = query["code_challenge"].clone();
+    }
+
+    let callback = format!("/admin/callback?code=good-code&state={}", query["state"]);
+    let response = send(&app, "GET", &callback, None).await;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER, "{:?}", response.into_body().collect().await.unwrap().to_bytes());
+    assert_eq!(location(&response), "/admin/session");
+    let set_cookie = response.headers()["set-cookie"].to_str().unwrap().to_string();
+    assert!(set_cookie.contains("HttpOnly") && set_cookie.contains("Path=/admin"), "{}", set_cookie);
+    let cookie = set_cookie.split(';').next().unwrap().to_string();
+    // A state can only be used once.
+    assert_eq!(send(&app, "GET", &callback, None).await.status(), StatusCode::BAD_REQUEST);
+
+    assert_eq!(session(&app, Some(&cookie)).await, json!({ "subject": "alice", "role": "admin", "authenticated": true }));
+    // The session is only for the admin routes.
+    assert_eq!(send(&app, "DELETE", "/movie/alien", Some(&cookie)).await.status(), StatusCode::UNAUTHORIZED);
+
+    // A bad code is refused by the provider, and that's passed on.
+    let response = send(&app, "GET", "/admin/login", None).await;
+    let query: HashMap<String, String> = serde_urlencoded::from_str(location(&response).split_once('?').unwrap().1).unwrap();
+    let callback = format!("/admin/callback?code=bad-code&state={}", query["state"]);
+    assert_eq!(send(&app, "GET", &callback, None).await.status(), StatusCode::UNAUTHORIZED);
+
+    assert_eq!(send(&app, "POST", "/admin/logout", Some(&cookie)).await.status(), StatusCode::NO_CONTENT);
+    assert_eq!(session(&app, Some(&cookie)).await["authenticated"], false);
+
+    oidc::set_config(None);
+}
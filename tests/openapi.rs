@@ -0,0 +1,86 @@
+mod common;
+
+use std::collections::BTreeSet;
+
+use axum::{body::Body, http::{Request, StatusCode}};
+use serde_json::Value;
+use syndica_rust::{model::{Movie, Review}, test_app};
+use tower::ServiceExt;
+use common::send;
+
+fn keys(value: &Value) -> BTreeSet<String> {
+    value.as_object().unwrap().keys().cloned().collect()
+}
+
+fn strings(value: &Value) -> BTreeSet<String> {
+    value.as_array().unwrap().iter().map(|value| value.as_str().unwrap().to_string()).collect()
+}
+
+#[tokio::test]
+async fn document_and_ui_are_served() {
+    let app = test_app();
+    let (status, document) = send(&app, "GET", "/api-docs/openapi.json", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(document["openapi"], "3.0.3");
+    for path in ["/v1/movie", "/v1/movies", "/v1/movie/{id}", "/healthz", "/readyz", "/metrics"] {
+        assert!(document["paths"].get(path).is_some(), "{} is missing from the document", path);
+    }
+    let response = app.oneshot(Request::get("/swagger-ui").body(Body::empty()).unwrap()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers()["content-type"].to_str().unwrap().starts_with("text/html"));
+}
+
+#[tokio::test]
+async fn schemas_match_what_the_server_sends() {
+    let app = test_app();
+    let (_, document) = send(&app, "GET", "/api-docs/openapi.json", None).await;
+    let schemas = &document["components"]["schemas"];
+
+    let movie = Movie { id: "alien".into(), name: "Alien".into(), year: 1979, was_good: true, version: 1, ..Movie::default() };
+    assert_eq!(strings(&schemas["Movie"]["required"]), keys(&serde_json::to_value(&movie).unwrap()));
+    let mut detailed = Movie {
+        genres: vec!["Horror".into()],
+        director: Some("Ridley Scott".into()),
+        runtime_minutes: Some(117),
+        synopsis: Some("In space no one can hear you scream.".into()),
+        poster_url: Some("https://img.example.com/alien.jpg".into()),
+        tags: vec!["creature feature".into()],
+        titles: [("fr".to_string(), "Alien, le huitième passager".to_string())].into(),
+        created_at: Some("2026-10-15T00:00:00Z".into()),
+        updated_at: Some("2026-10-15T00:00:00Z".into()),
+        ..movie
+    };
+    detailed.rate("ripley".into(), 9);
+    detailed.add_review(Review { id: "1".into(), author: "ripley".into(), text: "Terrifying.".into(), created_at: "2026-10-15T00:00:00Z".into() });
+    assert_eq!(keys(&schemas["Movie"]["properties"]), keys(&serde_json::to_value(&detailed).unwrap()));
+
+    let new_movie = serde_json::json!({ "name": "Alien", "year": 1979, "was_good": true });
+    let (status, _) = send(&app, "POST", "/movie", Some(new_movie.clone())).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(strings(&schemas["NewMovie"]["required"]), keys(&new_movie));
+
+    let (status, _) = send(&app, "POST", "/movie", Some(new_movie.clone())).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let (_, page) = send(&app, "GET", "/movies?cursor=&limit=1", None).await;
+    assert_eq!(keys(&schemas["MoviePage"]["properties"]), keys(&page));
+    assert_eq!(strings(&schemas["MoviePage"]["required"]), keys(&send(&app, "GET", "/movies", None).await.1));
+
+    // Every kind of error the router can be made to return has to be in the documented enum and shape.
+    let error_schema = &schemas["Error"]["properties"]["error"];
+    let codes = strings(&error_schema["properties"]["code"]["enum"]);
+    let bad_movie = serde_json::json!({ "id": "bad id", "name": "", "year": 1, "was_good": true });
+    let errors = [
+        send(&app, "GET", "/movie/missing", None).await,
+        send(&app, "POST", "/movie", Some(serde_json::json!({ "id": "x", "name": "X", "year": 2000 }))).await,
+        send(&app, "POST", "/movie", Some(bad_movie.clone())).await,
+        send(&app, "PUT", "/movie/other", Some(bad_movie)).await,
+        send(&app, "GET", "/movies?limit=many", None).await,
+        send(&app, "GET", "/movie", None).await,
+        send(&app, "GET", "/no/such/route", None).await,
+    ];
+    for (status, body) in errors {
+        assert!(status.is_client_error());
+        assert_eq!(keys(&body["error"]), strings(&error_schema["required"]));
+        assert!(codes.contains(body["error"]["code"].as_str().unwrap()), "{} isn't documented", body["error"]["code"]);
+    }
+}
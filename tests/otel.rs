@@ -0,0 +1,57 @@
+use axum::{body::Body, http::{Request, StatusCode}};
+use syndica_rust::{otel::OtelExporter, telemetry::{self, LogFormat}, test_app};
+use tokio::{io::{AsyncReadExt, AsyncWriteExt}, net::TcpListener};
+use tower::ServiceExt;
+use tracing::level_filters::LevelFilter;
+
+// Accepts one export and hands back its body.
+async fn fake_collector() -> (String, tokio::task::JoinHandle<(String, serde_json::Value)>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let endpoint = format!("http://{}", listener.local_addr().unwrap());
+    let accepted = tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut request = Vec::new();
+        let mut chunk = [0u8; 4096];
+        let (head_len, body_len) = loop {
+            let read = stream.read(&mut chunk).await.unwrap();
+            request.extend_from_slice(&chunk[..read]);
+            let mut headers = [httparse::EMPTY_HEADER; 32];
+            let mut parsed = httparse::Request::new(&mut headers);
+            if let httparse::Status::Complete(head_len) = parsed.parse(&request).unwrap() {
+                let length = parsed.headers.iter().find(|header| header.name.eq_ignore_ascii_case("content-length")).unwrap();
+                let body_len: usize = std::str::from_utf8(length.value).unwrap().parse().unwrap();
+                if request.len() >= head_len + body_len {
+                    break (head_len, body_len);
+                }
+            }
+        };
+        let request_line = String::from_utf8_lossy(&request[..request.iter().position(|byte| *byte == b'\r').unwrap()]).to_string();
+        stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\n{}").await.unwrap();
+        (request_line, serde_json::from_slice(&request[head_len..head_len + body_len]).unwrap())
+    });
+    (endpoint, accepted)
+}
+
+#[tokio::test]
+async fn request_spans_are_exported_with_storage_children() {
+    telemetry::init(LevelFilter::INFO, LogFormat::Text);
+    let (endpoint, collector) = fake_collector().await;
+    let exporter = OtelExporter::start(&endpoint);
+
+    let app = test_app();
+    let response = app.oneshot(Request::get("/movie/missing").body(Body::empty()).unwrap()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    exporter.flush().await;
+
+    let (request_line, export) = collector.await.unwrap();
+    assert_eq!(request_line, "POST /v1/traces HTTP/1.1");
+    let spans = export["resourceSpans"][0]["scopeSpans"][0]["spans"].as_array().unwrap();
+    let request = spans.iter().find(|span| span["name"] == "GET /movie/{id}").expect("no request span");
+    let lookup = spans.iter().find(|span| span["name"] == "memory_store.get").expect("no storage span");
+    assert_eq!(request["kind"], 2);
+    assert!(request.get("parentSpanId").is_none());
+    assert_eq!(lookup["parentSpanId"], request["spanId"]);
+    assert_eq!(lookup["traceId"], request["traceId"]);
+    assert!(request["attributes"].as_array().unwrap().iter()
+        .any(|attribute| attribute["key"] == "status" && attribute["value"]["intValue"] == "404"));
+}
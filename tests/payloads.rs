@@ -0,0 +1,116 @@
+mod common;
+
+use axum::{body::Body, http::{Request, StatusCode}, Router};
+use serde_json::{json, Value};
+use tower::ServiceExt;
+use common::{movie, seeded_app};
+
+// Malformed movies, in every format and to every route that takes one, must be turned away with a 4xx and never panic
+// or be a 5xx. The fuzz target in fuzz/ tries far more of them, this runs a seeded few in every test run.
+
+const REQUESTS: [(&str, &str); 5] = [
+    ("POST", "/v1/movie"),
+    ("POST", "/v1/movie?upsert=true"),
+    ("PUT", "/v1/movie/fuzz"),
+    ("PATCH", "/v1/movie/fuzz"),
+    ("POST", "/v1/movies/batch"),
+];
+const CONTENT_TYPES: [&str; 3] = ["application/json", "application/msgpack", "application/cbor"];
+const MUTATIONS: u64 = 200;
+
+// xorshift64*, as in store_properties.rs.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Rng {
+        Rng(seed.wrapping_mul(0x9e3779b97f4a7c15) | 1)
+    }
+
+    fn below(&mut self, bound: usize) -> usize {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        (self.0.wrapping_mul(0x2545f4914f6cdd1d) >> 32) as usize % bound
+    }
+}
+
+fn encode(content_type: &str, value: &Value) -> Vec<u8> {
+    match content_type {
+        "application/msgpack" => rmp_serde::to_vec_named(value).unwrap(),
+        "application/cbor" => {
+            let mut bytes = Vec::new();
+            ciborium::into_writer(value, &mut bytes).unwrap();
+            bytes
+        },
+        _ => serde_json::to_vec(value).unwrap(),
+    }
+}
+
+// Flips, drops, repeats or cuts off a few bytes.
+fn mutate(rng: &mut Rng, mut bytes: Vec<u8>) -> Vec<u8> {
+    for _ in 0..1 + rng.below(4) {
+        if bytes.is_empty() {
+            break;
+        }
+        let at = rng.below(bytes.len());
+        match rng.below(4) {
+            0 => bytes[at] ^= 1 << rng.below(8),
+            1 => { bytes.remove(at); },
+            2 => bytes.insert(at, bytes[at]),
+            _ => bytes.truncate(at),
+        }
+    }
+    bytes
+}
+
+async fn send(app: &Router, (method, uri): (&str, &str), content_type: &str, body: Vec<u8>) -> StatusCode {
+    let request = Request::builder().method(method).uri(uri).header("content-type", content_type).body(Body::from(body)).unwrap();
+    app.clone().oneshot(request).await.unwrap().status()
+}
+
+fn assert_4xx(status: StatusCode, (method, uri): (&str, &str), content_type: &str, body: &[u8]) {
+    assert!(status.is_client_error(), "{} {} as {} was answered with {} for {:?}", method, uri, content_type, status, String::from_utf8_lossy(body));
+}
+
+#[tokio::test]
+async fn broken_payloads_are_client_errors() {
+    let app = seeded_app([movie("fuzz", "Fuzz", 2000)]).await;
+    let deep = format!("{}{}", "[".repeat(10_000), "]".repeat(10_000));
+    let bodies: [&[u8]; 10] = [
+        b"",
+        b"null",
+        b"{",
+        b"{\"name\": \"Alien\", \"year\": 1979, \"was_good\": tru",
+        b"{\"name\": \"Alien\", \"year\": 99999999999999999999, \"was_good\": true}",
+        b"{\"name\": \"Alien\", \"year\": -1, \"was_good\": true}",
+        b"{\"name\": \"Alien\", \"year\": 1979.5, \"was_good\": true}",
+        b"{\"name\": \"\\ud800\", \"year\": 1979, \"was_good\": true}",
+        b"\xff\xfe\x00\x01",
+        deep.as_bytes(),
+    ];
+    for request in REQUESTS {
+        for content_type in CONTENT_TYPES {
+            for body in bodies {
+                assert_4xx(send(&app, request, content_type, body.to_vec()).await, request, content_type, body);
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn mangled_movies_never_fail_the_server() {
+    let app = seeded_app([movie("fuzz", "Fuzz", 2000)]).await;
+    let movie = json!({ "id": "alien", "name": "Alien", "year": 1979, "was_good": true, "genres": ["Horror"], "tags": ["space"], "runtime_minutes": 117 });
+    let mut rng = Rng::new(89);
+    for request in REQUESTS {
+        let valid = if request.1.ends_with("batch") { json!([movie]) } else { movie.clone() };
+        for content_type in CONTENT_TYPES {
+            for _ in 0..MUTATIONS {
+                let body = mutate(&mut rng, encode(content_type, &valid));
+                // Some mutations still leave a movie that can be stored, and that's fine too.
+                let status = send(&app, request, content_type, body.clone()).await;
+                assert!(status.is_success() || status.is_client_error(), "{} {} as {} was answered with {} for {:?}", request.0, request.1, content_type, status, body);
+            }
+        }
+    }
+}
@@ -0,0 +1,120 @@
+mod common;
+
+use axum::{body::{Body, Bytes}, http::{header, Request, StatusCode}, Router};
+use http_body_util::BodyExt;
+use serde_json::{json, Value};
+use syndica_rust::posters::{self, DiskPosterStore, PosterStore, MAX_POSTER_BYTES};
+use tower::ServiceExt;
+use common::{movie, seeded_app};
+
+const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR not really the rest of a png";
+const JPEG: &[u8] = b"\xff\xd8\xff\xe0\0\x10JFIF not really the rest of a jpeg";
+
+async fn put_poster(app: &Router, id: &str, content_type: &str, body: impl Into<Body>) -> (StatusCode, Value) {
+    let request = Request::put(format!("/v1/movie/{id}/poster")).header("content-type", content_type).body(body.into()).unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+async fn get_poster(app: &Router, id: &str, if_none_match: Option<&str>) -> (StatusCode, axum::http::HeaderMap, Bytes) {
+    let mut request = Request::get(format!("/v1/movie/{id}/poster"));
+    if let Some(etag) = if_none_match {
+        request = request.header("if-none-match", etag);
+    }
+    let response = app.clone().oneshot(request.body(Body::empty()).unwrap()).await.unwrap();
+    let (parts, body) = response.into_parts();
+    (parts.status, parts.headers, body.collect().await.unwrap().to_bytes())
+}
+
+#[tokio::test]
+async fn posters_are_served_back_with_cache_headers() {
+    let app = seeded_app([movie("alien", "Alien", 1979)]).await;
+    let (status, _, _) = get_poster(&app, "alien", None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, poster) = put_poster(&app, "alien", "image/png", PNG).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!((poster["content_type"].as_str(), poster["size"].as_u64()), (Some("image/png"), Some(PNG.len() as u64)));
+    let (status, headers, image) = get_poster(&app, "alien", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(image, PNG);
+    assert_eq!(headers[header::CONTENT_TYPE], "image/png");
+    assert_eq!(headers[header::CACHE_CONTROL], "public, max-age=3600");
+    let etag = headers[header::ETAG].to_str().unwrap().to_string();
+    assert_eq!(etag, format!("\"{}\"", poster["sha256"].as_str().unwrap()));
+    let (status, headers, image) = get_poster(&app, "alien", Some(&etag)).await;
+    assert_eq!(status, StatusCode::NOT_MODIFIED);
+    assert_eq!(headers[header::ETAG], etag.as_str());
+    assert!(image.is_empty());
+
+    // A new poster gets a new ETag.
+    put_poster(&app, "alien", "application/octet-stream", JPEG).await;
+    let (status, headers, image) = get_poster(&app, "alien", Some(&etag)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!((headers[header::CONTENT_TYPE].to_str().unwrap(), &image[..]), ("image/jpeg", JPEG));
+}
+
+#[tokio::test]
+async fn posters_can_come_as_multipart_forms() {
+    let app = seeded_app([movie("alien", "Alien", 1979)]).await;
+    let mut body = b"--XyZ\r\nContent-Disposition: form-data; name=\"title\"\r\n\r\nAlien\r\n".to_vec();
+    body.extend_from_slice(b"--XyZ\r\nContent-Disposition: form-data; name=\"poster\"; filename=\"alien.jpg\"\r\nContent-Type: image/jpeg\r\n\r\n");
+    body.extend_from_slice(JPEG);
+    body.extend_from_slice(b"\r\n--XyZ--\r\n");
+    let (status, poster) = put_poster(&app, "alien", "multipart/form-data; boundary=XyZ", body).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(poster["size"], JPEG.len());
+    let (_, _, image) = get_poster(&app, "alien", None).await;
+    assert_eq!(image, JPEG);
+
+    let no_file = b"--XyZ\r\nContent-Disposition: form-data; name=\"title\"\r\n\r\nAlien\r\n--XyZ--\r\n".to_vec();
+    assert_eq!(put_poster(&app, "alien", "multipart/form-data; boundary=XyZ", no_file).await.0, StatusCode::BAD_REQUEST);
+    assert_eq!(put_poster(&app, "alien", "multipart/form-data", JPEG).await.0, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn only_images_of_a_reasonable_size_are_taken() {
+    let app = seeded_app([movie("alien", "Alien", 1979)]).await;
+    let (status, body) = put_poster(&app, "alien", "image/png", JPEG).await;
+    assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    assert_eq!(body["error"]["code"], "invalid_body");
+    assert_eq!(put_poster(&app, "alien", "image/gif", &b"GIF89a"[..]).await.0, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    let mut huge = PNG.to_vec();
+    huge.resize(MAX_POSTER_BYTES + 1, 0);
+    assert_eq!(put_poster(&app, "alien", "image/png", huge).await.0, StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(put_poster(&app, "missing", "image/png", PNG).await.0, StatusCode::NOT_FOUND);
+    assert_eq!(get_poster(&app, "missing", None).await.0, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn posters_go_with_their_movie() {
+    let app = seeded_app([movie("alien", "Alien", 1979)]).await;
+    put_poster(&app, "alien", "image/png", PNG).await;
+    // Replacing the movie keeps it.
+    let movie = json!({ "id": "alien", "name": "Alien: Director's Cut", "year": 1979, "was_good": true, "version": 2 });
+    let request = Request::put("/v1/movie/alien").header("content-type", "application/json").body(Body::from(movie.to_string())).unwrap();
+    assert_eq!(app.clone().oneshot(request).await.unwrap().status(), StatusCode::OK);
+    assert_eq!(get_poster(&app, "alien", None).await.0, StatusCode::OK);
+
+    let request = Request::delete("/v1/movie/alien").body(Body::empty()).unwrap();
+    assert_eq!(app.clone().oneshot(request).await.unwrap().status(), StatusCode::NO_CONTENT);
+    let movie = json!({ "id": "alien", "name": "Alien", "year": 1979, "was_good": true });
+    let request = Request::post("/v1/movie").header("content-type", "application/json").body(Body::from(movie.to_string())).unwrap();
+    app.clone().oneshot(request).await.unwrap();
+    assert_eq!(get_poster(&app, "alien", None).await.0, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn disk_store_keeps_images_by_hash() {
+    let dir = std::env::temp_dir().join(format!("syndica-posters-{}", std::process::id()));
+    let poster = posters::describe(PNG, Some("image/png")).unwrap();
+    let store = DiskPosterStore::new(dir.clone());
+    assert!(store.get(&poster.sha256).await.unwrap().is_none());
+    store.put(poster.sha256.clone(), Bytes::from_static(PNG)).await.unwrap();
+    store.put(poster.sha256.clone(), Bytes::from_static(PNG)).await.unwrap();
+    let store = DiskPosterStore::new(dir.clone());
+    assert_eq!(store.get(&poster.sha256).await.unwrap().unwrap(), PNG);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,153 @@
+#![cfg(feature = "postgres")]
+
+// These need a Postgres server to talk to, as a superuser so they can make a database each and cut their connections:
+// MOVIES_TEST_POSTGRES_URL=postgres://postgres@localhost/postgres. Without one they pass without doing anything.
+
+mod common;
+
+use std::{process::Command, sync::Arc};
+
+use axum::{http::StatusCode, Router};
+use serde_json::json;
+use syndica_rust::{build_router, postgres::{PostgresConfig, PostgresMovieStore}, settings::Settings};
+use common::{movie, seed, send};
+
+// A database of the test's own on the server, dropped again by `drop_database`.
+fn database(name: &str) -> Option<String> {
+    let Ok(server) = std::env::var("MOVIES_TEST_POSTGRES_URL") else {
+        eprintln!("MOVIES_TEST_POSTGRES_URL isn't set, skipping");
+        return None;
+    };
+    let database = format!("syndica_{}_{}", std::process::id(), name);
+    psql(&server, &format!("DROP DATABASE IF EXISTS {0} WITH (FORCE); CREATE DATABASE {0}", database));
+    Some(format!("{}/{}?application_name={}", server.rsplit_once('/').unwrap().0, database, database))
+}
+
+fn drop_database(url: &str) {
+    let server = std::env::var("MOVIES_TEST_POSTGRES_URL").unwrap();
+    psql(&server, &format!("DROP DATABASE {} WITH (FORCE)", database_name(url)));
+}
+
+fn database_name(url: &str) -> &str {
+    url.rsplit_once('/').unwrap().1.split_once('?').unwrap().0
+}
+
+fn psql(url: &str, sql: &str) {
+    let output = Command::new("psql").args([url, "-q", "-v", "ON_ERROR_STOP=1"]).args(sql.split("; ").flat_map(|sql| ["-c", sql])).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+}
+
+fn config(url: &str, pool_size: usize) -> PostgresConfig {
+    PostgresConfig { url: url.to_string(), pool_size, schema: None }
+}
+
+async fn open(config: PostgresConfig) -> Router {
+    build_router(Arc::new(PostgresMovieStore::open(config).await.unwrap()), Settings::default())
+}
+
+#[tokio::test]
+async fn everything_survives_a_restart() {
+    let Some(url) = database("restart") else { return };
+    let app = open(config(&url, 2)).await;
+    seed(&app, [movie("alien", "Alien", 1979), movie("aliens", "Aliens", 1986), movie("heat", "Heat", 1995), movie("ran", "Ran", 1985)]).await;
+    send(&app, "PATCH", "/v1/movie/ran", Some(json!({ "year": 1986 }))).await;
+    send(&app, "POST", "/v1/users", Some(json!({ "id": "ripley", "name": "Ellen Ripley" }))).await;
+    for movie_id in ["alien", "heat"] {
+        send(&app, "POST", "/v1/users/ripley/watchlist", Some(json!({ "movie_id": movie_id }))).await;
+    }
+    send(&app, "POST", "/v1/movie/aliens/related", Some(json!({ "movie_id": "alien", "relation": "sequel-of" }))).await;
+    send(&app, "POST", "/v1/movie/heat/related", Some(json!({ "movie_id": "ran", "relation": "remake-of" }))).await;
+    // Takes heat off the watchlist and unlinks it, which has to be written too.
+    assert_eq!(send(&app, "DELETE", "/v1/movie/heat", None).await.0, StatusCode::NO_CONTENT);
+
+    let app = open(config(&url, 2)).await;
+    let (_, page) = send(&app, "GET", "/v1/movies", None).await;
+    let ids: Vec<&str> = page["items"].as_array().unwrap().iter().map(|movie| movie["id"].as_str().unwrap()).collect();
+    assert_eq!(ids, ["alien", "aliens", "ran"]);
+    let (_, ran) = send(&app, "GET", "/v1/movie/ran", None).await;
+    assert_eq!((ran["year"].clone(), ran["version"].clone()), (json!(1986), json!(2)));
+    assert_eq!(send(&app, "GET", "/v1/users/ripley", None).await.1["watchlist"], json!(["alien"]));
+    let (_, related) = send(&app, "GET", "/v1/movie/aliens/related", None).await;
+    assert_eq!(related["items"][0]["movie"]["id"], "alien");
+    assert_eq!(send(&app, "GET", "/v1/movie/ran/related", None).await.1["items"], json!([]));
+    let (_, trash) = send(&app, "GET", "/v1/movies/trash", None).await;
+    assert_eq!(trash["items"][0]["movie"]["id"], "heat");
+
+    assert_eq!(send(&app, "POST", "/v1/movie/heat/restore", None).await.0, StatusCode::OK);
+    let app = open(config(&url, 2)).await;
+    assert_eq!(send(&app, "GET", "/v1/movie/heat", None).await.1["name"], "Heat");
+    assert_eq!(send(&app, "GET", "/v1/movies/trash", None).await.1["items"], json!([]));
+    drop_database(&url);
+}
+
+#[tokio::test]
+async fn tenants_get_a_schema_each() {
+    let Some(url) = database("tenants") else { return };
+    let team_a = open(PostgresConfig { schema: Some("team-a".to_string()), ..config(&url, 1) }).await;
+    let team_b = open(PostgresConfig { schema: Some("team-b".to_string()), ..config(&url, 1) }).await;
+    seed(&team_a, [movie("alien", "Alien", 1979)]).await;
+    assert_eq!(send(&team_b, "GET", "/v1/movie/alien", None).await.0, StatusCode::NOT_FOUND);
+    let team_a = open(PostgresConfig { schema: Some("team-a".to_string()), ..config(&url, 1) }).await;
+    assert_eq!(send(&team_a, "GET", "/v1/movie/alien", None).await.0, StatusCode::OK);
+    drop_database(&url);
+}
+
+#[tokio::test]
+async fn movies_are_read_from_the_database() {
+    let Some(url) = database("reads") else { return };
+    let app = open(config(&url, 2)).await;
+    seed(&app, [movie("alien", "Alien", 1979)]).await;
+
+    // Another server on the same database reads what this one wrote, but still refuses to insert over it.
+    psql(&url, r#"INSERT INTO movies (id, movie) SELECT 'aliens', replace(replace(movie, 'alien', 'aliens'), 'Alien', 'Aliens') FROM movies"#);
+    let other = open(config(&url, 2)).await;
+    seed(&app, [movie("heat", "Heat", 1995)]).await;
+    assert_eq!(send(&other, "GET", "/v1/movie/heat", None).await.1["name"], "Heat");
+    let (_, page) = send(&other, "GET", "/v1/movies", None).await;
+    let ids: Vec<&str> = page["items"].as_array().unwrap().iter().map(|movie| movie["id"].as_str().unwrap()).collect();
+    assert_eq!(ids, ["alien", "aliens", "heat"]);
+    let (status, body) = send(&other, "POST", "/v1/movie", Some(movie("heat", "Heat", 1995))).await;
+    assert_eq!(status, StatusCode::CONFLICT, "{}", body);
+    drop_database(&url);
+}
+
+#[tokio::test]
+async fn requests_wait_their_turn_for_a_connection() {
+    let Some(url) = database("pool") else { return };
+    let app = open(config(&url, 1)).await;
+    seed(&app, [movie("alien", "Alien", 1979), movie("heat", "Heat", 1995)]).await;
+    let requests = (0..32).map(|i| {
+        let app = app.clone();
+        tokio::spawn(async move { send(&app, "GET", if i % 2 == 0 { "/v1/movie/alien" } else { "/v1/movies" }, None).await.0 })
+    });
+    for request in requests.collect::<Vec<_>>() {
+        assert_eq!(request.await.unwrap(), StatusCode::OK);
+    }
+    drop_database(&url);
+}
+
+#[tokio::test]
+async fn readiness_follows_the_pool() {
+    let Some(url) = database("ready") else { return };
+    let app = open(config(&url, 2)).await;
+    assert_eq!(send(&app, "GET", "/readyz", None).await.0, StatusCode::OK);
+    let server = std::env::var("MOVIES_TEST_POSTGRES_URL").unwrap();
+    let database = database_name(&url);
+    let terminate = format!("SELECT pg_terminate_backend(pid) FROM pg_stat_activity WHERE application_name = '{}'", database);
+
+    // As a server restart would: the connections are made again as they're used.
+    psql(&server, &terminate);
+    assert_eq!(send(&app, "GET", "/readyz", None).await.0, StatusCode::OK);
+    seed(&app, [movie("alien", "Alien", 1979)]).await;
+
+    psql(&server, &format!("ALTER DATABASE {} WITH ALLOW_CONNECTIONS false; {}", database, terminate));
+    let (status, body) = send(&app, "GET", "/readyz", None).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(body["error"]["code"], "unavailable");
+    // Reads are answered from memory in the meantime.
+    assert_eq!(send(&app, "GET", "/v1/movie/alien", None).await.0, StatusCode::OK);
+
+    psql(&server, &format!("ALTER DATABASE {} WITH ALLOW_CONNECTIONS true", database));
+    assert_eq!(send(&app, "GET", "/readyz", None).await.0, StatusCode::OK);
+    drop_database(&url);
+}
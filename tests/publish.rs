@@ -0,0 +1,116 @@
+use std::{sync::{Arc, Mutex}, time::Duration};
+use axum::{body::Body, extract::{Path, State}, http::{HeaderMap, Request, StatusCode}, routing::post, Json, Router};
+use serde_json::{json, Value};
+use syndica_rust::{build_router, publish::{self, Broker, PublishConfig}, settings::Settings, state::{state_init, StateWrapper}};
+use tokio::{io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader}, net::TcpListener};
+use tower::ServiceExt;
+
+async fn change_movies(state: StateWrapper) {
+    let app = build_router(state, Settings::default());
+    let send = |method: &str, uri: &str, body: Value| Request::builder().method(method).uri(uri).header("content-type", "application/json").body(Body::from(body.to_string())).unwrap();
+    let movie = json!({ "id": "alien", "name": "Alien", "year": 1979, "was_good": true });
+    assert_eq!(app.clone().oneshot(send("POST", "/v1/movie", movie)).await.unwrap().status(), StatusCode::CREATED);
+    assert_eq!(app.clone().oneshot(send("PATCH", "/v1/movie/alien", json!({ "director": "Ridley Scott" }))).await.unwrap().status(), StatusCode::OK);
+    assert_eq!(app.oneshot(Request::delete("/v1/movie/alien").body(Body::empty()).unwrap()).await.unwrap().status(), StatusCode::NO_CONTENT);
+}
+
+// Changes are published in the background, so this waits for them.
+async fn wait_for<T>(messages: &Mutex<Vec<T>>, count: usize) {
+    for _ in 0..100 {
+        if messages.lock().unwrap().len() >= count {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    panic!("Gave up waiting for {} messages", count);
+}
+
+// Speaks enough NATS to take PUBs, keeping the CONNECT options and each subject and message. The first connection is
+// dropped as soon as something is published on it, to see the publisher connect again.
+async fn spawn_nats() -> (String, Arc<Mutex<Vec<(String, Value)>>>, Arc<Mutex<Vec<Value>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    let messages = Arc::new(Mutex::new(Vec::new()));
+    let connects = Arc::new(Mutex::new(Vec::new()));
+    let (received, connected) = (messages.clone(), connects.clone());
+    tokio::spawn(async move {
+        for connection in 0.. {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = stream.into_split();
+            let mut reader = BufReader::new(reader);
+            writer.write_all(b"INFO {\"server_id\":\"fake\",\"max_payload\":1048576}\r\n").await.unwrap();
+            let mut line = String::new();
+            while reader.read_line(&mut line).await.unwrap() > 0 {
+                let words: Vec<&str> = line.split_whitespace().collect();
+                match words[..] {
+                    ["CONNECT", ..] => connected.lock().unwrap().push(serde_json::from_str(&line["CONNECT ".len()..]).unwrap()),
+                    ["PUB", subject, size] => {
+                        let mut payload = vec![0; size.parse::<usize>().unwrap() + 2];
+                        reader.read_exact(&mut payload).await.unwrap();
+                        if connection == 0 {
+                            break;
+                        }
+                        received.lock().unwrap().push((subject.to_string(), serde_json::from_slice(&payload[..payload.len() - 2]).unwrap()));
+                    },
+                    ["PING"] => writer.write_all(b"PING\r\nPONG\r\n").await.unwrap(),
+                    _ => {},
+                }
+                line.clear();
+            }
+        }
+    });
+    (address, messages, connects)
+}
+
+#[tokio::test]
+async fn changes_are_published_to_nats() {
+    let (address, messages, connects) = spawn_nats().await;
+    let state = state_init();
+    let broker = Broker::nats(&format!("nats://svc:s3cret@{address}")).unwrap();
+    assert!(!format!("{:?}", broker).contains("s3cret"));
+    publish::start(PublishConfig { broker, topic: "movies.changes".to_string() }, state.subscribe());
+    change_movies(state).await;
+    wait_for(&messages, 3).await;
+
+    let messages = messages.lock().unwrap();
+    assert!(messages.iter().all(|(subject, _)| subject == "movies.changes"));
+    let events: Vec<&str> = messages.iter().map(|(_, message)| message["event"].as_str().unwrap()).collect();
+    assert_eq!(events, ["created", "updated", "deleted"]);
+    assert_eq!((&messages[1].1["movie"]["director"], &messages[2].1["movie"]["id"]), (&json!("Ridley Scott"), &json!("alien")));
+    assert!(messages[0].1["id"].is_string() && messages[0].1["occurred_at"].is_string());
+    let connects = connects.lock().unwrap();
+    assert_eq!(connects.len(), 2);
+    assert_eq!((&connects[1]["user"], &connects[1]["pass"], &connects[1]["verbose"]), (&json!("svc"), &json!("s3cret"), &json!(false)));
+}
+
+type Records = Arc<Mutex<Vec<(String, HeaderMap, Value)>>>;
+
+async fn produce(State(records): State<Records>, Path(topic): Path<String>, headers: HeaderMap, Json(body): Json<Value>) -> Json<Value> {
+    let mut records = records.lock().unwrap();
+    let offsets: Vec<Value> = body["records"].as_array().unwrap().iter().map(|record| {
+        records.push((topic.clone(), headers.clone(), record.clone()));
+        json!({ "partition": 0, "offset": records.len() - 1 })
+    }).collect();
+    Json(json!({ "offsets": offsets }))
+}
+
+#[tokio::test]
+async fn changes_are_published_through_the_kafka_rest_proxy() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let records: Records = Arc::new(Mutex::new(Vec::new()));
+    let proxy = Router::new().route("/topics/{topic}", post(produce)).with_state(records.clone());
+    tokio::spawn(async move { axum::serve(listener, proxy).await.unwrap() });
+
+    let state = state_init();
+    publish::start(PublishConfig { broker: Broker::KafkaRest { url }, topic: "movie-changes".to_string() }, state.subscribe());
+    change_movies(state).await;
+    wait_for(&records, 3).await;
+
+    let records = records.lock().unwrap();
+    let (topic, headers, record) = &records[0];
+    assert_eq!((topic.as_str(), headers["content-type"].to_str().unwrap()), ("movie-changes", "application/vnd.kafka.json.v2+json"));
+    assert_eq!((&record["key"], &record["value"]["event"], &record["value"]["movie"]["name"]), (&json!("alien"), &json!("created"), &json!("Alien")));
+    let events: Vec<&str> = records.iter().map(|(_, _, record)| record["value"]["event"].as_str().unwrap()).collect();
+    assert_eq!(events, ["created", "updated", "deleted"]);
+}
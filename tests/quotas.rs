@@ -0,0 +1,51 @@
+mod common;
+
+use std::sync::Arc;
+
+use axum::{http::StatusCode, Router};
+use serde_json::{json, Value};
+use syndica_rust::{build_router, quota::QuotaMovieStore, settings::Settings, state::{state_init, StateWrapper}, tenant::{TenantConfig, TenantMovieStore}};
+use common::{send, send_with};
+
+// The app, and its quota to change the limit of as a reload would.
+fn app(max_movies: Option<usize>) -> (Router, Arc<QuotaMovieStore>) {
+    let store: StateWrapper = Arc::new(TenantMovieStore::new(state_init(), vec![(TenantConfig { id: "team-a".into(), max_movies: None }, state_init())]));
+    let quota = Arc::new(QuotaMovieStore::new(store, max_movies));
+    (build_router(quota.clone(), Settings::default()), quota)
+}
+
+fn movie(id: &str) -> Value {
+    json!({ "id": id, "name": "Movie", "year": 1979, "was_good": true })
+}
+
+#[tokio::test]
+async fn max_movies_caps_the_whole_store() {
+    let (app, quota) = app(Some(2));
+    // Every tenant's movies count towards it.
+    let team_a = [("x-tenant-id", "team-a")];
+    assert_eq!(send_with(&app, "POST", "/v1/movie", &team_a, Some(movie("alien"))).await.0, StatusCode::CREATED);
+    assert_eq!(send_with(&app, "POST", "/v1/movie", &team_a, Some(movie("heat"))).await.0, StatusCode::CREATED);
+    let (status, body) = send(&app, "POST", "/v1/movie", Some(movie("ran"))).await;
+    assert_eq!((status, body["error"]["code"].as_str()), (StatusCode::INSUFFICIENT_STORAGE, Some("quota_exceeded")));
+    assert_eq!(body["error"]["details"], json!({ "quota": "global", "limit": 2, "used": 2 }));
+    assert_eq!(send_with(&app, "POST", "/v1/movie?upsert=true", &team_a, Some(movie("heat"))).await.0, StatusCode::OK);
+    let (_, report) = send(&app, "POST", "/v1/movies/batch", Some(json!([movie("ran")]))).await;
+    assert_eq!(report["results"][0]["error"]["code"], "quota_exceeded");
+
+    let (status, usage) = send(&app, "GET", "/admin/quotas", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(usage, json!({ "movies": 2, "max_movies": 2, "tenants": [
+        { "id": "default", "movies": 0, "trashed": 0 },
+        { "id": "team-a", "movies": 2, "trashed": 0 },
+    ] }));
+
+    // Raising it, as a reload does, makes room straight away.
+    quota.set_max_movies(Some(3));
+    assert_eq!(send(&app, "POST", "/v1/movie", Some(movie("ran"))).await.0, StatusCode::CREATED);
+    quota.set_max_movies(None);
+    assert_eq!(send(&app, "POST", "/v1/movie", Some(movie("sorcerer"))).await.0, StatusCode::CREATED);
+    assert_eq!(send(&app, "GET", "/admin/quotas", None).await.1, json!({ "movies": 4, "tenants": [
+        { "id": "default", "movies": 2, "trashed": 0 },
+        { "id": "team-a", "movies": 2, "trashed": 0 },
+    ] }));
+}
@@ -0,0 +1,57 @@
+mod common;
+
+use std::collections::HashSet;
+use axum::{http::{header, StatusCode}, Router};
+use serde_json::{json, Value};
+use syndica_rust::random;
+use common::{json, seeded_app, send_request};
+
+fn catalog() -> Vec<Value> {
+    let movies = [
+        ("fight-club", 1999, true),
+        ("inception", 2010, true),
+        ("matrix", 1999, true),
+        ("matrix-reloaded", 2003, false),
+    ];
+    movies.into_iter().map(|(id, year, was_good)| json!({ "id": id, "name": id, "year": year, "was_good": was_good })).collect()
+}
+
+async fn pick(app: &Router, query: &str) -> (StatusCode, Value) {
+    let response = send_request(app, "GET", &format!("/v1/movies/random?{query}"), &[], None).await;
+    let status = response.status();
+    if status == StatusCode::OK {
+        assert_eq!(response.headers()[header::CACHE_CONTROL], "no-store");
+    }
+    (status, json(response).await)
+}
+
+#[tokio::test]
+async fn picks_only_matching_movies_and_all_of_them() {
+    let app = seeded_app(catalog()).await;
+    let mut picked = HashSet::new();
+    for _ in 0..200 {
+        let (status, movie) = pick(&app, "was_good=true&year_gte=2000").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(movie["id"], "inception");
+        let (_, movie) = pick(&app, "year_lte=2003").await;
+        picked.insert(movie["id"].as_str().unwrap().to_string());
+    }
+    assert_eq!(picked, HashSet::from(["fight-club", "matrix", "matrix-reloaded"].map(String::from)));
+}
+
+#[tokio::test]
+async fn nothing_matching_is_404() {
+    let app = seeded_app(catalog()).await;
+    let (status, _) = pick(&app, "year=1950").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn below_stays_in_range() {
+    assert_eq!(random::below(1), 0);
+    let mut seen = [false; 5];
+    for _ in 0..1000 {
+        seen[random::below(5)] = true;
+    }
+    assert!(seen.iter().all(|seen| *seen));
+}
@@ -0,0 +1,99 @@
+mod common;
+
+use std::sync::Arc;
+
+use axum::{http::StatusCode, Router};
+use serde_json::{json, Value};
+use syndica_rust::{build_router, settings::Settings, snapshot::SnapshotMovieStore, wal::{WalConfig, WalMovieStore}};
+use common::{movie, seed, seeded_app, send};
+
+async fn rate(app: &Router, user: &str, score: u8) -> (StatusCode, Value) {
+    send(app, "POST", "/v1/movie/alien/ratings", Some(json!({ "user": user, "score": score }))).await
+}
+
+#[tokio::test]
+async fn ratings_are_averaged_on_the_movie() {
+    let app = seeded_app([movie("alien", "Alien", 1979)]).await;
+    let (_, movie) = send(&app, "GET", "/v1/movie/alien", None).await;
+    assert!(movie.get("average_rating").is_none() && movie.get("rating_count").is_none());
+
+    let (status, movie) = rate(&app, "ripley", 9).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!((movie["average_rating"].as_f64(), movie["rating_count"].as_u64()), (Some(9.0), Some(1)));
+    rate(&app, "dallas", 6).await;
+    // A second rating from the same user replaces the first.
+    let (_, movie) = rate(&app, "ripley", 10).await;
+    assert_eq!((movie["average_rating"].as_f64(), movie["rating_count"].as_u64()), (Some(8.0), Some(2)));
+    assert_eq!(movie["version"], 4);
+    // The individual ratings stay off the movie.
+    assert!(movie.get("ratings").is_none());
+
+    let (status, page) = send(&app, "GET", "/v1/movie/alien/ratings?limit=1", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(page["items"], json!([{ "user": "dallas", "score": 6 }]));
+    assert_eq!(page["total"], 2);
+    assert_eq!(page["average_rating"], 8.0);
+    let (_, page) = send(&app, "GET", page["next"].as_str().unwrap(), None).await;
+    assert_eq!(page["items"], json!([{ "user": "ripley", "score": 10 }]));
+    assert_eq!(page["next"], Value::Null);
+}
+
+#[tokio::test]
+async fn invalid_ratings_are_rejected() {
+    let app = seeded_app([movie("alien", "Alien", 1979)]).await;
+    for body in [json!({ "user": "ripley", "score": 0 }), json!({ "user": "ripley", "score": 11 }), json!({ "user": " ", "score": 5 }), json!({ "score": 5 })] {
+        let (status, body) = send(&app, "POST", "/v1/movie/alien/ratings", Some(body)).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["error"]["code"], "validation_failed");
+    }
+    let (status, _) = send(&app, "POST", "/v1/movie/missing/ratings", Some(json!({ "user": "ripley", "score": 5 }))).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(send(&app, "GET", "/v1/movie/missing/ratings", None).await.0, StatusCode::NOT_FOUND);
+    // Nor can a patch set them.
+    let (status, _) = send(&app, "PATCH", "/v1/movie/alien", Some(json!({ "rating": ["ripley", 5] }))).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[tokio::test]
+async fn replacing_a_movie_keeps_its_ratings() {
+    let app = seeded_app([movie("alien", "Alien", 1979)]).await;
+    let (_, mut movie) = rate(&app, "ripley", 9).await;
+    movie["name"] = json!("Alien: Director's Cut");
+    movie["average_rating"] = json!(1.0);
+    movie["rating_count"] = json!(50);
+    let (status, replaced) = send(&app, "PUT", "/v1/movie/alien", Some(movie)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!((replaced["average_rating"].as_f64(), replaced["rating_count"].as_u64()), (Some(9.0), Some(1)));
+    assert_eq!(send(&app, "GET", "/v1/movie/alien", None).await.1, replaced);
+
+    let upsert = json!({ "id": "alien", "name": "Alien", "year": 1979, "was_good": true });
+    let (_, upserted) = send(&app, "POST", "/v1/movie?upsert=true", Some(upsert)).await;
+    assert_eq!(upserted["rating_count"], 1);
+}
+
+#[tokio::test]
+async fn ratings_survive_a_restart() {
+    let dir = std::env::temp_dir().join(format!("syndica-ratings-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let config = WalConfig { path: dir.join("movies.wal"), max_bytes: u64::MAX };
+    let app = build_router(Arc::new(WalMovieStore::open(config.clone()).await.unwrap()), Settings::default());
+    seed(&app, [movie("alien", "Alien", 1979)]).await;
+    rate(&app, "ripley", 9).await;
+    rate(&app, "dallas", 4).await;
+    let app = build_router(Arc::new(WalMovieStore::open(config).await.unwrap()), Settings::default());
+    let (_, page) = send(&app, "GET", "/v1/movie/alien/ratings", None).await;
+    assert_eq!(page["items"], json!([{ "user": "dallas", "score": 4 }, { "user": "ripley", "score": 9 }]));
+
+    let path = dir.join("movies.json");
+    let store = Arc::new(SnapshotMovieStore::open(path.clone()).await.unwrap());
+    let app = build_router(store.clone(), Settings::default());
+    seed(&app, [movie("alien", "Alien", 1979)]).await;
+    rate(&app, "ripley", 7).await;
+    store.flush().await.unwrap();
+    let app = build_router(Arc::new(SnapshotMovieStore::open(path).await.unwrap()), Settings::default());
+    let (_, movie) = send(&app, "GET", "/v1/movie/alien", None).await;
+    assert_eq!(movie["average_rating"], 7.0);
+    let (_, page) = send(&app, "GET", "/v1/movie/alien/ratings", None).await;
+    assert_eq!(page["items"], json!([{ "user": "ripley", "score": 7 }]));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
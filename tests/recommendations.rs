@@ -0,0 +1,62 @@
+mod common;
+
+use axum::{http::StatusCode, Router};
+use serde_json::{json, Value};
+use common::send;
+
+async fn seeded_app() -> Router {
+    let movies = [
+        ("alien", "Alien", vec!["Horror", "Sci-Fi"], vec!["space"]),
+        ("aliens", "Aliens", vec!["Action", "Sci-Fi"], vec!["space", "marines"]),
+        ("chinatown", "Chinatown", vec!["Noir"], vec![]),
+        ("event-horizon", "Event Horizon", vec!["horror"], vec!["Space"]),
+        ("heat", "Heat", vec!["Crime"], vec![]),
+        ("solaris", "Solaris", vec!["sci-fi"], vec![]),
+        ("the-thing", "The Thing", vec!["Horror"], vec!["snow"]),
+    ];
+    let app = common::seeded_app(movies.into_iter().map(|(id, name, genres, tags)| {
+        json!({ "id": id, "name": name, "year": 1980, "was_good": true, "genres": genres, "tags": tags })
+    })).await;
+    assert_eq!(send(&app, "POST", "/v1/users", Some(json!({ "id": "ripley" }))).await.0, StatusCode::CREATED);
+    app
+}
+
+fn ids(body: &Value) -> Vec<&str> {
+    body["items"].as_array().unwrap().iter().map(|item| item["movie"]["id"].as_str().unwrap()).collect()
+}
+
+#[tokio::test]
+async fn recommends_what_favorites_have_in_common() {
+    let app = seeded_app().await;
+    let (status, body) = send(&app, "GET", "/v1/users/ripley/recommendations", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["items"], json!([]));
+
+    send(&app, "PUT", "/v1/users/ripley/favorites/alien", None).await;
+    send(&app, "PUT", "/v1/users/ripley/favorites/the-thing", None).await;
+    let (_, body) = send(&app, "GET", "/v1/users/ripley/recommendations", None).await;
+    // Horror is on both favorites, Sci-Fi and space on one each. Heat and Chinatown share nothing.
+    assert_eq!(ids(&body), ["event-horizon", "aliens", "solaris"]);
+    assert_eq!(body["items"][0]["score"], 1.5);
+    assert_eq!(body["items"][0]["because"], json!(["horror", "Space"]));
+    assert_eq!(body["items"][1]["because"], json!(["Sci-Fi", "space"]));
+    let (_, body) = send(&app, "GET", "/v1/users/ripley/recommendations?limit=1", None).await;
+    assert_eq!(ids(&body), ["event-horizon"]);
+}
+
+#[tokio::test]
+async fn leaves_out_what_was_watched() {
+    let app = seeded_app().await;
+    send(&app, "PUT", "/v1/users/ripley/favorites/alien", None).await;
+    send(&app, "POST", "/v1/users/ripley/history", Some(json!({ "movie_id": "aliens" }))).await;
+    let (_, body) = send(&app, "GET", "/v1/users/ripley/recommendations", None).await;
+    assert_eq!(ids(&body), ["event-horizon", "solaris", "the-thing"]);
+    // Even once it's deleted and put back.
+    send(&app, "DELETE", "/v1/movie/aliens", None).await;
+    let body = json!({ "id": "aliens", "name": "Aliens", "year": 1986, "was_good": true, "genres": ["Sci-Fi"] });
+    send(&app, "POST", "/v1/movie", Some(body)).await;
+    let (_, body) = send(&app, "GET", "/v1/users/ripley/recommendations", None).await;
+    assert!(!ids(&body).contains(&"aliens"));
+
+    assert_eq!(send(&app, "GET", "/v1/users/dallas/recommendations", None).await.0, StatusCode::NOT_FOUND);
+}
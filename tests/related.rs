@@ -0,0 +1,99 @@
+mod common;
+
+use std::sync::Arc;
+
+use axum::{http::StatusCode, Router};
+use serde_json::{json, Value};
+use syndica_rust::{build_router, settings::Settings, snapshot::SnapshotMovieStore, wal::{WalConfig, WalMovieStore}};
+use common::{movie, seed, seeded_app, send};
+
+fn catalog() -> Vec<Value> {
+    let movies = [("alien", "Alien", 1979), ("aliens", "Aliens", 1986), ("alien-3", "Alien 3", 1992), ("prometheus", "Prometheus", 2012)];
+    movies.into_iter().map(|(id, name, year)| movie(id, name, year)).collect()
+}
+
+async fn link(app: &Router, from: &str, relation: &str, to: &str) -> (StatusCode, Value) {
+    send(app, "POST", &format!("/v1/movie/{from}/related"), Some(json!({ "movie_id": to, "relation": relation }))).await
+}
+
+fn related(body: &Value) -> Vec<(&str, &str)> {
+    body["items"].as_array().unwrap().iter().map(|item| (item["relation"].as_str().unwrap(), item["movie"]["id"].as_str().unwrap())).collect()
+}
+
+#[tokio::test]
+async fn links_are_listed_from_both_ends() {
+    let app = seeded_app(catalog()).await;
+    let (status, body) = link(&app, "aliens", "sequel-of", "alien").await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(body, json!({ "from": "aliens", "relation": "sequel-of", "to": "alien" }));
+    assert_eq!(link(&app, "aliens", "sequel-of", "alien").await.0, StatusCode::OK);
+    // Franchises go both ways, so this is the same link as prometheus to alien.
+    let (_, body) = link(&app, "prometheus", "part-of-franchise", "alien").await;
+    assert_eq!(body, json!({ "from": "alien", "relation": "part-of-franchise", "to": "prometheus" }));
+    assert_eq!(link(&app, "alien", "part-of-franchise", "prometheus").await.0, StatusCode::OK);
+
+    let (status, body) = send(&app, "GET", "/v1/movie/alien/related", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(related(&body), [("part-of-franchise", "prometheus"), ("followed-by", "aliens")]);
+    let (_, body) = send(&app, "GET", "/v1/movie/aliens/related", None).await;
+    assert_eq!(related(&body), [("sequel-of", "alien")]);
+
+    assert_eq!(send(&app, "DELETE", "/v1/movie/prometheus/related/part-of-franchise/alien", None).await.0, StatusCode::NO_CONTENT);
+    assert_eq!(send(&app, "DELETE", "/v1/movie/prometheus/related/part-of-franchise/alien", None).await.0, StatusCode::NO_CONTENT);
+    assert_eq!(send(&app, "DELETE", "/v1/movie/prometheus/related/prequel-of/alien", None).await.0, StatusCode::BAD_REQUEST);
+    // Deleting a movie unlinks it.
+    assert_eq!(send(&app, "DELETE", "/v1/movie/aliens", None).await.0, StatusCode::NO_CONTENT);
+    let (_, body) = send(&app, "GET", "/v1/movie/alien/related", None).await;
+    assert_eq!(body["items"], json!([]));
+}
+
+#[tokio::test]
+async fn sequels_cant_go_round_in_circles() {
+    let app = seeded_app(catalog()).await;
+    link(&app, "aliens", "sequel-of", "alien").await;
+    link(&app, "alien-3", "sequel-of", "aliens").await;
+    let (status, body) = link(&app, "alien", "sequel-of", "alien-3").await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(body["error"]["code"], "conflict");
+    assert_eq!(link(&app, "alien", "sequel-of", "aliens").await.0, StatusCode::CONFLICT);
+    // Other relations don't make a chain.
+    assert_eq!(link(&app, "alien", "remake-of", "alien-3").await.0, StatusCode::CREATED);
+
+    let (status, body) = link(&app, "alien", "remake-of", "alien").await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["error"]["details"]["fields"][0]["field"], "movie_id");
+    assert_eq!(link(&app, "alien", "prequel-of", "aliens").await.0, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(link(&app, "alien", "sequel-of", "missing").await.0, StatusCode::NOT_FOUND);
+    assert_eq!(link(&app, "missing", "sequel-of", "alien").await.0, StatusCode::NOT_FOUND);
+    assert_eq!(send(&app, "GET", "/v1/movie/missing/related", None).await.0, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn links_survive_a_restart() {
+    let dir = std::env::temp_dir().join(format!("syndica-related-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let config = WalConfig { path: dir.join("movies.wal"), max_bytes: u64::MAX };
+    let app = build_router(Arc::new(WalMovieStore::open(config.clone()).await.unwrap()), Settings::default());
+    seed(&app, catalog()).await;
+    link(&app, "aliens", "sequel-of", "alien").await;
+    link(&app, "alien-3", "sequel-of", "aliens").await;
+    link(&app, "prometheus", "part-of-franchise", "alien").await;
+    send(&app, "DELETE", "/v1/movie/alien-3", None).await;
+    send(&app, "DELETE", "/v1/movie/prometheus/related/part-of-franchise/alien", None).await;
+    let app = build_router(Arc::new(WalMovieStore::open(config).await.unwrap()), Settings::default());
+    let (_, body) = send(&app, "GET", "/v1/movie/aliens/related", None).await;
+    assert_eq!(related(&body), [("sequel-of", "alien")]);
+    let (_, body) = send(&app, "GET", "/v1/movie/prometheus/related", None).await;
+    assert_eq!(body["items"], json!([]));
+
+    let path = dir.join("movies.json");
+    let store = Arc::new(SnapshotMovieStore::open(path.clone()).await.unwrap());
+    let app = build_router(store.clone(), Settings::default());
+    seed(&app, catalog()).await;
+    link(&app, "alien", "remake-of", "prometheus").await;
+    store.flush().await.unwrap();
+    let app = build_router(Arc::new(SnapshotMovieStore::open(path).await.unwrap()), Settings::default());
+    let (_, body) = send(&app, "GET", "/v1/movie/prometheus/related", None).await;
+    assert_eq!(related(&body), [("remade-as", "alien")]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,83 @@
+mod common;
+
+use std::time::Duration;
+use axum::{body::Body, http::{Request, StatusCode}};
+use http_body_util::BodyExt;
+use serde_json::json;
+use syndica_rust::{audit, build_router, model::Movie, replication::{self, FollowConfig}, settings::Settings, state::{state_init, StateWrapper}};
+use tokio::net::TcpListener;
+use tower::ServiceExt;
+use common::send;
+
+// Making an instance a follower makes the whole process refuse writes, so this is the only test in the file, and the
+// leader's movies are written to its store directly rather than through its routes.
+
+fn movie(id: &str, name: &str) -> Movie {
+    Movie { id: id.into(), name: name.into(), year: 1979, was_good: true, ..Movie::default() }
+}
+
+async fn names(state: &StateWrapper) -> Vec<(String, String)> {
+    state.backup().await.movies.into_iter().map(|movie| (movie.id, movie.name)).collect()
+}
+
+// Waits for the follower to have the same movies as the leader.
+async fn caught_up(leader: &StateWrapper, follower: &StateWrapper) {
+    for _ in 0..100 {
+        if names(leader).await == names(follower).await {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    panic!("the follower has {:?}, not {:?}", names(follower).await, names(leader).await);
+}
+
+#[tokio::test]
+async fn followers_copy_the_leader_and_keep_up_with_it() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let leader_url = format!("http://{}", listener.local_addr().unwrap());
+    let leader = state_init();
+    // More movies than fit on a page, so the copy has to follow the cursors.
+    for i in 0..150 {
+        leader.insert(movie(&format!("movie-{:03}", i), "Alien")).await.unwrap();
+    }
+    let leader_app = build_router(leader.clone(), Settings::default());
+    tokio::spawn(async move { axum::serve(listener, leader_app).await.unwrap() });
+
+    let follower = state_init();
+    let app = build_router(follower.clone(), Settings::default());
+    follower.insert(movie("stale", "Not the leader's")).await.unwrap();
+    replication::start(FollowConfig { leader: leader_url.clone(), api_key: None, interval: Duration::from_millis(100) }, follower.clone());
+    caught_up(&leader, &follower).await;
+    assert_eq!(send(&app, "GET", "/readyz", None).await.0, StatusCode::OK);
+
+    // Changes are applied one by one, not by replacing everything again like the copy did.
+    let replaced = || audit::entries(None, None).into_iter().filter(|entry| entry.entity == "store").count();
+    assert_eq!(replaced(), 1);
+    leader.insert(movie("heat", "Heat")).await.unwrap();
+    caught_up(&leader, &follower).await;
+    assert_eq!(replaced(), 1);
+    leader.upsert(movie("movie-000", "Aliens")).await.unwrap();
+    leader.delete("movie-001").await.unwrap();
+    caught_up(&leader, &follower).await;
+    assert_eq!(replaced(), 1);
+    // Versions and all.
+    let (copied, original) = (follower.get("movie-000").await.unwrap(), leader.get("movie-000").await.unwrap());
+    assert_eq!((copied.version, &copied.updated_at), (original.version, &original.updated_at));
+
+    // Reads are answered, writes are sent to the leader.
+    let (status, fetched) = send(&app, "GET", "/v1/movie/heat", None).await;
+    assert_eq!((status, fetched["name"].as_str()), (StatusCode::OK, Some("Heat")));
+    assert_eq!(send(&app, "POST", "/v1/movies/lookup", Some(json!(["heat"]))).await.0, StatusCode::OK);
+    let (status, error) = send(&app, "POST", "/v1/movie", Some(json!({ "name": "Ran", "year": 1985, "was_good": true }))).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert!(error["error"]["message"].as_str().unwrap().contains(&leader_url), "{error}");
+    assert_eq!(send(&app, "DELETE", "/v1/movie/heat", None).await.0, StatusCode::FORBIDDEN);
+    let mutation = json!({ "query": "mutation { deleteMovie(id: \"heat\") { id } }" });
+    let (_, answer) = send(&app, "POST", "/v1/graphql", Some(mutation)).await;
+    assert!(answer["errors"].is_array(), "{answer}");
+    assert!(follower.get("heat").await.is_some());
+
+    let response = app.clone().oneshot(Request::get("/metrics").body(Body::empty()).unwrap()).await.unwrap();
+    let metrics = String::from_utf8(response.into_body().collect().await.unwrap().to_bytes().to_vec()).unwrap();
+    assert!(metrics.lines().any(|line| line.starts_with("movies_follower_last_sync_timestamp_seconds ") && !line.ends_with(" 0")), "{metrics}");
+}
@@ -0,0 +1,44 @@
+use axum::{body::Body, http::{Request, StatusCode}, Router};
+use http_body_util::BodyExt;
+use syndica_rust::test_app;
+use tower::ServiceExt;
+
+async fn get(app: &Router, request: Request<Body>) -> (StatusCode, String, serde_json::Value) {
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let id = response.headers()["x-request-id"].to_str().unwrap().to_string();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, id, serde_json::from_slice(&body).unwrap_or(serde_json::Value::Null))
+}
+
+#[tokio::test]
+async fn ids_are_generated_and_show_up_in_errors() {
+    let app = test_app();
+    let (status, id, body) = get(&app, Request::get("/movie/missing").body(Body::empty()).unwrap()).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(id.len(), 36);
+    assert_eq!(body["error"]["request_id"], id.as_str());
+
+    let (_, other_id, _) = get(&app, Request::get("/healthz").body(Body::empty()).unwrap()).await;
+    assert_ne!(id, other_id);
+}
+
+#[tokio::test]
+async fn inbound_ids_are_honored() {
+    let app = test_app();
+    let request = Request::get("/movie/missing").header("x-request-id", "client-abc-123").body(Body::empty()).unwrap();
+    let (_, id, body) = get(&app, request).await;
+    assert_eq!(id, "client-abc-123");
+    assert_eq!(body["error"]["request_id"], "client-abc-123");
+}
+
+#[tokio::test]
+async fn unreasonable_inbound_ids_are_replaced() {
+    let app = test_app();
+    for inbound in ["has spaces", &"x".repeat(200)] {
+        let request = Request::get("/healthz").header("x-request-id", inbound).body(Body::empty()).unwrap();
+        let (_, id, _) = get(&app, request).await;
+        assert_ne!(id, inbound);
+        assert_eq!(id.len(), 36);
+    }
+}
@@ -0,0 +1,70 @@
+use std::{sync::atomic::{AtomicU32, Ordering}, time::Duration};
+use syndica_rust::resilience::{self, BreakerPolicy, BreakerState, CallError, Integration, RetryPolicy};
+
+const RETRY: RetryPolicy = RetryPolicy { attempts: 3, base_delay: Duration::from_millis(1), max_delay: Duration::from_millis(2) };
+const BREAKER: BreakerPolicy = BreakerPolicy { failures_to_open: 2, open_for: Duration::from_millis(100) };
+
+// Fails the first `failures` attempts with `error`, then succeeds.
+async fn flaky(attempts: &AtomicU32, failures: u32, error: fn() -> CallError) -> Result<u32, CallError> {
+    let attempt = attempts.fetch_add(1, Ordering::SeqCst) + 1;
+    if attempt <= failures { Err(error()) } else { Ok(attempt) }
+}
+
+fn transient() -> CallError {
+    CallError::Transient("returned 503".to_string())
+}
+
+fn permanent() -> CallError {
+    CallError::Permanent("returned 401".to_string())
+}
+
+#[tokio::test]
+async fn transient_failures_are_retried() {
+    let integration = Integration::register("test-retries", RETRY, BREAKER);
+    let attempts = AtomicU32::new(0);
+    assert_eq!(integration.call(|| flaky(&attempts, 2, transient)).await.unwrap(), 3);
+
+    let attempts = AtomicU32::new(0);
+    assert!(matches!(integration.call(|| flaky(&attempts, 5, transient)).await, Err(CallError::Transient(_))));
+    assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    let attempts = AtomicU32::new(0);
+    assert!(matches!(integration.call(|| flaky(&attempts, 5, permanent)).await, Err(CallError::Permanent(_))));
+    assert_eq!(attempts.load(Ordering::SeqCst), 1);
+
+    let stats = resilience::stats().into_iter().find(|stats| stats.name == "test-retries").unwrap();
+    assert_eq!((stats.successes, stats.failures, stats.retries, stats.trips), (1, 2, 4, 1));
+    assert_eq!(resilience::status_error(429).to_string(), "returned 429");
+    assert!(matches!(resilience::status_error(404), CallError::Permanent(_)));
+}
+
+#[tokio::test]
+async fn the_breaker_opens_and_lets_a_trial_through_later() {
+    let integration = Integration::register("test-breaker", RetryPolicy { attempts: 1, ..RETRY }, BREAKER);
+    let attempts = AtomicU32::new(0);
+    for _ in 0..2 {
+        assert!(integration.call(|| flaky(&attempts, 10, transient)).await.is_err());
+    }
+    assert_eq!(integration.state(), BreakerState::Open);
+    assert!(matches!(integration.call(|| flaky(&attempts, 10, transient)).await, Err(CallError::Open)));
+    assert_eq!(attempts.load(Ordering::SeqCst), 2);
+
+    // A failed trial keeps it open for another while.
+    tokio::time::sleep(BREAKER.open_for).await;
+    assert_eq!(integration.state(), BreakerState::HalfOpen);
+    assert!(matches!(integration.call(|| flaky(&attempts, 10, transient)).await, Err(CallError::Transient(_))));
+    assert_eq!(integration.state(), BreakerState::Open);
+
+    tokio::time::sleep(BREAKER.open_for).await;
+    let attempts = AtomicU32::new(0);
+    assert!(integration.call(|| flaky(&attempts, 0, transient)).await.is_ok());
+    assert_eq!(integration.state(), BreakerState::Closed);
+    let stats = resilience::stats().into_iter().find(|stats| stats.name == "test-breaker").unwrap();
+    assert_eq!((stats.rejections, stats.trips), (1, 1));
+
+    for _ in 0..2 {
+        integration.call(|| flaky(&attempts, 10, permanent)).await.ok();
+    }
+    assert_eq!(integration.state(), BreakerState::Open);
+    integration.reset();
+    assert_eq!(integration.state(), BreakerState::Closed);
+}
@@ -0,0 +1,81 @@
+mod common;
+
+use std::sync::Arc;
+
+use axum::{http::StatusCode, Router};
+use serde_json::{json, Value};
+use syndica_rust::{build_router, settings::Settings, wal::{WalConfig, WalMovieStore}};
+use common::{movie, seed, seeded_app, send};
+
+async fn review(app: &Router, author: &str, text: &str) -> (StatusCode, Value) {
+    send(app, "POST", "/v1/movie/alien/reviews", Some(json!({ "author": author, "text": text }))).await
+}
+
+#[tokio::test]
+async fn reviews_are_listed_newest_first() {
+    let app = seeded_app([movie("alien", "Alien", 1979)]).await;
+    let (status, first) = review(&app, "ripley", "Don't go in the vents.").await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!((first["author"].as_str(), first["text"].as_str()), (Some("ripley"), Some("Don't go in the vents.")));
+    assert!(first["id"].is_string());
+    assert!(first["created_at"].as_str().unwrap().ends_with('Z'));
+    review(&app, "dallas", "Should have stayed on the ship.").await;
+    review(&app, "ash", "A perfect organism.").await;
+
+    let (_, movie) = send(&app, "GET", "/v1/movie/alien", None).await;
+    assert_eq!(movie["review_count"], 3);
+    assert!(movie.get("reviews").is_none());
+
+    let (status, page) = send(&app, "GET", "/v1/movie/alien/reviews?limit=2", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(page["total"], 3);
+    let authors: Vec<&str> = page["items"].as_array().unwrap().iter().map(|review| review["author"].as_str().unwrap()).collect();
+    assert_eq!(authors, ["ash", "dallas"]);
+    let (_, page) = send(&app, "GET", page["next"].as_str().unwrap(), None).await;
+    assert_eq!(page["items"], json!([first]));
+    assert_eq!(page["next"], Value::Null);
+}
+
+#[tokio::test]
+async fn invalid_reviews_are_rejected() {
+    let app = seeded_app([movie("alien", "Alien", 1979)]).await;
+    for body in [json!({ "author": "ripley", "text": "" }), json!({ "author": "", "text": "Great." }), json!({ "text": "Great." })] {
+        let (status, body) = send(&app, "POST", "/v1/movie/alien/reviews", Some(body)).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["error"]["code"], "validation_failed");
+    }
+    let (status, _) = send(&app, "POST", "/v1/movie/missing/reviews", Some(json!({ "author": "ripley", "text": "Great." }))).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn reviews_go_with_their_movie() {
+    let app = seeded_app([movie("alien", "Alien", 1979)]).await;
+    review(&app, "ripley", "Don't go in the vents.").await;
+    // Replacing the movie keeps them.
+    let (_, mut alien) = send(&app, "GET", "/v1/movie/alien", None).await;
+    alien["review_count"] = json!(0);
+    let (_, replaced) = send(&app, "PUT", "/v1/movie/alien", Some(alien)).await;
+    assert_eq!(replaced["review_count"], 1);
+
+    assert_eq!(send(&app, "DELETE", "/v1/movie/alien", None).await.0, StatusCode::NO_CONTENT);
+    assert_eq!(send(&app, "GET", "/v1/movie/alien/reviews", None).await.0, StatusCode::NOT_FOUND);
+    seed(&app, [movie("alien", "Alien", 1979)]).await;
+    let (_, page) = send(&app, "GET", "/v1/movie/alien/reviews", None).await;
+    assert_eq!(page["total"], 0);
+}
+
+#[tokio::test]
+async fn reviews_survive_a_restart() {
+    let dir = std::env::temp_dir().join(format!("syndica-reviews-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let config = WalConfig { path: dir.join("movies.wal"), max_bytes: u64::MAX };
+    let app = build_router(Arc::new(WalMovieStore::open(config.clone()).await.unwrap()), Settings::default());
+    seed(&app, [movie("alien", "Alien", 1979)]).await;
+    let (_, written) = review(&app, "ripley", "Don't go in the vents.").await;
+
+    let app = build_router(Arc::new(WalMovieStore::open(config).await.unwrap()), Settings::default());
+    let (_, page) = send(&app, "GET", "/v1/movie/alien/reviews", None).await;
+    assert_eq!(page["items"], json!([written]));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
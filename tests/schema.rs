@@ -0,0 +1,105 @@
+use std::path::{Path, PathBuf};
+use syndica_rust::{
+    event_sourced::EventSourcedMovieStore,
+    schema,
+    snapshot::SnapshotMovieStore,
+    store::MovieStore,
+    wal::{WalConfig, WalMovieStore},
+};
+
+// What the stores keep on disk is stamped with its schema version: older data is migrated as it's opened, and data from
+// a newer version is refused.
+
+fn path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("syndica-schema-{}-{}", std::process::id(), name));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+fn lines(path: &Path) -> Vec<String> {
+    std::fs::read_to_string(path).unwrap().lines().map(str::to_string).collect()
+}
+
+async fn open_wal(path: &Path) -> std::io::Result<WalMovieStore> {
+    WalMovieStore::open(WalConfig { path: path.to_path_buf(), max_bytes: u64::MAX }).await
+}
+
+const OLD_WAL: &str = "{\"op\":\"insert\",\"movie\":{\"id\":\"alien\",\"name\":\"Alien\",\"year\":1979,\"was_good\":true,\"version\":1}}\n";
+const OLD_EVENTS: &str = "{\"seq\":1,\"at\":\"2026-01-01T00:00:00Z\",\"event\":\"movie_created\",\"movie\":{\"id\":\"alien\",\"name\":\"Alien\",\"year\":1979,\"was_good\":true,\"version\":1}}\n";
+
+#[tokio::test]
+async fn new_stores_start_in_the_current_version() {
+    let wal = path("new.wal");
+    let store = open_wal(&wal).await.unwrap();
+    drop(store);
+    assert_eq!(lines(&wal), [r#"{"schema":2}"#]);
+    // Opening it again doesn't stamp it twice.
+    drop(open_wal(&wal).await.unwrap());
+    assert_eq!(lines(&wal).len(), 1);
+
+    let events = path("new.events");
+    drop(EventSourcedMovieStore::open(events.clone()).await.unwrap());
+    assert_eq!(lines(&events), [r#"{"schema":2}"#]);
+    for path in [wal, events] {
+        std::fs::remove_file(path).unwrap();
+    }
+}
+
+#[tokio::test]
+async fn data_from_a_newer_version_is_refused() {
+    let wal = path("newer.wal");
+    std::fs::write(&wal, format!("{}{{\"schema\":9}}\n", OLD_WAL)).unwrap();
+    let error = open_wal(&wal).await.err().unwrap();
+    assert!(error.to_string().contains("schema version 9"), "{}", error);
+    // And left as it was.
+    assert_eq!(lines(&wal).len(), 2);
+
+    let events = path("newer.events");
+    std::fs::write(&events, "{\"schema\":3}\n").unwrap();
+    assert!(EventSourcedMovieStore::open(events.clone()).await.is_err());
+
+    let snapshot = path("newer.json");
+    std::fs::write(&snapshot, r#"{"schema_version":4,"movies":[],"users":[]}"#).unwrap();
+    assert!(SnapshotMovieStore::open(snapshot.clone()).await.is_err());
+    for path in [wal, events, snapshot] {
+        std::fs::remove_file(path).unwrap();
+    }
+}
+
+// The only test turning auto_migrate off, since it's process-wide.
+#[tokio::test]
+async fn older_data_is_migrated_unless_auto_migrate_is_off() {
+    let wal = path("old.wal");
+    let events = path("old.events");
+    let snapshot = path("old.json");
+    std::fs::write(&wal, OLD_WAL).unwrap();
+    std::fs::write(&events, OLD_EVENTS).unwrap();
+    std::fs::write(&snapshot, r#"[{"id":"alien","name":"Alien","year":1979,"was_good":true}]"#).unwrap();
+
+    schema::set_auto_migrate(false);
+    let refused = open_wal(&wal).await.err().unwrap();
+    assert!(refused.to_string().contains("syndica-rust migrate"), "{}", refused);
+    assert!(EventSourcedMovieStore::open(events.clone()).await.is_err());
+    assert!(SnapshotMovieStore::open(snapshot.clone()).await.is_err());
+    assert_eq!(lines(&wal), [OLD_WAL.trim_end()]);
+    schema::set_auto_migrate(true);
+
+    // The logs keep what they had, and are stamped so what's appended after is in the current version.
+    let store = open_wal(&wal).await.unwrap();
+    assert_eq!(store.get("alien").await.unwrap().name, "Alien");
+    drop(store);
+    assert_eq!(lines(&wal), [OLD_WAL.trim_end(), r#"{"schema":2}"#]);
+    let store = EventSourcedMovieStore::open(events.clone()).await.unwrap();
+    assert_eq!(store.count().await, 1);
+    drop(store);
+    assert_eq!(lines(&events), [OLD_EVENTS.trim_end(), r#"{"schema":2}"#]);
+
+    // A snapshot is written out in the current version at its next flush.
+    let store = SnapshotMovieStore::open(snapshot.clone()).await.unwrap();
+    store.flush().await.unwrap();
+    let written: serde_json::Value = serde_json::from_slice(&std::fs::read(&snapshot).unwrap()).unwrap();
+    assert_eq!((written["schema_version"].as_u64(), written["movies"][0]["id"].as_str()), (Some(3), Some("alien")));
+    for path in [wal, events, snapshot] {
+        std::fs::remove_file(path).unwrap();
+    }
+}
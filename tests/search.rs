@@ -0,0 +1,121 @@
+mod common;
+
+use axum::{body::Body, http::{Request, StatusCode}, Router};
+use http_body_util::BodyExt;
+use serde_json::{json, Value};
+use tower::ServiceExt;
+use common::{seeded_app, send};
+
+fn catalog() -> Vec<Value> {
+    let movies = [
+        ("fight-club", "Fight Club", 1999, true),
+        ("inception", "Inception", 2010, true),
+        ("matrix", "The Matrix", 1999, true),
+        ("matrix-reloaded", "The Matrix Reloaded", 2003, false),
+        ("matrix-resurrections", "The Matrix Resurrections", 2021, false),
+        ("phantom-menace", "Star Wars: Episode I - The Phantom Menace", 1999, false),
+        ("tom-jerry", "Tom & Jerry", 2021, false),
+        ("amelie", "Amélie", 2001, true),
+    ];
+    movies.into_iter().map(|(id, name, year, was_good)| json!({ "id": id, "name": name, "year": year, "was_good": was_good })).collect()
+}
+
+async fn search(app: &Router, query: &str) -> (StatusCode, Value) {
+    send(app, "GET", &format!("/v1/movies/search?{query}"), None).await
+}
+
+fn ids(page: &serde_json::Value) -> Vec<&str> {
+    page["items"].as_array().unwrap().iter().map(|hit| hit["movie"]["id"].as_str().unwrap()).collect()
+}
+
+#[tokio::test]
+async fn matches_words_ignoring_case() {
+    let app = seeded_app(catalog()).await;
+    let (status, page) = search(&app, "q=MATRIX").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(page["total"], 3);
+    // The shortest name has the most of its words matched.
+    assert_eq!(ids(&page), ["matrix", "matrix-reloaded", "matrix-resurrections"]);
+    assert_eq!(page["items"][1]["highlighted"], "The <em>Matrix</em> Reloaded");
+}
+
+#[tokio::test]
+async fn matches_words_ignoring_accents() {
+    let app = seeded_app(catalog()).await;
+    for query in ["q=amelie", "q=AM%C3%89LIE", "q=ame"] {
+        let (_, page) = search(&app, query).await;
+        assert_eq!(ids(&page), ["amelie"], "{query}");
+        assert_eq!(page["items"][0]["highlighted"], "<em>Amélie</em>");
+    }
+    // And as it's filtered by name too.
+    let request = Request::get("/v1/movies?name_contains=AMEL").body(Body::empty()).unwrap();
+    let body = app.oneshot(request).await.unwrap().into_body().collect().await.unwrap().to_bytes();
+    let page: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(page["items"][0]["id"], "amelie");
+}
+
+#[tokio::test]
+async fn matches_whole_words_only() {
+    let app = seeded_app(catalog()).await;
+    // "at" is inside Matrix, but doesn't start any word.
+    let (_, page) = search(&app, "q=at").await;
+    assert_eq!(page["total"], 0);
+    assert_eq!(page["next"], serde_json::Value::Null);
+}
+
+#[tokio::test]
+async fn prefixes_match_but_rank_below_whole_words() {
+    let app = seeded_app(catalog()).await;
+    let (_, page) = search(&app, "q=re").await;
+    assert_eq!(ids(&page), ["matrix-reloaded", "matrix-resurrections"]);
+    assert_eq!(page["items"][0]["highlighted"], "The Matrix <em>Reloaded</em>");
+
+    let (_, whole) = search(&app, "q=reloaded").await;
+    assert!(whole["items"][0]["score"].as_f64().unwrap() > page["items"][0]["score"].as_f64().unwrap());
+}
+
+#[tokio::test]
+async fn more_matched_words_rank_higher() {
+    let app = seeded_app(catalog()).await;
+    let (_, page) = search(&app, "q=matrix%20reloaded").await;
+    assert_eq!(ids(&page)[0], "matrix-reloaded");
+    assert_eq!(page["items"][0]["highlighted"], "The <em>Matrix</em> <em>Reloaded</em>");
+}
+
+#[tokio::test]
+async fn rare_words_count_for_more() {
+    let app = seeded_app(catalog()).await;
+    // "the" is in four names and "club" in one.
+    let (_, page) = search(&app, "q=the%20club").await;
+    assert_eq!(ids(&page)[0], "fight-club");
+}
+
+#[tokio::test]
+async fn highlighted_names_are_escaped() {
+    let app = seeded_app(catalog()).await;
+    let (_, page) = search(&app, "q=jerry").await;
+    assert_eq!(page["items"][0]["highlighted"], "Tom &amp; <em>Jerry</em>");
+}
+
+#[tokio::test]
+async fn pages_through_results() {
+    let app = seeded_app(catalog()).await;
+    let (_, first) = search(&app, "q=matrix&limit=2").await;
+    assert_eq!(ids(&first), ["matrix", "matrix-reloaded"]);
+    assert_eq!(first["total"], 3);
+    let next = first["next"].as_str().unwrap();
+    assert_eq!(next, "/v1/movies/search?q=matrix&limit=2&offset=2");
+
+    let (_, second) = search(&app, next.split_once('?').unwrap().1).await;
+    assert_eq!(ids(&second), ["matrix-resurrections"]);
+    assert_eq!(second["next"], serde_json::Value::Null);
+}
+
+#[tokio::test]
+async fn query_without_words_is_rejected() {
+    let app = seeded_app(catalog()).await;
+    for query in ["", "q=", "q=%20-%20"] {
+        let (status, _) = search(&app, query).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{query}");
+    }
+}
@@ -0,0 +1,72 @@
+use std::time::Duration;
+
+use axum::{body::Body, http::{Request, StatusCode}, Router};
+use serde_json::json;
+use syndica_rust::{audit, build_router, seed::{self, SeedReport}, settings::Settings, state::state_init};
+use tower::ServiceExt;
+
+async fn status(app: &Router, uri: &str) -> StatusCode {
+    app.clone().oneshot(Request::get(uri).body(Body::empty()).unwrap()).await.unwrap().status()
+}
+
+#[tokio::test]
+async fn seeding_reports_duplicates_and_failures() {
+    let state = state_init();
+    let app = build_router(state.clone(), Settings::default());
+    let request = Request::post("/v1/movie").header("content-type", "application/json")
+        .body(Body::from(json!({ "id": "heat", "name": "Heat", "year": 1995, "was_good": true }).to_string())).unwrap();
+    assert_eq!(app.clone().oneshot(request).await.unwrap().status(), StatusCode::CREATED);
+
+    let entries = vec![
+        json!({ "id": "alien", "name": "Alien", "year": 1979, "was_good": true, "genres": ["horror"] }),
+        json!({ "id": "heat", "name": "Heat", "year": 1995, "was_good": true }),
+        json!({ "id": "ran", "name": "Ran", "year": 1700, "was_good": true }),
+        json!({ "id": "alien", "name": "Alien again", "year": 1979, "was_good": false }),
+        json!({ "name": "Ran", "year": 1985 }),
+        json!({ "name": "No id", "year": 2001, "was_good": false }),
+    ];
+    let report = seed::load(&state, entries).await;
+    assert_eq!((report.inserted, report.duplicates.as_slice()), (2, ["heat".to_string(), "alien".to_string()].as_slice()));
+    let failed: Vec<usize> = report.failures.iter().map(|(index, _)| *index).collect();
+    assert_eq!(failed, [2, 4]);
+    assert!(report.failures[0].1.starts_with("year: "));
+    assert!(report.failures[1].1.contains("was_good"));
+
+    assert_eq!(status(&app, "/v1/movie/alien").await, StatusCode::OK);
+    assert_eq!(state.count().await, 3);
+    assert!(audit::entries(None, Some("seed")).iter().any(|entry| entry.id == "alien"));
+    // Nothing new the second time round.
+    let again = seed::load(&state, vec![json!({ "id": "alien", "name": "Alien", "year": 1979, "was_good": true })]).await;
+    assert_eq!(again, SeedReport { inserted: 0, duplicates: vec!["alien".to_string()], failures: Vec::new() });
+}
+
+#[test]
+fn seed_files_have_to_be_an_array() {
+    let dir = std::env::temp_dir().join(format!("syndica-seed-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("movies.json"), r#"[{ "name": "Alien", "year": 1979, "was_good": true }, 7]"#).unwrap();
+    std::fs::write(dir.join("movie.json"), r#"{ "name": "Alien", "year": 1979, "was_good": true }"#).unwrap();
+    assert_eq!(seed::read(&dir.join("movies.json")).unwrap().len(), 2);
+    assert!(seed::read(&dir.join("movie.json")).unwrap_err().starts_with("expected a JSON array of movies"));
+    assert!(seed::read(&dir.join("nope.json")).is_err());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+// Whether seeding is pending is process-wide, so only this test starts any.
+#[tokio::test]
+async fn not_ready_until_seeded() {
+    let state = state_init();
+    let app = build_router(state.clone(), Settings::default());
+    let entries = (0..500).map(|i| json!({ "id": format!("movie-{i}"), "name": format!("Movie {i}"), "year": 2000, "was_good": true })).collect();
+    seed::start(state.clone(), entries);
+    assert_eq!(status(&app, "/readyz").await, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(status(&app, "/healthz").await, StatusCode::OK);
+    for _ in 0..100 {
+        if !seed::is_pending() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(status(&app, "/readyz").await, StatusCode::OK);
+    assert_eq!(state.count().await, 500);
+}
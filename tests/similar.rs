@@ -0,0 +1,85 @@
+mod common;
+
+use axum::{http::StatusCode, Router};
+use serde_json::Value;
+use syndica_rust::similar::{jaro_winkler, levenshtein, normalize};
+use common::{movie, seeded_app, send};
+
+fn catalog() -> Vec<Value> {
+    let movies = [
+        ("matrix", "The Matrix", 1999),
+        ("matrix-reloaded", "The Matrix Reloaded", 2003),
+        ("inception", "Inception", 2010),
+        ("solaris", "Solaris", 1972),
+        ("solaris-remake", "Solaris", 2002),
+    ];
+    movies.into_iter().map(|(id, name, year)| movie(id, name, year)).collect()
+}
+
+async fn similar(app: &Router, query: &str) -> (StatusCode, Value) {
+    send(app, "GET", &format!("/v1/movies/similar?{query}"), None).await
+}
+
+fn ids(body: &serde_json::Value) -> Vec<&str> {
+    body["items"].as_array().unwrap().iter().map(|item| item["movie"]["id"].as_str().unwrap()).collect()
+}
+
+#[test]
+fn distances() {
+    let chars = |s: &str| s.chars().collect::<Vec<char>>();
+    assert_eq!(levenshtein(&chars("kitten"), &chars("sitting")), 3);
+    assert_eq!(levenshtein(&chars(""), &chars("abc")), 3);
+    assert!((jaro_winkler(&chars("martha"), &chars("marhta")) - 0.9611).abs() < 0.001);
+    assert!((jaro_winkler(&chars("dixon"), &chars("dicksonx")) - 0.8133).abs() < 0.001);
+    assert_eq!(jaro_winkler(&chars("abc"), &chars("xyz")), 0.0);
+    assert_eq!(normalize("  The MATRIX: Reloaded (2003) "), ("matrix reloaded".to_string(), Some(2003)));
+    assert_eq!(normalize("Se7en"), ("se7en".to_string(), None));
+    assert_eq!(normalize("AMÉLIE's Fabuleux Destin"), ("amelies fabuleux destin".to_string(), None));
+    assert_eq!(normalize("The"), ("the".to_string(), None));
+}
+
+#[tokio::test]
+async fn catches_typos_and_years_in_the_name() {
+    let app = seeded_app(catalog()).await;
+    let (status, body) = similar(&app, "name=The%20Matr1x%20(1999)").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(ids(&body)[0], "matrix");
+    assert_eq!(body["items"][0]["distance"], 1);
+    assert!(!ids(&body).contains(&"inception"));
+}
+
+#[tokio::test]
+async fn exact_matches_score_one() {
+    let app = seeded_app(catalog()).await;
+    let (_, body) = similar(&app, "name=the%20matrix").await;
+    assert_eq!(body["items"][0]["movie"]["id"], "matrix");
+    assert_eq!(body["items"][0]["score"], 1.0);
+    assert_eq!(body["items"][0]["distance"], 0);
+}
+
+#[tokio::test]
+async fn other_years_rank_lower() {
+    let app = seeded_app(catalog()).await;
+    let (_, body) = similar(&app, "name=Solaris&year=2002").await;
+    assert_eq!(ids(&body), ["solaris-remake", "solaris"]);
+    let (_, body) = similar(&app, "name=Solaris").await;
+    assert_eq!(ids(&body), ["solaris", "solaris-remake"]);
+}
+
+#[tokio::test]
+async fn threshold_and_limit() {
+    let app = seeded_app(catalog()).await;
+    let (_, body) = similar(&app, "name=The%20Matrix&threshold=0.5").await;
+    assert_eq!(ids(&body)[..2], ["matrix", "matrix-reloaded"]);
+    let (_, body) = similar(&app, "name=The%20Matrix&threshold=0.5&limit=1").await;
+    assert_eq!(ids(&body), ["matrix"]);
+}
+
+#[tokio::test]
+async fn bad_parameters_are_rejected() {
+    let app = seeded_app(catalog()).await;
+    for query in ["", "name=", "name=%20-%20", "name=x&threshold=1.5"] {
+        let (status, _) = similar(&app, query).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{query}");
+    }
+}
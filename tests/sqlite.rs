@@ -0,0 +1,87 @@
+#![cfg(feature = "sqlite")]
+
+mod common;
+
+use std::{path::{Path, PathBuf}, sync::Arc};
+
+use axum::{http::StatusCode, Router};
+use serde_json::json;
+use syndica_rust::{build_router, settings::Settings, sqlite::SqliteMovieStore};
+use common::{movie, seed, send};
+
+fn dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("syndica-sqlite-{}-{}", std::process::id(), name));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+async fn open(path: &Path) -> Router {
+    build_router(Arc::new(SqliteMovieStore::open(path.to_path_buf()).await.unwrap()), Settings::default())
+}
+
+#[tokio::test]
+async fn everything_survives_a_restart() {
+    let dir = dir("restart");
+    let path = dir.join("movies.db");
+    let app = open(&path).await;
+    seed(&app, [movie("alien", "Alien", 1979), movie("aliens", "Aliens", 1986), movie("heat", "Heat", 1995), movie("ran", "Ran", 1985)]).await;
+    send(&app, "PATCH", "/v1/movie/ran", Some(json!({ "year": 1986 }))).await;
+    send(&app, "POST", "/v1/users", Some(json!({ "id": "ripley", "name": "Ellen Ripley" }))).await;
+    for movie_id in ["alien", "heat"] {
+        send(&app, "POST", "/v1/users/ripley/watchlist", Some(json!({ "movie_id": movie_id }))).await;
+    }
+    send(&app, "POST", "/v1/movie/aliens/related", Some(json!({ "movie_id": "alien", "relation": "sequel-of" }))).await;
+    send(&app, "POST", "/v1/movie/heat/related", Some(json!({ "movie_id": "ran", "relation": "remake-of" }))).await;
+    // Takes heat off the watchlist and unlinks it, which has to be written too.
+    assert_eq!(send(&app, "DELETE", "/v1/movie/heat", None).await.0, StatusCode::NO_CONTENT);
+
+    // The file is a real SQLite database, with its tables in it.
+    assert!(std::fs::read(&path).unwrap().starts_with(b"SQLite format 3\0"));
+
+    let app = open(&path).await;
+    let (_, page) = send(&app, "GET", "/v1/movies", None).await;
+    let ids: Vec<&str> = page["items"].as_array().unwrap().iter().map(|movie| movie["id"].as_str().unwrap()).collect();
+    assert_eq!(ids, ["alien", "aliens", "ran"]);
+    let (_, ran) = send(&app, "GET", "/v1/movie/ran", None).await;
+    assert_eq!((ran["year"].clone(), ran["version"].clone()), (json!(1986), json!(2)));
+    assert_eq!(send(&app, "GET", "/v1/users/ripley", None).await.1["watchlist"], json!(["alien"]));
+    let (_, related) = send(&app, "GET", "/v1/movie/aliens/related", None).await;
+    assert_eq!(related["items"][0]["movie"]["id"], "alien");
+    assert_eq!(send(&app, "GET", "/v1/movie/ran/related", None).await.1["items"], json!([]));
+    let (_, trash) = send(&app, "GET", "/v1/movies/trash", None).await;
+    assert_eq!(trash["items"][0]["movie"]["id"], "heat");
+
+    assert_eq!(send(&app, "POST", "/v1/movie/heat/restore", None).await.0, StatusCode::OK);
+    let app = open(&path).await;
+    assert_eq!(send(&app, "GET", "/v1/movie/heat", None).await.1["name"], "Heat");
+    assert_eq!(send(&app, "GET", "/v1/movies/trash", None).await.1["items"], json!([]));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn databases_from_a_newer_version_are_refused() {
+    let dir = dir("newer");
+    let path = dir.join("movies.db");
+    seed(&open(&path).await, [movie("alien", "Alien", 1979)]).await;
+
+    // SQLite keeps user_version in the four bytes at offset 60 of the file's header.
+    let mut file = std::fs::read(&path).unwrap();
+    file[60..64].copy_from_slice(&99u32.to_be_bytes());
+    std::fs::write(&path, file).unwrap();
+    let error = SqliteMovieStore::open(path.clone()).await.err().unwrap();
+    assert!(error.to_string().contains("schema version 99"), "{}", error);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn not_ready_once_writes_would_fail() {
+    let dir = dir("ready");
+    let app = open(&dir.join("movies.db")).await;
+    assert_eq!(send(&app, "GET", "/readyz", None).await.0, StatusCode::OK);
+
+    // Writes need a journal next to the database, which can't be made once its directory has gone.
+    std::fs::remove_dir_all(&dir).unwrap();
+    let (status, body) = send(&app, "GET", "/readyz", None).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(body["error"]["code"], "unavailable");
+}
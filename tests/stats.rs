@@ -0,0 +1,51 @@
+mod common;
+
+use axum::{http::StatusCode, Router};
+use serde_json::json;
+use syndica_rust::test_app;
+use common::{seed, send};
+
+async fn add(app: &Router, id: &str, year: u16, was_good: bool) {
+    seed(app, [json!({ "id": id, "name": id, "year": year, "was_good": was_good })]).await;
+}
+
+async fn stats(app: &Router) -> serde_json::Value {
+    let (status, stats) = send(app, "GET", "/v1/movies/stats", None).await;
+    assert_eq!(status, StatusCode::OK);
+    stats
+}
+
+#[tokio::test]
+async fn empty_catalog() {
+    let app = test_app();
+    assert_eq!(stats(&app).await, json!({
+        "total": 0,
+        "by_year": {},
+        "by_was_good": { "true": 0, "false": 0 },
+        "min_year": null,
+        "max_year": null,
+        "median_year": null,
+    }));
+}
+
+#[tokio::test]
+async fn counts_and_years() {
+    let app = test_app();
+    add(&app, "a", 1999, true).await;
+    add(&app, "b", 1999, false).await;
+    add(&app, "c", 2003, false).await;
+    assert_eq!(stats(&app).await, json!({
+        "total": 3,
+        "by_year": { "1999": 2, "2003": 1 },
+        "by_was_good": { "true": 1, "false": 2 },
+        "min_year": 1999,
+        "max_year": 2003,
+        "median_year": 1999.0,
+    }));
+
+    // An even number of movies has the median between the middle two.
+    add(&app, "d", 2010, true).await;
+    let stats = stats(&app).await;
+    assert_eq!(stats["total"], 4);
+    assert_eq!(stats["median_year"], 2001.0);
+}
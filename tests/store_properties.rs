@@ -0,0 +1,255 @@
+use std::{collections::BTreeMap, path::{Path, PathBuf}, sync::Arc};
+
+use syndica_rust::{
+    cache::{CacheConfig, CachedMovieStore},
+    event_sourced::EventSourcedMovieStore,
+    model::Movie,
+    quota::QuotaMovieStore,
+    snapshot::SnapshotMovieStore,
+    state::{state_init, StateWrapper},
+    store::{MovieFilter, StoreError},
+    tenant::{TenantConfig, TenantMovieStore},
+    wal::{WalConfig, WalMovieStore},
+};
+#[cfg(feature = "sqlite")]
+use syndica_rust::sqlite::SqliteMovieStore;
+
+// Random runs of creates, updates and deletes against every store, each checked after every step against a plain map
+// of what should be there. The runs are seeded, so a failure names the seed and the steps that led to it, and running
+// again gives the same steps.
+
+const RUNS: u64 = 24;
+const STEPS: usize = 40;
+// Few enough ids that the steps keep running into each other's movies.
+const IDS: [&str; 5] = ["alien", "heat", "ran", "amelie", "tiger"];
+
+// xorshift64*, which is plenty for picking steps and needs no more than the seed to replay.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Rng {
+        Rng(seed.wrapping_mul(0x9e3779b97f4a7c15) | 1)
+    }
+
+    fn below(&mut self, bound: usize) -> usize {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        (self.0.wrapping_mul(0x2545f4914f6cdd1d) >> 32) as usize % bound
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Step {
+    Insert(&'static str, u16),
+    Upsert(&'static str, u16),
+    Update(&'static str, u16),
+    Delete(&'static str),
+    // Stores the movie at the version given, as a follower copies its leader's.
+    Put(&'static str, u16, u64),
+}
+
+impl Step {
+    fn random(rng: &mut Rng) -> Step {
+        let id = IDS[rng.below(IDS.len())];
+        let year = 1950 + rng.below(70) as u16;
+        match rng.below(5) {
+            0 => Step::Insert(id, year),
+            1 => Step::Upsert(id, year),
+            2 => Step::Update(id, year),
+            3 => Step::Put(id, year, 1 + rng.below(100) as u64),
+            _ => Step::Delete(id),
+        }
+    }
+}
+
+fn movie(id: &str, year: u16) -> Movie {
+    // Version 1, as NewMovie::into_movie gives it. Stores number the versions after that themselves.
+    Movie { id: id.to_string(), name: format!("{} ({})", id, year), year, was_good: year.is_multiple_of(2), version: 1, ..Default::default() }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Kind {
+    Memory,
+    Snapshot,
+    Wal,
+    EventSourced,
+    Cached,
+    Tenant,
+    Quota,
+    #[cfg(feature = "sqlite")]
+    Sqlite,
+}
+
+const KINDS: &[Kind] = &[Kind::Memory, Kind::Snapshot, Kind::Wal, Kind::EventSourced, Kind::Cached, Kind::Tenant, Kind::Quota, #[cfg(feature = "sqlite")] Kind::Sqlite];
+
+struct Opened {
+    store: StateWrapper,
+    // Kept to flush it before it's opened again, since it only writes its file when flushed.
+    snapshot: Option<Arc<SnapshotMovieStore>>,
+}
+
+impl Kind {
+    async fn open(self, path: &Path) -> Opened {
+        let store: StateWrapper = match self {
+            Kind::Memory => state_init(),
+            Kind::Snapshot => {
+                let snapshot = Arc::new(SnapshotMovieStore::open(path.to_path_buf()).await.unwrap());
+                return Opened { store: snapshot.clone(), snapshot: Some(snapshot) };
+            },
+            // Small enough to be compacted a few times in a run.
+            Kind::Wal => Arc::new(WalMovieStore::open(WalConfig { path: path.to_path_buf(), max_bytes: 2048 }).await.unwrap()),
+            Kind::EventSourced => Arc::new(EventSourcedMovieStore::open(path.to_path_buf()).await.unwrap()),
+            Kind::Cached => Arc::new(CachedMovieStore::new(state_init(), CacheConfig { capacity: 2, ttl: None })),
+            Kind::Tenant => Arc::new(TenantMovieStore::new(state_init(), vec![(TenantConfig { id: "team-a".to_string(), max_movies: None }, state_init())])),
+            Kind::Quota => Arc::new(QuotaMovieStore::new(state_init(), None)),
+            #[cfg(feature = "sqlite")]
+            Kind::Sqlite => Arc::new(SqliteMovieStore::open(path.to_path_buf()).await.unwrap()),
+        };
+        Opened { store, snapshot: None }
+    }
+
+    fn persists(self) -> bool {
+        match self {
+            Kind::Snapshot | Kind::Wal | Kind::EventSourced => true,
+            #[cfg(feature = "sqlite")]
+            Kind::Sqlite => true,
+            _ => false,
+        }
+    }
+}
+
+// What should be stored after each step: the movies by id, with how many times each has been written.
+type Model = BTreeMap<&'static str, (Movie, u64)>;
+
+// Applies the step to the model, and says whether the store should have taken it.
+fn expect(model: &mut Model, step: &Step) -> bool {
+    match *step {
+        Step::Insert(id, year) => {
+            if model.contains_key(id) {
+                return false;
+            }
+            model.insert(id, (movie(id, year), 1));
+        },
+        Step::Upsert(id, year) => {
+            let writes = model.get(id).map_or(0, |(_, writes)| *writes);
+            model.insert(id, (movie(id, year), writes + 1));
+        },
+        Step::Update(id, year) => {
+            let Some((stored, writes)) = model.get_mut(id) else {
+                return false;
+            };
+            *stored = movie(id, year);
+            *writes += 1;
+        },
+        Step::Delete(id) => return model.remove(id).is_some(),
+        // Counted as that many writes, so the versions after it follow on from it.
+        Step::Put(id, year, version) => {
+            model.insert(id, (movie(id, year), version));
+        },
+    }
+    true
+}
+
+async fn apply(store: &StateWrapper, step: &Step) -> Result<(), StoreError> {
+    match *step {
+        Step::Insert(id, year) => store.insert(movie(id, year)).await,
+        Step::Upsert(id, year) => store.upsert(movie(id, year)).await.map(|_| ()),
+        Step::Update(id, year) => store.update(movie(id, year)).await,
+        Step::Delete(id) => store.delete(id).await.map(|_| ()),
+        Step::Put(id, year, version) => store.put(Movie { version, ..movie(id, year) }).await.map(|_| ()),
+    }
+}
+
+// Everything that should hold between any two steps.
+async fn check(store: &StateWrapper, model: &Model) -> Result<(), String> {
+    for id in IDS {
+        let stored = store.get(id).await;
+        match (model.get(id), &stored) {
+            (None, None) => {},
+            (Some((expected, writes)), Some(stored)) => {
+                if (&stored.name, stored.year, stored.was_good) != (&expected.name, expected.year, expected.was_good) {
+                    return Err(format!("{} is {:?}, expected {:?}", id, stored, expected));
+                }
+                if stored.version != *writes {
+                    return Err(format!("{} is at version {} after {} writes", id, stored.version, writes));
+                }
+            },
+            (expected, stored) => return Err(format!("{} is {:?}, expected {:?}", id, stored, expected)),
+        }
+    }
+
+    let listed: Vec<String> = store.list(&MovieFilter::default()).await.into_iter().map(|movie| movie.id).collect();
+    let expected: Vec<&str> = model.keys().copied().collect();
+    if listed != expected {
+        return Err(format!("listed {:?}, expected {:?}", listed, expected));
+    }
+    let count = store.count().await;
+    if count != model.len() {
+        return Err(format!("count is {}, expected {}", count, model.len()));
+    }
+
+    // Paging through with scan meets each movie once.
+    let mut scanned = Vec::new();
+    loop {
+        let page = store.scan(scanned.last().map(String::as_str), 2).await;
+        let full = page.len() == 2;
+        scanned.extend(page.into_iter().map(|movie| movie.id));
+        if !full {
+            break;
+        }
+    }
+    if scanned != expected {
+        return Err(format!("scanned {:?}, expected {:?}", scanned, expected));
+    }
+    Ok(())
+}
+
+fn path(kind: Kind, seed: u64) -> PathBuf {
+    std::env::temp_dir().join(format!("syndica-properties-{}-{:?}-{}", std::process::id(), kind, seed))
+}
+
+async fn run(kind: Kind, seed: u64) -> Result<(), String> {
+    let path = path(kind, seed);
+    let opened = kind.open(&path).await;
+    let mut rng = Rng::new(seed);
+    let mut model = Model::new();
+    let mut steps = Vec::new();
+    for _ in 0..STEPS {
+        let step = Step::random(&mut rng);
+        steps.push(step.clone());
+        let fail = |problem: String| format!("{:?} with seed {}: {}, after {:?}", kind, seed, problem, steps);
+        let accepted = expect(&mut model, &step);
+        match (apply(&opened.store, &step).await, accepted) {
+            (Ok(()), true) => {},
+            (Err(StoreError::AlreadyExists(_)), false) if matches!(step, Step::Insert(..)) => {},
+            (Err(StoreError::NotFound), false) if matches!(step, Step::Update(..) | Step::Delete(_)) => {},
+            (result, _) => return Err(fail(format!("got {:?}", result.err()))),
+        }
+        check(&opened.store, &model).await.map_err(fail)?;
+    }
+
+    // What a store on disk kept is what it had.
+    if kind.persists() {
+        if let Some(snapshot) = &opened.snapshot {
+            snapshot.flush().await.unwrap();
+        }
+        drop(opened);
+        let reopened = kind.open(&path).await;
+        check(&reopened.store, &model).await.map_err(|problem| format!("{:?} with seed {} after reopening: {}, after {:?}", kind, seed, problem, steps))?;
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn every_store_keeps_what_it_was_given() {
+    for &kind in KINDS {
+        for seed in 0..RUNS {
+            let result = run(kind, seed).await;
+            let _ = std::fs::remove_file(path(kind, seed));
+            if let Err(problem) = result {
+                panic!("{}", problem);
+            }
+        }
+    }
+}
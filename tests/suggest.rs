@@ -0,0 +1,71 @@
+mod common;
+
+use axum::{body::Body, http::{Request, StatusCode}, Router};
+use serde_json::Value;
+use tower::ServiceExt;
+use common::{movie, seeded_app, send};
+
+fn catalog() -> Vec<Value> {
+    let movies = [
+        ("star-wars", "Star Wars", 1977),
+        ("stardust", "Stardust", 2007),
+        ("star-trek", "Star Trek", 2009),
+        ("star-trek-remake", "STAR TREK", 2016),
+        ("stalker", "Stalker", 1979),
+        ("matrix", "The Matrix", 1999),
+        ("amelie", "Amélie", 2001),
+    ];
+    movies.into_iter().map(|(id, name, year)| movie(id, name, year)).collect()
+}
+
+async fn suggest(app: &Router, query: &str) -> (StatusCode, Value) {
+    send(app, "GET", &format!("/v1/movies/suggest?{query}"), None).await
+}
+
+#[tokio::test]
+async fn suggests_names_with_the_prefix_in_order() {
+    let app = seeded_app(catalog()).await;
+    let (status, body) = suggest(&app, "prefix=sta").await;
+    assert_eq!(status, StatusCode::OK);
+    // Star Trek is there twice, in different cases, but only suggested once.
+    assert_eq!(body["names"], serde_json::json!(["Stalker", "Star Trek", "Star Wars", "Stardust"]));
+
+    let (_, body) = suggest(&app, "prefix=STAR%20w").await;
+    assert_eq!(body["names"], serde_json::json!(["Star Wars"]));
+    let (_, body) = suggest(&app, "prefix=matrix").await;
+    assert_eq!(body["names"], serde_json::json!([]));
+    // Accents don't matter either way.
+    let (_, body) = suggest(&app, "prefix=amel").await;
+    assert_eq!(body["names"], serde_json::json!(["Amélie"]));
+}
+
+#[tokio::test]
+async fn limit_caps_the_suggestions() {
+    let app = seeded_app(catalog()).await;
+    let (_, body) = suggest(&app, "prefix=star&limit=2").await;
+    assert_eq!(body["names"], serde_json::json!(["Star Trek", "Star Wars"]));
+}
+
+#[tokio::test]
+async fn suggestions_follow_writes() {
+    let app = seeded_app(catalog()).await;
+    let request = Request::patch("/v1/movie/stalker")
+        .header("content-type", "application/json")
+        .body(Body::from(r#"{"name": "Solaris"}"#))
+        .unwrap();
+    assert_eq!(app.clone().oneshot(request).await.unwrap().status(), StatusCode::OK);
+    let request = Request::delete("/v1/movie/stardust").body(Body::empty()).unwrap();
+    assert_eq!(app.clone().oneshot(request).await.unwrap().status(), StatusCode::NO_CONTENT);
+
+    let (_, body) = suggest(&app, "prefix=s").await;
+    assert_eq!(body["names"], serde_json::json!(["Solaris", "Star Trek", "Star Wars"]));
+}
+
+#[tokio::test]
+async fn empty_prefix_is_rejected() {
+    let app = seeded_app(catalog()).await;
+    for query in ["", "prefix=", "prefix=%20"] {
+        let (status, _) = suggest(&app, query).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{query}");
+    }
+}
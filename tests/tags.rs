@@ -0,0 +1,84 @@
+mod common;
+
+use axum::http::StatusCode;
+use serde_json::{json, Value};
+use common::{seeded_app, send};
+
+fn catalog() -> Vec<Value> {
+    let movies = [
+        ("blade-runner", "Blade Runner", 1982, vec!["Noir", "rain"]),
+        ("chinatown", "Chinatown", 1974, vec!["noir"]),
+        ("heat", "Heat", 1995, vec![]),
+        ("se7en", "Se7en", 1995, vec!["RAIN", "noir"]),
+    ];
+    movies.into_iter().map(|(id, name, year, tags)| json!({ "id": id, "name": name, "year": year, "was_good": true, "tags": tags })).collect()
+}
+
+fn ids(page: &Value) -> Vec<&str> {
+    page["items"].as_array().unwrap().iter().map(|movie| movie["id"].as_str().unwrap()).collect()
+}
+
+#[tokio::test]
+async fn lists_tags_with_counts_ignoring_case() {
+    let app = seeded_app(catalog()).await;
+    let (status, body) = send(&app, "GET", "/v1/tags", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["items"], json!([
+        { "name": "Noir", "movie_count": 3 },
+        { "name": "rain", "movie_count": 2 },
+    ]));
+}
+
+#[tokio::test]
+async fn filters_movies_by_tag() {
+    let app = seeded_app(catalog()).await;
+    let (status, page) = send(&app, "GET", "/v1/movies?tag=NOIR", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(ids(&page), ["blade-runner", "chinatown", "se7en"]);
+    assert_eq!(page["total"], 3);
+    // Along with the other filters, and paged like any other list.
+    let (_, page) = send(&app, "GET", "/v1/movies?tag=noir&year=1995", None).await;
+    assert_eq!(ids(&page), ["se7en"]);
+    let (_, page) = send(&app, "GET", "/v1/movies?tag=noir&limit=2", None).await;
+    assert_eq!(page["next"], "/v1/movies?limit=2&offset=2&tag=noir");
+    let (_, page) = send(&app, "GET", "/v1/movies?tag=western", None).await;
+    assert_eq!(page["total"], 0);
+}
+
+#[tokio::test]
+async fn putting_tags_replaces_them() {
+    let app = seeded_app(catalog()).await;
+    let (status, movie) = send(&app, "PUT", "/v1/movie/heat/tags", Some(json!(["heist", "Noir"]))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(movie["tags"], json!(["heist", "Noir"]));
+    assert_eq!(movie["version"], 2);
+    let (status, movie) = send(&app, "PUT", "/v1/movie/chinatown/tags", Some(json!([]))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(movie.get("tags").is_none());
+
+    let (_, body) = send(&app, "GET", "/v1/tags", None).await;
+    assert_eq!(body["items"], json!([
+        { "name": "heist", "movie_count": 1 },
+        { "name": "Noir", "movie_count": 3 },
+        { "name": "rain", "movie_count": 2 },
+    ]));
+    let (_, page) = send(&app, "GET", "/v1/movies?tag=noir", None).await;
+    assert_eq!(ids(&page), ["blade-runner", "heat", "se7en"]);
+
+    assert_eq!(send(&app, "DELETE", "/v1/movie/se7en", None).await.0, StatusCode::NO_CONTENT);
+    let (_, body) = send(&app, "GET", "/v1/tags", None).await;
+    assert_eq!(body["items"][2], json!({ "name": "rain", "movie_count": 1 }));
+}
+
+#[tokio::test]
+async fn invalid_tags_are_rejected() {
+    let app = seeded_app(catalog()).await;
+    let too_many: Vec<String> = (0..21).map(|i| format!("tag {i}")).collect();
+    for tags in [json!([""]), json!(["noir", "NOIR"]), json!(["x".repeat(51)]), json!(too_many)] {
+        let (status, body) = send(&app, "PUT", "/v1/movie/heat/tags", Some(tags)).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["error"]["details"]["fields"][0]["field"], "tags");
+    }
+    assert_eq!(send(&app, "PUT", "/v1/movie/missing/tags", Some(json!(["noir"]))).await.0, StatusCode::NOT_FOUND);
+    assert_eq!(send(&app, "PUT", "/v1/movie/heat/tags", Some(json!({ "tags": ["noir"] }))).await.0, StatusCode::UNPROCESSABLE_ENTITY);
+}
@@ -0,0 +1,127 @@
+mod common;
+
+use std::sync::Arc;
+
+use axum::{http::StatusCode, Router};
+use http_body_util::BodyExt;
+use serde_json::{json, Value};
+use syndica_rust::{build_router, cache::{CacheConfig, CachedMovieStore}, crypto, quota::QuotaMovieStore, settings::Settings, state::{state_init, StateWrapper}, tenant::{TenantConfig, TenantMovieStore}};
+use common::{send_request, send_with};
+
+const ADMIN_KEY: &str = "tenants-admin-key";
+const TEAM_A_KEY: &str = "tenants-team-a-key";
+
+// With keys configured, so every test sends a key with everything.
+fn app() -> Router {
+    let tenants = vec![TenantConfig { id: "team-a".into(), max_movies: None }, TenantConfig { id: "team-b".into(), max_movies: Some(2) }];
+    let settings = Settings::default();
+    settings.set_api_keys(vec![crypto::sha256(ADMIN_KEY.as_bytes())]);
+    settings.set_tenant_keys(vec![(crypto::sha256(TEAM_A_KEY.as_bytes()), "team-a".into())]);
+    let store: StateWrapper = Arc::new(TenantMovieStore::new(state_init(), tenants.into_iter().map(|tenant| (tenant, state_init())).collect()));
+    let store: StateWrapper = Arc::new(QuotaMovieStore::new(store, None));
+    // Movies are cached by tenant as well as id.
+    build_router(Arc::new(CachedMovieStore::new(store, CacheConfig { capacity: 100, ttl: None })), settings)
+}
+
+fn movie(id: &str, name: &str) -> Value {
+    json!({ "id": id, "name": name, "year": 1979, "was_good": true })
+}
+
+#[tokio::test]
+async fn tenants_only_see_their_own_movies() {
+    let app = app();
+    let as_admin = |tenant: &'static str| [("x-api-key", ADMIN_KEY), ("x-tenant-id", tenant)];
+    assert_eq!(send_with(&app, "POST", "/v1/movie", &as_admin("team-a"), Some(movie("alien", "Alien"))).await.0, StatusCode::CREATED);
+    // The same id means a different movie to another tenant.
+    assert_eq!(send_with(&app, "POST", "/v1/movie", &as_admin("team-b"), Some(movie("alien", "Aliens"))).await.0, StatusCode::CREATED);
+
+    assert_eq!(send_with(&app, "GET", "/v1/movie/alien", &[("x-api-key", ADMIN_KEY)], None).await.0, StatusCode::NOT_FOUND);
+    assert_eq!(send_with(&app, "GET", "/v1/movie/alien", &as_admin("default"), None).await.0, StatusCode::NOT_FOUND);
+    assert_eq!(send_with(&app, "GET", "/v1/movie/alien", &as_admin("team-a"), None).await.1["name"], "Alien");
+    assert_eq!(send_with(&app, "GET", "/v1/movie/alien", &as_admin("team-b"), None).await.1["name"], "Aliens");
+    assert_eq!(send_with(&app, "GET", "/v1/movies", &as_admin("team-a"), None).await.1["total"], 1);
+    assert_eq!(send_with(&app, "GET", "/v1/movies/stats", &[("x-api-key", ADMIN_KEY)], None).await.1["total"], 0);
+
+    // Idempotency-Keys are per tenant too.
+    let retried = |tenant| [("x-api-key", ADMIN_KEY), ("x-tenant-id", tenant), ("idempotency-key", "add-heat")];
+    assert_eq!(send_with(&app, "POST", "/v1/movie", &retried("team-a"), Some(movie("heat", "Heat"))).await.0, StatusCode::CREATED);
+    assert_eq!(send_with(&app, "POST", "/v1/movie", &retried("default"), Some(movie("heat", "Heat"))).await.0, StatusCode::CREATED);
+
+    assert_eq!(send_with(&app, "GET", "/v1/movies", &as_admin("team-c"), None).await.0, StatusCode::NOT_FOUND);
+    assert_eq!(send_with(&app, "GET", "/v1/movies", &as_admin("Team A"), None).await.0, StatusCode::BAD_REQUEST);
+    // Anonymous readers can only see the default catalog.
+    assert_eq!(send_with(&app, "GET", "/v1/movies", &[("x-tenant-id", "team-a")], None).await.0, StatusCode::UNAUTHORIZED);
+    assert_eq!(send_with(&app, "GET", "/v1/movies", &[], None).await.0, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn a_tenants_key_only_reaches_its_tenant() {
+    let app = app();
+    let team_a = [("x-api-key", TEAM_A_KEY)];
+    assert_eq!(send_with(&app, "POST", "/v1/movie", &team_a, Some(movie("ran", "Ran"))).await.0, StatusCode::CREATED);
+    assert_eq!(send_with(&app, "GET", "/v1/movie/ran", &[("x-api-key", ADMIN_KEY), ("x-tenant-id", "team-a")], None).await.0, StatusCode::OK);
+    assert_eq!(send_with(&app, "GET", "/v1/movie/ran", &[("x-api-key", ADMIN_KEY)], None).await.0, StatusCode::NOT_FOUND);
+    assert_eq!(send_with(&app, "DELETE", "/v1/movie/ran", &team_a, None).await.0, StatusCode::NO_CONTENT);
+
+    let (status, body) = send_with(&app, "GET", "/v1/movies", &[("x-api-key", TEAM_A_KEY), ("x-tenant-id", "team-b")], None).await;
+    assert_eq!((status, body["error"]["code"].as_str()), (StatusCode::FORBIDDEN, Some("forbidden")));
+    assert_eq!(send_with(&app, "GET", "/v1/movies", &[("x-api-key", TEAM_A_KEY), ("x-tenant-id", "default")], None).await.0, StatusCode::FORBIDDEN);
+    assert_eq!(send_with(&app, "GET", "/v1/movies", &[("x-api-key", TEAM_A_KEY), ("x-tenant-id", "team-a")], None).await.0, StatusCode::OK);
+    assert_eq!(send_with(&app, "GET", "/admin/tenants", &team_a, None).await.0, StatusCode::FORBIDDEN);
+    assert_eq!(send_with(&app, "POST", "/admin/snapshot", &team_a, None).await.0, StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn quotas_cap_how_many_movies_a_tenant_has() {
+    let app = app();
+    let team_b = [("x-api-key", ADMIN_KEY), ("x-tenant-id", "team-b")];
+    assert_eq!(send_with(&app, "POST", "/v1/movie", &team_b, Some(movie("alien", "Alien"))).await.0, StatusCode::CREATED);
+    assert_eq!(send_with(&app, "POST", "/v1/movie", &team_b, Some(movie("heat", "Heat"))).await.0, StatusCode::CREATED);
+    let (status, body) = send_with(&app, "POST", "/v1/movie", &team_b, Some(movie("ran", "Ran"))).await;
+    assert_eq!((status, body["error"]["code"].as_str()), (StatusCode::INSUFFICIENT_STORAGE, Some("quota_exceeded")));
+    assert_eq!(body["error"]["details"], json!({ "quota": "tenant", "tenant": "team-b", "limit": 2, "used": 2 }));
+    assert!(body["error"]["message"].as_str().unwrap().contains("its quota is 2"));
+    // Overwriting one takes no more room, adding one does.
+    assert_eq!(send_with(&app, "POST", "/v1/movie?upsert=true", &team_b, Some(movie("heat", "Heat!"))).await.0, StatusCode::OK);
+    assert_eq!(send_with(&app, "POST", "/v1/movie?upsert=true", &team_b, Some(movie("ran", "Ran"))).await.0, StatusCode::INSUFFICIENT_STORAGE);
+    let (_, report) = send_with(&app, "POST", "/v1/movies/batch", &team_b, Some(json!([movie("ran", "Ran")]))).await;
+    assert_eq!(report["created"], 0);
+
+    // Movies in the trash don't count, until they're restored.
+    assert_eq!(send_with(&app, "DELETE", "/v1/movie/alien", &team_b, None).await.0, StatusCode::NO_CONTENT);
+    assert_eq!(send_with(&app, "POST", "/v1/movie", &team_b, Some(movie("ran", "Ran"))).await.0, StatusCode::CREATED);
+    assert_eq!(send_with(&app, "POST", "/v1/movie/alien/restore", &team_b, None).await.0, StatusCode::INSUFFICIENT_STORAGE);
+
+    // Other tenants have no quota.
+    for i in 0..3 {
+        let id = format!("movie-{}", i);
+        assert_eq!(send_with(&app, "POST", "/v1/movie", &[("x-api-key", ADMIN_KEY)], Some(movie(&id, "Movie"))).await.0, StatusCode::CREATED);
+    }
+
+    let (status, stats) = send_with(&app, "GET", "/admin/tenants", &[("x-api-key", ADMIN_KEY)], None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(stats["items"], json!([
+        { "id": "default", "movies": 3, "trashed": 0 },
+        { "id": "team-a", "movies": 0, "trashed": 0 },
+        { "id": "team-b", "movies": 2, "trashed": 1, "max_movies": 2 },
+    ]));
+}
+
+#[tokio::test]
+async fn exports_only_hold_the_tenants_movies() {
+    let app = app();
+    let team_a = [("x-api-key", TEAM_A_KEY)];
+    let admin = [("x-api-key", ADMIN_KEY)];
+    assert_eq!(send_with(&app, "POST", "/v1/movie", &team_a, Some(movie("stalker", "Stalker"))).await.0, StatusCode::CREATED);
+    assert_eq!(send_with(&app, "POST", "/v1/movie", &admin, Some(movie("solaris", "Solaris"))).await.0, StatusCode::CREATED);
+
+    // The export is streamed after the handler has returned, so this is about where the body is read, not the handler.
+    let export = async |headers: &[(&str, &str)]| {
+        let response = send_request(&app, "GET", "/movies/export", headers, None).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        String::from_utf8(body.to_vec()).unwrap().lines().map(|line| serde_json::from_str::<Value>(line).unwrap()["id"].clone()).collect::<Vec<_>>()
+    };
+    assert_eq!(export(&team_a).await, vec![json!("stalker")]);
+    assert_eq!(export(&admin).await, vec![json!("solaris")]);
+}
@@ -0,0 +1,25 @@
+use std::{convert::Infallible, time::Duration};
+use axum::{body::{Body, Bytes}, http::{Request, StatusCode}};
+use futures_util::{stream, StreamExt};
+use http_body_util::BodyExt;
+use serde_json::Value;
+use syndica_rust::{settings::Settings, test_app_with};
+use tower::ServiceExt;
+
+#[tokio::test]
+async fn slow_requests_get_a_504() {
+    let settings = Settings::default();
+    settings.set_timeout(Duration::from_millis(50));
+    let app = test_app_with(settings);
+
+    // A client that stops halfway through its body keeps the handler waiting.
+    let body = Body::from_stream(stream::once(async { Ok::<_, Infallible>(Bytes::from("{\"name\": ")) }).chain(stream::pending()));
+    let request = Request::post("/movie").header("content-type", "application/json").body(body).unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+    let error: Value = serde_json::from_slice(&response.into_body().collect().await.unwrap().to_bytes()).unwrap();
+    assert_eq!(error["error"]["code"], "timeout");
+
+    let response = app.clone().oneshot(Request::get("/movies").body(Body::empty()).unwrap()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
@@ -0,0 +1,80 @@
+mod common;
+
+use axum::{http::{header, HeaderMap, StatusCode}, Router};
+use serde_json::{json, Value};
+use syndica_rust::test_app;
+use common::{json, send_request};
+
+// Every movie says when it was added and last changed, so a client can fetch only what changed since it last synced,
+// and a GET can answer 304 to a copy that's still current.
+
+async fn send(app: &Router, method: &str, uri: &str, headers: &[(header::HeaderName, &str)], body: Option<Value>) -> (StatusCode, HeaderMap, Value) {
+    let headers: Vec<_> = headers.iter().map(|(name, value)| (name.as_str(), *value)).collect();
+    let response = send_request(app, method, uri, &headers, body).await;
+    let (status, headers) = (response.status(), response.headers().clone());
+    (status, headers, json(response).await)
+}
+
+fn movie(id: &str, name: &str) -> Value {
+    json!({ "id": id, "name": name, "year": 1979, "was_good": true })
+}
+
+#[tokio::test]
+async fn writes_keep_created_at_and_move_updated_at() {
+    let app = test_app();
+    let (status, _, created) = send(&app, "POST", "/v1/movie", &[], Some(movie("alien", "Alien"))).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert!(created["created_at"].is_string() && created["created_at"] == created["updated_at"], "{created}");
+
+    // The server keeps them, whatever a client sends.
+    let mut replaced = movie("alien", "Alien");
+    replaced["created_at"] = json!("2000-01-01T00:00:00Z");
+    replaced["version"] = json!(1);
+    let (status, _, put) = send(&app, "PUT", "/v1/movie/alien", &[], Some(replaced)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(put["created_at"], created["created_at"]);
+    let (_, _, patched) = send(&app, "PATCH", "/v1/movie/alien", &[], Some(json!({ "was_good": false }))).await;
+    assert_eq!(patched["created_at"], created["created_at"]);
+    assert!(patched["updated_at"].as_str() >= put["updated_at"].as_str() && put["updated_at"].as_str() >= created["updated_at"].as_str());
+    let (_, _, fetched) = send(&app, "GET", "/v1/movie/alien", &[], None).await;
+    assert_eq!(fetched["updated_at"], patched["updated_at"]);
+}
+
+#[tokio::test]
+async fn updated_since_lists_only_what_changed() {
+    let app = test_app();
+    let (_, _, alien) = send(&app, "POST", "/v1/movie", &[], Some(movie("alien", "Alien"))).await;
+    tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    let (_, _, heat) = send(&app, "POST", "/v1/movie", &[], Some(movie("heat", "Heat"))).await;
+    assert!(heat["updated_at"].as_str() > alien["updated_at"].as_str());
+
+    let ids = |page: &Value| page["items"].as_array().unwrap().iter().map(|movie| movie["id"].as_str().unwrap().to_string()).collect::<Vec<_>>();
+    let since = heat["updated_at"].as_str().unwrap().replace('+', "%2B");
+    let (status, _, page) = send(&app, "GET", &format!("/v1/movies?updated_since={}", since), &[], None).await;
+    assert_eq!((status, ids(&page)), (StatusCode::OK, vec!["heat".to_string()]));
+    let (_, _, page) = send(&app, "GET", "/v1/movies?updated_since=2000-01-01T00:00:00Z", &[], None).await;
+    assert_eq!(ids(&page), ["alien", "heat"]);
+
+    let (status, _, error) = send(&app, "GET", "/v1/movies?updated_since=yesterday", &[], None).await;
+    assert_eq!((status, error["error"]["code"].as_str()), (StatusCode::BAD_REQUEST, Some("invalid_query")));
+}
+
+#[tokio::test]
+async fn get_answers_if_modified_since() {
+    let app = test_app();
+    send(&app, "POST", "/v1/movie", &[], Some(movie("alien", "Alien"))).await;
+    let (status, headers, _) = send(&app, "GET", "/v1/movie/alien", &[], None).await;
+    assert_eq!(status, StatusCode::OK);
+    let last_modified = headers[header::LAST_MODIFIED].to_str().unwrap().to_string();
+    assert!(last_modified.ends_with(" GMT"), "{last_modified}");
+
+    let (status, headers, _) = send(&app, "GET", "/v1/movie/alien", &[(header::IF_MODIFIED_SINCE, &last_modified)], None).await;
+    assert_eq!(status, StatusCode::NOT_MODIFIED);
+    assert_eq!(headers[header::LAST_MODIFIED], last_modified.as_str());
+    let (status, _, _) = send(&app, "GET", "/v1/movie/alien", &[(header::IF_MODIFIED_SINCE, "Mon, 01 Jan 2001 00:00:00 GMT")], None).await;
+    assert_eq!(status, StatusCode::OK);
+    // A date that can't be read is ignored, and If-None-Match outranks it.
+    assert_eq!(send(&app, "GET", "/v1/movie/alien", &[(header::IF_MODIFIED_SINCE, "soon")], None).await.0, StatusCode::OK);
+    let headers = [(header::IF_MODIFIED_SINCE, last_modified.as_str()), (header::IF_NONE_MATCH, "\"stale\"")];
+    assert_eq!(send(&app, "GET", "/v1/movie/alien", &headers, None).await.0, StatusCode::OK);
+}
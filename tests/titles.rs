@@ -0,0 +1,76 @@
+mod common;
+
+use axum::{body::Body, http::{header, Request, StatusCode}, Router};
+use serde_json::{json, Value};
+use tower::ServiceExt;
+use common::send_with;
+
+// Movies can have titles in other languages besides their name: GET /movie/{id} picks one by Accept-Language, and
+// search looks through them all.
+
+async fn send(app: &Router, method: &str, uri: &str, language: Option<&str>, body: Option<Value>) -> (StatusCode, Value) {
+    let headers: Vec<_> = language.map(|language| ("accept-language", language)).into_iter().collect();
+    send_with(app, method, uri, &headers, body).await
+}
+
+async fn seeded_app() -> Router {
+    common::seeded_app([json!({
+        "id": "seven-samurai", "name": "Seven Samurai", "year": 1954, "was_good": true,
+        "titles": { "ja": "七人の侍", "pt-BR": "Os Sete Samurais", "fr": "Les Sept Samouraïs" },
+    })]).await
+}
+
+#[tokio::test]
+async fn get_picks_the_title_for_accept_language() {
+    let app = seeded_app().await;
+    let title = |movie: &Value| (movie["title"].as_str().map(str::to_string), movie["title_language"].as_str().map(str::to_string));
+    for (language, expected) in [
+        ("ja", (Some("七人の侍"), Some("ja"))),
+        // The most preferred language with a title, whatever order they're listed in.
+        ("de, fr;q=0.5, ja;q=0.8", (Some("七人の侍"), Some("ja"))),
+        // pt-PT falls back to pt, and pt takes pt-BR.
+        ("pt-PT", (Some("Os Sete Samurais"), Some("pt-BR"))),
+        ("FR-ca", (Some("Les Sept Samouraïs"), Some("fr"))),
+        // No title for the language, or only for one ruled out, is the name.
+        ("de, ja;q=0", (Some("Seven Samurai"), None)),
+    ] {
+        let (status, movie) = send(&app, "GET", "/v1/movie/seven-samurai", Some(language), None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(movie["name"], "Seven Samurai", "{language}");
+        assert_eq!(title(&movie), (expected.0.map(str::to_string), expected.1.map(str::to_string)), "{language}");
+    }
+    // Without the header the movie is as it always was.
+    let (_, movie) = send(&app, "GET", "/v1/movie/seven-samurai", None, None).await;
+    assert_eq!((movie.get("title"), movie["titles"]["ja"].as_str()), (None, Some("七人の侍")));
+
+    let request = Request::get("/v1/movie/seven-samurai").body(Body::empty()).unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let vary: Vec<_> = response.headers().get_all(header::VARY).iter().map(|vary| vary.to_str().unwrap().to_string()).collect();
+    assert!(vary.contains(&"accept-language".to_string()), "{vary:?}");
+}
+
+#[tokio::test]
+async fn search_looks_through_every_title() {
+    let app = seeded_app().await;
+    let (_, page) = send(&app, "GET", "/v1/movies/search?q=samourais", None, None).await;
+    assert_eq!(page["items"][0]["movie"]["id"], "seven-samurai");
+    assert_eq!((page["items"][0]["highlighted"].as_str(), page["items"][0]["language"].as_str()), (Some("Les Sept <em>Samouraïs</em>"), Some("fr")));
+    // A match on the name is shown as the name.
+    let (_, page) = send(&app, "GET", "/v1/movies/search?q=samurai", None, None).await;
+    assert_eq!((page["items"][0]["highlighted"].as_str(), page["items"][0].get("language")), (Some("Seven <em>Samurai</em>"), None));
+    let (_, page) = send(&app, "GET", "/v1/movies/search?q=%E4%B8%83%E4%BA%BA%E3%81%AE%E4%BE%8D", None, None).await;
+    assert_eq!(page["total"], 1);
+}
+
+#[tokio::test]
+async fn titles_are_validated_and_replaced_whole() {
+    let app = seeded_app().await;
+    for titles in [json!({ "not a language": "x" }), json!({ "en": " " }), json!({ "en": "A", "EN": "B" })] {
+        let (status, error) = send(&app, "PATCH", "/v1/movie/seven-samurai", None, Some(json!({ "titles": titles }))).await;
+        assert_eq!((status, error["error"]["details"]["fields"][0]["field"].as_str()), (StatusCode::UNPROCESSABLE_ENTITY, Some("titles")), "{titles}");
+    }
+    let (_, movie) = send(&app, "PATCH", "/v1/movie/seven-samurai", None, Some(json!({ "titles": { "de": "Die sieben Samurai" } }))).await;
+    assert_eq!(movie["titles"], json!({ "de": "Die sieben Samurai" }));
+    let (_, movie) = send(&app, "PATCH", "/v1/movie/seven-samurai", None, Some(json!({ "titles": null }))).await;
+    assert_eq!(movie.get("titles"), None);
+}
@@ -0,0 +1,102 @@
+#![cfg(feature = "tls")]
+
+// The certificates are made with the openssl command and the server is called with curl, both of which have to be on
+// the PATH.
+
+use std::{future::pending, net::SocketAddr, path::{Path, PathBuf}, process::Command, sync::Arc};
+use syndica_rust::{listener::{HttpConfig, Listener}, test_app, tls::{TlsAcceptor, TlsConfig}};
+
+fn dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("syndica-tls-{}-{}", std::process::id(), name));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+// A self-signed certificate for localhost, as <name>.crt and <name>.key.
+fn certificate(dir: &Path, name: &str) -> TlsConfig {
+    let config = TlsConfig { cert: dir.join(format!("{}.crt", name)), key: dir.join(format!("{}.key", name)) };
+    let output = Command::new("openssl")
+        .args(["req", "-x509", "-newkey", "ec", "-pkeyopt", "ec_paramgen_curve:prime256v1", "-nodes", "-days", "1"])
+        .args(["-subj", &format!("/CN={}", name), "-addext", "subjectAltName=DNS:localhost"])
+        .arg("-keyout").arg(&config.key).arg("-out").arg(&config.cert)
+        .output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    config
+}
+
+async fn serve(acceptor: Arc<TlsAcceptor>, http: HttpConfig) -> SocketAddr {
+    let listener = Listener::bind(&"127.0.0.1:0".parse().unwrap()).await.unwrap();
+    let Listener::Tcp(tcp) = &listener else { unreachable!() };
+    let address = tcp.local_addr().unwrap();
+    tokio::spawn(listener.with_tls(acceptor).serve(test_app(), http, pending()));
+    address
+}
+
+// The HTTP version and status of each request, trusting only `ca`, or why curl failed. Run on a thread of its own, so
+// the server can answer meanwhile.
+async fn curl(address: SocketAddr, ca: &Path, args: &[&str], paths: &[&str]) -> Result<String, String> {
+    let mut command = Command::new("curl");
+    command.args(["-sS", "-w", "%{http_version} %{http_code}\\n", "--cacert"]).arg(ca).args(args);
+    command.arg("--resolve").arg(format!("localhost:{}:127.0.0.1", address.port()));
+    for path in paths {
+        command.args(["-o", "/dev/null"]).arg(format!("https://localhost:{}{}", address.port(), path));
+    }
+    let output = tokio::task::spawn_blocking(move || command.output().unwrap()).await.unwrap();
+    match output.status.success() {
+        true => Ok(String::from_utf8(output.stdout).unwrap()),
+        false => Err(String::from_utf8_lossy(&output.stderr).into_owned()),
+    }
+}
+
+#[tokio::test]
+async fn serves_https() {
+    let dir = dir("serve");
+    let config = certificate(&dir, "server");
+    let address = serve(Arc::new(TlsAcceptor::new(config.clone(), true).unwrap()), HttpConfig::default()).await;
+
+    // HTTP/2 is agreed on during the handshake, and both requests go over the one connection.
+    assert_eq!(curl(address, &config.cert, &[], &["/v1/movies", "/healthz"]).await.unwrap(), "2 200\n2 200\n");
+    assert_eq!(curl(address, &config.cert, &["--http1.1"], &["/v1/movies", "/healthz"]).await.unwrap(), "1.1 200\n1.1 200\n");
+    // Plain HTTP gets nowhere.
+    let mut plain = Command::new("curl");
+    plain.args(["-sS", "-o", "/dev/null", &format!("http://127.0.0.1:{}/healthz", address.port())]);
+    assert!(!tokio::task::spawn_blocking(move || plain.output().unwrap()).await.unwrap().status.success());
+
+    let address = serve(Arc::new(TlsAcceptor::new(config.clone(), false).unwrap()), HttpConfig { http2: false, ..HttpConfig::default() }).await;
+    assert_eq!(curl(address, &config.cert, &[], &["/healthz"]).await.unwrap(), "1.1 200\n");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn reloads_the_certificate() {
+    let dir = dir("reload");
+    let (old, new) = (certificate(&dir, "old"), certificate(&dir, "new"));
+    let config = TlsConfig { cert: dir.join("server.crt"), key: dir.join("server.key") };
+    std::fs::copy(&old.cert, &config.cert).unwrap();
+    std::fs::copy(&old.key, &config.key).unwrap();
+    let acceptor = Arc::new(TlsAcceptor::new(config.clone(), true).unwrap());
+    let address = serve(acceptor.clone(), HttpConfig::default()).await;
+    assert!(curl(address, &old.cert, &[], &["/healthz"]).await.is_ok());
+    assert!(curl(address, &new.cert, &[], &["/healthz"]).await.is_err());
+
+    std::fs::copy(&new.cert, &config.cert).unwrap();
+    std::fs::copy(&new.key, &config.key).unwrap();
+    acceptor.reload().unwrap();
+    assert!(curl(address, &new.cert, &[], &["/healthz"]).await.is_ok());
+    assert!(curl(address, &old.cert, &[], &["/healthz"]).await.is_err());
+
+    // A key that doesn't go with the certificate is refused, and the last good pair stays in use.
+    std::fs::copy(&old.key, &config.key).unwrap();
+    assert!(acceptor.reload().unwrap_err().to_string().contains("isn't the key for"));
+    assert!(curl(address, &new.cert, &[], &["/healthz"]).await.is_ok());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn missing_files_are_reported() {
+    let dir = dir("missing");
+    let config = TlsConfig { cert: dir.join("none.crt"), key: dir.join("none.key") };
+    let error = TlsAcceptor::new(config, true).err().unwrap();
+    assert!(error.to_string().contains("none.key"), "{}", error);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
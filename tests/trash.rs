@@ -0,0 +1,110 @@
+mod common;
+
+use std::{sync::Arc, time::Duration};
+
+use axum::{http::StatusCode, Router};
+use serde_json::{json, Value};
+use syndica_rust::{build_router, settings::Settings, snapshot::SnapshotMovieStore, state::{state_init, StateWrapper}, trash, wal::{WalConfig, WalMovieStore}, test_app};
+use time::OffsetDateTime;
+use common::send;
+
+async fn create(app: &Router, id: &str, name: &str) {
+    let movie = json!({ "id": id, "name": name, "year": 1979, "was_good": true });
+    assert_eq!(send(app, "POST", "/v1/movie", Some(movie)).await.0, StatusCode::CREATED);
+}
+
+fn trashed_ids(page: &Value) -> Vec<&str> {
+    page["items"].as_array().unwrap().iter().map(|trashed| trashed["movie"]["id"].as_str().unwrap()).collect()
+}
+
+#[tokio::test]
+async fn deleted_movies_can_be_restored() {
+    let app = test_app();
+    create(&app, "alien", "Alien").await;
+    create(&app, "heat", "Heat").await;
+    create(&app, "ran", "Ran").await;
+    assert_eq!(send(&app, "DELETE", "/v1/movie/ran", None).await.0, StatusCode::NO_CONTENT);
+    assert_eq!(send(&app, "DELETE", "/v1/movie/alien", None).await.0, StatusCode::NO_CONTENT);
+
+    assert_eq!(send(&app, "GET", "/v1/movie/alien", None).await.0, StatusCode::NOT_FOUND);
+    assert_eq!(send(&app, "GET", "/v1/movies", None).await.1["total"], 1);
+    let (status, page) = send(&app, "GET", "/v1/movies/trash", None).await;
+    assert_eq!((status, trashed_ids(&page)), (StatusCode::OK, vec!["alien", "ran"]));
+    assert!(OffsetDateTime::parse(page["items"][0]["deleted_at"].as_str().unwrap(), &time::format_description::well_known::Rfc3339).is_ok());
+    let (_, first) = send(&app, "GET", "/v1/movies/trash?limit=1", None).await;
+    assert_eq!((trashed_ids(&first), first["next"].as_str()), (vec!["alien"], Some("/v1/movies/trash?limit=1&offset=1")));
+
+    let (status, restored) = send(&app, "POST", "/v1/movie/alien/restore", None).await;
+    assert_eq!((status, restored["name"].as_str()), (StatusCode::OK, Some("Alien")));
+    assert_eq!(send(&app, "GET", "/v1/movie/alien", None).await.1, restored);
+    assert_eq!(send(&app, "POST", "/v1/movie/alien/restore", None).await.0, StatusCode::NOT_FOUND);
+    assert_eq!(trashed_ids(&send(&app, "GET", "/v1/movies/trash", None).await.1), ["ran"]);
+
+    // Its id has been taken since, so it stays in the trash.
+    create(&app, "ran", "Ran (remastered)").await;
+    assert_eq!(send(&app, "POST", "/v1/movie/ran/restore", None).await.0, StatusCode::CONFLICT);
+    assert_eq!(trashed_ids(&send(&app, "GET", "/v1/movies/trash", None).await.1), ["ran"]);
+}
+
+#[tokio::test]
+async fn purging_drops_movies_deleted_before_the_cutoff() {
+    let state: StateWrapper = state_init();
+    let app = build_router(state.clone(), Settings::default());
+    create(&app, "alien", "Alien").await;
+    send(&app, "DELETE", "/v1/movie/alien", None).await;
+
+    assert_eq!(state.purge(OffsetDateTime::now_utc() - Duration::from_secs(60)).await.unwrap(), Vec::<String>::new());
+    assert_eq!(state.trash().await.len(), 1);
+    assert_eq!(state.purge(OffsetDateTime::now_utc() + Duration::from_secs(1)).await.unwrap(), ["alien"]);
+    assert!(state.trash().await.is_empty());
+    assert_eq!(send(&app, "POST", "/v1/movie/alien/restore", None).await.0, StatusCode::NOT_FOUND);
+
+    // The retention is process-wide, so only this test sets it.
+    create(&app, "heat", "Heat").await;
+    send(&app, "DELETE", "/v1/movie/heat", None).await;
+    trash::set_retention(Duration::ZERO);
+    assert!(trash::purge_expired(&state).await.is_empty());
+    trash::set_retention(Duration::from_secs(1));
+    assert!(trash::purge_expired(&state).await.is_empty());
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    assert_eq!(trash::purge_expired(&state).await, ["heat"]);
+    trash::set_retention(trash::DEFAULT_RETENTION);
+}
+
+#[tokio::test]
+async fn the_trash_survives_a_restart() {
+    let dir = std::env::temp_dir().join(format!("syndica-trash-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    // Compacting after every write, so the trash has to make it through that too.
+    for max_bytes in [u64::MAX, 1] {
+        let config = WalConfig { path: dir.join(format!("movies-{max_bytes}.wal")), max_bytes };
+        let app = build_router(Arc::new(WalMovieStore::open(config.clone()).await.unwrap()), Settings::default());
+        for (id, name) in [("alien", "Alien"), ("heat", "Heat"), ("ran", "Ran")] {
+            create(&app, id, name).await;
+            send(&app, "DELETE", &format!("/v1/movie/{id}"), None).await;
+        }
+        send(&app, "POST", "/v1/movie/heat/restore", None).await;
+        let state: StateWrapper = Arc::new(WalMovieStore::open(config.clone()).await.unwrap());
+        let trashed: Vec<String> = state.trash().await.into_iter().map(|trashed| trashed.movie.id).collect();
+        assert_eq!(trashed, ["alien", "ran"]);
+        state.purge(OffsetDateTime::now_utc() + Duration::from_secs(1)).await.unwrap();
+        create(&build_router(state, Settings::default()), "ran", "Ran").await;
+        let app = build_router(Arc::new(WalMovieStore::open(config).await.unwrap()), Settings::default());
+        assert!(trashed_ids(&send(&app, "GET", "/v1/movies/trash", None).await.1).is_empty());
+        assert_eq!(send(&app, "GET", "/v1/movie/heat", None).await.1["name"], "Heat");
+        assert_eq!(send(&app, "GET", "/v1/movies", None).await.1["total"], 2);
+    }
+
+    let path = dir.join("movies.json");
+    let store = Arc::new(SnapshotMovieStore::open(path.clone()).await.unwrap());
+    let app = build_router(store.clone(), Settings::default());
+    create(&app, "alien", "Alien").await;
+    send(&app, "DELETE", "/v1/movie/alien", None).await;
+    store.flush().await.unwrap();
+    let app = build_router(Arc::new(SnapshotMovieStore::open(path).await.unwrap()), Settings::default());
+    let (_, page) = send(&app, "GET", "/v1/movies/trash", None).await;
+    assert_eq!(trashed_ids(&page), ["alien"]);
+    assert_eq!(send(&app, "POST", "/v1/movie/alien/restore", None).await.0, StatusCode::OK);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,211 @@
+mod common;
+
+use std::sync::Arc;
+
+use axum::{body::Body, http::{Request, StatusCode}, Router};
+use serde_json::{json, Value};
+use syndica_rust::{build_router, settings::Settings, snapshot::SnapshotMovieStore, wal::{WalConfig, WalMovieStore}, test_app};
+use tower::ServiceExt;
+use common::{movie, seed, send};
+
+// The movies, and ripley to watch them.
+async fn with_ripley(app: Router) -> Router {
+    seed(&app, [movie("alien", "Alien", 1979), movie("heat", "Heat", 1995), movie("se7en", "Se7en", 1995)]).await;
+    let (status, _) = send(&app, "POST", "/v1/users", Some(json!({ "id": "ripley", "name": "Ellen Ripley" }))).await;
+    assert_eq!(status, StatusCode::CREATED);
+    app
+}
+
+async fn watch(app: &Router, user: &str, movie_id: &str) -> (StatusCode, Value) {
+    send(app, "POST", &format!("/v1/users/{user}/watchlist"), Some(json!({ "movie_id": movie_id }))).await
+}
+
+fn ids(page: &Value) -> Vec<&str> {
+    page["items"].as_array().unwrap().iter().map(|movie| movie["id"].as_str().unwrap()).collect()
+}
+
+#[tokio::test]
+async fn registering_users() {
+    let app = test_app();
+    let response = app.clone().oneshot(Request::post("/v1/users")
+        .header("content-type", "application/json")
+        .body(Body::from(json!({ "id": "ripley", "name": "Ellen Ripley" }).to_string()))
+        .unwrap()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    assert_eq!(response.headers()["location"], "/users/ripley");
+
+    let (status, user) = send(&app, "GET", "/v1/users/ripley", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!((user["id"].as_str(), user["name"].as_str()), (Some("ripley"), Some("Ellen Ripley")));
+    assert!(user["created_at"].as_str().unwrap().ends_with('Z'));
+    assert_eq!(user["watchlist"], json!([]));
+
+    let (status, body) = send(&app, "POST", "/v1/users", Some(json!({ "id": "ripley" }))).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(body["error"]["code"], "conflict");
+    // Without an id, or a bearer token to take it from, one is made up.
+    let (status, user) = send(&app, "POST", "/v1/users", Some(json!({}))).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert!(!user["id"].as_str().unwrap().is_empty());
+    for body in [json!({ "id": " " }), json!({ "id": "dallas", "name": "" })] {
+        let (status, body) = send(&app, "POST", "/v1/users", Some(body)).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["error"]["code"], "validation_failed");
+    }
+    assert_eq!(send(&app, "GET", "/v1/users/dallas", None).await.0, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn watchlists_keep_the_order_movies_were_added_in() {
+    let app = with_ripley(test_app()).await;
+    watch(&app, "ripley", "se7en").await;
+    watch(&app, "ripley", "alien").await;
+    // Adding one that's there already leaves it where it was.
+    let (status, user) = watch(&app, "ripley", "se7en").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(user["watchlist"], json!(["se7en", "alien"]));
+
+    let (status, page) = send(&app, "GET", "/v1/users/ripley/watchlist?limit=1", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(ids(&page), ["se7en"]);
+    assert_eq!(page["total"], 2);
+    let (_, page) = send(&app, "GET", page["next"].as_str().unwrap(), None).await;
+    assert_eq!(ids(&page), ["alien"]);
+    assert_eq!(page["next"], Value::Null);
+
+    assert_eq!(send(&app, "DELETE", "/v1/users/ripley/watchlist/se7en", None).await.0, StatusCode::NO_CONTENT);
+    // Whether or not it was there.
+    assert_eq!(send(&app, "DELETE", "/v1/users/ripley/watchlist/se7en", None).await.0, StatusCode::NO_CONTENT);
+    let (_, page) = send(&app, "GET", "/v1/users/ripley/watchlist", None).await;
+    assert_eq!(ids(&page), ["alien"]);
+}
+
+#[tokio::test]
+async fn watchlists_only_hold_movies_that_exist() {
+    let app = with_ripley(test_app()).await;
+    let (status, body) = watch(&app, "ripley", "missing").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body["error"]["message"], "No such movie");
+    let (status, body) = watch(&app, "dallas", "alien").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body["error"]["message"], "No such user");
+    assert_eq!(send(&app, "GET", "/v1/users/dallas/watchlist", None).await.0, StatusCode::NOT_FOUND);
+    assert_eq!(send(&app, "DELETE", "/v1/users/dallas/watchlist/alien", None).await.0, StatusCode::NOT_FOUND);
+
+    watch(&app, "ripley", "alien").await;
+    watch(&app, "ripley", "heat").await;
+    assert_eq!(send(&app, "DELETE", "/v1/movie/alien", None).await.0, StatusCode::NO_CONTENT);
+    let (_, user) = send(&app, "GET", "/v1/users/ripley", None).await;
+    assert_eq!(user["watchlist"], json!(["heat"]));
+}
+
+async fn record(app: &Router, user: &str, body: Value) -> (StatusCode, Value) {
+    send(app, "POST", &format!("/v1/users/{user}/history"), Some(body)).await
+}
+
+fn movie_ids(page: &Value) -> Vec<&str> {
+    page["items"].as_array().unwrap().iter().map(|watch| watch["movie_id"].as_str().unwrap()).collect()
+}
+
+#[tokio::test]
+async fn favorites_are_marked_and_unmarked() {
+    let app = with_ripley(test_app()).await;
+    assert_eq!(send(&app, "PUT", "/v1/users/ripley/favorites/heat", None).await.0, StatusCode::NO_CONTENT);
+    assert_eq!(send(&app, "PUT", "/v1/users/ripley/favorites/alien", None).await.0, StatusCode::NO_CONTENT);
+    assert_eq!(send(&app, "PUT", "/v1/users/ripley/favorites/heat", None).await.0, StatusCode::NO_CONTENT);
+    let (_, user) = send(&app, "GET", "/v1/users/ripley", None).await;
+    assert_eq!(user["favorites"], json!(["heat", "alien"]));
+    let (status, page) = send(&app, "GET", "/v1/users/ripley/favorites", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(ids(&page), ["heat", "alien"]);
+
+    assert_eq!(send(&app, "PUT", "/v1/users/ripley/favorites/missing", None).await.0, StatusCode::NOT_FOUND);
+    assert_eq!(send(&app, "PUT", "/v1/users/dallas/favorites/heat", None).await.0, StatusCode::NOT_FOUND);
+    assert_eq!(send(&app, "DELETE", "/v1/users/ripley/favorites/heat", None).await.0, StatusCode::NO_CONTENT);
+    assert_eq!(send(&app, "DELETE", "/v1/movie/alien", None).await.0, StatusCode::NO_CONTENT);
+    let (_, page) = send(&app, "GET", "/v1/users/ripley/favorites", None).await;
+    assert_eq!(page["total"], 0);
+}
+
+#[tokio::test]
+async fn history_syncs_from_since() {
+    let app = with_ripley(test_app()).await;
+    let (status, first) = record(&app, "ripley", json!({ "movie_id": "alien", "watched_at": "2024-05-01T21:30:00+02:00" })).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(first["watched_at"], "2024-05-01T19:30:00Z");
+    assert!(first["recorded_at"].as_str().unwrap().ends_with('Z'));
+    // Without watched_at it was watched as it's recorded.
+    let (_, second) = record(&app, "ripley", json!({ "movie_id": "heat" })).await;
+    assert_eq!(second["watched_at"], second["recorded_at"]);
+    record(&app, "ripley", json!({ "movie_id": "alien" })).await;
+
+    let (status, page) = send(&app, "GET", "/v1/users/ripley/history?limit=2", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(movie_ids(&page), ["alien", "heat"]);
+    assert_eq!(page["total"], 3);
+    let (_, page) = send(&app, "GET", page["next"].as_str().unwrap(), None).await;
+    assert_eq!(movie_ids(&page), ["alien"]);
+    assert_eq!(page["next"], Value::Null);
+
+    let since = first["recorded_at"].as_str().unwrap().replace('+', "%2B");
+    let (_, page) = send(&app, "GET", &format!("/v1/users/ripley/history?since={since}"), None).await;
+    assert_eq!(movie_ids(&page), ["heat", "alien"]);
+    // Deleting a movie doesn't rewrite the past.
+    assert_eq!(send(&app, "DELETE", "/v1/movie/heat", None).await.0, StatusCode::NO_CONTENT);
+    let (_, page) = send(&app, "GET", "/v1/users/ripley/history", None).await;
+    assert_eq!(page["items"][1], second);
+    assert_eq!(send(&app, "GET", "/v1/users/ripley/history?since=yesterday", None).await.0, StatusCode::BAD_REQUEST);
+    assert_eq!(send(&app, "GET", "/v1/users/dallas/history", None).await.0, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn invalid_watches_are_rejected() {
+    let app = with_ripley(test_app()).await;
+    for watched_at in ["last tuesday", "2999-01-01T00:00:00Z"] {
+        let (status, body) = record(&app, "ripley", json!({ "movie_id": "alien", "watched_at": watched_at })).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["error"]["details"]["fields"][0]["field"], "watched_at");
+    }
+    assert_eq!(record(&app, "ripley", json!({ "movie_id": "missing" })).await.0, StatusCode::NOT_FOUND);
+    assert_eq!(record(&app, "dallas", json!({ "movie_id": "alien" })).await.0, StatusCode::NOT_FOUND);
+    let (_, page) = send(&app, "GET", "/v1/users/ripley/history", None).await;
+    assert_eq!(page["total"], 0);
+}
+
+#[tokio::test]
+async fn users_survive_a_restart() {
+    let dir = std::env::temp_dir().join(format!("syndica-users-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let config = WalConfig { path: dir.join("movies.wal"), max_bytes: u64::MAX };
+    let app = with_ripley(build_router(Arc::new(WalMovieStore::open(config.clone()).await.unwrap()), Settings::default())).await;
+    watch(&app, "ripley", "heat").await;
+    watch(&app, "ripley", "alien").await;
+    send(&app, "PUT", "/v1/users/ripley/favorites/alien", None).await;
+    let (_, watched) = record(&app, "ripley", json!({ "movie_id": "heat" })).await;
+    send(&app, "DELETE", "/v1/movie/heat", None).await;
+    let app = build_router(Arc::new(WalMovieStore::open(config).await.unwrap()), Settings::default());
+    let (_, user) = send(&app, "GET", "/v1/users/ripley", None).await;
+    assert_eq!(user["watchlist"], json!(["alien"]));
+    assert_eq!(user["favorites"], json!(["alien"]));
+    let (_, page) = send(&app, "GET", "/v1/users/ripley/history", None).await;
+    assert_eq!(page["items"], json!([watched]));
+
+    let path = dir.join("movies.json");
+    let store = Arc::new(SnapshotMovieStore::open(path.clone()).await.unwrap());
+    let app = with_ripley(build_router(store.clone(), Settings::default())).await;
+    watch(&app, "ripley", "se7en").await;
+    record(&app, "ripley", json!({ "movie_id": "se7en" })).await;
+    store.flush().await.unwrap();
+    let app = build_router(Arc::new(SnapshotMovieStore::open(path.clone()).await.unwrap()), Settings::default());
+    let (_, page) = send(&app, "GET", "/v1/users/ripley/watchlist", None).await;
+    assert_eq!(ids(&page), ["se7en"]);
+    let (_, page) = send(&app, "GET", "/v1/users/ripley/history", None).await;
+    assert_eq!(movie_ids(&page), ["se7en"]);
+
+    // Snapshots from before there were users are only the movies.
+    std::fs::write(&path, json!([{ "id": "alien", "name": "Alien", "year": 1979, "was_good": true, "version": 1 }]).to_string()).unwrap();
+    let app = build_router(Arc::new(SnapshotMovieStore::open(path).await.unwrap()), Settings::default());
+    assert_eq!(send(&app, "GET", "/v1/movie/alien", None).await.0, StatusCode::OK);
+    assert_eq!(send(&app, "GET", "/v1/users/ripley", None).await.0, StatusCode::NOT_FOUND);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
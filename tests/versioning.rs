@@ -0,0 +1,65 @@
+mod common;
+
+use axum::{http::StatusCode, routing::get, Router};
+use http_body_util::BodyExt;
+use serde_json::json;
+use syndica_rust::{state::state_init, versioning::{unversioned_route, Deprecation, VersionedRouter}, test_app};
+use time::macros::date;
+use common::send_request;
+
+async fn bytes(response: axum::response::Response) -> Vec<u8> {
+    response.into_body().collect().await.unwrap().to_bytes().to_vec()
+}
+
+#[tokio::test]
+async fn v1_and_the_old_paths_answer_the_same() {
+    let app = test_app();
+    let created = send_request(&app, "POST", "/v1/movie", &[], Some(json!({ "id": "heat", "name": "Heat", "year": 1995, "was_good": true }))).await;
+    assert_eq!(created.status(), StatusCode::CREATED);
+    assert!(created.headers().get("deprecation").is_none());
+
+    let current = send_request(&app, "GET", "/v1/movies?limit=5", &[], None).await;
+    assert!(current.headers().get("deprecation").is_none() && current.headers().get("link").is_none());
+    let old = send_request(&app, "GET", "/movies?limit=5", &[], None).await;
+    assert_eq!(old.status(), StatusCode::OK);
+    assert!(old.headers()["deprecation"].to_str().unwrap().starts_with('@'));
+    assert_eq!(old.headers()["link"], "</v1/movies>; rel=\"successor-version\"");
+    assert_eq!(bytes(current).await, bytes(old).await);
+
+    // Operator routes aren't versioned.
+    assert_eq!(send_request(&app, "GET", "/healthz", &[], None).await.status(), StatusCode::OK);
+    assert_eq!(send_request(&app, "GET", "/v1/healthz", &[], None).await.status(), StatusCode::NOT_FOUND);
+    assert_eq!(send_request(&app, "GET", "/v1/graphql", &[], None).await.status(), StatusCode::OK);
+}
+
+#[test]
+fn version_prefixes_are_stripped() {
+    assert_eq!(unversioned_route("/v1/movie/{id}"), "/movie/{id}");
+    assert_eq!(unversioned_route("/v12/movies"), "/movies");
+    assert_eq!(unversioned_route("/movies"), "/movies");
+    assert_eq!(unversioned_route("/v1"), "/v1");
+    assert_eq!(unversioned_route("/video/x"), "/video/x");
+}
+
+#[tokio::test]
+async fn deprecated_versions_point_at_their_successor() {
+    let v1 = Router::new().route("/movies", get(|| async { "old" }));
+    let v2 = Router::new().route("/movies", get(|| async { "new" }));
+    let since = date!(2026 - 10 - 15).midnight().assume_utc();
+    let sunset = date!(2027 - 04 - 01).midnight().assume_utc();
+    let app = VersionedRouter::new()
+        .version("v1", v1)
+        .version("v2", v2)
+        .deprecate("v1", Deprecation { since, sunset: Some(sunset), successor: "/v2" })
+        .build()
+        .with_state(state_init());
+
+    let old = send_request(&app, "GET", "/v1/movies", &[], None).await;
+    assert_eq!(old.headers()["deprecation"], format!("@{}", since.unix_timestamp()));
+    assert_eq!(old.headers()["sunset"], "Thu, 01 Apr 2027 00:00:00 GMT");
+    assert_eq!(old.headers()["link"], "</v2/movies>; rel=\"successor-version\"");
+    assert_eq!(bytes(old).await, b"old");
+    let new = send_request(&app, "GET", "/v2/movies", &[], None).await;
+    assert!(new.headers().get("deprecation").is_none());
+    assert_eq!(bytes(new).await, b"new");
+}
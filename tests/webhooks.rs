@@ -0,0 +1,112 @@
+mod common;
+
+use std::{sync::{Arc, Mutex}, time::Duration};
+use axum::{body::Bytes, extract::{Path, State}, http::{HeaderMap, StatusCode}, routing::post, Router};
+use serde_json::{json, Value};
+use syndica_rust::{build_router, settings::Settings, state::state_init, webhooks};
+use common::send;
+
+const SECRET: &str = "a-secret-of-some-length";
+
+// Keeps every delivery it gets, and answers /ok with 200, /gone with 410 and /flaky with 503 the first time only.
+#[derive(Default)]
+struct Receiver {
+    deliveries: Mutex<Vec<(String, HeaderMap, Bytes)>>,
+}
+
+async fn receive(State(receiver): State<Arc<Receiver>>, Path(path): Path<String>, headers: HeaderMap, body: Bytes) -> StatusCode {
+    let mut deliveries = receiver.deliveries.lock().unwrap();
+    let tries = deliveries.iter().filter(|(other, _, _)| *other == path).count();
+    deliveries.push((path.clone(), headers, body));
+    match path.as_str() {
+        "gone" => StatusCode::GONE,
+        "flaky" if tries == 0 => StatusCode::SERVICE_UNAVAILABLE,
+        _ => StatusCode::OK,
+    }
+}
+
+async fn spawn_receiver() -> (String, Arc<Receiver>) {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let receiver = Arc::new(Receiver::default());
+    let app = Router::new().route("/{path}", post(receive)).with_state(receiver.clone());
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    (url, receiver)
+}
+
+// Deliveries happen in the background, so this waits for them.
+async fn wait_for(what: &str, mut done: impl FnMut() -> bool) {
+    for _ in 0..100 {
+        if done() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    panic!("Gave up waiting for {}", what);
+}
+
+// Webhooks are process-wide, so everything that depends on them is in this one test.
+#[tokio::test]
+async fn changes_are_delivered_signed_and_failures_dead_lettered() {
+    let state = state_init();
+    webhooks::spawn_dispatcher(state.subscribe());
+    let app = build_router(state, Settings::default());
+    let (url, receiver) = spawn_receiver().await;
+    let deliveries = |path: &str| receiver.deliveries.lock().unwrap().iter().filter(|(other, _, _)| other == path).cloned().collect::<Vec<_>>();
+
+    let (status, body) = send(&app, "POST", "/admin/webhooks", Some(json!({ "url": "https://example.com/hook" }))).await;
+    assert_eq!((status, body["error"]["details"]["fields"][0]["field"].as_str()), (StatusCode::UNPROCESSABLE_ENTITY, Some("url")));
+    let (status, _) = send(&app, "POST", "/admin/webhooks", Some(json!({ "url": format!("{url}/ok"), "events": ["renamed"] }))).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    let (status, ok) = send(&app, "POST", "/admin/webhooks", Some(json!({ "url": format!("{url}/ok"), "secret": SECRET }))).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!((ok["events"].clone(), ok["secret"].as_str()), (json!(["created", "updated", "deleted"]), Some(SECRET)));
+    let (_, deletes) = send(&app, "POST", "/admin/webhooks", Some(json!({ "url": format!("{url}/deletes"), "events": ["deleted"] }))).await;
+    assert!(deletes["secret"].as_str().unwrap().len() >= 32);
+    let (_, list) = send(&app, "GET", "/admin/webhooks", None).await;
+    assert_eq!(list["items"].as_array().unwrap().len(), 2);
+    assert!(list["items"][0].get("secret").is_none());
+
+    let movie = json!({ "id": "alien", "name": "Alien", "year": 1979, "was_good": true });
+    assert_eq!(send(&app, "POST", "/v1/movie", Some(movie)).await.0, StatusCode::CREATED);
+    assert_eq!(send(&app, "PATCH", "/v1/movie/alien", Some(json!({ "director": "Ridley Scott" }))).await.0, StatusCode::OK);
+    assert_eq!(send(&app, "DELETE", "/v1/movie/alien", None).await.0, StatusCode::NO_CONTENT);
+    wait_for("every change to reach /ok", || deliveries("ok").len() == 3).await;
+    let mut events: Vec<(String, String)> = deliveries("ok").iter().map(|(_, headers, body)| {
+        let timestamp: u64 = headers["x-webhook-timestamp"].to_str().unwrap().parse().unwrap();
+        assert_eq!(headers["x-webhook-signature"], webhooks::signature(SECRET, timestamp, body).as_str());
+        assert_eq!(headers["x-webhook-id"], ok["id"].as_str().unwrap());
+        let payload: Value = serde_json::from_slice(body).unwrap();
+        assert_eq!((&payload["event"], &payload["movie"]["id"]), (&headers["x-webhook-event"].to_str().unwrap().into(), &json!("alien")));
+        assert_eq!(payload["id"], headers["x-webhook-delivery"].to_str().unwrap());
+        (payload["event"].as_str().unwrap().to_string(), payload["movie"]["director"].as_str().unwrap_or_default().to_string())
+    }).collect();
+    events.sort();
+    assert_eq!(events, [("created".into(), "".into()), ("deleted".into(), "Ridley Scott".into()), ("updated".into(), "Ridley Scott".into())]);
+    wait_for("the delete to reach /deletes", || deliveries("deletes").len() == 1).await;
+    assert_eq!(deliveries("deletes")[0].1["x-webhook-event"], "deleted");
+
+    // Deleted webhooks get nothing more.
+    let path = format!("/admin/webhooks/{}", ok["id"].as_str().unwrap());
+    assert_eq!(send(&app, "DELETE", &path, None).await.0, StatusCode::NO_CONTENT);
+    assert_eq!(send(&app, "DELETE", &path, None).await.0, StatusCode::NOT_FOUND);
+    send(&app, "DELETE", &format!("/admin/webhooks/{}", deletes["id"].as_str().unwrap()), None).await;
+
+    // A 410 won't get any better, so it's dead-lettered without a retry, whereas a 503 is tried again.
+    send(&app, "POST", "/admin/webhooks", Some(json!({ "url": format!("{url}/gone"), "events": ["created"] }))).await;
+    send(&app, "POST", "/admin/webhooks", Some(json!({ "url": format!("{url}/flaky"), "events": ["created"] }))).await;
+    let movie = json!({ "id": "heat", "name": "Heat", "year": 1995, "was_good": true });
+    send(&app, "POST", "/v1/movie", Some(movie)).await;
+    wait_for("the retry of /flaky", || deliveries("flaky").len() == 2).await;
+    let flaky_deliveries = deliveries("flaky");
+    assert_eq!(flaky_deliveries[0].1["x-webhook-delivery"], flaky_deliveries[1].1["x-webhook-delivery"]);
+    wait_for("the dead letter", || !webhooks::dead_letters().is_empty()).await;
+    let (_, dead_letters) = send(&app, "GET", "/admin/webhooks/dead-letters", None).await;
+    let dead_letter = &dead_letters["items"][0];
+    assert_eq!((dead_letter["url"].as_str(), dead_letter["event"].as_str()), (Some(format!("{url}/gone").as_str()), Some("created")));
+    assert_eq!((dead_letter["movie_id"].as_str(), dead_letter["attempts"].as_u64()), (Some("heat"), Some(1)));
+    assert_eq!(dead_letter["error"], "returned 410");
+    assert_eq!(deliveries("gone").len(), 1);
+    assert_eq!(dead_letters["items"].as_array().unwrap().len(), 1);
+}
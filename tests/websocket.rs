@@ -0,0 +1,105 @@
+mod common;
+
+use std::time::Duration;
+use axum::{body::Body, http::{Request, StatusCode}, Router};
+use serde_json::{json, Value};
+use syndica_rust::{websocket::accept_key, test_app};
+use tokio::{io::{AsyncReadExt, AsyncWriteExt}, net::TcpStream};
+use tower::ServiceExt;
+use common::send;
+
+// Serves the router on a real socket, since upgrades need one, and returns its address.
+async fn serve(app: Router) -> std::net::SocketAddr {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    address
+}
+
+async fn connect(address: std::net::SocketAddr, path: &str) -> TcpStream {
+    let mut stream = TcpStream::connect(address).await.unwrap();
+    let request = format!(
+        "GET {} HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+         Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n",
+        path,
+    );
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = Vec::new();
+    while !response.ends_with(b"\r\n\r\n") {
+        response.push(stream.read_u8().await.unwrap());
+    }
+    let response = String::from_utf8(response).unwrap().to_lowercase();
+    assert!(response.starts_with("http/1.1 101"), "{}", response);
+    // The sample key and answer from RFC 6455.
+    assert!(response.contains("sec-websocket-accept: s3pplmbitxaq9kygzzhzrbk+xoo="), "{}", response);
+    stream
+}
+
+// Sends a masked text frame, as clients have to.
+async fn send_text(stream: &mut TcpStream, text: &str) {
+    let mask = [0x12, 0x34, 0x56, 0x78];
+    let mut frame = vec![0x81, 0x80 | text.len() as u8];
+    frame.extend_from_slice(&mask);
+    frame.extend(text.bytes().enumerate().map(|(i, byte)| byte ^ mask[i % 4]));
+    stream.write_all(&frame).await.unwrap();
+}
+
+// Reads one unfragmented frame from the server, as (opcode, payload).
+async fn read_frame(stream: &mut TcpStream) -> (u8, Vec<u8>) {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        let header = [stream.read_u8().await.unwrap(), stream.read_u8().await.unwrap()];
+        assert_eq!(header[1] & 0x80, 0, "server frames aren't masked");
+        let len = match header[1] {
+            126 => stream.read_u16().await.unwrap() as usize,
+            len => len as usize,
+        };
+        let mut payload = vec![0; len];
+        stream.read_exact(&mut payload).await.unwrap();
+        (header[0] & 0x0f, payload)
+    }).await.expect("no frame within 5s")
+}
+
+async fn read_json(stream: &mut TcpStream) -> Value {
+    let (opcode, payload) = read_frame(stream).await;
+    assert_eq!(opcode, 1);
+    serde_json::from_slice(&payload).unwrap()
+}
+
+#[tokio::test]
+async fn subscribers_get_matching_changes() {
+    let app = test_app();
+    let address = serve(app.clone()).await;
+    let mut socket = connect(address, "/movies/subscribe?min_year=1970&max_year=1989").await;
+
+    send(&app, "POST", "/movie", Some(json!({ "id": "cats", "name": "Cats", "year": 2019, "was_good": false }))).await;
+    send(&app, "POST", "/movie", Some(json!({ "id": "alien", "name": "Alien", "year": 1979, "was_good": true }))).await;
+    let mut created = read_json(&mut socket).await;
+    assert!(created["movie"].as_object_mut().unwrap().remove("created_at").is_some());
+    assert!(created["movie"].as_object_mut().unwrap().remove("updated_at").is_some());
+    assert_eq!(created, json!({ "event": "created", "movie": { "id": "alien", "name": "Alien", "year": 1979, "was_good": true, "version": 1 } }));
+
+    send_text(&mut socket, r#"{"filter": {"was_good": false}}"#).await;
+    assert_eq!(read_json(&mut socket).await, json!({ "subscribed": { "was_good": false } }));
+    send(&app, "PATCH", "/movie/alien", Some(json!({ "name": "Aliens" }))).await;
+    send(&app, "DELETE", "/movie/cats", None).await;
+    let deleted = read_json(&mut socket).await;
+    assert_eq!(deleted["event"], "deleted");
+    assert_eq!(deleted["movie"]["id"], "cats");
+
+    send_text(&mut socket, r#"{"filter": {"rating": 5}}"#).await;
+    assert_eq!(read_json(&mut socket).await["error"]["code"], "invalid_body");
+
+    // A masked close frame with code 1000, which the server answers in kind.
+    socket.write_all(&[0x88, 0x82, 0, 0, 0, 0, 0x03, 0xe8]).await.unwrap();
+    let (opcode, payload) = read_frame(&mut socket).await;
+    assert_eq!(opcode, 8);
+    assert_eq!(payload[..2], 1000u16.to_be_bytes());
+}
+
+#[tokio::test]
+async fn plain_requests_are_rejected() {
+    let app = test_app();
+    let response = app.oneshot(Request::get("/movies/subscribe").body(Body::empty()).unwrap()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(accept_key("dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+}